@@ -14,9 +14,11 @@ use crate::{
     assets::Assets,
     ecs::{RunLevel, StateId, System, Systemized},
     input::Input,
+    renderer::Renderer,
     window::Window,
     State,
 };
+use dotrix_math::Vec2u;
 
 /// Application data to maintain the process
 ///
@@ -63,6 +65,17 @@ impl Application {
 
         run(event_loop, window, self);
     }
+
+    /// Runs the application without a window and the event loop
+    ///
+    /// The frames of the size are rendered offscreen one after another, until the `frames`
+    /// number is reached or the window is closed by [`Window::close`]. Before the return, GPU
+    /// finishes the frames, so the readbacks and screenshots of the last frame are completed.
+    pub fn run_headless(self, width: u32, height: u32, frames: usize) {
+        wgpu_subscriber::initialize_default_subscriber(None);
+
+        run_headless(self, Vec2u::new(width.max(1), height.max(1)), frames);
+    }
 }
 
 /// Service wrapper
@@ -124,7 +137,7 @@ fn run(
 
     scheduler.run_startup(&mut services, current_state_ptr);
 
-    event_loop.run(move |event, _, control_flow| {
+    event_loop.run(move |event, event_loop, control_flow| {
         *control_flow = ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(10));
 
         if let Some(input) = services.get_mut::<Input>() {
@@ -157,11 +170,21 @@ fn run(
             }
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
-                ..
+                window_id,
             } => {
-                *control_flow = ControlFlow::Exit;
+                // closing an additional window does not exit
+                let extra = services
+                    .get_mut::<Window>()
+                    .map(|window| window.close_window_by_id(window_id))
+                    .unwrap_or(false);
+                if !extra {
+                    *control_flow = ControlFlow::Exit;
+                }
             }
             Event::RedrawRequested(_) => {
+                if let Some(window) = services.get_mut::<Window>() {
+                    window.create_windows(event_loop);
+                }
                 scheduler.run_bind(&mut services, current_state_ptr);
                 scheduler.run_update(&mut services, current_state_ptr);
                 scheduler.run_load(&mut services, current_state_ptr);
@@ -174,6 +197,53 @@ fn run(
     });
 }
 
+/// Headless application run cycle
+fn run_headless(
+    Application {
+        mut scheduler,
+        mut services,
+        ..
+    }: Application,
+    size: Vec2u,
+    frames: usize,
+) {
+    let current_state: StateId = StateId::of::<bool>();
+    let current_state_ptr: *const StateId = &current_state;
+
+    if let Some(window) = services.get_mut::<Window>() {
+        window.set_headless(size);
+    }
+
+    if let Some(state) = services.get_mut::<State>() {
+        state.set_pointer(current_state_ptr as usize);
+    }
+
+    scheduler.run_startup(&mut services, current_state_ptr);
+
+    for _ in 0..frames {
+        if services
+            .get::<Window>()
+            .map(|window| window.close_request())
+            .unwrap_or(false)
+        {
+            break;
+        }
+        if let Some(assets) = services.get_mut::<Assets>() {
+            assets.fetch();
+        }
+        scheduler.run_bind(&mut services, current_state_ptr);
+        scheduler.run_update(&mut services, current_state_ptr);
+        scheduler.run_load(&mut services, current_state_ptr);
+        scheduler.run_compute(&mut services, current_state_ptr);
+        scheduler.run_render(&mut services, current_state_ptr);
+        scheduler.run_release(&mut services, current_state_ptr);
+    }
+
+    if let Some(renderer) = services.get_mut::<Renderer>() {
+        renderer.finish();
+    }
+}
+
 /// Services manager
 pub struct Services {
     storage: HashMap<TypeId, Box<dyn std::any::Any>>,
@@ -290,3 +360,47 @@ impl Scheduler {
         Self::run(&mut self.resize, services, state_ptr);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::Mut;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Frames {
+        sizes: Arc<Mutex<Vec<Vec2u>>>,
+    }
+
+    fn count_frames(frames: Mut<Frames>, mut window: Mut<Window>) {
+        let mut sizes = frames.sizes.lock().unwrap();
+        sizes.push(window.inner_size());
+        if sizes.len() == 3 {
+            window.close();
+        }
+    }
+
+    #[test]
+    fn headless_application_runs_until_window_is_closed() {
+        let frames = Frames::default();
+        let sizes = Arc::clone(&frames.sizes);
+        let mut app = Application::new("Headless");
+        app.add_service(frames);
+        app.add_service(Window::default());
+        app.add_system(System::from(count_frames));
+
+        run_headless(app, Vec2u::new(64, 32), 5);
+        assert_eq!(*sizes.lock().unwrap(), vec![Vec2u::new(64, 32); 3]);
+
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        let mut app = Application::new("Headless");
+        app.add_service(Frames {
+            sizes: Arc::clone(&sizes),
+        });
+        app.add_service(Window::default());
+        app.add_system(System::from(count_frames));
+
+        run_headless(app, Vec2u::new(1, 1), 2);
+        assert_eq!(sizes.lock().unwrap().len(), 2);
+    }
+}
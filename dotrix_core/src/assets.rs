@@ -1,6 +1,7 @@
 //! Assets and management service
 pub mod animation;
 mod load_gltf;
+mod load_ktx2;
 pub mod loader;
 pub mod mesh;
 pub mod resource;
@@ -12,19 +13,22 @@ pub use animation::Animation;
 pub use loader::*;
 pub use mesh::*;
 pub use resource::*;
-pub use shader::Shader;
+pub use shader::{Shader, ShaderError, ShaderVariant};
 pub use skin::Skin;
 pub use texture::*;
 
 use std::{
     collections::{hash_map, HashMap, HashSet},
+    path::{Path, PathBuf},
     sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
     vec::Vec,
 };
 
 use crate::{ecs::Mut, id::Id, Renderer};
 
 const THREADS_COUNT: usize = 4;
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
 
 /// Assets management service
 ///
@@ -51,6 +55,8 @@ pub struct Assets {
     id_generator: u64,
     removed_shaders: HashSet<Id<Shader>>,
     hot_reload: bool,
+    watched: HashMap<PathBuf, Option<SystemTime>>,
+    watched_at: Instant,
     root: std::path::PathBuf,
 }
 
@@ -90,6 +96,8 @@ impl Assets {
             id_generator: 1,
             removed_shaders: HashSet::new(),
             hot_reload: true,
+            watched: HashMap::new(),
+            watched_at: Instant::now(),
             root,
         }
     }
@@ -112,6 +120,9 @@ impl Assets {
 
     /// Imports an asset file from specified absolute or relative path and returns [`Id`] of the
     /// [`Resource`]
+    ///
+    /// WGSL files are watched and imported again, when they change, if the hot reload is
+    /// enabled.
     pub fn import_from(&mut self, path: std::path::PathBuf) -> Id<Resource> {
         let name = path
             .file_stem()
@@ -121,6 +132,10 @@ impl Assets {
         let resource = Resource::new(name.clone(), path.as_path().display().to_string());
         let id = self.store_as::<Resource>(resource, &name);
 
+        if path.extension().map(|ext| ext == "wgsl").unwrap_or(false) {
+            self.watched.insert(path.clone(), modified_time(&path));
+        }
+
         let task = Task { path, name };
         self.sender.send(Request::Import(task)).unwrap();
         id
@@ -242,7 +257,17 @@ impl Assets {
                     self.store_as(*mesh.asset, &mesh.name);
                 }
                 Response::Shader(shader) => {
-                    self.store_as(*shader.asset, &shader.name);
+                    // loaded shaders keep their modules, until the new code is preprocessed
+                    let loaded = self
+                        .find::<Shader>(&shader.name)
+                        .and_then(|id| self.shaders.get_mut(&id))
+                        .filter(|loaded| loaded.loaded());
+                    match loaded {
+                        Some(loaded) => loaded.pending_code = Some(shader.asset.code),
+                        None => {
+                            self.store_as(*shader.asset, &shader.name);
+                        }
+                    }
                 }
                 Response::Skin(skin) => {
                     self.store_as(*skin.asset, &skin.name);
@@ -257,9 +282,51 @@ impl Assets {
     /// Enable/Disable hot reload of certain assets. If this is
     /// disabled certain assets like `Shaders` need to be
     /// cleaned up manually with `renderer.drop_pipeline`
+    ///
+    /// Imported WGSL shaders are imported again, when their files are modified, and the
+    /// pipelines of the replaced shaders are recreated.
     pub fn hot_reload_enable(&mut self, enable: bool) {
         self.hot_reload = enable;
     }
+
+    /// Imports the watched files modified since the last check
+    fn reload_modified(&mut self) {
+        if self.watched_at.elapsed() < WATCH_INTERVAL {
+            return;
+        }
+        self.watched_at = Instant::now();
+        for path in self.modified() {
+            let name = path
+                .file_stem()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            self.sender
+                .send(Request::Import(Task { path, name }))
+                .unwrap();
+        }
+    }
+
+    /// Returns paths of the watched files modified since the last check
+    fn modified(&mut self) -> Vec<PathBuf> {
+        self.watched
+            .iter_mut()
+            .filter_map(|(path, time)| {
+                let modified = modified_time(path);
+                if modified.is_some() && modified != *time {
+                    *time = modified;
+                    Some(path.clone())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
 }
 
 /// Reload assets and cleanup any assets that need some post process
@@ -277,13 +344,16 @@ pub fn release(mut assets: Mut<Assets>, mut renderer: Mut<Renderer>) {
     }
     assets.removed_shaders.clear();
 
-    // Any shader that has its shader module dropped/uninitalised should have it's
-    // pipeline dropped from the renderer
+    // Any shader that has its shader module dropped/uninitalised or reloaded code should have
+    // it's pipeline dropped from the renderer
     for (id, shader) in &assets.shaders {
-        if !shader.loaded() {
+        if !shader.loaded() || shader.pending_code.is_some() {
             renderer.drop_pipeline(*id);
         }
     }
+
+    // Modified shaders replace the old ones, when they are fetched
+    assets.reload_modified();
 }
 
 /// Asset map getting trait
@@ -391,3 +461,26 @@ impl Default for Assets {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modified_shader_files_are_reported_once() {
+        let path = std::env::temp_dir().join(format!("dotrix_watch_{}.wgsl", std::process::id()));
+        std::fs::write(&path, "// shader").unwrap();
+
+        let mut assets = Assets::new();
+        assets.import_from(path.clone());
+        assert!(assets.modified().is_empty());
+
+        // some file systems store the modification time in seconds
+        std::thread::sleep(Duration::from_millis(1100));
+        std::fs::write(&path, "// modified shader").unwrap();
+        assert_eq!(assets.modified(), vec![path.clone()]);
+        assert!(assets.modified().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
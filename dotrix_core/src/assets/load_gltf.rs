@@ -121,7 +121,7 @@ fn load_node(
 
     if let Some(mesh) = node.mesh() {
         for primitive in mesh.primitives() {
-            load_mesh(sender, name, &primitive, buffers)?;
+            load_mesh(sender, name, &primitive, mesh.weights(), buffers)?;
             let material = primitive.material();
             if let Some(texture) = material.pbr_metallic_roughness().base_color_texture() {
                 load_texture(sender, name, &texture, buffers)?;
@@ -147,6 +147,7 @@ fn load_mesh(
     sender: &Arc<Mutex<mpsc::Sender<Response>>>,
     name: &str,
     primitive: &gltf::Primitive,
+    weights: Option<&[f32]>,
     buffers: &[Vec<u8>],
 ) -> Result<(), ImportError> {
     let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
@@ -204,6 +205,19 @@ fn load_mesh(
         mesh.with_vertices(joints.into_u16().collect::<Vec<[u16; 4]>>().as_slice());
     }
 
+    for (positions, normals, _) in reader.read_morph_targets() {
+        let positions = positions
+            .map(|p| p.collect::<Vec<[f32; 3]>>())
+            .unwrap_or_else(|| vec![[0.0; 3]; mesh.vertices.len()]);
+        let normals = normals.map(|n| n.collect::<Vec<[f32; 3]>>());
+        mesh.with_morph_target(&positions, normals.as_deref());
+    }
+    if let Some(weights) = weights {
+        for (default, &weight) in mesh.morph_weights.iter_mut().zip(weights.iter()) {
+            *default = weight;
+        }
+    }
+
     let name = [name, "mesh"].join("::");
 
     info!("import mesh as `{}`", name);
@@ -0,0 +1,258 @@
+use std::{
+    convert::TryInto,
+    sync::{mpsc, Arc, Mutex},
+};
+
+use wgpu::TextureFormat as WgpuTextureFormat;
+
+use crate::renderer::{StorageTextureAccess, TextureBuffer, TextureFormat};
+
+use super::{
+    loader::{Asset, ImportError, Response},
+    texture::Texture,
+};
+
+const IDENTIFIER: [u8; 12] = [171, 75, 84, 88, 32, 50, 48, 187, 13, 10, 26, 10];
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+/// Loads KTX2 texture with its mip levels, layers and cube map faces
+pub fn load_ktx2(
+    sender: &Arc<Mutex<mpsc::Sender<Response>>>,
+    name: String,
+    data: Vec<u8>,
+) -> Result<(), ImportError> {
+    let texture = Asset {
+        name,
+        asset: Box::new(parse_ktx2(&data)?),
+    };
+    sender
+        .lock()
+        .unwrap()
+        .send(Response::Texture(texture))
+        .unwrap();
+    Ok(())
+}
+
+/// Parses KTX2 container into the texture
+///
+/// Basis Universal and supercompressed textures are not supported yet.
+pub(crate) fn parse_ktx2(data: &[u8]) -> Result<Texture, ImportError> {
+    if data.len() < HEADER_SIZE || data[0..12] != IDENTIFIER {
+        return Err(ImportError::Corruption("KTX2 identifier"));
+    }
+    let vk_format = read_u32(data, 12)?;
+    let width = read_u32(data, 20)?;
+    let height = read_u32(data, 24)?.max(1);
+    let pixel_depth = read_u32(data, 28)?;
+    let layers = read_u32(data, 32)?.max(1);
+    let faces = read_u32(data, 36)?.max(1);
+    let mip_levels = read_u32(data, 40)?;
+    let supercompression = read_u32(data, 44)?;
+
+    if pixel_depth > 0 {
+        return Err(ImportError::NotImplemented("KTX2 3D texture", None));
+    }
+    if supercompression != 0 {
+        return Err(ImportError::NotImplemented(
+            "KTX2 supercompression scheme",
+            Some(supercompression.to_string()),
+        ));
+    }
+    let format = match vk_format {
+        0 => {
+            return Err(ImportError::NotImplemented(
+                "Basis Universal transcoding",
+                None,
+            ))
+        }
+        format => TextureFormat {
+            wgpu_texture_format: texture_format(format).ok_or_else(|| {
+                ImportError::NotImplemented("KTX2 format", Some(format.to_string()))
+            })?,
+        },
+    };
+
+    // levels store all images of the level, texture data stores all levels of the image
+    let images = (layers * faces) as usize;
+    // zero levels request generation of the mip levels from the first one
+    let stored_levels = mip_levels.max(1);
+    let mut levels = Vec::with_capacity(stored_levels as usize);
+    for level in 0..stored_levels {
+        let entry = HEADER_SIZE + level as usize * LEVEL_INDEX_ENTRY_SIZE;
+        let offset = read_u64(data, entry)? as usize;
+        let length = read_u64(data, entry + 8)? as usize;
+        let image_size = format.data_size((width >> level).max(1), (height >> level).max(1));
+        if length < image_size * images {
+            return Err(ImportError::Corruption("KTX2 level size"));
+        }
+        let level_data = data
+            .get(offset..offset + image_size * images)
+            .ok_or(ImportError::Corruption("KTX2 level offset"))?;
+        levels.push((level_data, image_size));
+    }
+    let mut texture_data = Vec::with_capacity(levels.iter().map(|(d, _)| d.len()).sum());
+    for image in 0..images {
+        for (level_data, image_size) in levels.iter() {
+            let start = image * image_size;
+            texture_data.extend_from_slice(&level_data[start..start + image_size]);
+        }
+    }
+
+    Ok(Texture {
+        width,
+        height,
+        depth: images as u32,
+        mip_levels,
+        data: texture_data,
+        buffer: TextureBuffer::new(StorageTextureAccess::Read, format),
+        ..Default::default()
+    })
+}
+
+/// Returns texture format of the Vulkan format
+fn texture_format(vk_format: u32) -> Option<WgpuTextureFormat> {
+    Some(match vk_format {
+        9 => WgpuTextureFormat::R8Unorm,
+        16 => WgpuTextureFormat::Rg8Unorm,
+        37 => WgpuTextureFormat::Rgba8Unorm,
+        43 => WgpuTextureFormat::Rgba8UnormSrgb,
+        44 => WgpuTextureFormat::Bgra8Unorm,
+        50 => WgpuTextureFormat::Bgra8UnormSrgb,
+        76 => WgpuTextureFormat::R16Float,
+        83 => WgpuTextureFormat::Rg16Float,
+        97 => WgpuTextureFormat::Rgba16Float,
+        100 => WgpuTextureFormat::R32Float,
+        103 => WgpuTextureFormat::Rg32Float,
+        109 => WgpuTextureFormat::Rgba32Float,
+        122 => WgpuTextureFormat::Rg11b10Float,
+        123 => WgpuTextureFormat::Rgb9e5Ufloat,
+        131 | 133 => WgpuTextureFormat::Bc1RgbaUnorm,
+        132 | 134 => WgpuTextureFormat::Bc1RgbaUnormSrgb,
+        135 => WgpuTextureFormat::Bc2RgbaUnorm,
+        136 => WgpuTextureFormat::Bc2RgbaUnormSrgb,
+        137 => WgpuTextureFormat::Bc3RgbaUnorm,
+        138 => WgpuTextureFormat::Bc3RgbaUnormSrgb,
+        139 => WgpuTextureFormat::Bc4RUnorm,
+        140 => WgpuTextureFormat::Bc4RSnorm,
+        141 => WgpuTextureFormat::Bc5RgUnorm,
+        142 => WgpuTextureFormat::Bc5RgSnorm,
+        143 => WgpuTextureFormat::Bc6hRgbUfloat,
+        144 => WgpuTextureFormat::Bc6hRgbSfloat,
+        145 => WgpuTextureFormat::Bc7RgbaUnorm,
+        146 => WgpuTextureFormat::Bc7RgbaUnormSrgb,
+        147 => WgpuTextureFormat::Etc2Rgb8Unorm,
+        148 => WgpuTextureFormat::Etc2Rgb8UnormSrgb,
+        149 => WgpuTextureFormat::Etc2Rgb8A1Unorm,
+        150 => WgpuTextureFormat::Etc2Rgb8A1UnormSrgb,
+        151 => WgpuTextureFormat::Etc2Rgba8Unorm,
+        152 => WgpuTextureFormat::Etc2Rgba8UnormSrgb,
+        153 => WgpuTextureFormat::EacR11Unorm,
+        154 => WgpuTextureFormat::EacR11Snorm,
+        155 => WgpuTextureFormat::EacRg11Unorm,
+        156 => WgpuTextureFormat::EacRg11Snorm,
+        157 => WgpuTextureFormat::Astc4x4RgbaUnorm,
+        158 => WgpuTextureFormat::Astc4x4RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ImportError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ImportError::Corruption("KTX2 header"))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, ImportError> {
+    data.get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ImportError::Corruption("KTX2 level index"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ktx2(vk_format: u32, size: u32, faces: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = IDENTIFIER.to_vec();
+        for value in [
+            vk_format,
+            1,
+            size,
+            size,
+            0,
+            0,
+            faces,
+            levels.len() as u32,
+            0,
+        ]
+        .iter()
+        {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.resize(HEADER_SIZE, 0);
+        let mut offset = HEADER_SIZE + levels.len() * LEVEL_INDEX_ENTRY_SIZE;
+        for level in levels.iter() {
+            for value in [offset, level.len(), level.len()].iter() {
+                data.extend_from_slice(&(*value as u64).to_le_bytes());
+            }
+            offset += level.len();
+        }
+        for level in levels.iter() {
+            data.extend_from_slice(level);
+        }
+        data
+    }
+
+    #[test]
+    fn mip_levels_are_grouped_by_face() {
+        // 2x2 RGBA cube map, faces are filled with their index plus 10 times the level
+        let levels = (0..2)
+            .map(|level| {
+                let pixels = 4 >> (2 * level);
+                (0..6u8)
+                    .flat_map(|face| vec![face + 10 * level as u8; pixels * 4])
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let texture = parse_ktx2(&ktx2(43, 2, 6, &levels)).unwrap();
+
+        assert_eq!((texture.width, texture.height), (2, 2));
+        assert_eq!(texture.depth, 6);
+        assert_eq!(texture.mip_levels, 2);
+        assert_eq!(texture.data.len(), 6 * (16 + 4));
+        let face = &texture.data[20..40];
+        assert!(face[..16].iter().all(|&b| b == 1));
+        assert!(face[16..].iter().all(|&b| b == 11));
+    }
+
+    #[test]
+    fn compressed_levels_are_padded_to_blocks() {
+        // 8x8 BC7 with 4 blocks, then 1 block for 4x4, 2x2 and 1x1
+        let levels = [64, 16, 16, 16]
+            .iter()
+            .map(|&size| vec![7; size])
+            .collect::<Vec<_>>();
+        let texture = parse_ktx2(&ktx2(146, 8, 1, &levels)).unwrap();
+
+        assert_eq!(texture.mip_levels, 4);
+        assert_eq!(texture.data.len(), 112);
+        assert!(texture.buffer.format().is_compressed());
+    }
+
+    #[test]
+    fn basis_and_broken_files_are_rejected() {
+        assert!(matches!(
+            parse_ktx2(&ktx2(0, 4, 1, &[vec![0; 16]])),
+            Err(ImportError::NotImplemented(..))
+        ));
+        assert!(matches!(
+            parse_ktx2(&ktx2(43, 4, 1, &[vec![0; 8]])),
+            Err(ImportError::Corruption(..))
+        ));
+        assert!(matches!(
+            parse_ktx2(b"not a texture"),
+            Err(ImportError::Corruption(..))
+        ));
+    }
+}
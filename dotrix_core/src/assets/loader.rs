@@ -8,9 +8,11 @@ use std::{
 
 use log::error;
 
+use crate::renderer::{StorageTextureAccess, TextureBuffer, TextureFormat};
+
 use super::{
-    animation::Animation, load_gltf::load_gltf, mesh::Mesh, shader::Shader, skin::Skin,
-    texture::Texture,
+    animation::Animation, load_gltf::load_gltf, load_ktx2::load_ktx2, mesh::Mesh, shader::Shader,
+    skin::Skin, texture::Texture,
 };
 
 /// Asset loading task
@@ -129,6 +131,8 @@ fn import_resource(
                 buffer,
                 image::ImageFormat::from_extension(extension).unwrap(),
             ),
+            "hdr" => load_hdr(sender, name, buffer),
+            "ktx2" => load_ktx2(sender, name, buffer),
             "wgsl" => load_wgsl(sender, name, buffer),
             "gltf" | "gltb" => load_gltf(sender, name, buffer, &task.path),
             _ => Err(ImportError::NotImplemented("extension", None)),
@@ -155,6 +159,7 @@ pub(crate) fn load_image(
             width,
             height,
             depth: 1,
+            mip_levels: 0,
             data: image.into_vec(),
             ..Default::default()
         }),
@@ -167,6 +172,39 @@ pub(crate) fn load_image(
     Ok(())
 }
 
+/// Loads Radiance HDR image as the texture of 32 bit floats
+pub(crate) fn load_hdr(
+    sender: &Arc<Mutex<mpsc::Sender<Response>>>,
+    name: String,
+    data: Vec<u8>,
+) -> Result<(), ImportError> {
+    let decoder = image::codecs::hdr::HdrDecoder::new(data.as_slice())?;
+    let metadata = decoder.metadata();
+    let pixels = decoder
+        .read_image_hdr()?
+        .iter()
+        .map(|pixel| [pixel[0], pixel[1], pixel[2], 1.0])
+        .collect::<Vec<[f32; 4]>>();
+
+    let texture = Asset {
+        name,
+        asset: Box::new(Texture {
+            width: metadata.width,
+            height: metadata.height,
+            depth: 1,
+            data: bytemuck::cast_slice(&pixels).to_vec(),
+            buffer: TextureBuffer::new(StorageTextureAccess::Read, TextureFormat::rgba_f32()),
+            ..Default::default()
+        }),
+    };
+    sender
+        .lock()
+        .unwrap()
+        .send(Response::Texture(texture))
+        .unwrap();
+    Ok(())
+}
+
 pub(crate) fn load_wgsl(
     sender: &Arc<Mutex<mpsc::Sender<Response>>>,
     name: String,
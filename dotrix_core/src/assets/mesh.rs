@@ -1,5 +1,5 @@
 //! Mesh Asset
-use crate::renderer::{AttributeFormat, Renderer, VertexBuffer};
+use crate::renderer::{AttributeFormat, Renderer, StorageBuffer, VertexBuffer};
 use bytemuck::{Pod, Zeroable};
 use dotrix_math::{InnerSpace, Vec2, Vec3, VectorSpace};
 use std::marker::PhantomData;
@@ -19,6 +19,21 @@ pub struct Mesh {
     pub vertex_buffer: VertexBuffer,
     /// Flag to react on the mesh changes
     pub changed: bool,
+    /// Morph targets (blend shapes) of the mesh
+    pub morph_targets: Vec<MorphTarget>,
+    /// Default weights of the morph targets
+    pub morph_weights: Vec<f32>,
+    /// Buffer of the morph targets deltas, see [`Mesh::load_morph_targets`]
+    pub morph_buffer: StorageBuffer,
+}
+
+/// Deltas of the mesh vertices, blended by the weight of the target
+#[derive(Debug, Default, Clone)]
+pub struct MorphTarget {
+    /// Deltas of the vertices positions
+    pub positions: Vec<[f32; 3]>,
+    /// Deltas of the vertices normals
+    pub normals: Vec<[f32; 3]>,
 }
 
 impl Mesh {
@@ -107,6 +122,56 @@ impl Mesh {
     /// Unloads the [`Mesh`] buffer
     pub fn unload(&mut self) {
         self.vertex_buffer.empty();
+        self.morph_buffer.empty();
+    }
+
+    /// Adds morph target with the deltas of the vertices positions and optional normals
+    pub fn with_morph_target(&mut self, positions: &[[f32; 3]], normals: Option<&[[f32; 3]]>) {
+        if self.vertices.len() != positions.len() {
+            panic!("Morph target deltas should have the size of the vertices array");
+        }
+        let normals = normals
+            .map(Vec::from)
+            .unwrap_or_else(|| vec![[0.0; 3]; positions.len()]);
+        if normals.len() != positions.len() {
+            panic!("Morph target deltas should have the size of the vertices array");
+        }
+        self.morph_targets.push(MorphTarget {
+            positions: Vec::from(positions),
+            normals,
+        });
+        self.morph_weights.push(0.0);
+        self.morph_buffer.empty();
+    }
+
+    /// Loads the morph targets deltas into the storage buffer
+    ///
+    /// Deltas of the target `t` of the vertex `v` are packed as position and normal `vec4`
+    /// pairs starting from the `(v * targets + t) * 2` element. Meshes without targets get
+    /// a buffer of zeros, so the shaders always have something to bind.
+    pub fn load_morph_targets(&mut self, renderer: &Renderer) {
+        if !self.morph_buffer.is_empty() {
+            return;
+        }
+        let deltas = self.morph_deltas();
+        renderer.load_storage_buffer(&mut self.morph_buffer, bytemuck::cast_slice(&deltas));
+    }
+
+    /// Returns morph targets deltas packed for the GPU
+    fn morph_deltas(&self) -> Vec<[f32; 4]> {
+        if self.morph_targets.is_empty() {
+            return vec![[0.0; 4]; 2];
+        }
+        let mut deltas = Vec::with_capacity(self.vertices.len() * self.morph_targets.len() * 2);
+        for vertex in 0..self.vertices.len() {
+            for target in self.morph_targets.iter() {
+                let [x, y, z] = target.positions[vertex];
+                deltas.push([x, y, z, 0.0]);
+                let [x, y, z] = target.normals[vertex];
+                deltas.push([x, y, z, 0.0]);
+            }
+        }
+        deltas
     }
 
     /// Returns actual mesh vertex buffer layout
@@ -323,6 +388,33 @@ where
 mod tests {
     use super::*;
 
+    #[test]
+    fn morph_deltas_are_packed_by_vertex() {
+        let mut mesh = Mesh::default();
+        assert_eq!(mesh.morph_deltas(), vec![[0.0; 4]; 2]);
+
+        mesh.with_vertices(&[[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0]]);
+        mesh.with_morph_target(&[[1.0, 0.0, 0.0], [2.0, 0.0, 0.0]], None);
+        mesh.with_morph_target(
+            &[[3.0, 0.0, 0.0], [4.0, 0.0, 0.0]],
+            Some(&[[0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]),
+        );
+        assert_eq!(mesh.morph_weights, vec![0.0, 0.0]);
+        assert_eq!(
+            mesh.morph_deltas(),
+            vec![
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [3.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [2.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [4.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+            ]
+        );
+    }
+
     #[test]
     fn test_vertices_as() {
         let mut width = 2.0;
@@ -1,7 +1,25 @@
 //! Shader Asset
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use log::error;
+
 use crate::renderer::{Renderer, ShaderModule};
 
 /// Shader Asset
+///
+/// The code is preprocessed, when the shader is loaded:
+/// - `{{ include(name) }}` lines are replaced by the code registered with
+///   [`Renderer::add_shader_include`], every include is inserted once;
+/// - lines between `#ifdef NAME` or `#ifndef NAME`, optional `#else` and `#endif` are kept
+///   depending on the [`Shader::defines`] and the names set by `#define NAME` lines;
+/// - `{{ NAME }}` is replaced by the value of the `NAME=VALUE` define or of the
+///   `#define NAME VALUE` line.
+///
+/// Pipelines specialize the shader by a [`ShaderVariant`], every used combination of the
+/// flags is compiled once.
+///
+/// Preprocessing errors are logged and the shader keeps the previously built module, so a typo
+/// in a hot reloaded file does not stop the application.
 #[derive(Default)]
 pub struct Shader {
     /// Shader name
@@ -10,13 +28,34 @@ pub struct Shader {
     pub code: String,
     /// Shader Module
     pub module: ShaderModule,
+    /// Names defined for the `#ifdef` blocks of the code, `NAME=VALUE` also sets the value
+    pub defines: Vec<String>,
+    /// Code imported again by the hot reload, replaces the `code` on the next load, if it can be
+    /// preprocessed
+    pub pending_code: Option<String>,
 }
 
 impl Shader {
     /// Loads the shader to GPU
+    ///
+    /// The pending code replaces the current one, if it can be preprocessed, otherwise the error
+    /// is logged and the shader stays unchanged.
     pub fn load(&mut self, renderer: &Renderer) {
+        if let Some(code) = self.pending_code.take() {
+            match preprocess(&code, renderer.shader_includes(), &self.defines) {
+                Ok(preprocessed) => {
+                    self.code = code;
+                    renderer.load_shader_module(&mut self.module, &self.name, &preprocessed);
+                    return;
+                }
+                Err(err) => error!("Shader `{}` is not reloaded: {}", self.name, err),
+            }
+        }
         if !self.module.loaded() {
-            renderer.load_shader_module(&mut self.module, &self.name, &self.code);
+            match preprocess(&self.code, renderer.shader_includes(), &self.defines) {
+                Ok(code) => renderer.load_shader_module(&mut self.module, &self.name, &code),
+                Err(err) => error!("Shader `{}`: {}", self.name, err),
+            }
         }
     }
 
@@ -24,4 +63,325 @@ impl Shader {
     pub fn loaded(&self) -> bool {
         self.module.loaded()
     }
+
+    /// Compiles the module of the shader specialized by the variant
+    pub fn load_variant(
+        &self,
+        renderer: &Renderer,
+        variant: &ShaderVariant,
+    ) -> Result<ShaderModule, ShaderError> {
+        let defines = variant.defines(&self.defines);
+        let code = preprocess(&self.code, renderer.shader_includes(), &defines)?;
+        let mut module = ShaderModule::default();
+        let name = format!("{} {}", self.name, variant);
+        renderer.load_shader_module(&mut module, &name, &code);
+        Ok(module)
+    }
+}
+
+/// Combination of the shader flags, a [`crate::Pipeline`] is specialized by
+///
+/// Flags override the [`Shader::defines`] of the same names, the combinations are cached by
+/// the renderer, so a single shader asset serves all of them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ShaderVariant {
+    /// Flags by their names: undefined, defined or defined with the value
+    flags: BTreeMap<String, Option<String>>,
+}
+
+impl ShaderVariant {
+    /// Constructs the variant of the shader defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defines or undefines the boolean flag, like `SKINNED`
+    #[must_use]
+    pub fn flag(mut self, name: &str, enabled: bool) -> Self {
+        self.flags
+            .insert(String::from(name), enabled.then(String::new));
+        self
+    }
+
+    /// Defines the flag with the value, like `NUM_CASCADES`, replacing `{{ NAME }}` in the code
+    #[must_use]
+    pub fn value(mut self, name: &str, value: impl std::fmt::Display) -> Self {
+        self.flags
+            .insert(String::from(name), Some(value.to_string()));
+        self
+    }
+
+    /// Returns true if the variant does not change the shader defaults
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty()
+    }
+
+    /// Returns the shader defines overridden by the flags
+    fn defines(&self, defaults: &[String]) -> Vec<String> {
+        defaults
+            .iter()
+            .filter(|define| !self.flags.contains_key(define_name(define)))
+            .cloned()
+            .chain(self.flags.iter().filter_map(|(name, value)| {
+                value.as_ref().map(|value| {
+                    if value.is_empty() {
+                        name.clone()
+                    } else {
+                        format!("{}={}", name, value)
+                    }
+                })
+            }))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for ShaderVariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[")?;
+        for (index, define) in self.defines(&[]).iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", define)?;
+        }
+        write!(f, "]")
+    }
+}
+
+fn define_name(define: &str) -> &str {
+    define.split('=').next().unwrap_or(define)
+}
+
+/// Shader preprocessing errors
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShaderError {
+    /// Code includes the name, that was not registered
+    UnknownInclude(String),
+    /// Directive is not supported or is out of its `#ifdef` block
+    InvalidDirective(String),
+    /// `#ifdef` block has no `#endif`
+    UnterminatedCondition,
+}
+
+impl std::error::Error for ShaderError {}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShaderError::UnknownInclude(name) => write!(f, "Unknown include `{}`", name),
+            ShaderError::InvalidDirective(line) => write!(f, "Invalid directive `{}`", line),
+            ShaderError::UnterminatedCondition => write!(f, "Missing `#endif`"),
+        }
+    }
+}
+
+/// Resolves includes and conditional blocks of the WGSL code, see [`Shader`]
+pub fn preprocess(
+    code: &str,
+    includes: &HashMap<String, String>,
+    defines: &[String],
+) -> Result<String, ShaderError> {
+    let mut preprocessor = Preprocessor {
+        includes,
+        defines: defines
+            .iter()
+            .map(|define| match define.split_once('=') {
+                Some((name, value)) => (String::from(name), String::from(value)),
+                None => (define.clone(), String::new()),
+            })
+            .collect(),
+        included: HashSet::new(),
+        output: String::with_capacity(code.len()),
+    };
+    preprocessor.process(code)?;
+    Ok(preprocessor.output)
+}
+
+struct Preprocessor<'a> {
+    includes: &'a HashMap<String, String>,
+    /// Defined names and their values, empty for the flags
+    defines: HashMap<String, String>,
+    included: HashSet<String>,
+    output: String,
+}
+
+/// `#ifdef` block: lines are kept, and `#else` was met
+struct Condition {
+    enabled: bool,
+    otherwise: bool,
+}
+
+impl<'a> Preprocessor<'a> {
+    fn process(&mut self, code: &'a str) -> Result<(), ShaderError> {
+        let mut conditions: Vec<Condition> = Vec::new();
+        for line in code.lines() {
+            let trimmed = line.trim();
+            let active = conditions.iter().all(|condition| condition.enabled);
+
+            if let Some(directive) = trimmed.strip_prefix('#') {
+                let invalid = || ShaderError::InvalidDirective(String::from(trimmed));
+                let mut words = directive.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some("ifdef"), Some(name), None) => conditions.push(Condition {
+                        enabled: self.defines.contains_key(name),
+                        otherwise: false,
+                    }),
+                    (Some("ifndef"), Some(name), None) => conditions.push(Condition {
+                        enabled: !self.defines.contains_key(name),
+                        otherwise: false,
+                    }),
+                    (Some("else"), None, None) => {
+                        let condition = conditions
+                            .last_mut()
+                            .filter(|condition| !condition.otherwise)
+                            .ok_or_else(invalid)?;
+                        condition.enabled = !condition.enabled;
+                        condition.otherwise = true;
+                    }
+                    (Some("endif"), None, None) => {
+                        conditions.pop().ok_or_else(invalid)?;
+                    }
+                    (Some("define"), Some(name), value) => {
+                        if words.next().is_some() {
+                            return Err(invalid());
+                        }
+                        if active {
+                            let value = String::from(value.unwrap_or_default());
+                            self.defines.insert(String::from(name), value);
+                        }
+                    }
+                    _ => return Err(invalid()),
+                }
+                continue;
+            }
+
+            if !active {
+                continue;
+            }
+
+            let include = trimmed
+                .strip_prefix("{{ include(")
+                .and_then(|rest| rest.strip_suffix(") }}"));
+            if let Some(name) = include {
+                if self.included.insert(String::from(name)) {
+                    let includes = self.includes;
+                    let code = includes
+                        .get(name)
+                        .ok_or_else(|| ShaderError::UnknownInclude(String::from(name)))?;
+                    self.process(code)?;
+                }
+                continue;
+            }
+
+            self.substitute(line);
+            self.output.push('\n');
+        }
+
+        if conditions.is_empty() {
+            Ok(())
+        } else {
+            Err(ShaderError::UnterminatedCondition)
+        }
+    }
+
+    /// Writes the line with the `{{ NAME }}` of the defined values replaced
+    fn substitute(&mut self, line: &str) {
+        let (defines, output) = (&self.defines, &mut self.output);
+        let mut rest = line;
+        while let Some(start) = rest.find("{{ ") {
+            let value = rest[start + 3..].find(" }}").and_then(|end| {
+                let name = &rest[start + 3..start + 3 + end];
+                defines
+                    .get(name)
+                    .filter(|value| !value.is_empty())
+                    .map(|value| (value, start + 3 + end + 3))
+            });
+            match value {
+                Some((value, end)) => {
+                    output.push_str(&rest[..start]);
+                    output.push_str(value);
+                    rest = &rest[end..];
+                }
+                None => {
+                    output.push_str(&rest[..start + 3]);
+                    rest = &rest[start + 3..];
+                }
+            }
+        }
+        output.push_str(rest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_and_conditions_are_resolved() {
+        let mut includes = HashMap::new();
+        includes.insert(String::from("camera"), String::from("var camera;"));
+        includes.insert(
+            String::from("noise"),
+            String::from(
+                "{{ include(camera) }}\n#ifdef FBM\nfn fbm();\n#else\nfn noise();\n#endif",
+            ),
+        );
+        let code = "{{ include(camera) }}\n{{ include(noise) }}\n#ifndef COLOR\n#define FLAT\n#endif\n#ifdef FLAT\nfn flat();\n#endif";
+
+        assert_eq!(
+            preprocess(code, &includes, &[]).unwrap(),
+            "var camera;\nfn noise();\nfn flat();\n"
+        );
+        assert_eq!(
+            preprocess(
+                code,
+                &includes,
+                &[String::from("FBM"), String::from("COLOR")]
+            )
+            .unwrap(),
+            "var camera;\nfn fbm();\n"
+        );
+    }
+
+    #[test]
+    fn variants_override_defines_and_values() {
+        let includes = HashMap::new();
+        let code = "#ifdef SKINNED\nfn skin();\n#endif\nlet CASCADES: u32 = {{ NUM_CASCADES }}u;\n#define SIZE 4\nvar x: array<f32, {{ SIZE }}>; // {{ unknown }}";
+        let defaults = [String::from("SKINNED"), String::from("NUM_CASCADES=1")];
+        let variant = ShaderVariant::new()
+            .flag("SKINNED", false)
+            .flag("SHADOWS", true)
+            .value("NUM_CASCADES", 4);
+
+        assert_eq!(variant.to_string(), "[NUM_CASCADES=4, SHADOWS]");
+        assert_eq!(
+            preprocess(code, &includes, &ShaderVariant::new().defines(&defaults)).unwrap(),
+            "fn skin();\nlet CASCADES: u32 = 1u;\nvar x: array<f32, 4>; // {{ unknown }}\n"
+        );
+        assert_eq!(
+            preprocess(code, &includes, &variant.defines(&defaults)).unwrap(),
+            "let CASCADES: u32 = 4u;\nvar x: array<f32, 4>; // {{ unknown }}\n"
+        );
+    }
+
+    #[test]
+    fn invalid_code_is_reported() {
+        let includes = HashMap::new();
+        assert_eq!(
+            preprocess("{{ include(light) }}", &includes, &[]).unwrap_err(),
+            ShaderError::UnknownInclude(String::from("light"))
+        );
+        assert_eq!(
+            preprocess("#endif", &includes, &[]).unwrap_err(),
+            ShaderError::InvalidDirective(String::from("#endif"))
+        );
+        assert_eq!(
+            preprocess("#ifdef A\n#else\n#else\n#endif", &includes, &[]).unwrap_err(),
+            ShaderError::InvalidDirective(String::from("#else"))
+        );
+        assert_eq!(
+            preprocess("#ifdef A", &includes, &[]).unwrap_err(),
+            ShaderError::UnterminatedCondition
+        );
+    }
 }
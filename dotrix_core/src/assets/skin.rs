@@ -6,9 +6,6 @@ use crate::{
 use dotrix_math::{Mat4, SquareMatrix};
 use std::collections::HashMap;
 
-/// Maximal number of joints in skin
-pub const MAX_JOINTS: usize = 32;
-
 /// Joint identificator (unsigned integer)
 pub type JointId = usize;
 
@@ -120,6 +117,9 @@ impl Skin {
         model_transform: &Mat4,
         local_transforms: Option<HashMap<JointId, TransformBuilder>>,
     ) {
+        skin_transform
+            .joints
+            .resize_with(self.joints.len(), JointTransform::default);
         for (i, joint) in self.joints.iter().enumerate() {
             let parent_transform = joint
                 .parent_id
@@ -132,11 +132,7 @@ impl Skin {
                 .map(|l| l.get(&joint.id))
                 .unwrap_or(None);
 
-            if i < skin_transform.joints.len() {
-                skin_transform.joints[i] = joint.transform(&parent_transform, local_transform);
-            } else {
-                panic!("Joints count exceeds limit of {:?}", MAX_JOINTS);
-            }
+            skin_transform.joints[i] = joint.transform(&parent_transform, local_transform);
         }
     }
 }
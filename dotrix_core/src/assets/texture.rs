@@ -1,5 +1,5 @@
 //! Texture asset
-use crate::renderer::{Renderer, TextureBuffer, TextureUsages};
+use crate::renderer::{Renderer, StorageTextureAccess, TextureBuffer, TextureUsages};
 
 /// Texture asset
 pub struct Texture {
@@ -7,9 +7,14 @@ pub struct Texture {
     pub width: u32,
     /// Texture height in pixels
     pub height: u32,
-    /// Texture depth
+    /// Number of the texture layers, 6 for the cube maps
     pub depth: u32,
+    /// Number of the mip levels, zero generates the full chain on GPU from the first level
+    pub mip_levels: u32,
     /// Raw texture data
+    ///
+    /// Layers follow one another, each of them contains its mip levels starting from the
+    /// largest one.
     pub data: Vec<u8>,
     /// Permitted texture usages
     pub usages: TextureUsages,
@@ -25,6 +30,7 @@ impl Default for Texture {
             width: 0,
             height: 0,
             depth: 0,
+            mip_levels: 1,
             data: vec![],
             usages: TextureUsages::create().texture().write(),
             buffer: Default::default(),
@@ -40,18 +46,85 @@ impl Texture {
             return;
         }
 
-        renderer.load_texture_buffer_with_usage(
+        let layers = self.depth.max(1) as usize;
+        let layers = self
+            .data
+            .chunks((self.data.len() / layers).max(1))
+            .collect::<Vec<_>>();
+        renderer.load_texture_buffer_with_mips(
             &mut self.buffer,
             self.width,
             self.height,
-            &[&self.data],
+            self.mip_levels,
+            &layers,
             self.usages,
         );
         self.changed = false;
     }
 
+    /// Constructs a texture array from the layers of the same size, format and mip levels
+    ///
+    /// Texture of more than one layer can be bound as [`crate::renderer::Binding::TextureArray`],
+    /// `None` is returned if the layers do not match.
+    pub fn from_layers(layers: &[&Texture]) -> Option<Self> {
+        let first = layers.first()?;
+        let format = first.buffer.format();
+        let matching = layers.iter().all(|layer| {
+            layer.width == first.width
+                && layer.height == first.height
+                && layer.mip_levels == first.mip_levels
+                && layer.data.len() == first.data.len()
+                && layer.buffer.format().wgpu_texture_format == format.wgpu_texture_format
+        });
+        if !matching {
+            return None;
+        }
+        Some(Self {
+            width: first.width,
+            height: first.height,
+            depth: layers.iter().map(|layer| layer.depth.max(1)).sum(),
+            mip_levels: first.mip_levels,
+            data: layers
+                .iter()
+                .flat_map(|layer| layer.data.iter().copied())
+                .collect(),
+            usages: first.usages,
+            buffer: TextureBuffer::new(StorageTextureAccess::Read, format),
+            changed: true,
+        })
+    }
+
     /// Unloads the [`Texture`] data from the buffer
     pub fn unload(&mut self) {
         self.buffer.unload();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(width: u32, value: u8) -> Texture {
+        Texture {
+            width,
+            height: 1,
+            depth: 1,
+            data: vec![value; width as usize * 4],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn layers_are_stacked_in_order() {
+        let array = Texture::from_layers(&[&layer(2, 1), &layer(2, 2), &layer(2, 3)]).unwrap();
+        assert_eq!(array.depth, 3);
+        assert_eq!(array.data.len(), 24);
+        assert_eq!(array.data[8..16], [2; 8]);
+    }
+
+    #[test]
+    fn mismatching_layers_are_rejected() {
+        assert!(Texture::from_layers(&[&layer(2, 1), &layer(4, 2)]).is_none());
+        assert!(Texture::from_layers(&[]).is_none());
+    }
+}
@@ -1,11 +1,12 @@
 //! Dotrix camera implementation
 use crate::{
     ecs::{Const, Mut},
+    frustum::Frustum,
     renderer::UniformBuffer,
     services::{Frame, Globals, Input, Renderer, Window},
 };
 
-use dotrix_math::{perspective, Mat4, Point3, Rad, Vec3};
+use dotrix_math::{perspective, Mat4, Point3, Rad, Vec2, Vec3, Vec4};
 use std::f32::consts::PI;
 
 const ROTATE_SPEED: f32 = PI / 10.0;
@@ -38,6 +39,11 @@ pub struct Camera {
     pub far_plane: f32,
     /// Projection matrix
     pub proj: Option<Mat4>,
+    /// Subpixel offset of the projection in normalized device coordinates
+    ///
+    /// The offset is applied to the `ProjView` binding only, temporal anti-aliasing changes it
+    /// every frame.
+    pub jitter: Vec2,
 }
 
 impl Camera {
@@ -61,6 +67,7 @@ impl Camera {
             near_plane,
             far_plane,
             proj: None,
+            jitter: Vec2::new(0.0, 0.0),
         }
     }
 
@@ -110,6 +117,28 @@ impl Camera {
     pub fn proj(&self) -> &Mat4 {
         self.proj.as_ref().expect("Projection matrix must be set")
     }
+
+    /// Returns projection matrix with the jitter applied
+    pub fn jittered_proj(&self) -> Option<Mat4> {
+        self.proj
+            .map(|proj| Mat4::from_translation(Vec3::new(self.jitter.x, self.jitter.y, 0.0)) * proj)
+    }
+
+    /// Returns distance from the camera to the point along the view direction
+    ///
+    /// Transparent draws are sorted by it, see [`crate::renderer::Options::depth`].
+    pub fn view_depth(&self, point: Vec3) -> f32 {
+        let view = self.view.unwrap_or_else(|| self.view_matrix());
+        -(view * Vec4::new(point.x, point.y, point.z, 1.0)).z
+    }
+
+    /// Returns view frustum, if view and projection matrices are set
+    pub fn frustum(&self) -> Option<Frustum> {
+        match (self.proj.as_ref(), self.view.as_ref()) {
+            (Some(proj), Some(view)) => Some(Frustum::from_matrix(&(proj * view))),
+            _ => None,
+        }
+    }
 }
 
 impl Default for Camera {
@@ -147,7 +176,7 @@ pub fn load(
 
     // Set uniform buffer with proj x view matrix
     if let Some(proj_view) = globals.get_mut::<ProjView>() {
-        let matrix = camera.proj.as_ref().unwrap() * camera.view.as_ref().unwrap();
+        let matrix = camera.jittered_proj().unwrap() * camera.view.as_ref().unwrap();
         let matrix_raw = AsRef::<[f32; 16]>::as_ref(&matrix);
 
         renderer.load_uniform_buffer(&mut proj_view.uniform, bytemuck::cast_slice(matrix_raw));
@@ -20,6 +20,11 @@ pub struct CubeMap {
     pub back: Id<Texture>,
     /// Id of the front cube side
     pub front: Id<Texture>,
+    /// Id of the equirectangular texture, converted to the cube sides on GPU instead of them
+    pub equirectangular: Id<Texture>,
+    /// Size of the converted cube sides in pixels, half of the equirectangular texture height
+    /// if zero
+    pub resolution: u32,
     /// Pipeline buffer
     pub buffer: TextureBuffer,
 }
@@ -31,6 +36,25 @@ impl CubeMap {
             return true;
         }
 
+        if !self.equirectangular.is_null() {
+            let texture = match assets.get_mut(self.equirectangular) {
+                Some(texture) => texture,
+                None => return false,
+            };
+            texture.load(renderer);
+            let resolution = if self.resolution > 0 {
+                self.resolution
+            } else {
+                texture.height / 2
+            };
+            renderer.load_cube_map_from_equirectangular(
+                &mut self.buffer,
+                &texture.buffer,
+                resolution,
+            );
+            return true;
+        }
+
         let faces = [
             self.right,
             self.left,
@@ -0,0 +1,161 @@
+//! Frustum culling of the bounded pipelines
+use dotrix_math::{Mat4, Vec3, Vec4};
+
+use crate::assets::Mesh;
+use crate::ecs::{Const, Mut};
+use crate::{Camera, Frustum, Pipeline, Transform, World};
+
+/// Local axis aligned bounding box of an entity
+///
+/// Entities with the `Bounds`, [`Transform`] and [`Pipeline`] components are culled by the
+/// [`Culling`] service, when their box is outside of the camera frustum.
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    /// Minimal corner of the box
+    pub min: Vec3,
+    /// Maximal corner of the box
+    pub max: Vec3,
+}
+
+impl Bounds {
+    /// Constructs the bounding box from its corners
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// Constructs the bounding box enclosing the points
+    pub fn from_points(points: impl IntoIterator<Item = [f32; 3]>) -> Self {
+        let mut points = points.into_iter();
+        let first = points
+            .next()
+            .map(Vec3::from)
+            .unwrap_or_else(|| Vec3::new(0.0, 0.0, 0.0));
+        points.fold(Self::new(first, first), |bounds, point| Self {
+            min: Vec3::new(
+                bounds.min.x.min(point[0]),
+                bounds.min.y.min(point[1]),
+                bounds.min.z.min(point[2]),
+            ),
+            max: Vec3::new(
+                bounds.max.x.max(point[0]),
+                bounds.max.y.max(point[1]),
+                bounds.max.z.max(point[2]),
+            ),
+        })
+    }
+
+    /// Constructs the bounding box enclosing the vertices of the mesh
+    ///
+    /// The first attribute of the mesh must be the `[f32; 3]` positions.
+    pub fn from_mesh(mesh: &Mesh) -> Self {
+        Self::from_points(mesh.vertices_as::<[f32; 3]>(0))
+    }
+
+    /// Returns the axis aligned box enclosing the bounds transformed by the matrix
+    pub fn transformed(&self, matrix: &Mat4) -> [Vec3; 2] {
+        let corners = (0..8).map(|i| {
+            let corner = Vec4::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+                1.0,
+            );
+            let point = matrix * corner;
+            [point.x, point.y, point.z]
+        });
+        let world = Self::from_points(corners);
+        [world.min, world.max]
+    }
+}
+
+/// Frustum culling service
+///
+/// Every frame the frustum is extracted from the camera and the pipelines of the entities,
+/// which [`Bounds`] are outside of it, are marked as [`Pipeline::culled`], so the renderer
+/// skips their runs.
+pub struct Culling {
+    /// Culling is performed
+    pub enabled: bool,
+    frustum: Option<Frustum>,
+    culled: usize,
+}
+
+impl Default for Culling {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            frustum: None,
+            culled: 0,
+        }
+    }
+}
+
+impl Culling {
+    /// Returns the camera frustum of the current frame
+    pub fn frustum(&self) -> Option<&Frustum> {
+        self.frustum.as_ref()
+    }
+
+    /// Returns number of the entities culled in the current frame
+    pub fn culled(&self) -> usize {
+        self.culled
+    }
+}
+
+/// Frustum culling system
+pub fn cull(mut culling: Mut<Culling>, camera: Const<Camera>, world: Const<World>) {
+    culling.frustum = camera.frustum();
+    culling.culled = 0;
+
+    let frustum = if culling.enabled {
+        culling.frustum
+    } else {
+        None
+    };
+
+    let query = world.query::<(&Bounds, &Transform, &mut Pipeline)>();
+    for (bounds, transform, pipeline) in query {
+        pipeline.culled = frustum
+            .map(|frustum| !frustum.intersects_aligned_box(bounds.transformed(&transform.matrix())))
+            .unwrap_or(false);
+        if pipeline.culled {
+            culling.culled += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dotrix_math::{perspective, Point3, Rad};
+
+    #[test]
+    fn transformed_bounds_are_culled_outside_of_frustum() {
+        let bounds =
+            Bounds::from_points(vec![[-1.0, -1.0, -1.0], [1.0, 2.0, 1.0], [0.0, 0.0, 0.0]]);
+        assert_eq!(bounds.min, Vec3::new(-1.0, -1.0, -1.0));
+        assert_eq!(bounds.max, Vec3::new(1.0, 2.0, 1.0));
+
+        let proj = perspective(Rad(1.0), 1.0, 0.1, 100.0);
+        let view = Mat4::look_at(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::unit_y(),
+        );
+        let frustum = Frustum::from_matrix(&(proj * view));
+
+        let ahead = Transform::builder()
+            .with_translate(Vec3::new(0.0, 0.0, -10.0))
+            .build();
+        let aside = Transform::builder()
+            .with_translate(Vec3::new(50.0, 0.0, -10.0))
+            .with_scale(Vec3::new(2.0, 2.0, 2.0))
+            .build();
+
+        let world = bounds.transformed(&aside.matrix());
+        assert_eq!(world[0], Vec3::new(48.0, -2.0, -12.0));
+        assert_eq!(world[1], Vec3::new(52.0, 4.0, -8.0));
+        assert!(frustum.intersects_aligned_box(bounds.transformed(&ahead.matrix())));
+        assert!(!frustum.intersects_aligned_box(world));
+    }
+}
@@ -0,0 +1,83 @@
+//! View frustum implementation
+use dotrix_math::{InnerSpace, Mat4, Vec3, Vec4};
+
+/// View frustum defined by six planes, normals are pointing inside
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far planes (`normal.xyz`, `distance`)
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts frustum planes from the projection view matrix
+    pub fn from_matrix(proj_view: &Mat4) -> Self {
+        let m = proj_view;
+        let row = |i: usize| Vec4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let mut planes = [w + x, w - x, w + y, w - y, w + z, w - z];
+        for plane in planes.iter_mut() {
+            let length = plane.truncate().magnitude();
+            if length > 0.0 {
+                *plane /= length;
+            }
+        }
+        Self { planes }
+    }
+
+    /// Checks if the axis aligned box intersects or is inside of the frustum
+    pub fn intersects_aligned_box(&self, bounds: [Vec3; 2]) -> bool {
+        self.planes.iter().all(|plane| {
+            // the box corner most distant along the plane normal
+            let corner = Vec3::new(
+                if plane.x >= 0.0 {
+                    bounds[1].x
+                } else {
+                    bounds[0].x
+                },
+                if plane.y >= 0.0 {
+                    bounds[1].y
+                } else {
+                    bounds[0].y
+                },
+                if plane.z >= 0.0 {
+                    bounds[1].z
+                } else {
+                    bounds[0].z
+                },
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    /// Checks if the sphere intersects or is inside of the frustum
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dotrix_math::{perspective, Point3, Rad};
+
+    #[test]
+    fn frustum_culls_boxes_behind_camera() {
+        let proj = perspective(Rad(1.0), 1.0, 0.1, 100.0);
+        let view = Mat4::look_at(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::unit_y(),
+        );
+        let frustum = Frustum::from_matrix(&(proj * view));
+
+        let unit = Vec3::new(1.0, 1.0, 1.0);
+        let ahead = Vec3::new(0.0, 0.0, -10.0);
+        let behind = Vec3::new(0.0, 0.0, 10.0);
+        assert!(frustum.intersects_aligned_box([ahead - unit, ahead + unit]));
+        assert!(!frustum.intersects_aligned_box([behind - unit, behind + unit]));
+        assert!(frustum.intersects_sphere(ahead, 1.0));
+        assert!(!frustum.intersects_sphere(Vec3::new(0.0, 0.0, -200.0), 1.0));
+    }
+}
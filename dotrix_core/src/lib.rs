@@ -17,7 +17,9 @@ mod world;
 pub mod animation;
 pub mod assets;
 pub mod camera;
+pub mod culling;
 pub mod ecs;
+pub mod frustum;
 pub mod input;
 pub mod ray;
 pub mod renderer;
@@ -30,15 +32,17 @@ pub use assets::Assets;
 pub use camera::Camera;
 pub use color::Color;
 pub use cubemap::CubeMap;
+pub use culling::{Bounds, Culling};
 pub use ecs::{Priority, RunLevel, StateId, System};
 pub use frame::Frame;
+pub use frustum::Frustum;
 pub use globals::Globals;
 pub use id::Id;
 pub use input::Input;
 pub use pipeline::Pipeline;
 pub use pose::Pose;
 pub use ray::Ray;
-pub use renderer::Renderer;
+pub use renderer::{RenderStats, Renderer};
 pub use state::State;
 pub use transform::Transform;
 pub use window::{Monitor, VideoMode, Window};
@@ -100,6 +104,8 @@ impl Dotrix {
         app.add_service(assets::Assets::default());
         // Camera service
         app.add_service(camera::Camera::default());
+        // Frustum culling service
+        app.add_service(culling::Culling::default());
         // FPS and delta time counter
         app.add_service(frame::Frame::default());
         // Input manager
@@ -108,6 +114,8 @@ impl Dotrix {
         app.add_service(globals::Globals::default());
         // Render manager
         app.add_service(renderer::Renderer::default());
+        // Rendering statistics
+        app.add_service(renderer::RenderStats::default());
         // States stack
         app.add_service(state::State::default());
 
@@ -129,12 +137,16 @@ impl Dotrix {
         app.add_system(System::from(frame::bind));
         // load proj_view matrices
         app.add_system(System::from(camera::load));
+        // Cull the bounded entities outside of the camera frustum
+        app.add_system(System::from(culling::cull));
 
         // Calculate skeletal animations
         app.add_system(System::from(animation::skeletal));
 
         // Finalize frame by Renderer
         app.add_system(System::from(renderer::release));
+        // Collect rendering statistics
+        app.add_system(System::from(renderer::stats));
         // Reset input events
         app.add_system(System::from(input::release));
 
@@ -178,6 +190,15 @@ impl Dotrix {
         let app = self.app.take().unwrap();
         app.run();
     }
+
+    /// Runs the application without a window, rendering `frames` offscreen frames of the size
+    ///
+    /// Headless applications need no display, so they can render in tests, on servers and
+    /// in CI, see [`Application::run_headless`].
+    pub fn run_headless(&mut self, width: u32, height: u32, frames: usize) {
+        let app = self.app.take().unwrap();
+        app.run_headless(width, height, frames);
+    }
 }
 
 /// Trait providing extendablity
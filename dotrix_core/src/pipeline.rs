@@ -1,5 +1,5 @@
 use crate::{
-    assets::Shader,
+    assets::{Shader, ShaderVariant},
     id::Id,
     renderer::{Bindings, Options, Renderer, ScissorsRect},
 };
@@ -9,14 +9,20 @@ use crate::{
 pub struct Pipeline {
     /// [`Id`] of the shader
     pub shader: Id<Shader>,
+    /// Flags the shader is specialized by, changing them requires binding the pipeline again
+    pub variant: ShaderVariant,
     /// Rendering bindings
     pub bindings: Bindings,
     /// rendering cycle
     pub cycle: usize,
     /// is rendering disabled
     pub disabled: bool,
+    /// is the entity outside of the camera frustum, see [`crate::culling::Culling`]
+    pub culled: bool,
     /// Pipeline Options
     pub options: Options,
+    /// Key of the backend pipeline, set when the pipeline is bound
+    pub layout_key: Option<u64>,
 }
 
 impl Pipeline {
@@ -30,6 +36,20 @@ impl Pipeline {
         self.bindings.loaded()
     }
 
+    /// Sets the render graph pass the pipeline runs in
+    #[must_use]
+    pub fn with_pass(mut self, pass: &str) -> Self {
+        self.options.pass = Some(String::from(pass));
+        self
+    }
+
+    /// Sets the flags the shader is specialized by
+    #[must_use]
+    pub fn with_variant(mut self, variant: ShaderVariant) -> Self {
+        self.variant = variant;
+        self
+    }
+
     /// Adds scissors rectangle for rendering
     #[must_use]
     pub fn with_scissors_rect(
@@ -1,10 +1,10 @@
 use crate::{
     assets::{
-        skin::{JointIndex, JointTransform, Skin, MAX_JOINTS},
+        skin::{JointIndex, JointTransform, Skin},
         Assets,
     },
     id::Id,
-    renderer::{Renderer, UniformBuffer},
+    renderer::{Renderer, StorageBuffer},
 };
 
 use dotrix_math::{Mat4, SquareMatrix};
@@ -15,8 +15,8 @@ pub struct Pose {
     pub skin: Id<Skin>,
     /// Transformations of the [`Skin`] joints
     pub joints: Vec<JointTransform>,
-    /// Joints transformations buffer
-    pub uniform: UniformBuffer,
+    /// Joints transformations buffer, skinning of the vertices is done by shaders
+    pub buffer: StorageBuffer,
 }
 
 impl Pose {
@@ -24,8 +24,8 @@ impl Pose {
     pub fn load(&mut self, renderer: &Renderer, assets: &Assets) -> bool {
         if let Some(skin) = assets.get(self.skin) {
             let joints_matrices = self.matrices(&skin.index);
-            renderer.load_uniform_buffer(
-                &mut self.uniform,
+            renderer.load_storage_buffer(
+                &mut self.buffer,
                 bytemuck::cast_slice(joints_matrices.as_slice()),
             );
             return true;
@@ -38,18 +38,22 @@ impl Pose {
         let mut result = index
             .iter()
             .map(|i| {
-                let joint_transform = self.joints.iter().find(|j| j.id == i.id).unwrap();
-                let global_transform = &joint_transform.global_transform;
-                let inverse_bind_matrix = i.inverse_bind_matrix;
-                inverse_bind_matrix
+                let global_transform = self
+                    .joints
+                    .iter()
+                    .find(|j| j.id == i.id)
+                    .map(|j| j.global_transform)
+                    .unwrap_or_else(Mat4::identity);
+                i.inverse_bind_matrix
                     .as_ref()
                     .map(|ibmx| global_transform * ibmx)
-                    .unwrap_or(*global_transform)
+                    .unwrap_or(global_transform)
                     .into()
             })
             .collect::<Vec<_>>();
 
-        while result.len() < MAX_JOINTS {
+        // storage buffers can not be empty
+        if result.is_empty() {
             result.push(Mat4::identity().into());
         }
         result
@@ -61,8 +65,55 @@ impl From<Id<Skin>> for Pose {
     fn from(skin: Id<Skin>) -> Self {
         Self {
             skin,
-            joints: vec![JointTransform::default(); MAX_JOINTS], // 32 -> MAX_JOINTS
-            uniform: UniformBuffer::default(),
+            joints: Vec::new(),
+            buffer: StorageBuffer::new_readonly(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assets::skin::Joint, transform::Transform};
+    use dotrix_math::Vec3;
+
+    #[test]
+    fn joints_are_not_limited() {
+        // every joint moves its child by one unit along the x axis
+        let joints = (0..40_usize)
+            .map(|id| {
+                let parent_id = id.checked_sub(1);
+                let translate = Vec3::new(1.0, 0.0, 0.0);
+                Joint::new(id, parent_id, None, Transform::from_translation(translate))
+            })
+            .collect::<Vec<_>>();
+        let index = (0..40)
+            .map(|id| JointIndex {
+                id,
+                inverse_bind_matrix: None,
+            })
+            .collect::<Vec<_>>();
+        let inverse_bind_matrices = (0..40)
+            .map(|id| Mat4::from_translation(Vec3::new(-(id as f32) - 1.0, 0.0, 0.0)))
+            .collect::<Vec<_>>();
+        let skin = Skin::new(joints, index, Some(inverse_bind_matrices));
+
+        let mut pose = Pose::from(Id::new(1));
+        skin.transform(&mut pose, &Mat4::identity(), None);
+        assert_eq!(pose.joints.len(), 40);
+        assert_eq!(pose.joints[39].global_transform.w.x, 40.0);
+
+        // joints are in the bind pose
+        let matrices = pose.matrices(&skin.index);
+        assert_eq!(matrices.len(), 40);
+        let identity: [[f32; 4]; 4] = Mat4::identity().into();
+        assert!(matrices.iter().all(|matrix| *matrix == identity));
+    }
+
+    #[test]
+    fn joints_buffer_is_never_empty() {
+        let pose = Pose::from(Id::new(1));
+        let identity: [[f32; 4]; 4] = Mat4::identity().into();
+        assert_eq!(pose.matrices(&[]), vec![identity]);
+    }
+}
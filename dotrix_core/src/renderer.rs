@@ -1,20 +1,36 @@
 //! Rendering service and system, pipelines, abstractions for models, transformation, skybox,
 //! lights and overlay
 mod backend;
+mod graph;
 mod mapped_wgpu;
+mod reflection;
+mod stats;
 
-use backend::Context as Backend;
+use std::collections::HashMap;
+
+use backend::{Context as Backend, Dispatch, Draw};
 use dotrix_math::Mat4;
+use mapped_wgpu::WgpuTextureFormat;
 
 use crate::assets::{Mesh, Shader};
 use crate::ecs::{Const, Mut};
-use crate::{Assets, Color, Globals, Id, Pipeline, Window};
+use crate::{Assets, Color, Globals, Id, Pipeline, Transform, Window};
 
 pub use backend::{
-    Bindings, PipelineBackend, Sampler, ShaderModule, StorageBuffer, TextureBuffer, UniformBuffer,
-    VertexBuffer, WorkGroups,
+    Bindings, InstanceBuffer, PipelineBackend, Sampler, Samplers, ShaderModule, StorageBuffer,
+    TextureBuffer, UniformBuffer, VertexBuffer, WorkGroups,
+};
+pub use graph::{
+    Attachment, AttachmentSize, GraphError, RenderGraph, RenderPass, RenderTarget, DEPTH,
+    DEPTH_PREPASS, MAIN_PASS, SURFACE,
+};
+pub use mapped_wgpu::{
+    AddressMode, BlendComponent, BlendFactor, BlendMode, BlendOperation, CompareFunction,
+    FilterMode, PolygonMode, PresentMode, PrimitiveTopology, StencilOperation, StencilState,
+    StorageTextureAccess, TextureCompression, TextureFormat, TextureUsages, VertexStepMode,
 };
-pub use mapped_wgpu::{StorageTextureAccess, TextureFormat, TextureUsages};
+pub use reflection::BindingError;
+pub use stats::{PassStats, RenderStats};
 
 /// Conversion matrix
 pub const OPENGL_TO_WGPU_MATRIX: Mat4 = Mat4::new(
@@ -36,38 +52,259 @@ pub struct ScissorsRect {
     pub height: u32,
 }
 
+/// Region of the attachments, the normalized device coordinates are mapped to
+///
+/// It must be inside of the attachments, so split-screen and picture-in-picture cameras render
+/// into the sub-regions of the frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// Left edge in pixels
+    pub x: f32,
+    /// Top edge in pixels
+    pub y: f32,
+    /// Width in pixels
+    pub width: f32,
+    /// Height in pixels
+    pub height: f32,
+    /// Depth the near plane is mapped to
+    pub min_depth: f32,
+    /// Depth the far plane is mapped to
+    pub max_depth: f32,
+}
+
+impl Viewport {
+    /// Constructs the viewport of the rectangle with the full depth range
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }
+    }
+}
+
 /// Pipeline options
 pub struct Options {
     /// Scissors Rectangle
     pub scissors_rect: Option<ScissorsRect>,
+    /// Viewport of the draw, overrides the one of the render pass
+    pub viewport: Option<Viewport>,
     /// Indexed draw start
     pub start_index: u32,
     /// Indexed draw end
     pub end_index: u32,
+    /// Name of the render graph pass, [`MAIN_PASS`] if not set
+    pub pass: Option<String>,
+    /// Push constants data of the draw, see [`Renderer::set_push_constants`]
+    pub push_constants: Vec<u8>,
+    /// Offsets of the dynamic uniforms of the draw, see [`Renderer::set_dynamic_offsets`]
+    pub dynamic_offsets: Vec<u32>,
+    /// Reference value of the stencil test, see [`PipelineOptions::stencil`]
+    pub stencil_reference: u32,
+    /// View-space depth of the draw, draws of the transparent pipelines are sorted back to
+    /// front by it, see [`PipelineOptions::transparent`] and [`crate::Camera::view_depth`]
+    pub depth: f32,
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
             scissors_rect: None,
+            viewport: None,
             start_index: 0,
             end_index: 1,
+            pass: None,
+            push_constants: Vec::new(),
+            dynamic_offsets: Vec::new(),
+            stencil_reference: 0,
+            depth: 0.0,
         }
     }
 }
 
 /// Service providing an interface to `WGPU` and `WINIT`
 pub struct Renderer {
-    clear_color: Color,
+    graph: RenderGraph,
+    sample_count: u32,
+    present_mode: PresentMode,
+    stencil: bool,
+    surface_format: Option<TextureFormat>,
+    srgb: Option<bool>,
     cycle: usize,
     backend: Option<Backend>,
     loaded: bool,
+    shader_includes: HashMap<String, String>,
 }
 
 impl Renderer {
     /// Sets default clear color
     pub fn set_clear_color(&mut self, color: Color) {
-        self.clear_color = color;
+        if let Some(pass) = self.graph.pass_mut(MAIN_PASS) {
+            pass.clear_color = Some(color);
+        }
+    }
+
+    /// Sets number of samples per pixel of the surface and its depth buffer
+    ///
+    /// Values above 1 enable multisample anti-aliasing, the frame is resolved to the surface.
+    /// Only 1 and 4 are supported by all adapters. Changes are applied on the next frame and
+    /// change [`Renderer::attachments_generation`], so pipelines have to be rebound.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        self.sample_count = sample_count.max(1);
+    }
+
+    /// Returns number of samples per pixel of the surface
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Sets presentation mode of the window surfaces
+    ///
+    /// [`PresentMode::Immediate`] disables the vertical synchronization for benchmarks and
+    /// latency sensitive games. The surfaces are reconfigured on the next frame.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        self.present_mode = present_mode;
+    }
+
+    /// Returns presentation mode of the window surfaces
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
+    /// Adds the stencil to the depth buffer of the surface
+    ///
+    /// The depth precision drops to 24 bit. Changes are applied on the next frame and change
+    /// [`Renderer::attachments_generation`], so pipelines have to be rebound.
+    pub fn set_stencil(&mut self, stencil: bool) {
+        self.stencil = stencil;
+    }
+
+    /// Returns true if the depth buffer of the surface has the stencil
+    pub fn stencil(&self) -> bool {
+        self.stencil
+    }
+
+    /// Sets format of the surface, instead of the one preferred by the platform
+    ///
+    /// The format must be supported by the surface. Changes are applied on the next frame and
+    /// change [`Renderer::attachments_generation`], so pipelines have to be rebound.
+    pub fn set_surface_format(&mut self, format: TextureFormat) {
+        self.surface_format = Some(format);
+    }
+
+    /// Selects the sRGB or the linear variant of the surface format
+    ///
+    /// Colors written to the sRGB surface are gamma encoded by GPU, pipelines writing to the
+    /// linear one have to encode them themselves, see [`Renderer::output_gamma`].
+    pub fn set_srgb(&mut self, srgb: bool) {
+        self.srgb = Some(srgb);
+    }
+
+    /// Returns current format of the surface
+    pub fn surface_format(&self) -> TextureFormat {
+        TextureFormat {
+            wgpu_texture_format: self.backend().surface_format(),
+        }
+    }
+
+    /// Returns format of the surface preferred by the platform
+    pub fn preferred_surface_format(&self) -> TextureFormat {
+        TextureFormat {
+            wgpu_texture_format: self.backend().preferred_surface_format(),
+        }
+    }
+
+    /// Returns gamma, that pipelines writing to the surface have to encode linear colors with
+    ///
+    /// It is 1.0 for the sRGB surfaces, which are encoded by GPU.
+    pub fn output_gamma(&self) -> f32 {
+        if self.surface_format().is_srgb() {
+            1.0
+        } else {
+            2.2
+        }
+    }
+
+    /// Enables or disables the depth-only prepass of the surface depth buffer
+    ///
+    /// Models rendered in the [`DEPTH_PREPASS`] fill the depth buffer before the main pass, so
+    /// their color pipelines with [`PipelineOptions::depth_prepassed`] shade every pixel only
+    /// once. Changes [`Renderer::attachments_generation`], so pipelines have to be rebound.
+    pub fn set_depth_prepass(&mut self, enabled: bool) {
+        if self.depth_prepass() == enabled {
+            return;
+        }
+        // the prepass takes over clearing of the depth from the main pass
+        if enabled {
+            let mut prepass = RenderPass::new(DEPTH_PREPASS).depth(DEPTH);
+            // the prepass draws the models of the main pass, so it waits for the same buffers
+            if let Some(main) = self.graph.pass_mut(MAIN_PASS) {
+                prepass.clear_depth = main.clear_depth;
+                prepass.consumes = main.consumes.clone();
+                main.clear_depth = false;
+            }
+            self.graph.add_pass_before(MAIN_PASS, prepass);
+        } else if let Some(prepass) = self.graph.remove_pass(DEPTH_PREPASS) {
+            if let Some(main) = self.graph.pass_mut(MAIN_PASS) {
+                main.clear_depth = prepass.clear_depth;
+            }
+        }
+        if let Some(backend) = self.backend.as_mut() {
+            backend.invalidate_attachments();
+        }
+    }
+
+    /// Returns true if the depth prepass is enabled
+    pub fn depth_prepass(&self) -> bool {
+        self.graph.pass(DEPTH_PREPASS).is_some()
+    }
+
+    /// Returns the render graph
+    pub fn graph(&self) -> &RenderGraph {
+        &self.graph
+    }
+
+    /// Returns mutable reference to the render graph
+    ///
+    /// Changes are applied on the next frame.
+    pub fn graph_mut(&mut self) -> &mut RenderGraph {
+        &mut self.graph
+    }
+
+    /// Adds offscreen render target, rendered before the main pass
+    ///
+    /// Pipelines render into the target with [`Pipeline::with_pass`] using the target name.
+    pub fn add_render_target(&mut self, name: &str, target: RenderTarget) {
+        self.graph.add_render_target(name, target);
+    }
+
+    /// Removes offscreen render target
+    pub fn remove_render_target(&mut self, name: &str) {
+        self.graph.remove_render_target(name);
+    }
+
+    /// Returns the color texture of the render target
+    ///
+    /// To bind the target in pipelines use [`Binding::Attachment`] with the target name.
+    pub fn render_target(&self, name: &str) -> Option<&TextureBuffer> {
+        self.attachment(name)
+    }
+
+    /// Returns the texture of the render graph attachment
+    ///
+    /// To bind the attachment in pipelines use [`Binding::Attachment`].
+    pub fn attachment(&self, name: &str) -> Option<&TextureBuffer> {
+        self.backend().attachment(name)
+    }
+
+    /// Returns the number, that changes every time the attachments textures are recreated
+    ///
+    /// Pipelines binding the attachments have to be rebound, when the number changes.
+    pub fn attachments_generation(&self) -> usize {
+        self.backend().attachments_generation()
     }
 
     fn backend(&self) -> &Backend {
@@ -83,6 +320,35 @@ impl Renderer {
         self.cycle
     }
 
+    /// Returns true if the adapter supports the texture format
+    ///
+    /// Block compressed formats are optional, uncompressed formats are always supported.
+    pub fn supports_texture_format(&self, format: TextureFormat) -> bool {
+        let required = WgpuTextureFormat::from(format).describe().required_features;
+        self.backend().features().contains(required)
+    }
+
+    /// Returns true if the adapter supports the polygon mode
+    pub fn supports_polygon_mode(&self, mode: PolygonMode) -> bool {
+        self.backend().features().contains(mode.features())
+    }
+
+    /// Returns the preferred family of the compressed texture formats, supported by the adapter
+    ///
+    /// BC formats are preferred over ASTC and ETC2 ones, `None` means textures have to be
+    /// uploaded uncompressed.
+    pub fn texture_compression(&self) -> Option<TextureCompression> {
+        let features = self.backend().features();
+        [
+            TextureCompression::Bc,
+            TextureCompression::Astc,
+            TextureCompression::Etc2,
+        ]
+        .iter()
+        .copied()
+        .find(|compression| features.contains(compression.features()))
+    }
+
     /// Laods the vertex buffer to GPU
     pub fn load_vertex_buffer<'a>(
         &self,
@@ -94,6 +360,19 @@ impl Renderer {
         buffer.load(self.backend(), attributes, indices, count as u32);
     }
 
+    /// Updates the region of the loaded vertex buffer attributes in place
+    ///
+    /// The `offset` in bytes and the size of the `attributes` must be multiples of 4, the region
+    /// must be within the loaded buffer.
+    pub fn update_vertex_buffer_region(
+        &self,
+        buffer: &VertexBuffer,
+        offset: u64,
+        attributes: &[u8],
+    ) {
+        buffer.update(self.backend(), offset, attributes);
+    }
+
     /// Loads the texture buffer to GPU
     pub fn load_texture_buffer<'a>(
         &self,
@@ -120,7 +399,83 @@ impl Renderer {
         layers: &'a [&'a [u8]],
         usages: TextureUsages,
     ) {
-        buffer.load(self.backend(), width, height, layers, usages.into());
+        self.load_texture_buffer_with_mips(buffer, width, height, 1, layers, usages);
+    }
+
+    /// Loads the texture buffer with mip levels to GPU with usages
+    ///
+    /// Every layer contains its `mip_levels` one after another, starting from the largest one.
+    /// Zero `mip_levels` generates the full chain from the first level on GPU, if the format
+    /// is filterable and can be rendered to, otherwise only the first level is loaded.
+    pub fn load_texture_buffer_with_mips<'a>(
+        &self,
+        buffer: &mut TextureBuffer,
+        width: u32,
+        height: u32,
+        mip_levels: u32,
+        layers: &'a [&'a [u8]],
+        usages: TextureUsages,
+    ) {
+        buffer.load(
+            self.backend(),
+            width,
+            height,
+            mip_levels,
+            layers,
+            usages.into(),
+        );
+    }
+
+    /// Converts the equirectangular texture to the cube map buffer on GPU
+    ///
+    /// Faces of the `size` pixels are stored as 16 bit floats, so HDR images keep their range.
+    pub fn load_cube_map_from_equirectangular(
+        &self,
+        buffer: &mut TextureBuffer,
+        source: &TextureBuffer,
+        size: u32,
+    ) {
+        buffer.load_equirectangular(self.backend(), source, size.max(1));
+    }
+
+    /// Renders a cube map of the `size` pixels with the mip levels from the `source` cube map
+    /// on GPU
+    ///
+    /// The `main` entry point of the compute `shader` with the workgroup size of 8 x 8 x 1 runs
+    /// for every texel of the six faces (`global_invocation_id.z`) of every mip level. The
+    /// shader binds the source `texture_cube<f32>` to the binding 0, filtering sampler to the
+    /// binding 1, uniform `vec4<f32>` with the mip level, number of levels and level size to
+    /// the binding 2 and the faces of the level as `texture_storage_2d_array<rgba16float, write>`
+    /// to the binding 3.
+    pub fn load_cube_map_from_compute(
+        &self,
+        buffer: &mut TextureBuffer,
+        source: &TextureBuffer,
+        shader: &str,
+        size: u32,
+        mip_levels: u32,
+    ) {
+        buffer.load_cube_from_compute(self.backend(), source, shader, size.max(1), mip_levels);
+    }
+
+    /// Renders a 16 bit float texture on GPU
+    ///
+    /// The `main` entry point of the compute `shader` with the workgroup size of 8 x 8 x 1 runs
+    /// for every texel and writes it to the `texture_storage_2d<rgba16float, write>` of the
+    /// binding 0.
+    pub fn load_texture_buffer_from_compute(
+        &self,
+        buffer: &mut TextureBuffer,
+        shader: &str,
+        width: u32,
+        height: u32,
+    ) {
+        buffer.load_from_compute(self.backend(), shader, width.max(1), height.max(1));
+    }
+
+    /// Loads the per-instance or extra per-vertex attributes to GPU
+    pub fn load_instance_buffer<'a>(&self, buffer: &mut InstanceBuffer, data: &'a [u8]) {
+        buffer.load(self.backend(), data);
     }
 
     /// Loads the uniform buffer to GPU
@@ -138,11 +493,79 @@ impl Renderer {
         buffer.load(self.backend(), data);
     }
 
+    /// Reads the loaded storage buffer back from GPU
+    ///
+    /// The buffer is copied after the passes of the current frame, the `callback` is called with
+    /// the data by the frame release system of a later cycle, once GPU is done with the frame.
+    pub fn read_storage_buffer<F>(&mut self, buffer: &StorageBuffer, callback: F)
+    where
+        F: FnOnce(&[u8]) + Send + 'static,
+    {
+        self.backend_mut()
+            .read_storage_buffer(buffer, Box::new(callback));
+    }
+
+    /// Reads the first mip level of the loaded texture layers back from GPU
+    ///
+    /// Textures have to be loaded with the read usage, attachments and textures rendered by
+    /// compute shaders can always be read. Layers follow one after another, their rows are not
+    /// padded. The `callback` is called like by [`Renderer::read_storage_buffer`].
+    pub fn read_texture<F>(&mut self, buffer: &TextureBuffer, callback: F)
+    where
+        F: FnOnce(&[u8]) + Send + 'static,
+    {
+        self.backend_mut().read_texture(buffer, Box::new(callback));
+    }
+
+    /// Checks if the renderer draws into the offscreen frame without a window
+    pub fn headless(&self) -> bool {
+        self.backend().headless()
+    }
+
+    /// Waits for GPU to finish the frames and calls callbacks of their readbacks
+    pub(crate) fn finish(&mut self) {
+        self.backend_mut().finish();
+    }
+
+    /// Captures the next frame into an image
+    ///
+    /// The frame is rendered into a texture, that is copied to the surface and read back, the
+    /// `callback` is called with the opaque RGBA image by the frame release system of a later
+    /// cycle. The image can be saved as PNG by `image.save("screenshot.png")`.
+    pub fn request_screenshot<F>(&mut self, callback: F)
+    where
+        F: FnOnce(image::RgbaImage) + Send + 'static,
+    {
+        self.backend_mut().request_screenshot(Box::new(callback));
+    }
+
+    /// Updates the region of the loaded storage buffer in place
+    ///
+    /// The `offset` in bytes and the size of the `data` must be multiples of 4, the region must
+    /// be within the loaded buffer.
+    pub fn update_storage_buffer_region(&self, buffer: &StorageBuffer, offset: u64, data: &[u8]) {
+        buffer.update(self.backend(), offset, data);
+    }
+
     /// Loads the sahder module to GPU
     pub fn load_shader_module(&self, shader_module: &mut ShaderModule, name: &str, code: &str) {
         shader_module.load(self.backend(), name, code);
     }
 
+    /// Registers the code inserted into shaders by the `{{ include(name) }}` lines
+    ///
+    /// Shared declarations and functions are written once for many shaders, see [`Shader`].
+    /// Shaders loaded before the registration are not affected.
+    pub fn add_shader_include(&mut self, name: &str, code: &str) {
+        self.shader_includes
+            .insert(String::from(name), String::from(code));
+    }
+
+    /// Returns the registered shader includes by their names
+    pub fn shader_includes(&self) -> &HashMap<String, String> {
+        &self.shader_includes
+    }
+
     /// Forces engine to reload shaders
     pub fn reload(&mut self) {
         self.loaded = false;
@@ -151,7 +574,8 @@ impl Renderer {
 
     /// Drop the backend pipeline for a shader
     ///
-    /// This should be called when a shader is removed.
+    /// This should be called when a shader is removed or replaced. Pipelines of the shader are
+    /// unbound on their next run.
     pub fn drop_pipeline(&mut self, shader: Id<Shader>) {
         self.loaded = false;
         self.backend_mut().drop_pipeline(shader);
@@ -164,34 +588,236 @@ impl Renderer {
     }
 
     /// Binds uniforms and other data to the pipeline
+    ///
+    /// Backend pipelines are cached by the hash of the layout and the [`Pipeline::variant`], so a
+    /// shader can be reused with different meshes, passes, options and flags. Panics with the
+    /// [`BindingError`], if the bind groups do not match the shader.
     pub fn bind(&mut self, pipeline: &mut Pipeline, layout: PipelineLayout) {
-        if !self.backend().has_pipeline(pipeline.shader) {
-            let pipeline_backend = PipelineBackend::new(self.backend(), &layout);
+        if let Err(err) = self.try_bind(pipeline, layout) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Binds uniforms and other data to the pipeline, if the bind groups match the shader
+    ///
+    /// Bindings of the shader variables used by the entry points of the pipeline are checked
+    /// before the backend pipeline is created: they have to be in the bind groups, be of the
+    /// same resource type and be visible in the stages using them.
+    pub fn try_bind(
+        &mut self,
+        pipeline: &mut Pipeline,
+        layout: PipelineLayout,
+    ) -> Result<(), BindingError> {
+        let pass = pipeline.options.pass.as_deref();
+        let key = self
+            .backend()
+            .pipeline_key(pipeline.shader, &pipeline.variant, &layout, pass);
+        if !self.backend().has_pipeline(key) {
+            let variant = &pipeline.variant;
+            if !variant.is_empty()
+                && self
+                    .backend()
+                    .shader_variant(pipeline.shader, variant)
+                    .is_none()
+            {
+                // failed variants are kept unloaded, so the error is logged once
+                let module = layout
+                    .shader
+                    .load_variant(self, variant)
+                    .unwrap_or_else(|err| {
+                        log::error!("Shader `{}` {}: {}", layout.shader.name, variant, err);
+                        ShaderModule::default()
+                    });
+                self.backend_mut()
+                    .add_shader_variant(pipeline.shader, variant.clone(), module);
+            }
+            let shader_module = if variant.is_empty() {
+                &layout.shader.module
+            } else {
+                self.backend()
+                    .shader_variant(pipeline.shader, variant)
+                    .unwrap()
+            };
+            if !shader_module.loaded() {
+                // the pipeline stays unbound until the shader is fixed
+                return Ok(());
+            }
+            PipelineBackend::validate(self.backend(), shader_module, &layout, pass)?;
+            let pipeline_backend =
+                PipelineBackend::new(self.backend(), shader_module, &layout, pass);
             self.backend_mut()
-                .add_pipeline(pipeline.shader, pipeline_backend);
+                .add_pipeline(key, pipeline.shader, pipeline_backend);
         }
+        pipeline.layout_key = Some(key);
 
-        let pipeline_backend = self.backend().pipeline(pipeline.shader).unwrap();
+        let pipeline_backend = self.backend().pipeline(key).unwrap();
 
         let mut bindings = Bindings::default();
         bindings.load(self.backend(), pipeline_backend, layout.bindings);
         pipeline.bindings = bindings;
+        Ok(())
+    }
+
+    /// Returns key of the bound backend pipeline
+    ///
+    /// Pipelines, which backend pipelines were dropped, are unbound, so their systems bind them
+    /// again with the reloaded shader.
+    fn layout_key(&self, pipeline: &mut Pipeline) -> Option<u64> {
+        let key = pipeline.layout_key?;
+        if self.backend().has_pipeline(key) {
+            return Some(key);
+        }
+        pipeline.layout_key = None;
+        pipeline.bindings = Bindings::default();
+        None
+    }
+
+    /// Returns maximal size of the push constants in bytes, 0 if they are not supported
+    ///
+    /// Pipelines have to fall back to uniform buffers, when the data do not fit.
+    pub fn max_push_constants_size(&self) -> u32 {
+        self.backend().max_push_constants_size()
+    }
+
+    /// Sets push constants data for the next runs of the pipeline
+    ///
+    /// Small per-draw data, like the model matrix, are set without a uniform buffer per object.
+    /// The pipeline must be bound with the [`PipelineLayout::push_constants`] range of the data
+    /// size, that is a multiple of 4 and fits into [`Renderer::max_push_constants_size`].
+    pub fn set_push_constants(&self, pipeline: &mut Pipeline, data: &[u8]) {
+        pipeline.options.push_constants.clear();
+        pipeline.options.push_constants.extend_from_slice(data);
+    }
+
+    /// Returns stride of the elements of the size in bytes in a dynamic uniform buffer
+    ///
+    /// Elements of many objects are packed into one buffer, loaded once per frame, and bound
+    /// as [`Binding::DynamicUniform`] at the offsets of the stride multiples.
+    pub fn dynamic_uniform_stride(&self, size: usize) -> usize {
+        aligned_size(size, self.backend().uniform_offset_alignment() as usize)
+    }
+
+    /// Sets offsets of the dynamic uniforms for the next runs of the pipeline
+    ///
+    /// Offsets of all [`Binding::DynamicUniform`] bindings follow the order of the bind groups
+    /// and bindings, and must be multiples of the [`Renderer::dynamic_uniform_stride`].
+    pub fn set_dynamic_offsets(&self, pipeline: &mut Pipeline, offsets: &[u32]) {
+        pipeline.options.dynamic_offsets.clear();
+        pipeline.options.dynamic_offsets.extend_from_slice(offsets);
     }
 
     /// Runs the render pipeline for a mesh
+    ///
+    /// Runs of the [`Pipeline::culled`] pipelines are skipped.
     pub fn run(&mut self, pipeline: &mut Pipeline, mesh: &Mesh) {
-        self.backend_mut().run_render_pipeline(
-            pipeline.shader,
-            &mesh.vertex_buffer,
-            &pipeline.bindings,
-            &pipeline.options,
-        );
+        if pipeline.culled {
+            return;
+        }
+        if let Some(key) = self.layout_key(pipeline) {
+            self.backend_mut().run_render_pipeline(
+                key,
+                &mesh.vertex_buffer,
+                Draw::Direct,
+                &pipeline.bindings,
+                &pipeline.options,
+            );
+        }
+    }
+
+    /// Runs the render pipeline for `count` instances of a mesh in a single draw call
+    ///
+    /// The pipeline must be bound with the single [`PipelineLayout::vertex_buffers`] layout
+    /// matching the data of the `instance_buffer`, for example [`Instance::layout`].
+    pub fn run_instanced(
+        &mut self,
+        pipeline: &mut Pipeline,
+        mesh: &Mesh,
+        instance_buffer: &InstanceBuffer,
+        count: u32,
+    ) {
+        self.run_with_buffers(pipeline, mesh, &[instance_buffer], count);
+    }
+
+    /// Runs the render pipeline for `count` instances of a mesh with the extra vertex buffers
+    ///
+    /// The `buffers` follow the [`PipelineLayout::vertex_buffers`] layouts of the pipeline, so
+    /// the mesh can be combined with per-vertex streams and per-instance attributes.
+    pub fn run_with_buffers(
+        &mut self,
+        pipeline: &mut Pipeline,
+        mesh: &Mesh,
+        buffers: &[&InstanceBuffer],
+        count: u32,
+    ) {
+        if pipeline.culled || count == 0 || buffers.iter().any(|buffer| buffer.is_empty()) {
+            return;
+        }
+        if let Some(key) = self.layout_key(pipeline) {
+            self.backend_mut().run_render_pipeline(
+                key,
+                &mesh.vertex_buffer,
+                Draw::Instanced(buffers, count),
+                &pipeline.bindings,
+                &pipeline.options,
+            );
+        }
+    }
+
+    /// Runs the render pipeline for a mesh with `count` draw calls from the indirect buffer
+    ///
+    /// The buffer is created with [`StorageBuffer::new_indirect`] and holds [`DrawIndirect`]
+    /// arguments, or [`DrawIndexedIndirect`] ones if the mesh is indexed, so compute shaders can
+    /// write them without CPU round trips.
+    pub fn run_indirect(
+        &mut self,
+        pipeline: &mut Pipeline,
+        mesh: &Mesh,
+        indirect_buffer: &StorageBuffer,
+        count: u32,
+    ) {
+        if pipeline.culled || count == 0 || indirect_buffer.is_empty() {
+            return;
+        }
+        if let Some(key) = self.layout_key(pipeline) {
+            self.backend_mut().run_render_pipeline(
+                key,
+                &mesh.vertex_buffer,
+                Draw::Indirect(indirect_buffer, count),
+                &pipeline.bindings,
+                &pipeline.options,
+            );
+        }
     }
 
     /// Runs the compute pipeline
     pub fn compute(&mut self, pipeline: &mut Pipeline, work_groups: WorkGroups) {
-        self.backend_mut()
-            .run_compute_pipeline(pipeline.shader, &pipeline.bindings, &work_groups);
+        if let Some(key) = self.layout_key(pipeline) {
+            self.backend_mut().run_compute_pipeline(
+                key,
+                &pipeline.bindings,
+                Dispatch::Direct(&work_groups),
+                &pipeline.options,
+            );
+        }
+    }
+
+    /// Runs the compute pipeline with numbers of work groups from the indirect buffer
+    ///
+    /// The buffer is created with [`StorageBuffer::new_indirect`] and starts with
+    /// [`DispatchIndirect`] arguments, so a previous compute pass can decide how much work to
+    /// dispatch.
+    pub fn compute_indirect(&mut self, pipeline: &mut Pipeline, indirect_buffer: &StorageBuffer) {
+        if indirect_buffer.is_empty() {
+            return;
+        }
+        if let Some(key) = self.layout_key(pipeline) {
+            self.backend_mut().run_compute_pipeline(
+                key,
+                &pipeline.bindings,
+                Dispatch::Indirect(indirect_buffer),
+                &pipeline.options,
+            );
+        }
     }
 }
 
@@ -199,10 +825,16 @@ impl Default for Renderer {
     /// Constructs new instance of the service
     fn default() -> Self {
         Renderer {
-            clear_color: Color::from([0.1, 0.2, 0.3, 1.0]),
+            graph: RenderGraph::default(),
+            sample_count: 1,
+            present_mode: PresentMode::default(),
+            stencil: false,
+            surface_format: None,
+            srgb: None,
             cycle: 1,
             backend: None,
             loaded: false,
+            shader_includes: HashMap::new(),
         }
     }
 }
@@ -210,23 +842,69 @@ impl Default for Renderer {
 unsafe impl Send for Renderer {}
 unsafe impl Sync for Renderer {}
 
+/// Rounds the size up to the multiple of the alignment
+fn aligned_size(size: usize, alignment: usize) -> usize {
+    match size % alignment.max(1) {
+        0 => size,
+        remainder => size + alignment - remainder,
+    }
+}
+
+/// Converts the read back pixels of the surface into an opaque RGBA image
+fn screenshot_image(width: u32, height: u32, data: &[u8], bgra: bool) -> image::RgbaImage {
+    let pixels = data
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            if bgra {
+                [pixel[2], pixel[1], pixel[0], 255]
+            } else {
+                [pixel[0], pixel[1], pixel[2], 255]
+            }
+        })
+        .collect();
+    image::RgbaImage::from_raw(width, height, pixels).expect("Screenshot must fit the image")
+}
+
+/// Returns number of the mip levels of the full chain, down to the 1x1 pixel
+pub fn mip_levels_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
 /// Startup system
 pub fn startup(mut renderer: Mut<Renderer>, mut globals: Mut<Globals>, window: Mut<Window>) {
     // Init backend backend
     if renderer.backend.is_none() {
-        renderer.backend = Some(futures::executor::block_on(backend::init(window.get())));
+        renderer.backend = Some(if window.headless() {
+            let size = window.inner_size();
+            futures::executor::block_on(backend::init_headless(size.x, size.y))
+        } else {
+            futures::executor::block_on(backend::init(window.get()))
+        });
     }
 
     // Create texture sampler and store it with Globals
-    let mut sampler = Sampler::default();
+    let mut sampler = Sampler::default().with_anisotropy(8);
     renderer.load_sampler(&mut sampler);
     globals.set(sampler);
+    globals.set(Samplers::default());
 }
 
 /// Frame binding system
-pub fn bind(mut renderer: Mut<Renderer>, mut assets: Mut<Assets>) {
-    let clear_color = renderer.clear_color;
-    renderer.backend_mut().bind_frame(&clear_color);
+pub fn bind(mut renderer: Mut<Renderer>, mut assets: Mut<Assets>, window: Const<Window>) {
+    let renderer = &mut *renderer;
+    let backend = renderer.backend.as_mut().expect(RENDERER_STARTUP);
+    backend.set_sample_count(renderer.sample_count);
+    backend.set_stencil(renderer.stencil);
+    backend.set_present_mode(renderer.present_mode.into());
+    let mut surface_format = renderer.surface_format.unwrap_or(TextureFormat {
+        wgpu_texture_format: backend.preferred_surface_format(),
+    });
+    if let Some(srgb) = renderer.srgb {
+        surface_format = surface_format.with_srgb(srgb);
+    }
+    backend.set_surface_format(surface_format.into());
+    backend.load_window_surfaces(window.windows());
+    backend.bind_frame(&renderer.graph);
 
     if renderer.loaded {
         return;
@@ -235,7 +913,7 @@ pub fn bind(mut renderer: Mut<Renderer>, mut assets: Mut<Assets>) {
     let mut loaded = true;
 
     for (_id, shader) in assets.iter_mut::<Shader>() {
-        shader.load(&renderer);
+        shader.load(renderer);
         if !shader.loaded() {
             loaded = false;
         }
@@ -253,6 +931,16 @@ pub fn release(mut renderer: Mut<Renderer>) {
     }
 }
 
+/// Rendering statistics system
+///
+/// Runs after the frame release to collect statistics of the frame.
+pub fn stats(mut renderer: Mut<Renderer>, mut stats: Mut<RenderStats>) {
+    let backend = renderer.backend_mut();
+    backend.set_profiling(stats.timestamps);
+    stats.supported = backend.timestamps_supported();
+    stats.passes = backend.pass_stats().to_vec();
+}
+
 /// Resize handling system
 pub fn resize(mut renderer: Mut<Renderer>, window: Const<Window>) {
     let size = window.inner_size();
@@ -260,11 +948,97 @@ pub fn resize(mut renderer: Mut<Renderer>, window: Const<Window>) {
 }
 
 /// Pipeline options
+#[derive(Hash)]
 pub struct PipelineOptions {
     /// Depth buffer mode
     pub depth_buffer_mode: DepthBufferMode,
     /// Disable cull mode
     pub disable_cull_mode: bool,
+    /// Comparison of the fragment depth with the depth buffer
+    pub depth_compare: DepthCompare,
+    /// Blending of the color targets by their index, the last mode applies to the rest
+    ///
+    /// If empty, the output of the pipelines reading or ignoring the depth buffer is blended
+    /// as premultiplied by alpha and the one of the pipelines writing it replaces the targets.
+    pub blend: Vec<BlendMode>,
+    /// Stencil test of the fragments, the depth attachment of the pass must have the stencil,
+    /// see [`Renderer::set_stencil`] and [`Attachment::depth_stencil`]
+    pub stencil: Option<StencilState>,
+    /// Rasterization of the triangles, the modes unsupported by the adapter fill them, see
+    /// [`Renderer::supports_polygon_mode`]
+    pub polygon_mode: PolygonMode,
+    /// Assembly of the vertices into triangles, lines or points
+    ///
+    /// Lines and points are not culled and always have a width of a single pixel.
+    pub topology: PrimitiveTopology,
+    /// Depth bias of the fragments
+    pub depth_bias: DepthBias,
+    /// Clamp the depth of the fragments beyond the near and far planes instead of clipping
+    /// them, so the shadow casters behind the light frustum are not lost
+    ///
+    /// It is ignored, if the adapter does not support it.
+    pub unclipped_depth: bool,
+    /// Draws of the pipeline are queued and run after the other draws of the pass, from the
+    /// farthest to the closest [`Options::depth`], and do not write the depth buffer
+    pub transparent: bool,
+}
+
+impl PipelineOptions {
+    /// Options of the color pipelines of models, that were rendered in the depth prepass
+    ///
+    /// The depth buffer is not written and only the fragments of the equal depth pass.
+    pub fn depth_prepassed() -> Self {
+        Self {
+            depth_buffer_mode: DepthBufferMode::Read,
+            depth_compare: DepthCompare::Equal,
+            ..Default::default()
+        }
+    }
+
+    /// Options of the pipelines blending all color targets in the mode
+    pub fn blended(blend: BlendMode) -> Self {
+        Self {
+            blend: vec![blend],
+            ..Default::default()
+        }
+    }
+
+    /// Options of the transparent pipelines mixing the fragments with the targets by alpha
+    pub fn transparent() -> Self {
+        Self {
+            transparent: true,
+            blend: vec![BlendMode::Alpha],
+            ..Default::default()
+        }
+    }
+
+    /// Returns the depth buffer mode, transparent pipelines never write the depth
+    pub(crate) fn depth_mode(&self) -> DepthBufferMode {
+        match self.depth_buffer_mode {
+            DepthBufferMode::Write if self.transparent => DepthBufferMode::Read,
+            mode => mode,
+        }
+    }
+
+    /// Returns blending of the color target
+    pub(crate) fn target_blend(&self, target: usize) -> wgpu::BlendState {
+        match self.blend.get(target).or_else(|| self.blend.last()) {
+            Some(blend) => (*blend).into(),
+            None if self.depth_mode() != DepthBufferMode::Write => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+            None => wgpu::BlendState::REPLACE,
+        }
+    }
 }
 
 impl Default for PipelineOptions {
@@ -272,6 +1046,14 @@ impl Default for PipelineOptions {
         Self {
             depth_buffer_mode: DepthBufferMode::Write,
             disable_cull_mode: false,
+            depth_compare: DepthCompare::Less,
+            blend: Vec::new(),
+            stencil: None,
+            polygon_mode: PolygonMode::Fill,
+            topology: PrimitiveTopology::TriangleList,
+            depth_bias: DepthBias::default(),
+            unclipped_depth: false,
+            transparent: false,
         }
     }
 }
@@ -286,21 +1068,126 @@ pub struct PipelineLayout<'a> {
     pub shader: &'a Shader,
     /// Pipeline bindings
     pub bindings: &'a [BindGroup<'a>],
+    /// Layouts of the vertex buffers bound after the mesh one, their attributes are located
+    /// after the mesh ones in order
+    pub vertex_buffers: &'a [VertexBufferLayout<'a>],
+    /// Push constants range of the pipeline, see [`Renderer::set_push_constants`]
+    pub push_constants: Option<PushConstants>,
     /// Pipeline options
     pub options: PipelineOptions,
 }
 
+/// Push constants range, starting from the offset 0
+pub struct PushConstants {
+    /// Shader stages using the push constants
+    pub stage: Stage,
+    /// Size of the range in bytes, multiple of 4
+    pub size: u32,
+}
+
 /// Mode of the depth buffer
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
 pub enum DepthBufferMode {
-    /// Read Only mode
+    /// Read Only mode, output is alpha blended
     Read,
     /// Read + Write mode
     Write,
-    /// Depth buffer is disabled
+    /// Depth buffer is disabled, output is alpha blended
     Disabled,
 }
 
+/// Depth bias of the rasterized fragments, that prevents acne of the shadow maps and
+/// z-fighting of the decals
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct DepthBias {
+    /// Constant bias in the smallest depth units
+    pub constant: i32,
+    /// Bias scaled by the depth slope of the triangle
+    pub slope_scale: f32,
+    /// Maximal absolute bias, 0.0 means no limit
+    pub clamp: f32,
+}
+
+impl DepthBias {
+    /// Constructs zero bias
+    pub fn none() -> Self {
+        Self {
+            constant: 0,
+            slope_scale: 0.0,
+            clamp: 0.0,
+        }
+    }
+}
+
+impl Default for DepthBias {
+    /// Constructs bias corresponding to the bilinear filtering
+    fn default() -> Self {
+        Self {
+            constant: 2,
+            slope_scale: 2.0,
+            clamp: 0.0,
+        }
+    }
+}
+
+impl std::hash::Hash for DepthBias {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.constant.hash(state);
+        self.slope_scale.to_bits().hash(state);
+        self.clamp.to_bits().hash(state);
+    }
+}
+
+impl From<DepthBias> for wgpu::DepthBiasState {
+    fn from(bias: DepthBias) -> Self {
+        Self {
+            constant: bias.constant,
+            slope_scale: bias.slope_scale,
+            clamp: bias.clamp,
+        }
+    }
+}
+
+/// Depth comparison function
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum DepthCompare {
+    /// Passes, if the fragment is closer
+    Less,
+    /// Passes, if the fragment is closer or at the same depth
+    LessEqual,
+    /// Passes, if the fragment is at the same depth
+    Equal,
+    /// Always passes
+    Always,
+}
+
+/// Layout of the vertex buffer with the attributes advancing per vertex or per instance
+#[derive(Debug, Clone, Copy)]
+pub struct VertexBufferLayout<'a> {
+    /// Rate of the attributes advancing
+    pub step_mode: VertexStepMode,
+    /// Attributes of the buffer elements
+    pub attributes: &'a [AttributeFormat],
+}
+
+impl<'a> VertexBufferLayout<'a> {
+    /// Constructs layout of the per-vertex attributes
+    pub fn vertex(attributes: &'a [AttributeFormat]) -> Self {
+        Self {
+            step_mode: VertexStepMode::Vertex,
+            attributes,
+        }
+    }
+
+    /// Constructs layout of the per-instance attributes
+    pub fn instance(attributes: &'a [AttributeFormat]) -> Self {
+        Self {
+            step_mode: VertexStepMode::Instance,
+            attributes,
+        }
+    }
+}
+
 /// Vertex Attribute Format
 #[derive(Debug)]
 pub enum AttributeFormat {
@@ -360,14 +1247,109 @@ impl AttributeFormat {
     }
 }
 
+/// Per-instance vertex attributes of the instanced rendering
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Instance {
+    /// Model transformation matrix
+    pub transform: [[f32; 4]; 4],
+    /// Instance color
+    pub color: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for Instance {}
+unsafe impl bytemuck::Pod for Instance {}
+
+impl Instance {
+    /// Constructs instance attributes from the transformation and color
+    pub fn new(transform: &Transform, color: Color) -> Self {
+        Self {
+            transform: transform.matrix().into(),
+            color: color.into(),
+        }
+    }
+
+    /// Returns layout of the instance attributes, the transform takes four locations with
+    /// matrix columns
+    pub fn layout() -> VertexBufferLayout<'static> {
+        VertexBufferLayout::instance(&[
+            AttributeFormat::Float32x4,
+            AttributeFormat::Float32x4,
+            AttributeFormat::Float32x4,
+            AttributeFormat::Float32x4,
+            AttributeFormat::Float32x4,
+        ])
+    }
+}
+
+/// Arguments of the indirect draw call
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+pub struct DrawIndirect {
+    /// Number of vertices to draw
+    pub vertex_count: u32,
+    /// Number of instances to draw
+    pub instance_count: u32,
+    /// Index of the first vertex
+    pub first_vertex: u32,
+    /// Index of the first instance
+    pub first_instance: u32,
+}
+
+unsafe impl bytemuck::Zeroable for DrawIndirect {}
+unsafe impl bytemuck::Pod for DrawIndirect {}
+
+/// Arguments of the indexed indirect draw call
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+pub struct DrawIndexedIndirect {
+    /// Number of indices to draw
+    pub index_count: u32,
+    /// Number of instances to draw
+    pub instance_count: u32,
+    /// Index of the first index
+    pub first_index: u32,
+    /// Value added to the vertex indices
+    pub base_vertex: i32,
+    /// Index of the first instance
+    pub first_instance: u32,
+}
+
+unsafe impl bytemuck::Zeroable for DrawIndexedIndirect {}
+unsafe impl bytemuck::Pod for DrawIndexedIndirect {}
+
+/// Arguments of the indirect compute dispatch
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+pub struct DispatchIndirect {
+    /// Number of Work Groups in X direction
+    pub x: u32,
+    /// Number of Work Groups in Y direction
+    pub y: u32,
+    /// Number of Work Groups in Z direction
+    pub z: u32,
+}
+
+unsafe impl bytemuck::Zeroable for DispatchIndirect {}
+unsafe impl bytemuck::Pod for DispatchIndirect {}
+
 /// Binding types (Label, Stage, Buffer)
 pub enum Binding<'a> {
     /// Uniform binding
     Uniform(&'a str, Stage, &'a UniformBuffer),
+    /// Uniform binding of the element of the size in bytes, at the dynamic offset of the draw
+    DynamicUniform(&'a str, Stage, &'a UniformBuffer, u32),
     /// Texture binding
     Texture(&'a str, Stage, &'a TextureBuffer),
-    /// 3D Texture binding
+    /// Render graph attachment texture binding by the attachment name
+    Attachment(&'a str, Stage, &'a str),
+    /// Cube map binding
+    #[deprecated(note = "cube maps are bound by `Binding::TextureCube`")]
     Texture3D(&'a str, Stage, &'a TextureBuffer),
+    /// Cube map texture binding, of the six layers of the texture
+    TextureCube(&'a str, Stage, &'a TextureBuffer),
+    /// 2D texture array binding, of all layers of the texture
+    TextureArray(&'a str, Stage, &'a TextureBuffer),
     /// Storage texture binding
     StorageTexture(&'a str, Stage, &'a TextureBuffer),
     /// Texture sampler binding
@@ -378,6 +1360,26 @@ pub enum Binding<'a> {
     StorageAsUniform(&'a str, Stage, &'a StorageBuffer),
 }
 
+impl<'a> Binding<'a> {
+    /// Returns name of the binding
+    pub fn label(&self) -> &'a str {
+        match self {
+            Binding::Uniform(label, ..)
+            | Binding::DynamicUniform(label, ..)
+            | Binding::Texture(label, ..)
+            | Binding::Attachment(label, ..)
+            | Binding::TextureCube(label, ..)
+            | Binding::TextureArray(label, ..)
+            | Binding::StorageTexture(label, ..)
+            | Binding::Sampler(label, ..)
+            | Binding::Storage(label, ..)
+            | Binding::StorageAsUniform(label, ..) => label,
+            #[allow(deprecated)]
+            Binding::Texture3D(label, ..) => label,
+        }
+    }
+}
+
 /// Rendering stage
 pub enum Stage {
     /// Vertex shader stage
@@ -402,3 +1404,236 @@ impl<'a> BindGroup<'a> {
         Self { label, bindings }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_blend_mode_applies_to_the_rest_of_targets() {
+        let options = PipelineOptions {
+            blend: vec![BlendMode::Replace, BlendMode::Additive],
+            ..Default::default()
+        };
+        assert_eq!(options.target_blend(0), wgpu::BlendState::REPLACE);
+        assert_eq!(options.target_blend(2), options.target_blend(1));
+        assert_eq!(
+            options.target_blend(1).color.dst_factor,
+            wgpu::BlendFactor::One
+        );
+
+        let transparent = PipelineOptions {
+            depth_buffer_mode: DepthBufferMode::Read,
+            ..Default::default()
+        };
+        assert_ne!(transparent.target_blend(0), wgpu::BlendState::REPLACE);
+        assert_eq!(
+            PipelineOptions::default().target_blend(0),
+            wgpu::BlendState::REPLACE
+        );
+    }
+
+    #[test]
+    fn transparent_pipelines_do_not_write_depth() {
+        let options = PipelineOptions::transparent();
+        assert_eq!(options.depth_mode(), DepthBufferMode::Read);
+        assert_ne!(options.target_blend(0), wgpu::BlendState::REPLACE);
+
+        let disabled = PipelineOptions {
+            depth_buffer_mode: DepthBufferMode::Disabled,
+            transparent: true,
+            ..Default::default()
+        };
+        assert_eq!(disabled.depth_mode(), DepthBufferMode::Disabled);
+        assert_eq!(
+            PipelineOptions::default().depth_mode(),
+            DepthBufferMode::Write
+        );
+    }
+
+    #[test]
+    fn depth_prepass_clears_depth_before_main_pass() {
+        let mut renderer = Renderer::default();
+        renderer.set_depth_prepass(true);
+        assert!(renderer.depth_prepass());
+
+        let passes = renderer.graph().schedule().unwrap();
+        assert_eq!(passes[0].name, DEPTH_PREPASS);
+        assert!(passes[0].clear_depth);
+        assert_eq!(passes[1].name, MAIN_PASS);
+        assert!(!passes[1].clear_depth);
+
+        renderer.set_depth_prepass(false);
+        assert!(!renderer.depth_prepass());
+        assert!(renderer.graph().pass(MAIN_PASS).unwrap().clear_depth);
+    }
+
+    #[test]
+    fn dynamic_uniforms_are_aligned() {
+        assert_eq!(aligned_size(64, 256), 256);
+        assert_eq!(aligned_size(256, 256), 256);
+        assert_eq!(aligned_size(300, 256), 512);
+        assert_eq!(aligned_size(12, 0), 12);
+    }
+
+    #[test]
+    fn screenshots_are_opaque_rgba() {
+        let image = screenshot_image(2, 1, &[1, 2, 3, 0, 4, 5, 6, 128], true);
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(0, 0).0, [3, 2, 1, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [6, 5, 4, 255]);
+
+        let image = screenshot_image(1, 1, &[1, 2, 3, 0], false);
+        assert_eq!(image.get_pixel(0, 0).0, [1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn mip_chain_ends_with_one_pixel() {
+        assert_eq!(mip_levels_count(1, 1), 1);
+        assert_eq!(mip_levels_count(256, 256), 9);
+        assert_eq!(mip_levels_count(300, 20), 9);
+        assert_eq!(mip_levels_count(0, 0), 1);
+    }
+
+    #[test]
+    fn indirect_draw_arguments_are_tightly_packed() {
+        let draws = [
+            DrawIndirect {
+                vertex_count: 3,
+                instance_count: 2,
+                ..Default::default()
+            },
+            DrawIndirect {
+                vertex_count: 6,
+                instance_count: 1,
+                first_vertex: 3,
+                first_instance: 0,
+            },
+        ];
+        let words: &[u32] = bytemuck::cast_slice(&draws);
+        assert_eq!(words, &[3, 2, 0, 0, 6, 1, 3, 0]);
+
+        let draw = DrawIndexedIndirect {
+            index_count: 36,
+            instance_count: 4,
+            first_index: 6,
+            base_vertex: -2,
+            first_instance: 0,
+        };
+        assert_eq!(std::mem::size_of::<DrawIndexedIndirect>(), 20);
+        let words: &[u32] = bytemuck::cast_slice(std::slice::from_ref(&draw));
+        assert_eq!(words, &[36, 4, 6, -2_i32 as u32, 0]);
+    }
+
+    #[test]
+    fn indirect_dispatch_arguments_are_tightly_packed() {
+        let dispatch = DispatchIndirect { x: 4, y: 2, z: 1 };
+        assert_eq!(std::mem::size_of::<DispatchIndirect>(), 12);
+        let words: &[u32] = bytemuck::cast_slice(std::slice::from_ref(&dispatch));
+        assert_eq!(words, &[4, 2, 1]);
+    }
+
+    #[test]
+    fn pipeline_options_are_part_of_the_layout_key() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let key = |options: &PipelineOptions| {
+            let mut hasher = DefaultHasher::new();
+            options.hash(&mut hasher);
+            hasher.finish()
+        };
+        let opaque = PipelineOptions::default();
+        assert_eq!(key(&opaque), key(&PipelineOptions::default()));
+        assert_ne!(key(&opaque), key(&PipelineOptions::transparent()));
+        let additive = PipelineOptions {
+            blend: vec![BlendMode::Additive],
+            ..Default::default()
+        };
+        assert_ne!(key(&opaque), key(&additive));
+    }
+
+    #[test]
+    fn unbound_pipelines_are_not_run() {
+        // the backend is not created, so running would panic if the key was not checked
+        let mut renderer = Renderer::default();
+        let mut pipeline = Pipeline::default();
+        assert_eq!(pipeline.layout_key, None);
+        renderer.run(&mut pipeline, &Mesh::default());
+        renderer.compute(&mut pipeline, WorkGroups { x: 1, y: 1, z: 1 });
+    }
+
+    #[test]
+    fn surface_has_at_least_one_sample() {
+        let mut renderer = Renderer::default();
+        assert_eq!(renderer.sample_count(), 1);
+        renderer.set_sample_count(4);
+        assert_eq!(renderer.sample_count(), 4);
+        renderer.set_sample_count(0);
+        assert_eq!(renderer.sample_count(), 1);
+    }
+
+    #[test]
+    fn push_constants_replace_previous_data() {
+        let renderer = Renderer::default();
+        let mut pipeline = Pipeline::default();
+        assert!(pipeline.options.push_constants.is_empty());
+
+        renderer.set_push_constants(&mut pipeline, bytemuck::cast_slice(&[1.0_f32, 2.0]));
+        assert_eq!(pipeline.options.push_constants.len(), 8);
+        renderer.set_push_constants(&mut pipeline, bytemuck::cast_slice(&[3_u32]));
+        assert_eq!(
+            pipeline.options.push_constants,
+            3_u32.to_ne_bytes().to_vec()
+        );
+    }
+
+    #[test]
+    fn present_mode_is_set_for_surfaces() {
+        let mut renderer = Renderer::default();
+        assert_eq!(renderer.present_mode(), PresentMode::Mailbox);
+
+        renderer.set_present_mode(PresentMode::Immediate);
+        assert_eq!(renderer.present_mode(), PresentMode::Immediate);
+        assert_eq!(
+            wgpu::PresentMode::from(renderer.present_mode()),
+            wgpu::PresentMode::Immediate
+        );
+        assert_eq!(
+            wgpu::PresentMode::from(PresentMode::Fifo),
+            wgpu::PresentMode::Fifo
+        );
+    }
+
+    #[test]
+    fn depth_bias_is_part_of_the_layout_key() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let key = |options: &PipelineOptions| {
+            let mut hasher = DefaultHasher::new();
+            options.hash(&mut hasher);
+            hasher.finish()
+        };
+        let state = wgpu::DepthBiasState::from(DepthBias::default());
+        assert_eq!(
+            (state.constant, state.slope_scale, state.clamp),
+            (2, 2.0, 0.0)
+        );
+        assert!(!wgpu::DepthBiasState::from(DepthBias::none()).is_enabled());
+
+        let shadows = PipelineOptions {
+            depth_bias: DepthBias {
+                slope_scale: 4.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_ne!(key(&PipelineOptions::default()), key(&shadows));
+        let unclipped = PipelineOptions {
+            unclipped_depth: true,
+            ..Default::default()
+        };
+        assert_ne!(key(&PipelineOptions::default()), key(&unclipped));
+    }
+}
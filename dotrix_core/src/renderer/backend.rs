@@ -1,82 +1,1240 @@
 /// WGPU backend wrapper module
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
 use std::{borrow::Cow, collections::HashMap};
 use wgpu;
 use wgpu::util::DeviceExt;
 use winit;
 
-use crate::{assets::Shader, color::Color, id::Id};
+use crate::{
+    assets::{Shader, ShaderVariant},
+    id::Id,
+};
 
-use super::{AttributeFormat, BindGroup, Binding, DepthBufferMode, Options, PipelineLayout, Stage};
+use super::reflection::Reflection;
+use super::{
+    AddressMode, AttributeFormat, BindGroup, Binding, BindingError, CompareFunction,
+    DepthBufferMode, DepthCompare, DrawIndexedIndirect, DrawIndirect, FilterMode, Options,
+    PassStats, PipelineLayout, PrimitiveTopology, RenderGraph, Stage, Viewport, DEPTH, MAIN_PASS,
+    SURFACE,
+};
 
 pub(crate) struct Context {
+    instance: wgpu::Instance,
     #[allow(dead_code)]
     adapter: wgpu::Adapter,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    surface: wgpu::Surface,
+    /// Surface of the window, headless backend renders into the offscreen frame instead
+    surface: Option<wgpu::Surface>,
     sur_desc: wgpu::SurfaceConfiguration,
+    /// Format preferred by the surface of the window
+    preferred_format: wgpu::TextureFormat,
+    offscreen_frame: Option<wgpu::Texture>,
+    /// Surfaces of the additional windows by their attachment names
+    window_surfaces: HashMap<String, WindowSurface>,
     depth_buffer: wgpu::TextureView,
+    /// Format of the depth buffer, with the stencil on demand
+    depth_format: wgpu::TextureFormat,
+    sample_count: u32,
+    /// Multisampled color buffer, resolved to the frame
+    multisampled_frame: Option<wgpu::TextureView>,
+    frame: Option<wgpu::SurfaceTexture>,
+    frame_view: Option<wgpu::TextureView>,
+    passes: Vec<PassBackend>,
+    attachments: HashMap<String, AttachmentBackend>,
+    attachments_generation: usize,
+    /// Pipelines by the layout keys with their shaders
+    pipelines: HashMap<u64, (Id<Shader>, PipelineBackend)>,
+    /// Shader modules of the used shader variants
+    shader_variants: HashMap<Id<Shader>, HashMap<ShaderVariant, ShaderModule>>,
+    profiling: bool,
+    profiler: Option<Profiler>,
+    gpu_times: HashMap<String, Duration>,
+    stats: Vec<PassStats>,
+    /// Buffer uploads, load functions share the context
+    uploads: Mutex<Uploads>,
+    /// Copies of the readbacks requested in the current frame
+    readback_encoder: Option<wgpu::CommandEncoder>,
+    readbacks: Vec<Readback>,
+    /// Screenshots requested for the next frame
+    screenshots: Vec<ScreenshotCallback>,
+    /// Texture of the captured frame, rendered instead of the surface and copied to it
+    screenshot_frame: Option<(wgpu::Texture, Vec<ScreenshotCallback>)>,
+}
+
+/// Surface of the additional window with its current frame
+struct WindowSurface {
+    surface: wgpu::Surface,
+    sur_desc: wgpu::SurfaceConfiguration,
     frame: Option<wgpu::SurfaceTexture>,
+}
+
+/// Scheduled pass of the render graph with its command encoder
+struct PassBackend {
+    name: String,
+    writes: Vec<String>,
+    depth: Option<String>,
+    layer: u32,
+    viewport: Option<Viewport>,
+    encoder: Option<wgpu::CommandEncoder>,
+    /// Draws of the transparent pipelines by their view depth, run after the pass encoder
+    transparent: Vec<(f32, wgpu::CommandBuffer)>,
+    draw_calls: u32,
+    triangles: u64,
+}
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+/// Size of the staging belt chunks in bytes
+const UPLOADS_CHUNK_SIZE: u64 = 1 << 20;
+
+/// Buffer updates staged in the reused chunks and copied by one command encoder
+struct Uploads {
+    belt: wgpu::util::StagingBelt,
     encoder: Option<wgpu::CommandEncoder>,
-    pipelines: HashMap<Id<Shader>, PipelineBackend>,
+    /// Chunks being returned to the belt, once GPU is done with them
+    recalls: Vec<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+impl Uploads {
+    fn new() -> Self {
+        Self {
+            belt: wgpu::util::StagingBelt::new(UPLOADS_CHUNK_SIZE),
+            encoder: None,
+            recalls: Vec::new(),
+        }
+    }
+}
+
+/// Returns size of the upload copied through the staging belt, `None` if the copy would not
+/// be aligned to `wgpu::COPY_BUFFER_ALIGNMENT`
+fn staged_size(offset: u64, len: usize) -> Option<wgpu::BufferSize> {
+    wgpu::BufferSize::new(len as u64).filter(|size| {
+        [offset, size.get()]
+            .iter()
+            .all(|value| value % wgpu::COPY_BUFFER_ALIGNMENT == 0)
+    })
+}
+
+/// Checks if the region of `len` bytes starting from `offset` is within the buffer of `size`
+fn region_fits(offset: u64, len: usize, size: usize) -> bool {
+    (offset as usize)
+        .checked_add(len)
+        .map(|end| end <= size)
+        .unwrap_or(false)
+}
+
+/// Strips the padding of the rows copied from a texture
+fn unpad_rows(
+    data: &[u8],
+    bytes_per_row: usize,
+    padded_bytes_per_row: usize,
+    rows: usize,
+) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes_per_row * rows);
+    for row in data.chunks(padded_bytes_per_row).take(rows) {
+        result.extend_from_slice(&row[..bytes_per_row]);
+    }
+    result
+}
+
+type ReadbackCallback = Box<dyn FnOnce(&[u8]) + Send>;
+
+type ScreenshotCallback = Box<dyn FnOnce(image::RgbaImage) + Send>;
+
+/// Copy of the GPU data, mapped for reading once the frame is submitted
+struct Readback {
+    buffer: wgpu::Buffer,
+    /// Size of the data in bytes
+    size: usize,
+    /// Bytes per row of the data and of the copy with the number of rows, if texture is read
+    rows: Option<(usize, usize, usize)>,
+    future: Option<Mutex<MapFuture>>,
+    callback: ReadbackCallback,
+}
+
+/// GPU timestamp queries of the render graph passes
+struct Profiler {
+    query_set: wgpu::QuerySet,
+    capacity: usize,
+    resolve_buffer: wgpu::Buffer,
+    read_buffer: wgpu::Buffer,
+    /// Passes measured in the current frame
+    measured: Vec<String>,
+    /// Passes of the frame, which timestamps are being read back
+    pending: Option<(Vec<String>, Mutex<MapFuture>)>,
+}
+
+impl Profiler {
+    fn new(device: &wgpu::Device, capacity: usize) -> Self {
+        let size = (capacity * 2 * std::mem::size_of::<u64>()) as u64;
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Profiler"),
+                ty: wgpu::QueryType::Timestamp,
+                count: capacity as u32 * 2,
+            }),
+            capacity,
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("ProfilerResolve"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            read_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("ProfilerRead"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            measured: Vec::new(),
+            pending: None,
+        }
+    }
+
+    fn size(&self) -> u64 {
+        (self.measured.len() * 2 * std::mem::size_of::<u64>()) as u64
+    }
+}
+
+/// Texture of the render graph attachment
+struct AttachmentBackend {
+    buffer: TextureBuffer,
+    width: u32,
+    height: u32,
+    layers: u32,
+    samples: u32,
+    /// Views of the texture array layers to render into, or the view of both aspects of the
+    /// depth and stencil attachment
+    layer_views: Vec<wgpu::TextureView>,
+    /// Multisampled texture to render into, resolved to the texture
+    multisampled_view: Option<wgpu::TextureView>,
 }
 
 impl Context {
-    pub(crate) fn bind_frame(&mut self, clear_color: &Color) {
-        let frame = match self.surface.get_current_texture() {
-            Ok(frame) => frame,
-            Err(_) => {
-                self.surface.configure(&self.device, &self.sur_desc);
-                self.surface
+    pub(crate) fn bind_frame(&mut self, graph: &RenderGraph) {
+        let frame = self.surface.as_ref().map(|surface| {
+            surface.get_current_texture().unwrap_or_else(|_| {
+                surface.configure(&self.device, &self.sur_desc);
+                surface
                     .get_current_texture()
                     .expect("Failed to acquire next surface texture")
+            })
+        });
+
+        // attachments and passes have to come from the same graph
+        let default_graph;
+        let (graph, passes) = match graph.schedule() {
+            Ok(passes) => (graph, passes),
+            Err(e) => {
+                log::error!(
+                    "Render graph can not be scheduled, using the default one: {}",
+                    e
+                );
+                default_graph = RenderGraph::default();
+                let passes = default_graph
+                    .schedule()
+                    .expect("Default render graph must be valid");
+                (&default_graph, passes)
             }
         };
 
+        self.load_attachments(graph);
+        self.bind_window_frames(graph);
+        self.load_profiler(passes.iter().map(|pass| pass.name.clone()).collect());
+        self.frame_view = Some(if self.screenshots.is_empty() {
+            frame
+                .as_ref()
+                .map(|frame| &frame.texture)
+                .or(self.offscreen_frame.as_ref())
+                .expect("Frame must be rendered to the surface or offscreen")
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        } else {
+            let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Screenshot"),
+                size: wgpu::Extent3d {
+                    width: self.sur_desc.width,
+                    height: self.sur_desc.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.sur_desc.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            self.screenshot_frame = Some((texture, std::mem::take(&mut self.screenshots)));
+            view
+        });
+        self.frame = frame;
+
         let command_encoder_descriptor = wgpu::CommandEncoderDescriptor { label: None };
-        let view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self
-            .device
-            .create_command_encoder(&command_encoder_descriptor);
-        {
-            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: clear_color.r as f64,
-                            g: clear_color.g as f64,
-                            b: clear_color.b as f64,
-                            a: clear_color.a as f64,
+        let query_set = self
+            .profiler
+            .as_ref()
+            .filter(|profiler| !profiler.measured.is_empty())
+            .map(|profiler| &profiler.query_set);
+        self.passes = passes
+            .into_iter()
+            .enumerate()
+            .map(|(index, pass)| {
+                let mut encoder = self
+                    .device
+                    .create_command_encoder(&command_encoder_descriptor);
+                if let Some(query_set) = query_set {
+                    encoder.write_timestamp(query_set, index as u32 * 2);
+                }
+                let color_attachments = pass
+                    .writes
+                    .iter()
+                    .filter_map(|name| {
+                        self.attachment_view(name, pass.layer)
+                            .map(|view| (view, self.resolve_target(name)))
+                    })
+                    .map(|(view, resolve_target)| wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target,
+                        ops: wgpu::Operations {
+                            load: match pass.clear_color {
+                                Some(clear_color) => wgpu::LoadOp::Clear(wgpu::Color {
+                                    r: clear_color.r as f64,
+                                    g: clear_color.g as f64,
+                                    b: clear_color.b as f64,
+                                    a: clear_color.a as f64,
+                                }),
+                                None => wgpu::LoadOp::Load,
+                            },
+                            store: true,
+                        },
+                    })
+                    .collect::<Vec<_>>();
+                let depth_view = pass
+                    .depth
+                    .as_ref()
+                    .and_then(|name| self.attachment_view(name, pass.layer));
+                let stencil = pass
+                    .depth
+                    .as_ref()
+                    .map(|name| has_stencil(name, self.depth_format, &self.attachments))
+                    .unwrap_or(false);
+                // passes of the closed windows have no attachments
+                let has_attachments = !color_attachments.is_empty() || depth_view.is_some();
+                if has_attachments && (pass.clear_color.is_some() || pass.clear_depth) {
+                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(&pass.name),
+                        color_attachments: &color_attachments,
+                        depth_stencil_attachment: depth_view.map(|view| {
+                            wgpu::RenderPassDepthStencilAttachment {
+                                view,
+                                depth_ops: Some(wgpu::Operations {
+                                    load: if pass.clear_depth {
+                                        wgpu::LoadOp::Clear(1.0)
+                                    } else {
+                                        wgpu::LoadOp::Load
+                                    },
+                                    store: true,
+                                }),
+                                stencil_ops: stencil.then_some(wgpu::Operations {
+                                    load: if pass.clear_depth {
+                                        wgpu::LoadOp::Clear(0)
+                                    } else {
+                                        wgpu::LoadOp::Load
+                                    },
+                                    store: true,
+                                }),
+                            }
                         }),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_buffer,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
-            });
-        }
-        self.encoder = Some(encoder);
-        self.frame = Some(frame);
+                    });
+                }
+                PassBackend {
+                    name: pass.name.clone(),
+                    writes: pass.writes.clone(),
+                    depth: pass.depth.clone(),
+                    layer: pass.layer,
+                    viewport: pass.viewport,
+                    encoder: Some(encoder),
+                    transparent: Vec::new(),
+                    draw_calls: 0,
+                    triangles: 0,
+                }
+            })
+            .collect();
     }
 
     pub(crate) fn release_frame(&mut self) {
-        if let Some(encoder) = self.encoder.take() {
-            self.queue.submit(Some(encoder.finish()));
+        let gpu_times = &self.gpu_times;
+        self.stats = self
+            .passes
+            .iter()
+            .map(|pass| PassStats {
+                name: pass.name.clone(),
+                draw_calls: pass.draw_calls,
+                triangles: pass.triangles,
+                gpu_time: gpu_times.get(&pass.name).copied(),
+            })
+            .collect();
+
+        self.capture_screenshot();
+        let mut command_buffers = Vec::with_capacity(self.passes.len() + 2);
+        // uploads of the frame are copied before the passes
+        command_buffers.extend(self.finish_uploads());
+        let profiler = self
+            .profiler
+            .as_mut()
+            .filter(|profiler| !profiler.measured.is_empty());
+        for (index, pass) in self.passes.iter_mut().enumerate() {
+            if let Some(mut encoder) = pass.encoder.take() {
+                let mut transparent = std::mem::take(&mut pass.transparent);
+                // transparent draws are blended from the farthest to the closest
+                transparent.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+                if transparent.is_empty() {
+                    if let Some(profiler) = profiler.as_ref() {
+                        encoder.write_timestamp(&profiler.query_set, index as u32 * 2 + 1);
+                    }
+                    command_buffers.push(encoder.finish());
+                } else {
+                    command_buffers.push(encoder.finish());
+                    command_buffers.extend(transparent.into_iter().map(|(_, buffer)| buffer));
+                    if let Some(profiler) = profiler.as_ref() {
+                        let mut encoder =
+                            self.device
+                                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                                    label: None,
+                                });
+                        encoder.write_timestamp(&profiler.query_set, index as u32 * 2 + 1);
+                        command_buffers.push(encoder.finish());
+                    }
+                }
+            }
+        }
+        // readbacks copy the results of the passes
+        if let Some(encoder) = self.readback_encoder.take() {
+            command_buffers.push(encoder.finish());
+        }
+
+        if let Some(profiler) = profiler {
+            let size = profiler.size();
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            encoder.resolve_query_set(
+                &profiler.query_set,
+                0..profiler.measured.len() as u32 * 2,
+                &profiler.resolve_buffer,
+                0,
+            );
+            encoder.copy_buffer_to_buffer(
+                &profiler.resolve_buffer,
+                0,
+                &profiler.read_buffer,
+                0,
+                size,
+            );
+            command_buffers.push(encoder.finish());
+            self.queue.submit(command_buffers);
+
+            let future = profiler
+                .read_buffer
+                .slice(..size)
+                .map_async(wgpu::MapMode::Read);
+            let measured = std::mem::take(&mut profiler.measured);
+            profiler.pending = Some((measured, Mutex::new(Box::pin(future))));
+        } else {
+            self.queue.submit(command_buffers);
         }
+
+        self.frame_view.take();
         if let Some(frame) = self.frame.take() {
             frame.present();
         }
+        for (name, window_surface) in self.window_surfaces.iter_mut() {
+            self.attachments.remove(name);
+            if let Some(frame) = window_surface.frame.take() {
+                frame.present();
+            }
+        }
+        self.recall_uploads();
+        self.read_profiler();
+        self.read_back();
+    }
+
+    /// Copies the storage buffer after the passes of the frame and calls the `callback` with
+    /// the data, once they are read back
+    pub(crate) fn read_storage_buffer(
+        &mut self,
+        source: &StorageBuffer,
+        callback: ReadbackCallback,
+    ) {
+        let size = source.size;
+        let copy_size = super::aligned_size(size, wgpu::COPY_BUFFER_ALIGNMENT as usize) as u64;
+        let buffer = self.create_readback_buffer(copy_size);
+        self.readback_encoder()
+            .copy_buffer_to_buffer(source.get(), 0, &buffer, 0, copy_size);
+        self.readbacks.push(Readback {
+            buffer,
+            size,
+            rows: None,
+            future: None,
+            callback,
+        });
+    }
+
+    /// Copies the first mip level of all texture layers after the passes of the frame and
+    /// calls the `callback` with the data, once they are read back
+    ///
+    /// Layers follow one after another, their rows are not padded.
+    pub(crate) fn read_texture(&mut self, source: &TextureBuffer, callback: ReadbackCallback) {
+        let texture = source
+            .wgpu_texture
+            .as_ref()
+            .expect("Texture must be loaded");
+        let format: wgpu::TextureFormat = source.format.into();
+        let layers = source.size.depth_or_array_layers;
+        let (bytes_per_row, rows) = source.format.layout(source.size.width, source.size.height);
+        let padded_bytes_per_row = super::aligned_size(
+            bytes_per_row as usize,
+            wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize,
+        );
+        let buffer =
+            self.create_readback_buffer((padded_bytes_per_row * (rows * layers) as usize) as u64);
+        self.readback_encoder().copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row as u32),
+                    rows_per_image: std::num::NonZeroU32::new(rows),
+                },
+            },
+            source.size.physical_size(format),
+        );
+        self.readbacks.push(Readback {
+            buffer,
+            size: bytes_per_row as usize * (rows * layers) as usize,
+            rows: Some((
+                bytes_per_row as usize,
+                padded_bytes_per_row,
+                (rows * layers) as usize,
+            )),
+            future: None,
+            callback,
+        });
+    }
+
+    /// Creates surfaces of the new additional windows, reconfigures the resized ones and drops
+    /// surfaces of the closed ones
+    pub(crate) fn load_window_surfaces<'a>(
+        &mut self,
+        windows: impl Iterator<Item = (&'a str, &'a winit::window::Window)>,
+    ) {
+        let count = self.window_surfaces.len();
+        let mut names = Vec::with_capacity(count);
+        let mut changed = false;
+        for (name, window) in windows {
+            names.push(name);
+            let size = window.inner_size();
+            if let Some(window_surface) = self.window_surfaces.get_mut(name) {
+                let sur_desc = &mut window_surface.sur_desc;
+                if size.width > 0
+                    && size.height > 0
+                    && (sur_desc.width != size.width || sur_desc.height != size.height)
+                {
+                    sur_desc.width = size.width;
+                    sur_desc.height = size.height;
+                    window_surface.surface.configure(&self.device, sur_desc);
+                }
+                continue;
+            }
+            let surface = unsafe { self.instance.create_surface(window) };
+            let sur_desc = wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: surface
+                    .get_preferred_format(&self.adapter)
+                    .unwrap_or(self.sur_desc.format),
+                width: size.width.max(1),
+                height: size.height.max(1),
+                present_mode: self.sur_desc.present_mode,
+            };
+            surface.configure(&self.device, &sur_desc);
+            self.window_surfaces.insert(
+                String::from(name),
+                WindowSurface {
+                    surface,
+                    sur_desc,
+                    frame: None,
+                },
+            );
+            changed = true;
+        }
+        self.window_surfaces
+            .retain(|name, _| names.contains(&name.as_str()));
+        // pipelines rendering to the windows have to be rebound
+        if changed || count != self.window_surfaces.len() {
+            self.attachments_generation += 1;
+        }
+    }
+
+    /// Acquires frames of the window surfaces and binds them as the graph attachments
+    fn bind_window_frames(&mut self, graph: &RenderGraph) {
+        for (name, window_surface) in self.window_surfaces.iter_mut() {
+            if !graph
+                .attachment(name)
+                .map(|attachment| attachment.window)
+                .unwrap_or(false)
+            {
+                continue;
+            }
+            let surface = &window_surface.surface;
+            let frame = match surface.get_current_texture() {
+                Ok(frame) => frame,
+                Err(_) => {
+                    surface.configure(&self.device, &window_surface.sur_desc);
+                    match surface.get_current_texture() {
+                        Ok(frame) => frame,
+                        Err(_) => continue,
+                    }
+                }
+            };
+            let sur_desc = &window_surface.sur_desc;
+            let mut buffer = TextureBuffer::new(
+                super::StorageTextureAccess::Read,
+                super::TextureFormat {
+                    wgpu_texture_format: sur_desc.format,
+                },
+            );
+            buffer.wgpu_texture_view = Some(
+                frame
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default()),
+            );
+            window_surface.frame = Some(frame);
+            self.attachments.insert(
+                name.clone(),
+                AttachmentBackend {
+                    buffer,
+                    width: sur_desc.width,
+                    height: sur_desc.height,
+                    layers: 1,
+                    samples: 1,
+                    layer_views: Vec::new(),
+                    multisampled_view: None,
+                },
+            );
+        }
+    }
+
+    /// Captures the next frame and calls the `callback` with the image, once it is read back
+    pub(crate) fn request_screenshot(&mut self, callback: ScreenshotCallback) {
+        self.screenshots.push(callback);
+    }
+
+    /// Copies the captured frame to the surface and reads it back
+    fn capture_screenshot(&mut self) {
+        let (texture, callbacks) = match self.screenshot_frame.take() {
+            Some(screenshot_frame) => screenshot_frame,
+            None => return,
+        };
+        let format = self.sur_desc.format;
+        // headless frame is not shown
+        if let Some(frame) = self.frame.as_ref() {
+            let surface_view = frame
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            let frame_view = self.frame_view.take().expect("Frame view must be bound");
+            let pipeline = create_blit_pipeline(&self.device, format);
+            let sampler = self
+                .device
+                .create_sampler(&wgpu::SamplerDescriptor::default());
+            let bind_group = create_blit_bind_group(&self.device, &pipeline, &frame_view, &sampler);
+            blit(
+                self.readback_encoder(),
+                &pipeline,
+                &bind_group,
+                &surface_view,
+            );
+        }
+
+        let mut buffer = TextureBuffer::new(
+            super::StorageTextureAccess::Read,
+            super::TextureFormat {
+                wgpu_texture_format: format,
+            },
+        );
+        let (width, height) = (self.sur_desc.width, self.sur_desc.height);
+        buffer.store_texture(
+            texture,
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        let bgra = matches!(
+            format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+        self.read_texture(
+            &buffer,
+            Box::new(move |data| {
+                let image = super::screenshot_image(width, height, data, bgra);
+                for callback in callbacks {
+                    callback(image.clone());
+                }
+            }),
+        );
+    }
+
+    fn create_readback_buffer(&self, size: u64) -> wgpu::Buffer {
+        self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn readback_encoder(&mut self) -> &mut wgpu::CommandEncoder {
+        let device = &self.device;
+        self.readback_encoder.get_or_insert_with(|| {
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback"),
+            })
+        })
+    }
+
+    /// Waits for GPU to finish the submitted frames and completes their readbacks
+    pub(crate) fn finish(&mut self) {
+        self.device.poll(wgpu::Maintain::Wait);
+        self.read_back();
+    }
+
+    /// Returns true if the backend renders into the offscreen frame
+    pub(crate) fn headless(&self) -> bool {
+        self.surface.is_none()
+    }
+
+    /// Maps the submitted readbacks and passes the mapped ones to their callbacks
+    fn read_back(&mut self) {
+        if self.readbacks.is_empty() {
+            return;
+        }
+        // copies of the readbacks requested after the frame release are not submitted yet
+        if self.readback_encoder.is_none() {
+            for readback in self.readbacks.iter_mut() {
+                if readback.future.is_none() {
+                    let future = readback.buffer.slice(..).map_async(wgpu::MapMode::Read);
+                    readback.future = Some(Mutex::new(Box::pin(future)));
+                }
+            }
+        }
+        self.device.poll(wgpu::Maintain::Poll);
+        let waker = futures::task::noop_waker();
+        let mut context = TaskContext::from_waker(&waker);
+        let mut index = 0;
+        while index < self.readbacks.len() {
+            let result = match self.readbacks[index].future.as_ref() {
+                Some(future) => {
+                    let mut future = future.lock().expect("Readback future must not be poisoned");
+                    future.as_mut().poll(&mut context)
+                }
+                None => Poll::Pending,
+            };
+            let ready = match result {
+                Poll::Ready(result) => result.is_ok(),
+                Poll::Pending => {
+                    index += 1;
+                    continue;
+                }
+            };
+            let readback = self.readbacks.remove(index);
+            if !ready {
+                continue;
+            }
+            {
+                let mapped = readback.buffer.slice(..).get_mapped_range();
+                match readback.rows {
+                    Some((bytes_per_row, padded_bytes_per_row, rows)) => {
+                        let data = unpad_rows(&mapped, bytes_per_row, padded_bytes_per_row, rows);
+                        (readback.callback)(&data);
+                    }
+                    None => (readback.callback)(&mapped[..readback.size]),
+                }
+            }
+            readback.buffer.unmap();
+        }
+    }
+
+    /// Writes data into the buffer through the staging belt
+    ///
+    /// The copies are recorded into the upload encoder, submitted before the passes of the
+    /// frame. Data of the size not aligned to `wgpu::COPY_BUFFER_ALIGNMENT` is written by the
+    /// queue.
+    pub(crate) fn write_buffer(&self, buffer: &wgpu::Buffer, offset: u64, data: &[u8]) {
+        let size = match staged_size(offset, data.len()) {
+            Some(size) => size,
+            None => {
+                self.queue.write_buffer(buffer, offset, data);
+                return;
+            }
+        };
+        let mut uploads = self.uploads.lock().expect("Uploads must not be poisoned");
+        let Uploads { belt, encoder, .. } = &mut *uploads;
+        let encoder = encoder.get_or_insert_with(|| {
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Uploads"),
+                })
+        });
+        belt.write_buffer(encoder, buffer, offset, size, &self.device)
+            .copy_from_slice(data);
+    }
+
+    /// Submits the staged uploads, so commands submitted immediately can read them
+    fn flush_uploads(&self) {
+        if let Some(command_buffer) = self.finish_uploads() {
+            self.queue.submit(Some(command_buffer));
+            self.recall_uploads();
+        }
+    }
+
+    /// Closes the staging belt and returns the copies of the uploads, if there are any
+    fn finish_uploads(&self) -> Option<wgpu::CommandBuffer> {
+        let mut uploads = self.uploads.lock().expect("Uploads must not be poisoned");
+        let encoder = uploads.encoder.take()?;
+        uploads.belt.finish();
+        Some(encoder.finish())
+    }
+
+    /// Returns submitted chunks of the staging belt for the reuse
+    fn recall_uploads(&self) {
+        let mut uploads = self.uploads.lock().expect("Uploads must not be poisoned");
+        let recall = uploads.belt.recall();
+        uploads.recalls.push(Box::pin(recall));
+        self.device.poll(wgpu::Maintain::Poll);
+        let waker = futures::task::noop_waker();
+        let mut context = TaskContext::from_waker(&waker);
+        uploads
+            .recalls
+            .retain_mut(|recall| recall.as_mut().poll(&mut context).is_pending());
+    }
+
+    /// Prepares timestamp queries of the frame, if profiling is enabled and previous results
+    /// were read back
+    fn load_profiler(&mut self, passes: Vec<String>) {
+        if !self.profiling || !self.timestamps_supported() {
+            self.profiler = None;
+            self.gpu_times.clear();
+            return;
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            if profiler.pending.is_some() {
+                profiler.measured.clear();
+                return;
+            }
+        }
+        if self
+            .profiler
+            .as_ref()
+            .map(|profiler| profiler.capacity < passes.len())
+            .unwrap_or(true)
+        {
+            self.profiler = Some(Profiler::new(&self.device, passes.len().max(1)));
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.measured = passes;
+        }
+    }
+
+    /// Reads back the timestamps, if they are ready
+    fn read_profiler(&mut self) {
+        let profiler = match self.profiler.as_mut() {
+            Some(profiler) => profiler,
+            None => return,
+        };
+        let ready = match profiler.pending.as_ref() {
+            Some((_, future)) => {
+                self.device.poll(wgpu::Maintain::Poll);
+                let waker = futures::task::noop_waker();
+                let mut context = TaskContext::from_waker(&waker);
+                let mut future = future.lock().expect("Profiler future must not be poisoned");
+                match future.as_mut().poll(&mut context) {
+                    Poll::Ready(result) => result.is_ok(),
+                    Poll::Pending => return,
+                }
+            }
+            None => return,
+        };
+        let (names, _) = profiler.pending.take().expect("Profiler must be pending");
+        if ready {
+            let size = (names.len() * 2 * std::mem::size_of::<u64>()) as u64;
+            let slice = profiler.read_buffer.slice(..size);
+            let period = self.queue.get_timestamp_period() as f64;
+            {
+                let data = slice.get_mapped_range();
+                let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                self.gpu_times = names
+                    .into_iter()
+                    .zip(timestamps.chunks(2))
+                    .map(|(name, pair)| {
+                        let ticks = pair[1].saturating_sub(pair[0]) as f64;
+                        (name, Duration::from_nanos((ticks * period) as u64))
+                    })
+                    .collect();
+            }
+            profiler.read_buffer.unmap();
+        }
+    }
+
+    pub(crate) fn set_profiling(&mut self, profiling: bool) {
+        self.profiling = profiling;
+    }
+
+    pub(crate) fn features(&self) -> wgpu::Features {
+        self.device.features()
+    }
+
+    pub(crate) fn uniform_offset_alignment(&self) -> u32 {
+        self.device.limits().min_uniform_buffer_offset_alignment
+    }
+
+    pub(crate) fn max_push_constants_size(&self) -> u32 {
+        if self.features().contains(wgpu::Features::PUSH_CONSTANTS) {
+            self.device.limits().max_push_constant_size
+        } else {
+            0
+        }
+    }
+
+    pub(crate) fn timestamps_supported(&self) -> bool {
+        self.device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+    }
+
+    pub(crate) fn pass_stats(&self) -> &[PassStats] {
+        &self.stats
+    }
+
+    /// Creates textures of the graph attachments, that are missing or do not match the surface
+    fn load_attachments(&mut self, graph: &RenderGraph) {
+        let (surface_width, surface_height) = (self.sur_desc.width, self.sur_desc.height);
+        let count = self.attachments.len();
+        self.attachments
+            .retain(|name, _| graph.attachment(name).is_some());
+        let mut changed = count != self.attachments.len();
+
+        for (name, attachment) in graph.attachments() {
+            // frames of the window surfaces are bound every frame
+            if attachment.window {
+                continue;
+            }
+            let (width, height) = attachment.size.resolve(surface_width, surface_height);
+            let format: wgpu::TextureFormat = attachment.format.into();
+            let samples = if attachment.multisampled && attachment.layers == 1 {
+                self.sample_count
+            } else {
+                1
+            };
+            if let Some(existing) = self.attachments.get(name) {
+                if existing.width == width
+                    && existing.height == height
+                    && existing.layers == attachment.layers
+                    && existing.samples == samples
+                    && wgpu::TextureFormat::from(existing.buffer.format) == format
+                {
+                    continue;
+                }
+            }
+            let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(name),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: attachment.layers,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+            });
+            let mut buffer =
+                TextureBuffer::new(super::StorageTextureAccess::Read, attachment.format);
+            // shaders sample only the depth of the combined depth and stencil attachments
+            let stencil = attachment.format.has_stencil();
+            let aspect = if stencil {
+                wgpu::TextureAspect::DepthOnly
+            } else {
+                wgpu::TextureAspect::All
+            };
+            let layer_views = if attachment.layers > 1 {
+                buffer.wgpu_texture_view =
+                    Some(texture.create_view(&wgpu::TextureViewDescriptor {
+                        dimension: Some(wgpu::TextureViewDimension::D2Array),
+                        aspect,
+                        ..wgpu::TextureViewDescriptor::default()
+                    }));
+                if attachment.layers == 6 {
+                    buffer.wgpu_cube_view =
+                        Some(texture.create_view(&wgpu::TextureViewDescriptor {
+                            dimension: Some(wgpu::TextureViewDimension::Cube),
+                            aspect,
+                            ..wgpu::TextureViewDescriptor::default()
+                        }));
+                }
+                (0..attachment.layers)
+                    .map(|layer| {
+                        texture.create_view(&wgpu::TextureViewDescriptor {
+                            dimension: Some(wgpu::TextureViewDimension::D2),
+                            base_array_layer: layer,
+                            array_layer_count: std::num::NonZeroU32::new(1),
+                            ..wgpu::TextureViewDescriptor::default()
+                        })
+                    })
+                    .collect()
+            } else if stencil {
+                buffer.wgpu_texture_view =
+                    Some(texture.create_view(&wgpu::TextureViewDescriptor {
+                        aspect,
+                        ..wgpu::TextureViewDescriptor::default()
+                    }));
+                vec![texture.create_view(&wgpu::TextureViewDescriptor::default())]
+            } else {
+                buffer.wgpu_texture_view =
+                    Some(texture.create_view(&wgpu::TextureViewDescriptor::default()));
+                Vec::new()
+            };
+            buffer.store_texture(
+                texture,
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: attachment.layers,
+                },
+            );
+            let multisampled_view = if samples > 1 {
+                let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(name),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: samples,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                });
+                Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+            } else {
+                None
+            };
+            self.attachments.insert(
+                String::from(name),
+                AttachmentBackend {
+                    buffer,
+                    width,
+                    height,
+                    layers: attachment.layers,
+                    samples,
+                    layer_views,
+                    multisampled_view,
+                },
+            );
+            changed = true;
+        }
+
+        if changed {
+            self.attachments_generation += 1;
+        }
+    }
+
+    fn attachment_view(&self, name: &str, layer: u32) -> Option<&wgpu::TextureView> {
+        attachment_view(
+            name,
+            layer,
+            self.multisampled_frame
+                .as_ref()
+                .or(self.frame_view.as_ref()),
+            &self.depth_buffer,
+            &self.attachments,
+        )
+    }
+
+    fn resolve_target(&self, name: &str) -> Option<&wgpu::TextureView> {
+        resolve_target(
+            name,
+            self.frame_view.as_ref(),
+            self.multisampled_frame.as_ref(),
+            &self.attachments,
+        )
+    }
+
+    /// Returns sample count of the pass attachments, only the surface, its depth buffer and
+    /// attachments with multisampling are multisampled
+    fn pass_sample_count(&self, pass: Option<&str>) -> u32 {
+        let pass = pass.unwrap_or(MAIN_PASS);
+        let multisampled = |name: &String| {
+            name == SURFACE
+                || self
+                    .attachments
+                    .get(name)
+                    .map(|attachment| attachment.samples > 1)
+                    .unwrap_or(false)
+        };
+        match self.passes.iter().find(|p| p.name == pass) {
+            Some(pass)
+                if !pass.writes.iter().any(multisampled)
+                    && pass.depth.as_deref() != Some(DEPTH) =>
+            {
+                1
+            }
+            _ => self.sample_count,
+        }
+    }
+
+    /// Makes pipelines bound to the attachments rebind
+    pub(crate) fn invalidate_attachments(&mut self) {
+        self.attachments_generation += 1;
+    }
+
+    /// Reconfigures the surfaces, if the mode changes
+    pub(crate) fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        if self.sur_desc.present_mode == present_mode {
+            return;
+        }
+        self.sur_desc.present_mode = present_mode;
+        if let Some(surface) = self.surface.as_ref() {
+            surface.configure(&self.device, &self.sur_desc);
+        }
+        for window_surface in self.window_surfaces.values_mut() {
+            window_surface.sur_desc.present_mode = present_mode;
+            window_surface
+                .surface
+                .configure(&self.device, &window_surface.sur_desc);
+        }
+    }
+
+    pub(crate) fn surface_format(&self) -> wgpu::TextureFormat {
+        self.sur_desc.format
+    }
+
+    pub(crate) fn preferred_surface_format(&self) -> wgpu::TextureFormat {
+        self.preferred_format
+    }
+
+    /// Reconfigures the surface and recreates the frame buffers, if the format changes
+    pub(crate) fn set_surface_format(&mut self, format: wgpu::TextureFormat) {
+        if self.sur_desc.format == format {
+            return;
+        }
+        self.sur_desc.format = format;
+        match self.surface.as_ref() {
+            Some(surface) => surface.configure(&self.device, &self.sur_desc),
+            None => {
+                self.offscreen_frame = Some(create_offscreen_frame(&self.device, &self.sur_desc))
+            }
+        }
+        self.load_frame_buffers();
+        // pipelines rendering to the surface have to be rebound
+        self.attachments_generation += 1;
+    }
+
+    /// Recreates the depth buffer with or without the stencil
+    pub(crate) fn set_stencil(&mut self, stencil: bool) {
+        let depth_format = if stencil {
+            wgpu::TextureFormat::Depth24PlusStencil8
+        } else {
+            wgpu::TextureFormat::Depth32Float
+        };
+        if self.depth_format != depth_format {
+            self.depth_format = depth_format;
+            self.load_frame_buffers();
+            // pipelines rendering to the depth buffer have to be rebound
+            self.attachments_generation += 1;
+        }
+    }
+
+    pub(crate) fn set_sample_count(&mut self, sample_count: u32) {
+        let sample_count = sample_count.max(1);
+        if self.sample_count != sample_count {
+            self.sample_count = sample_count;
+            self.load_frame_buffers();
+            // pipelines rendering to the surface have to be rebound
+            self.attachments_generation += 1;
+        }
+    }
+
+    fn load_frame_buffers(&mut self) {
+        let (width, height) = (self.sur_desc.width, self.sur_desc.height);
+        self.depth_buffer = create_depth_buffer(
+            &self.device,
+            width,
+            height,
+            self.sample_count,
+            self.depth_format,
+        );
+        self.multisampled_frame =
+            create_multisampled_frame(&self.device, &self.sur_desc, self.sample_count);
+    }
+
+    pub(crate) fn attachment(&self, name: &str) -> Option<&TextureBuffer> {
+        self.attachments
+            .get(name)
+            .map(|attachment| &attachment.buffer)
+    }
+
+    fn attachment_layers(&self, name: &str) -> u32 {
+        self.attachments
+            .get(name)
+            .map(|attachment| attachment.layers)
+            .unwrap_or(1)
+    }
+
+    pub(crate) fn attachments_generation(&self) -> usize {
+        self.attachments_generation
+    }
+
+    /// Returns formats of color targets and depth of the pass
+    fn pass_formats(
+        &self,
+        pass: Option<&str>,
+    ) -> (Vec<wgpu::TextureFormat>, Option<wgpu::TextureFormat>) {
+        let pass = pass.unwrap_or(MAIN_PASS);
+        let format = |name: &String| match name.as_str() {
+            SURFACE => Some(self.sur_desc.format),
+            DEPTH => Some(self.depth_format),
+            name => self
+                .attachments
+                .get(name)
+                .map(|attachment| attachment.buffer.format.into()),
+        };
+        match self.passes.iter().find(|p| p.name == pass) {
+            Some(pass) => (
+                pass.writes.iter().filter_map(format).collect(),
+                pass.depth.as_ref().and_then(format),
+            ),
+            None => (vec![self.sur_desc.format], Some(self.depth_format)),
+        }
     }
 
     pub(crate) fn resize(&mut self, width: u32, height: u32) {
@@ -84,74 +1242,221 @@ impl Context {
             self.sur_desc.width = width;
             self.sur_desc.height = height;
 
-            self.surface.configure(&self.device, &self.sur_desc);
-            self.depth_buffer = create_depth_buffer(&self.device, width, height);
+            match self.surface.as_ref() {
+                Some(surface) => surface.configure(&self.device, &self.sur_desc),
+                None => {
+                    self.offscreen_frame =
+                        Some(create_offscreen_frame(&self.device, &self.sur_desc))
+                }
+            }
+            self.load_frame_buffers();
         }
     }
 
-    pub(crate) fn drop_pipeline(&mut self, shader: Id<Shader>) {
-        self.pipelines.remove(&shader);
+    pub(crate) fn drop_pipeline(&mut self, shader: Id<Shader>) {
+        self.pipelines
+            .retain(|_, (pipeline_shader, _)| *pipeline_shader != shader);
+        self.shader_variants.remove(&shader);
+    }
+
+    pub(crate) fn drop_all_pipelines(&mut self) {
+        self.pipelines.clear();
+        self.shader_variants.clear();
+    }
+
+    pub(crate) fn add_shader_variant(
+        &mut self,
+        shader: Id<Shader>,
+        variant: ShaderVariant,
+        module: ShaderModule,
+    ) {
+        self.shader_variants
+            .entry(shader)
+            .or_default()
+            .insert(variant, module);
+    }
+
+    pub(crate) fn shader_variant(
+        &self,
+        shader: Id<Shader>,
+        variant: &ShaderVariant,
+    ) -> Option<&ShaderModule> {
+        self.shader_variants
+            .get(&shader)
+            .and_then(|variants| variants.get(variant))
     }
 
-    pub(crate) fn drop_all_pipelines(&mut self) {
-        self.pipelines.clear();
+    pub(crate) fn add_pipeline(
+        &mut self,
+        key: u64,
+        shader: Id<Shader>,
+        pipeline_backend: PipelineBackend,
+    ) {
+        self.pipelines.insert(key, (shader, pipeline_backend));
     }
 
-    pub(crate) fn add_pipeline(&mut self, shader: Id<Shader>, pipeline_backend: PipelineBackend) {
-        self.pipelines.insert(shader, pipeline_backend);
+    pub(crate) fn has_pipeline(&self, key: u64) -> bool {
+        self.pipelines.contains_key(&key)
     }
 
-    pub(crate) fn has_pipeline(&self, shader: Id<Shader>) -> bool {
-        self.pipelines.contains_key(&shader)
+    pub(crate) fn pipeline(&self, key: u64) -> Option<&PipelineBackend> {
+        self.pipelines.get(&key).map(|(_, pipeline)| pipeline)
     }
 
-    pub(crate) fn pipeline(&self, shader: Id<Shader>) -> Option<&PipelineBackend> {
-        self.pipelines.get(&shader)
+    /// Returns hash of everything the backend pipeline is built from: the shader and its variant,
+    /// pass formats, vertex layouts, options and bind group layouts
+    pub(crate) fn pipeline_key(
+        &self,
+        shader: Id<Shader>,
+        variant: &ShaderVariant,
+        layout: &PipelineLayout,
+        pass: Option<&str>,
+    ) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        shader.hash(&mut hasher);
+        variant.hash(&mut hasher);
+        pass.unwrap_or(MAIN_PASS).hash(&mut hasher);
+        self.pass_formats(pass).hash(&mut hasher);
+        self.pass_sample_count(pass).hash(&mut hasher);
+        layout
+            .mesh
+            .map(|mesh| wgpu_vertex_attributes(mesh.vertex_buffer_layout(), 0))
+            .hash(&mut hasher);
+        for buffer in layout.vertex_buffers.iter() {
+            buffer.step_mode.hash(&mut hasher);
+            wgpu_vertex_attributes(buffer.attributes, 0).hash(&mut hasher);
+        }
+        layout.options.hash(&mut hasher);
+        layout
+            .push_constants
+            .as_ref()
+            .map(|range| (visibility(&range.stage), range.size))
+            .hash(&mut hasher);
+        for bind_group in layout.bindings.iter() {
+            PipelineBackend::bind_group_layout_entries(self, bind_group).hash(&mut hasher);
+        }
+        hasher.finish()
     }
 
     pub(crate) fn run_render_pipeline(
         &mut self,
-        shader: Id<Shader>,
+        key: u64,
         vertex_buffer: &VertexBuffer,
+        draw: Draw<'_>,
         bindings: &Bindings,
         options: &Options,
     ) {
-        if let Some(pipeline) = self.pipelines.get(&shader) {
+        if let Some((_, pipeline)) = self.pipelines.get(&key) {
             let pipeline_backend = pipeline.instance.render();
             let depth_buffer_mode = pipeline_backend.depth_buffer_mode;
-            let encoder = self.encoder.as_mut().expect("WGPU encoder must be set");
-
-            let frame = self.frame.as_ref().expect("WGPU frame must be set");
-            let view = frame
-                .texture
-                .create_view(&wgpu::TextureViewDescriptor::default());
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
+            let stencil = pipeline_backend.stencil;
+            let device = &self.device;
+            let pass_name = options.pass.as_deref().unwrap_or(MAIN_PASS);
+            let frame_view = self.frame_view.as_ref();
+            let multisampled_frame = self.multisampled_frame.as_ref();
+            let target_view = multisampled_frame.or(frame_view);
+            let depth_buffer = &self.depth_buffer;
+            let depth_format = self.depth_format;
+            let attachments = &self.attachments;
+            let pass = match self.passes.iter_mut().find(|pass| pass.name == pass_name) {
+                Some(pass) => pass,
+                None => return,
+            };
+            let color_attachments = pass
+                .writes
+                .iter()
+                .filter_map(|name| {
+                    attachment_view(name, pass.layer, target_view, depth_buffer, attachments).map(
+                        |view| {
+                            let resolve_target =
+                                resolve_target(name, frame_view, multisampled_frame, attachments);
+                            (view, resolve_target)
+                        },
+                    )
+                })
+                .map(|(view, resolve_target)| wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Load,
                         store: true,
                     },
-                }],
-                depth_stencil_attachment: if depth_buffer_mode != DepthBufferMode::Disabled {
-                    Some(wgpu::RenderPassDepthStencilAttachment {
-                        view: &self.depth_buffer,
-                        depth_ops: Some(wgpu::Operations {
-                            load: wgpu::LoadOp::Load,
-                            store: true,
-                        }),
-                        stencil_ops: None,
-                    })
-                } else {
-                    None
+                })
+                .collect::<Vec<_>>();
+            let depth_view = pass.depth.as_ref().and_then(|name| {
+                attachment_view(name, pass.layer, target_view, depth_buffer, attachments)
+            });
+            let stencil_ops = pass
+                .depth
+                .as_ref()
+                .filter(|name| has_stencil(name, depth_format, attachments))
+                .map(|_| wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                });
+            if color_attachments.is_empty() && depth_view.is_none() {
+                return;
+            }
+            let count = vertex_buffer.count;
+            let instances = match draw {
+                Draw::Direct => options.start_index..options.end_index,
+                Draw::Instanced(_, instances_count) => 0..instances_count,
+                Draw::Indirect(_, _) => 0..0,
+            };
+            match draw {
+                Draw::Indirect(_, draws) => pass.draw_calls += draws,
+                _ => {
+                    pass.draw_calls += 1;
+                    pass.triangles +=
+                        pipeline_backend.topology.triangles(count) as u64 * instances.len() as u64;
+                }
+            }
+            let pass_viewport = pass.viewport;
+            // transparent draws are recorded separately, so they can be sorted
+            let mut queued = pipeline_backend.transparent.then(|| {
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Transparent"),
+                })
+            });
+            let encoder = match queued.as_mut() {
+                Some(encoder) => encoder,
+                None => pass.encoder.as_mut().expect("WGPU encoder must be set"),
+            };
+
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &color_attachments,
+                depth_stencil_attachment: match depth_view {
+                    Some(view) if depth_buffer_mode != DepthBufferMode::Disabled || stencil => {
+                        Some(wgpu::RenderPassDepthStencilAttachment {
+                            view,
+                            depth_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: true,
+                            }),
+                            stencil_ops,
+                        })
+                    }
+                    _ => None,
                 },
             });
 
             rpass.push_debug_group("Prepare to run pipeline");
             rpass.set_pipeline(&pipeline_backend.wgpu_pipeline);
+            if stencil {
+                rpass.set_stencil_reference(options.stencil_reference);
+            }
 
+            if let Some(viewport) = options.viewport.or(pass_viewport) {
+                rpass.set_viewport(
+                    viewport.x,
+                    viewport.y,
+                    viewport.width,
+                    viewport.height,
+                    viewport.min_depth,
+                    viewport.max_depth,
+                );
+            }
             if let Some(scissors_rect) = options.scissors_rect.as_ref() {
                 rpass.set_scissor_rect(
                     scissors_rect.clip_min_x,
@@ -161,43 +1466,101 @@ impl Context {
                 );
             }
 
-            for (index, wgpu_bind_group) in bindings.wgpu_bind_groups.iter().enumerate() {
-                rpass.set_bind_group(index as u32, wgpu_bind_group, &[]);
+            for (index, (wgpu_bind_group, offsets)) in
+                bindings.with_offsets(&options.dynamic_offsets).enumerate()
+            {
+                rpass.set_bind_group(index as u32, wgpu_bind_group, offsets);
+            }
+            if !options.push_constants.is_empty() {
+                rpass.set_push_constants(pipeline.push_constants, 0, &options.push_constants);
             }
             rpass.set_vertex_buffer(0, vertex_buffer.get().slice(..));
+            if let Draw::Instanced(buffers, _) = draw {
+                for (slot, buffer) in buffers.iter().enumerate() {
+                    rpass.set_vertex_buffer(slot as u32 + 1, buffer.get().slice(..));
+                }
+            }
             rpass.pop_debug_group();
 
-            let count = vertex_buffer.count;
+            let multi_draw = device
+                .features()
+                .contains(wgpu::Features::MULTI_DRAW_INDIRECT);
 
             if let Some(indices_buffer) = vertex_buffer.indices().as_ref() {
-                rpass.insert_debug_marker("Draw indexed");
                 rpass.set_index_buffer(indices_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                rpass.draw_indexed(0..count, 0, options.start_index..options.end_index);
+                if let Draw::Indirect(indirect_buffer, draws) = draw {
+                    rpass.insert_debug_marker("Draw indexed indirect");
+                    let buffer = indirect_buffer.get();
+                    if multi_draw {
+                        rpass.multi_draw_indexed_indirect(buffer, 0, draws);
+                    } else {
+                        let stride = std::mem::size_of::<DrawIndexedIndirect>() as u64;
+                        for i in 0..draws as u64 {
+                            rpass.draw_indexed_indirect(buffer, i * stride);
+                        }
+                    }
+                } else {
+                    rpass.insert_debug_marker("Draw indexed");
+                    rpass.draw_indexed(0..count, 0, instances);
+                }
+            } else if let Draw::Indirect(indirect_buffer, draws) = draw {
+                rpass.insert_debug_marker("Draw indirect");
+                let buffer = indirect_buffer.get();
+                if multi_draw {
+                    rpass.multi_draw_indirect(buffer, 0, draws);
+                } else {
+                    let stride = std::mem::size_of::<DrawIndirect>() as u64;
+                    for i in 0..draws as u64 {
+                        rpass.draw_indirect(buffer, i * stride);
+                    }
+                }
             } else {
                 rpass.insert_debug_marker("Draw");
-                rpass.draw(0..count, options.start_index..options.end_index);
+                rpass.draw(0..count, instances);
+            }
+            drop(rpass);
+
+            if let Some(encoder) = queued {
+                pass.transparent.push((options.depth, encoder.finish()));
             }
         }
     }
 
     pub(crate) fn run_compute_pipeline(
         &mut self,
-        shader: Id<Shader>,
+        key: u64,
         bindings: &Bindings,
-        work_groups: &WorkGroups,
+        dispatch: Dispatch<'_>,
+        options: &Options,
     ) {
-        if let Some(pipeline) = self.pipelines.get(&shader) {
+        if let Some((_, pipeline)) = self.pipelines.get(&key) {
             let pipeline_backend = pipeline.instance.compute();
-            let encoder = self.encoder.as_mut().expect("WGPU encoder must be set");
+            let pass_name = options.pass.as_deref().unwrap_or(MAIN_PASS);
+            let encoder = match self.passes.iter_mut().find(|pass| pass.name == pass_name) {
+                Some(pass) => pass.encoder.as_mut().expect("WGPU encoder must be set"),
+                None => return,
+            };
 
             // compute pass
             let mut cpass =
                 encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
             cpass.set_pipeline(&pipeline_backend.wgpu_pipeline);
-            for (index, wgpu_bind_group) in bindings.wgpu_bind_groups.iter().enumerate() {
-                cpass.set_bind_group(index as u32, wgpu_bind_group, &[]);
+            for (index, (wgpu_bind_group, offsets)) in
+                bindings.with_offsets(&options.dynamic_offsets).enumerate()
+            {
+                cpass.set_bind_group(index as u32, wgpu_bind_group, offsets);
+            }
+            if !options.push_constants.is_empty() {
+                cpass.set_push_constants(0, &options.push_constants);
+            }
+            match dispatch {
+                Dispatch::Direct(work_groups) => {
+                    cpass.dispatch(work_groups.x, work_groups.y, work_groups.z)
+                }
+                Dispatch::Indirect(indirect_buffer) => {
+                    cpass.dispatch_indirect(indirect_buffer.get(), 0)
+                }
             }
-            cpass.dispatch(work_groups.x, work_groups.y, work_groups.z);
         }
     }
 }
@@ -205,56 +1568,243 @@ impl Context {
 pub(crate) async fn init(window: &winit::window::Window) -> Context {
     let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
     let surface = unsafe { instance.create_surface(window) };
-    let adapter = instance
+    // Request an adapter which can render to our surface
+    let adapter = request_adapter(&instance, Some(&surface)).await;
+    let format = surface.get_preferred_format(&adapter).unwrap();
+    let size = window.inner_size();
+    create_context(
+        instance,
+        adapter,
+        Some(surface),
+        format,
+        size.width,
+        size.height,
+    )
+    .await
+}
+
+/// Initializes the backend rendering into the offscreen frame of the size without a window
+pub(crate) async fn init_headless(width: u32, height: u32) -> Context {
+    let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
+    let adapter = request_adapter(&instance, None).await;
+    create_context(
+        instance,
+        adapter,
+        None,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+        width.max(1),
+        height.max(1),
+    )
+    .await
+}
+
+async fn request_adapter(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface>,
+) -> wgpu::Adapter {
+    instance
         .request_adapter(&wgpu::RequestAdapterOptions {
             power_preference: wgpu::PowerPreference::HighPerformance,
-            // Request an adapter which can render to our surface
-            compatible_surface: Some(&surface),
+            compatible_surface,
             force_fallback_adapter: false,
         })
         .await
-        .expect("Failed to find an appropiate adapter");
+        .expect("Failed to find an appropiate adapter")
+}
 
+async fn create_context(
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    surface: Option<wgpu::Surface>,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+) -> Context {
     // Create the logical device and command queue
     let (device, queue) = adapter
         .request_device(
             &wgpu::DeviceDescriptor {
                 label: None,
-                features: wgpu::Features::VERTEX_WRITABLE_STORAGE,
-                limits: wgpu::Limits::default(),
+                // indirect draws fall back to a loop if multi draw is not supported, timestamps,
+                // texture compression, polygon modes and unclipped depth are optional
+                features: wgpu::Features::VERTEX_WRITABLE_STORAGE
+                    | (adapter.features()
+                        & (wgpu::Features::MULTI_DRAW_INDIRECT
+                            | wgpu::Features::TIMESTAMP_QUERY
+                            | wgpu::Features::PUSH_CONSTANTS
+                            | wgpu::Features::POLYGON_MODE_LINE
+                            | wgpu::Features::POLYGON_MODE_POINT
+                            | wgpu::Features::DEPTH_CLIP_CONTROL
+                            | super::TextureCompression::all_features())),
+                limits: wgpu::Limits {
+                    max_push_constant_size: adapter.limits().max_push_constant_size,
+                    ..wgpu::Limits::default()
+                },
             },
             None, // Some(&std::path::Path::new("./wgpu-trace/")),
         )
         .await
         .expect("Failed to create device");
 
-    let size = window.inner_size();
-
     let sur_desc = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: surface.get_preferred_format(&adapter).unwrap(),
-        width: size.width,
-        height: size.height,
+        format,
+        width,
+        height,
         present_mode: wgpu::PresentMode::Mailbox,
     };
 
-    surface.configure(&device, &sur_desc);
-    let depth_buffer = create_depth_buffer(&device, size.width, size.height);
+    let offscreen_frame = match surface.as_ref() {
+        Some(surface) => {
+            surface.configure(&device, &sur_desc);
+            None
+        }
+        None => Some(create_offscreen_frame(&device, &sur_desc)),
+    };
+    let depth_format = wgpu::TextureFormat::Depth32Float;
+    let depth_buffer = create_depth_buffer(&device, width, height, 1, depth_format);
 
     Context {
+        instance,
         adapter,
         device,
         queue,
         surface,
         sur_desc,
+        preferred_format: format,
+        offscreen_frame,
+        window_surfaces: HashMap::new(),
         depth_buffer,
+        depth_format,
+        sample_count: 1,
+        multisampled_frame: None,
         frame: None,
-        encoder: None,
+        frame_view: None,
+        passes: Vec::new(),
+        attachments: HashMap::new(),
+        attachments_generation: 0,
         pipelines: std::collections::HashMap::new(),
+        shader_variants: std::collections::HashMap::new(),
+        profiling: false,
+        profiler: None,
+        gpu_times: HashMap::new(),
+        stats: Vec::new(),
+        uploads: Mutex::new(Uploads::new()),
+        readback_encoder: None,
+        readbacks: Vec::new(),
+        screenshots: Vec::new(),
+        screenshot_frame: None,
+    }
+}
+
+/// Creates texture of the headless frame, that can be read back like the attachments
+fn create_offscreen_frame(
+    device: &wgpu::Device,
+    sur_desc: &wgpu::SurfaceConfiguration,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("OffscreenFrame"),
+        size: wgpu::Extent3d {
+            width: sur_desc.width,
+            height: sur_desc.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: sur_desc.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+    })
+}
+
+/// Returns true if the depth attachment has the stencil
+fn has_stencil(
+    name: &str,
+    depth_format: wgpu::TextureFormat,
+    attachments: &HashMap<String, AttachmentBackend>,
+) -> bool {
+    match name {
+        DEPTH => depth_format == wgpu::TextureFormat::Depth24PlusStencil8,
+        name => attachments
+            .get(name)
+            .map(|attachment| attachment.buffer.format.has_stencil())
+            .unwrap_or(false),
+    }
+}
+
+fn attachment_view<'a>(
+    name: &str,
+    layer: u32,
+    frame_view: Option<&'a wgpu::TextureView>,
+    depth_buffer: &'a wgpu::TextureView,
+    attachments: &'a HashMap<String, AttachmentBackend>,
+) -> Option<&'a wgpu::TextureView> {
+    match name {
+        SURFACE => frame_view,
+        DEPTH => Some(depth_buffer),
+        name => attachments.get(name).and_then(|attachment| {
+            if let Some(view) = attachment.multisampled_view.as_ref() {
+                Some(view)
+            } else if attachment.layer_views.is_empty() {
+                attachment.buffer.wgpu_texture_view.as_ref()
+            } else {
+                attachment.layer_views.get(layer as usize)
+            }
+        }),
+    }
+}
+
+/// Returns the frame view or the attachment texture, which the multisampled texture is resolved
+/// to
+fn resolve_target<'a>(
+    name: &str,
+    frame_view: Option<&'a wgpu::TextureView>,
+    multisampled_frame: Option<&'a wgpu::TextureView>,
+    attachments: &'a HashMap<String, AttachmentBackend>,
+) -> Option<&'a wgpu::TextureView> {
+    match (name, multisampled_frame) {
+        (SURFACE, Some(_)) => frame_view,
+        (SURFACE, None) => None,
+        (name, _) => attachments
+            .get(name)
+            .filter(|attachment| attachment.multisampled_view.is_some())
+            .and_then(|attachment| attachment.buffer.wgpu_texture_view.as_ref()),
     }
 }
 
-fn create_depth_buffer(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+fn create_multisampled_frame(
+    device: &wgpu::Device,
+    sur_desc: &wgpu::SurfaceConfiguration,
+    sample_count: u32,
+) -> Option<wgpu::TextureView> {
+    if sample_count <= 1 {
+        return None;
+    }
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Multisampled Frame"),
+        size: wgpu::Extent3d {
+            width: sur_desc.width,
+            height: sur_desc.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: sur_desc.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+    Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+}
+
+fn create_depth_buffer(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+    format: wgpu::TextureFormat,
+) -> wgpu::TextureView {
     let buffer_extent = wgpu::Extent3d {
         width,
         height,
@@ -265,9 +1815,9 @@ fn create_depth_buffer(device: &wgpu::Device, width: u32, height: u32) -> wgpu::
         label: Some("Depth Buffer"),
         size: buffer_extent,
         mip_level_count: 1,
-        sample_count: 1,
+        sample_count,
         dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Depth32Float,
+        format,
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT
             | wgpu::TextureUsages::TEXTURE_BINDING
             | wgpu::TextureUsages::COPY_DST,
@@ -286,6 +1836,8 @@ pub struct VertexBuffer {
     /// Optional Indices buffer
     indices: Option<wgpu::Buffer>,
     count: u32,
+    /// Size of the attributes buffer in bytes
+    size: usize,
 }
 
 impl VertexBuffer {
@@ -298,27 +1850,28 @@ impl VertexBuffer {
         count: u32,
     ) {
         if let Some(buffer) = self.attributes.as_ref() {
-            ctx.queue.write_buffer(buffer, 0, attributes);
+            ctx.write_buffer(buffer, 0, attributes);
         } else {
+            self.size = attributes.len();
             self.attributes = Some(ctx.device.create_buffer_init(
                 &wgpu::util::BufferInitDescriptor {
                     label: Some("VertexBuffer"),
                     contents: attributes,
-                    usage: wgpu::BufferUsages::VERTEX,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
                 },
             ));
         }
 
         if let Some(buffer) = self.indices.as_ref() {
             let indices = indices.expect("Indexed meshed can't be reloaded without indices");
-            ctx.queue.write_buffer(buffer, 0, indices);
+            ctx.write_buffer(buffer, 0, indices);
         } else {
             self.indices = indices.map(|contents| {
                 ctx.device
                     .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                         label: Some("IndexBuffer"),
                         contents,
-                        usage: wgpu::BufferUsages::INDEX,
+                        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
                     })
             });
         }
@@ -326,6 +1879,15 @@ impl VertexBuffer {
         self.count = count;
     }
 
+    /// Writes attributes into the region of the loaded buffer, starting from `offset` in bytes
+    pub(crate) fn update(&self, ctx: &Context, offset: u64, attributes: &[u8]) {
+        assert!(
+            region_fits(offset, attributes.len(), self.size),
+            "Region must be within the vertex buffer"
+        );
+        ctx.write_buffer(self.get(), offset, attributes);
+    }
+
     /// Checks if buffer is empty
     pub fn is_empty(&self) -> bool {
         self.attributes.is_none()
@@ -335,6 +1897,7 @@ impl VertexBuffer {
     pub fn empty(&mut self) {
         self.attributes.take();
         self.indices.take();
+        self.size = 0;
     }
 
     fn get(&self) -> &wgpu::Buffer {
@@ -348,9 +1911,83 @@ impl VertexBuffer {
     }
 }
 
+/// Kind of the render pipeline draw call
+#[derive(Clone, Copy)]
+pub(crate) enum Draw<'a> {
+    /// Draws instances from the pipeline options
+    Direct,
+    /// Draws instances with attributes from the extra vertex buffers
+    Instanced(&'a [&'a InstanceBuffer], u32),
+    /// Draws calls with arguments from the buffer
+    Indirect(&'a StorageBuffer, u32),
+}
+
+/// Kind of the compute pipeline dispatch
+#[derive(Clone, Copy)]
+pub(crate) enum Dispatch<'a> {
+    /// Dispatches fixed numbers of work groups
+    Direct(&'a WorkGroups),
+    /// Dispatches numbers of work groups from the buffer
+    Indirect(&'a StorageBuffer),
+}
+
+/// Buffer for the per-instance or extra per-vertex attributes, see
+/// [`VertexBufferLayout`](super::VertexBufferLayout)
+#[derive(Default)]
+pub struct InstanceBuffer {
+    wgpu_buffer: Option<wgpu::Buffer>,
+    size: usize,
+}
+
+impl InstanceBuffer {
+    /// Loads data into the instance buffer, the buffer grows if the data does not fit
+    pub(crate) fn load<'a>(&mut self, ctx: &Context, data: &'a [u8]) {
+        match self.wgpu_buffer.as_ref() {
+            Some(buffer) if self.size >= data.len() => {
+                ctx.write_buffer(buffer, 0, data);
+            }
+            _ => {
+                self.wgpu_buffer = Some(ctx.device.create_buffer_init(
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("InstanceBuffer"),
+                        contents: data,
+                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    },
+                ));
+                self.size = data.len();
+            }
+        }
+    }
+
+    /// Checks if buffer is empty
+    pub fn is_empty(&self) -> bool {
+        self.wgpu_buffer.is_none()
+    }
+
+    /// Release all resources used by the buffer
+    pub fn empty(&mut self) {
+        self.wgpu_buffer.take();
+        self.size = 0;
+    }
+
+    fn get(&self) -> &wgpu::Buffer {
+        self.wgpu_buffer
+            .as_ref()
+            .expect("Instance buffer must be loaded")
+    }
+}
+
 /// Texture Buffer
 pub struct TextureBuffer {
+    /// Texture of the views, kept for the readbacks
+    wgpu_texture: Option<wgpu::Texture>,
+    /// Size of the first mip level with the number of layers
+    size: wgpu::Extent3d,
     wgpu_texture_view: Option<wgpu::TextureView>,
+    /// View of all layers, when the main view is not an array
+    wgpu_array_view: Option<wgpu::TextureView>,
+    /// View of the six layers as cube faces, when the main view is not a cube
+    wgpu_cube_view: Option<wgpu::TextureView>,
     mode: super::StorageTextureAccess,
     format: super::TextureFormat,
 }
@@ -360,7 +1997,11 @@ impl Default for TextureBuffer {
         Self {
             mode: super::StorageTextureAccess::Read,
             format: super::TextureFormat::rgba_u8norm_srgb(),
+            wgpu_texture: None,
+            size: wgpu::Extent3d::default(),
             wgpu_texture_view: None,
+            wgpu_array_view: None,
+            wgpu_cube_view: None,
         }
     }
 }
@@ -369,21 +2010,31 @@ impl TextureBuffer {
     /// Create a texture with alternate storage texture access/formats
     ///
     /// * mode: Storage texture access mode (only used when binding a storage texture)
-    /// * format: Pixel format
+    /// * format: Pixel format, block compressed formats have to be supported by the adapter,
+    ///   see [`crate::Renderer::supports_texture_format`]
     pub fn new(mode: super::StorageTextureAccess, format: super::TextureFormat) -> Self {
         Self {
             mode,
             format,
+            wgpu_texture: Default::default(),
+            size: wgpu::Extent3d::default(),
             wgpu_texture_view: Default::default(),
+            wgpu_array_view: Default::default(),
+            wgpu_cube_view: Default::default(),
         }
     }
 
     /// Loads data into the texture buffer
+    ///
+    /// Every layer contains its mip levels one after another, starting from the largest one.
+    /// Zero `mip_levels` generates the full chain from the first level, if the format can be
+    /// rendered to.
     pub(crate) fn load<'a>(
         &mut self,
         ctx: &Context,
         width: u32,
         height: u32,
+        mip_levels: u32,
         layers: &[&'a [u8]],
         usage: wgpu::TextureUsages,
     ) {
@@ -395,96 +2046,572 @@ impl TextureBuffer {
             depth_or_array_layers,
         };
 
-        let layer_size = wgpu::Extent3d {
-            depth_or_array_layers: 1,
-            ..size
-        };
-
-        let max_mips = 1; //layer_size.max_mips();
         let format: wgpu::TextureFormat = self.format.into();
+        let generate_mips = mip_levels == 0 && self.format.is_mipmappable();
+        let (mip_level_count, uploaded_levels, usage) = if generate_mips {
+            (
+                super::mip_levels_count(width, height),
+                1,
+                usage | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            )
+        } else {
+            (mip_levels.max(1), mip_levels.max(1), usage)
+        };
 
         let texture = ctx.device.create_texture(&wgpu::TextureDescriptor {
             label: Some("TextureBuffer"),
             size,
-            mip_level_count: max_mips as u32,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format,
             usage,
         });
 
+        // six layers are sampled as a cube map, the others as an array
+        let dimension = match depth_or_array_layers {
+            1 => wgpu::TextureViewDimension::D2,
+            6 => wgpu::TextureViewDimension::Cube,
+            _ => wgpu::TextureViewDimension::D2Array,
+        };
         self.wgpu_texture_view = Some(texture.create_view(&wgpu::TextureViewDescriptor {
             label: None,
             format: Some(format),
-            dimension: Some(if depth_or_array_layers == 6 {
-                wgpu::TextureViewDimension::Cube
-            } else {
-                wgpu::TextureViewDimension::D2
-            }),
+            dimension: Some(dimension),
             ..wgpu::TextureViewDescriptor::default()
         }));
+        self.wgpu_array_view = if dimension == wgpu::TextureViewDimension::D2Array {
+            None
+        } else {
+            Some(texture.create_view(&wgpu::TextureViewDescriptor {
+                label: None,
+                format: Some(format),
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..wgpu::TextureViewDescriptor::default()
+            }))
+        };
 
         for (i, data) in layers.iter().enumerate() {
-            let bytes_per_row = std::num::NonZeroU32::new(data.len() as u32 / height).unwrap();
-
-            ctx.queue.write_texture(
-                wgpu::ImageCopyTexture {
-                    texture: &texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d {
-                        x: 0,
-                        y: 0,
-                        z: i as u32,
+            let mut offset = 0;
+            for level in 0..uploaded_levels {
+                let level_width = (width >> level).max(1);
+                let level_height = (height >> level).max(1);
+                // compressed images are copied by the whole blocks
+                let level_size = wgpu::Extent3d {
+                    width: level_width,
+                    height: level_height,
+                    depth_or_array_layers: 1,
+                }
+                .physical_size(format);
+                let (bytes_per_row, rows_per_image) = self.format.layout(level_width, level_height);
+                let level_end = offset + self.format.data_size(level_width, level_height);
+                ctx.queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level: level,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: i as u32,
+                        },
+                        aspect: wgpu::TextureAspect::All,
                     },
-                    aspect: wgpu::TextureAspect::All,
-                },
-                data,
-                wgpu::ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(bytes_per_row),
-                    rows_per_image: Some(std::num::NonZeroU32::new(height).unwrap()),
-                },
-                layer_size,
+                    &data[offset..level_end],
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: std::num::NonZeroU32::new(bytes_per_row),
+                        rows_per_image: std::num::NonZeroU32::new(rows_per_image),
+                    },
+                    level_size,
+                );
+                offset = level_end;
+            }
+        }
+
+        if generate_mips {
+            generate_mipmaps(
+                ctx,
+                &texture,
+                format,
+                mip_level_count,
+                depth_or_array_layers,
+            );
+        }
+        self.store_texture(texture, size);
+    }
+
+    /// Renders the equirectangular texture into the faces of a cube map of the `size` pixels
+    pub(crate) fn load_equirectangular(
+        &mut self,
+        ctx: &Context,
+        source: &TextureBuffer,
+        size: u32,
+    ) {
+        let texture = create_cube_texture(ctx, size, 1);
+        let faces = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..wgpu::TextureViewDescriptor::default()
+        });
+        let work_groups = (size as f32 / 8.0).ceil() as u32;
+        dispatch_compute(
+            ctx,
+            include_str!("shaders/equirectangular.wgsl"),
+            vec![
+                (
+                    wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    wgpu::BindingResource::TextureView(source.get()),
+                ),
+                (
+                    storage_binding_type(wgpu::TextureViewDimension::D2Array),
+                    wgpu::BindingResource::TextureView(&faces),
+                ),
+            ],
+            [work_groups, work_groups, 6],
+        );
+
+        self.format = super::TextureFormat::rgba_f16();
+        self.wgpu_texture_view = Some(texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..wgpu::TextureViewDescriptor::default()
+        }));
+        self.store_texture(
+            texture,
+            wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+        );
+    }
+
+    /// Renders mip levels of a cube map of the `size` pixels from the `source` cube map by the
+    /// compute shader
+    pub(crate) fn load_cube_from_compute(
+        &mut self,
+        ctx: &Context,
+        source: &TextureBuffer,
+        shader: &str,
+        size: u32,
+        mip_levels: u32,
+    ) {
+        let texture = create_cube_texture(ctx, size, mip_levels);
+        let sampler = ctx.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("ComputeSampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        for level in 0..mip_levels {
+            let level_size = (size >> level).max(1);
+            let faces = texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                base_mip_level: level,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..wgpu::TextureViewDescriptor::default()
+            });
+            let params = [level as f32, mip_levels as f32, level_size as f32, 0.0];
+            let uniform = ctx
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("ComputeParams"),
+                    contents: bytemuck::cast_slice(&params),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+            let work_groups = (level_size as f32 / 8.0).ceil() as u32;
+            dispatch_compute(
+                ctx,
+                shader,
+                vec![
+                    (
+                        wgpu::BindingType::Texture {
+                            multisampled: false,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::Cube,
+                        },
+                        wgpu::BindingResource::TextureView(source.get()),
+                    ),
+                    (
+                        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        wgpu::BindingResource::Sampler(&sampler),
+                    ),
+                    (
+                        wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        uniform.as_entire_binding(),
+                    ),
+                    (
+                        storage_binding_type(wgpu::TextureViewDimension::D2Array),
+                        wgpu::BindingResource::TextureView(&faces),
+                    ),
+                ],
+                [work_groups, work_groups, 6],
             );
         }
+
+        self.format = super::TextureFormat::rgba_f16();
+        self.wgpu_texture_view = Some(texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..wgpu::TextureViewDescriptor::default()
+        }));
+        self.store_texture(
+            texture,
+            wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+        );
+    }
+
+    /// Renders a 2D texture by the compute shader
+    pub(crate) fn load_from_compute(
+        &mut self,
+        ctx: &Context,
+        shader: &str,
+        width: u32,
+        height: u32,
+    ) {
+        let texture = ctx.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("TextureBuffer"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        dispatch_compute(
+            ctx,
+            shader,
+            vec![(
+                storage_binding_type(wgpu::TextureViewDimension::D2),
+                wgpu::BindingResource::TextureView(&view),
+            )],
+            [
+                (width as f32 / 8.0).ceil() as u32,
+                (height as f32 / 8.0).ceil() as u32,
+                1,
+            ],
+        );
+
+        self.format = super::TextureFormat::rgba_f16();
+        self.wgpu_texture_view = Some(view);
+        self.store_texture(
+            texture,
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    fn store_texture(&mut self, texture: wgpu::Texture, size: wgpu::Extent3d) {
+        self.wgpu_texture = Some(texture);
+        self.size = size;
+    }
+
+    /// Returns pixel format of the texture
+    pub fn format(&self) -> super::TextureFormat {
+        self.format
+    }
+
+    /// Returns width and height of the first mip level of the texture
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.size.width, self.size.height)
+    }
+
+    /// Checks if buffer is empty
+    pub fn loaded(&self) -> bool {
+        self.wgpu_texture_view.is_some()
+    }
+
+    /// Release all resources used by the buffer
+    pub fn unload(&mut self) {
+        self.wgpu_texture.take();
+        self.wgpu_texture_view.take();
+        self.wgpu_array_view.take();
+        self.wgpu_cube_view.take();
+    }
+
+    fn get(&self) -> &wgpu::TextureView {
+        self.wgpu_texture_view
+            .as_ref()
+            .expect("Texture must be loaded")
+    }
+
+    fn get_array(&self) -> &wgpu::TextureView {
+        self.wgpu_array_view.as_ref().unwrap_or_else(|| self.get())
+    }
+
+    fn get_cube(&self) -> &wgpu::TextureView {
+        self.wgpu_cube_view.as_ref().unwrap_or_else(|| self.get())
+    }
+}
+
+/// Creates 16 bit float cube texture, that can be written by compute shaders
+fn create_cube_texture(ctx: &Context, size: u32, mip_levels: u32) -> wgpu::Texture {
+    ctx.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("CubeMap"),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count: mip_levels.max(1),
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+    })
+}
+
+fn storage_binding_type(view_dimension: wgpu::TextureViewDimension) -> wgpu::BindingType {
+    wgpu::BindingType::StorageTexture {
+        access: wgpu::StorageTextureAccess::WriteOnly,
+        format: wgpu::TextureFormat::Rgba16Float,
+        view_dimension,
+    }
+}
+
+/// Renders every mip level of the texture layers from the previous one by the linear filtering
+fn generate_mipmaps(
+    ctx: &Context,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    mip_levels: u32,
+    layers: u32,
+) {
+    let pipeline = create_blit_pipeline(&ctx.device, format);
+    let sampler = ctx.device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Mipmaps"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let mut encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmaps"),
+        });
+    for layer in 0..layers {
+        let views = (0..mip_levels)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Mipmap"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_mip_level: level,
+                    mip_level_count: std::num::NonZeroU32::new(1),
+                    base_array_layer: layer,
+                    array_layer_count: std::num::NonZeroU32::new(1),
+                    ..wgpu::TextureViewDescriptor::default()
+                })
+            })
+            .collect::<Vec<_>>();
+        for level in 1..mip_levels as usize {
+            let bind_group =
+                create_blit_bind_group(&ctx.device, &pipeline, &views[level - 1], &sampler);
+            blit(&mut encoder, &pipeline, &bind_group, &views[level]);
+        }
     }
+    ctx.flush_uploads();
+    ctx.queue.submit(Some(encoder.finish()));
+}
+
+/// Creates pipeline drawing the filtered texture over the target of the format
+fn create_blit_pipeline(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("Blit"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/mipmap.wgsl"))),
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Blit"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &module,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &module,
+            entry_point: "fs_main",
+            targets: &[format.into()],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+fn create_blit_bind_group(
+    device: &wgpu::Device,
+    pipeline: &wgpu::RenderPipeline,
+    source: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Blit"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(source),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
 
-    /// Checks if buffer is empty
-    pub fn loaded(&self) -> bool {
-        self.wgpu_texture_view.is_some()
-    }
+/// Draws the source of the bind group over the target
+fn blit(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    target: &wgpu::TextureView,
+) {
+    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Blit"),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: None,
+    });
+    rpass.set_pipeline(pipeline);
+    rpass.set_bind_group(0, bind_group, &[]);
+    rpass.draw(0..3, 0..1);
+}
 
-    /// Release all resources used by the buffer
-    pub fn unload(&mut self) {
-        self.wgpu_texture_view.take();
-    }
+/// Runs the `main` entry point of the compute shader once with the bindings of the group 0
+fn dispatch_compute(
+    ctx: &Context,
+    shader: &str,
+    bindings: Vec<(wgpu::BindingType, wgpu::BindingResource)>,
+    work_groups: [u32; 3],
+) {
+    let layout_entries = bindings
+        .iter()
+        .enumerate()
+        .map(|(index, (ty, _))| wgpu::BindGroupLayoutEntry {
+            binding: index as u32,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: *ty,
+            count: None,
+        })
+        .collect::<Vec<_>>();
+    let bind_group_layout = ctx
+        .device
+        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Compute"),
+            entries: &layout_entries,
+        });
+    let entries = bindings
+        .into_iter()
+        .enumerate()
+        .map(|(index, (_, resource))| wgpu::BindGroupEntry {
+            binding: index as u32,
+            resource,
+        })
+        .collect::<Vec<_>>();
+    let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Compute"),
+        layout: &bind_group_layout,
+        entries: &entries,
+    });
+    let layout = ctx
+        .device
+        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Compute"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+    let module = ctx
+        .device
+        .create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Compute"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(shader)),
+        });
+    let pipeline = ctx
+        .device
+        .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute"),
+            layout: Some(&layout),
+            module: &module,
+            entry_point: "main",
+        });
 
-    fn get(&self) -> &wgpu::TextureView {
-        self.wgpu_texture_view
-            .as_ref()
-            .expect("Texture must be loaded")
+    let mut encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Compute"),
+        });
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute"),
+        });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch(work_groups[0], work_groups[1], work_groups[2]);
     }
+    ctx.flush_uploads();
+    ctx.queue.submit(Some(encoder.finish()));
 }
 
 /// Uniform Buffer
 #[derive(Default)]
 pub struct UniformBuffer {
     wgpu_buffer: Option<wgpu::Buffer>,
+    size: usize,
 }
 
 impl UniformBuffer {
     /// Loads data into the uniform buffer
+    ///
+    /// The buffer is recreated, when the data do not fit.
     pub(crate) fn load<'a>(&mut self, ctx: &Context, data: &'a [u8]) {
-        if let Some(buffer) = self.wgpu_buffer.as_ref() {
-            ctx.queue.write_buffer(buffer, 0, data);
-        } else {
-            self.wgpu_buffer = Some(ctx.device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
-                    label: Some("UniformBuffer"),
-                    contents: data,
-                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                },
-            ));
+        match self.wgpu_buffer.as_ref() {
+            Some(buffer) if data.len() <= self.size => {
+                ctx.write_buffer(buffer, 0, data);
+            }
+            _ => {
+                self.size = data.len();
+                self.wgpu_buffer = Some(ctx.device.create_buffer_init(
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("UniformBuffer"),
+                        contents: data,
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    },
+                ));
+            }
         }
     }
 
@@ -496,6 +2623,12 @@ impl UniformBuffer {
     /// Release all resources used by the buffer
     pub fn empty(&mut self) {
         self.wgpu_buffer.take();
+        self.size = 0;
+    }
+
+    /// Returns size of the buffer in bytes
+    pub fn size(&self) -> usize {
+        self.size
     }
 
     fn get(&self) -> &wgpu::Buffer {
@@ -506,26 +2639,123 @@ impl UniformBuffer {
 }
 
 /// Texture Sampler
-#[derive(Default)]
+///
+/// The default sampler repeats the texture, magnifies the nearest texels and interpolates the
+/// minified ones and the mip levels.
 pub struct Sampler {
     wgpu_sampler: Option<wgpu::Sampler>,
+    address_modes: [AddressMode; 3],
+    mag_filter: FilterMode,
+    min_filter: FilterMode,
+    mipmap_filter: FilterMode,
+    compare: Option<CompareFunction>,
+    anisotropy: u8,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            wgpu_sampler: None,
+            address_modes: [AddressMode::Repeat; 3],
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            compare: None,
+            anisotropy: 0,
+        }
+    }
 }
 
 impl Sampler {
+    /// Create a depth comparison sampler, used for shadow maps
+    pub fn comparison() -> Self {
+        Self::linear_clamp()
+            .with_mipmap_filter(FilterMode::Nearest)
+            .with_compare(CompareFunction::LessEqual)
+    }
+
+    /// Create a linear sampler clamping to the edges, used for post-processing and mip chains
+    pub fn linear_clamp() -> Self {
+        Self::default()
+            .with_address_mode(AddressMode::ClampToEdge)
+            .with_filter(FilterMode::Linear, FilterMode::Linear)
+    }
+
+    /// Sets addressing of the coordinates outside of the texture on all axes
+    #[must_use]
+    pub fn with_address_mode(self, mode: AddressMode) -> Self {
+        self.with_address_modes(mode, mode, mode)
+    }
+
+    /// Sets addressing of the coordinates outside of the texture on the `u`, `v` and `w` axes
+    #[must_use]
+    pub fn with_address_modes(mut self, u: AddressMode, v: AddressMode, w: AddressMode) -> Self {
+        self.address_modes = [u, v, w];
+        self
+    }
+
+    /// Sets filtering of the magnified and minified texels
+    #[must_use]
+    pub fn with_filter(mut self, mag_filter: FilterMode, min_filter: FilterMode) -> Self {
+        self.mag_filter = mag_filter;
+        self.min_filter = min_filter;
+        self
+    }
+
+    /// Sets filtering between the mip levels
+    #[must_use]
+    pub fn with_mipmap_filter(mut self, mipmap_filter: FilterMode) -> Self {
+        self.mipmap_filter = mipmap_filter;
+        self
+    }
+
+    /// Makes the sampler compare the sampled values with the reference ones, the binding
+    /// becomes the comparison sampler of the shader
+    #[must_use]
+    pub fn with_compare(mut self, compare: CompareFunction) -> Self {
+        self.compare = Some(compare);
+        self
+    }
+
+    /// Enables anisotropic filtering of the mip levels, sharpening textures seen at the grazing
+    /// angles
+    ///
+    /// The `level` is rounded down to the power of two up to 16, adapters without the
+    /// anisotropic filtering ignore it. Anisotropic sampler filters everything linearly.
+    #[must_use]
+    pub fn with_anisotropy(mut self, level: u8) -> Self {
+        self.anisotropy = anisotropy_clamp(level);
+        self
+    }
+
     /// Loads the Sampler
     pub(crate) fn load(&mut self, ctx: &Context) {
         if self.wgpu_sampler.is_some() {
             return;
         }
-        self.wgpu_sampler = Some(ctx.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+        self.wgpu_sampler = Some(ctx.device.create_sampler(&self.descriptor()));
+    }
+
+    fn descriptor(&self) -> wgpu::SamplerDescriptor<'static> {
+        // anisotropic filtering requires the linear filters
+        let filter = |mode| {
+            if self.anisotropy > 0 {
+                wgpu::FilterMode::Linear
+            } else {
+                wgpu::FilterMode::from(mode)
+            }
+        };
+        wgpu::SamplerDescriptor {
+            address_mode_u: self.address_modes[0].into(),
+            address_mode_v: self.address_modes[1].into(),
+            address_mode_w: self.address_modes[2].into(),
+            mag_filter: filter(self.mag_filter),
+            min_filter: filter(self.min_filter),
+            mipmap_filter: filter(self.mipmap_filter),
+            compare: self.compare.map(wgpu::CompareFunction::from),
+            anisotropy_clamp: std::num::NonZeroU8::new(self.anisotropy),
             ..Default::default()
-        }));
+        }
     }
 
     /// Checks if the Sampler is empty
@@ -543,9 +2773,50 @@ impl Sampler {
     }
 }
 
+/// Samplers by their names (global)
+///
+/// Unlike the default [`Sampler`] of the [`crate::Globals`], there can be any number of them,
+/// e.g. the nearest one for the pixel art and the clamping one for the UI. Samplers have to be
+/// loaded by [`crate::Renderer::load_sampler`] before binding.
+#[derive(Default)]
+pub struct Samplers {
+    map: HashMap<String, Sampler>,
+}
+
+impl Samplers {
+    /// Gets a reference to the sampler by its name
+    pub fn get(&self, name: &str) -> Option<&Sampler> {
+        self.map.get(name)
+    }
+
+    /// Gets a mutable reference to the sampler by its name
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Sampler> {
+        self.map.get_mut(name)
+    }
+
+    /// Stores the sampler by its name, replacing the previous one
+    pub fn insert(&mut self, name: &str, sampler: Sampler) {
+        self.map.insert(String::from(name), sampler);
+    }
+
+    /// Removes the sampler by its name
+    pub fn remove(&mut self, name: &str) -> Option<Sampler> {
+        self.map.remove(name)
+    }
+}
+
+/// Returns the largest power of two anisotropy clamp up to 16, 0 if the filtering is disabled
+fn anisotropy_clamp(level: u8) -> u8 {
+    match level {
+        0 | 1 => 0,
+        level => 1 << (7 - level.min(16).leading_zeros()),
+    }
+}
+
 enum StorageBufferMode {
     Read,
     ReadWrite,
+    Indirect,
 }
 
 impl Default for StorageBufferMode {
@@ -559,6 +2830,7 @@ impl Default for StorageBufferMode {
 pub struct StorageBuffer {
     mode: StorageBufferMode,
     wgpu_buffer: Option<wgpu::Buffer>,
+    size: usize,
 }
 
 impl StorageBuffer {
@@ -567,6 +2839,7 @@ impl StorageBuffer {
         Self {
             mode: StorageBufferMode::Read,
             wgpu_buffer: Default::default(),
+            size: 0,
         }
     }
 
@@ -575,19 +2848,30 @@ impl StorageBuffer {
         Self {
             mode: StorageBufferMode::ReadWrite,
             wgpu_buffer: Default::default(),
+            size: 0,
+        }
+    }
+
+    /// Create a read-write storage buffer of indirect draw or dispatch arguments
+    pub fn new_indirect() -> Self {
+        Self {
+            mode: StorageBufferMode::Indirect,
+            wgpu_buffer: Default::default(),
+            size: 0,
         }
     }
 
     /// Loads data into the storage buffer
     pub(crate) fn load<'a>(&mut self, ctx: &Context, data: &'a [u8]) {
         if let Some(buffer) = self.wgpu_buffer.as_ref() {
-            ctx.queue.write_buffer(buffer, 0, data);
+            ctx.write_buffer(buffer, 0, data);
         } else {
             let usage = match self.mode {
                 StorageBufferMode::Read => {
                     wgpu::BufferUsages::STORAGE
                         | wgpu::BufferUsages::UNIFORM
                         | wgpu::BufferUsages::COPY_DST
+                        | wgpu::BufferUsages::COPY_SRC
                 }
                 StorageBufferMode::ReadWrite => {
                     wgpu::BufferUsages::STORAGE
@@ -595,7 +2879,14 @@ impl StorageBuffer {
                         | wgpu::BufferUsages::COPY_DST
                         | wgpu::BufferUsages::COPY_SRC
                 }
+                StorageBufferMode::Indirect => {
+                    wgpu::BufferUsages::STORAGE
+                        | wgpu::BufferUsages::INDIRECT
+                        | wgpu::BufferUsages::COPY_DST
+                        | wgpu::BufferUsages::COPY_SRC
+                }
             };
+            self.size = data.len();
             self.wgpu_buffer = Some(ctx.device.create_buffer_init(
                 &wgpu::util::BufferInitDescriptor {
                     label: Some("StorageBuffer"),
@@ -606,6 +2897,15 @@ impl StorageBuffer {
         }
     }
 
+    /// Writes data into the region of the loaded buffer, starting from `offset` in bytes
+    pub(crate) fn update(&self, ctx: &Context, offset: u64, data: &[u8]) {
+        assert!(
+            region_fits(offset, data.len(), self.size),
+            "Region must be within the storage buffer"
+        );
+        ctx.write_buffer(self.get(), offset, data);
+    }
+
     /// Checks if buffer is empty
     pub fn is_empty(&self) -> bool {
         self.wgpu_buffer.is_none()
@@ -614,6 +2914,7 @@ impl StorageBuffer {
     /// Release all resources used by the buffer
     pub fn empty(&mut self) {
         self.wgpu_buffer.take();
+        self.size = 0;
     }
 
     fn get(&self) -> &wgpu::Buffer {
@@ -628,6 +2929,12 @@ pub struct RenderPipelineBackend {
     /// WGPU pipeline
     wgpu_pipeline: wgpu::RenderPipeline,
     depth_buffer_mode: DepthBufferMode,
+    /// The stencil is tested
+    stencil: bool,
+    /// Draws are sorted back to front
+    transparent: bool,
+    /// Assembly of the vertices, to count the drawn triangles
+    topology: PrimitiveTopology,
 }
 
 /// Compute pipeline backend
@@ -671,6 +2978,8 @@ pub struct WorkGroups {
 pub struct PipelineBackend {
     /// WGPU bind group layout
     wgpu_bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    /// Stages of the push constants
+    push_constants: wgpu::ShaderStages,
     /// WGPU pipeline
     instance: PipelineInstance,
 }
@@ -685,16 +2994,80 @@ fn visibility(stage: &Stage) -> wgpu::ShaderStages {
     }
 }
 
+/// Returns stride and wgpu attributes of the vertex buffer layout
+fn wgpu_vertex_attributes(
+    layout: &[AttributeFormat],
+    first_location: u32,
+) -> (u64, Vec<wgpu::VertexAttribute>) {
+    let mut stride = 0;
+    let attributes = layout
+        .iter()
+        .enumerate()
+        .map(|(index, attr)| {
+            let offset = stride;
+            stride += attr.size();
+            wgpu::VertexAttribute {
+                format: match attr {
+                    AttributeFormat::Float32 => wgpu::VertexFormat::Float32,
+                    AttributeFormat::Float32x2 => wgpu::VertexFormat::Float32x2,
+                    AttributeFormat::Float32x3 => wgpu::VertexFormat::Float32x3,
+                    AttributeFormat::Float32x4 => wgpu::VertexFormat::Float32x4,
+                    AttributeFormat::Uint16x2 => wgpu::VertexFormat::Uint16x2,
+                    AttributeFormat::Uint16x4 => wgpu::VertexFormat::Uint16x4,
+                    AttributeFormat::Uint32 => wgpu::VertexFormat::Uint32,
+                    AttributeFormat::Uint32x2 => wgpu::VertexFormat::Uint32x2,
+                    AttributeFormat::Uint32x3 => wgpu::VertexFormat::Uint32x3,
+                    AttributeFormat::Uint32x4 => wgpu::VertexFormat::Uint32x4,
+                },
+                offset: offset as u64,
+                shader_location: first_location + index as u32,
+            }
+        })
+        .collect::<Vec<_>>();
+    (stride as u64, attributes)
+}
+
+/// Returns array strides and attributes of the extra vertex buffers, located one after another
+/// from the `first_location`
+fn extra_vertex_attributes(
+    buffers: &[super::VertexBufferLayout],
+    first_location: u32,
+) -> Vec<(u64, Vec<wgpu::VertexAttribute>)> {
+    let mut location = first_location;
+    buffers
+        .iter()
+        .map(|buffer| {
+            let attributes = wgpu_vertex_attributes(buffer.attributes, location);
+            location += buffer.attributes.len() as u32;
+            attributes
+        })
+        .collect()
+}
+
 impl PipelineBackend {
-    pub(crate) fn new(ctx: &Context, pipeline: &PipelineLayout) -> Self {
-        let wgpu_shader_module = pipeline.shader.module.get();
+    pub(crate) fn new(
+        ctx: &Context,
+        shader_module: &ShaderModule,
+        pipeline: &PipelineLayout,
+        pass: Option<&str>,
+    ) -> Self {
+        let wgpu_shader_module = shader_module.get();
         let wgpu_bind_group_layouts = pipeline
             .bindings
             .iter()
-            .map(|bind_group_layout| Self::bind_group_layout(&ctx.device, bind_group_layout))
+            .map(|bind_group_layout| Self::bind_group_layout(ctx, bind_group_layout))
             .collect::<Vec<_>>();
 
         // create pipeline layout
+        let push_constant_ranges = pipeline
+            .push_constants
+            .as_ref()
+            .map(|range| wgpu::PushConstantRange {
+                stages: visibility(&range.stage),
+                range: 0..range.size,
+            })
+            .into_iter()
+            .collect::<Vec<_>>();
         let pipeline_layout = ctx
             .device
             .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -703,46 +3076,50 @@ impl PipelineBackend {
                     .iter()
                     .collect::<Vec<_>>()
                     .as_slice(),
-                push_constant_ranges: &[],
+                push_constant_ranges: &push_constant_ranges,
             });
 
         let instance = if let Some(mesh) = pipeline.mesh {
-            let depth_buffer_mode = pipeline.options.depth_buffer_mode;
+            let depth_buffer_mode = pipeline.options.depth_mode();
 
             // render pipeline: prepare vertex buffers layout
-            let mut vertex_array_stride = 0;
-            let vertex_attributes = mesh
-                .vertex_buffer_layout()
-                .iter()
-                .enumerate()
-                .map(|(index, attr)| {
-                    let offset = vertex_array_stride;
-                    vertex_array_stride += attr.size();
-                    wgpu::VertexAttribute {
-                        format: match attr {
-                            AttributeFormat::Float32 => wgpu::VertexFormat::Float32,
-                            AttributeFormat::Float32x2 => wgpu::VertexFormat::Float32x2,
-                            AttributeFormat::Float32x3 => wgpu::VertexFormat::Float32x3,
-                            AttributeFormat::Float32x4 => wgpu::VertexFormat::Float32x4,
-                            AttributeFormat::Uint16x2 => wgpu::VertexFormat::Uint16x2,
-                            AttributeFormat::Uint16x4 => wgpu::VertexFormat::Uint16x4,
-                            AttributeFormat::Uint32 => wgpu::VertexFormat::Uint32,
-                            AttributeFormat::Uint32x2 => wgpu::VertexFormat::Uint32x2,
-                            AttributeFormat::Uint32x3 => wgpu::VertexFormat::Uint32x3,
-                            AttributeFormat::Uint32x4 => wgpu::VertexFormat::Uint32x4,
-                        },
-                        offset: offset as u64,
-                        shader_location: index as u32,
-                    }
-                })
-                .collect::<Vec<_>>();
-
-            let vertex_buffers = [wgpu::VertexBufferLayout {
-                array_stride: vertex_array_stride as u64,
+            let mesh_layout = mesh.vertex_buffer_layout();
+            let (vertex_array_stride, vertex_attributes) = wgpu_vertex_attributes(mesh_layout, 0);
+            let mut vertex_buffers = vec![wgpu::VertexBufferLayout {
+                array_stride: vertex_array_stride,
                 step_mode: wgpu::VertexStepMode::Vertex,
                 attributes: vertex_attributes.as_slice(),
             }];
 
+            // attributes of the extra buffers are located after the mesh ones
+            let extra_attributes =
+                extra_vertex_attributes(pipeline.vertex_buffers, mesh_layout.len() as u32);
+            for (buffer, (array_stride, attributes)) in
+                pipeline.vertex_buffers.iter().zip(extra_attributes.iter())
+            {
+                vertex_buffers.push(wgpu::VertexBufferLayout {
+                    array_stride: *array_stride,
+                    step_mode: buffer.step_mode.into(),
+                    attributes: attributes.as_slice(),
+                });
+            }
+
+            let (color_formats, depth_format) = ctx.pass_formats(pass);
+            // the stencil is tested only if the depth attachment has it
+            let stencil = match (pipeline.options.stencil, depth_format) {
+                (Some(state), Some(wgpu::TextureFormat::Depth24PlusStencil8)) => Some(state),
+                _ => None,
+            };
+            let color_targets = color_formats
+                .into_iter()
+                .enumerate()
+                .map(|(target, format)| wgpu::ColorTargetState {
+                    format,
+                    blend: Some(pipeline.options.target_blend(target)),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })
+                .collect::<Vec<_>>();
+
             // create the pipeline
             let wgpu_pipeline =
                 ctx.device
@@ -752,70 +3129,81 @@ impl PipelineBackend {
                         vertex: wgpu::VertexState {
                             module: wgpu_shader_module,
                             entry_point: "vs_main",
-                            buffers: &vertex_buffers,
+                            buffers: vertex_buffers.as_slice(),
+                        },
+                        // depth only passes have no fragment stage
+                        fragment: if color_targets.is_empty() {
+                            None
+                        } else {
+                            Some(wgpu::FragmentState {
+                                module: wgpu_shader_module,
+                                entry_point: "fs_main",
+                                targets: &color_targets,
+                            })
                         },
-                        fragment: Some(wgpu::FragmentState {
-                            module: wgpu_shader_module,
-                            entry_point: "fs_main",
-                            targets: &[if depth_buffer_mode == DepthBufferMode::Disabled {
-                                wgpu::ColorTargetState {
-                                    format: ctx.sur_desc.format,
-                                    blend: Some(wgpu::BlendState {
-                                        color: wgpu::BlendComponent {
-                                            src_factor: wgpu::BlendFactor::One,
-                                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                                            operation: wgpu::BlendOperation::Add,
-                                        },
-                                        alpha: wgpu::BlendComponent {
-                                            src_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                                            dst_factor: wgpu::BlendFactor::One,
-                                            operation: wgpu::BlendOperation::Add,
-                                        },
-                                    }),
-                                    write_mask: wgpu::ColorWrites::ALL,
-                                }
-                            } else {
-                                wgpu::ColorTargetState {
-                                    format: ctx.sur_desc.format,
-                                    blend: Some(wgpu::BlendState {
-                                        color: wgpu::BlendComponent::REPLACE,
-                                        alpha: wgpu::BlendComponent::REPLACE,
-                                    }),
-                                    write_mask: wgpu::ColorWrites::ALL,
-                                }
-                            }],
-                        }),
                         primitive: wgpu::PrimitiveState {
+                            topology: pipeline.options.topology.into(),
+                            // indexed strips use the index format of the meshes
+                            strip_index_format: pipeline
+                                .options
+                                .topology
+                                .is_strip()
+                                .then_some(wgpu::IndexFormat::Uint32),
                             front_face: wgpu::FrontFace::Ccw,
                             cull_mode: if !pipeline.options.disable_cull_mode {
                                 Some(wgpu::Face::Back)
                             } else {
                                 None
                             },
+                            polygon_mode: pipeline.options.polygon_mode.rasterized(ctx.features()),
+                            unclipped_depth: pipeline.options.unclipped_depth
+                                && ctx.features().contains(wgpu::Features::DEPTH_CLIP_CONTROL),
                             ..Default::default()
                         },
-                        depth_stencil: if depth_buffer_mode != DepthBufferMode::Disabled {
-                            Some(wgpu::DepthStencilState {
-                                format: wgpu::TextureFormat::Depth32Float,
-                                depth_write_enabled: depth_buffer_mode == DepthBufferMode::Write,
-                                depth_compare: wgpu::CompareFunction::Less,
-                                stencil: wgpu::StencilState::default(),
-                                bias: wgpu::DepthBiasState {
-                                    constant: 2, // corresponds to bilinear filtering
-                                    slope_scale: 2.0,
-                                    clamp: 0.0,
-                                },
-                            })
-                        } else {
-                            None
+                        depth_stencil: match depth_format {
+                            Some(format)
+                                if depth_buffer_mode != DepthBufferMode::Disabled
+                                    || stencil.is_some() =>
+                            {
+                                Some(wgpu::DepthStencilState {
+                                    format,
+                                    depth_write_enabled: depth_buffer_mode
+                                        == DepthBufferMode::Write,
+                                    depth_compare: match (
+                                        depth_buffer_mode,
+                                        pipeline.options.depth_compare,
+                                    ) {
+                                        (DepthBufferMode::Disabled, _) => {
+                                            wgpu::CompareFunction::Always
+                                        }
+                                        (_, DepthCompare::Less) => wgpu::CompareFunction::Less,
+                                        (_, DepthCompare::LessEqual) => {
+                                            wgpu::CompareFunction::LessEqual
+                                        }
+                                        (_, DepthCompare::Equal) => wgpu::CompareFunction::Equal,
+                                        (_, DepthCompare::Always) => wgpu::CompareFunction::Always,
+                                    },
+                                    stencil: stencil
+                                        .map(wgpu::StencilState::from)
+                                        .unwrap_or_default(),
+                                    bias: pipeline.options.depth_bias.into(),
+                                })
+                            }
+                            _ => None,
+                        },
+                        multisample: wgpu::MultisampleState {
+                            count: ctx.pass_sample_count(pass),
+                            ..Default::default()
                         },
-                        multisample: wgpu::MultisampleState::default(),
                         multiview: None,
                     });
 
             PipelineInstance::Render(RenderPipelineBackend {
                 wgpu_pipeline,
                 depth_buffer_mode,
+                stencil: stencil.is_some(),
+                transparent: pipeline.options.transparent,
+                topology: pipeline.options.topology,
             })
         } else {
             // compute pipeline
@@ -833,39 +3221,162 @@ impl PipelineBackend {
 
         Self {
             wgpu_bind_group_layouts,
+            push_constants: push_constant_ranges
+                .first()
+                .map(|range| range.stages)
+                .unwrap_or_else(wgpu::ShaderStages::empty),
             instance,
         }
     }
 
-    fn bind_group_layout(device: &wgpu::Device, bind_group: &BindGroup) -> wgpu::BindGroupLayout {
-        let entries = bind_group
+    /// Checks, that the bind groups of the layout match the shader
+    pub(crate) fn validate(
+        ctx: &Context,
+        shader_module: &ShaderModule,
+        pipeline: &PipelineLayout,
+        pass: Option<&str>,
+    ) -> Result<(), BindingError> {
+        let reflection = match shader_module.reflection.as_ref() {
+            Some(reflection) => reflection,
+            None => return Ok(()),
+        };
+        let mut entry_points = Vec::with_capacity(2);
+        if pipeline.mesh.is_some() {
+            entry_points.push(("vs_main", wgpu::ShaderStages::VERTEX));
+            // depth only passes have no fragment stage
+            if !ctx.pass_formats(pass).0.is_empty() {
+                entry_points.push(("fs_main", wgpu::ShaderStages::FRAGMENT));
+            }
+        } else {
+            entry_points.push(("main", wgpu::ShaderStages::COMPUTE));
+        }
+        let bind_groups = pipeline
             .bindings
             .iter()
-            .enumerate()
-            .map(|(index, binding)| match binding {
-                Binding::Uniform(_, stage, _) => wgpu::BindGroupLayoutEntry {
-                    binding: index as u32,
-                    visibility: visibility(stage),
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+            .map(|bind_group| {
+                bind_group
+                    .bindings
+                    .iter()
+                    .map(Binding::label)
+                    .zip(Self::bind_group_layout_entries(ctx, bind_group))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        reflection.validate(&pipeline.shader.name, &entry_points, &bind_groups)
+    }
+
+    fn bind_group_layout(ctx: &Context, bind_group: &BindGroup) -> wgpu::BindGroupLayout {
+        let entries = Self::bind_group_layout_entries(ctx, bind_group);
+        ctx.device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(bind_group.label),
+                entries: entries.as_slice(),
+            })
+    }
+
+    fn bind_group_layout_entries(
+        ctx: &Context,
+        bind_group: &BindGroup,
+    ) -> Vec<wgpu::BindGroupLayoutEntry> {
+        layout_entries(bind_group, |name| {
+            let format = ctx
+                .attachment(name)
+                .expect("Render graph attachment must be created")
+                .format;
+            (format, ctx.attachment_layers(name))
+        })
+    }
+}
+
+/// Returns layout entries of the bind group, `attachment` returns format and number of layers
+/// of the render graph attachments
+fn layout_entries(
+    bind_group: &BindGroup,
+    attachment: impl Fn(&str) -> (super::TextureFormat, u32),
+) -> Vec<wgpu::BindGroupLayoutEntry> {
+    bind_group
+        .bindings
+        .iter()
+        .enumerate()
+        .map(|(index, binding)| match binding {
+            Binding::Uniform(_, stage, _) => wgpu::BindGroupLayoutEntry {
+                binding: index as u32,
+                visibility: visibility(stage),
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            Binding::DynamicUniform(_, stage, _, size) => wgpu::BindGroupLayoutEntry {
+                binding: index as u32,
+                visibility: visibility(stage),
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(*size as u64),
+                },
+                count: None,
+            },
+            Binding::Texture(_, stage, texture) => wgpu::BindGroupLayoutEntry {
+                binding: index as u32,
+                visibility: visibility(stage),
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    sample_type: if texture.format.is_depth() {
+                        wgpu::TextureSampleType::Depth
+                    } else {
+                        wgpu::TextureSampleType::Float {
+                            filterable: texture.format.is_filterable(),
+                        }
                     },
-                    count: None,
+                    view_dimension: wgpu::TextureViewDimension::D2,
                 },
-                Binding::Texture(_, stage, texture) => wgpu::BindGroupLayoutEntry {
+                count: None,
+            },
+            Binding::Attachment(_, stage, name) => {
+                let (format, layers) = attachment(name);
+                wgpu::BindGroupLayoutEntry {
                     binding: index as u32,
                     visibility: visibility(stage),
                     ty: wgpu::BindingType::Texture {
                         multisampled: false,
-                        sample_type: wgpu::TextureSampleType::Float {
-                            filterable: texture.format.is_filterable(),
+                        sample_type: if format.is_depth() {
+                            wgpu::TextureSampleType::Depth
+                        } else {
+                            wgpu::TextureSampleType::Float {
+                                filterable: format.is_filterable(),
+                            }
+                        },
+                        view_dimension: if layers > 1 {
+                            wgpu::TextureViewDimension::D2Array
+                        } else {
+                            wgpu::TextureViewDimension::D2
                         },
-                        view_dimension: wgpu::TextureViewDimension::D2,
                     },
                     count: None,
+                }
+            }
+            Binding::TextureArray(_, stage, texture) => wgpu::BindGroupLayoutEntry {
+                binding: index as u32,
+                visibility: visibility(stage),
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    sample_type: if texture.format.is_depth() {
+                        wgpu::TextureSampleType::Depth
+                    } else {
+                        wgpu::TextureSampleType::Float {
+                            filterable: texture.format.is_filterable(),
+                        }
+                    },
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
                 },
-                Binding::Texture3D(_, stage, texture) => wgpu::BindGroupLayoutEntry {
+                count: None,
+            },
+            #[allow(deprecated)]
+            Binding::TextureCube(_, stage, texture) | Binding::Texture3D(_, stage, texture) => {
+                wgpu::BindGroupLayoutEntry {
                     binding: index as u32,
                     visibility: visibility(stage),
                     ty: wgpu::BindingType::Texture {
@@ -876,60 +3387,61 @@ impl PipelineBackend {
                         view_dimension: wgpu::TextureViewDimension::Cube,
                     },
                     count: None,
-                },
-                Binding::StorageTexture(_, stage, texture) => wgpu::BindGroupLayoutEntry {
-                    binding: index as u32,
-                    visibility: visibility(stage),
-                    ty: wgpu::BindingType::StorageTexture {
-                        access: texture.mode.into(),
-                        format: texture.format.into(),
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                },
-                Binding::Sampler(_, stage, _) => wgpu::BindGroupLayoutEntry {
-                    binding: index as u32,
-                    visibility: visibility(stage),
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-                Binding::Storage(_, stage, storage) => {
-                    let read_only = matches!(storage.mode, StorageBufferMode::Read);
-                    wgpu::BindGroupLayoutEntry {
-                        binding: index as u32,
-                        visibility: visibility(stage),
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }
                 }
-                Binding::StorageAsUniform(_, stage, _) => wgpu::BindGroupLayoutEntry {
+            }
+            Binding::StorageTexture(_, stage, texture) => wgpu::BindGroupLayoutEntry {
+                binding: index as u32,
+                visibility: visibility(stage),
+                ty: wgpu::BindingType::StorageTexture {
+                    access: texture.mode.into(),
+                    format: texture.format.into(),
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            Binding::Sampler(_, stage, sampler) => wgpu::BindGroupLayoutEntry {
+                binding: index as u32,
+                visibility: visibility(stage),
+                ty: wgpu::BindingType::Sampler(if sampler.compare.is_some() {
+                    wgpu::SamplerBindingType::Comparison
+                } else {
+                    wgpu::SamplerBindingType::Filtering
+                }),
+                count: None,
+            },
+            Binding::Storage(_, stage, storage) => {
+                let read_only = matches!(storage.mode, StorageBufferMode::Read);
+                wgpu::BindGroupLayoutEntry {
                     binding: index as u32,
                     visibility: visibility(stage),
                     ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
+                        ty: wgpu::BufferBindingType::Storage { read_only },
                         has_dynamic_offset: false,
                         min_binding_size: None,
                     },
                     count: None,
+                }
+            }
+            Binding::StorageAsUniform(_, stage, _) => wgpu::BindGroupLayoutEntry {
+                binding: index as u32,
+                visibility: visibility(stage),
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
                 },
-            })
-            .collect::<Vec<_>>();
-
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some(bind_group.label),
-            entries: entries.as_slice(),
+                count: None,
+            },
         })
-    }
+        .collect::<Vec<_>>()
 }
 
 /// Pipeline Bindings
 #[derive(Default)]
 pub struct Bindings {
     wgpu_bind_groups: Vec<wgpu::BindGroup>,
+    /// Numbers of the dynamic uniforms of the bind groups
+    dynamic_offsets: Vec<usize>,
 }
 
 impl Bindings {
@@ -939,6 +3451,16 @@ impl Bindings {
         pipeline: &PipelineBackend,
         bind_groups: &[BindGroup],
     ) {
+        self.dynamic_offsets = bind_groups
+            .iter()
+            .map(|bind_group| {
+                bind_group
+                    .bindings
+                    .iter()
+                    .filter(|binding| matches!(binding, Binding::DynamicUniform(..)))
+                    .count()
+            })
+            .collect();
         self.wgpu_bind_groups = pipeline
             .wgpu_bind_group_layouts
             .iter()
@@ -956,11 +3478,32 @@ impl Bindings {
                                 Binding::Uniform(_, _, uniform) => {
                                     uniform.get().as_entire_binding()
                                 }
+                                Binding::DynamicUniform(_, _, uniform, size) => {
+                                    wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                                        buffer: uniform.get(),
+                                        offset: 0,
+                                        size: wgpu::BufferSize::new(*size as u64),
+                                    })
+                                }
                                 Binding::Texture(_, _, texture)
-                                | Binding::Texture3D(_, _, texture)
                                 | Binding::StorageTexture(_, _, texture) => {
                                     wgpu::BindingResource::TextureView(texture.get())
                                 }
+                                #[allow(deprecated)]
+                                Binding::TextureCube(_, _, texture)
+                                | Binding::Texture3D(_, _, texture) => {
+                                    wgpu::BindingResource::TextureView(texture.get_cube())
+                                }
+                                Binding::TextureArray(_, _, texture) => {
+                                    wgpu::BindingResource::TextureView(texture.get_array())
+                                }
+                                Binding::Attachment(_, _, name) => {
+                                    wgpu::BindingResource::TextureView(
+                                        ctx.attachment(name)
+                                            .expect("Render graph attachment must be created")
+                                            .get(),
+                                    )
+                                }
                                 Binding::Sampler(_, _, sampler) => {
                                     wgpu::BindingResource::Sampler(sampler.get())
                                 }
@@ -988,6 +3531,24 @@ impl Bindings {
     /// Unloads bindings from GPU
     pub fn unload(&mut self) {
         self.wgpu_bind_groups.clear();
+        self.dynamic_offsets.clear();
+    }
+
+    /// Returns bind groups with their slices of the dynamic offsets
+    fn with_offsets<'a>(
+        &'a self,
+        offsets: &'a [u32],
+    ) -> impl Iterator<Item = (&'a wgpu::BindGroup, &'a [u32])> {
+        let mut start = 0;
+        self.wgpu_bind_groups
+            .iter()
+            .zip(self.dynamic_offsets.iter())
+            .map(move |(bind_group, &count)| {
+                let end = (start + count).min(offsets.len());
+                let group_offsets = &offsets[start.min(end)..end];
+                start += count;
+                (bind_group, group_offsets)
+            })
     }
 }
 
@@ -995,10 +3556,12 @@ impl Bindings {
 #[derive(Default)]
 pub struct ShaderModule {
     wgpu_shader_model: Option<wgpu::ShaderModule>,
+    reflection: Option<Reflection>,
 }
 
 impl ShaderModule {
     pub(crate) fn load(&mut self, ctx: &Context, name: &str, code: &str) {
+        self.reflection = Reflection::new(code);
         self.wgpu_shader_model = Some(ctx.device.create_shader_module(
             &wgpu::ShaderModuleDescriptor {
                 label: Some(name),
@@ -1023,3 +3586,271 @@ impl ShaderModule {
             .expect("Shader model must be loaded")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::Instance;
+
+    #[test]
+    fn instance_attributes_follow_mesh_locations() {
+        let (stride, attributes) =
+            wgpu_vertex_attributes(&[AttributeFormat::Float32x3, AttributeFormat::Float32x2], 0);
+        assert_eq!(stride, 20);
+        assert_eq!(attributes[1].offset, 12);
+        assert_eq!(attributes[1].shader_location, 1);
+        assert_eq!(attributes[1].format, wgpu::VertexFormat::Float32x2);
+
+        let (stride, attributes) = wgpu_vertex_attributes(Instance::layout().attributes, 2);
+        assert_eq!(stride, std::mem::size_of::<Instance>() as u64);
+        assert_eq!(
+            attributes
+                .iter()
+                .map(|attribute| (attribute.shader_location, attribute.offset))
+                .collect::<Vec<_>>(),
+            vec![(2, 0), (3, 16), (4, 32), (5, 48), (6, 64)]
+        );
+    }
+
+    #[test]
+    fn cube_maps_are_bound_as_cube_views() {
+        use super::super::{StorageTextureAccess, TextureFormat};
+
+        let cube = TextureBuffer::new(StorageTextureAccess::Read, TextureFormat::rgba_f16());
+        let bind_group = BindGroup::new(
+            "Globals",
+            vec![
+                Binding::TextureCube("Environment", Stage::Fragment, &cube),
+                Binding::Attachment("Shadows", Stage::Fragment, "shadows"),
+            ],
+        );
+        let entries = layout_entries(&bind_group, |name| {
+            assert_eq!(name, "shadows");
+            (TextureFormat::depth_f32(), 6)
+        });
+        assert_eq!(
+            entries[0].ty,
+            wgpu::BindingType::Texture {
+                multisampled: false,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::Cube,
+            }
+        );
+        // six layers attachments are sampled as arrays, unless bound as cube maps
+        assert_eq!(
+            entries[1].ty,
+            wgpu::BindingType::Texture {
+                multisampled: false,
+                sample_type: wgpu::TextureSampleType::Depth,
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+            }
+        );
+    }
+
+    #[test]
+    fn only_aligned_uploads_are_staged() {
+        assert_eq!(staged_size(0, 16).map(|size| size.get()), Some(16));
+        assert_eq!(staged_size(64, 4).map(|size| size.get()), Some(4));
+        assert_eq!(staged_size(0, 0), None);
+        assert_eq!(staged_size(0, 6), None);
+        assert_eq!(staged_size(2, 8), None);
+    }
+
+    #[test]
+    fn updated_regions_are_within_buffers() {
+        assert!(region_fits(0, 16, 16));
+        assert!(region_fits(12, 4, 16));
+        assert!(region_fits(16, 0, 16));
+        assert!(!region_fits(12, 8, 16));
+        assert!(!region_fits(u64::MAX, 4, 16));
+    }
+
+    #[test]
+    fn texture_readbacks_strip_row_padding() {
+        let format = crate::renderer::TextureFormat::rgba_u8norm();
+        let (bytes_per_row, rows) = format.layout(3, 2);
+        assert_eq!((bytes_per_row, rows), (12, 2));
+        let padded_bytes_per_row = super::super::aligned_size(
+            bytes_per_row as usize,
+            wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize,
+        );
+        assert_eq!(padded_bytes_per_row, 256);
+
+        // two layers of two rows each, padding is filled with zeros
+        let mut mapped = vec![0; padded_bytes_per_row * 4];
+        for (row, chunk) in mapped.chunks_mut(padded_bytes_per_row).enumerate() {
+            chunk[..12].fill(row as u8 + 1);
+        }
+        let data = unpad_rows(&mapped, 12, padded_bytes_per_row, 4);
+        assert_eq!(data.len(), format.data_size(3, 2) * 2);
+        for (row, chunk) in data.chunks(12).enumerate() {
+            assert!(chunk.iter().all(|&value| value == row as u8 + 1));
+        }
+    }
+
+    #[test]
+    fn samplers_describe_their_modes() {
+        let descriptor = Sampler::default().descriptor();
+        assert_eq!(descriptor.address_mode_u, wgpu::AddressMode::Repeat);
+        assert_eq!(descriptor.mag_filter, wgpu::FilterMode::Nearest);
+        assert_eq!(descriptor.min_filter, wgpu::FilterMode::Linear);
+        assert_eq!(descriptor.compare, None);
+
+        let descriptor = Sampler::default()
+            .with_address_modes(
+                AddressMode::ClampToEdge,
+                AddressMode::MirrorRepeat,
+                AddressMode::Repeat,
+            )
+            .with_filter(FilterMode::Nearest, FilterMode::Nearest)
+            .with_mipmap_filter(FilterMode::Nearest)
+            .descriptor();
+        assert_eq!(descriptor.address_mode_u, wgpu::AddressMode::ClampToEdge);
+        assert_eq!(descriptor.address_mode_v, wgpu::AddressMode::MirrorRepeat);
+        assert_eq!(descriptor.address_mode_w, wgpu::AddressMode::Repeat);
+        assert_eq!(descriptor.min_filter, wgpu::FilterMode::Nearest);
+        assert_eq!(descriptor.mipmap_filter, wgpu::FilterMode::Nearest);
+
+        // anisotropic filtering overrides the nearest filters
+        let descriptor = Sampler::default()
+            .with_filter(FilterMode::Nearest, FilterMode::Nearest)
+            .with_anisotropy(8)
+            .descriptor();
+        assert_eq!(descriptor.mag_filter, wgpu::FilterMode::Linear);
+        assert_eq!(descriptor.min_filter, wgpu::FilterMode::Linear);
+        assert_eq!(descriptor.anisotropy_clamp, std::num::NonZeroU8::new(8));
+
+        let comparison = Sampler::comparison();
+        let sampler = Sampler::default();
+        assert_eq!(
+            comparison.descriptor().compare,
+            Some(wgpu::CompareFunction::LessEqual)
+        );
+        let bind_group = BindGroup::new(
+            "Globals",
+            vec![
+                Binding::Sampler("Shadows", Stage::Fragment, &comparison),
+                Binding::Sampler("Sampler", Stage::Fragment, &sampler),
+            ],
+        );
+        let entries = layout_entries(&bind_group, |_| unreachable!());
+        assert_eq!(
+            entries[0].ty,
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison)
+        );
+        assert_eq!(
+            entries[1].ty,
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+        );
+    }
+
+    #[test]
+    fn named_samplers_are_replaced() {
+        let mut samplers = Samplers::default();
+        samplers.insert("pixel", Sampler::default());
+        samplers.insert("pixel", Sampler::linear_clamp());
+        assert_eq!(
+            samplers.get("pixel").map(|sampler| sampler.mag_filter),
+            Some(FilterMode::Linear)
+        );
+        assert!(samplers.remove("pixel").is_some());
+        assert!(samplers.get("pixel").is_none());
+    }
+
+    #[test]
+    fn stencil_is_tested_on_depth_stencil_attachments() {
+        use super::super::{
+            Attachment, StencilOperation, StencilState, StorageTextureAccess, TextureFormat,
+        };
+
+        let attachment = |format| AttachmentBackend {
+            buffer: TextureBuffer::new(StorageTextureAccess::Read, format),
+            width: 1,
+            height: 1,
+            layers: 1,
+            samples: 1,
+            layer_views: Vec::new(),
+            multisampled_view: None,
+        };
+        let mut attachments = HashMap::new();
+        attachments.insert(
+            String::from("mask"),
+            attachment(Attachment::depth_stencil().format),
+        );
+        attachments.insert(
+            String::from("shadows"),
+            attachment(TextureFormat::depth_f32()),
+        );
+
+        assert!(has_stencil(
+            "mask",
+            wgpu::TextureFormat::Depth32Float,
+            &attachments
+        ));
+        assert!(!has_stencil(
+            "shadows",
+            wgpu::TextureFormat::Depth32Float,
+            &attachments
+        ));
+        assert!(!has_stencil(
+            "missing",
+            wgpu::TextureFormat::Depth32Float,
+            &attachments
+        ));
+        assert!(!has_stencil(
+            DEPTH,
+            wgpu::TextureFormat::Depth32Float,
+            &attachments
+        ));
+        assert!(has_stencil(
+            DEPTH,
+            wgpu::TextureFormat::Depth24PlusStencil8,
+            &attachments
+        ));
+
+        let state = wgpu::StencilState::from(StencilState::write());
+        assert_eq!(state.front, state.back);
+        assert_eq!(state.front.compare, wgpu::CompareFunction::Always);
+        assert_eq!(state.front.pass_op, wgpu::StencilOperation::Replace);
+        assert_eq!(state.write_mask, !0);
+
+        let state = wgpu::StencilState::from(StencilState {
+            fail_op: StencilOperation::IncrementWrap,
+            ..StencilState::test(CompareFunction::NotEqual)
+        });
+        assert_eq!(state.back.compare, wgpu::CompareFunction::NotEqual);
+        assert_eq!(state.back.fail_op, wgpu::StencilOperation::IncrementWrap);
+        assert_eq!(state.back.pass_op, wgpu::StencilOperation::Keep);
+        assert_eq!(state.write_mask, 0);
+    }
+
+    #[test]
+    fn extra_vertex_buffers_follow_each_other() {
+        use crate::renderer::{VertexBufferLayout, VertexStepMode};
+
+        let buffers = [
+            VertexBufferLayout::vertex(&[AttributeFormat::Float32x4]),
+            Instance::layout(),
+        ];
+        assert_eq!(buffers[0].step_mode, VertexStepMode::Vertex);
+        assert_eq!(buffers[1].step_mode, VertexStepMode::Instance);
+        assert_eq!(
+            wgpu::VertexStepMode::from(buffers[1].step_mode),
+            wgpu::VertexStepMode::Instance
+        );
+
+        let attributes = extra_vertex_attributes(&buffers, 3);
+        assert_eq!(attributes[0].0, 16);
+        assert_eq!(attributes[0].1[0].shader_location, 3);
+        assert_eq!(attributes[1].0, std::mem::size_of::<Instance>() as u64);
+        assert_eq!(
+            attributes[1]
+                .1
+                .iter()
+                .map(|attribute| attribute.shader_location)
+                .collect::<Vec<_>>(),
+            vec![4, 5, 6, 7, 8]
+        );
+        assert!(extra_vertex_attributes(&[], 3).is_empty());
+    }
+}
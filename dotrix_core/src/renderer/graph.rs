@@ -0,0 +1,744 @@
+//! Render graph: named passes declaring attachments they read and write
+use std::collections::HashMap;
+
+use super::{TextureFormat, Viewport};
+use crate::Color;
+
+/// Name of the swapchain surface attachment
+pub const SURFACE: &str = "surface";
+/// Name of the default depth buffer attachment
+pub const DEPTH: &str = "depth";
+/// Name of the default pass, used by pipelines without explicit pass
+pub const MAIN_PASS: &str = "main";
+/// Name of the optional depth-only pass of the default depth buffer before the main pass
+pub const DEPTH_PREPASS: &str = "depth_prepass";
+
+/// Size of the graph attachment
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttachmentSize {
+    /// The same size as the surface
+    Surface,
+    /// Surface size multiplied by the factor
+    Scaled(f32),
+    /// Fixed width and height
+    Fixed(u32, u32),
+}
+
+impl AttachmentSize {
+    /// Returns width and height of the attachment for the surface size
+    pub fn resolve(&self, width: u32, height: u32) -> (u32, u32) {
+        match *self {
+            AttachmentSize::Surface => (width, height),
+            AttachmentSize::Scaled(factor) => (
+                ((width as f32 * factor) as u32).max(1),
+                ((height as f32 * factor) as u32).max(1),
+            ),
+            AttachmentSize::Fixed(width, height) => (width.max(1), height.max(1)),
+        }
+    }
+}
+
+/// Intermediate texture created by the graph
+#[derive(Clone, Copy, Debug)]
+pub struct Attachment {
+    /// Pixel format
+    pub format: TextureFormat,
+    /// Size of the texture
+    pub size: AttachmentSize,
+    /// Number of the texture array layers
+    pub layers: u32,
+    /// Rendered with the sample count of the surface and resolved to the texture
+    pub multisampled: bool,
+    /// Passes access the attachment in the order they were added to the graph
+    pub sequential: bool,
+    /// The attachment is the surface of the additional window of the same name, its format
+    /// and size are defined by the window, see [`crate::Window::open_window`]
+    pub window: bool,
+}
+
+impl Attachment {
+    /// Constructs new color attachment of the surface size
+    pub fn color(format: TextureFormat) -> Self {
+        Self {
+            format,
+            size: AttachmentSize::Surface,
+            layers: 1,
+            multisampled: false,
+            sequential: false,
+            window: false,
+        }
+    }
+
+    /// Constructs new depth attachment of the surface size
+    pub fn depth() -> Self {
+        Self {
+            format: TextureFormat::depth_f32(),
+            size: AttachmentSize::Surface,
+            layers: 1,
+            multisampled: false,
+            sequential: false,
+            window: false,
+        }
+    }
+
+    /// Constructs new combined depth and stencil attachment of the surface size
+    ///
+    /// Stencil is cleared with the depth and pipelines test it with
+    /// [`super::PipelineOptions::stencil`]. When sampled, the attachment provides the depth.
+    pub fn depth_stencil() -> Self {
+        Self {
+            format: TextureFormat::depth24_stencil8(),
+            ..Self::depth()
+        }
+    }
+
+    /// Constructs the attachment of the additional window surface
+    ///
+    /// Passes writing the attachment are rendered into the window of the attachment name. Until
+    /// the window is opened, [`crate::Renderer::attachment`] returns `None` and the passes render
+    /// nothing, so their pipelines have to be bound after. The surface can't be read by passes.
+    pub fn window() -> Self {
+        Self {
+            window: true,
+            ..Self::color(TextureFormat::rgba_u8norm_srgb())
+        }
+    }
+
+    /// Sets the size of the attachment
+    #[must_use]
+    pub fn with_size(mut self, size: AttachmentSize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Makes the attachment a texture array, passes render into its layers
+    #[must_use]
+    pub fn with_layers(mut self, layers: u32) -> Self {
+        self.layers = layers.max(1);
+        self
+    }
+
+    /// Enables multisampling of the color attachment, so it can be rendered together with the
+    /// surface depth buffer
+    #[must_use]
+    pub fn with_multisampling(mut self) -> Self {
+        self.multisampled = true;
+        self
+    }
+
+    /// Makes passes access the attachment in the order they were added to the graph
+    ///
+    /// A reader waits only for the closest writer added before it and a writer waits for the
+    /// readers added before it, so a chain of passes can ping-pong between two attachments.
+    #[must_use]
+    pub fn sequential(mut self) -> Self {
+        self.sequential = true;
+        self
+    }
+}
+
+/// Render pass of the graph
+#[derive(Clone, Debug)]
+pub struct RenderPass {
+    /// Unique name of the pass
+    pub name: String,
+    /// Attachments sampled by the pipelines of the pass
+    pub reads: Vec<String>,
+    /// Attachments sampled by the pass before their writers run, so they keep the content of
+    /// the previous frame
+    pub history: Vec<String>,
+    /// Color attachments rendered by the pass
+    pub writes: Vec<String>,
+    /// Depth attachment of the pass
+    pub depth: Option<String>,
+    /// Clear color of the written attachments at the beginning of the frame
+    pub clear_color: Option<Color>,
+    /// Clear the depth attachment at the beginning of the frame
+    pub clear_depth: bool,
+    /// Layer of the written texture array attachments
+    pub layer: u32,
+    /// Viewport of the pipelines of the pass, the whole attachments if not set
+    pub viewport: Option<Viewport>,
+    /// GPU buffers written by the compute pipelines of the pass
+    pub produces: Vec<String>,
+    /// GPU buffers read by the pipelines of the pass, written by the producing passes
+    pub consumes: Vec<String>,
+}
+
+impl RenderPass {
+    /// Constructs new pass without attachments
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+            reads: Vec::new(),
+            history: Vec::new(),
+            writes: Vec::new(),
+            depth: None,
+            clear_color: None,
+            clear_depth: false,
+            layer: 0,
+            viewport: None,
+            produces: Vec::new(),
+            consumes: Vec::new(),
+        }
+    }
+
+    /// Adds attachment sampled by the pass
+    #[must_use]
+    pub fn reads(mut self, attachment: &str) -> Self {
+        self.reads.push(String::from(attachment));
+        self
+    }
+
+    /// Adds attachment of the previous frame sampled by the pass
+    ///
+    /// The pass does not wait for the writers of the attachment, but runs before them, so
+    /// temporal effects can feed their output back.
+    #[must_use]
+    pub fn reads_history(mut self, attachment: &str) -> Self {
+        self.history.push(String::from(attachment));
+        self
+    }
+
+    /// Adds color attachment rendered by the pass
+    #[must_use]
+    pub fn writes(mut self, attachment: &str) -> Self {
+        self.writes.push(String::from(attachment));
+        self
+    }
+
+    /// Sets depth attachment of the pass
+    #[must_use]
+    pub fn depth(mut self, attachment: &str) -> Self {
+        self.depth = Some(String::from(attachment));
+        self
+    }
+
+    /// Sets layer of the written texture array attachments
+    #[must_use]
+    pub fn layer(mut self, layer: u32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Sets viewport of the pipelines of the pass
+    #[must_use]
+    pub fn viewport(mut self, viewport: Viewport) -> Self {
+        self.viewport = Some(viewport);
+        self
+    }
+
+    /// Adds GPU buffer written by the compute pipelines of the pass
+    ///
+    /// The pass runs before every pass consuming the buffer, so the draws and dispatches of
+    /// the consumers read the buffer computed in the same frame. Buffers are named freely and
+    /// do not have to be added to the graph.
+    #[must_use]
+    pub fn produces(mut self, buffer: &str) -> Self {
+        self.produces.push(String::from(buffer));
+        self
+    }
+
+    /// Adds GPU buffer read by the pipelines of the pass, see [`RenderPass::produces`]
+    #[must_use]
+    pub fn consumes(mut self, buffer: &str) -> Self {
+        self.consumes.push(String::from(buffer));
+        self
+    }
+
+    /// Clears written attachments with the color and depth attachment at the frame beginning
+    #[must_use]
+    pub fn clear(mut self, color: Color) -> Self {
+        self.clear_color = Some(color);
+        self.clear_depth = true;
+        self
+    }
+
+    fn outputs(&self) -> impl Iterator<Item = &String> {
+        self.writes.iter().chain(self.depth.iter())
+    }
+}
+
+/// Offscreen color and depth textures rendered by their own pass
+///
+/// Pipelines render into the target using the pass of the target name, the color texture is
+/// available as the attachment of the same name, so it can be bound in later passes.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderTarget {
+    /// Color texture format
+    pub format: TextureFormat,
+    /// Size of the target
+    pub size: AttachmentSize,
+    /// Render target has depth texture
+    pub depth: bool,
+    /// Clear color of the target
+    pub clear_color: Color,
+}
+
+impl RenderTarget {
+    /// Constructs new target of the fixed size with depth texture
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            format: TextureFormat::rgba_u8norm(),
+            size: AttachmentSize::Fixed(width, height),
+            depth: true,
+            clear_color: Color::black(),
+        }
+    }
+
+    /// Sets the color texture format
+    #[must_use]
+    pub fn with_format(mut self, format: TextureFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the size of the target
+    #[must_use]
+    pub fn with_size(mut self, size: AttachmentSize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Sets the clear color
+    #[must_use]
+    pub fn with_clear_color(mut self, clear_color: Color) -> Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    /// Disables the depth texture
+    #[must_use]
+    pub fn without_depth(mut self) -> Self {
+        self.depth = false;
+        self
+    }
+
+    /// Returns name of the depth attachment of the target
+    pub fn depth_attachment(name: &str) -> String {
+        format!("{}.depth", name)
+    }
+}
+
+/// Render graph errors
+#[derive(Debug, PartialEq, Eq)]
+pub enum GraphError {
+    /// Pass refers the attachment, that was not added to the graph
+    UnknownAttachment {
+        /// Name of the pass
+        pass: String,
+        /// Name of the attachment
+        attachment: String,
+    },
+    /// Passes depend on each other
+    Cycle(String),
+}
+
+impl std::error::Error for GraphError {}
+
+impl std::fmt::Display for GraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphError::UnknownAttachment { pass, attachment } => write!(
+                f,
+                "Pass `{}` refers unknown attachment `{}`",
+                pass, attachment
+            ),
+            GraphError::Cycle(pass) => write!(f, "Pass `{}` depends on itself", pass),
+        }
+    }
+}
+
+/// Render graph
+///
+/// Passes are scheduled so that every pass runs after the passes writing the attachments it
+/// reads. Passes writing the same attachment keep the order they were added in. Intermediate
+/// attachments are created by the renderer and recreated, when the surface is resized.
+///
+/// Compute pipelines run in the pass of their [`super::Options::pass`] in the order of calls,
+/// so a buffer computed and drawn in different passes has to be declared with
+/// [`RenderPass::produces`] and [`RenderPass::consumes`].
+#[derive(Clone, Debug)]
+pub struct RenderGraph {
+    attachments: HashMap<String, Attachment>,
+    passes: Vec<RenderPass>,
+}
+
+impl RenderGraph {
+    /// Adds or replaces the attachment
+    pub fn add_attachment(&mut self, name: &str, attachment: Attachment) {
+        self.attachments.insert(String::from(name), attachment);
+    }
+
+    /// Removes the attachment
+    pub fn remove_attachment(&mut self, name: &str) -> Option<Attachment> {
+        self.attachments.remove(name)
+    }
+
+    /// Returns the attachment by name
+    pub fn attachment(&self, name: &str) -> Option<&Attachment> {
+        self.attachments.get(name)
+    }
+
+    /// Returns iterator over attachments created by the graph
+    pub fn attachments(&self) -> impl Iterator<Item = (&str, &Attachment)> {
+        self.attachments
+            .iter()
+            .map(|(name, attachment)| (name.as_str(), attachment))
+    }
+
+    /// Adds the pass or replaces the pass with the same name
+    pub fn add_pass(&mut self, pass: RenderPass) {
+        match self.passes.iter_mut().find(|p| p.name == pass.name) {
+            Some(existing) => *existing = pass,
+            None => self.passes.push(pass),
+        }
+    }
+
+    /// Adds the pass before another one, so it runs first among the passes writing the same
+    /// attachments
+    ///
+    /// The pass is added to the end, if there is no pass named `before`.
+    pub fn add_pass_before(&mut self, before: &str, pass: RenderPass) {
+        self.remove_pass(&pass.name);
+        match self.passes.iter().position(|p| p.name == before) {
+            Some(index) => self.passes.insert(index, pass),
+            None => self.passes.push(pass),
+        }
+    }
+
+    /// Removes the pass
+    pub fn remove_pass(&mut self, name: &str) -> Option<RenderPass> {
+        let index = self.passes.iter().position(|pass| pass.name == name)?;
+        Some(self.passes.remove(index))
+    }
+
+    /// Returns the pass by name
+    pub fn pass(&self, name: &str) -> Option<&RenderPass> {
+        self.passes.iter().find(|pass| pass.name == name)
+    }
+
+    /// Returns mutable reference to the pass by name
+    pub fn pass_mut(&mut self, name: &str) -> Option<&mut RenderPass> {
+        self.passes.iter_mut().find(|pass| pass.name == name)
+    }
+
+    /// Adds attachments and the pass of the render target
+    ///
+    /// The main pass reads the target, so the target is rendered before it.
+    pub fn add_render_target(&mut self, name: &str, target: RenderTarget) {
+        let depth_attachment = RenderTarget::depth_attachment(name);
+        let mut pass = RenderPass::new(name).writes(name).clear(target.clear_color);
+
+        self.add_attachment(
+            name,
+            Attachment::color(target.format).with_size(target.size),
+        );
+        if target.depth {
+            self.add_attachment(
+                &depth_attachment,
+                Attachment::depth().with_size(target.size),
+            );
+            pass = pass.depth(&depth_attachment);
+        } else {
+            self.remove_attachment(&depth_attachment);
+        }
+        self.add_pass(pass);
+
+        if let Some(main) = self.pass_mut(MAIN_PASS) {
+            if !main.reads.iter().any(|read| read == name) {
+                main.reads.push(String::from(name));
+            }
+        }
+    }
+
+    /// Removes attachments and the pass of the render target
+    pub fn remove_render_target(&mut self, name: &str) {
+        self.remove_pass(name);
+        self.remove_attachment(name);
+        self.remove_attachment(&RenderTarget::depth_attachment(name));
+        for pass in self.passes.iter_mut() {
+            pass.reads.retain(|read| read != name);
+        }
+    }
+
+    /// Returns passes in the order of execution
+    pub fn schedule(&self) -> Result<Vec<&RenderPass>, GraphError> {
+        for pass in self.passes.iter() {
+            let names = pass
+                .reads
+                .iter()
+                .chain(pass.history.iter())
+                .chain(pass.outputs());
+            for name in names {
+                if name != SURFACE && name != DEPTH && !self.attachments.contains_key(name) {
+                    return Err(GraphError::UnknownAttachment {
+                        pass: pass.name.clone(),
+                        attachment: name.clone(),
+                    });
+                }
+            }
+        }
+
+        // dependencies[i] lists passes, that have to run before the pass `i`
+        let count = self.passes.len();
+        let mut dependencies = vec![Vec::new(); count];
+        for (i, pass) in self.passes.iter().enumerate() {
+            for (j, other) in self.passes.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+                let reads_output = pass.reads.iter().any(|name| {
+                    if self.sequential(name) {
+                        self.last_writer(name, i) == Some(j)
+                    } else {
+                        other.outputs().any(|output| output == name)
+                    }
+                });
+                let reads_history = other
+                    .history
+                    .iter()
+                    .any(|name| pass.outputs().any(|output| output == name));
+                let writes_before = j < i
+                    && pass
+                        .outputs()
+                        .filter(|name| !self.sequential(name))
+                        .any(|name| other.outputs().any(|output| output == name))
+                    && !other
+                        .reads
+                        .iter()
+                        .any(|name| pass.outputs().any(|output| output == name));
+                let accesses_before = j < i
+                    && pass
+                        .outputs()
+                        .filter(|name| self.sequential(name))
+                        .any(|name| {
+                            other.reads.contains(name)
+                                || other.outputs().any(|output| output == name)
+                        });
+                let consumes_output = pass
+                    .consumes
+                    .iter()
+                    .any(|name| other.produces.contains(name));
+                if reads_output
+                    || reads_history
+                    || writes_before
+                    || accesses_before
+                    || consumes_output
+                {
+                    dependencies[i].push(j);
+                }
+            }
+        }
+
+        let mut scheduled = vec![false; count];
+        let mut order = Vec::with_capacity(count);
+        while order.len() < count {
+            let next = (0..count).find(|&i| {
+                !scheduled[i]
+                    && dependencies[i]
+                        .iter()
+                        .all(|&dependency| scheduled[dependency])
+            });
+            match next {
+                Some(i) => {
+                    scheduled[i] = true;
+                    order.push(&self.passes[i]);
+                }
+                None => {
+                    let pass = (0..count).find(|&i| !scheduled[i]).unwrap();
+                    return Err(GraphError::Cycle(self.passes[pass].name.clone()));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    fn sequential(&self, name: &str) -> bool {
+        self.attachments
+            .get(name)
+            .map(|attachment| attachment.sequential)
+            .unwrap_or(false)
+    }
+
+    /// Returns index of the closest pass before the `index`, that writes the attachment
+    fn last_writer(&self, name: &str, index: usize) -> Option<usize> {
+        self.passes[..index]
+            .iter()
+            .rposition(|pass| pass.outputs().any(|output| output == name))
+    }
+}
+
+impl Default for RenderGraph {
+    /// Constructs the graph of the main pass rendering to the surface
+    fn default() -> Self {
+        Self {
+            attachments: HashMap::new(),
+            passes: vec![RenderPass::new(MAIN_PASS)
+                .writes(SURFACE)
+                .depth(DEPTH)
+                .clear(Color::from([0.1, 0.2, 0.3, 1.0]))],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(graph: &RenderGraph) -> Vec<String> {
+        graph
+            .schedule()
+            .unwrap()
+            .iter()
+            .map(|pass| pass.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn passes_run_after_their_inputs() {
+        let mut graph = RenderGraph::default();
+        graph.add_attachment("scene", Attachment::color(TextureFormat::rgba_f16()));
+        graph.add_attachment("shadow", Attachment::depth());
+
+        graph.pass_mut(MAIN_PASS).unwrap().writes = vec![String::from("scene")];
+        graph.pass_mut(MAIN_PASS).unwrap().reads = vec![String::from("shadow")];
+        graph.add_pass(RenderPass::new("post").reads("scene").writes(SURFACE));
+        graph.add_pass(RenderPass::new("shadow").depth("shadow"));
+        graph.add_pass(RenderPass::new("overlay").writes(SURFACE));
+
+        assert_eq!(names(&graph), vec!["shadow", "main", "post", "overlay"]);
+    }
+
+    #[test]
+    fn window_attachments_are_scheduled() {
+        let mut graph = RenderGraph::default();
+        graph.add_attachment("editor", Attachment::window());
+        graph.add_pass(
+            RenderPass::new("editor")
+                .writes("editor")
+                .clear(Color::black()),
+        );
+
+        assert!(graph.attachment("editor").unwrap().window);
+        assert_eq!(names(&graph), vec!["main", "editor"]);
+    }
+
+    #[test]
+    fn render_targets_are_rendered_before_main_pass() {
+        let mut graph = RenderGraph::default();
+        graph.add_render_target("minimap", RenderTarget::new(256, 256));
+
+        assert_eq!(names(&graph), vec!["minimap", "main"]);
+        assert!(graph.attachment("minimap.depth").is_some());
+
+        graph.remove_render_target("minimap");
+        assert_eq!(names(&graph), vec!["main"]);
+        assert!(graph.attachment("minimap").is_none());
+    }
+
+    #[test]
+    fn passes_can_be_added_before_others() {
+        let mut graph = RenderGraph::default();
+        graph.add_attachment("hdr", Attachment::color(TextureFormat::rgba_f16()));
+        graph.pass_mut(MAIN_PASS).unwrap().writes = vec![String::from("hdr")];
+        graph.add_pass(RenderPass::new("overlay").writes(SURFACE));
+        graph.add_pass_before(
+            "overlay",
+            RenderPass::new("tone_mapping").reads("hdr").writes(SURFACE),
+        );
+
+        assert_eq!(names(&graph), vec!["main", "tone_mapping", "overlay"]);
+    }
+
+    #[test]
+    fn history_is_read_before_it_is_written() {
+        let mut graph = RenderGraph::default();
+        graph.add_attachment("scene", Attachment::color(TextureFormat::rgba_f16()));
+        graph.add_attachment("resolved", Attachment::color(TextureFormat::rgba_f16()));
+        graph.add_attachment("history", Attachment::color(TextureFormat::rgba_f16()));
+        graph.pass_mut(MAIN_PASS).unwrap().writes = vec![String::from("scene")];
+        graph.add_pass(RenderPass::new("copy").reads("resolved").writes("history"));
+        graph.add_pass(
+            RenderPass::new("resolve")
+                .reads("scene")
+                .reads_history("history")
+                .writes("resolved"),
+        );
+
+        assert_eq!(names(&graph), vec!["main", "resolve", "copy"]);
+    }
+
+    #[test]
+    fn sequential_attachments_can_be_reused() {
+        let mut graph = RenderGraph::default();
+        let ping_pong = Attachment::color(TextureFormat::rgba_f16()).sequential();
+        graph.add_attachment("a", ping_pong);
+        graph.add_attachment("b", ping_pong);
+        graph.pass_mut(MAIN_PASS).unwrap().writes = vec![String::from("a")];
+        graph.add_pass(RenderPass::new("overlay").writes(SURFACE));
+        graph.add_pass_before("overlay", RenderPass::new("blur").reads("a").writes("b"));
+        graph.add_pass_before("overlay", RenderPass::new("sharpen").reads("b").writes("a"));
+        graph.add_pass_before(
+            "overlay",
+            RenderPass::new("grain").reads("a").writes(SURFACE),
+        );
+
+        assert_eq!(
+            names(&graph),
+            vec!["main", "blur", "sharpen", "grain", "overlay"]
+        );
+    }
+
+    #[test]
+    fn unknown_attachments_and_cycles_are_reported() {
+        let mut graph = RenderGraph::default();
+        graph.add_pass(RenderPass::new("blur").reads("bloom").writes(SURFACE));
+        assert_eq!(
+            graph.schedule().unwrap_err(),
+            GraphError::UnknownAttachment {
+                pass: String::from("blur"),
+                attachment: String::from("bloom"),
+            }
+        );
+
+        graph.add_attachment("a", Attachment::color(TextureFormat::rgba_u8norm()));
+        graph.add_attachment("b", Attachment::color(TextureFormat::rgba_u8norm()));
+        graph.add_pass(RenderPass::new("blur").reads("a").writes("b"));
+        graph.add_pass(RenderPass::new("sharpen").reads("b").writes("a"));
+        assert!(matches!(graph.schedule(), Err(GraphError::Cycle(_))));
+    }
+
+    #[test]
+    fn consumers_run_after_producers_of_buffers() {
+        let mut graph = RenderGraph::default();
+        graph.pass_mut(MAIN_PASS).unwrap().consumes = vec![String::from("particles")];
+        graph.add_pass(RenderPass::new("prepass").consumes("vertices"));
+        graph.add_pass(RenderPass::new("simulation").produces("particles"));
+        graph.add_pass(RenderPass::new("generator").produces("vertices"));
+
+        assert_eq!(
+            names(&graph),
+            vec!["simulation", "main", "generator", "prepass"]
+        );
+    }
+
+    #[test]
+    fn split_screen_passes_keep_their_viewports() {
+        let left = Viewport::new(0.0, 0.0, 400.0, 600.0);
+        let right = Viewport { x: 400.0, ..left };
+        assert_eq!((left.min_depth, left.max_depth), (0.0, 1.0));
+
+        let mut graph = RenderGraph::default();
+        graph.add_pass(RenderPass::new("left").writes(SURFACE).viewport(left));
+        graph.add_pass(RenderPass::new("right").writes(SURFACE).viewport(right));
+
+        let viewports = graph
+            .schedule()
+            .unwrap()
+            .iter()
+            .map(|pass| pass.viewport)
+            .collect::<Vec<_>>();
+        assert_eq!(viewports, vec![None, Some(left), Some(right)]);
+    }
+}
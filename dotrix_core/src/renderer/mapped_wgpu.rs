@@ -130,6 +130,497 @@ impl From<StorageTextureAccess> for wgpu::StorageTextureAccess {
     }
 }
 
+/// Presentation of the rendered frames to the window
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum PresentMode {
+    /// Frames are shown right away without the vertical synchronization, tearing is possible
+    Immediate,
+    /// Frames are synchronized vertically, the newest frame replaces the waiting one, so
+    /// rendering is not blocked
+    #[default]
+    Mailbox,
+    /// Frames are synchronized vertically and queued, rendering waits for the display (VSync)
+    Fifo,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+        }
+    }
+}
+
+/// Rasterization of the pipeline triangles
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum PolygonMode {
+    /// Triangles are filled
+    #[default]
+    Fill,
+    /// Only the edges of the triangles are drawn, to inspect the mesh topology
+    Line,
+    /// Only the vertices of the triangles are drawn
+    Point,
+}
+
+impl PolygonMode {
+    /// Returns the device features required by the mode
+    pub(crate) fn features(self) -> wgpu::Features {
+        match self {
+            PolygonMode::Fill => wgpu::Features::empty(),
+            PolygonMode::Line => wgpu::Features::POLYGON_MODE_LINE,
+            PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
+        }
+    }
+
+    /// Returns the mode rasterized by the device, unsupported modes fill the triangles
+    pub(crate) fn rasterized(self, features: wgpu::Features) -> wgpu::PolygonMode {
+        if features.contains(self.features()) {
+            self.into()
+        } else {
+            wgpu::PolygonMode::Fill
+        }
+    }
+}
+
+impl From<PolygonMode> for wgpu::PolygonMode {
+    fn from(mode: PolygonMode) -> Self {
+        match mode {
+            PolygonMode::Fill => wgpu::PolygonMode::Fill,
+            PolygonMode::Line => wgpu::PolygonMode::Line,
+            PolygonMode::Point => wgpu::PolygonMode::Point,
+        }
+    }
+}
+
+/// Assembly of the vertices into primitives
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum PrimitiveTopology {
+    /// Every three vertices form a triangle
+    #[default]
+    TriangleList,
+    /// Every vertex forms a triangle with the two previous ones
+    TriangleStrip,
+    /// Every two vertices form a line
+    LineList,
+    /// Every vertex forms a line with the previous one
+    LineStrip,
+    /// Every vertex is a point of a single pixel
+    PointList,
+}
+
+impl PrimitiveTopology {
+    /// Returns number of the triangles assembled from the vertices
+    pub(crate) fn triangles(self, vertices: u32) -> u32 {
+        match self {
+            PrimitiveTopology::TriangleList => vertices / 3,
+            PrimitiveTopology::TriangleStrip => vertices.saturating_sub(2),
+            _ => 0,
+        }
+    }
+
+    /// Returns true if the vertices form a strip
+    pub(crate) fn is_strip(self) -> bool {
+        matches!(
+            self,
+            PrimitiveTopology::TriangleStrip | PrimitiveTopology::LineStrip
+        )
+    }
+}
+
+impl From<PrimitiveTopology> for wgpu::PrimitiveTopology {
+    fn from(topology: PrimitiveTopology) -> Self {
+        match topology {
+            PrimitiveTopology::TriangleList => wgpu::PrimitiveTopology::TriangleList,
+            PrimitiveTopology::TriangleStrip => wgpu::PrimitiveTopology::TriangleStrip,
+            PrimitiveTopology::LineList => wgpu::PrimitiveTopology::LineList,
+            PrimitiveTopology::LineStrip => wgpu::PrimitiveTopology::LineStrip,
+            PrimitiveTopology::PointList => wgpu::PrimitiveTopology::PointList,
+        }
+    }
+}
+
+/// Rate of the vertex buffer attributes advancing
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum VertexStepMode {
+    /// Attributes advance with every vertex
+    #[default]
+    Vertex,
+    /// Attributes advance with every instance
+    Instance,
+}
+
+impl From<VertexStepMode> for wgpu::VertexStepMode {
+    fn from(mode: VertexStepMode) -> Self {
+        match mode {
+            VertexStepMode::Vertex => wgpu::VertexStepMode::Vertex,
+            VertexStepMode::Instance => wgpu::VertexStepMode::Instance,
+        }
+    }
+}
+
+/// Addressing of the texture coordinates outside of the 0..1 range by the sampler
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum AddressMode {
+    /// Coordinates are clamped to the edge pixels
+    #[default]
+    ClampToEdge,
+    /// Texture repeats
+    Repeat,
+    /// Texture repeats mirrored on every other repetition
+    MirrorRepeat,
+}
+
+impl From<AddressMode> for wgpu::AddressMode {
+    fn from(mode: AddressMode) -> Self {
+        match mode {
+            AddressMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+            AddressMode::Repeat => wgpu::AddressMode::Repeat,
+            AddressMode::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+/// Filtering of the texels or mip levels by the sampler
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum FilterMode {
+    /// The nearest texel or level is sampled, keeping the pixel art sharp
+    #[default]
+    Nearest,
+    /// The nearest texels or levels are interpolated
+    Linear,
+}
+
+impl From<FilterMode> for wgpu::FilterMode {
+    fn from(mode: FilterMode) -> Self {
+        match mode {
+            FilterMode::Nearest => wgpu::FilterMode::Nearest,
+            FilterMode::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+/// Comparison of the sampled value with the reference one by the comparison sampler or of
+/// the reference value with the stencil buffer
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum CompareFunction {
+    /// Never passes
+    Never,
+    /// Passes, if the reference is less than the sampled value
+    Less,
+    /// Passes, if the reference is equal to the sampled value
+    Equal,
+    /// Passes, if the reference is less than or equal to the sampled value
+    LessEqual,
+    /// Passes, if the reference is greater than the sampled value
+    Greater,
+    /// Passes, if the reference is not equal to the sampled value
+    NotEqual,
+    /// Passes, if the reference is greater than or equal to the sampled value
+    GreaterEqual,
+    /// Always passes
+    Always,
+}
+
+impl From<CompareFunction> for wgpu::CompareFunction {
+    fn from(function: CompareFunction) -> Self {
+        match function {
+            CompareFunction::Never => wgpu::CompareFunction::Never,
+            CompareFunction::Less => wgpu::CompareFunction::Less,
+            CompareFunction::Equal => wgpu::CompareFunction::Equal,
+            CompareFunction::LessEqual => wgpu::CompareFunction::LessEqual,
+            CompareFunction::Greater => wgpu::CompareFunction::Greater,
+            CompareFunction::NotEqual => wgpu::CompareFunction::NotEqual,
+            CompareFunction::GreaterEqual => wgpu::CompareFunction::GreaterEqual,
+            CompareFunction::Always => wgpu::CompareFunction::Always,
+        }
+    }
+}
+
+/// Operation on the stencil buffer value
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum StencilOperation {
+    /// Keeps the value
+    Keep,
+    /// Sets the value to 0
+    Zero,
+    /// Replaces the value with the reference one
+    Replace,
+    /// Inverts the bits of the value
+    Invert,
+    /// Increments the value, clamping to the maximum
+    IncrementClamp,
+    /// Decrements the value, clamping to 0
+    DecrementClamp,
+    /// Increments the value, wrapping to 0
+    IncrementWrap,
+    /// Decrements the value, wrapping to the maximum
+    DecrementWrap,
+}
+
+impl From<StencilOperation> for wgpu::StencilOperation {
+    fn from(operation: StencilOperation) -> Self {
+        match operation {
+            StencilOperation::Keep => wgpu::StencilOperation::Keep,
+            StencilOperation::Zero => wgpu::StencilOperation::Zero,
+            StencilOperation::Replace => wgpu::StencilOperation::Replace,
+            StencilOperation::Invert => wgpu::StencilOperation::Invert,
+            StencilOperation::IncrementClamp => wgpu::StencilOperation::IncrementClamp,
+            StencilOperation::DecrementClamp => wgpu::StencilOperation::DecrementClamp,
+            StencilOperation::IncrementWrap => wgpu::StencilOperation::IncrementWrap,
+            StencilOperation::DecrementWrap => wgpu::StencilOperation::DecrementWrap,
+        }
+    }
+}
+
+/// Stencil test of the pipeline fragments
+///
+/// The reference value is set per draw, see [`crate::renderer::Options::stencil_reference`].
+/// Both faces of the triangles are tested the same way.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct StencilState {
+    /// Comparison of the reference value with the stencil buffer, fragments failing it are
+    /// discarded
+    pub compare: CompareFunction,
+    /// Operation on the value, if the stencil test fails
+    pub fail_op: StencilOperation,
+    /// Operation on the value, if the stencil test passes but the depth test fails
+    pub depth_fail_op: StencilOperation,
+    /// Operation on the value, if both tests pass
+    pub pass_op: StencilOperation,
+    /// Bits of the value and the reference to compare
+    pub read_mask: u32,
+    /// Bits of the value to write
+    pub write_mask: u32,
+}
+
+impl StencilState {
+    /// Writes the reference value under the rendered fragments, e.g. to mask a portal or an
+    /// outlined model
+    pub fn write() -> Self {
+        Self {
+            compare: CompareFunction::Always,
+            fail_op: StencilOperation::Keep,
+            depth_fail_op: StencilOperation::Keep,
+            pass_op: StencilOperation::Replace,
+            read_mask: !0,
+            write_mask: !0,
+        }
+    }
+
+    /// Renders only the fragments passing the comparison with the stored value, keeping it
+    pub fn test(compare: CompareFunction) -> Self {
+        Self {
+            compare,
+            fail_op: StencilOperation::Keep,
+            depth_fail_op: StencilOperation::Keep,
+            pass_op: StencilOperation::Keep,
+            read_mask: !0,
+            write_mask: 0,
+        }
+    }
+}
+
+impl From<StencilState> for wgpu::StencilState {
+    fn from(state: StencilState) -> Self {
+        let face = wgpu::StencilFaceState {
+            compare: state.compare.into(),
+            fail_op: state.fail_op.into(),
+            depth_fail_op: state.depth_fail_op.into(),
+            pass_op: state.pass_op.into(),
+        };
+        Self {
+            front: face,
+            back: face,
+            read_mask: state.read_mask,
+            write_mask: state.write_mask,
+        }
+    }
+}
+
+/// Factor of the source or destination color in the blend equation
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BlendFactor {
+    /// 0.0
+    Zero,
+    /// 1.0
+    One,
+    /// Source color
+    Src,
+    /// 1.0 - source color
+    OneMinusSrc,
+    /// Source alpha
+    SrcAlpha,
+    /// 1.0 - source alpha
+    OneMinusSrcAlpha,
+    /// Destination color
+    Dst,
+    /// 1.0 - destination color
+    OneMinusDst,
+    /// Destination alpha
+    DstAlpha,
+    /// 1.0 - destination alpha
+    OneMinusDstAlpha,
+    /// min(source alpha, 1.0 - destination alpha)
+    SrcAlphaSaturated,
+}
+
+impl From<BlendFactor> for wgpu::BlendFactor {
+    fn from(factor: BlendFactor) -> Self {
+        match factor {
+            BlendFactor::Zero => wgpu::BlendFactor::Zero,
+            BlendFactor::One => wgpu::BlendFactor::One,
+            BlendFactor::Src => wgpu::BlendFactor::Src,
+            BlendFactor::OneMinusSrc => wgpu::BlendFactor::OneMinusSrc,
+            BlendFactor::SrcAlpha => wgpu::BlendFactor::SrcAlpha,
+            BlendFactor::OneMinusSrcAlpha => wgpu::BlendFactor::OneMinusSrcAlpha,
+            BlendFactor::Dst => wgpu::BlendFactor::Dst,
+            BlendFactor::OneMinusDst => wgpu::BlendFactor::OneMinusDst,
+            BlendFactor::DstAlpha => wgpu::BlendFactor::DstAlpha,
+            BlendFactor::OneMinusDstAlpha => wgpu::BlendFactor::OneMinusDstAlpha,
+            BlendFactor::SrcAlphaSaturated => wgpu::BlendFactor::SrcAlphaSaturated,
+        }
+    }
+}
+
+/// Operation combining the source and destination colors multiplied by their factors
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BlendOperation {
+    /// Source + destination
+    Add,
+    /// Source - destination
+    Subtract,
+    /// Destination - source
+    ReverseSubtract,
+    /// min(source, destination), factors are ignored
+    Min,
+    /// max(source, destination), factors are ignored
+    Max,
+}
+
+impl From<BlendOperation> for wgpu::BlendOperation {
+    fn from(operation: BlendOperation) -> Self {
+        match operation {
+            BlendOperation::Add => wgpu::BlendOperation::Add,
+            BlendOperation::Subtract => wgpu::BlendOperation::Subtract,
+            BlendOperation::ReverseSubtract => wgpu::BlendOperation::ReverseSubtract,
+            BlendOperation::Min => wgpu::BlendOperation::Min,
+            BlendOperation::Max => wgpu::BlendOperation::Max,
+        }
+    }
+}
+
+/// Blend equation of the color or alpha channel
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BlendComponent {
+    /// Factor of the fragment color
+    pub src_factor: BlendFactor,
+    /// Factor of the color in the target
+    pub dst_factor: BlendFactor,
+    /// Operation combining them
+    pub operation: BlendOperation,
+}
+
+impl BlendComponent {
+    /// Creates the component adding the factored colors
+    pub fn add(src_factor: BlendFactor, dst_factor: BlendFactor) -> Self {
+        Self {
+            src_factor,
+            dst_factor,
+            operation: BlendOperation::Add,
+        }
+    }
+}
+
+impl From<BlendComponent> for wgpu::BlendComponent {
+    fn from(component: BlendComponent) -> Self {
+        Self {
+            src_factor: component.src_factor.into(),
+            dst_factor: component.dst_factor.into(),
+            operation: component.operation.into(),
+        }
+    }
+}
+
+/// Blending of the fragments with the color target
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BlendMode {
+    /// Fragments replace the target
+    Replace,
+    /// Fragments are mixed with the target by their alpha, used for glass and UI
+    Alpha,
+    /// Fragments with the colors premultiplied by alpha are added to the target scaled by
+    /// 1.0 - alpha
+    Premultiplied,
+    /// Fragments scaled by alpha are added to the target, used for fire, sparks and glow
+    Additive,
+    /// Custom equations of the color and alpha channels
+    Custom {
+        /// Color channels equation
+        color: BlendComponent,
+        /// Alpha channel equation
+        alpha: BlendComponent,
+    },
+}
+
+impl From<BlendMode> for wgpu::BlendState {
+    fn from(mode: BlendMode) -> Self {
+        use BlendFactor::*;
+        let (color, alpha) = match mode {
+            BlendMode::Replace => (
+                BlendComponent::add(One, Zero),
+                BlendComponent::add(One, Zero),
+            ),
+            BlendMode::Alpha => (
+                BlendComponent::add(SrcAlpha, OneMinusSrcAlpha),
+                BlendComponent::add(One, OneMinusSrcAlpha),
+            ),
+            BlendMode::Premultiplied => (
+                BlendComponent::add(One, OneMinusSrcAlpha),
+                BlendComponent::add(One, OneMinusSrcAlpha),
+            ),
+            BlendMode::Additive => (
+                BlendComponent::add(SrcAlpha, One),
+                BlendComponent::add(Zero, One),
+            ),
+            BlendMode::Custom { color, alpha } => (color, alpha),
+        };
+        Self {
+            color: color.into(),
+            alpha: alpha.into(),
+        }
+    }
+}
+
+/// Family of the block compressed texture formats, supported by the adapter
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TextureCompression {
+    /// BC1-7 formats of the desktop GPUs
+    Bc,
+    /// ETC2 and EAC formats of the mobile GPUs
+    Etc2,
+    /// ASTC formats of the mobile GPUs
+    Astc,
+}
+
+impl TextureCompression {
+    pub(crate) fn features(self) -> wgpu::Features {
+        match self {
+            TextureCompression::Bc => wgpu::Features::TEXTURE_COMPRESSION_BC,
+            TextureCompression::Etc2 => wgpu::Features::TEXTURE_COMPRESSION_ETC2,
+            TextureCompression::Astc => wgpu::Features::TEXTURE_COMPRESSION_ASTC_LDR,
+        }
+    }
+
+    pub(crate) fn all_features() -> wgpu::Features {
+        wgpu::Features::TEXTURE_COMPRESSION_BC
+            | wgpu::Features::TEXTURE_COMPRESSION_ETC2
+            | wgpu::Features::TEXTURE_COMPRESSION_ASTC_LDR
+    }
+}
+
 /// The texture format used while on the gpu
 #[derive(Copy, Clone, Debug)]
 pub struct TextureFormat {
@@ -369,6 +860,181 @@ impl TextureFormat {
         }
     }
 
+    /// BC1 compressed red, green, blue and alpha channel in sRGB space, 8 bytes per 4x4 block
+    pub fn bc1_rgba_u8norm_srgb() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Bc1RgbaUnormSrgb,
+        }
+    }
+    /// BC3 compressed red, green, blue and alpha channel in sRGB space, 16 bytes per 4x4 block
+    pub fn bc3_rgba_u8norm_srgb() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Bc3RgbaUnormSrgb,
+        }
+    }
+    /// BC4 compressed red channel, 8 bytes per 4x4 block
+    pub fn bc4_r_u8norm() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Bc4RUnorm,
+        }
+    }
+    /// BC5 compressed red and green channel, like the normal maps, 16 bytes per 4x4 block
+    pub fn bc5_rg_u8norm() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Bc5RgUnorm,
+        }
+    }
+    /// BC6H compressed red, green and blue channel unsigned float, 16 bytes per 4x4 block
+    pub fn bc6h_rgb_uf16() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Bc6hRgbUfloat,
+        }
+    }
+    /// BC7 compressed red, green, blue and alpha channel, 16 bytes per 4x4 block
+    pub fn bc7_rgba_u8norm() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Bc7RgbaUnorm,
+        }
+    }
+    /// BC7 compressed red, green, blue and alpha channel in sRGB space, 16 bytes per 4x4 block
+    pub fn bc7_rgba_u8norm_srgb() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Bc7RgbaUnormSrgb,
+        }
+    }
+
+    /// ETC2 compressed red, green and blue channel in sRGB space, 8 bytes per 4x4 block
+    pub fn etc2_rgb_u8norm_srgb() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Etc2Rgb8UnormSrgb,
+        }
+    }
+    /// ETC2 compressed red, green, blue and alpha channel, 16 bytes per 4x4 block
+    pub fn etc2_rgba_u8norm() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Etc2Rgba8Unorm,
+        }
+    }
+    /// ETC2 compressed red, green, blue and alpha channel in sRGB space, 16 bytes per 4x4 block
+    pub fn etc2_rgba_u8norm_srgb() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Etc2Rgba8UnormSrgb,
+        }
+    }
+    /// EAC compressed red and green channel, 16 bytes per 4x4 block
+    pub fn eac_rg_u11norm() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::EacRg11Unorm,
+        }
+    }
+
+    /// ASTC compressed red, green, blue and alpha channel, 16 bytes per 4x4 block
+    pub fn astc_4x4_rgba_u8norm() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Astc4x4RgbaUnorm,
+        }
+    }
+    /// ASTC compressed red, green, blue and alpha channel in sRGB space, 16 bytes per 4x4 block
+    pub fn astc_4x4_rgba_u8norm_srgb() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Astc4x4RgbaUnormSrgb,
+        }
+    }
+
+    /// Depth 32 bit float
+    pub fn depth_f32() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Depth32Float,
+        }
+    }
+
+    /// Depth 24 bit and stencil 8 bit
+    pub fn depth24_stencil8() -> Self {
+        Self {
+            wgpu_texture_format: WgpuTextureFormat::Depth24PlusStencil8,
+        }
+    }
+
+    /// Returns true if the format has the stencil aspect
+    pub fn has_stencil(&self) -> bool {
+        self.wgpu_texture_format == WgpuTextureFormat::Depth24PlusStencil8
+    }
+
+    /// Returns true if the format is a depth or stencil format
+    pub fn is_depth(&self) -> bool {
+        self.wgpu_texture_format.describe().sample_type == wgpu::TextureSampleType::Depth
+    }
+
+    /// Returns true if the colors are encoded to sRGB on write and decoded on read by GPU
+    pub fn is_srgb(&self) -> bool {
+        self.wgpu_texture_format.describe().srgb
+    }
+
+    /// Returns the sRGB or the linear variant of the 8 bit color format, other formats are
+    /// returned as they are
+    #[must_use]
+    pub fn with_srgb(self, srgb: bool) -> Self {
+        use WgpuTextureFormat::*;
+        let wgpu_texture_format = match (self.wgpu_texture_format, srgb) {
+            (Rgba8Unorm, true) => Rgba8UnormSrgb,
+            (Rgba8UnormSrgb, false) => Rgba8Unorm,
+            (Bgra8Unorm, true) => Bgra8UnormSrgb,
+            (Bgra8UnormSrgb, false) => Bgra8Unorm,
+            (format, _) => format,
+        };
+        Self {
+            wgpu_texture_format,
+        }
+    }
+
+    /// Returns true if the format is block compressed
+    pub fn is_compressed(&self) -> bool {
+        self.compression().is_some()
+    }
+
+    /// Returns the family of the block compressed format
+    pub fn compression(&self) -> Option<TextureCompression> {
+        let features = self.wgpu_texture_format.describe().required_features;
+        [
+            TextureCompression::Bc,
+            TextureCompression::Etc2,
+            TextureCompression::Astc,
+        ]
+        .iter()
+        .copied()
+        .find(|compression| features.contains(compression.features()))
+    }
+
+    /// Returns number of bytes in a row of blocks and number of block rows of the image
+    ///
+    /// Blocks of the uncompressed formats are single pixels, compressed images are padded to
+    /// the whole blocks.
+    pub fn layout(&self, width: u32, height: u32) -> (u32, u32) {
+        let info = self.wgpu_texture_format.describe();
+        let (block_width, block_height) = info.block_dimensions;
+        let columns = (width as f32 / block_width as f32).ceil() as u32;
+        let rows = (height as f32 / block_height as f32).ceil() as u32;
+        (columns * info.block_size as u32, rows)
+    }
+
+    /// Returns number of bytes of the image of the format
+    pub fn data_size(&self, width: u32, height: u32) -> usize {
+        let (bytes_per_row, rows) = self.layout(width, height);
+        bytes_per_row as usize * rows as usize
+    }
+
+    /// Returns true if the mip levels of the format can be generated by the linear filtering
+    pub(crate) fn is_mipmappable(&self) -> bool {
+        let features = self
+            .wgpu_texture_format
+            .describe()
+            .guaranteed_format_features;
+        features.filterable
+            && features
+                .allowed_usages
+                .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    }
+
     pub(crate) fn is_filterable(&self) -> bool {
         self.wgpu_texture_format
             .describe()
@@ -382,3 +1048,79 @@ impl From<TextureFormat> for WgpuTextureFormat {
         orig.wgpu_texture_format
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_rows_are_padded_to_blocks() {
+        let bc1 = TextureFormat::bc1_rgba_u8norm_srgb();
+        assert_eq!(bc1.compression(), Some(TextureCompression::Bc));
+        assert_eq!(bc1.layout(256, 128), (64 * 8, 32));
+        assert_eq!(bc1.layout(6, 6), (16, 2));
+        assert_eq!(TextureFormat::bc7_rgba_u8norm().data_size(8, 4), 32);
+        assert_eq!(
+            TextureFormat::astc_4x4_rgba_u8norm_srgb().compression(),
+            Some(TextureCompression::Astc)
+        );
+    }
+
+    #[test]
+    fn srgb_variants_are_switched() {
+        let srgb = TextureFormat::rgba_u8norm_srgb();
+        assert!(srgb.is_srgb());
+        assert_eq!(
+            srgb.with_srgb(false).wgpu_texture_format,
+            WgpuTextureFormat::Rgba8Unorm
+        );
+        assert!(TextureFormat::rgba_u8norm().with_srgb(true).is_srgb());
+        assert!(srgb.with_srgb(true).is_srgb());
+        assert_eq!(
+            TextureFormat::rgba_f16()
+                .with_srgb(true)
+                .wgpu_texture_format,
+            WgpuTextureFormat::Rgba16Float
+        );
+    }
+
+    #[test]
+    fn uncompressed_rows_are_pixels() {
+        let rgba = TextureFormat::rgba_u8norm_srgb();
+        assert!(!rgba.is_compressed());
+        assert_eq!(rgba.layout(3, 2), (12, 2));
+        assert_eq!(TextureFormat::rgba_f32().data_size(2, 2), 64);
+    }
+
+    #[test]
+    fn only_triangle_topologies_count_triangles() {
+        assert_eq!(PrimitiveTopology::TriangleList.triangles(9), 3);
+        assert_eq!(PrimitiveTopology::TriangleStrip.triangles(5), 3);
+        assert_eq!(PrimitiveTopology::TriangleStrip.triangles(1), 0);
+        assert_eq!(PrimitiveTopology::LineList.triangles(9), 0);
+        assert_eq!(PrimitiveTopology::PointList.triangles(9), 0);
+        assert!(PrimitiveTopology::LineStrip.is_strip());
+        assert!(!PrimitiveTopology::LineList.is_strip());
+    }
+
+    #[test]
+    fn unsupported_polygon_modes_fill_triangles() {
+        let features = wgpu::Features::POLYGON_MODE_LINE;
+        assert_eq!(
+            PolygonMode::Fill.rasterized(wgpu::Features::empty()),
+            wgpu::PolygonMode::Fill
+        );
+        assert_eq!(
+            PolygonMode::Line.rasterized(features),
+            wgpu::PolygonMode::Line
+        );
+        assert_eq!(
+            PolygonMode::Point.rasterized(features),
+            wgpu::PolygonMode::Fill
+        );
+        assert_eq!(
+            PolygonMode::Point.rasterized(wgpu::Features::POLYGON_MODE_POINT),
+            wgpu::PolygonMode::Point
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! Reflection of the shader bindings, validating bind groups before pipelines are created
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+/// Resource of the binding
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Resource {
+    Uniform,
+    Storage {
+        read_only: bool,
+    },
+    Texture {
+        dimension: wgpu::TextureViewDimension,
+        depth: bool,
+        multisampled: bool,
+    },
+    StorageTexture {
+        dimension: wgpu::TextureViewDimension,
+        access: wgpu::StorageTextureAccess,
+    },
+    Sampler {
+        comparison: bool,
+    },
+}
+
+impl Resource {
+    fn from_entry(ty: &wgpu::BindingType) -> Self {
+        match *ty {
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                ..
+            } => Resource::Uniform,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                ..
+            } => Resource::Storage { read_only },
+            wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension,
+                multisampled,
+            } => Resource::Texture {
+                dimension: view_dimension,
+                depth: sample_type == wgpu::TextureSampleType::Depth,
+                multisampled,
+            },
+            wgpu::BindingType::StorageTexture {
+                access,
+                view_dimension,
+                ..
+            } => Resource::StorageTexture {
+                dimension: view_dimension,
+                access,
+            },
+            wgpu::BindingType::Sampler(ty) => Resource::Sampler {
+                comparison: ty == wgpu::SamplerBindingType::Comparison,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Resource::Uniform => write!(f, "uniform buffer"),
+            Resource::Storage { read_only: true } => write!(f, "read only storage buffer"),
+            Resource::Storage { read_only: false } => write!(f, "read write storage buffer"),
+            Resource::Texture {
+                dimension,
+                depth,
+                multisampled,
+            } => write!(
+                f,
+                "{}{}{:?} texture",
+                if *multisampled { "multisampled " } else { "" },
+                if *depth { "depth " } else { "" },
+                dimension
+            ),
+            Resource::StorageTexture { dimension, access } => {
+                write!(f, "{:?} {:?} storage texture", access, dimension)
+            }
+            Resource::Sampler { comparison: true } => write!(f, "comparison sampler"),
+            Resource::Sampler { comparison: false } => write!(f, "sampler"),
+        }
+    }
+}
+
+/// Binding of the shader variable
+#[derive(Debug)]
+struct ShaderBinding {
+    group: u32,
+    binding: u32,
+    variable: String,
+    resource: Resource,
+    /// Names of the entry points using the variable
+    entry_points: Vec<String>,
+}
+
+/// Bindings of the shader module
+#[derive(Debug, Default)]
+pub(crate) struct Reflection {
+    bindings: Vec<ShaderBinding>,
+}
+
+impl Reflection {
+    /// Parses the WGSL code, returns `None` if it is invalid
+    pub(crate) fn new(code: &str) -> Option<Self> {
+        let module = naga::front::wgsl::parse_str(code).ok()?;
+        let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .ok()?;
+
+        let bindings = module
+            .global_variables
+            .iter()
+            .filter_map(|(handle, variable)| {
+                let binding = variable.binding.as_ref()?;
+                let resource = match (variable.class, &module.types[variable.ty].inner) {
+                    (naga::StorageClass::Uniform, _) => Resource::Uniform,
+                    (naga::StorageClass::Storage { access }, _) => Resource::Storage {
+                        read_only: !access.contains(naga::StorageAccess::STORE),
+                    },
+                    (
+                        _,
+                        naga::TypeInner::Image {
+                            dim,
+                            arrayed,
+                            class,
+                        },
+                    ) => {
+                        let dimension = match (dim, arrayed) {
+                            (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+                            (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                            (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                            (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+                            (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                            (naga::ImageDimension::Cube, true) => {
+                                wgpu::TextureViewDimension::CubeArray
+                            }
+                        };
+                        match *class {
+                            naga::ImageClass::Sampled { multi, .. } => Resource::Texture {
+                                dimension,
+                                depth: false,
+                                multisampled: multi,
+                            },
+                            naga::ImageClass::Depth { multi } => Resource::Texture {
+                                dimension,
+                                depth: true,
+                                multisampled: multi,
+                            },
+                            naga::ImageClass::Storage { access, .. } => Resource::StorageTexture {
+                                dimension,
+                                access: match (
+                                    access.contains(naga::StorageAccess::LOAD),
+                                    access.contains(naga::StorageAccess::STORE),
+                                ) {
+                                    (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+                                    (false, _) => wgpu::StorageTextureAccess::WriteOnly,
+                                    (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+                                },
+                            },
+                        }
+                    }
+                    (_, naga::TypeInner::Sampler { comparison }) => Resource::Sampler {
+                        comparison: *comparison,
+                    },
+                    _ => return None,
+                };
+                let entry_points = module
+                    .entry_points
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| !info.get_entry_point(*index)[handle].is_empty())
+                    .map(|(_, entry_point)| entry_point.name.clone())
+                    .collect();
+                Some(ShaderBinding {
+                    group: binding.group,
+                    binding: binding.binding,
+                    variable: variable.name.clone().unwrap_or_default(),
+                    resource,
+                    entry_points,
+                })
+            })
+            .collect();
+
+        Some(Self { bindings })
+    }
+
+    /// Checks, that the bind groups provide the variables used by the entry points
+    ///
+    /// Entries of the `bind_groups` are labeled by the names of their bindings.
+    pub(crate) fn validate(
+        &self,
+        shader: &str,
+        entry_points: &[(&str, wgpu::ShaderStages)],
+        bind_groups: &[Vec<(&str, wgpu::BindGroupLayoutEntry)>],
+    ) -> Result<(), BindingError> {
+        for shader_binding in self.bindings.iter() {
+            let stages = entry_points
+                .iter()
+                .filter(|(name, _)| shader_binding.entry_points.iter().any(|used| used == name))
+                .map(|(_, stage)| *stage)
+                .collect::<Vec<_>>();
+            if stages.is_empty() {
+                continue;
+            }
+
+            let (label, entry) = bind_groups
+                .get(shader_binding.group as usize)
+                .and_then(|entries| {
+                    entries
+                        .iter()
+                        .find(|(_, entry)| entry.binding == shader_binding.binding)
+                })
+                .ok_or_else(|| BindingError::Missing {
+                    shader: String::from(shader),
+                    variable: shader_binding.variable.clone(),
+                    group: shader_binding.group,
+                    binding: shader_binding.binding,
+                })?;
+
+            let resource = Resource::from_entry(&entry.ty);
+            if resource != shader_binding.resource {
+                return Err(BindingError::Mismatch {
+                    shader: String::from(shader),
+                    variable: shader_binding.variable.clone(),
+                    binding: String::from(*label),
+                    expected: shader_binding.resource.to_string(),
+                    found: resource.to_string(),
+                });
+            }
+
+            if let Some(stage) = stages
+                .into_iter()
+                .find(|stage| !entry.visibility.contains(*stage))
+            {
+                return Err(BindingError::Invisible {
+                    shader: String::from(shader),
+                    variable: shader_binding.variable.clone(),
+                    binding: String::from(*label),
+                    stage: format!("{:?}", stage),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Mismatch of the pipeline bind groups and the shader, see [`super::Renderer::try_bind`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum BindingError {
+    /// Shader variable is not bound by the bind groups
+    Missing {
+        /// Name of the shader
+        shader: String,
+        /// Name of the shader variable
+        variable: String,
+        /// Index of the bind group of the variable
+        group: u32,
+        /// Index of the variable binding in the group
+        binding: u32,
+    },
+    /// Resource of the binding does not match the shader variable
+    Mismatch {
+        /// Name of the shader
+        shader: String,
+        /// Name of the shader variable
+        variable: String,
+        /// Name of the binding
+        binding: String,
+        /// Resource declared by the shader
+        expected: String,
+        /// Resource of the binding
+        found: String,
+    },
+    /// Binding is not visible in the stage of the shader using the variable
+    Invisible {
+        /// Name of the shader
+        shader: String,
+        /// Name of the shader variable
+        variable: String,
+        /// Name of the binding
+        binding: String,
+        /// Shader stage using the variable
+        stage: String,
+    },
+}
+
+impl std::error::Error for BindingError {}
+
+impl std::fmt::Display for BindingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindingError::Missing {
+                shader,
+                variable,
+                group,
+                binding,
+            } => write!(
+                f,
+                "Shader `{}` uses `{}` of group {} binding {}, that is not bound",
+                shader, variable, group, binding
+            ),
+            BindingError::Mismatch {
+                shader,
+                variable,
+                binding,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Shader `{}` declares `{}` as {}, but binding `{}` is {}",
+                shader, variable, expected, binding, found
+            ),
+            BindingError::Invisible {
+                shader,
+                variable,
+                binding,
+                stage,
+            } => write!(
+                f,
+                "Shader `{}` uses `{}` in {} stage, but binding `{}` is not visible there",
+                shader, variable, stage, binding
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = r#"
+struct Camera {
+    proj_view: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> u_camera: Camera;
+[[group(0), binding(1)]]
+var r_texture: texture_2d<f32>;
+[[group(0), binding(2)]]
+var r_sampler: sampler;
+
+[[stage(vertex)]]
+fn vs_main([[location(0)]] position: vec3<f32>) -> [[builtin(position)]] vec4<f32> {
+    return u_camera.proj_view * vec4<f32>(position, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    return textureSample(r_texture, r_sampler, position.xy);
+}
+"#;
+
+    fn entry(
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        ty: wgpu::BindingType,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty,
+            count: None,
+        }
+    }
+
+    fn uniform() -> wgpu::BindingType {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        }
+    }
+
+    fn texture(sample_type: wgpu::TextureSampleType) -> wgpu::BindingType {
+        wgpu::BindingType::Texture {
+            sample_type,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        }
+    }
+
+    #[test]
+    fn bind_groups_are_validated_against_shader() {
+        let reflection = Reflection::new(CODE).unwrap();
+        let stages = [
+            ("vs_main", wgpu::ShaderStages::VERTEX),
+            ("fs_main", wgpu::ShaderStages::FRAGMENT),
+        ];
+        let float = wgpu::TextureSampleType::Float { filterable: true };
+        let sampler = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering);
+
+        let valid = vec![
+            ("Camera", entry(0, wgpu::ShaderStages::VERTEX, uniform())),
+            (
+                "Texture",
+                entry(1, wgpu::ShaderStages::FRAGMENT, texture(float)),
+            ),
+            ("Sampler", entry(2, wgpu::ShaderStages::FRAGMENT, sampler)),
+        ];
+        assert_eq!(
+            reflection.validate("test", &stages, std::slice::from_ref(&valid)),
+            Ok(())
+        );
+        // depth only pipelines do not use the fragment bindings
+        assert_eq!(
+            reflection.validate("test", &stages[..1], &[valid[..1].to_vec()]),
+            Ok(())
+        );
+
+        let mut depth = valid.clone();
+        depth[1].1.ty = texture(wgpu::TextureSampleType::Depth);
+        assert_eq!(
+            reflection.validate("test", &stages, &[depth]),
+            Err(BindingError::Mismatch {
+                shader: String::from("test"),
+                variable: String::from("r_texture"),
+                binding: String::from("Texture"),
+                expected: String::from("D2 texture"),
+                found: String::from("depth D2 texture"),
+            })
+        );
+
+        let mut invisible = valid.clone();
+        invisible[0].1.visibility = wgpu::ShaderStages::FRAGMENT;
+        assert!(matches!(
+            reflection.validate("test", &stages, &[invisible]),
+            Err(BindingError::Invisible { .. })
+        ));
+
+        assert_eq!(
+            reflection.validate("test", &stages, &[valid[..2].to_vec()]),
+            Err(BindingError::Missing {
+                shader: String::from("test"),
+                variable: String::from("r_sampler"),
+                group: 0,
+                binding: 2,
+            })
+        );
+    }
+}
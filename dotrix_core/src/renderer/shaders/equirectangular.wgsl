@@ -0,0 +1,65 @@
+[[group(0), binding(0)]]
+var r_source: texture_2d<f32>;
+
+[[group(0), binding(1)]]
+var w_cube: texture_storage_2d_array<rgba16float, write>;
+
+let PI: f32 = 3.14159265359;
+
+// Direction of the texel of the cube face in the order +X, -X, +Y, -Y, +Z, -Z
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let s = uv.x * 2.0 - 1.0;
+    let t = uv.y * 2.0 - 1.0;
+    if (face == 0u) {
+        return vec3<f32>(1.0, -t, -s);
+    }
+    if (face == 1u) {
+        return vec3<f32>(-1.0, -t, s);
+    }
+    if (face == 2u) {
+        return vec3<f32>(s, 1.0, t);
+    }
+    if (face == 3u) {
+        return vec3<f32>(s, -1.0, -t);
+    }
+    if (face == 4u) {
+        return vec3<f32>(s, -t, 1.0);
+    }
+    return vec3<f32>(-s, -t, -1.0);
+}
+
+fn load_texel(x: i32, y: i32, size: vec2<i32>) -> vec3<f32> {
+    // longitude wraps around, latitude is clamped at the poles
+    let wrapped = vec2<i32>((x % size.x + size.x) % size.x, clamp(y, 0, size.y - 1));
+    return textureLoad(r_source, wrapped, 0).rgb;
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(w_cube);
+    if (i32(id.x) >= size.x || i32(id.y) >= size.y) {
+        return;
+    }
+
+    let uv = (vec2<f32>(id.xy) + vec2<f32>(0.5, 0.5)) / vec2<f32>(size);
+    let direction = normalize(face_direction(id.z, uv));
+    let longitude = atan2(direction.z, direction.x);
+    let latitude = acos(clamp(direction.y, -1.0, 1.0));
+
+    // bilinear filtering, 32 bit float textures are not filterable
+    let source_size = textureDimensions(r_source);
+    let position = vec2<f32>(longitude / (2.0 * PI) + 0.5, latitude / PI)
+        * vec2<f32>(source_size) - vec2<f32>(0.5, 0.5);
+    let base = floor(position);
+    let f = position - base;
+    let x = i32(base.x);
+    let y = i32(base.y);
+    let top = mix(load_texel(x, y, source_size), load_texel(x + 1, y, source_size), f.x);
+    let bottom = mix(
+        load_texel(x, y + 1, source_size),
+        load_texel(x + 1, y + 1, source_size),
+        f.x
+    );
+
+    textureStore(w_cube, vec2<i32>(id.xy), i32(id.z), vec4<f32>(mix(top, bottom, f.y), 1.0));
+}
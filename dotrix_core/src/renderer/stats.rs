@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+/// Statistics of the render graph pass
+#[derive(Debug, Clone, Default)]
+pub struct PassStats {
+    /// Name of the pass
+    pub name: String,
+    /// Number of draw calls
+    pub draw_calls: u32,
+    /// Number of drawn triangles, indirect draws are not counted
+    pub triangles: u64,
+    /// GPU time of the pass, if it was measured
+    pub gpu_time: Option<Duration>,
+}
+
+/// Rendering statistics service
+///
+/// Counts draw calls and triangles of every render graph pass in the last frame. If
+/// `timestamps` are enabled and the adapter supports timestamp queries, GPU time of the passes
+/// is measured too. Timings are read back asynchronously, so they arrive a few frames later.
+#[derive(Default)]
+pub struct RenderStats {
+    /// Enables GPU timestamp queries
+    pub timestamps: bool,
+    pub(crate) supported: bool,
+    pub(crate) passes: Vec<PassStats>,
+}
+
+impl RenderStats {
+    /// Returns statistics of the passes in the order of execution
+    pub fn passes(&self) -> &[PassStats] {
+        &self.passes
+    }
+
+    /// Returns statistics of the pass
+    pub fn pass(&self, name: &str) -> Option<&PassStats> {
+        self.passes.iter().find(|pass| pass.name == name)
+    }
+
+    /// Returns total number of draw calls
+    pub fn draw_calls(&self) -> u32 {
+        self.passes.iter().map(|pass| pass.draw_calls).sum()
+    }
+
+    /// Returns total number of drawn triangles
+    pub fn triangles(&self) -> u64 {
+        self.passes.iter().map(|pass| pass.triangles).sum()
+    }
+
+    /// Returns total GPU time of the measured passes
+    pub fn gpu_time(&self) -> Option<Duration> {
+        self.passes
+            .iter()
+            .filter_map(|pass| pass.gpu_time)
+            .fold(None, |total, time| Some(total.unwrap_or_default() + time))
+    }
+
+    /// Returns true if the adapter supports timestamp queries
+    pub fn timestamps_supported(&self) -> bool {
+        self.supported
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_sum_up_passes() {
+        let mut stats = RenderStats::default();
+        assert_eq!(stats.gpu_time(), None);
+        stats.passes = vec![
+            PassStats {
+                name: String::from("shadow"),
+                draw_calls: 2,
+                triangles: 100,
+                gpu_time: Some(Duration::from_micros(300)),
+            },
+            PassStats {
+                name: String::from("main"),
+                draw_calls: 3,
+                triangles: 50,
+                gpu_time: None,
+            },
+        ];
+        assert_eq!(stats.draw_calls(), 5);
+        assert_eq!(stats.triangles(), 150);
+        assert_eq!(stats.gpu_time(), Some(Duration::from_micros(300)));
+        assert_eq!(stats.pass("main").map(|pass| pass.draw_calls), Some(3));
+    }
+}
@@ -1,11 +1,14 @@
 //! Window service - a wrapper for [`winit::window::Window`] instance.
 
+use std::collections::HashMap;
+
 use crate::assets::Texture;
 
 use dotrix_math::{clamp_min, Vec2, Vec2i, Vec2u};
 use winit::dpi::{PhysicalPosition, PhysicalSize, Position};
+use winit::event_loop::EventLoopWindowTarget;
 use winit::monitor::{MonitorHandle as WinitMonitor, VideoMode as WinitVideoMode};
-use winit::window::{Icon as WinitIcon, Window as WinitWindow};
+use winit::window::{Icon as WinitIcon, Window as WinitWindow, WindowBuilder, WindowId};
 
 pub use winit::window::{CursorIcon, Fullscreen as WinitFullscreen, UserAttentionType};
 
@@ -60,6 +63,13 @@ pub enum Fullscreen {
     Exclusive(VideoMode),
 }
 
+/// Additional window, created by the event loop
+struct ExtraWindow {
+    title: String,
+    size: Vec2u,
+    window: Option<WinitWindow>,
+}
+
 /// Window service - a wrapper for [`winit::window::Window`] instance.
 pub struct Window {
     always_on_top: bool,
@@ -74,6 +84,10 @@ pub struct Window {
     title: String,
     /// winit window instance
     window: Option<winit::window::Window>,
+    /// Size of the frame of the headless application, that has no window
+    headless_size: Option<Vec2u>,
+    /// Additional windows by their names
+    extra_windows: HashMap<String, ExtraWindow>,
 }
 
 impl Default for Window {
@@ -90,6 +104,8 @@ impl Default for Window {
             monitors: Vec::with_capacity(2),
             title: String::from("Dotrix"),
             window: None,
+            headless_size: None,
+            extra_windows: HashMap::new(),
         }
     }
 }
@@ -123,6 +139,93 @@ impl Window {
         self.window = Some(window);
     }
 
+    /// Sets the frame size of the headless application
+    pub(crate) fn set_headless(&mut self, size: Vec2u) {
+        self.headless_size = Some(size);
+    }
+
+    /// Checks if the application runs without a window, see [`crate::Dotrix::run_headless`]
+    ///
+    /// Headless window has no handle, only its size, scale factor and close requests are
+    /// available.
+    pub fn headless(&self) -> bool {
+        self.window.is_none() && self.headless_size.is_some()
+    }
+
+    /// Opens an additional window of the inner `size` in pixels
+    ///
+    /// The window is created by the event loop before the next frame. Passes writing the
+    /// [`crate::renderer::Attachment::window`] attachment of the same `name` render into it,
+    /// so editors and tools can show different views of the scene. Closing the additional
+    /// window does not exit the application.
+    pub fn open_window(&mut self, name: &str, title: &str, size: Vec2u) {
+        self.extra_windows.insert(
+            String::from(name),
+            ExtraWindow {
+                title: String::from(title),
+                size,
+                window: None,
+            },
+        );
+    }
+
+    /// Closes the additional window
+    pub fn close_window(&mut self, name: &str) {
+        self.extra_windows.remove(name);
+    }
+
+    /// Checks if the additional window is open
+    pub fn window_opened(&self, name: &str) -> bool {
+        self.extra_windows.contains_key(name)
+    }
+
+    /// Returns the inner size of the additional window in pixels
+    pub fn window_size(&self, name: &str) -> Option<Vec2u> {
+        self.extra_windows.get(name).map(|extra| {
+            extra
+                .window
+                .as_ref()
+                .map(|window| {
+                    let PhysicalSize { width, height } = window.inner_size();
+                    Vec2u::new(width.max(1), height.max(1))
+                })
+                .unwrap_or(extra.size)
+        })
+    }
+
+    /// Creates the requested additional windows
+    pub(crate) fn create_windows<T>(&mut self, event_loop: &EventLoopWindowTarget<T>) {
+        for extra in self.extra_windows.values_mut() {
+            if extra.window.is_none() {
+                extra.window = WindowBuilder::new()
+                    .with_title(&extra.title)
+                    .with_inner_size(PhysicalSize::new(extra.size.x, extra.size.y))
+                    .build(event_loop)
+                    .ok();
+            }
+        }
+    }
+
+    /// Closes the additional window of the id, returns false for the main window
+    pub(crate) fn close_window_by_id(&mut self, id: WindowId) -> bool {
+        let count = self.extra_windows.len();
+        self.extra_windows.retain(|_, extra| {
+            extra
+                .window
+                .as_ref()
+                .map(|window| window.id() != id)
+                .unwrap_or(true)
+        });
+        count != self.extra_windows.len()
+    }
+
+    /// Returns handles of the created additional windows by their names
+    pub(crate) fn windows(&self) -> impl Iterator<Item = (&str, &WinitWindow)> {
+        self.extra_windows
+            .iter()
+            .filter_map(|(name, extra)| extra.window.as_ref().map(|window| (name.as_str(), window)))
+    }
+
     /// Gets the window handle from the wrapper
     pub fn get(&self) -> &WinitWindow {
         self.window.as_ref().expect("Window handle must be set")
@@ -220,6 +323,9 @@ impl Window {
     /// The client area is the content of the window, excluding the title bar and
     /// borders.
     pub fn inner_size(&self) -> Vec2u {
+        if let (None, Some(size)) = (self.window.as_ref(), self.headless_size) {
+            return size;
+        }
         let PhysicalSize { width, height } = self.get().inner_size();
 
         Vec2u {
@@ -301,6 +407,9 @@ impl Window {
     /// Returns the scale factor that can be used to map logical pixels to physical
     /// pixels, and vice versa.
     pub fn scale_factor(&self) -> f32 {
+        if self.headless() {
+            return 1.0;
+        }
         self.get().scale_factor() as f32
     }
 
@@ -508,3 +617,19 @@ fn init_monitors(window: &WinitWindow) -> Vec<Monitor> {
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_window_has_frame_size() {
+        let mut window = Window::default();
+        assert!(!window.headless());
+
+        window.set_headless(Vec2u::new(320, 240));
+        assert!(window.headless());
+        assert_eq!(window.inner_size(), Vec2u::new(320, 240));
+        assert_eq!(window.scale_factor(), 1.0);
+    }
+}
@@ -8,6 +8,7 @@
 pub mod math;
 pub use cgmath::num_traits::clamp;
 pub use cgmath::num_traits::clamp_min;
+pub use cgmath::ortho;
 pub use cgmath::perspective;
 pub use cgmath::Deg;
 pub use cgmath::InnerSpace;
@@ -8,13 +8,16 @@ use std::{
 use dotrix_core::assets::Shader;
 use dotrix_core::ecs::{Const, Mut, Priority, System};
 use dotrix_core::renderer::{
-    BindGroup, Binding, DepthBufferMode, PipelineLayout, PipelineOptions, Sampler, Stage,
-    UniformBuffer,
+    BindGroup, Binding, DepthBufferMode, PipelineLayout, PipelineOptions, RenderPass, Sampler,
+    Stage, UniformBuffer, SURFACE,
 };
 use dotrix_core::{Application, Assets, Globals, Input, Pipeline, Renderer, Window};
 
 const PIPELINE_LABEL: &str = "dotrix::overlay";
 
+/// Name of the render graph pass rendering the overlay on top of the surface
+pub const OVERLAY_PASS: &str = "overlay";
+
 pub use widget::Widget;
 
 /// Overlay providers container
@@ -125,7 +128,11 @@ impl dyn Ui {
     }
 }
 
-pub fn startup(mut assets: Mut<Assets>, renderer: Const<Renderer>) {
+pub fn startup(mut assets: Mut<Assets>, mut renderer: Mut<Renderer>) {
+    renderer
+        .graph_mut()
+        .add_pass(RenderPass::new(OVERLAY_PASS).writes(SURFACE));
+
     let mut shader = Shader {
         name: String::from(PIPELINE_LABEL),
         code: String::from(include_str!("shaders/overlay.wgsl")),
@@ -165,6 +172,12 @@ pub fn render(
                 window_size.x as f32 / scale_factor,
                 window_size.y as f32 / scale_factor,
             ],
+            srgb: if renderer.surface_format().is_srgb() {
+                1.0
+            } else {
+                0.0
+            },
+            padding: 0.0,
         }]),
     );
 
@@ -173,6 +186,9 @@ pub fn render(
             if pipeline.shader.is_null() {
                 pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
             }
+            if pipeline.options.pass.is_none() {
+                pipeline.options.pass = Some(String::from(OVERLAY_PASS));
+            }
 
             // check if model is disabled or already rendered
             if !pipeline.cycle(&renderer) {
@@ -222,9 +238,12 @@ pub fn render(
                                     ],
                                 ),
                             ],
+                            vertex_buffers: &[],
+                            push_constants: None,
                             options: PipelineOptions {
                                 depth_buffer_mode: DepthBufferMode::Disabled,
                                 disable_cull_mode: true,
+                                ..Default::default()
                             },
                         },
                     );
@@ -241,6 +260,8 @@ pub fn render(
 #[derive(Default, Copy, Clone)]
 struct Uniform {
     window_size: [f32; 2],
+    srgb: f32,
+    padding: f32,
 }
 
 unsafe impl bytemuck::Zeroable for Uniform {}
@@ -8,6 +8,8 @@ struct VertexOutput {
 
 struct Overlay {
     window_size: vec2<f32>;
+    // 1.0 if the surface encodes colors to sRGB
+    srgb: f32;
 };
 [[group(0), binding(0)]]
 var<uniform> u_overlay: Overlay;
@@ -27,7 +29,9 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
     out.tex_uv = tex_uv;
-    out.color = vec4<f32>(linear_from_srgb(color.rgb), color.a);
+    // egui colors are in sRGB already, they are decoded only to be encoded by the surface
+    let rgb = select(color.rgb, linear_from_srgb(color.rgb), u_overlay.srgb > 0.5);
+    out.color = vec4<f32>(rgb, color.a);
     out.position = vec4<f32>(
       2.0 * position.x / u_overlay.window_size.x - 1.0,
       1.0 - 2.0 * position.y / u_overlay.window_size.y,
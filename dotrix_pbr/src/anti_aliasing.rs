@@ -0,0 +1,416 @@
+//! Post-process anti-aliasing
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    Attachment, BindGroup, Binding, DepthBufferMode, PipelineLayout, PipelineOptions, RenderPass,
+    Sampler, Stage, TextureFormat, UniformBuffer, SURFACE,
+};
+use dotrix_core::{Assets, Camera, Globals, Pipeline, Renderer, Window};
+
+use dotrix_math::Vec2;
+
+use crate::tone_mapping::{
+    ToneMapping, FULLSCREEN_TRIANGLE, HDR_TARGET, OVERLAY_PASS, TONE_MAPPING_PASS,
+};
+use crate::velocity::VELOCITY;
+
+const FXAA_LABEL: &str = "dotrix::anti_aliasing::fxaa";
+const TAA_LABEL: &str = "dotrix::anti_aliasing::taa";
+const HISTORY_LABEL: &str = "dotrix::anti_aliasing::history";
+
+/// Number of the jittered projections, the temporal anti-aliasing cycles through
+pub const TAA_SAMPLES: usize = 8;
+
+/// Name of the render graph attachment with the tone mapped scene, smoothed by the FXAA
+pub const LDR_TARGET: &str = "ldr";
+
+/// Name of the render graph pass writing the smoothed scene to the surface
+pub const FXAA_PASS: &str = "fxaa";
+
+/// Name of the render graph pass and attachment with the temporally smoothed HDR scene
+pub const TAA_TARGET: &str = "taa";
+
+/// Name of the render graph pass and attachment keeping the [`TAA_TARGET`] for the next frame
+pub const TAA_HISTORY: &str = "taa_history";
+
+/// Anti-aliasing of the scene
+///
+/// The anti-aliasing is selected once per application, before it runs:
+/// `app.add_service(AntiAliasing::Taa)` after the PBR extension. Post-process methods work
+/// with the single sample per pixel, see `Renderer::set_sample_count`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AntiAliasing {
+    /// Multisampling of the surface configured by the renderer, no post-processing
+    #[default]
+    Msaa,
+    /// Fast approximate anti-aliasing blurs the edges of the tone mapped scene in the
+    /// [`FXAA_PASS`], see [`Fxaa`]
+    Fxaa,
+    /// Temporal anti-aliasing jitters the projection every frame and blends the scene with
+    /// the reprojected history, see [`Taa`]
+    Taa,
+}
+
+/// Fast approximate anti-aliasing service
+pub struct Fxaa {
+    /// Minimal contrast of the edges
+    pub contrast_threshold: f32,
+    /// Minimal contrast of the edges relative to the brightest neighbor
+    pub relative_threshold: f32,
+    /// Maximal length of the blur along the edges in pixels
+    pub span: f32,
+    /// FXAA uniform buffer
+    pub uniform: UniformBuffer,
+    sampler: Sampler,
+    pipeline: Pipeline,
+    generation: Option<usize>,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Self {
+            contrast_threshold: 0.0312,
+            relative_threshold: 0.125,
+            span: 8.0,
+            uniform: UniformBuffer::default(),
+            sampler: Sampler::linear_clamp(),
+            pipeline: Pipeline::default().with_pass(FXAA_PASS),
+            generation: None,
+        }
+    }
+}
+
+/// Temporal anti-aliasing service
+///
+/// The history is reprojected by the [`VELOCITY`] of the pixels and limited by the colors of
+/// the pixel neighborhood, so moving edges do not ghost.
+pub struct Taa {
+    /// Weight of the history, higher values are smoother, but react to changes slower
+    pub feedback: f32,
+    /// TAA uniform buffer
+    pub uniform: UniformBuffer,
+    sampler: Sampler,
+    resolve: Pipeline,
+    history: Pipeline,
+    frame: usize,
+    generation: Option<usize>,
+}
+
+impl Default for Taa {
+    fn default() -> Self {
+        Self {
+            feedback: 0.9,
+            uniform: UniformBuffer::default(),
+            sampler: Sampler::linear_clamp(),
+            resolve: Pipeline::default().with_pass(TAA_TARGET),
+            history: Pipeline::default().with_pass(TAA_HISTORY),
+            frame: 0,
+            generation: None,
+        }
+    }
+}
+
+/// Returns element of the Halton low discrepancy sequence in the range 0..1
+pub fn halton(mut index: usize, base: usize) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Anti-aliasing startup system
+pub fn startup(
+    anti_aliasing: Const<AntiAliasing>,
+    mut renderer: Mut<Renderer>,
+    mut assets: Mut<Assets>,
+    mut globals: Mut<Globals>,
+) {
+    let graph = renderer.graph_mut();
+    let shaders = match *anti_aliasing {
+        AntiAliasing::Msaa => return,
+        AntiAliasing::Fxaa => {
+            graph.add_attachment(LDR_TARGET, Attachment::color(TextureFormat::rgba_u8norm()));
+            if let Some(tone_mapping) = graph.pass_mut(TONE_MAPPING_PASS) {
+                for write in tone_mapping.writes.iter_mut().filter(|w| *w == SURFACE) {
+                    *write = String::from(LDR_TARGET);
+                }
+            }
+            graph.add_pass_before(
+                OVERLAY_PASS,
+                RenderPass::new(FXAA_PASS).reads(LDR_TARGET).writes(SURFACE),
+            );
+            vec![(FXAA_LABEL, include_str!("shaders/fxaa.wgsl"))]
+        }
+        AntiAliasing::Taa => {
+            graph.add_attachment(TAA_TARGET, Attachment::color(TextureFormat::rgba_f16()));
+            graph.add_attachment(TAA_HISTORY, Attachment::color(TextureFormat::rgba_f16()));
+            graph.add_pass(
+                RenderPass::new(TAA_TARGET)
+                    .reads(HDR_TARGET)
+                    .reads(VELOCITY)
+                    .reads_history(TAA_HISTORY)
+                    .writes(TAA_TARGET),
+            );
+            graph.add_pass(
+                RenderPass::new(TAA_HISTORY)
+                    .reads(TAA_TARGET)
+                    .writes(TAA_HISTORY),
+            );
+            if let Some(tone_mapping) = graph.pass_mut(TONE_MAPPING_PASS) {
+                for read in tone_mapping.reads.iter_mut().filter(|r| *r == HDR_TARGET) {
+                    *read = String::from(TAA_TARGET);
+                }
+            }
+            if let Some(tone_mapping) = globals.get_mut::<ToneMapping>() {
+                tone_mapping.source = TAA_TARGET;
+            }
+            vec![
+                (TAA_LABEL, include_str!("shaders/taa.wgsl")),
+                (HISTORY_LABEL, include_str!("shaders/taa_history.wgsl")),
+            ]
+        }
+    };
+
+    for (label, code) in shaders.into_iter() {
+        let mut shader = Shader {
+            name: String::from(label),
+            code: String::from(code),
+            ..Default::default()
+        };
+        shader.load(&renderer);
+        assets.store_as(shader, label);
+    }
+}
+
+/// FXAA rendering system
+pub fn render_fxaa(
+    mut fxaa: Mut<Fxaa>,
+    anti_aliasing: Const<AntiAliasing>,
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+) {
+    if *anti_aliasing != AntiAliasing::Fxaa {
+        return;
+    }
+
+    let uniform = FxaaUniform {
+        params: [
+            fxaa.contrast_threshold,
+            fxaa.relative_threshold,
+            fxaa.span,
+            0.0,
+        ],
+    };
+    renderer.load_uniform_buffer(&mut fxaa.uniform, bytemuck::cast_slice(&[uniform]));
+    renderer.load_sampler(&mut fxaa.sampler);
+
+    // the tone mapped scene is recreated on resize
+    let generation = renderer.attachments_generation();
+    if fxaa.generation != Some(generation) {
+        fxaa.generation = Some(generation);
+        fxaa.pipeline.bindings.unload();
+    }
+
+    let Fxaa {
+        uniform,
+        sampler,
+        pipeline,
+        ..
+    } = &mut *fxaa;
+    if pipeline.shader.is_null() {
+        pipeline.shader = assets.find::<Shader>(FXAA_LABEL).unwrap_or_default();
+    }
+    if !pipeline.cycle(&renderer) || renderer.attachment(LDR_TARGET).is_none() {
+        return;
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    if !pipeline.ready() {
+        let shader = match assets.get(pipeline.shader) {
+            Some(shader) if shader.loaded() => shader,
+            _ => return,
+        };
+        renderer.bind(
+            pipeline,
+            PipelineLayout {
+                label: String::from(FXAA_LABEL),
+                mesh: Some(mesh),
+                shader,
+                bindings: &[BindGroup::new(
+                    "Globals",
+                    vec![
+                        Binding::Uniform("Fxaa", Stage::Fragment, uniform),
+                        Binding::Attachment("Source", Stage::Fragment, LDR_TARGET),
+                        Binding::Sampler("Sampler", Stage::Fragment, sampler),
+                    ],
+                )],
+                vertex_buffers: &[],
+                push_constants: None,
+                options: PipelineOptions {
+                    depth_buffer_mode: DepthBufferMode::Disabled,
+                    disable_cull_mode: true,
+                    ..Default::default()
+                },
+            },
+        );
+    }
+
+    renderer.run(pipeline, mesh);
+}
+
+/// TAA rendering system
+pub fn render_taa(
+    mut taa: Mut<Taa>,
+    anti_aliasing: Const<AntiAliasing>,
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    mut camera: Mut<Camera>,
+    window: Const<Window>,
+) {
+    if *anti_aliasing != AntiAliasing::Taa {
+        return;
+    }
+
+    let uniform = TaaUniform {
+        params: [taa.feedback.clamp(0.0, 1.0), 0.0, 0.0, 0.0],
+    };
+    renderer.load_uniform_buffer(&mut taa.uniform, bytemuck::cast_slice(&[uniform]));
+    renderer.load_sampler(&mut taa.sampler);
+
+    // the projection of the next frame is moved within the pixel
+    taa.frame = (taa.frame + 1) % TAA_SAMPLES;
+    let size = window.inner_size();
+    camera.jitter = Vec2::new(
+        (halton(taa.frame + 1, 2) - 0.5) * 2.0 / size.x as f32,
+        (halton(taa.frame + 1, 3) - 0.5) * 2.0 / size.y as f32,
+    );
+
+    // the velocity, history and scene textures are recreated on resize
+    let generation = renderer.attachments_generation();
+    if taa.generation != Some(generation) {
+        taa.generation = Some(generation);
+        taa.resolve.bindings.unload();
+        taa.history.bindings.unload();
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    let Taa {
+        uniform,
+        sampler,
+        resolve,
+        history,
+        ..
+    } = &mut *taa;
+
+    for (pipeline, label, sources) in [
+        (resolve, TAA_LABEL, vec![HDR_TARGET, VELOCITY, TAA_HISTORY]),
+        (history, HISTORY_LABEL, vec![TAA_TARGET]),
+    ] {
+        if pipeline.shader.is_null() {
+            pipeline.shader = assets.find::<Shader>(label).unwrap_or_default();
+        }
+        if !pipeline.cycle(&renderer)
+            || !sources
+                .iter()
+                .all(|source| renderer.attachment(source).is_some())
+        {
+            return;
+        }
+
+        if !pipeline.ready() {
+            let shader = match assets.get(pipeline.shader) {
+                Some(shader) if shader.loaded() => shader,
+                _ => return,
+            };
+            // the history copy samples nothing but its source
+            let mut bindings = Vec::with_capacity(sources.len() + 2);
+            if label != HISTORY_LABEL {
+                bindings.push(Binding::Uniform("Taa", Stage::Fragment, &*uniform));
+            }
+            bindings.extend(
+                sources
+                    .iter()
+                    .map(|source| Binding::Attachment("Source", Stage::Fragment, source)),
+            );
+            if label == TAA_LABEL {
+                bindings.push(Binding::Sampler("Sampler", Stage::Fragment, &*sampler));
+            }
+
+            renderer.bind(
+                pipeline,
+                PipelineLayout {
+                    label: String::from(label),
+                    mesh: Some(mesh),
+                    shader,
+                    bindings: &[BindGroup::new("Globals", bindings)],
+                    vertex_buffers: &[],
+                    push_constants: None,
+                    options: PipelineOptions {
+                        depth_buffer_mode: DepthBufferMode::Disabled,
+                        disable_cull_mode: true,
+                        ..Default::default()
+                    },
+                },
+            );
+        }
+
+        renderer.run(pipeline, mesh);
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct FxaaUniform {
+    /// Contrast threshold, relative threshold and span
+    params: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for FxaaUniform {}
+unsafe impl bytemuck::Pod for FxaaUniform {}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct TaaUniform {
+    /// Feedback
+    params: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for TaaUniform {}
+unsafe impl bytemuck::Pod for TaaUniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halton_sequence_fills_the_pixel() {
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(2, 2), 0.25);
+        assert_eq!(halton(3, 2), 0.75);
+        assert!((halton(1, 3) - 1.0 / 3.0).abs() < f32::EPSILON);
+        assert!((halton(2, 3) - 2.0 / 3.0).abs() < f32::EPSILON);
+
+        for index in 1..=TAA_SAMPLES {
+            let sample = halton(index, 3);
+            assert!(sample > 0.0 && sample < 1.0);
+        }
+    }
+}
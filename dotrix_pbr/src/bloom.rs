@@ -0,0 +1,312 @@
+//! Bloom of the bright HDR colors
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    Attachment, AttachmentSize, BindGroup, Binding, DepthBufferMode, PipelineLayout,
+    PipelineOptions, RenderPass, Sampler, Stage, TextureFormat, UniformBuffer,
+};
+use dotrix_core::{Assets, Pipeline, Renderer};
+
+use crate::tone_mapping::{FULLSCREEN_TRIANGLE, HDR_TARGET, TONE_MAPPING_PASS};
+
+const PREFILTER_LABEL: &str = "dotrix::bloom::prefilter";
+const DOWNSAMPLE_LABEL: &str = "dotrix::bloom::downsample";
+const UPSAMPLE_LABEL: &str = "dotrix::bloom::upsample";
+
+/// Number of the bloom mip levels, the first one has half of the surface size
+pub const BLOOM_LEVELS: usize = 5;
+
+/// Name of the render graph attachment with the blurred bright colors
+pub const BLOOM_TARGET: &str = "bloom_up_0";
+
+/// Bloom service
+///
+/// Colors of the [`HDR_TARGET`] brighter than `threshold` are downsampled through
+/// [`BLOOM_LEVELS`] textures and blurred back up, so every level adds a wider glow. The tone
+/// mapping pass adds the result multiplied by `intensity` to the scene.
+pub struct Bloom {
+    /// Bloom is enabled
+    pub enabled: bool,
+    /// Brightness, where colors start to bloom
+    pub threshold: f32,
+    /// Soft transition below the threshold, fraction of the threshold
+    pub knee: f32,
+    /// Strength of the bloom added to the scene
+    pub intensity: f32,
+    /// Bloom uniform buffer
+    pub uniform: UniformBuffer,
+    sampler: Sampler,
+    passes: Vec<BloomPass>,
+    generation: Option<usize>,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        let mut passes = Vec::with_capacity(2 * BLOOM_LEVELS - 1);
+        passes.push(BloomPass::new(
+            &down_attachment(0),
+            PREFILTER_LABEL,
+            vec![String::from(HDR_TARGET)],
+        ));
+        for level in 1..BLOOM_LEVELS {
+            passes.push(BloomPass::new(
+                &down_attachment(level),
+                DOWNSAMPLE_LABEL,
+                vec![down_attachment(level - 1)],
+            ));
+        }
+        for level in (0..BLOOM_LEVELS - 1).rev() {
+            let source = if level == BLOOM_LEVELS - 2 {
+                down_attachment(level + 1)
+            } else {
+                up_attachment(level + 1)
+            };
+            passes.push(BloomPass::new(
+                &up_attachment(level),
+                UPSAMPLE_LABEL,
+                vec![source, down_attachment(level)],
+            ));
+        }
+
+        Self {
+            enabled: false,
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.05,
+            uniform: UniformBuffer::default(),
+            sampler: Sampler::linear_clamp(),
+            passes,
+            generation: None,
+        }
+    }
+}
+
+/// Fullscreen pass of the bloom chain, named after the attachment it writes
+struct BloomPass {
+    name: String,
+    shader: &'static str,
+    sources: Vec<String>,
+    pipeline: Pipeline,
+}
+
+impl BloomPass {
+    fn new(name: &str, shader: &'static str, sources: Vec<String>) -> Self {
+        Self {
+            name: String::from(name),
+            shader,
+            sources,
+            pipeline: Pipeline::default().with_pass(name),
+        }
+    }
+}
+
+fn down_attachment(level: usize) -> String {
+    format!("bloom_down_{}", level)
+}
+
+fn up_attachment(level: usize) -> String {
+    format!("bloom_up_{}", level)
+}
+
+/// Returns size of the bloom level
+fn level_size(level: usize) -> AttachmentSize {
+    AttachmentSize::Scaled(0.5f32.powi(level as i32 + 1))
+}
+
+/// Bloom startup system
+pub fn startup(bloom: Const<Bloom>, mut renderer: Mut<Renderer>, mut assets: Mut<Assets>) {
+    let graph = renderer.graph_mut();
+    for level in 0..BLOOM_LEVELS {
+        let attachment = Attachment::color(TextureFormat::rgba_f16()).with_size(level_size(level));
+        graph.add_attachment(&down_attachment(level), attachment);
+        if level < BLOOM_LEVELS - 1 {
+            graph.add_attachment(&up_attachment(level), attachment);
+        }
+    }
+    for pass in bloom.passes.iter() {
+        let render_pass = pass
+            .sources
+            .iter()
+            .fold(RenderPass::new(&pass.name), |render_pass, source| {
+                render_pass.reads(source)
+            })
+            .writes(&pass.name);
+        graph.add_pass(render_pass);
+    }
+    if let Some(tone_mapping) = graph.pass_mut(TONE_MAPPING_PASS) {
+        tone_mapping.reads.push(String::from(BLOOM_TARGET));
+    }
+
+    for (label, code) in [
+        (
+            PREFILTER_LABEL,
+            include_str!("shaders/bloom_prefilter.wgsl"),
+        ),
+        (
+            DOWNSAMPLE_LABEL,
+            include_str!("shaders/bloom_downsample.wgsl"),
+        ),
+        (UPSAMPLE_LABEL, include_str!("shaders/bloom_upsample.wgsl")),
+    ]
+    .iter()
+    {
+        let mut shader = Shader {
+            name: String::from(*label),
+            code: String::from(*code),
+            ..Default::default()
+        };
+        shader.load(&renderer);
+        assets.store_as(shader, label);
+    }
+}
+
+/// Bloom rendering system
+pub fn render(mut bloom: Mut<Bloom>, mut renderer: Mut<Renderer>, assets: Const<Assets>) {
+    let uniform = Uniform {
+        threshold: bloom.threshold,
+        knee: bloom.knee * bloom.threshold,
+        padding: [0.0; 2],
+    };
+    renderer.load_uniform_buffer(&mut bloom.uniform, bytemuck::cast_slice(&[uniform]));
+    renderer.load_sampler(&mut bloom.sampler);
+
+    // bloom textures are recreated on resize
+    let generation = renderer.attachments_generation();
+    if bloom.generation != Some(generation) {
+        bloom.generation = Some(generation);
+        for pass in bloom.passes.iter_mut() {
+            pass.pipeline.bindings.unload();
+        }
+    }
+
+    if !bloom.enabled {
+        return;
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    let Bloom {
+        passes,
+        uniform,
+        sampler,
+        ..
+    } = &mut *bloom;
+
+    for pass in passes.iter_mut() {
+        let pipeline = &mut pass.pipeline;
+        if pipeline.shader.is_null() {
+            pipeline.shader = assets.find::<Shader>(pass.shader).unwrap_or_default();
+        }
+        if !pipeline.cycle(&renderer) {
+            continue;
+        }
+        if !pass
+            .sources
+            .iter()
+            .all(|source| renderer.attachment(source).is_some())
+        {
+            return;
+        }
+
+        if !pipeline.ready() {
+            let shader = match assets.get(pipeline.shader) {
+                Some(shader) if shader.loaded() => shader,
+                _ => return,
+            };
+            let mut bindings = vec![Binding::Uniform("Bloom", Stage::Fragment, uniform)];
+            bindings.extend(
+                pass.sources
+                    .iter()
+                    .map(|source| Binding::Attachment("Source", Stage::Fragment, source)),
+            );
+            bindings.push(Binding::Sampler("Sampler", Stage::Fragment, sampler));
+
+            renderer.bind(
+                pipeline,
+                PipelineLayout {
+                    label: String::from(pass.shader),
+                    mesh: Some(mesh),
+                    shader,
+                    bindings: &[BindGroup::new("Globals", bindings)],
+                    vertex_buffers: &[],
+                    push_constants: None,
+                    options: PipelineOptions {
+                        depth_buffer_mode: DepthBufferMode::Disabled,
+                        disable_cull_mode: true,
+                        ..Default::default()
+                    },
+                },
+            );
+        }
+
+        renderer.run(pipeline, mesh);
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    threshold: f32,
+    knee: f32,
+    padding: [f32; 2],
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_go_down_and_back_up_the_chain() {
+        let bloom = Bloom::default();
+        let names = bloom
+            .passes
+            .iter()
+            .map(|pass| pass.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "bloom_down_0",
+                "bloom_down_1",
+                "bloom_down_2",
+                "bloom_down_3",
+                "bloom_down_4",
+                "bloom_up_3",
+                "bloom_up_2",
+                "bloom_up_1",
+                "bloom_up_0",
+            ]
+        );
+        assert_eq!(bloom.passes[0].sources, vec![String::from(HDR_TARGET)]);
+        assert_eq!(bloom.passes[0].shader, PREFILTER_LABEL);
+        // the lowest level is upsampled from the last downsampled texture
+        assert_eq!(
+            bloom.passes[5].sources,
+            vec![down_attachment(4), down_attachment(3)]
+        );
+        assert_eq!(
+            bloom.passes[6].sources,
+            vec![up_attachment(3), down_attachment(2)]
+        );
+        assert_eq!(bloom.passes.last().unwrap().name, BLOOM_TARGET);
+    }
+
+    #[test]
+    fn every_level_halves_the_size() {
+        assert_eq!(level_size(0), AttachmentSize::Scaled(0.5));
+        assert_eq!(level_size(1), AttachmentSize::Scaled(0.25));
+        assert_eq!(
+            level_size(BLOOM_LEVELS - 1),
+            AttachmentSize::Scaled(1.0 / 32.0)
+        );
+    }
+}
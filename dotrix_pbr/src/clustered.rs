@@ -0,0 +1,347 @@
+//! Clustered forward lighting
+use dotrix_core::assets::Shader;
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    BindGroup, Binding, PipelineLayout, PipelineOptions, RenderPass, Stage, StorageBuffer,
+    UniformBuffer, WorkGroups, MAIN_PASS,
+};
+use dotrix_core::{Assets, Camera, Globals, Pipeline, Renderer, World};
+
+use dotrix_math::{Mat4, SquareMatrix};
+
+use crate::deferred::LIGHTING_PASS;
+use crate::shadow::{LocalLight, Shadows};
+use crate::Light;
+
+const PIPELINE_LABEL: &str = "dotrix::clustered::culling";
+
+const WORKGROUP_SIZE: usize = 64;
+
+/// Number of the clusters along the screen width
+pub const CLUSTERS_X: usize = 16;
+
+/// Number of the clusters along the screen height
+pub const CLUSTERS_Y: usize = 9;
+
+/// Number of the exponential depth slices of the view frustum
+pub const CLUSTERS_Z: usize = 24;
+
+/// Maximal number of the point and spot lights
+pub const MAX_CLUSTERED_LIGHTS: usize = 1024;
+
+/// Maximal number of the lights affecting a single cluster
+pub const MAX_LIGHTS_PER_CLUSTER: usize = 63;
+
+/// Name of the render graph pass culling the lights of the clusters
+pub const CLUSTERS_PASS: &str = "light_clusters";
+
+/// Name of the light lists buffer produced by the [`CLUSTERS_PASS`] for the lit passes
+pub const CLUSTERS_BUFFER: &str = "dotrix::light_clusters";
+
+/// Lights, that are dimmer than this value, do not reach the clusters
+const LIGHT_THRESHOLD: f32 = 0.01;
+
+const CLUSTERS_COUNT: usize = CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z;
+
+/// Number of the `u32` values of the cluster: count of the lights followed by their indices
+const CLUSTER_SIZE: usize = MAX_LIGHTS_PER_CLUSTER + 1;
+
+/// Clustered point and spot lights service
+///
+/// The view frustum is split into [`CLUSTERS_X`] x [`CLUSTERS_Y`] x [`CLUSTERS_Z`] froxels.
+/// The compute pipeline of the [`CLUSTERS_PASS`] lists the point and spot lights reaching every
+/// froxel, so pipelines including `{{ include(clusters) }}` shade up to
+/// [`MAX_CLUSTERED_LIGHTS`] lights by looking only at the lights of the fragment froxel, see
+/// [`ClusteredLights::add_to_shader`].
+pub struct ClusteredLights {
+    /// Clusters uniform buffer
+    pub uniform: UniformBuffer,
+    /// Storage buffer of the point and spot lights
+    pub lights: StorageBuffer,
+    /// Storage buffer of the light lists of the clusters
+    pub lists: StorageBuffer,
+    pipeline: Pipeline,
+}
+
+impl Default for ClusteredLights {
+    fn default() -> Self {
+        Self {
+            uniform: UniformBuffer::default(),
+            lights: StorageBuffer::new_readonly(),
+            lists: StorageBuffer::new_readwrite(),
+            pipeline: Pipeline::default().with_pass(CLUSTERS_PASS),
+        }
+    }
+}
+
+impl ClusteredLights {
+    /// Integrates clusters into shader
+    ///
+    /// The `source` shader code must contain `{{ include(clusters) }}` label before the
+    /// `{{ include(light) }}` one, then point and spot lights are taken from the clusters
+    /// instead of the lights uniform. Clusters take three bindings, starting from `binding`.
+    pub fn add_to_shader(source: &str, bind_group: usize, binding: usize) -> String {
+        let clusters_code = include_str!("shaders/clusters.inc.wgsl")
+            .replace("{{ bind_group }}", &bind_group.to_string())
+            .replace("{{ binding }}", &binding.to_string())
+            .replace("{{ lights_binding }}", &(binding + 1).to_string())
+            .replace("{{ lists_binding }}", &(binding + 2).to_string())
+            .replace("{{ clusters_x }}", &format!("{:?}u", CLUSTERS_X))
+            .replace("{{ clusters_y }}", &format!("{:?}u", CLUSTERS_Y))
+            .replace("{{ clusters_z }}", &format!("{:?}u", CLUSTERS_Z))
+            .replace("{{ cluster_size }}", &format!("{:?}u", CLUSTER_SIZE));
+        source.replace("{{ include(clusters) }}", &clusters_code)
+    }
+
+    /// Returns bindings of the clusters for lit pipelines
+    pub fn bindings(&self) -> Vec<Binding<'_>> {
+        vec![
+            Binding::Uniform("Clusters", Stage::Fragment, &self.uniform),
+            Binding::Storage("ClusteredLights", Stage::Fragment, &self.lights),
+            Binding::Storage("ClusterLists", Stage::Fragment, &self.lists),
+        ]
+    }
+
+    /// Returns true if the buffers are loaded and the pipelines can be bound
+    pub fn ready(&self) -> bool {
+        !self.lights.is_empty() && !self.lists.is_empty()
+    }
+}
+
+/// Returns distance, where the attenuated light gets dimmer than the threshold
+///
+/// Zero is returned for lights, that are not attenuated.
+pub fn light_range(brightness: f32, constant: f32, linear: f32, quadratic: f32) -> f32 {
+    // brightness / (constant + linear * d + quadratic * d^2) = LIGHT_THRESHOLD
+    let c = constant - brightness / LIGHT_THRESHOLD;
+    if c >= 0.0 {
+        f32::EPSILON
+    } else if quadratic > 0.0 {
+        (-linear + (linear * linear - 4.0 * quadratic * c).sqrt()) / (2.0 * quadratic)
+    } else if linear > 0.0 {
+        -c / linear
+    } else {
+        0.0
+    }
+}
+
+/// Clusters startup system
+pub fn startup(mut renderer: Mut<Renderer>, mut assets: Mut<Assets>) {
+    let graph = renderer.graph_mut();
+    graph.add_pass_before(
+        MAIN_PASS,
+        RenderPass::new(CLUSTERS_PASS).produces(CLUSTERS_BUFFER),
+    );
+    for pass in [MAIN_PASS, LIGHTING_PASS].iter() {
+        if let Some(pass) = graph.pass_mut(pass) {
+            pass.consumes.push(String::from(CLUSTERS_BUFFER));
+        }
+    }
+
+    let mut shader = Shader {
+        name: String::from(PIPELINE_LABEL),
+        code: ClusteredLights::add_to_shader(include_str!("shaders/light_clusters.wgsl"), 0, 0),
+        ..Default::default()
+    };
+    shader.load(&renderer);
+    assets.store_as(shader, PIPELINE_LABEL);
+}
+
+/// Clusters loading and light culling system
+pub fn compute(
+    mut clusters: Mut<ClusteredLights>,
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    camera: Const<Camera>,
+    globals: Const<Globals>,
+    world: Const<World>,
+) {
+    let (proj, view) = match (camera.proj, camera.view) {
+        (Some(proj), Some(view)) => (proj, view),
+        _ => return,
+    };
+
+    let local_lights = globals
+        .get::<Shadows>()
+        .map(|shadows| shadows.local_lights())
+        .unwrap_or_default();
+    let shadow = |light: LocalLight| {
+        local_lights
+            .iter()
+            .position(|local_light| *local_light == light)
+            .map(|slot| slot as f32)
+            .unwrap_or(-1.0)
+    };
+
+    let mut lights = Vec::new();
+    let (mut points, mut spots) = (0, 0);
+    for (light,) in world.query::<(&Light,)>() {
+        if lights.len() == MAX_CLUSTERED_LIGHTS {
+            break;
+        }
+        match *light {
+            Light::Point {
+                color,
+                position,
+                intensity,
+                enabled: true,
+                constant,
+                linear,
+                quadratic,
+            } => {
+                let color = color * intensity;
+                let brightness = color.r.max(color.g).max(color.b);
+                let range = light_range(brightness, constant, linear, quadratic);
+                lights.push(ClusteredLight {
+                    position: [position.x, position.y, position.z, range],
+                    color: [color.r, color.g, color.b, 0.0],
+                    direction: [0.0, 0.0, 0.0, shadow(LocalLight::Point(points))],
+                    params: [constant, linear, quadratic, 0.0],
+                });
+                points += 1;
+            }
+            Light::Spot {
+                color,
+                position,
+                direction,
+                intensity,
+                enabled: true,
+                cut_off,
+                outer_cut_off,
+            } => {
+                let color = color * intensity;
+                lights.push(ClusteredLight {
+                    position: [position.x, position.y, position.z, 0.0],
+                    color: [color.r, color.g, color.b, 1.0],
+                    direction: [
+                        direction.x,
+                        direction.y,
+                        direction.z,
+                        shadow(LocalLight::Spot(spots)),
+                    ],
+                    params: [cut_off, outer_cut_off, 0.0, 0.0],
+                });
+                spots += 1;
+            }
+            _ => {}
+        }
+    }
+
+    let uniform = Uniform {
+        view: view.into(),
+        proj: proj.into(),
+        inverse_proj: proj.invert().unwrap_or_else(Mat4::identity).into(),
+        params: [
+            camera.near_plane,
+            camera.far_plane,
+            lights.len() as f32,
+            0.0,
+        ],
+    };
+    renderer.load_uniform_buffer(&mut clusters.uniform, bytemuck::cast_slice(&[uniform]));
+
+    // buffers are allocated once, so the bindings stay valid
+    if clusters.lights.is_empty() {
+        let capacity = vec![ClusteredLight::default(); MAX_CLUSTERED_LIGHTS];
+        renderer.load_storage_buffer(&mut clusters.lights, bytemuck::cast_slice(&capacity));
+    }
+    if !lights.is_empty() {
+        renderer.load_storage_buffer(&mut clusters.lights, bytemuck::cast_slice(&lights));
+    }
+    if clusters.lists.is_empty() {
+        let lists = vec![0u32; CLUSTERS_COUNT * CLUSTER_SIZE];
+        renderer.load_storage_buffer(&mut clusters.lists, bytemuck::cast_slice(&lists));
+    }
+
+    let ClusteredLights {
+        uniform,
+        lights,
+        lists,
+        pipeline,
+    } = &mut *clusters;
+
+    if pipeline.shader.is_null() {
+        pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+    }
+
+    if !pipeline.ready() {
+        let shader = match assets.get(pipeline.shader) {
+            Some(shader) if shader.loaded() => shader,
+            _ => return,
+        };
+        renderer.bind(
+            pipeline,
+            PipelineLayout {
+                label: String::from(PIPELINE_LABEL),
+                mesh: None,
+                shader,
+                bindings: &[BindGroup::new(
+                    "Globals",
+                    vec![
+                        Binding::Uniform("Clusters", Stage::Compute, uniform),
+                        Binding::Storage("ClusteredLights", Stage::Compute, lights),
+                        Binding::Storage("ClusterLists", Stage::Compute, lists),
+                    ],
+                )],
+                vertex_buffers: &[],
+                push_constants: None,
+                options: PipelineOptions::default(),
+            },
+        );
+    }
+
+    renderer.compute(
+        pipeline,
+        WorkGroups {
+            x: (CLUSTERS_COUNT as f32 / WORKGROUP_SIZE as f32).ceil() as u32,
+            y: 1,
+            z: 1,
+        },
+    );
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    view: [[f32; 4]; 4],
+    proj: [[f32; 4]; 4],
+    inverse_proj: [[f32; 4]; 4],
+    /// Near plane, far plane and number of lights
+    params: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct ClusteredLight {
+    /// Position and range
+    position: [f32; 4],
+    /// Color and 1.0 for spot lights
+    color: [f32; 4],
+    /// Direction of spot lights and shadow slot
+    direction: [f32; 4],
+    /// Attenuation of point lights or cut offs of spot lights
+    params: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for ClusteredLight {}
+unsafe impl bytemuck::Pod for ClusteredLight {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_range_reaches_the_threshold() {
+        let range = light_range(1.0, 1.0, 0.35, 0.44);
+        let attenuation = 1.0 / (1.0 + 0.35 * range + 0.44 * range * range);
+        assert!((attenuation - LIGHT_THRESHOLD).abs() < 1e-4);
+
+        let range = light_range(2.0, 1.0, 0.5, 0.0);
+        assert!((2.0 / (1.0 + 0.5 * range) - LIGHT_THRESHOLD).abs() < 1e-4);
+
+        assert_eq!(light_range(1.0, 1.0, 0.0, 0.0), 0.0);
+    }
+}
@@ -0,0 +1,455 @@
+//! Immediate mode debug drawing
+use std::f32::consts::PI;
+
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    AttributeFormat, BindGroup, Binding, BlendMode, DepthBufferMode, PipelineLayout,
+    PipelineOptions, PrimitiveTopology, Stage, UniformBuffer,
+};
+use dotrix_core::{Assets, Camera, Color, Pipeline, Renderer, Transform};
+
+use dotrix_math::{Vec3, Vec4};
+
+const PIPELINE_LABEL: &str = "dotrix::debug_draw";
+
+/// Number of the lines of the sphere circles
+const CIRCLE_SEGMENTS: usize = 24;
+
+/// Ends of the glyph segments in the cell of 1 x 2 units, from the bottom left corner
+const SEGMENTS: [[f32; 4]; 15] = [
+    // top, upper right, lower right, bottom, lower left, upper left
+    [0.0, 2.0, 1.0, 2.0],
+    [1.0, 2.0, 1.0, 1.0],
+    [1.0, 1.0, 1.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0, 2.0],
+    // middle left and right halves
+    [0.0, 1.0, 0.5, 1.0],
+    [0.5, 1.0, 1.0, 1.0],
+    // upper left diagonal, upper center, upper right diagonal
+    [0.0, 2.0, 0.5, 1.0],
+    [0.5, 2.0, 0.5, 1.0],
+    [1.0, 2.0, 0.5, 1.0],
+    // lower left diagonal, lower center, lower right diagonal
+    [0.0, 0.0, 0.5, 1.0],
+    [0.5, 1.0, 0.5, 0.0],
+    [1.0, 0.0, 0.5, 1.0],
+    // dot
+    [0.4, 0.0, 0.6, 0.0],
+];
+
+/// Immediate mode debug drawing service
+///
+/// Lines, boxes, spheres, axes and labels are batched during the frame and rendered as lines
+/// in the main pass, then the batches are cleared, so the primitives are added every frame.
+/// Primitives added while the `depth_test` is disabled are drawn on top of the scene.
+pub struct DebugDraw {
+    /// Primitives are drawn
+    pub enabled: bool,
+    /// Next primitives are hidden behind the models
+    pub depth_test: bool,
+    uniform: UniformBuffer,
+    /// Depth tested and always visible primitives
+    batches: [Batch; 2],
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            depth_test: true,
+            uniform: UniformBuffer::default(),
+            batches: [Batch::default(), Batch::default()],
+        }
+    }
+}
+
+impl DebugDraw {
+    /// Draws line between the points
+    pub fn line(&mut self, from: Vec3, to: Vec3, color: Color) {
+        if !self.enabled {
+            return;
+        }
+        let color = color.into();
+        let vertices = &mut self.batch().vertices;
+        vertices.push(DebugVertex {
+            position: from.into(),
+            color,
+        });
+        vertices.push(DebugVertex {
+            position: to.into(),
+            color,
+        });
+    }
+
+    /// Draws edges of the axis aligned box
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: Color) {
+        let corner = |i: usize| {
+            Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        for i in 0..8 {
+            for axis in [1, 2, 4].iter() {
+                if i & axis == 0 {
+                    self.line(corner(i), corner(i | axis), color);
+                }
+            }
+        }
+    }
+
+    /// Draws circles of the sphere in the planes of the axes
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: Color) {
+        let planes = [
+            (Vec3::unit_x(), Vec3::unit_y()),
+            (Vec3::unit_x(), Vec3::unit_z()),
+            (Vec3::unit_y(), Vec3::unit_z()),
+        ];
+        for (u, v) in planes.iter() {
+            let point = |i: usize| {
+                let angle = 2.0 * PI * i as f32 / CIRCLE_SEGMENTS as f32;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            for i in 0..CIRCLE_SEGMENTS {
+                self.line(point(i), point(i + 1), color);
+            }
+        }
+    }
+
+    /// Draws X, Y and Z axes of the transformation in red, green and blue
+    pub fn axes(&mut self, transform: &Transform, size: f32) {
+        let matrix = transform.matrix();
+        let origin = transform.translate;
+        let axes = [
+            (Vec4::new(size, 0.0, 0.0, 1.0), Color::red()),
+            (Vec4::new(0.0, size, 0.0, 1.0), Color::green()),
+            (Vec4::new(0.0, 0.0, size, 1.0), Color::blue()),
+        ];
+        for (axis, color) in axes.iter() {
+            self.line(origin, (matrix * axis).truncate(), *color);
+        }
+    }
+
+    /// Draws camera facing text centered at the position with the letters of the `size` height
+    ///
+    /// Letters are drawn by the segments, only digits, latin letters regardless of their case
+    /// and a few punctuation marks are supported.
+    pub fn text3d(&mut self, position: Vec3, text: &str, size: f32, color: Color) {
+        if !self.enabled {
+            return;
+        }
+        self.batch().labels.push(Label {
+            position,
+            text: String::from(text),
+            size,
+            color,
+        });
+    }
+
+    fn batch(&mut self) -> &mut Batch {
+        &mut self.batches[if self.depth_test { 0 } else { 1 }]
+    }
+}
+
+/// Debug drawing startup system
+pub fn startup(mut assets: Mut<Assets>, renderer: Const<Renderer>) {
+    let mut shader = Shader {
+        name: String::from(PIPELINE_LABEL),
+        code: String::from(include_str!("shaders/debug_draw.wgsl")),
+        ..Default::default()
+    };
+    shader.load(&renderer);
+    assets.store_as(shader, PIPELINE_LABEL);
+}
+
+/// Debug drawing rendering system
+pub fn render(
+    mut debug: Mut<DebugDraw>,
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    camera: Const<Camera>,
+) {
+    let DebugDraw {
+        uniform, batches, ..
+    } = &mut *debug;
+
+    let (proj, view) = match (camera.proj, camera.view) {
+        (Some(proj), Some(view)) => (proj, view),
+        _ => {
+            for batch in batches.iter_mut() {
+                batch.clear();
+            }
+            return;
+        }
+    };
+    let proj_view = proj * view;
+    let proj_view_raw = AsRef::<[f32; 16]>::as_ref(&proj_view);
+    renderer.load_uniform_buffer(uniform, bytemuck::cast_slice(proj_view_raw));
+
+    // labels face the camera
+    let right = Vec3::new(view.x.x, view.y.x, view.z.x);
+    let up = Vec3::new(view.x.y, view.y.y, view.z.y);
+
+    for (index, batch) in batches.iter_mut().enumerate() {
+        let labels = std::mem::take(&mut batch.labels);
+        for label in labels.iter() {
+            let color = label.color.into();
+            for line in label_lines(label, right, up) {
+                for point in line.iter() {
+                    batch.vertices.push(DebugVertex {
+                        position: (*point).into(),
+                        color,
+                    });
+                }
+            }
+        }
+        batch.draw(&mut renderer, &assets, uniform, index == 0);
+        batch.clear();
+    }
+}
+
+struct Batch {
+    vertices: Vec<DebugVertex>,
+    labels: Vec<Label>,
+    /// Lines mesh, which vertex buffer grows with the number of the vertices
+    mesh: Mesh,
+    capacity: usize,
+    pipeline: Pipeline,
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self {
+            vertices: Vec::new(),
+            labels: Vec::new(),
+            mesh: Mesh {
+                stride: std::mem::size_of::<DebugVertex>(),
+                layout: vec![AttributeFormat::Float32x3, AttributeFormat::Float32x4],
+                ..Default::default()
+            },
+            capacity: 0,
+            pipeline: Pipeline::default(),
+        }
+    }
+}
+
+impl Batch {
+    fn draw(
+        &mut self,
+        renderer: &mut Renderer,
+        assets: &Assets,
+        uniform: &UniformBuffer,
+        depth_test: bool,
+    ) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        let pipeline = &mut self.pipeline;
+        if pipeline.shader.is_null() {
+            pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+        }
+        if !pipeline.cycle(renderer) {
+            return;
+        }
+
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            self.mesh.vertex_buffer.empty();
+        }
+        let mut data = Vec::from(bytemuck::cast_slice::<DebugVertex, u8>(&self.vertices));
+        data.resize(self.capacity * std::mem::size_of::<DebugVertex>(), 0);
+        renderer.load_vertex_buffer(
+            &mut self.mesh.vertex_buffer,
+            &data,
+            None,
+            self.vertices.len(),
+        );
+
+        if !pipeline.ready() {
+            let shader = match assets.get(pipeline.shader) {
+                Some(shader) if shader.loaded() => shader,
+                _ => return,
+            };
+            // primitives on top of the scene are drawn after the transparent ones
+            pipeline.options.depth = f32::MIN;
+            renderer.bind(
+                pipeline,
+                PipelineLayout {
+                    label: String::from(PIPELINE_LABEL),
+                    mesh: Some(&self.mesh),
+                    shader,
+                    bindings: &[BindGroup::new(
+                        "Globals",
+                        vec![Binding::Uniform("Camera", Stage::Vertex, uniform)],
+                    )],
+                    vertex_buffers: &[],
+                    push_constants: None,
+                    options: PipelineOptions {
+                        depth_buffer_mode: if depth_test {
+                            DepthBufferMode::Read
+                        } else {
+                            DepthBufferMode::Disabled
+                        },
+                        disable_cull_mode: true,
+                        topology: PrimitiveTopology::LineList,
+                        transparent: !depth_test,
+                        ..PipelineOptions::blended(BlendMode::Alpha)
+                    },
+                },
+            );
+        }
+
+        renderer.run(pipeline, &self.mesh);
+    }
+
+    fn clear(&mut self) {
+        self.vertices.clear();
+        self.labels.clear();
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for DebugVertex {}
+unsafe impl bytemuck::Pod for DebugVertex {}
+
+struct Label {
+    position: Vec3,
+    text: String,
+    size: f32,
+    color: Color,
+}
+
+/// Returns the mask of the [`SEGMENTS`] of the character glyph
+fn glyph(character: char) -> u16 {
+    let segments: &[usize] = match character.to_ascii_uppercase() {
+        '0' => &[0, 1, 2, 3, 4, 5, 10, 11],
+        '1' => &[1, 2],
+        '2' => &[0, 1, 3, 4, 6, 7],
+        '3' => &[0, 1, 2, 3, 7],
+        '4' => &[1, 2, 5, 6, 7],
+        '5' | 'S' => &[0, 2, 3, 5, 6, 7],
+        '6' => &[0, 2, 3, 4, 5, 6, 7],
+        '7' => &[0, 1, 2],
+        '8' => &[0, 1, 2, 3, 4, 5, 6, 7],
+        '9' => &[0, 1, 2, 3, 5, 6, 7],
+        'A' => &[0, 1, 2, 4, 5, 6, 7],
+        'B' => &[0, 1, 2, 3, 7, 9, 12],
+        'C' => &[0, 3, 4, 5],
+        'D' => &[0, 1, 2, 3, 9, 12],
+        'E' => &[0, 3, 4, 5, 6],
+        'F' => &[0, 4, 5, 6],
+        'G' => &[0, 2, 3, 4, 5, 7],
+        'H' => &[1, 2, 4, 5, 6, 7],
+        'I' => &[0, 3, 9, 12],
+        'J' => &[1, 2, 3, 4],
+        'K' => &[4, 5, 6, 10, 13],
+        'L' => &[3, 4, 5],
+        'M' => &[1, 2, 4, 5, 8, 10],
+        'N' => &[1, 2, 4, 5, 8, 13],
+        'O' => &[0, 1, 2, 3, 4, 5],
+        'P' => &[0, 1, 4, 5, 6, 7],
+        'Q' => &[0, 1, 2, 3, 4, 5, 13],
+        'R' => &[0, 1, 4, 5, 6, 7, 13],
+        'T' => &[0, 9, 12],
+        'U' => &[1, 2, 3, 4, 5],
+        'V' => &[4, 5, 10, 11],
+        'W' => &[1, 2, 4, 5, 11, 13],
+        'X' => &[8, 10, 11, 13],
+        'Y' => &[8, 10, 12],
+        'Z' => &[0, 3, 10, 11],
+        '-' => &[6, 7],
+        '+' => &[6, 7, 9, 12],
+        '=' => &[3, 6, 7],
+        '_' => &[3],
+        '/' => &[10, 11],
+        '.' | ',' => &[14],
+        _ => &[],
+    };
+    segments.iter().fold(0, |mask, segment| mask | 1 << segment)
+}
+
+/// Returns lines of the label glyphs in the plane of the `right` and `up` directions
+fn label_lines(label: &Label, right: Vec3, up: Vec3) -> impl Iterator<Item = [Vec3; 2]> + '_ {
+    // cell of 1 x 2 units with the half unit spacing
+    let scale = label.size / 2.0;
+    let count = label.text.chars().count() as f32;
+    let width = (count * 1.5 - 0.5).max(0.0) * scale;
+    let start = label.position - right * (width / 2.0) - up * (label.size / 2.0);
+    label
+        .text
+        .chars()
+        .enumerate()
+        .flat_map(move |(index, character)| {
+            let origin = start + right * (index as f32 * 1.5 * scale);
+            let mask = glyph(character);
+            SEGMENTS
+                .iter()
+                .enumerate()
+                .filter(move |(segment, _)| mask & (1 << segment) != 0)
+                .map(move |(_, [x0, y0, x1, y1])| {
+                    [
+                        origin + right * (x0 * scale) + up * (y0 * scale),
+                        origin + right * (x1 * scale) + up * (y1 * scale),
+                    ]
+                })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitives_are_batched_as_lines() {
+        let mut debug = DebugDraw::default();
+        debug.aabb(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Color::white(),
+        );
+        debug.depth_test = false;
+        debug.sphere(Vec3::new(0.0, 0.0, 0.0), 1.0, Color::white());
+        debug.axes(&Transform::default(), 1.0);
+        assert_eq!(debug.batches[0].vertices.len(), 12 * 2);
+        assert_eq!(
+            debug.batches[1].vertices.len(),
+            (CIRCLE_SEGMENTS * 3 + 3) * 2
+        );
+
+        debug.enabled = false;
+        debug.line(Vec3::unit_x(), Vec3::unit_y(), Color::white());
+        assert_eq!(
+            debug.batches[1].vertices.len(),
+            (CIRCLE_SEGMENTS * 3 + 3) * 2
+        );
+    }
+
+    #[test]
+    fn labels_are_drawn_by_segments() {
+        assert_eq!(glyph('8').count_ones(), 8);
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph(' '), 0);
+
+        let label = Label {
+            position: Vec3::new(0.0, 0.0, 0.0),
+            text: String::from("1 1"),
+            size: 2.0,
+            color: Color::white(),
+        };
+        let lines = label_lines(&label, Vec3::unit_x(), Vec3::unit_y()).collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        // text is centered at the position
+        assert_eq!(lines[0][0], Vec3::new(-1.0, 1.0, 0.0));
+        assert_eq!(lines[3][1], Vec3::new(2.0, -1.0, 0.0));
+    }
+}
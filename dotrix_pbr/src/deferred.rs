@@ -0,0 +1,336 @@
+//! Deferred rendering path
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    Attachment, BindGroup, Binding, DepthBufferMode, DepthCompare, PipelineLayout, PipelineOptions,
+    RenderPass, Stage, TextureFormat, UniformBuffer, DEPTH, DEPTH_PREPASS, MAIN_PASS,
+};
+use dotrix_core::{Assets, Camera, Color, Globals, Pipeline, Renderer};
+
+use dotrix_math::{Mat4, SquareMatrix};
+
+use crate::tone_mapping::FULLSCREEN_TRIANGLE;
+use crate::{ClusteredLights, Environment, Lights, Shadows, Ssao};
+
+const PIPELINE_LABEL: &str = "dotrix::deferred::lighting";
+
+/// Name of the render graph pass rendering the surfaces of the models into the G-buffer
+pub const GBUFFER_PASS: &str = "gbuffer";
+
+/// Name of the G-buffer attachment with the linear albedo and the ambient occlusion in alpha
+pub const GBUFFER_ALBEDO: &str = "gbuffer_albedo";
+
+/// Name of the G-buffer attachment with the world space normals
+pub const GBUFFER_NORMAL: &str = "gbuffer_normal";
+
+/// Name of the G-buffer attachment with the roughness and metallic in the red and green
+pub const GBUFFER_MATERIAL: &str = "gbuffer_material";
+
+/// Name of the G-buffer depth attachment
+pub const GBUFFER_DEPTH: &str = "gbuffer_depth";
+
+/// Name of the render graph pass lighting the G-buffer
+pub const LIGHTING_PASS: &str = "deferred_lighting";
+
+/// Path of the solid and skeletal models through the renderer
+///
+/// The path is selected once per application, before it runs:
+/// `app.add_service(RenderingPath::Deferred)` after the PBR extension.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderingPath {
+    /// Models are lit by their own pipelines in the main pass
+    #[default]
+    Forward,
+    /// Models render their surfaces into the G-buffer in the [`GBUFFER_PASS`] and the
+    /// [`LIGHTING_PASS`] lights every pixel once, so the cost of the lights does not grow with
+    /// the overdraw. The lighting pass restores the depth, so the pipelines of the main pass are
+    /// still occluded by the models.
+    Deferred,
+}
+
+impl RenderingPath {
+    /// Integrates the fragment stage of the path into the shader of a lit model
+    ///
+    /// The `source` shader code must contain `{{ include(fragment) }}` label after the
+    /// `fn calculate_surface(in: VertexOutput) -> Surface` function. The deferred fragment
+    /// stage does not need the lights, shadows, clusters and ambient occlusion, so their labels
+    /// are removed.
+    pub fn add_to_shader(self, source: &str) -> String {
+        match self {
+            RenderingPath::Forward => source.replace(
+                "{{ include(fragment) }}",
+                include_str!("shaders/forward.inc.wgsl"),
+            ),
+            RenderingPath::Deferred => {
+                let mut code = source.replace(
+                    "{{ include(fragment) }}",
+                    include_str!("shaders/gbuffer.inc.wgsl"),
+                );
+                for label in [
+                    "{{ include(ssao) }}",
+                    "{{ include(shadow) }}",
+                    "{{ include(clusters) }}",
+                    "{{ include(environment) }}",
+                    "{{ include(light) }}",
+                ]
+                .iter()
+                {
+                    code = code.replace(label, "");
+                }
+                code
+            }
+        }
+    }
+}
+
+/// Lighting of the G-buffer service
+pub struct DeferredLighting {
+    /// Lighting uniform buffer
+    pub uniform: UniformBuffer,
+    pipeline: Pipeline,
+    generation: Option<usize>,
+}
+
+impl Default for DeferredLighting {
+    fn default() -> Self {
+        Self {
+            uniform: UniformBuffer::default(),
+            pipeline: Pipeline::default().with_pass(LIGHTING_PASS),
+            generation: None,
+        }
+    }
+}
+
+/// Deferred rendering startup system
+pub fn startup(path: Const<RenderingPath>, mut renderer: Mut<Renderer>, mut assets: Mut<Assets>) {
+    if *path != RenderingPath::Deferred {
+        return;
+    }
+
+    let graph = renderer.graph_mut();
+    graph.add_attachment(
+        GBUFFER_ALBEDO,
+        Attachment::color(TextureFormat::rgba_u8norm()),
+    );
+    graph.add_attachment(GBUFFER_NORMAL, Attachment::color(TextureFormat::rgba_f16()));
+    graph.add_attachment(
+        GBUFFER_MATERIAL,
+        Attachment::color(TextureFormat::rgba_u8norm()),
+    );
+    graph.add_attachment(GBUFFER_DEPTH, Attachment::depth());
+    graph.add_pass(
+        RenderPass::new(GBUFFER_PASS)
+            .writes(GBUFFER_ALBEDO)
+            .writes(GBUFFER_NORMAL)
+            .writes(GBUFFER_MATERIAL)
+            .depth(GBUFFER_DEPTH)
+            .clear(Color::black()),
+    );
+
+    // the lighting pass clears the targets of the main pass and samples the same attachments
+    let main = graph
+        .pass_mut(MAIN_PASS)
+        .expect("Main pass must be in the render graph");
+    let mut lighting = RenderPass::new(LIGHTING_PASS);
+    lighting.reads = main.reads.clone();
+    lighting.consumes = main.consumes.clone();
+    lighting.writes = main.writes.clone();
+    lighting.depth = Some(String::from(DEPTH));
+    lighting.clear_color = main.clear_color.take();
+    lighting.clear_depth = true;
+    main.clear_depth = false;
+    for gbuffer in [
+        GBUFFER_ALBEDO,
+        GBUFFER_NORMAL,
+        GBUFFER_MATERIAL,
+        GBUFFER_DEPTH,
+    ]
+    .iter()
+    {
+        lighting.reads.push(String::from(*gbuffer));
+    }
+    graph.add_pass_before(MAIN_PASS, lighting);
+    if let Some(prepass) = graph.pass_mut(DEPTH_PREPASS) {
+        prepass.clear_depth = false;
+    }
+
+    let mut shader = Shader {
+        name: String::from(PIPELINE_LABEL),
+        code: Environment::add_to_shader(
+            &ClusteredLights::add_to_shader(
+                &Ssao::add_to_shader(
+                    &Shadows::add_to_shader(
+                        &Lights::add_to_shader(
+                            include_str!("shaders/deferred_lighting.wgsl"),
+                            0,
+                            5,
+                        ),
+                        0,
+                        6,
+                    ),
+                    0,
+                    13,
+                ),
+                0,
+                14,
+            ),
+            0,
+            17,
+        ),
+        ..Default::default()
+    };
+    shader.load(&renderer);
+    assets.store_as(shader, PIPELINE_LABEL);
+}
+
+/// Deferred lighting rendering system
+pub fn render(
+    mut lighting: Mut<DeferredLighting>,
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    camera: Const<Camera>,
+    ssao: Const<Ssao>,
+    clusters: Const<ClusteredLights>,
+    globals: Const<Globals>,
+) {
+    // the pass is set up by the deferred path only
+    if renderer.graph().pass(LIGHTING_PASS).is_none() {
+        return;
+    }
+
+    let proj_view = match (camera.proj, camera.view) {
+        (Some(proj), Some(view)) => proj * view,
+        _ => return,
+    };
+    let uniform = Uniform {
+        inverse_proj_view: proj_view.invert().unwrap_or_else(Mat4::identity).into(),
+    };
+    renderer.load_uniform_buffer(&mut lighting.uniform, bytemuck::cast_slice(&[uniform]));
+
+    // the G-buffer and shadow maps are recreated on resize
+    let generation = renderer.attachments_generation();
+    let environment_changed = globals
+        .get::<Environment>()
+        .map(|environment| environment.changed())
+        .unwrap_or(false);
+    if lighting.generation != Some(generation) || environment_changed {
+        lighting.generation = Some(generation);
+        lighting.pipeline.bindings.unload();
+    }
+
+    let DeferredLighting {
+        uniform, pipeline, ..
+    } = &mut *lighting;
+    if pipeline.shader.is_null() {
+        pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+    }
+    if !pipeline.cycle(&renderer) {
+        return;
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    if !pipeline.ready() {
+        let shader = match assets.get(pipeline.shader) {
+            Some(shader) if shader.loaded() => shader,
+            _ => return,
+        };
+        let lights = match globals.get::<Lights>() {
+            Some(lights) => lights,
+            None => return,
+        };
+        let shadows = match globals.get::<Shadows>() {
+            Some(shadows) => shadows,
+            None => return,
+        };
+        let environment = match globals.get::<Environment>() {
+            Some(environment) => environment,
+            None => return,
+        };
+        let gbuffer = [
+            GBUFFER_ALBEDO,
+            GBUFFER_NORMAL,
+            GBUFFER_MATERIAL,
+            GBUFFER_DEPTH,
+        ];
+        if !gbuffer
+            .iter()
+            .all(|name| renderer.attachment(name).is_some())
+            || !shadows.ready(&renderer)
+            || !ssao.ready(&renderer)
+            || !clusters.ready()
+        {
+            return;
+        }
+
+        renderer.bind(
+            pipeline,
+            PipelineLayout {
+                label: String::from(PIPELINE_LABEL),
+                mesh: Some(mesh),
+                shader,
+                bindings: &[BindGroup::new(
+                    "Globals",
+                    vec![
+                        Binding::Uniform("Deferred", Stage::Fragment, uniform),
+                        Binding::Attachment("Albedo", Stage::Fragment, GBUFFER_ALBEDO),
+                        Binding::Attachment("Normal", Stage::Fragment, GBUFFER_NORMAL),
+                        Binding::Attachment("Material", Stage::Fragment, GBUFFER_MATERIAL),
+                        Binding::Attachment("Depth", Stage::Fragment, GBUFFER_DEPTH),
+                        Binding::Uniform("Lights", Stage::Fragment, &lights.uniform),
+                    ]
+                    .into_iter()
+                    .chain(shadows.bindings())
+                    .chain(Some(ssao.binding()))
+                    .chain(clusters.bindings())
+                    .chain(environment.bindings())
+                    .collect(),
+                )],
+                vertex_buffers: &[],
+                push_constants: None,
+                // the depth of the G-buffer is written as it is
+                options: PipelineOptions {
+                    depth_buffer_mode: DepthBufferMode::Write,
+                    depth_compare: DepthCompare::Always,
+                    disable_cull_mode: true,
+                    ..Default::default()
+                },
+            },
+        );
+    }
+
+    renderer.run(pipeline, mesh);
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    inverse_proj_view: [[f32; 4]; 4],
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deferred_shaders_have_no_lighting() {
+        let source = "{{ include(ssao) }}{{ include(shadow) }}{{ include(light) }}\
+            {{ include(fragment) }}";
+        let deferred = RenderingPath::Deferred.add_to_shader(source);
+        assert!(!deferred.contains("{{"));
+        assert!(deferred.contains("GBufferOutput"));
+
+        let forward = RenderingPath::Forward.add_to_shader(source);
+        assert!(forward.contains("{{ include(light) }}"));
+        assert!(forward.contains("calculate_shadowed_lighting"));
+    }
+}
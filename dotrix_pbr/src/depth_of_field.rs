@@ -0,0 +1,243 @@
+//! Depth of field
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    Attachment, BindGroup, Binding, DepthBufferMode, PipelineLayout, PipelineOptions, RenderPass,
+    Sampler, Stage, TextureFormat, UniformBuffer,
+};
+use dotrix_core::{Assets, Camera, Globals, Pipeline, Renderer};
+
+use dotrix_math::{Mat4, SquareMatrix, Vec2};
+
+use crate::ssao::SSAO_DEPTH;
+use crate::tone_mapping::{ToneMapping, FULLSCREEN_TRIANGLE, HDR_TARGET, TONE_MAPPING_PASS};
+
+const PIPELINE_LABEL: &str = "dotrix::depth_of_field";
+
+/// Maximal number of the blur samples per pixel
+pub const MAX_DOF_SAMPLES: u32 = 64;
+
+/// Name of the render graph pass and attachment with the HDR scene blurred out of focus
+pub const DOF_TARGET: &str = "depth_of_field";
+
+/// Depth of field service
+///
+/// Pixels of the solid and skeletal models are blurred by the circle of confusion, that grows
+/// with their distance from the focus and the `aperture`. The focus is either set by the
+/// `focus_distance` or sampled from the depth at the `focus_point`, when `autofocus` is on.
+pub struct DepthOfField {
+    /// Depth of field is enabled
+    pub enabled: bool,
+    /// Distance from the camera to the sharp plane in world units
+    pub focus_distance: f32,
+    /// Size of the lens aperture, larger values make the focus area narrower
+    pub aperture: f32,
+    /// Maximal radius of the blur in pixels
+    pub max_blur: f32,
+    /// Number of the blur samples per pixel, up to [`MAX_DOF_SAMPLES`]
+    pub samples: u32,
+    /// Focus distance is taken from the depth at the `focus_point`
+    pub autofocus: bool,
+    /// Point of the screen in the range 0..1, the autofocus looks at, the center by default
+    pub focus_point: Vec2,
+    /// Depth of field uniform buffer
+    pub uniform: UniformBuffer,
+    source: &'static str,
+    sampler: Sampler,
+    pipeline: Pipeline,
+    generation: Option<usize>,
+}
+
+impl Default for DepthOfField {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            focus_distance: 10.0,
+            aperture: 0.5,
+            max_blur: 8.0,
+            samples: 32,
+            autofocus: false,
+            focus_point: Vec2::new(0.5, 0.5),
+            uniform: UniformBuffer::default(),
+            source: HDR_TARGET,
+            sampler: Sampler::linear_clamp(),
+            pipeline: Pipeline::default().with_pass(DOF_TARGET),
+            generation: None,
+        }
+    }
+}
+
+/// Depth of field startup system
+///
+/// The scene is blurred after the anti-aliasing, just before the tone mapping.
+pub fn startup(
+    mut dof: Mut<DepthOfField>,
+    mut renderer: Mut<Renderer>,
+    mut assets: Mut<Assets>,
+    mut globals: Mut<Globals>,
+) {
+    if let Some(tone_mapping) = globals.get_mut::<ToneMapping>() {
+        dof.source = tone_mapping.source;
+        tone_mapping.source = DOF_TARGET;
+    }
+
+    let graph = renderer.graph_mut();
+    graph.add_attachment(DOF_TARGET, Attachment::color(TextureFormat::rgba_f16()));
+    graph.add_pass(
+        RenderPass::new(DOF_TARGET)
+            .reads(dof.source)
+            .reads(SSAO_DEPTH)
+            .writes(DOF_TARGET),
+    );
+    if let Some(tone_mapping) = graph.pass_mut(TONE_MAPPING_PASS) {
+        for read in tone_mapping.reads.iter_mut().filter(|r| *r == dof.source) {
+            *read = String::from(DOF_TARGET);
+        }
+    }
+
+    let mut shader = Shader {
+        name: String::from(PIPELINE_LABEL),
+        code: String::from(include_str!("shaders/depth_of_field.wgsl")),
+        ..Default::default()
+    };
+    shader.load(&renderer);
+    assets.store_as(shader, PIPELINE_LABEL);
+}
+
+/// Depth of field rendering system
+///
+/// Disabled depth of field copies the scene, so the tone mapping source stays the same.
+pub fn render(
+    mut dof: Mut<DepthOfField>,
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    camera: Const<Camera>,
+) {
+    let proj = camera.proj.unwrap_or_else(Mat4::identity);
+    let uniform = Uniform::new(&dof, proj);
+    renderer.load_uniform_buffer(&mut dof.uniform, bytemuck::cast_slice(&[uniform]));
+    renderer.load_sampler(&mut dof.sampler);
+
+    // the scene and depth textures are recreated on resize
+    let generation = renderer.attachments_generation();
+    if dof.generation != Some(generation) {
+        dof.generation = Some(generation);
+        dof.pipeline.bindings.unload();
+    }
+
+    let DepthOfField {
+        uniform,
+        source,
+        sampler,
+        pipeline,
+        ..
+    } = &mut *dof;
+    if pipeline.shader.is_null() {
+        pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+    }
+    let ready = [*source, SSAO_DEPTH]
+        .iter()
+        .all(|name| renderer.attachment(name).is_some());
+    if !pipeline.cycle(&renderer) || !ready {
+        return;
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    if !pipeline.ready() {
+        let shader = match assets.get(pipeline.shader) {
+            Some(shader) if shader.loaded() => shader,
+            _ => return,
+        };
+        renderer.bind(
+            pipeline,
+            PipelineLayout {
+                label: String::from(PIPELINE_LABEL),
+                mesh: Some(mesh),
+                shader,
+                bindings: &[BindGroup::new(
+                    "Globals",
+                    vec![
+                        Binding::Uniform("DepthOfField", Stage::Fragment, uniform),
+                        Binding::Attachment("Scene", Stage::Fragment, source),
+                        Binding::Attachment("Depth", Stage::Fragment, SSAO_DEPTH),
+                        Binding::Sampler("Sampler", Stage::Fragment, sampler),
+                    ],
+                )],
+                vertex_buffers: &[],
+                push_constants: None,
+                options: PipelineOptions {
+                    depth_buffer_mode: DepthBufferMode::Disabled,
+                    disable_cull_mode: true,
+                    ..Default::default()
+                },
+            },
+        );
+    }
+
+    renderer.run(pipeline, mesh);
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    inverse_proj: [[f32; 4]; 4],
+    /// Focus distance, aperture, maximal blur and autofocus
+    params: [f32; 4],
+    /// Focus point and number of samples
+    focus: [f32; 4],
+}
+
+impl Uniform {
+    fn new(dof: &DepthOfField, proj: Mat4) -> Self {
+        Self {
+            inverse_proj: proj.invert().unwrap_or_else(Mat4::identity).into(),
+            params: [
+                dof.focus_distance,
+                if dof.enabled { dof.aperture } else { 0.0 },
+                dof.max_blur,
+                if dof.autofocus { 1.0 } else { 0.0 },
+            ],
+            focus: [
+                dof.focus_point.x,
+                dof.focus_point.y,
+                dof.samples.clamp(1, MAX_DOF_SAMPLES) as f32,
+                0.0,
+            ],
+        }
+    }
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_depth_of_field_has_no_aperture() {
+        let mut dof = DepthOfField {
+            samples: 1000,
+            autofocus: true,
+            ..Default::default()
+        };
+        let uniform = Uniform::new(&dof, Mat4::identity());
+        assert_eq!(uniform.params, [10.0, 0.0, 8.0, 1.0]);
+        assert_eq!(uniform.focus, [0.5, 0.5, MAX_DOF_SAMPLES as f32, 0.0]);
+
+        dof.enabled = true;
+        dof.samples = 0;
+        let uniform = Uniform::new(&dof, Mat4::from_scale(4.0));
+        assert_eq!(uniform.params[1], 0.5);
+        assert_eq!(uniform.focus[2], 1.0);
+        let inverse: [[f32; 4]; 4] = Mat4::from_scale(0.25).into();
+        assert_eq!(uniform.inverse_proj, inverse);
+    }
+}
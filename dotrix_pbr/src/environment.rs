@@ -0,0 +1,208 @@
+//! Image based lighting of the environment map
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{Binding, Sampler, Stage, TextureBuffer, UniformBuffer};
+use dotrix_core::{Assets, CubeMap, Globals, Renderer};
+
+/// Size of the irradiance cube map sides in pixels
+pub const IRRADIANCE_SIZE: u32 = 32;
+
+/// Size of the top mip level of the prefiltered cube map sides in pixels
+pub const PREFILTERED_SIZE: u32 = 128;
+
+/// Number of the prefiltered cube map mip levels, rougher surfaces reflect the lower ones
+pub const PREFILTERED_MIP_LEVELS: u32 = 5;
+
+/// Size of the BRDF lookup table in pixels
+pub const BRDF_LUT_SIZE: u32 = 128;
+
+/// Environment map (global)
+///
+/// Once the `map` is loaded, compute shaders convolve it into the irradiance cube map lighting
+/// the diffuse surfaces and the prefiltered cube map, whose mip levels are reflected by the
+/// surfaces of the growing roughness. Together with the BRDF lookup table, they light the
+/// pipelines including `{{ include(environment) }}`, see [`Environment::add_to_shader`].
+///
+/// The map is either made of the six cube sides or converted from an equirectangular HDR
+/// texture on GPU, so the same texture can be used by the skybox. Until the map is loaded,
+/// the environment is black.
+pub struct Environment {
+    /// Environment cube map
+    pub map: CubeMap,
+    /// Scale of the environment lighting
+    pub intensity: f32,
+    /// Environment uniform buffer
+    pub uniform: UniformBuffer,
+    irradiance: TextureBuffer,
+    prefiltered: TextureBuffer,
+    brdf_lut: TextureBuffer,
+    sampler: Sampler,
+    fallback: TextureBuffer,
+    loaded: bool,
+    changed: bool,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            map: CubeMap::default(),
+            intensity: 1.0,
+            uniform: UniformBuffer::default(),
+            irradiance: TextureBuffer::default(),
+            prefiltered: TextureBuffer::default(),
+            brdf_lut: TextureBuffer::default(),
+            sampler: Sampler::linear_clamp(),
+            fallback: TextureBuffer::default(),
+            loaded: false,
+            changed: false,
+        }
+    }
+}
+
+impl Environment {
+    /// Integrates image based lighting into shader
+    ///
+    /// The `source` shader code must contain `{{ include(environment) }}` label before the
+    /// `{{ include(light) }}` one, the uniform, irradiance and prefiltered cube maps, BRDF
+    /// lookup table and sampler take five bindings, starting from `binding`.
+    pub fn add_to_shader(source: &str, bind_group: usize, binding: usize) -> String {
+        let environment_code = include_str!("shaders/environment.inc.wgsl")
+            .replace("{{ bind_group }}", &bind_group.to_string())
+            .replace("{{ binding }}", &binding.to_string())
+            .replace("{{ irradiance_binding }}", &(binding + 1).to_string())
+            .replace("{{ prefiltered_binding }}", &(binding + 2).to_string())
+            .replace("{{ brdf_lut_binding }}", &(binding + 3).to_string())
+            .replace("{{ sampler_binding }}", &(binding + 4).to_string());
+        source.replace("{{ include(environment) }}", &environment_code)
+    }
+
+    /// Returns bindings of the uniform, lighting textures and sampler for lit pipelines
+    pub fn bindings(&self) -> Vec<Binding<'_>> {
+        let (irradiance, prefiltered) = if self.loaded {
+            (&self.irradiance, &self.prefiltered)
+        } else {
+            (&self.fallback, &self.fallback)
+        };
+        vec![
+            Binding::Uniform("Environment", Stage::Fragment, &self.uniform),
+            Binding::TextureCube("Irradiance", Stage::Fragment, irradiance),
+            Binding::TextureCube("Prefiltered", Stage::Fragment, prefiltered),
+            Binding::Texture("BrdfLut", Stage::Fragment, &self.brdf_lut),
+            Binding::Sampler("EnvironmentSampler", Stage::Fragment, &self.sampler),
+        ]
+    }
+
+    /// Returns true if the map is loaded
+    pub fn loaded(&self) -> bool {
+        self.loaded
+    }
+
+    /// Returns true if the lighting textures were replaced this frame, so pipelines have to be
+    /// rebound
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+}
+
+/// Environment startup system
+pub fn startup(renderer: Const<Renderer>, mut globals: Mut<Globals>) {
+    let mut environment = Environment::default();
+    let face: &[u8] = &[0, 0, 0, 0];
+    renderer.load_texture_buffer(&mut environment.fallback, 1, 1, &[face; 6]);
+    renderer.load_texture_buffer_from_compute(
+        &mut environment.brdf_lut,
+        &include_str!("shaders/brdf_lut.wgsl").replace(
+            "{{ include(importance_sample) }}",
+            include_str!("shaders/importance_sample.inc.wgsl"),
+        ),
+        BRDF_LUT_SIZE,
+        BRDF_LUT_SIZE,
+    );
+    renderer.load_sampler(&mut environment.sampler);
+    load_uniform(&renderer, &mut environment);
+    globals.set(environment);
+}
+
+/// Environment loading system
+///
+/// Lighting textures are computed, when the map gets loaded.
+pub fn load(renderer: Const<Renderer>, mut assets: Mut<Assets>, mut globals: Mut<Globals>) {
+    if let Some(environment) = globals.get_mut::<Environment>() {
+        let loaded = environment.map.load(&renderer, &mut assets);
+        environment.changed = loaded != environment.loaded;
+        environment.loaded = loaded;
+        if environment.changed && loaded {
+            renderer.load_cube_map_from_compute(
+                &mut environment.irradiance,
+                &environment.map.buffer,
+                &convolution_shader(include_str!("shaders/irradiance.wgsl")),
+                IRRADIANCE_SIZE,
+                1,
+            );
+            renderer.load_cube_map_from_compute(
+                &mut environment.prefiltered,
+                &environment.map.buffer,
+                &convolution_shader(include_str!("shaders/prefilter.wgsl")),
+                PREFILTERED_SIZE,
+                PREFILTERED_MIP_LEVELS,
+            );
+        }
+        load_uniform(&renderer, environment);
+    }
+}
+
+fn convolution_shader(source: &str) -> String {
+    source
+        .replace(
+            "{{ include(cube_face) }}",
+            include_str!("shaders/cube_face.inc.wgsl"),
+        )
+        .replace(
+            "{{ include(importance_sample) }}",
+            include_str!("shaders/importance_sample.inc.wgsl"),
+        )
+}
+
+fn load_uniform(renderer: &Renderer, environment: &mut Environment) {
+    let uniform = Uniform {
+        params: [
+            environment.intensity,
+            PREFILTERED_MIP_LEVELS as f32,
+            0.0,
+            0.0,
+        ],
+    };
+    renderer.load_uniform_buffer(&mut environment.uniform, bytemuck::cast_slice(&[uniform]));
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    /// Intensity and number of the prefiltered mip levels
+    params: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_takes_five_bindings() {
+        let code = Environment::add_to_shader("{{ include(environment) }}", 0, 14);
+        assert!(!code.contains("{{"));
+        assert!(code.contains("binding(14)"));
+        assert!(code.contains("binding(18)"));
+        assert!(code.contains("fn calculate_environment"));
+    }
+
+    #[test]
+    fn convolution_shaders_are_complete() {
+        let irradiance = convolution_shader(include_str!("shaders/irradiance.wgsl"));
+        let prefilter = convolution_shader(include_str!("shaders/prefilter.wgsl"));
+        assert!(!irradiance.contains("{{"));
+        assert!(!prefilter.contains("{{"));
+        assert!(prefilter.contains("fn importance_sample_ggx"));
+    }
+}
@@ -1,27 +1,151 @@
 //! Physically based rendering implementation
 
-use dotrix_core::ecs::System;
+use dotrix_core::ecs::{Priority, System};
 use dotrix_core::Application;
 
 mod light;
 mod material;
 mod model;
 
+/// Post-process anti-aliasing
+pub mod anti_aliasing;
+
+/// Bloom of the bright HDR colors
+pub mod bloom;
+
+/// Clustered forward lighting
+pub mod clustered;
+
+/// Immediate mode debug drawing
+pub mod debug_draw;
+
+/// Deferred rendering path
+pub mod deferred;
+
+/// Depth of field
+pub mod depth_of_field;
+
+/// Image based lighting of the environment map
+pub mod environment;
+
+/// Motion blur
+pub mod motion_blur;
+
+/// GPU occlusion culling
+pub mod occlusion;
+
+/// Order-independent transparency
+pub mod oit;
+
+/// GPU particles
+pub mod particles;
+
+/// Post-processing chain of the custom effects
+pub mod post_process;
+
+/// Directional, point and spot light shadows
+pub mod shadow;
+
 /// Solid models rendering
 pub mod solid;
 
 /// Skeletal models rendering
 pub mod skeletal;
 
+/// Screen-space ambient occlusion
+pub mod ssao;
+
+/// Screen-space reflections
+pub mod ssr;
+
+/// HDR rendering and tone mapping
+pub mod tone_mapping;
+
+/// Velocity buffer
+pub mod velocity;
+
+pub use anti_aliasing::{AntiAliasing, Fxaa, Taa};
+pub use bloom::Bloom;
+pub use clustered::ClusteredLights;
+pub use debug_draw::DebugDraw;
+pub use deferred::{DeferredLighting, RenderingPath};
+pub use depth_of_field::DepthOfField;
+pub use environment::Environment;
 pub use light::{Light, Lights};
 pub use material::Material;
 pub use model::Model;
+pub use motion_blur::MotionBlur;
+pub use occlusion::{OcclusionBatch, OcclusionCulling};
+pub use oit::Oit;
+pub use particles::{ParticleEmitter, Particles};
+pub use post_process::{PostEffect, PostProcess};
+pub use shadow::Shadows;
+pub use ssao::Ssao;
+pub use ssr::Ssr;
+pub use tone_mapping::{ToneMapping, ToneMappingOperator};
+pub use velocity::Velocity;
 
 /// Enables PBR Dotrix Extension
 pub fn extension(app: &mut Application) {
     app.add_system(System::from(material::startup));
     app.add_system(System::from(light::startup));
+    app.add_system(System::from(shadow::startup));
+    // shadow slots of the lights are selected before the lights are loaded
+    app.add_system(System::from(shadow::load));
     app.add_system(System::from(light::load));
+    app.add_system(System::from(environment::startup));
+    app.add_system(System::from(environment::load));
+    app.add_system(System::from(clustered::startup));
+    app.add_system(System::from(clustered::compute));
+    app.add_system(System::from(tone_mapping::startup));
+    app.add_system(System::from(bloom::startup));
+    app.add_system(System::from(bloom::render).with(Priority::Low));
+    app.add_system(System::from(ssao::startup));
+    app.add_system(System::from(ssao::render));
+    // models render their motion together with the depth of the ambient occlusion
+    app.add_system(System::from(velocity::startup));
+    app.add_system(System::from(velocity::render));
+    // the lighting pass copies the main pass, once the other passes set it up
+    app.add_system(System::from(deferred::startup));
+    app.add_system(System::from(deferred::render));
+    app.add_system(System::from(ssr::startup));
+    // particles are simulated before and blended after the opaque models of the main pass
+    app.add_system(System::from(particles::startup));
+    app.add_system(System::from(particles::simulate));
+    app.add_system(System::from(particles::render).with(Priority::Low));
+    // objects are culled by the depth of the models before the main pass
+    app.add_system(System::from(occlusion::startup));
+    app.add_system(System::from(occlusion::compute));
+    app.add_system(System::from(oit::startup));
+    app.add_system(System::from(oit::render).with(Priority::Low));
+    app.add_system(System::from(debug_draw::startup));
+    app.add_system(System::from(debug_draw::render).with(Priority::Low));
+    app.add_system(System::from(ssr::render).with(Priority::Low));
+    // the tone mapping pass is set up before the anti-aliasing takes its source or target
+    app.add_system(System::from(anti_aliasing::startup));
+    app.add_system(System::from(anti_aliasing::render_taa).with(Priority::Low));
+    app.add_system(System::from(anti_aliasing::render_fxaa).with(Priority::Low));
+    app.add_system(System::from(motion_blur::startup));
+    app.add_system(System::from(motion_blur::render).with(Priority::Low));
+    app.add_system(System::from(depth_of_field::startup));
+    app.add_system(System::from(depth_of_field::render).with(Priority::Low));
+    app.add_system(System::from(tone_mapping::render).with(Priority::Low));
+    app.add_system(System::from(post_process::render).with(Priority::Low));
+    app.add_service(Bloom::default());
+    app.add_service(Ssao::default());
+    app.add_service(ClusteredLights::default());
+    app.add_service(RenderingPath::default());
+    app.add_service(DeferredLighting::default());
+    app.add_service(Ssr::default());
+    app.add_service(AntiAliasing::default());
+    app.add_service(Fxaa::default());
+    app.add_service(Taa::default());
+    app.add_service(MotionBlur::default());
+    app.add_service(DepthOfField::default());
+    app.add_service(Particles::default());
+    app.add_service(Oit::default());
+    app.add_service(DebugDraw::default());
+    app.add_service(PostProcess::default());
 
     solid::extension(app);
     skeletal::extension(app);
@@ -5,7 +5,9 @@ use dotrix_core::{Camera, Color, Globals, Renderer, World};
 
 use dotrix_math::Vec3;
 
-const MAX_LIGHTS: usize = 10;
+use crate::shadow::{LocalLight, Shadows};
+
+pub(crate) const MAX_LIGHTS: usize = 10;
 
 /// Light component of different types and settings
 pub enum Light {
@@ -130,14 +132,44 @@ impl Lights {
     /// Integrates light support into shader
     /// The `source` shader code must contain `{{ include(light) }}` label and then
     /// `let light_color = calculate_light(world_position, normal);` can be called
+    ///
+    /// Point and spot lights are shadowed only if the shader also includes shadows, which
+    /// must go before the light. If the shader includes clusters, point and spot lights are
+    /// taken from the [`crate::ClusteredLights`] instead of the uniform. The
+    /// [`crate::Environment`] is reflected only if the shader includes it before the light.
     pub fn add_to_shader(source: &str, bind_group: usize, binding: usize) -> String {
         let bind_group = format!("{:?}", bind_group);
         let binding = format!("{:?}", binding);
         let lights_count = format!("{:?}u", MAX_LIGHTS);
+        let local_shadow = if source.contains("{{ include(shadow) }}")
+            || source.contains("fn calculate_local_shadow")
+        {
+            ""
+        } else {
+            LOCAL_SHADOW_STUB
+        };
+
+        let clustered_lights =
+            if source.contains("{{ include(clusters) }}") || source.contains("fn cluster_offset") {
+                include_str!("shaders/clustered_light.inc.wgsl")
+            } else {
+                CLUSTERED_LIGHTS_STUB
+            };
+
+        let environment = if source.contains("{{ include(environment) }}")
+            || source.contains("fn calculate_environment")
+        {
+            ""
+        } else {
+            ENVIRONMENT_STUB
+        };
 
         let light_code = include_str!("shaders/light.inc.wgsl");
 
         let light_code = str::replace(light_code, "{{ max_lights_count }}", &lights_count)
+            .replace("{{ local_shadow }}", local_shadow)
+            .replace("{{ clustered_lights }}", clustered_lights)
+            .replace("{{ environment }}", environment)
             .replace("{{ bind_group }}", &bind_group)
             .replace("{{ binding }}", &binding);
 
@@ -145,6 +177,41 @@ impl Lights {
     }
 }
 
+/// Lights of shaders without shadows are never shadowed
+const LOCAL_SHADOW_STUB: &str = "fn calculate_local_shadow(slot: f32, position: vec3<f32>) -> f32 {
+    return 1.0;
+}
+";
+
+/// Shaders without clusters take point and spot lights from the uniform
+const CLUSTERED_LIGHTS_STUB: &str = "let CLUSTERED_LIGHTS: bool = false;
+
+fn calculate_clustered_lights(
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    camera_direction: vec3<f32>,
+    fresnel_schlick_0: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    return vec3<f32>(0.0);
+}
+";
+
+/// Shaders without the environment do not reflect it
+const ENVIRONMENT_STUB: &str = "fn calculate_environment(
+    normal: vec3<f32>,
+    camera_direction: vec3<f32>,
+    fresnel_schlick_0: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    return vec3<f32>(0.0);
+}
+";
+
 /// Lights startup system
 pub fn startup(mut globals: Mut<Globals>) {
     globals.set(Lights::default());
@@ -157,6 +224,11 @@ pub fn load(
     mut globals: Mut<Globals>,
     camera: Const<Camera>,
 ) {
+    let local_lights = globals
+        .get::<Shadows>()
+        .map(|shadows| shadows.local_lights().to_vec())
+        .unwrap_or_default();
+
     if let Some(lights) = globals.get_mut::<Lights>() {
         let camera_position = camera.position();
         let mut uniform = Uniform {
@@ -167,6 +239,12 @@ pub fn load(
         for (light,) in world.query::<(&Light,)>() {
             uniform.store(light);
         }
+        for (slot, local_light) in local_lights.iter().enumerate() {
+            match *local_light {
+                LocalLight::Point(i) => uniform.point[i].shadow = slot as f32,
+                LocalLight::Spot(i) => uniform.spot[i].shadow = slot as f32,
+            }
+        }
         renderer.load_uniform_buffer(&mut lights.uniform, bytemuck::cast_slice(&[uniform]));
     }
 }
@@ -231,7 +309,7 @@ impl Uniform {
                         a_constant: *constant,
                         a_linear: *linear,
                         a_quadratic: *quadratic,
-                        ..Default::default()
+                        shadow: -1.0,
                     };
                     self.count[1] = i as u32 + 1;
                 }
@@ -268,6 +346,7 @@ impl Uniform {
                         color: (*color * (*intensity)).into(),
                         cut_off: *cut_off,
                         outer_cut_off: *outer_cut_off,
+                        shadow: -1.0,
                         ..Default::default()
                     };
                     self.count[3] = i as u32 + 1;
@@ -304,7 +383,8 @@ struct PointLight {
     a_linear: f32,
     /// Quadratic light attenuation
     a_quadratic: f32,
-    a_unused: f32,
+    /// Shadow slot or -1.0
+    shadow: f32,
 }
 
 unsafe impl bytemuck::Zeroable for PointLight {}
@@ -337,8 +417,10 @@ struct SpotLight {
     cut_off: f32,
     /// Light source outer cut off
     outer_cut_off: f32,
+    /// Shadow slot or -1.0
+    shadow: f32,
     /// structure padding
-    padding: [f32; 2],
+    padding: f32,
 }
 
 unsafe impl bytemuck::Zeroable for SpotLight {}
@@ -1,6 +1,8 @@
 use dotrix_core::assets::{Assets, Mesh};
-use dotrix_core::renderer::UniformBuffer;
-use dotrix_core::{Id, Renderer, Transform};
+use dotrix_core::renderer::{StorageBuffer, UniformBuffer};
+use dotrix_core::{Id, Pipeline, Renderer, Transform};
+
+use dotrix_math::Mat4;
 
 /// Model component
 #[derive(Default)]
@@ -9,6 +11,21 @@ pub struct Model {
     pub mesh: Id<Mesh>,
     /// Model transformation uniform
     pub transform: UniformBuffer,
+    /// Model transformation uniform of the previous frame, for the velocity buffer
+    pub previous_transform: UniformBuffer,
+    /// Shadow casting pipelines of the shadow views
+    pub shadows: Vec<Pipeline>,
+    /// Pipeline rendering the depth for the ambient occlusion and the motion of the model
+    pub depth: Pipeline,
+    /// Pipeline rendering the depth in the depth prepass, see [`Renderer::set_depth_prepass`]
+    pub prepass: Pipeline,
+    /// Transformation matrix of the latest [`Model::transform`] call
+    pub last_transform: Option<Mat4>,
+    /// Weights of the mesh morph targets, the default weights of the mesh apply to the missing
+    /// ones
+    pub morph_weights: Vec<f32>,
+    /// Morph targets weights buffer
+    pub morph: StorageBuffer,
 }
 
 impl Model {
@@ -16,16 +33,65 @@ impl Model {
     pub fn load(&mut self, renderer: &Renderer, assets: &mut Assets) -> bool {
         if let Some(mesh) = assets.get_mut(self.mesh) {
             mesh.load(renderer);
+            mesh.load_morph_targets(renderer);
+            let weights = self.weights(mesh);
+            renderer.load_storage_buffer(&mut self.morph, bytemuck::cast_slice(&weights));
         } else {
             return false;
         }
         true
     }
+
+    /// Returns weights of every morph target of the mesh, or a single zero weight if the mesh
+    /// has no targets
+    fn weights(&self, mesh: &Mesh) -> Vec<f32> {
+        let count = mesh.morph_targets.len().max(1);
+        (0..count)
+            .map(|i| {
+                self.morph_weights
+                    .get(i)
+                    .or_else(|| mesh.morph_weights.get(i))
+                    .copied()
+                    .unwrap_or(0.0)
+            })
+            .collect()
+    }
+
+    /// Integrates the morph targets blending into shader
+    ///
+    /// The `source` shader code must contain `{{ include(morph) }}` label and bind the
+    /// `MorphTargets` deltas of the mesh and `MorphWeights` of the model to the group 2, then
+    /// the vertex
+    /// stage blends the attributes with `morph_position(vertex_index, position)` and
+    /// `morph_normal(vertex_index, normal)`.
+    pub fn add_to_shader(source: &str) -> String {
+        source.replace(
+            "{{ include(morph) }}",
+            include_str!("shaders/morph.inc.wgsl"),
+        )
+    }
     /// Loads data to the transformation buffer
+    ///
+    /// The transformation loaded in the previous call goes to the previous transformation
+    /// buffer, so the method is expected to be called once per frame.
     pub fn transform(&mut self, renderer: &Renderer, transform: &Transform) {
         let transform_matrix = transform.matrix();
+        let previous_matrix = self.advance_transform(transform_matrix);
         let transform_raw = AsRef::<[f32; 16]>::as_ref(&transform_matrix);
         renderer.load_uniform_buffer(&mut self.transform, bytemuck::cast_slice(transform_raw));
+        let previous_raw = AsRef::<[f32; 16]>::as_ref(&previous_matrix);
+        renderer.load_uniform_buffer(
+            &mut self.previous_transform,
+            bytemuck::cast_slice(previous_raw),
+        );
+    }
+
+    /// Stores the latest transformation matrix and returns the previous one, the first
+    /// transformation is not moving
+    fn advance_transform(&mut self, transform_matrix: Mat4) -> Mat4 {
+        self.last_transform
+            .replace(transform_matrix)
+            .unwrap_or(transform_matrix)
     }
 }
 
@@ -37,3 +103,34 @@ impl From<Id<Mesh>> for Model {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn morph_weights_fall_back_to_mesh_defaults() {
+        let mut mesh = Mesh::default();
+        let mut model = Model::default();
+        assert_eq!(model.weights(&mesh), vec![0.0]);
+
+        mesh.with_vertices(&[[0.0f32, 0.0, 0.0]]);
+        for _ in 0..3 {
+            mesh.with_morph_target(&[[1.0, 0.0, 0.0]], None);
+        }
+        mesh.morph_weights[2] = 0.5;
+        model.morph_weights = vec![1.0];
+        assert_eq!(model.weights(&mesh), vec![1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn previous_transform_lags_one_frame() {
+        let mut model = Model::default();
+        let first = Mat4::from_scale(1.0);
+        let second = Mat4::from_scale(2.0);
+        assert_eq!(model.advance_transform(first), first);
+        assert_eq!(model.advance_transform(second), first);
+        assert_eq!(model.advance_transform(second), second);
+        assert_eq!(model.last_transform, Some(second));
+    }
+}
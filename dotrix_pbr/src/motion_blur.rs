@@ -0,0 +1,226 @@
+//! Motion blur
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    Attachment, BindGroup, Binding, DepthBufferMode, PipelineLayout, PipelineOptions, RenderPass,
+    Sampler, Stage, TextureFormat, UniformBuffer,
+};
+use dotrix_core::{Assets, Globals, Pipeline, Renderer};
+
+use crate::tone_mapping::{ToneMapping, FULLSCREEN_TRIANGLE, HDR_TARGET, TONE_MAPPING_PASS};
+use crate::velocity::VELOCITY;
+
+const PIPELINE_LABEL: &str = "dotrix::motion_blur";
+
+/// Maximal number of the blur samples per pixel
+pub const MAX_MOTION_BLUR_SAMPLES: u32 = 32;
+
+/// Name of the render graph pass and attachment with the HDR scene blurred along the motion
+pub const MOTION_BLUR_TARGET: &str = "motion_blur";
+
+/// Motion blur service
+///
+/// Pixels are blurred along their [`VELOCITY`], scaled by the `intensity`, that is the
+/// fraction of the frame time, the shutter stays open.
+pub struct MotionBlur {
+    /// Motion blur is enabled
+    pub enabled: bool,
+    /// Scale of the velocity
+    pub intensity: f32,
+    /// Maximal length of the blur in pixels
+    pub max_length: f32,
+    /// Number of the blur samples per pixel, up to [`MAX_MOTION_BLUR_SAMPLES`]
+    pub samples: u32,
+    /// Motion blur uniform buffer
+    pub uniform: UniformBuffer,
+    source: &'static str,
+    sampler: Sampler,
+    pipeline: Pipeline,
+    generation: Option<usize>,
+}
+
+impl Default for MotionBlur {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.5,
+            max_length: 32.0,
+            samples: 12,
+            uniform: UniformBuffer::default(),
+            source: HDR_TARGET,
+            sampler: Sampler::linear_clamp(),
+            pipeline: Pipeline::default().with_pass(MOTION_BLUR_TARGET),
+            generation: None,
+        }
+    }
+}
+
+/// Motion blur startup system
+///
+/// The scene is blurred after the anti-aliasing, before the depth of field.
+pub fn startup(
+    mut motion_blur: Mut<MotionBlur>,
+    mut renderer: Mut<Renderer>,
+    mut assets: Mut<Assets>,
+    mut globals: Mut<Globals>,
+) {
+    if let Some(tone_mapping) = globals.get_mut::<ToneMapping>() {
+        motion_blur.source = tone_mapping.source;
+        tone_mapping.source = MOTION_BLUR_TARGET;
+    }
+
+    let graph = renderer.graph_mut();
+    graph.add_attachment(
+        MOTION_BLUR_TARGET,
+        Attachment::color(TextureFormat::rgba_f16()),
+    );
+    graph.add_pass(
+        RenderPass::new(MOTION_BLUR_TARGET)
+            .reads(motion_blur.source)
+            .reads(VELOCITY)
+            .writes(MOTION_BLUR_TARGET),
+    );
+    if let Some(tone_mapping) = graph.pass_mut(TONE_MAPPING_PASS) {
+        for read in tone_mapping
+            .reads
+            .iter_mut()
+            .filter(|r| *r == motion_blur.source)
+        {
+            *read = String::from(MOTION_BLUR_TARGET);
+        }
+    }
+
+    let mut shader = Shader {
+        name: String::from(PIPELINE_LABEL),
+        code: String::from(include_str!("shaders/motion_blur.wgsl")),
+        ..Default::default()
+    };
+    shader.load(&renderer);
+    assets.store_as(shader, PIPELINE_LABEL);
+}
+
+/// Motion blur rendering system
+///
+/// Disabled motion blur copies the scene, so the tone mapping source stays the same.
+pub fn render(
+    mut motion_blur: Mut<MotionBlur>,
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+) {
+    let uniform = Uniform::new(&motion_blur);
+    renderer.load_uniform_buffer(&mut motion_blur.uniform, bytemuck::cast_slice(&[uniform]));
+    renderer.load_sampler(&mut motion_blur.sampler);
+
+    // the scene and velocity textures are recreated on resize
+    let generation = renderer.attachments_generation();
+    if motion_blur.generation != Some(generation) {
+        motion_blur.generation = Some(generation);
+        motion_blur.pipeline.bindings.unload();
+    }
+
+    let MotionBlur {
+        uniform,
+        source,
+        sampler,
+        pipeline,
+        ..
+    } = &mut *motion_blur;
+    if pipeline.shader.is_null() {
+        pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+    }
+    let ready = [*source, VELOCITY]
+        .iter()
+        .all(|name| renderer.attachment(name).is_some());
+    if !pipeline.cycle(&renderer) || !ready {
+        return;
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    if !pipeline.ready() {
+        let shader = match assets.get(pipeline.shader) {
+            Some(shader) if shader.loaded() => shader,
+            _ => return,
+        };
+        renderer.bind(
+            pipeline,
+            PipelineLayout {
+                label: String::from(PIPELINE_LABEL),
+                mesh: Some(mesh),
+                shader,
+                bindings: &[BindGroup::new(
+                    "Globals",
+                    vec![
+                        Binding::Uniform("MotionBlur", Stage::Fragment, uniform),
+                        Binding::Attachment("Scene", Stage::Fragment, source),
+                        Binding::Attachment("Velocity", Stage::Fragment, VELOCITY),
+                        Binding::Sampler("Sampler", Stage::Fragment, sampler),
+                    ],
+                )],
+                vertex_buffers: &[],
+                push_constants: None,
+                options: PipelineOptions {
+                    depth_buffer_mode: DepthBufferMode::Disabled,
+                    disable_cull_mode: true,
+                    ..Default::default()
+                },
+            },
+        );
+    }
+
+    renderer.run(pipeline, mesh);
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    /// Intensity, maximal length and number of samples
+    params: [f32; 4],
+}
+
+impl Uniform {
+    fn new(motion_blur: &MotionBlur) -> Self {
+        Self {
+            params: [
+                if motion_blur.enabled {
+                    motion_blur.intensity
+                } else {
+                    0.0
+                },
+                motion_blur.max_length,
+                motion_blur.samples.clamp(1, MAX_MOTION_BLUR_SAMPLES) as f32,
+                0.0,
+            ],
+        }
+    }
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_motion_blur_has_no_intensity() {
+        let mut motion_blur = MotionBlur {
+            samples: 0,
+            ..Default::default()
+        };
+        assert_eq!(Uniform::new(&motion_blur).params, [0.0, 32.0, 1.0, 0.0]);
+
+        motion_blur.enabled = true;
+        motion_blur.samples = 1000;
+        assert_eq!(
+            Uniform::new(&motion_blur).params,
+            [0.5, 32.0, MAX_MOTION_BLUR_SAMPLES as f32, 0.0]
+        );
+    }
+}
@@ -0,0 +1,508 @@
+//! GPU occlusion culling
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    BindGroup, Binding, DrawIndexedIndirect, DrawIndirect, PipelineLayout, PipelineOptions,
+    RenderPass, Stage, StorageBuffer, UniformBuffer, WorkGroups, DEPTH_PREPASS, MAIN_PASS,
+};
+use dotrix_core::{Assets, Camera, Globals, Id, Pipeline, Renderer, World};
+
+use crate::ssao::SSAO_DEPTH;
+
+const PYRAMID_LABEL: &str = "dotrix::occlusion::pyramid";
+const CULLING_LABEL: &str = "dotrix::occlusion::culling";
+
+/// Maximal number of the depth pyramid levels
+pub const MAX_PYRAMID_LEVELS: usize = 16;
+
+/// Name of the render graph pass building the depth pyramid and culling the objects
+pub const OCCLUSION_PASS: &str = "occlusion";
+
+/// Name of the visibility buffer produced by the [`OCCLUSION_PASS`] for the indirect draws
+pub const VISIBILITY_BUFFER: &str = "dotrix::occlusion::visible";
+
+const PYRAMID_WORKGROUP_SIZE: u32 = 8;
+const CULLING_WORKGROUP_SIZE: u32 = 64;
+
+/// Level of the depth pyramid in the storage buffer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PyramidLevel {
+    /// Index of the first texel of the level
+    pub offset: u32,
+    /// Width of the level in texels
+    pub width: u32,
+    /// Height of the level in texels
+    pub height: u32,
+}
+
+/// Returns levels of the depth pyramid of the depth size
+///
+/// The first level has the half resolution of the depth, every next one halves the previous
+/// one, down to a single texel or [`MAX_PYRAMID_LEVELS`].
+pub fn pyramid_levels(width: u32, height: u32) -> Vec<PyramidLevel> {
+    let mut levels = Vec::new();
+    let (mut width, mut height) = (width, height);
+    let mut offset = 0;
+    while (width > 1 || height > 1) && levels.len() < MAX_PYRAMID_LEVELS {
+        width = width.div_ceil(2);
+        height = height.div_ceil(2);
+        levels.push(PyramidLevel {
+            offset,
+            width,
+            height,
+        });
+        offset += width * height;
+    }
+    levels
+}
+
+/// Occlusion culling (global)
+///
+/// The depth of the models rendered in the [`crate::ssao::SSAO_DEPTH_PASS`] is reduced into
+/// a pyramid of the farthest depths in the [`OCCLUSION_PASS`] before the main pass. Bounding
+/// spheres of the [`OcclusionBatch`] objects are tested against the view frustum and the level
+/// of the pyramid, where the sphere covers a few texels, and the visible ones are written into
+/// the indirect draw buffer of the batch. Dense scenes of the many objects over the terrain
+/// draw only the objects, that are not hidden by the models.
+///
+/// Without the culling the batches are culled by the view frustum only.
+pub struct OcclusionCulling {
+    /// Objects are culled by the depth of the models
+    pub enabled: bool,
+    /// Culling uniform buffer
+    pub uniform: UniformBuffer,
+    /// Storage buffer of the depth pyramid levels
+    pub pyramid: StorageBuffer,
+    levels: Vec<PyramidLevel>,
+    levels_uniform: UniformBuffer,
+    pipeline: Pipeline,
+    generation: Option<usize>,
+    pyramid_generation: usize,
+}
+
+impl Default for OcclusionCulling {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            uniform: UniformBuffer::default(),
+            pyramid: StorageBuffer::new_readwrite(),
+            levels: Vec::new(),
+            levels_uniform: UniformBuffer::default(),
+            pipeline: Pipeline::default().with_pass(OCCLUSION_PASS),
+            generation: None,
+            pyramid_generation: 0,
+        }
+    }
+}
+
+impl OcclusionCulling {
+    /// Returns levels of the current depth pyramid
+    pub fn levels(&self) -> &[PyramidLevel] {
+        &self.levels
+    }
+}
+
+/// Objects culled on GPU and drawn by a single indirect draw call (component)
+///
+/// The render pipeline of the batch binds the [`OcclusionBatch::visible`] storage buffer as
+/// `var<storage, read_write>` array of `u32` and takes the index of the object by the
+/// `instance_index`, then it runs with
+/// `renderer.run_indirect(pipeline, mesh, batch.indirect(), 1)`.
+pub struct OcclusionBatch {
+    /// Mesh of the objects
+    pub mesh: Id<Mesh>,
+    /// World space centers and radii of the bounding spheres of the objects
+    pub spheres: Vec<[f32; 4]>,
+    /// Spheres have to be reloaded
+    pub changed: bool,
+    spheres_buffer: StorageBuffer,
+    visible: StorageBuffer,
+    indirect: StorageBuffer,
+    pipeline: Pipeline,
+    loaded: usize,
+    pyramid_generation: Option<usize>,
+}
+
+impl OcclusionBatch {
+    /// Constructs the batch of the objects of the mesh
+    pub fn new(mesh: Id<Mesh>, spheres: Vec<[f32; 4]>) -> Self {
+        Self {
+            mesh,
+            spheres,
+            changed: true,
+            spheres_buffer: StorageBuffer::new_readonly(),
+            visible: StorageBuffer::new_readwrite(),
+            indirect: StorageBuffer::new_indirect(),
+            pipeline: Pipeline::default().with_pass(OCCLUSION_PASS),
+            loaded: 0,
+            pyramid_generation: None,
+        }
+    }
+
+    /// Returns storage buffer of the indices of the visible objects
+    pub fn visible(&self) -> &StorageBuffer {
+        &self.visible
+    }
+
+    /// Returns indirect buffer of the draw call of the visible objects
+    pub fn indirect(&self) -> &StorageBuffer {
+        &self.indirect
+    }
+
+    /// Returns true if the buffers are loaded and the pipelines can be bound
+    pub fn ready(&self) -> bool {
+        !self.visible.is_empty() && !self.indirect.is_empty()
+    }
+}
+
+/// Occlusion culling startup system
+pub fn startup(mut renderer: Mut<Renderer>, mut assets: Mut<Assets>, mut globals: Mut<Globals>) {
+    let graph = renderer.graph_mut();
+    graph.add_pass_before(
+        MAIN_PASS,
+        RenderPass::new(OCCLUSION_PASS)
+            .reads(SSAO_DEPTH)
+            .produces(VISIBILITY_BUFFER),
+    );
+    for pass in [MAIN_PASS, DEPTH_PREPASS].iter() {
+        if let Some(pass) = graph.pass_mut(pass) {
+            pass.consumes.push(String::from(VISIBILITY_BUFFER));
+        }
+    }
+
+    for (label, code) in [
+        (PYRAMID_LABEL, include_str!("shaders/depth_pyramid.wgsl")),
+        (
+            CULLING_LABEL,
+            include_str!("shaders/occlusion_culling.wgsl"),
+        ),
+    ]
+    .iter()
+    {
+        let mut shader = Shader {
+            name: String::from(*label),
+            code: String::from(*code),
+            ..Default::default()
+        };
+        shader.load(&renderer);
+        assets.store_as(shader, label);
+    }
+
+    globals.set(OcclusionCulling::default());
+}
+
+/// Depth pyramid and culling system
+pub fn compute(
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    camera: Const<Camera>,
+    mut globals: Mut<Globals>,
+    world: Const<World>,
+) {
+    let culling = match globals.get_mut::<OcclusionCulling>() {
+        Some(culling) => culling,
+        None => return,
+    };
+    let proj_view = match (camera.proj, camera.view) {
+        (Some(proj), Some(view)) => proj * view,
+        _ => return,
+    };
+
+    // the pyramid is recreated with the depth
+    let generation = renderer.attachments_generation();
+    if culling.generation != Some(generation) {
+        culling.generation = Some(generation);
+        culling.pipeline.bindings.unload();
+        culling.pyramid.empty();
+        culling.levels.clear();
+        culling.pyramid_generation += 1;
+        if let Some(depth) = renderer.attachment(SSAO_DEPTH) {
+            let (width, height) = depth.dimensions();
+            culling.levels = pyramid_levels(width, height);
+            let size = culling
+                .levels
+                .last()
+                .map(|level| level.offset + level.width * level.height)
+                .unwrap_or(1);
+            let texels = vec![1.0f32; size as usize];
+            renderer.load_storage_buffer(&mut culling.pyramid, bytemuck::cast_slice(&texels));
+        }
+    }
+
+    let (width, height) = renderer
+        .attachment(SSAO_DEPTH)
+        .map(|depth| depth.dimensions())
+        .unwrap_or((1, 1));
+    let pyramid_ready = culling.enabled && !culling.levels.is_empty();
+    let mut uniform = Uniform {
+        proj_view: proj_view.into(),
+        params: [
+            width,
+            height,
+            if pyramid_ready {
+                culling.levels.len() as u32
+            } else {
+                0
+            },
+            0,
+        ],
+        ..Default::default()
+    };
+    for (i, level) in culling.levels.iter().enumerate() {
+        uniform.levels[i] = [level.offset, level.width, level.height, 0];
+    }
+    renderer.load_uniform_buffer(&mut culling.uniform, bytemuck::cast_slice(&[uniform]));
+
+    if pyramid_ready {
+        build_pyramid(culling, &mut renderer, &assets, width, height);
+    }
+
+    if culling.pyramid.is_empty() {
+        let texels = [1.0f32];
+        renderer.load_storage_buffer(&mut culling.pyramid, bytemuck::cast_slice(&texels));
+    }
+
+    for (batch,) in world.query::<(&mut OcclusionBatch,)>() {
+        let mesh = match assets.get(batch.mesh) {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        if batch.spheres.is_empty() {
+            continue;
+        }
+
+        // buffers are recreated for the new number of the objects
+        if batch.loaded != batch.spheres.len() {
+            batch.loaded = batch.spheres.len();
+            batch.changed = true;
+            batch.spheres_buffer.empty();
+            batch.visible.empty();
+            batch.pipeline.bindings.unload();
+            let indices = vec![0u32; batch.loaded];
+            renderer.load_storage_buffer(&mut batch.visible, bytemuck::cast_slice(&indices));
+        }
+        if batch.changed {
+            batch.changed = false;
+            renderer.load_storage_buffer(
+                &mut batch.spheres_buffer,
+                bytemuck::cast_slice(&batch.spheres),
+            );
+        }
+        if batch.pyramid_generation != Some(culling.pyramid_generation) {
+            batch.pyramid_generation = Some(culling.pyramid_generation);
+            batch.pipeline.bindings.unload();
+        }
+
+        // the number of the visible objects is counted by the culling shader
+        match mesh.indices() {
+            Some(indices) => {
+                let args = DrawIndexedIndirect {
+                    index_count: indices.len() as u32,
+                    ..Default::default()
+                };
+                renderer.load_storage_buffer(&mut batch.indirect, bytemuck::cast_slice(&[args]));
+            }
+            None => {
+                let args = DrawIndirect {
+                    vertex_count: mesh.vertices.len() as u32,
+                    ..Default::default()
+                };
+                renderer.load_storage_buffer(&mut batch.indirect, bytemuck::cast_slice(&[args]));
+            }
+        }
+
+        let OcclusionBatch {
+            spheres_buffer,
+            visible,
+            indirect,
+            pipeline,
+            loaded,
+            ..
+        } = batch;
+        if pipeline.shader.is_null() {
+            pipeline.shader = assets.find::<Shader>(CULLING_LABEL).unwrap_or_default();
+        }
+
+        if !pipeline.ready() {
+            let shader = match assets.get(pipeline.shader) {
+                Some(shader) if shader.loaded() => shader,
+                _ => continue,
+            };
+            renderer.bind(
+                pipeline,
+                PipelineLayout {
+                    label: String::from(CULLING_LABEL),
+                    mesh: None,
+                    shader,
+                    bindings: &[
+                        BindGroup::new(
+                            "Globals",
+                            vec![
+                                Binding::Uniform("Culling", Stage::Compute, &culling.uniform),
+                                Binding::Storage("Pyramid", Stage::Compute, &culling.pyramid),
+                            ],
+                        ),
+                        BindGroup::new(
+                            "Locals",
+                            vec![
+                                Binding::Storage("Spheres", Stage::Compute, spheres_buffer),
+                                Binding::Storage("Visible", Stage::Compute, visible),
+                                Binding::Storage("Indirect", Stage::Compute, indirect),
+                            ],
+                        ),
+                    ],
+                    vertex_buffers: &[],
+                    push_constants: None,
+                    options: PipelineOptions::default(),
+                },
+            );
+        }
+
+        renderer.compute(
+            pipeline,
+            WorkGroups {
+                x: (*loaded as u32).div_ceil(CULLING_WORKGROUP_SIZE),
+                y: 1,
+                z: 1,
+            },
+        );
+    }
+}
+
+/// Reduces the depth into the levels of the pyramid, one dispatch per level
+fn build_pyramid(
+    culling: &mut OcclusionCulling,
+    renderer: &mut Renderer,
+    assets: &Assets,
+    width: u32,
+    height: u32,
+) {
+    let stride = renderer.dynamic_uniform_stride(std::mem::size_of::<LevelUniform>());
+    let mut data = vec![0u8; stride * MAX_PYRAMID_LEVELS];
+    let mut source = PyramidLevel {
+        offset: 0,
+        width,
+        height,
+    };
+    for (i, level) in culling.levels.iter().enumerate() {
+        let uniform = LevelUniform {
+            source: [source.offset, source.width, source.height, 0],
+            target: [level.offset, level.width, level.height, i as u32],
+        };
+        data[i * stride..i * stride + std::mem::size_of::<LevelUniform>()]
+            .copy_from_slice(bytemuck::cast_slice(&[uniform]));
+        source = *level;
+    }
+    renderer.load_uniform_buffer(&mut culling.levels_uniform, &data);
+
+    let OcclusionCulling {
+        pyramid,
+        levels,
+        levels_uniform,
+        pipeline,
+        ..
+    } = culling;
+    if pipeline.shader.is_null() {
+        pipeline.shader = assets.find::<Shader>(PYRAMID_LABEL).unwrap_or_default();
+    }
+
+    if !pipeline.ready() {
+        let shader = match assets.get(pipeline.shader) {
+            Some(shader) if shader.loaded() => shader,
+            _ => return,
+        };
+        renderer.bind(
+            pipeline,
+            PipelineLayout {
+                label: String::from(PYRAMID_LABEL),
+                mesh: None,
+                shader,
+                bindings: &[BindGroup::new(
+                    "Globals",
+                    vec![
+                        Binding::DynamicUniform(
+                            "Level",
+                            Stage::Compute,
+                            levels_uniform,
+                            std::mem::size_of::<LevelUniform>() as u32,
+                        ),
+                        Binding::Attachment("Depth", Stage::Compute, SSAO_DEPTH),
+                        Binding::Storage("Pyramid", Stage::Compute, pyramid),
+                    ],
+                )],
+                vertex_buffers: &[],
+                push_constants: None,
+                options: PipelineOptions::default(),
+            },
+        );
+    }
+
+    for (i, level) in levels.iter().enumerate() {
+        renderer.set_dynamic_offsets(pipeline, &[(i * stride) as u32]);
+        renderer.compute(
+            pipeline,
+            WorkGroups {
+                x: level.width.div_ceil(PYRAMID_WORKGROUP_SIZE),
+                y: level.height.div_ceil(PYRAMID_WORKGROUP_SIZE),
+                z: 1,
+            },
+        );
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    proj_view: [[f32; 4]; 4],
+    /// Width and height of the depth and number of the pyramid levels
+    params: [u32; 4],
+    /// Offset, width and height of the pyramid levels
+    levels: [[u32; 4]; MAX_PYRAMID_LEVELS],
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct LevelUniform {
+    /// Offset, width and height of the source level
+    source: [u32; 4],
+    /// Offset, width and height of the target level and its index
+    target: [u32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for LevelUniform {}
+unsafe impl bytemuck::Pod for LevelUniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pyramid_levels_halve_down_to_a_texel() {
+        let levels = pyramid_levels(5, 3);
+        assert_eq!(
+            levels,
+            vec![
+                PyramidLevel {
+                    offset: 0,
+                    width: 3,
+                    height: 2
+                },
+                PyramidLevel {
+                    offset: 6,
+                    width: 2,
+                    height: 1
+                },
+                PyramidLevel {
+                    offset: 8,
+                    width: 1,
+                    height: 1
+                },
+            ]
+        );
+        assert_eq!(pyramid_levels(1920, 1080).len(), 11);
+        assert!(pyramid_levels(1, 1).is_empty());
+    }
+}
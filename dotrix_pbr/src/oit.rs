@@ -0,0 +1,207 @@
+//! Weighted blended order-independent transparency
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    Attachment, BindGroup, Binding, BlendComponent, BlendFactor, BlendMode, DepthBufferMode,
+    PipelineLayout, PipelineOptions, RenderPass, Stage, TextureFormat, DEPTH,
+};
+use dotrix_core::{Assets, Color, Pipeline, Renderer};
+
+use crate::tone_mapping::{FULLSCREEN_TRIANGLE, HDR_TARGET};
+
+const PIPELINE_LABEL: &str = "dotrix::oit::resolve";
+
+/// Name of the render graph pass accumulating the transparent fragments
+pub const OIT_PASS: &str = "oit";
+
+/// Name of the render graph pass blending the accumulated fragments over the scene
+pub const OIT_RESOLVE_PASS: &str = "oit_resolve";
+
+/// Name of the render graph attachment with the weighted sum of the transparent colors
+pub const OIT_ACCUM: &str = "oit_accum";
+
+/// Name of the render graph attachment with the coverage of the transparent fragments
+pub const OIT_COVERAGE: &str = "oit_coverage";
+
+/// Order-independent transparency service
+///
+/// Sorting fails for the intersecting and heavily overlapping surfaces like smoke, foliage
+/// cards or water. Pipelines running in the [`OIT_PASS`] instead add their fragments weighted
+/// by the depth into the [`OIT_ACCUM`] and blend their alpha into the [`OIT_COVERAGE`], then
+/// the [`OIT_RESOLVE_PASS`] blends the weighted average color over the scene, so the order of
+/// the draws does not matter.
+///
+/// The passes are set up once per application, before it runs:
+/// `app.add_service(Oit { enabled: true, ..Default::default() })` after the PBR extension.
+pub struct Oit {
+    /// Transparency passes are set up
+    pub enabled: bool,
+    pipeline: Pipeline,
+    generation: Option<usize>,
+}
+
+impl Default for Oit {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pipeline: Pipeline::default().with_pass(OIT_RESOLVE_PASS),
+            generation: None,
+        }
+    }
+}
+
+impl Oit {
+    /// Integrates the transparency output into shader
+    ///
+    /// The `source` shader code must contain `{{ include(oit) }}` label, then the fragment
+    /// stage returns `oit_output(color, in.position)` of the `OitOutput` type.
+    pub fn add_to_shader(source: &str) -> String {
+        source.replace("{{ include(oit) }}", include_str!("shaders/oit.inc.wgsl"))
+    }
+
+    /// Returns options of the pipelines running in the [`OIT_PASS`]
+    ///
+    /// Transparent fragments are tested against the depth of the scene, but do not write it.
+    pub fn pipeline_options() -> PipelineOptions {
+        PipelineOptions {
+            depth_buffer_mode: DepthBufferMode::Read,
+            blend: vec![
+                BlendMode::Custom {
+                    color: BlendComponent::add(BlendFactor::One, BlendFactor::One),
+                    alpha: BlendComponent::add(BlendFactor::One, BlendFactor::One),
+                },
+                BlendMode::Custom {
+                    color: BlendComponent::add(BlendFactor::One, BlendFactor::OneMinusSrc),
+                    alpha: BlendComponent::add(BlendFactor::One, BlendFactor::OneMinusSrc),
+                },
+            ],
+            ..Default::default()
+        }
+    }
+}
+
+/// Transparency startup system
+pub fn startup(oit: Const<Oit>, mut renderer: Mut<Renderer>, mut assets: Mut<Assets>) {
+    if !oit.enabled {
+        return;
+    }
+
+    let graph = renderer.graph_mut();
+    graph.add_attachment(
+        OIT_ACCUM,
+        Attachment::color(TextureFormat::rgba_f16()).with_multisampling(),
+    );
+    graph.add_attachment(
+        OIT_COVERAGE,
+        Attachment::color(TextureFormat::r_f16()).with_multisampling(),
+    );
+    // transparent fragments are occluded by the depth of the main pass
+    graph.add_pass(
+        RenderPass::new(OIT_PASS)
+            .writes(OIT_ACCUM)
+            .writes(OIT_COVERAGE)
+            .depth(DEPTH)
+            .clear(Color::rgba(0.0, 0.0, 0.0, 0.0)),
+    );
+    graph.add_pass(
+        RenderPass::new(OIT_RESOLVE_PASS)
+            .reads(OIT_ACCUM)
+            .reads(OIT_COVERAGE)
+            .writes(HDR_TARGET),
+    );
+
+    let mut shader = Shader {
+        name: String::from(PIPELINE_LABEL),
+        code: String::from(include_str!("shaders/oit_resolve.wgsl")),
+        ..Default::default()
+    };
+    shader.load(&renderer);
+    assets.store_as(shader, PIPELINE_LABEL);
+}
+
+/// Transparency resolve system
+pub fn render(mut oit: Mut<Oit>, mut renderer: Mut<Renderer>, assets: Const<Assets>) {
+    if renderer.graph().pass(OIT_RESOLVE_PASS).is_none() {
+        return;
+    }
+
+    // transparency targets are recreated on resize
+    let generation = renderer.attachments_generation();
+    if oit.generation != Some(generation) {
+        oit.generation = Some(generation);
+        oit.pipeline.bindings.unload();
+    }
+
+    let pipeline = &mut oit.pipeline;
+    if pipeline.shader.is_null() {
+        pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+    }
+    if !pipeline.cycle(&renderer) {
+        return;
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    if !pipeline.ready() {
+        let shader = match assets.get(pipeline.shader) {
+            Some(shader) if shader.loaded() => shader,
+            _ => return,
+        };
+        if renderer.attachment(OIT_ACCUM).is_none() || renderer.attachment(OIT_COVERAGE).is_none() {
+            return;
+        }
+        renderer.bind(
+            pipeline,
+            PipelineLayout {
+                label: String::from(PIPELINE_LABEL),
+                mesh: Some(mesh),
+                shader,
+                bindings: &[BindGroup::new(
+                    "Globals",
+                    vec![
+                        Binding::Attachment("Accum", Stage::Fragment, OIT_ACCUM),
+                        Binding::Attachment("Coverage", Stage::Fragment, OIT_COVERAGE),
+                    ],
+                )],
+                vertex_buffers: &[],
+                push_constants: None,
+                options: PipelineOptions {
+                    depth_buffer_mode: DepthBufferMode::Disabled,
+                    disable_cull_mode: true,
+                    ..PipelineOptions::blended(BlendMode::Alpha)
+                },
+            },
+        );
+    }
+
+    renderer.run(pipeline, mesh);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transparent_targets_are_blended_separately() {
+        let options = Oit::pipeline_options();
+        let accum = options.blend[0];
+        let coverage = options.blend[1];
+        assert!(matches!(
+            accum,
+            BlendMode::Custom { color, .. } if color.dst_factor == BlendFactor::One
+        ));
+        assert!(matches!(
+            coverage,
+            BlendMode::Custom { color, .. } if color.dst_factor == BlendFactor::OneMinusSrc
+        ));
+
+        let shader = Oit::add_to_shader("{{ include(oit) }}");
+        assert!(shader.contains("fn oit_output"));
+    }
+}
@@ -0,0 +1,480 @@
+//! GPU particles
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    BindGroup, Binding, BlendMode, DepthBufferMode, PipelineLayout, PipelineOptions, RenderPass,
+    Stage, StorageBuffer, UniformBuffer, WorkGroups, MAIN_PASS,
+};
+use dotrix_core::{Assets, Camera, Color, Frame, Pipeline, Renderer, Transform, World};
+
+use dotrix_math::{InnerSpace, Mat4, SquareMatrix, Vec3};
+
+use crate::ssao::SSAO_DEPTH;
+
+const SIMULATE_LABEL: &str = "dotrix::particles::simulate";
+const PIPELINE_LABEL: &str = "dotrix::particles";
+
+/// Name of the mesh asset of the particle quad
+const PARTICLE_QUAD: &str = "dotrix::particles::quad";
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Name of the render graph pass simulating the particles
+pub const PARTICLES_PASS: &str = "particles";
+
+/// Name of the particles buffer produced by the [`PARTICLES_PASS`] for the main pass
+pub const PARTICLES_BUFFER: &str = "dotrix::particles";
+
+/// Emitter of the GPU particles (component)
+///
+/// Particles are spawned at the [`Transform`] of the entity and simulated by a compute shader
+/// in the [`PARTICLES_PASS`], so CPU never touches them. Living particles are affected by the
+/// `gravity`, the `drag` and the curl noise turbulence, and are rendered as camera facing
+/// billboards, blended with the `blend` mode in the main pass. Billboards fade out close to the
+/// models behind them within the `soft_distance`.
+pub struct ParticleEmitter {
+    /// Maximal number of the living particles, the oldest ones are replaced by the new ones
+    pub capacity: u32,
+    /// Number of the particles spawned per second
+    pub rate: f32,
+    /// Lifetime of the particles in seconds
+    pub lifetime: f32,
+    /// Part of the lifetime, that is randomly cut off, from 0.0 to 1.0
+    pub lifetime_variance: f32,
+    /// Initial speed of the particles
+    pub speed: f32,
+    /// Angle of the cone around the `direction`, particles are spawned in, in radians
+    pub spread: f32,
+    /// Direction of the spawned particles
+    pub direction: Vec3,
+    /// Acceleration of the particles
+    pub gravity: Vec3,
+    /// Air resistance slowing the particles down
+    pub drag: f32,
+    /// Strength of the curl noise turbulence
+    pub curl_strength: f32,
+    /// Frequency of the curl noise turbulence
+    pub curl_scale: f32,
+    /// Size of the spawned particles
+    pub start_size: f32,
+    /// Size of the particles at the end of their lifetime
+    pub end_size: f32,
+    /// Color of the spawned particles
+    pub start_color: Color,
+    /// Color of the particles at the end of their lifetime
+    pub end_color: Color,
+    /// Blending of the particles with the scene
+    pub blend: BlendMode,
+    /// Distance of the fade out in front of the models, zero disables soft particles
+    pub soft_distance: f32,
+    /// New particles are spawned
+    pub emitting: bool,
+    particles: StorageBuffer,
+    uniform: UniformBuffer,
+    simulation: Pipeline,
+    pipeline: Pipeline,
+    spawn: f32,
+    next: u32,
+    time: f32,
+    loaded_capacity: u32,
+    generation: Option<usize>,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            rate: 64.0,
+            lifetime: 2.0,
+            lifetime_variance: 0.25,
+            speed: 1.0,
+            spread: 0.3,
+            direction: Vec3::unit_y(),
+            gravity: Vec3::new(0.0, -1.0, 0.0),
+            drag: 0.1,
+            curl_strength: 0.0,
+            curl_scale: 0.5,
+            start_size: 0.1,
+            end_size: 0.05,
+            start_color: Color::white(),
+            end_color: Color::rgba(1.0, 1.0, 1.0, 0.0),
+            blend: BlendMode::Additive,
+            soft_distance: 0.5,
+            emitting: true,
+            particles: StorageBuffer::new_readwrite(),
+            uniform: UniformBuffer::default(),
+            simulation: Pipeline::default().with_pass(PARTICLES_PASS),
+            pipeline: Pipeline::default(),
+            spawn: 0.0,
+            next: 0,
+            time: 0.0,
+            loaded_capacity: 0,
+            generation: None,
+        }
+    }
+}
+
+impl ParticleEmitter {
+    /// Spawns `count` particles in the next frame
+    pub fn burst(&mut self, count: u32) {
+        self.spawn += count as f32;
+    }
+
+    /// Returns the first slot and the number of the particles spawned in `delta` seconds
+    ///
+    /// Slots are taken from the ring of the `capacity`, so the oldest particles are replaced.
+    pub fn spawn_range(&mut self, delta: f32) -> (u32, u32) {
+        if self.emitting {
+            self.spawn += self.rate.max(0.0) * delta;
+        }
+        let count = (self.spawn.floor() as u32).min(self.capacity);
+        self.spawn -= self.spawn.floor();
+        let first = self.next;
+        if self.capacity > 0 {
+            self.next = (self.next + count) % self.capacity;
+        }
+        (first, count)
+    }
+}
+
+/// GPU particles service
+#[derive(Default)]
+pub struct Particles {
+    /// Camera uniform buffer of the particle billboards
+    pub uniform: UniformBuffer,
+    generation: Option<usize>,
+}
+
+/// Particles startup system
+pub fn startup(mut renderer: Mut<Renderer>, mut assets: Mut<Assets>) {
+    let graph = renderer.graph_mut();
+    graph.add_pass_before(
+        MAIN_PASS,
+        RenderPass::new(PARTICLES_PASS).produces(PARTICLES_BUFFER),
+    );
+    // soft particles compare their depth with the models
+    if let Some(main) = graph.pass_mut(MAIN_PASS) {
+        main.reads.push(String::from(SSAO_DEPTH));
+        main.consumes.push(String::from(PARTICLES_BUFFER));
+    }
+
+    let mut mesh = Mesh::default();
+    mesh.with_vertices(&[
+        [-1.0, -1.0],
+        [1.0, -1.0],
+        [1.0, 1.0],
+        [-1.0, -1.0],
+        [1.0, 1.0],
+        [-1.0, 1.0],
+    ]);
+    mesh.load(&renderer);
+    assets.store_as(mesh, PARTICLE_QUAD);
+
+    for (label, code) in [
+        (
+            SIMULATE_LABEL,
+            include_str!("shaders/particles_simulate.wgsl"),
+        ),
+        (PIPELINE_LABEL, include_str!("shaders/particles.wgsl")),
+    ]
+    .iter()
+    {
+        let mut shader = Shader {
+            name: String::from(*label),
+            code: String::from(*code),
+            ..Default::default()
+        };
+        shader.load(&renderer);
+        assets.store_as(shader, label);
+    }
+}
+
+/// Particles simulation system
+pub fn simulate(
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    frame: Const<Frame>,
+    world: Const<World>,
+) {
+    let delta = frame.delta().as_secs_f32();
+    let seed = renderer.cycle() as u32;
+
+    let query = world.query::<(&mut ParticleEmitter, &Transform)>();
+    for (emitter, transform) in query {
+        if emitter.capacity == 0 {
+            continue;
+        }
+
+        // the buffer of the particles is recreated with the new capacity
+        if emitter.loaded_capacity != emitter.capacity {
+            emitter.loaded_capacity = emitter.capacity;
+            emitter.next = 0;
+            emitter.particles.empty();
+            emitter.simulation.bindings.unload();
+            emitter.pipeline.bindings.unload();
+            let particles = vec![Particle::default(); emitter.capacity as usize];
+            renderer.load_storage_buffer(&mut emitter.particles, bytemuck::cast_slice(&particles));
+        }
+
+        let (first, count) = emitter.spawn_range(delta);
+        emitter.time += delta;
+        let direction = if emitter.direction.magnitude2() > 0.0 {
+            emitter.direction.normalize()
+        } else {
+            Vec3::unit_y()
+        };
+        let position = transform.translate;
+        let uniform = Uniform {
+            position: [position.x, position.y, position.z, delta],
+            direction: [direction.x, direction.y, direction.z, emitter.spread],
+            gravity: [
+                emitter.gravity.x,
+                emitter.gravity.y,
+                emitter.gravity.z,
+                emitter.drag.max(0.0),
+            ],
+            params: [
+                emitter.speed,
+                emitter.lifetime,
+                emitter.lifetime_variance.clamp(0.0, 1.0),
+                emitter.time,
+            ],
+            noise: [emitter.curl_strength, emitter.curl_scale, 0.0, 0.0],
+            size: [
+                emitter.start_size,
+                emitter.end_size,
+                emitter.soft_distance,
+                0.0,
+            ],
+            spawn: [first, count, emitter.capacity, seed],
+            start_color: emitter.start_color.into(),
+            end_color: emitter.end_color.into(),
+        };
+        renderer.load_uniform_buffer(&mut emitter.uniform, bytemuck::cast_slice(&[uniform]));
+
+        let ParticleEmitter {
+            particles,
+            uniform,
+            simulation,
+            capacity,
+            ..
+        } = emitter;
+
+        if simulation.shader.is_null() {
+            simulation.shader = assets.find::<Shader>(SIMULATE_LABEL).unwrap_or_default();
+        }
+
+        if !simulation.ready() {
+            let shader = match assets.get(simulation.shader) {
+                Some(shader) if shader.loaded() => shader,
+                _ => continue,
+            };
+            renderer.bind(
+                simulation,
+                PipelineLayout {
+                    label: String::from(SIMULATE_LABEL),
+                    mesh: None,
+                    shader,
+                    bindings: &[BindGroup::new(
+                        "Globals",
+                        vec![
+                            Binding::Uniform("Emitter", Stage::Compute, uniform),
+                            Binding::Storage("Particles", Stage::Compute, particles),
+                        ],
+                    )],
+                    vertex_buffers: &[],
+                    push_constants: None,
+                    options: PipelineOptions::default(),
+                },
+            );
+        }
+
+        renderer.compute(
+            simulation,
+            WorkGroups {
+                x: capacity.div_ceil(WORKGROUP_SIZE),
+                y: 1,
+                z: 1,
+            },
+        );
+    }
+}
+
+/// Particles rendering system
+pub fn render(
+    mut particles: Mut<Particles>,
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    camera: Const<Camera>,
+    world: Const<World>,
+) {
+    let (proj, view) = match (camera.proj, camera.view) {
+        (Some(proj), Some(view)) => (proj, view),
+        _ => return,
+    };
+    let uniform = CameraUniform {
+        proj_view: (proj * view).into(),
+        inverse_proj: proj.invert().unwrap_or_else(Mat4::identity).into(),
+        right: [view.x.x, view.y.x, view.z.x, 0.0],
+        up: [view.x.y, view.y.y, view.z.y, 0.0],
+    };
+    renderer.load_uniform_buffer(&mut particles.uniform, bytemuck::cast_slice(&[uniform]));
+
+    // the depth of the models is recreated on resize
+    let generation = renderer.attachments_generation();
+    let resized = particles.generation != Some(generation);
+    particles.generation = Some(generation);
+
+    let mesh = match assets
+        .find::<Mesh>(PARTICLE_QUAD)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    let query = world.query::<(&mut ParticleEmitter, &Transform)>();
+    for (emitter, transform) in query {
+        if emitter.particles.is_empty() || emitter.uniform.is_empty() {
+            continue;
+        }
+        if resized || emitter.generation != Some(generation) {
+            emitter.generation = Some(generation);
+            emitter.pipeline.bindings.unload();
+        }
+
+        let ParticleEmitter {
+            particles: storage,
+            uniform,
+            pipeline,
+            blend,
+            loaded_capacity,
+            ..
+        } = emitter;
+
+        if pipeline.shader.is_null() {
+            pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+        }
+        if !pipeline.cycle(&renderer) {
+            continue;
+        }
+
+        if !pipeline.ready() {
+            let shader = match assets.get(pipeline.shader) {
+                Some(shader) if shader.loaded() => shader,
+                _ => continue,
+            };
+            if renderer.attachment(SSAO_DEPTH).is_none() {
+                continue;
+            }
+            renderer.bind(
+                pipeline,
+                PipelineLayout {
+                    label: String::from(PIPELINE_LABEL),
+                    mesh: Some(mesh),
+                    shader,
+                    bindings: &[
+                        BindGroup::new(
+                            "Globals",
+                            vec![
+                                Binding::Uniform("Camera", Stage::All, &particles.uniform),
+                                Binding::Attachment("Depth", Stage::Fragment, SSAO_DEPTH),
+                            ],
+                        ),
+                        BindGroup::new(
+                            "Locals",
+                            vec![
+                                Binding::Uniform("Emitter", Stage::All, uniform),
+                                Binding::Storage("Particles", Stage::Vertex, storage),
+                            ],
+                        ),
+                    ],
+                    vertex_buffers: &[],
+                    push_constants: None,
+                    // particles are tested against the models, but do not occlude each other,
+                    // emitters are blended from the farthest to the closest
+                    options: PipelineOptions {
+                        depth_buffer_mode: DepthBufferMode::Read,
+                        disable_cull_mode: true,
+                        transparent: true,
+                        ..PipelineOptions::blended(*blend)
+                    },
+                },
+            );
+        }
+
+        pipeline.options.end_index = *loaded_capacity;
+        pipeline.options.depth = camera.view_depth(transform.translate);
+        renderer.run(pipeline, mesh);
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Particle {
+    /// Position and age
+    position: [f32; 4],
+    /// Velocity and lifetime
+    velocity: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for Particle {}
+unsafe impl bytemuck::Pod for Particle {}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    /// Position and delta time
+    position: [f32; 4],
+    /// Direction and spread angle
+    direction: [f32; 4],
+    /// Gravity and drag
+    gravity: [f32; 4],
+    /// Speed, lifetime, lifetime variance and time
+    params: [f32; 4],
+    /// Curl noise strength and scale
+    noise: [f32; 4],
+    /// Start size, end size and soft distance
+    size: [f32; 4],
+    /// First spawned particle, number of the spawned particles, capacity and seed
+    spawn: [u32; 4],
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct CameraUniform {
+    proj_view: [[f32; 4]; 4],
+    inverse_proj: [[f32; 4]; 4],
+    right: [f32; 4],
+    up: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for CameraUniform {}
+unsafe impl bytemuck::Pod for CameraUniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawned_particles_wrap_around_the_ring() {
+        let mut emitter = ParticleEmitter {
+            capacity: 8,
+            rate: 10.0,
+            ..Default::default()
+        };
+        assert_eq!(emitter.spawn_range(0.25), (0, 2));
+        assert_eq!(emitter.spawn_range(0.25), (2, 3));
+        emitter.burst(20);
+        assert_eq!(emitter.spawn_range(0.0), (5, 8));
+        assert_eq!(emitter.next, 5);
+
+        emitter.emitting = false;
+        assert_eq!(emitter.spawn_range(1.0), (5, 0));
+    }
+}
@@ -0,0 +1,341 @@
+//! Post-processing chain of the custom fullscreen effects
+use dotrix_core::assets::{Mesh, Shader, Texture};
+use dotrix_core::ecs::Mut;
+use dotrix_core::renderer::{
+    Attachment, BindGroup, Binding, DepthBufferMode, PipelineLayout, PipelineOptions, RenderGraph,
+    RenderPass, Sampler, Stage, TextureFormat, UniformBuffer,
+};
+use dotrix_core::{Assets, Id, Pipeline, Renderer};
+
+use crate::tone_mapping::{FULLSCREEN_TRIANGLE, OVERLAY_PASS, TONE_MAPPING_PASS};
+
+/// Names of the two render graph attachments, the effects ping-pong between
+pub const POST_PROCESS_TARGETS: [&str; 2] = ["post_process_a", "post_process_b"];
+
+/// Prefix of the render graph passes of the effects, followed by the effect name
+pub const POST_PROCESS_PASS: &str = "post_process::";
+
+/// Custom fullscreen effect of the [`PostProcess`] chain
+///
+/// The effect shader binds the uniform with the `data` to the binding 0, the output of the
+/// previous effect to the binding 1, the linear sampler to the binding 2 and the `textures`
+/// starting from the binding 3. Bindings 1 and 2, the `VertexOutput` with the `uv` and the
+/// `vs_main` vertex shader are added by [`PostEffect::add_to_shader`].
+pub struct PostEffect {
+    /// Unique name of the effect
+    pub name: String,
+    /// Effect shader
+    pub shader: Id<Shader>,
+    /// Effect is rendered
+    pub enabled: bool,
+    /// Additional textures of the effect, like a color grading lookup table
+    pub textures: Vec<Id<Texture>>,
+    data: Vec<u8>,
+    changed: bool,
+    uniform: UniformBuffer,
+    pipeline: Pipeline,
+}
+
+impl PostEffect {
+    /// Constructs new effect rendered by the shader
+    pub fn new(name: &str, shader: Id<Shader>) -> Self {
+        Self {
+            name: String::from(name),
+            shader,
+            enabled: true,
+            textures: Vec::new(),
+            data: vec![0; 16],
+            changed: true,
+            uniform: UniformBuffer::default(),
+            pipeline: Pipeline::default(),
+        }
+    }
+
+    /// Sets the uniform data of the effect
+    #[must_use]
+    pub fn with_data(mut self, data: &[u8]) -> Self {
+        self.set_data(data);
+        self
+    }
+
+    /// Adds texture of the effect
+    #[must_use]
+    pub fn with_texture(mut self, texture: Id<Texture>) -> Self {
+        self.textures.push(texture);
+        self
+    }
+
+    /// Updates the uniform data of the effect
+    pub fn set_data(&mut self, data: &[u8]) {
+        if self.data.len() != data.len() {
+            // size of the buffer is a part of the pipeline layout
+            self.pipeline.bindings.unload();
+        }
+        self.data = data.to_vec();
+        self.changed = true;
+    }
+
+    /// Integrates the bindings and the vertex shader of the effects into the shader
+    ///
+    /// The `source` shader code must contain `{{ include(post_process) }}` label.
+    pub fn add_to_shader(source: &str) -> String {
+        source.replace(
+            "{{ include(post_process) }}",
+            include_str!("shaders/post_process.inc.wgsl"),
+        )
+    }
+}
+
+/// Post-processing service
+///
+/// Effects run in the order they were added, after the tone mapping and before the overlay.
+/// Each effect reads the output of the previous one from one of [`POST_PROCESS_TARGETS`] and
+/// writes to the other one, the last effect writes to the former target of the tone mapping:
+/// the surface or the input of the FXAA.
+pub struct PostProcess {
+    effects: Vec<PostEffect>,
+    chain: Vec<String>,
+    target: Option<String>,
+    sampler: Sampler,
+    generation: Option<usize>,
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self {
+            effects: Vec::new(),
+            chain: Vec::new(),
+            target: None,
+            sampler: Sampler::linear_clamp(),
+            generation: None,
+        }
+    }
+}
+
+impl PostProcess {
+    /// Adds the effect to the end of the chain or replaces the effect of the same name
+    pub fn add(&mut self, effect: PostEffect) {
+        match self.effects.iter_mut().find(|e| e.name == effect.name) {
+            Some(existing) => *existing = effect,
+            None => self.effects.push(effect),
+        }
+    }
+
+    /// Removes the effect by name
+    pub fn remove(&mut self, name: &str) -> Option<PostEffect> {
+        let index = self.effects.iter().position(|e| e.name == name)?;
+        Some(self.effects.remove(index))
+    }
+
+    /// Returns the effect by name
+    pub fn get(&self, name: &str) -> Option<&PostEffect> {
+        self.effects.iter().find(|e| e.name == name)
+    }
+
+    /// Returns mutable reference to the effect by name
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut PostEffect> {
+        self.effects.iter_mut().find(|e| e.name == name)
+    }
+
+    /// Returns effects in the order of rendering
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    /// Rebuilds passes of the enabled effects, when the chain changes
+    fn configure(&mut self, graph: &mut RenderGraph) -> bool {
+        let chain = self
+            .effects
+            .iter()
+            .filter(|e| e.enabled)
+            .map(|e| e.name.clone())
+            .collect::<Vec<_>>();
+        if chain == self.chain || graph.pass(TONE_MAPPING_PASS).is_none() {
+            return false;
+        }
+
+        for name in self.chain.iter() {
+            graph.remove_pass(&pass_name(name));
+        }
+        let tone_mapping = graph.pass_mut(TONE_MAPPING_PASS).unwrap();
+        let target = self
+            .target
+            .get_or_insert_with(|| tone_mapping.writes[0].clone())
+            .clone();
+        let targets = ping_pong(&target, chain.len());
+        tone_mapping.writes[0] = targets[0].clone();
+
+        if chain.is_empty() {
+            for name in POST_PROCESS_TARGETS.iter() {
+                graph.remove_attachment(name);
+            }
+        } else {
+            let attachment = Attachment::color(TextureFormat::rgba_f16()).sequential();
+            for name in POST_PROCESS_TARGETS.iter() {
+                graph.add_attachment(name, attachment);
+            }
+        }
+        for (i, name) in chain.iter().enumerate() {
+            graph.add_pass_before(
+                OVERLAY_PASS,
+                RenderPass::new(&pass_name(name))
+                    .reads(&targets[i])
+                    .writes(&targets[i + 1]),
+            );
+        }
+
+        self.chain = chain;
+        true
+    }
+}
+
+/// Returns the targets of the tone mapping and the effects of the chain in the order of writing
+fn ping_pong(target: &str, count: usize) -> Vec<String> {
+    (0..count)
+        .map(|i| String::from(POST_PROCESS_TARGETS[i % 2]))
+        .chain(std::iter::once(String::from(target)))
+        .collect()
+}
+
+fn pass_name(effect: &str) -> String {
+    format!("{}{}", POST_PROCESS_PASS, effect)
+}
+
+/// Post-processing rendering system
+pub fn render(
+    mut post_process: Mut<PostProcess>,
+    mut renderer: Mut<Renderer>,
+    mut assets: Mut<Assets>,
+) {
+    let PostProcess {
+        effects, sampler, ..
+    } = &mut *post_process;
+    renderer.load_sampler(sampler);
+    for effect in effects.iter_mut().filter(|e| e.enabled) {
+        if effect.changed {
+            effect.changed = false;
+            renderer.load_uniform_buffer(&mut effect.uniform, &effect.data);
+        }
+        for &id in effect.textures.iter() {
+            if let Some(texture) = assets.get_mut(id) {
+                texture.load(&renderer);
+            }
+        }
+        if let Some(shader) = assets.get_mut(effect.shader) {
+            shader.load(&renderer);
+        }
+    }
+
+    // the source and target of the effects are changed by the chain
+    let configured = post_process.configure(renderer.graph_mut());
+    let generation = renderer.attachments_generation();
+    if configured || post_process.generation != Some(generation) {
+        post_process.generation = Some(generation);
+        for effect in post_process.effects.iter_mut() {
+            effect.pipeline.bindings.unload();
+        }
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    let PostProcess {
+        effects,
+        target,
+        sampler,
+        ..
+    } = &mut *post_process;
+    let target = match target {
+        Some(target) => target,
+        None => return,
+    };
+    let enabled = effects.iter().filter(|e| e.enabled).count();
+    let targets = ping_pong(target, enabled);
+    for (effect, source) in effects.iter_mut().filter(|e| e.enabled).zip(targets.iter()) {
+        let PostEffect {
+            name,
+            shader,
+            textures,
+            uniform,
+            pipeline,
+            ..
+        } = effect;
+        if pipeline.shader != *shader {
+            pipeline.shader = *shader;
+            pipeline.bindings.unload();
+        }
+        if pipeline.options.pass.is_none() {
+            pipeline.options.pass = Some(pass_name(name));
+        }
+        if !pipeline.cycle(&renderer) || renderer.attachment(source).is_none() {
+            continue;
+        }
+
+        if !pipeline.ready() {
+            let shader = match assets.get(pipeline.shader) {
+                Some(shader) if shader.loaded() => shader,
+                _ => continue,
+            };
+            let textures = match textures
+                .iter()
+                .map(|&id| assets.get(id).filter(|texture| texture.buffer.loaded()))
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(textures) => textures,
+                None => continue,
+            };
+            let mut bindings = vec![
+                Binding::Uniform("PostEffect", Stage::Fragment, uniform),
+                Binding::Attachment("Source", Stage::Fragment, source),
+                Binding::Sampler("Sampler", Stage::Fragment, sampler),
+            ];
+            bindings.extend(
+                textures
+                    .iter()
+                    .map(|texture| Binding::Texture("Texture", Stage::Fragment, &texture.buffer)),
+            );
+            renderer.bind(
+                pipeline,
+                PipelineLayout {
+                    label: name.clone(),
+                    mesh: Some(mesh),
+                    shader,
+                    bindings: &[BindGroup::new("Globals", bindings)],
+                    vertex_buffers: &[],
+                    push_constants: None,
+                    options: PipelineOptions {
+                        depth_buffer_mode: DepthBufferMode::Disabled,
+                        disable_cull_mode: true,
+                        ..Default::default()
+                    },
+                },
+            );
+        }
+
+        renderer.run(pipeline, mesh);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effects_ping_pong_to_the_target() {
+        assert_eq!(ping_pong("surface", 0), vec!["surface"]);
+        assert_eq!(
+            ping_pong("surface", 3),
+            vec![
+                POST_PROCESS_TARGETS[0],
+                POST_PROCESS_TARGETS[1],
+                POST_PROCESS_TARGETS[0],
+                "surface"
+            ]
+        );
+    }
+}
@@ -0,0 +1,50 @@
+struct Bloom {
+    threshold: f32;
+    knee: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_bloom: Bloom;
+
+[[group(0), binding(1)]]
+var r_source: texture_2d<f32>;
+
+[[group(0), binding(2)]]
+var r_sampler: sampler;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+    return out;
+}
+
+// Keeps the part of the color above the threshold with a quadratic soft knee
+fn threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = max(u_bloom.knee, 0.0001);
+    var soft = clamp(brightness - u_bloom.threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+    let contribution = max(soft, brightness - u_bloom.threshold) / max(brightness, 0.0001);
+    return color * contribution;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(r_source));
+    let color = (
+        textureSample(r_source, r_sampler, in.uv + texel * vec2<f32>(-1.0, -1.0)).rgb +
+        textureSample(r_source, r_sampler, in.uv + texel * vec2<f32>(1.0, -1.0)).rgb +
+        textureSample(r_source, r_sampler, in.uv + texel * vec2<f32>(-1.0, 1.0)).rgb +
+        textureSample(r_source, r_sampler, in.uv + texel * vec2<f32>(1.0, 1.0)).rgb
+    ) * 0.25;
+    return vec4<f32>(threshold(color), 1.0);
+}
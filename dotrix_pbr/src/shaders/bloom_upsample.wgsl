@@ -0,0 +1,47 @@
+[[group(0), binding(1)]]
+var r_source: texture_2d<f32>;
+
+[[group(0), binding(2)]]
+var r_level: texture_2d<f32>;
+
+[[group(0), binding(3)]]
+var r_sampler: sampler;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+    return out;
+}
+
+fn sample_source(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    return textureSample(r_source, r_sampler, uv + offset).rgb;
+}
+
+// 3x3 tent filter of the smaller level added to the level of the same size
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(r_source));
+    var blur = sample_source(in.uv, vec2<f32>(0.0, 0.0)) * 4.0;
+    blur = blur + (
+        sample_source(in.uv, texel * vec2<f32>(-1.0, 0.0)) +
+        sample_source(in.uv, texel * vec2<f32>(1.0, 0.0)) +
+        sample_source(in.uv, texel * vec2<f32>(0.0, -1.0)) +
+        sample_source(in.uv, texel * vec2<f32>(0.0, 1.0))
+    ) * 2.0;
+    blur = blur +
+        sample_source(in.uv, texel * vec2<f32>(-1.0, -1.0)) +
+        sample_source(in.uv, texel * vec2<f32>(1.0, -1.0)) +
+        sample_source(in.uv, texel * vec2<f32>(-1.0, 1.0)) +
+        sample_source(in.uv, texel * vec2<f32>(1.0, 1.0));
+    let level = textureSample(r_level, r_sampler, in.uv).rgb;
+    return vec4<f32>(level + blur / 16.0, 1.0);
+}
@@ -0,0 +1,53 @@
+[[group(0), binding(0)]]
+var w_lut: texture_storage_2d<rgba16float, write>;
+
+{{ include(importance_sample) }}
+
+let SAMPLE_COUNT: u32 = 512u;
+
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    // the image based lighting remaps the roughness differently from the analytic lights
+    let k = roughness * roughness / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+// Scale and bias of the Fresnel reflectance at the normal incidence, integrated over the
+// GGX distribution for the view angle (x) and roughness (y)
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(w_lut);
+    if (i32(id.x) >= size.x || i32(id.y) >= size.y) {
+        return;
+    }
+
+    let uv = (vec2<f32>(id.xy) + vec2<f32>(0.5, 0.5)) / vec2<f32>(size);
+    let n_dot_v = uv.x;
+    let roughness = uv.y;
+    let view = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    var scale: f32 = 0.0;
+    var bias: f32 = 0.0;
+    var i: u32 = 0u;
+    loop {
+        if (i >= SAMPLE_COUNT) {
+            break;
+        }
+        let halfway = importance_sample_ggx(i, SAMPLE_COUNT, roughness);
+        let light = normalize(2.0 * dot(view, halfway) * halfway - view);
+        let n_dot_l = max(light.z, 0.0);
+        let n_dot_h = max(halfway.z, 0.0);
+        let v_dot_h = max(dot(view, halfway), 0.0);
+        if (n_dot_l > 0.0) {
+            let geometry = geometry_schlick_ggx(n_dot_v, roughness)
+                * geometry_schlick_ggx(n_dot_l, roughness);
+            let visibility = geometry * v_dot_h / max(n_dot_h * n_dot_v, 0.0001);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+            scale = scale + (1.0 - fresnel) * visibility;
+            bias = bias + fresnel * visibility;
+        }
+        i = i + 1u;
+    }
+
+    let count = f32(SAMPLE_COUNT);
+    textureStore(w_lut, vec2<i32>(id.xy), vec4<f32>(scale / count, bias / count, 0.0, 1.0));
+}
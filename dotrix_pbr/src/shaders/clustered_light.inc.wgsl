@@ -0,0 +1,49 @@
+let CLUSTERED_LIGHTS: bool = true;
+
+fn calculate_clustered_lights(
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    camera_direction: vec3<f32>,
+    fresnel_schlick_0: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let offset = cluster_offset(position);
+    let count = s_cluster_lists.data[offset];
+    var light_color: vec3<f32> = vec3<f32>(0.);
+
+    var i: u32 = 0u;
+    for (i = 0u; i < count; i = i + 1u) {
+      let index = s_cluster_lists.data[offset + 1u + i];
+      let light = s_clustered_lights.data[index];
+      var light_result: LightCalcOutput;
+      if (light.color.w > 0.5) {
+        var spot: SpotLight;
+        spot.position = vec4<f32>(light.position.xyz, 1.0);
+        spot.direction = vec4<f32>(light.direction.xyz, 1.0);
+        spot.color = vec4<f32>(light.color.rgb, 1.0);
+        spot.cut_off = light.params.x;
+        spot.outer_cut_off = light.params.y;
+        light_result = calculate_spot(spot, position, normal);
+      } else {
+        var point: PointLight;
+        point.position = vec4<f32>(light.position.xyz, 1.0);
+        point.color = vec4<f32>(light.color.rgb, 1.0);
+        point.attenuation = vec4<f32>(light.params.xyz, light.direction.w);
+        light_result = calculate_point(point, position, normal);
+      }
+      light_result.radiance = light_result.radiance
+        * calculate_local_shadow(light.direction.w, position);
+      light_color = light_color + pbr(
+        light_result,
+        camera_direction,
+        normal,
+        fresnel_schlick_0,
+        albedo,
+        metallic,
+        roughness
+      );
+    }
+    return light_color;
+}
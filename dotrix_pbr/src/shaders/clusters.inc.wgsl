@@ -0,0 +1,56 @@
+struct Clusters {
+    view: mat4x4<f32>;
+    proj: mat4x4<f32>;
+    inverse_proj: mat4x4<f32>;
+    // near plane, far plane, number of lights
+    params: vec4<f32>;
+};
+
+struct ClusteredLight {
+    // range in w, zero if the light is not attenuated
+    position: vec4<f32>;
+    // 1.0 for spot lights in w
+    color: vec4<f32>;
+    // shadow slot or -1.0 in w
+    direction: vec4<f32>;
+    // attenuation of point lights, cut offs of spot lights
+    params: vec4<f32>;
+};
+
+struct ClusteredLights {
+    data: [[stride(64)]] array<ClusteredLight>;
+};
+
+struct ClusterLists {
+    data: [[stride(4)]] array<u32>;
+};
+
+[[group({{ bind_group }}), binding({{ binding }})]]
+var<uniform> u_clusters: Clusters;
+
+[[group({{ bind_group }}), binding({{ lights_binding }})]]
+var<storage, read> s_clustered_lights: ClusteredLights;
+
+[[group({{ bind_group }}), binding({{ lists_binding }})]]
+var<storage, read_write> s_cluster_lists: ClusterLists;
+
+let CLUSTERS_X: u32 = {{ clusters_x }};
+let CLUSTERS_Y: u32 = {{ clusters_y }};
+let CLUSTERS_Z: u32 = {{ clusters_z }};
+let CLUSTER_SIZE: u32 = {{ cluster_size }};
+
+// Returns offset of the light list of the cluster containing the world space position
+fn cluster_offset(position: vec3<f32>) -> u32 {
+    let view_position = u_clusters.view * vec4<f32>(position, 1.0);
+    let clip = u_clusters.proj * view_position;
+    let ndc = clip.xy / clip.w;
+    let near = u_clusters.params.x;
+    let far = u_clusters.params.y;
+
+    let x = u32(clamp((ndc.x * 0.5 + 0.5) * f32(CLUSTERS_X), 0.0, f32(CLUSTERS_X - 1u)));
+    let y = u32(clamp((ndc.y * 0.5 + 0.5) * f32(CLUSTERS_Y), 0.0, f32(CLUSTERS_Y - 1u)));
+    let slice = log(max(-view_position.z, near) / near) / log(far / near) * f32(CLUSTERS_Z);
+    let z = u32(clamp(slice, 0.0, f32(CLUSTERS_Z - 1u)));
+
+    return ((z * CLUSTERS_Y + y) * CLUSTERS_X + x) * CLUSTER_SIZE;
+}
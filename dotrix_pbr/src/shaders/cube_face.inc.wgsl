@@ -0,0 +1,50 @@
+[[group(0), binding(0)]]
+var r_source: texture_cube<f32>;
+
+[[group(0), binding(1)]]
+var r_sampler: sampler;
+
+struct Params {
+    // mip level, number of levels, level size, 0
+    level: vec4<f32>;
+};
+
+[[group(0), binding(2)]]
+var<uniform> u_params: Params;
+
+[[group(0), binding(3)]]
+var w_faces: texture_storage_2d_array<rgba16float, write>;
+
+let PI: f32 = 3.14159265359;
+
+// Direction of the texel of the cube face in the order +X, -X, +Y, -Y, +Z, -Z
+fn face_direction(face: u32, texel: vec2<u32>) -> vec3<f32> {
+    let uv = (vec2<f32>(texel) + vec2<f32>(0.5, 0.5)) / u_params.level.z;
+    let s = uv.x * 2.0 - 1.0;
+    let t = uv.y * 2.0 - 1.0;
+    var direction: vec3<f32> = vec3<f32>(-s, -t, -1.0);
+    if (face == 0u) {
+        direction = vec3<f32>(1.0, -t, -s);
+    } else if (face == 1u) {
+        direction = vec3<f32>(-1.0, -t, s);
+    } else if (face == 2u) {
+        direction = vec3<f32>(s, 1.0, t);
+    } else if (face == 3u) {
+        direction = vec3<f32>(s, -1.0, -t);
+    } else if (face == 4u) {
+        direction = vec3<f32>(s, -t, 1.0);
+    }
+    return normalize(direction);
+}
+
+// Orthonormal basis around the normal
+fn tangent_to_world(tangent_direction: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var up: vec3<f32> = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(normal.y) > 0.999) {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return tangent * tangent_direction.x + bitangent * tangent_direction.y
+        + normal * tangent_direction.z;
+}
@@ -0,0 +1,83 @@
+struct Deferred {
+    inverse_proj_view: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_deferred: Deferred;
+
+// albedo and ambient occlusion
+[[group(0), binding(1)]]
+var r_albedo: texture_2d<f32>;
+
+[[group(0), binding(2)]]
+var r_normal: texture_2d<f32>;
+
+// roughness and metallic
+[[group(0), binding(3)]]
+var r_material: texture_2d<f32>;
+
+[[group(0), binding(4)]]
+var r_depth: texture_depth_2d;
+
+{{ include(ssao) }}
+
+{{ include(shadow) }}
+
+{{ include(clusters) }}
+
+{{ include(environment) }}
+
+{{ include(light) }}
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+    return out;
+}
+
+struct FragmentOutput {
+    [[location(0)]] color: vec4<f32>;
+    [[builtin(frag_depth)]] depth: f32;
+};
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let texel = vec2<i32>(in.position.xy);
+    let depth = textureLoad(r_depth, texel, 0);
+    let albedo = textureLoad(r_albedo, texel, 0);
+    let normal = textureLoad(r_normal, texel, 0).xyz;
+    let material = textureLoad(r_material, texel, 0);
+
+    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth, 1.0);
+    let world_position = u_deferred.inverse_proj_view * ndc;
+    let position = world_position.xyz / world_position.w;
+
+    let color = calculate_shadowed_lighting(
+        position,
+        normal,
+        albedo.rgb,
+        material.r,
+        material.g,
+        albedo.a * calculate_ssao(in.position),
+        calculate_shadow(position),
+    );
+
+    // nothing was rendered into the G-buffer, the clear color stays
+    if (depth >= 1.0) {
+        discard;
+    }
+
+    var out: FragmentOutput;
+    out.color = color;
+    out.depth = depth;
+    return out;
+}
@@ -0,0 +1,93 @@
+struct DepthOfField {
+    inverse_proj: mat4x4<f32>;
+    // focus distance, aperture, maximal blur radius in pixels, autofocus
+    params: vec4<f32>;
+    // autofocus point, number of samples, 0
+    focus: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_dof: DepthOfField;
+
+[[group(0), binding(1)]]
+var r_scene: texture_2d<f32>;
+
+[[group(0), binding(2)]]
+var r_depth: texture_depth_2d;
+
+[[group(0), binding(3)]]
+var r_sampler: sampler;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+    return out;
+}
+
+// Distance from the camera to the scene at the screen position
+fn scene_distance(uv: vec2<f32>) -> f32 {
+    let size = textureDimensions(r_depth);
+    let texel = clamp(
+        vec2<i32>(uv * vec2<f32>(size)),
+        vec2<i32>(0, 0),
+        size - vec2<i32>(1, 1)
+    );
+    let depth = textureLoad(r_depth, texel, 0);
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = u_dof.inverse_proj * ndc;
+    return length(position.xyz / position.w);
+}
+
+// Radius of the blur in pixels
+fn circle_of_confusion(distance: f32, focus_distance: f32) -> f32 {
+    let aperture = u_dof.params.y;
+    let coc = aperture * abs(distance - focus_distance) / max(distance, 0.0001);
+    return clamp(coc, 0.0, 1.0) * u_dof.params.z;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSampleLevel(r_scene, r_sampler, in.uv, 0.0).rgb;
+
+    var focus_distance: f32 = u_dof.params.x;
+    if (u_dof.params.w > 0.5) {
+        focus_distance = scene_distance(u_dof.focus.xy);
+    }
+
+    let coc = circle_of_confusion(scene_distance(in.uv), focus_distance);
+    if (coc < 0.5) {
+        return vec4<f32>(color, 1.0);
+    }
+
+    // samples spread by the golden angle over the disk of the circle of confusion
+    let texel = 1.0 / vec2<f32>(textureDimensions(r_scene));
+    let count = u_dof.focus.z;
+    var sum: vec3<f32> = color;
+    var weight: f32 = 1.0;
+    var i: f32 = 0.0;
+    loop {
+        if (i >= count) {
+            break;
+        }
+        let radius = sqrt((i + 0.5) / count) * coc;
+        let angle = i * 2.3999632;
+        let uv = in.uv + vec2<f32>(cos(angle), sin(angle)) * radius * texel;
+        let sample_coc = circle_of_confusion(scene_distance(uv), focus_distance);
+        // samples in focus do not bleed into the blurred pixels around them
+        let sample_weight = clamp(sample_coc - radius + 1.0, 0.0, 1.0);
+        sum = sum + textureSampleLevel(r_scene, r_sampler, uv, 0.0).rgb * sample_weight;
+        weight = weight + sample_weight;
+        i = i + 1.0;
+    }
+
+    return vec4<f32>(sum / weight, 1.0);
+}
@@ -0,0 +1,51 @@
+struct Level {
+    // offset, width and height of the source level, 0
+    source: vec4<u32>;
+    // offset, width and height of the target level, index of the target level
+    target: vec4<u32>;
+};
+
+struct Pyramid {
+    depth: [[stride(4)]] array<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_level: Level;
+
+[[group(0), binding(1)]]
+var r_depth: texture_depth_2d;
+
+[[group(0), binding(2)]]
+var<storage, read_write> s_pyramid: Pyramid;
+
+fn source_depth(texel_x: u32, texel_y: u32) -> f32 {
+    let x = min(texel_x, u_level.source.y - 1u);
+    let y = min(texel_y, u_level.source.z - 1u);
+    // the first level is reduced from the depth attachment
+    if (u_level.target.w == 0u) {
+        return textureLoad(r_depth, vec2<i32>(i32(x), i32(y)), 0);
+    }
+    return s_pyramid.depth[u_level.source.x + y * u_level.source.y + x];
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    let width = u_level.target.y;
+    let height = u_level.target.z;
+    if (global_id.x >= width || global_id.y >= height) {
+        return;
+    }
+
+    // the last texel of the odd sized source is covered by the last texel of the target
+    let extent_x = select(2u, 3u, global_id.x == width - 1u && (u_level.source.y & 1u) == 1u);
+    let extent_y = select(2u, 3u, global_id.y == height - 1u && (u_level.source.z & 1u) == 1u);
+
+    // the farthest depth of the covered texels is kept, so the occlusion is conservative
+    var depth = 0.0;
+    for (var j = 0u; j < extent_y; j = j + 1u) {
+        for (var i = 0u; i < extent_x; i = i + 1u) {
+            depth = max(depth, source_depth(global_id.x * 2u + i, global_id.y * 2u + j));
+        }
+    }
+    s_pyramid.depth[u_level.target.x + global_id.y * width + global_id.x] = depth;
+}
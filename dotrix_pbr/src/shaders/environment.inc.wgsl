@@ -0,0 +1,55 @@
+struct Environment {
+    // intensity, number of the prefiltered mip levels, 0, 0
+    params: vec4<f32>;
+};
+
+[[group({{ bind_group }}), binding({{ binding }})]]
+var<uniform> u_environment: Environment;
+
+[[group({{ bind_group }}), binding({{ irradiance_binding }})]]
+var r_irradiance: texture_cube<f32>;
+
+[[group({{ bind_group }}), binding({{ prefiltered_binding }})]]
+var r_prefiltered: texture_cube<f32>;
+
+[[group({{ bind_group }}), binding({{ brdf_lut_binding }})]]
+var r_brdf_lut: texture_2d<f32>;
+
+[[group({{ bind_group }}), binding({{ sampler_binding }})]]
+var r_environment_sampler: sampler;
+
+// Image based lighting: diffuse irradiance and specular reflection of the environment
+fn calculate_environment(
+    normal: vec3<f32>,
+    camera_direction: vec3<f32>,
+    fresnel_schlick_0: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let n_dot_v = max(dot(normal, camera_direction), 0.0);
+    let fresnel = fresnel_schlick_0
+        + (max(vec3<f32>(1.0 - roughness), fresnel_schlick_0) - fresnel_schlick_0)
+        * pow(1.0 - n_dot_v, 5.0);
+
+    let irradiance = textureSampleLevel(r_irradiance, r_environment_sampler, normal, 0.0).rgb;
+    let diffuse = (vec3<f32>(1.0) - fresnel) * (1.0 - metallic) * irradiance * albedo;
+
+    let reflection = reflect(-camera_direction, normal);
+    let max_lod = max(u_environment.params.y - 1.0, 0.0);
+    let prefiltered = textureSampleLevel(
+        r_prefiltered,
+        r_environment_sampler,
+        reflection,
+        roughness * max_lod
+    ).rgb;
+    let brdf = textureSampleLevel(
+        r_brdf_lut,
+        r_environment_sampler,
+        vec2<f32>(n_dot_v, roughness),
+        0.0
+    ).rg;
+    let specular = prefiltered * (fresnel * brdf.x + brdf.y);
+
+    return (diffuse + specular) * u_environment.params.x;
+}
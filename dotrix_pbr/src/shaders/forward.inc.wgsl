@@ -0,0 +1,13 @@
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let surface = calculate_surface(in);
+    return calculate_shadowed_lighting(
+        in.world_position.xyz,
+        surface.normal,
+        surface.albedo,
+        surface.roughness,
+        surface.metallic,
+        surface.ao * calculate_ssao(in.position),
+        calculate_shadow(in.world_position.xyz),
+    );
+}
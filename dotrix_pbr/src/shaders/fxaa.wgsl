@@ -0,0 +1,79 @@
+struct Fxaa {
+    // contrast threshold, relative contrast threshold, maximal span of the blur, 0
+    params: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_fxaa: Fxaa;
+
+[[group(0), binding(1)]]
+var r_source: texture_2d<f32>;
+
+[[group(0), binding(2)]]
+var r_sampler: sampler;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+    return out;
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+fn sample(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(r_source, r_sampler, uv, 0.0).rgb;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(r_source));
+    let color = sample(in.uv);
+    let luma_m = luma(color);
+    let luma_nw = luma(sample(in.uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample(in.uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample(in.uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample(in.uv + vec2<f32>(1.0, 1.0) * texel));
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // low contrast pixels are not on the edges
+    if (luma_max - luma_min < max(u_fxaa.params.x, luma_max * u_fxaa.params.y)) {
+        return vec4<f32>(color, 1.0);
+    }
+
+    // blur along the edge
+    var direction: vec2<f32> = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.03125, 0.0078125);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    let span = u_fxaa.params.z;
+    direction = clamp(direction * scale, vec2<f32>(-span), vec2<f32>(span)) * texel;
+
+    let color_a = 0.5 * (
+        sample(in.uv + direction * (1.0 / 3.0 - 0.5))
+        + sample(in.uv + direction * (2.0 / 3.0 - 0.5))
+    );
+    let color_b = color_a * 0.5 + 0.25 * (
+        sample(in.uv - direction * 0.5)
+        + sample(in.uv + direction * 0.5)
+    );
+    let luma_b = luma(color_b);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        return vec4<f32>(color_a, 1.0);
+    }
+    return vec4<f32>(color_b, 1.0);
+}
@@ -0,0 +1,15 @@
+struct GBufferOutput {
+    [[location(0)]] albedo: vec4<f32>;
+    [[location(1)]] normal: vec4<f32>;
+    [[location(2)]] material: vec4<f32>;
+};
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> GBufferOutput {
+    let surface = calculate_surface(in);
+    var out: GBufferOutput;
+    out.albedo = vec4<f32>(surface.albedo, surface.ao);
+    out.normal = vec4<f32>(normalize(surface.normal), 0.0);
+    out.material = vec4<f32>(surface.roughness, surface.metallic, 0.0, 1.0);
+    return out;
+}
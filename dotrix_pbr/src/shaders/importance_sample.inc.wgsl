@@ -0,0 +1,19 @@
+// Van der Corput sequence of the Hammersley point set
+fn radical_inverse(index: u32) -> f32 {
+    var bits: u32 = (index << 16u) | (index >> 16u);
+    bits = ((bits & 1431655765u) << 1u) | ((bits & 2863311530u) >> 1u);
+    bits = ((bits & 858993459u) << 2u) | ((bits & 3435973836u) >> 2u);
+    bits = ((bits & 252645135u) << 4u) | ((bits & 4042322160u) >> 4u);
+    bits = ((bits & 16711935u) << 8u) | ((bits & 4278255360u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+// Halfway vector of the GGX distribution in the tangent space
+fn importance_sample_ggx(index: u32, count: u32, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * 3.14159265359 * f32(index) / f32(count);
+    let x = radical_inverse(index);
+    let cos_theta = sqrt((1.0 - x) / (1.0 + (a * a - 1.0) * x));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
@@ -0,0 +1,42 @@
+{{ include(cube_face) }}
+
+// Cosine weighted convolution of the environment over the hemisphere of the normal
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = u32(u_params.level.z);
+    if (id.x >= size || id.y >= size) {
+        return;
+    }
+
+    let normal = face_direction(id.z, id.xy);
+    let step = 0.05;
+    var irradiance: vec3<f32> = vec3<f32>(0.0);
+    var count: f32 = 0.0;
+    var phi: f32 = 0.0;
+    loop {
+        if (phi >= 2.0 * PI) {
+            break;
+        }
+        var theta: f32 = 0.0;
+        loop {
+            if (theta >= 0.5 * PI) {
+                break;
+            }
+            let tangent_direction = vec3<f32>(
+                sin(theta) * cos(phi),
+                sin(theta) * sin(phi),
+                cos(theta)
+            );
+            let direction = tangent_to_world(tangent_direction, normal);
+            // lower mip levels are not available, so the samples are taken from the top one
+            let color = textureSampleLevel(r_source, r_sampler, direction, 0.0).rgb;
+            irradiance = irradiance + color * cos(theta) * sin(theta);
+            count = count + 1.0;
+            theta = theta + step;
+        }
+        phi = phi + step;
+    }
+
+    let color = PI * irradiance / count;
+    textureStore(w_faces, vec2<i32>(id.xy), i32(id.z), vec4<f32>(color, 1.0));
+}
@@ -2,6 +2,8 @@
 let MAX_LIGHTS_COUNT: u32 = {{ max_lights_count }};
 let PI: f32 = 3.14159;
 
+{{ local_shadow }}
+
 struct LightCalcOutput {
   light_direction: vec3<f32>;
   radiance: vec3<f32>;
@@ -20,7 +22,7 @@ struct PointLight {
     // a_constant: f32;
     // a_linear: f32;
     // a_quadratic: f32;
-    // unused: f32;
+    // shadow: f32;
 };
 
 struct SimpleLight {
@@ -34,6 +36,7 @@ struct SpotLight {
     color: vec4<f32>;
     cut_off: f32;
     outer_cut_off: f32;
+    shadow: f32;
 };
 
 struct Light {
@@ -183,13 +186,20 @@ fn pbr(
   return (refraction_diffuse_fraction * albedo / PI + specular) * light_out.radiance * n_dot_l;
 }
 
-fn calculate_lighting(
+{{ clustered_lights }}
+
+{{ environment }}
+
+// `shadow` is the visibility of the first directional light, point and spot lights are shadowed
+// by `calculate_local_shadow`
+fn calculate_shadowed_lighting(
     position: vec3<f32>,
     normal_in: vec3<f32>,
     albedo: vec3<f32>,
     roughness: f32,
     metallic: f32,
     ao: f32,
+    shadow: f32,
 ) -> vec4<f32> {
     let camera_position: vec3<f32> = u_light.camera_position.xyz;
     var light_color: vec3<f32> = vec3<f32>(0.);
@@ -204,10 +214,13 @@ fn calculate_lighting(
     var i: u32 = 0u;
     var count: u32 = min(u32(u_light.count.x), MAX_LIGHTS_COUNT);
     for (i = 0u; i< count; i = i + 1u) {
-      let light_result = calculate_directional(
+      var light_result = calculate_directional(
           u_light.directional[i],
           normal
       );
+      if (i == 0u) {
+        light_result.radiance = light_result.radiance * shadow;
+      }
       light_color = light_color + pbr(
         light_result,
         camera_direction,
@@ -218,14 +231,16 @@ fn calculate_lighting(
         roughness
       );
     }
-    // Point light
-    count = min(u32(u_light.count.y), MAX_LIGHTS_COUNT);
+    // Point light, clustered lights replace the point and spot lights of the uniform
+    count = select(min(u32(u_light.count.y), MAX_LIGHTS_COUNT), 0u, CLUSTERED_LIGHTS);
     for (i = 0u; i< count; i = i + 1u) {
-      let light_result = calculate_point(
+      var light_result = calculate_point(
           u_light.point[i],
           position,
           normal
       );
+      light_result.radiance = light_result.radiance
+        * calculate_local_shadow(u_light.point[i].attenuation.w, position);
       light_color = light_color + pbr(
         light_result,
         camera_direction,
@@ -255,13 +270,15 @@ fn calculate_lighting(
       );
     }
     // Spot light
-    count = min(u32(u_light.count.w), MAX_LIGHTS_COUNT);
+    count = select(min(u32(u_light.count.w), MAX_LIGHTS_COUNT), 0u, CLUSTERED_LIGHTS);
     for (i = 0u; i< count; i = i + 1u) {
-      let light_result = calculate_spot(
+      var light_result = calculate_spot(
           u_light.spot[i],
           position,
           normal
       );
+      light_result.radiance = light_result.radiance
+        * calculate_local_shadow(u_light.spot[i].shadow, position);
       light_color = light_color + pbr(
         light_result,
         camera_direction,
@@ -273,13 +290,39 @@ fn calculate_lighting(
       );
     }
 
+    light_color = light_color + calculate_clustered_lights(
+      position,
+      normal,
+      camera_direction,
+      fresnel_schlick_0,
+      albedo,
+      metallic,
+      roughness
+    );
+
     // Ambient
     let ambient = u_light.ambient.xyz * albedo * ao;
-    light_color = light_color + ambient;
-
-    // Gamma correct
-    light_color = light_color / (light_color + vec3<f32>(1.0));
-    light_color = pow(light_color, vec3<f32>(1.0/2.2));
+    let environment = calculate_environment(
+      normal,
+      camera_direction,
+      fresnel_schlick_0,
+      albedo,
+      metallic,
+      roughness
+    );
+    light_color = light_color + ambient + environment * ao;
 
+    // HDR radiance, tone mapped by the tone mapping pass
     return vec4<f32>(light_color, 1.0);
 }
+
+fn calculate_lighting(
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    albedo: vec3<f32>,
+    roughness: f32,
+    metallic: f32,
+    ao: f32,
+) -> vec4<f32> {
+    return calculate_shadowed_lighting(position, normal, albedo, roughness, metallic, ao, 1.0);
+}
@@ -0,0 +1,76 @@
+{{ include(clusters) }}
+
+// Returns view space point of the tile corner at the depth
+fn tile_corner(ndc: vec2<f32>, depth: f32) -> vec3<f32> {
+    let far = u_clusters.inverse_proj * vec4<f32>(ndc, 1.0, 1.0);
+    let direction = far.xyz / far.w;
+    return direction * (depth / -direction.z);
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    let cluster = global_id.x;
+    if (cluster >= CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z) {
+        return;
+    }
+    let x = cluster % CLUSTERS_X;
+    let y = (cluster / CLUSTERS_X) % CLUSTERS_Y;
+    let z = cluster / (CLUSTERS_X * CLUSTERS_Y);
+
+    // exponential depth slices of the view frustum
+    let near = u_clusters.params.x;
+    let far = u_clusters.params.y;
+    let slice_near = near * pow(far / near, f32(z) / f32(CLUSTERS_Z));
+    let slice_far = near * pow(far / near, f32(z + 1u) / f32(CLUSTERS_Z));
+
+    let tile_size = vec2<f32>(2.0 / f32(CLUSTERS_X), 2.0 / f32(CLUSTERS_Y));
+    let ndc_min = vec2<f32>(f32(x), f32(y)) * tile_size - vec2<f32>(1.0);
+    let ndc_max = ndc_min + tile_size;
+
+    var aabb_min: vec3<f32> = tile_corner(ndc_min, slice_near);
+    var aabb_max: vec3<f32> = aabb_min;
+    var corner: u32 = 1u;
+    for (corner = 1u; corner < 8u; corner = corner + 1u) {
+        let ndc = vec2<f32>(
+            select(ndc_min.x, ndc_max.x, (corner & 1u) == 1u),
+            select(ndc_min.y, ndc_max.y, (corner & 2u) == 2u),
+        );
+        let point = tile_corner(ndc, select(slice_near, slice_far, (corner & 4u) == 4u));
+        aabb_min = min(aabb_min, point);
+        aabb_max = max(aabb_max, point);
+    }
+    let center = (aabb_min + aabb_max) * 0.5;
+    let radius = length(aabb_max - center);
+
+    let offset = cluster * CLUSTER_SIZE;
+    let lights_count = u32(u_clusters.params.z);
+    var count: u32 = 0u;
+    var i: u32 = 0u;
+    for (i = 0u; i < lights_count && count < CLUSTER_SIZE - 1u; i = i + 1u) {
+        let light = s_clustered_lights.data[i];
+        let position = (u_clusters.view * vec4<f32>(light.position.xyz, 1.0)).xyz;
+        let range = light.position.w;
+
+        var visible: bool = true;
+        if (range > 0.0) {
+            let closest = clamp(position, aabb_min, aabb_max) - position;
+            visible = dot(closest, closest) <= range * range;
+        }
+        if (visible && light.color.w > 0.5) {
+            // cone of the outer cut off against the bounding sphere of the cluster
+            let direction = normalize((u_clusters.view * vec4<f32>(light.direction.xyz, 0.0)).xyz);
+            let cos_angle = light.params.y;
+            let sin_angle = sqrt(max(1.0 - cos_angle * cos_angle, 0.0));
+            let to_center = center - position;
+            let axis_distance = dot(to_center, direction);
+            let closest = cos_angle * sqrt(max(dot(to_center, to_center) - axis_distance * axis_distance, 0.0))
+                - axis_distance * sin_angle;
+            visible = closest <= radius && axis_distance >= -radius;
+        }
+        if (visible) {
+            s_cluster_lists.data[offset + 1u + count] = i;
+            count = count + 1u;
+        }
+    }
+    s_cluster_lists.data[offset] = count;
+}
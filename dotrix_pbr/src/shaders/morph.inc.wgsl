@@ -0,0 +1,34 @@
+struct MorphTargets {
+    // position and normal deltas of the targets of every vertex
+    deltas: [[stride(16)]] array<vec4<f32>>;
+};
+[[group(2), binding(0)]]
+var<storage, read> s_morph_targets: MorphTargets;
+
+struct MorphWeights {
+    weights: [[stride(4)]] array<f32>;
+};
+[[group(2), binding(1)]]
+var<storage, read> s_morph_weights: MorphWeights;
+
+// Blends the deltas of the morph targets at the offset (0 for positions, 1 for normals)
+fn morph(vertex: u32, offset: u32, value: vec3<f32>) -> vec3<f32> {
+    let targets = arrayLength(&s_morph_weights.weights);
+    var result = value;
+    for (var i: u32 = 0u; i < targets; i = i + 1u) {
+        let weight = s_morph_weights.weights[i];
+        // meshes without targets have a single zero weight and no deltas to read
+        if (weight != 0.0) {
+            result = result + weight * s_morph_targets.deltas[(vertex * targets + i) * 2u + offset].xyz;
+        }
+    }
+    return result;
+}
+
+fn morph_position(vertex: u32, position: vec3<f32>) -> vec3<f32> {
+    return morph(vertex, 0u, position);
+}
+
+fn morph_normal(vertex: u32, normal: vec3<f32>) -> vec3<f32> {
+    return morph(vertex, 1u, normal);
+}
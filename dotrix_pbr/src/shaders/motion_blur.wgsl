@@ -0,0 +1,64 @@
+struct MotionBlur {
+    // intensity, maximal length of the blur in pixels, number of samples, 0
+    params: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_motion_blur: MotionBlur;
+
+[[group(0), binding(1)]]
+var r_scene: texture_2d<f32>;
+
+[[group(0), binding(2)]]
+var r_velocity: texture_2d<f32>;
+
+[[group(0), binding(3)]]
+var r_sampler: sampler;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let size = vec2<f32>(textureDimensions(r_scene));
+    let color = textureSampleLevel(r_scene, r_sampler, in.uv, 0.0).rgb;
+
+    // blur of the velocity in pixels is limited, so the fast objects do not smear over the screen
+    var velocity: vec2<f32> = textureLoad(r_velocity, vec2<i32>(in.position.xy), 0).xy
+        * u_motion_blur.params.x;
+    let length_px = length(velocity * size);
+    let max_length = u_motion_blur.params.y;
+    if (length_px < 0.5) {
+        return vec4<f32>(color, 1.0);
+    }
+    if (length_px > max_length) {
+        velocity = velocity * (max_length / length_px);
+    }
+
+    // samples along the motion, centered on the pixel
+    let count = u_motion_blur.params.z;
+    var sum: vec3<f32> = color;
+    var i: f32 = 0.0;
+    loop {
+        if (i >= count) {
+            break;
+        }
+        let offset = (i + 0.5) / count - 0.5;
+        sum = sum + textureSampleLevel(r_scene, r_sampler, in.uv + velocity * offset, 0.0).rgb;
+        i = i + 1.0;
+    }
+
+    return vec4<f32>(sum / (count + 1.0), 1.0);
+}
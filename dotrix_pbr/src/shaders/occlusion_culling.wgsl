@@ -0,0 +1,115 @@
+struct Culling {
+    proj_view: mat4x4<f32>;
+    // width and height of the depth, number of the pyramid levels, 0
+    params: vec4<u32>;
+    // offset, width and height of the pyramid levels
+    levels: [[stride(16)]] array<vec4<u32>, 16>;
+};
+
+struct Pyramid {
+    depth: [[stride(4)]] array<f32>;
+};
+
+struct Spheres {
+    // center and radius
+    data: [[stride(16)]] array<vec4<f32>>;
+};
+
+struct Visible {
+    indices: [[stride(4)]] array<u32>;
+};
+
+struct Indirect {
+    // arguments of the indirect draw, the number of instances is the second one
+    args: [[stride(4)]] array<atomic<u32>>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_culling: Culling;
+
+[[group(0), binding(1)]]
+var<storage, read_write> s_pyramid: Pyramid;
+
+[[group(1), binding(0)]]
+var<storage, read> s_spheres: Spheres;
+
+[[group(1), binding(1)]]
+var<storage, read_write> s_visible: Visible;
+
+[[group(1), binding(2)]]
+var<storage, read_write> s_indirect: Indirect;
+
+fn is_visible(sphere: vec4<f32>) -> bool {
+    let radius = sphere.w;
+    var min_ndc = vec3<f32>(1000000.0);
+    var max_ndc = vec3<f32>(-1000000.0);
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        let offset = vec3<f32>(
+            select(-radius, radius, (i & 1u) != 0u),
+            select(-radius, radius, (i & 2u) != 0u),
+            select(-radius, radius, (i & 4u) != 0u)
+        );
+        let clip = u_culling.proj_view * vec4<f32>(sphere.xyz + offset, 1.0);
+        // spheres crossing the camera plane are always visible
+        if (clip.w <= 0.0) {
+            return true;
+        }
+        let ndc = clip.xyz / clip.w;
+        min_ndc = min(min_ndc, ndc);
+        max_ndc = max(max_ndc, ndc);
+    }
+
+    // view frustum
+    if (max_ndc.x < -1.0 || min_ndc.x > 1.0 || max_ndc.y < -1.0 || min_ndc.y > 1.0
+        || min_ndc.z > 1.0) {
+        return false;
+    }
+
+    let levels = u_culling.params.z;
+    if (levels == 0u) {
+        return true;
+    }
+
+    // rectangle of the sphere in the pixels of the depth
+    let size = vec2<f32>(f32(u_culling.params.x), f32(u_culling.params.y));
+    let rect_min = clamp(
+        vec2<f32>(min_ndc.x * 0.5 + 0.5, 0.5 - max_ndc.y * 0.5),
+        vec2<f32>(0.0),
+        vec2<f32>(1.0)
+    ) * size;
+    let rect_max = clamp(
+        vec2<f32>(max_ndc.x * 0.5 + 0.5, 0.5 - min_ndc.y * 0.5),
+        vec2<f32>(0.0),
+        vec2<f32>(1.0)
+    ) * size;
+    let extent = max(max(rect_max.x - rect_min.x, rect_max.y - rect_min.y), 1.0);
+
+    // texels of the level cover the rectangle, the first level has the half resolution
+    let level = min(u32(max(ceil(log2(extent)) - 1.0, 0.0)), levels - 1u);
+    let info = u_culling.levels[level];
+    let texel = f32(1u << (level + 1u));
+    let x0 = min(u32(rect_min.x / texel), info.y - 1u);
+    let y0 = min(u32(rect_min.y / texel), info.z - 1u);
+    let x1 = min(u32(rect_max.x / texel), info.y - 1u);
+    let y1 = min(u32(rect_max.y / texel), info.z - 1u);
+
+    var farthest = 0.0;
+    for (var y = y0; y <= y1; y = y + 1u) {
+        for (var x = x0; x <= x1; x = x + 1u) {
+            farthest = max(farthest, s_pyramid.depth[info.x + y * info.y + x]);
+        }
+    }
+    return min_ndc.z <= farthest;
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= arrayLength(&s_spheres.data)) {
+        return;
+    }
+    if (is_visible(s_spheres.data[index])) {
+        let slot = atomicAdd(&s_indirect.args[1], 1u);
+        s_visible.indices[slot] = index;
+    }
+}
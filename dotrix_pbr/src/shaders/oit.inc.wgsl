@@ -0,0 +1,17 @@
+struct OitOutput {
+    // weighted premultiplied color and weighted alpha
+    [[location(0)]] accum: vec4<f32>;
+    // alpha, blended into the coverage of the pixel
+    [[location(1)]] coverage: vec4<f32>;
+};
+
+// Weights the transparent fragment by its depth, closer fragments dominate the average
+fn oit_output(color: vec4<f32>, position: vec4<f32>) -> OitOutput {
+    let alpha = clamp(color.a, 0.0, 1.0);
+    let depth = 1.0 - position.z;
+    let weight = clamp(alpha * 3000.0 * depth * depth * depth, 0.01, 3000.0);
+    var out: OitOutput;
+    out.accum = vec4<f32>(color.rgb * alpha, alpha) * weight;
+    out.coverage = vec4<f32>(alpha);
+    return out;
+}
@@ -0,0 +1,31 @@
+[[group(0), binding(0)]]
+var r_accum: texture_2d<f32>;
+
+[[group(0), binding(1)]]
+var r_coverage: texture_2d<f32>;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = vec2<i32>(in.position.xy);
+    let coverage = textureLoad(r_coverage, texel, 0).r;
+    if (coverage <= 0.0) {
+        discard;
+    }
+    let accum = textureLoad(r_accum, texel, 0);
+    // weighted average of the colors is blended over the scene by the coverage
+    let color = accum.rgb / max(accum.a, 0.00001);
+    return vec4<f32>(color, clamp(coverage, 0.0, 1.0));
+}
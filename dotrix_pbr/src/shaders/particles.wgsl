@@ -0,0 +1,102 @@
+struct Particle {
+    // position, age
+    position: vec4<f32>;
+    // velocity, lifetime
+    velocity: vec4<f32>;
+};
+
+struct Particles {
+    particles: [[stride(32)]] array<Particle>;
+};
+
+struct Camera {
+    proj_view: mat4x4<f32>;
+    inverse_proj: mat4x4<f32>;
+    // camera right and up directions in the world space
+    right: vec4<f32>;
+    up: vec4<f32>;
+};
+
+struct Emitter {
+    position: vec4<f32>;
+    direction: vec4<f32>;
+    gravity: vec4<f32>;
+    params: vec4<f32>;
+    noise: vec4<f32>;
+    // start size, end size, soft distance, 0
+    size: vec4<f32>;
+    spawn: vec4<u32>;
+    start_color: vec4<f32>;
+    end_color: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_camera: Camera;
+
+[[group(0), binding(1)]]
+var r_depth: texture_depth_2d;
+
+[[group(1), binding(0)]]
+var<uniform> u_emitter: Emitter;
+
+[[group(1), binding(1)]]
+var<storage, read_write> s_particles: Particles;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] corner: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] corner: vec2<f32>,
+    [[builtin(instance_index)]] instance: u32,
+) -> VertexOutput {
+    let particle = s_particles.particles[instance];
+    let age = particle.position.w;
+    let lifetime = particle.velocity.w;
+    var out: VertexOutput;
+    out.corner = corner;
+    // dead particles are clipped
+    if (age >= lifetime) {
+        out.position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+        out.color = vec4<f32>(0.0);
+        return out;
+    }
+
+    let t = age / lifetime;
+    let size = mix(u_emitter.size.x, u_emitter.size.y, t) * 0.5;
+    let offset = u_camera.right.xyz * corner.x + u_camera.up.xyz * corner.y;
+    out.position = u_camera.proj_view * vec4<f32>(particle.position.xyz + offset * size, 1.0);
+    out.color = mix(u_emitter.start_color, u_emitter.end_color, t);
+    return out;
+}
+
+// Distance from the camera to the point of the depth at the pixel
+fn view_distance(pixel: vec2<f32>, depth: f32) -> f32 {
+    let size = vec2<f32>(textureDimensions(r_depth));
+    let uv = pixel / size;
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = u_camera.inverse_proj * ndc;
+    return length(position.xyz / position.w);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let shape = clamp(1.0 - length(in.corner), 0.0, 1.0);
+
+    // particles fade out close to the geometry behind them instead of the hard intersection
+    var fade = 1.0;
+    let soft_distance = u_emitter.size.z;
+    if (soft_distance > 0.0) {
+        let size = textureDimensions(r_depth);
+        let texel = clamp(vec2<i32>(in.position.xy), vec2<i32>(0, 0), size - vec2<i32>(1, 1));
+        let scene_depth = textureLoad(r_depth, texel, 0);
+        let scene = view_distance(in.position.xy, scene_depth);
+        let particle = view_distance(in.position.xy, in.position.z);
+        fade = clamp((scene - particle) / soft_distance, 0.0, 1.0);
+    }
+
+    return vec4<f32>(in.color.rgb, in.color.a * shape * fade);
+}
@@ -0,0 +1,151 @@
+struct Particle {
+    // position, age
+    position: vec4<f32>;
+    // velocity, lifetime
+    velocity: vec4<f32>;
+};
+
+struct Particles {
+    particles: [[stride(32)]] array<Particle>;
+};
+
+struct Emitter {
+    // position, delta time
+    position: vec4<f32>;
+    // direction, spread angle
+    direction: vec4<f32>;
+    // gravity, drag
+    gravity: vec4<f32>;
+    // speed, lifetime, lifetime variance, time
+    params: vec4<f32>;
+    // curl noise strength, curl noise scale, 0, 0
+    noise: vec4<f32>;
+    // start size, end size, soft distance, 0
+    size: vec4<f32>;
+    // first spawned particle, number of the spawned particles, capacity, seed
+    spawn: vec4<u32>;
+    start_color: vec4<f32>;
+    end_color: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_emitter: Emitter;
+
+[[group(0), binding(1)]]
+var<storage, read_write> s_particles: Particles;
+
+let PI: f32 = 3.14159265359;
+
+fn pcg(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Random value in 0..1 of the particle and the channel
+fn random(index: u32, channel: u32) -> f32 {
+    let seed = pcg(u_emitter.spawn.w ^ pcg(index * 4u + channel));
+    return f32(seed) / 4294967295.0;
+}
+
+fn hash(point: vec3<f32>) -> f32 {
+    return fract(sin(dot(point, vec3<f32>(127.1, 311.7, 74.7))) * 43758.5453);
+}
+
+fn value_noise(point: vec3<f32>) -> f32 {
+    let cell = floor(point);
+    let f = fract(point);
+    let w = f * f * (vec3<f32>(3.0) - 2.0 * f);
+    let x00 = mix(hash(cell), hash(cell + vec3<f32>(1.0, 0.0, 0.0)), w.x);
+    let x10 = mix(
+        hash(cell + vec3<f32>(0.0, 1.0, 0.0)),
+        hash(cell + vec3<f32>(1.0, 1.0, 0.0)),
+        w.x
+    );
+    let x01 = mix(
+        hash(cell + vec3<f32>(0.0, 0.0, 1.0)),
+        hash(cell + vec3<f32>(1.0, 0.0, 1.0)),
+        w.x
+    );
+    let x11 = mix(
+        hash(cell + vec3<f32>(0.0, 1.0, 1.0)),
+        hash(cell + vec3<f32>(1.0, 1.0, 1.0)),
+        w.x
+    );
+    return mix(mix(x00, x10, w.y), mix(x01, x11, w.y), w.z);
+}
+
+fn potential(point: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(
+        value_noise(point),
+        value_noise(point + vec3<f32>(31.4, 17.2, 9.3)),
+        value_noise(point + vec3<f32>(-11.7, 43.1, 27.5))
+    );
+}
+
+// Divergence free velocity field, particles swirl without gathering at the sinks
+fn curl_noise(point: vec3<f32>) -> vec3<f32> {
+    let e = 0.1;
+    let dx = vec3<f32>(e, 0.0, 0.0);
+    let dy = vec3<f32>(0.0, e, 0.0);
+    let dz = vec3<f32>(0.0, 0.0, e);
+    let px = potential(point + dx) - potential(point - dx);
+    let py = potential(point + dy) - potential(point - dy);
+    let pz = potential(point + dz) - potential(point - dz);
+    return vec3<f32>(py.z - pz.y, pz.x - px.z, px.y - py.x) / (2.0 * e);
+}
+
+// Random direction in the cone of the spread angle around the emitter direction
+fn spawn_direction(index: u32) -> vec3<f32> {
+    let direction = u_emitter.direction.xyz;
+    let cos_theta = mix(1.0, cos(u_emitter.direction.w), random(index, 0u));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = 2.0 * PI * random(index, 1u);
+    let up = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(direction.y) > 0.99);
+    let tangent = normalize(cross(up, direction));
+    let bitangent = cross(direction, tangent);
+    return tangent * sin_theta * cos(phi) + bitangent * sin_theta * sin(phi)
+        + direction * cos_theta;
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    let index = global_id.x;
+    let capacity = u_emitter.spawn.z;
+    if (index >= capacity) {
+        return;
+    }
+
+    // spawned particles replace the oldest ones of the ring
+    let offset = (index + capacity - u_emitter.spawn.x) % capacity;
+    if (offset < u_emitter.spawn.y) {
+        let lifetime = u_emitter.params.y
+            * (1.0 - u_emitter.params.z * random(index, 2u));
+        s_particles.particles[index].position = vec4<f32>(u_emitter.position.xyz, 0.0);
+        s_particles.particles[index].velocity = vec4<f32>(
+            spawn_direction(index) * u_emitter.params.x,
+            max(lifetime, 0.0001)
+        );
+        return;
+    }
+
+    let particle = s_particles.particles[index];
+    let age = particle.position.w;
+    let lifetime = particle.velocity.w;
+    if (age >= lifetime) {
+        return;
+    }
+
+    let dt = u_emitter.position.w;
+    var velocity = particle.velocity.xyz + u_emitter.gravity.xyz * dt;
+    velocity = velocity / (1.0 + u_emitter.gravity.w * dt);
+    let noise_point = particle.position.xyz * u_emitter.noise.y
+        + vec3<f32>(0.0, u_emitter.params.w * 0.1, 0.0);
+    velocity = velocity + curl_noise(noise_point) * u_emitter.noise.x * dt;
+
+    s_particles.particles[index].position = vec4<f32>(
+        particle.position.xyz + velocity * dt,
+        age + dt
+    );
+    s_particles.particles[index].velocity = vec4<f32>(velocity, lifetime);
+}
@@ -0,0 +1,41 @@
+{{ include(cube_face) }}
+
+{{ include(importance_sample) }}
+
+let SAMPLE_COUNT: u32 = 256u;
+
+// Environment convolved with the GGX distribution, the roughness grows with the mip level
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = u32(u_params.level.z);
+    if (id.x >= size || id.y >= size) {
+        return;
+    }
+
+    let normal = face_direction(id.z, id.xy);
+    let roughness = u_params.level.x / max(u_params.level.y - 1.0, 1.0);
+    var color: vec3<f32> = vec3<f32>(0.0);
+    var weight: f32 = 0.0;
+    var i: u32 = 0u;
+    loop {
+        if (i >= SAMPLE_COUNT) {
+            break;
+        }
+        // the view direction is assumed to be equal to the normal
+        let halfway = tangent_to_world(importance_sample_ggx(i, SAMPLE_COUNT, roughness), normal);
+        let light = normalize(2.0 * dot(normal, halfway) * halfway - normal);
+        let n_dot_l = dot(normal, light);
+        if (n_dot_l > 0.0) {
+            color = color + textureSampleLevel(r_source, r_sampler, light, 0.0).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+        i = i + 1u;
+    }
+
+    textureStore(
+        w_faces,
+        vec2<i32>(id.xy),
+        i32(id.z),
+        vec4<f32>(color / max(weight, 0.0001), 1.0)
+    );
+}
@@ -0,0 +1,161 @@
+struct LocalShadow {
+    // faces of the point light cube or the spot light projection in the first one
+    proj_view: [[stride(64)]] array<mat4x4<f32>, 6u>;
+    // xyz: light position, w: 0.0 for point lights, 1.0 for spot lights
+    position: vec4<f32>;
+};
+
+struct Shadow {
+    proj_view: [[stride(64)]] array<mat4x4<f32>, 4u>;
+    splits: vec4<f32>;
+    camera_position: vec4<f32>;
+    camera_direction: vec4<f32>;
+    // x: depth bias, y: shadow map texel size, z: number of cascades, w: blend fraction
+    params: vec4<f32>;
+    // x: depth bias, y: shadow map texel size of point and spot lights
+    local_params: vec4<f32>;
+    local: [[stride(400)]] array<LocalShadow, 4u>;
+};
+
+[[group({{ bind_group }}), binding({{ binding }})]]
+var<uniform> u_shadow: Shadow;
+
+[[group({{ bind_group }}), binding({{ shadow_map_0_binding }})]]
+var r_shadow_map_0: texture_depth_2d;
+
+[[group({{ bind_group }}), binding({{ shadow_map_1_binding }})]]
+var r_shadow_map_1: texture_depth_2d;
+
+[[group({{ bind_group }}), binding({{ shadow_map_2_binding }})]]
+var r_shadow_map_2: texture_depth_2d;
+
+[[group({{ bind_group }}), binding({{ shadow_map_3_binding }})]]
+var r_shadow_map_3: texture_depth_2d;
+
+[[group({{ bind_group }}), binding({{ local_shadow_map_binding }})]]
+var r_local_shadow_map: texture_depth_2d_array;
+
+[[group({{ bind_group }}), binding({{ shadow_sampler_binding }})]]
+var r_shadow_sampler: sampler_comparison;
+
+fn sample_shadow_map(cascade: u32, uv: vec2<f32>, depth: f32) -> f32 {
+    if (cascade == 0u) {
+        return textureSampleCompareLevel(r_shadow_map_0, r_shadow_sampler, uv, depth);
+    }
+    if (cascade == 1u) {
+        return textureSampleCompareLevel(r_shadow_map_1, r_shadow_sampler, uv, depth);
+    }
+    if (cascade == 2u) {
+        return textureSampleCompareLevel(r_shadow_map_2, r_shadow_sampler, uv, depth);
+    }
+    return textureSampleCompareLevel(r_shadow_map_3, r_shadow_sampler, uv, depth);
+}
+
+// Returns visibility of the position in the cascade, 3x3 PCF filtered
+fn calculate_cascade_shadow(cascade: u32, position: vec3<f32>) -> f32 {
+    let light_space = u_shadow.proj_view[cascade] * vec4<f32>(position, 1.0);
+    let coords = light_space.xyz / light_space.w;
+    if (coords.x < -1.0 || coords.x > 1.0 || coords.y < -1.0 || coords.y > 1.0 || coords.z > 1.0) {
+        return 1.0;
+    }
+    let uv = vec2<f32>(coords.x * 0.5 + 0.5, 0.5 - coords.y * 0.5);
+    let depth = coords.z - u_shadow.params.x;
+    let texel = u_shadow.params.y;
+
+    var visibility: f32 = 0.0;
+    for (var x: i32 = -1; x <= 1; x = x + 1) {
+        for (var y: i32 = -1; y <= 1; y = y + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            visibility = visibility + sample_shadow_map(cascade, uv + offset, depth);
+        }
+    }
+    return visibility / 9.0;
+}
+
+// Returns visibility of the fragment from the shadow casting light
+fn calculate_shadow(position: vec3<f32>) -> f32 {
+    let count = u32(u_shadow.params.z);
+    let depth = dot(position - u_shadow.camera_position.xyz, u_shadow.camera_direction.xyz);
+
+    var cascade: u32 = count;
+    for (var i: u32 = 0u; i < count; i = i + 1u) {
+        if (depth < u_shadow.splits[i]) {
+            cascade = i;
+            break;
+        }
+    }
+    if (cascade >= count) {
+        return 1.0;
+    }
+
+    let shadow = calculate_cascade_shadow(cascade, position);
+
+    // blend the end of the cascade with the next one, the last one fades out
+    var near: f32 = 0.0;
+    if (cascade > 0u) {
+        near = u_shadow.splits[cascade - 1u];
+    }
+    let far = u_shadow.splits[cascade];
+    let fade = (far - depth) / max((far - near) * u_shadow.params.w, 0.0001);
+    if (fade >= 1.0) {
+        return shadow;
+    }
+    var next: f32 = 1.0;
+    if (cascade + 1u < count) {
+        next = calculate_cascade_shadow(cascade + 1u, position);
+    }
+    return mix(next, shadow, clamp(fade, 0.0, 1.0));
+}
+
+// Returns visibility of the position from the point or spot light in the shadow `slot`
+fn calculate_local_shadow(slot: f32, position: vec3<f32>) -> f32 {
+    if (slot < 0.0) {
+        return 1.0;
+    }
+    let index = u32(slot);
+    let light_position = u_shadow.local[index].position;
+
+    // point lights render the cube face of the major axis
+    var face: u32 = 0u;
+    if (light_position.w < 0.5) {
+        let direction = position - light_position.xyz;
+        let axis = abs(direction);
+        if (axis.x >= axis.y && axis.x >= axis.z) {
+            face = select(1u, 0u, direction.x > 0.0);
+        } else {
+            if (axis.y >= axis.z) {
+                face = select(3u, 2u, direction.y > 0.0);
+            } else {
+                face = select(5u, 4u, direction.z > 0.0);
+            }
+        }
+    }
+
+    let light_space = u_shadow.local[index].proj_view[face] * vec4<f32>(position, 1.0);
+    if (light_space.w <= 0.0) {
+        return 1.0;
+    }
+    let coords = light_space.xyz / light_space.w;
+    if (coords.x < -1.0 || coords.x > 1.0 || coords.y < -1.0 || coords.y > 1.0 || coords.z > 1.0) {
+        return 1.0;
+    }
+    let uv = vec2<f32>(coords.x * 0.5 + 0.5, 0.5 - coords.y * 0.5);
+    let depth = coords.z - u_shadow.local_params.x;
+    let texel = u_shadow.local_params.y;
+    let layer = i32(index * 6u + face);
+
+    var visibility: f32 = 0.0;
+    for (var x: i32 = -1; x <= 1; x = x + 1) {
+        for (var y: i32 = -1; y <= 1; y = y + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            visibility = visibility + textureSampleCompareLevel(
+                r_local_shadow_map,
+                r_shadow_sampler,
+                uv + offset,
+                layer,
+                depth
+            );
+        }
+    }
+    return visibility / 9.0;
+}
@@ -1,7 +1,5 @@
 // STAGE: VERTEX ---------------------------------------------------------------------------------
 
-let MAX_JOINTS_COUNT: u32 = 32u;
-
 struct VertexOutput {
     [[builtin(position)]] position: vec4<f32>;
     [[location(0)]] world_position: vec4<f32>;
@@ -26,10 +24,12 @@ struct Model {
 var<uniform> u_model: Model;
 
 struct Joints {
-    transform: [[stride(64)]] array<mat4x4<f32>, MAX_JOINTS_COUNT>;
+    transform: [[stride(64)]] array<mat4x4<f32>>;
 };
 [[group(1), binding(7)]]
-var<uniform> u_joints: Joints;
+var<storage, read> s_joints: Joints;
+
+{{ include(morph) }}
 
 [[stage(vertex)]]
 fn vs_main(
@@ -40,36 +40,40 @@ fn vs_main(
     [[location(4)]] tex_uv: vec2<f32>,
     [[location(5)]] weights: vec4<f32>,
     [[location(6)]] joints: vec4<u32>,
+    [[builtin(vertex_index)]] vertex: u32,
 ) -> VertexOutput {
     var out: VertexOutput;
+    // blend shapes are applied before the skinning
+    let position = morph_position(vertex, position);
+    let normal = morph_normal(vertex, normal);
     out.tex_uv = tex_uv;
 
     // at the moment of writing `Mul (Scalar, Matrix)` was not supported,
     // let skin_transform: mat4x4<f32> =
-    //    weights.x * u_joints.transform[joints.x] +
-    //    weights.y * u_joints.transform[joints.y] +
-    //    weights.z * u_joints.transform[joints.z] +
-    //    weights.w * u_joints.transform[joints.w];
+    //    weights.x * s_joints.transform[joints.x] +
+    //    weights.y * s_joints.transform[joints.y] +
+    //    weights.z * s_joints.transform[joints.z] +
+    //    weights.w * s_joints.transform[joints.w];
     let skin_transform: mat4x4<f32> = mat4x4<f32>(
-        weights.x * u_joints.transform[joints.x].x +
-        weights.y * u_joints.transform[joints.y].x +
-        weights.z * u_joints.transform[joints.z].x +
-        weights.w * u_joints.transform[joints.w].x,
-
-        weights.x * u_joints.transform[joints.x].y +
-        weights.y * u_joints.transform[joints.y].y +
-        weights.z * u_joints.transform[joints.z].y +
-        weights.w * u_joints.transform[joints.w].y,
-
-        weights.x * u_joints.transform[joints.x].z +
-        weights.y * u_joints.transform[joints.y].z +
-        weights.z * u_joints.transform[joints.z].z +
-        weights.w * u_joints.transform[joints.w].z,
-
-        weights.x * u_joints.transform[joints.x].w +
-        weights.y * u_joints.transform[joints.y].w +
-        weights.z * u_joints.transform[joints.z].w +
-        weights.w * u_joints.transform[joints.w].w
+        weights.x * s_joints.transform[joints.x].x +
+        weights.y * s_joints.transform[joints.y].x +
+        weights.z * s_joints.transform[joints.z].x +
+        weights.w * s_joints.transform[joints.w].x,
+
+        weights.x * s_joints.transform[joints.x].y +
+        weights.y * s_joints.transform[joints.y].y +
+        weights.z * s_joints.transform[joints.z].y +
+        weights.w * s_joints.transform[joints.w].y,
+
+        weights.x * s_joints.transform[joints.x].z +
+        weights.y * s_joints.transform[joints.y].z +
+        weights.z * s_joints.transform[joints.z].z +
+        weights.w * s_joints.transform[joints.w].z,
+
+        weights.x * s_joints.transform[joints.x].w +
+        weights.y * s_joints.transform[joints.y].w +
+        weights.z * s_joints.transform[joints.z].w +
+        weights.w * s_joints.transform[joints.w].w
     );
 
     out.normal = normalize(
@@ -142,14 +146,29 @@ var r_normal_texture: texture_2d<f32>;
 [[group(0), binding(1)]]
 var r_sampler: sampler;
 
+{{ include(ssao) }}
+
+{{ include(shadow) }}
+
+{{ include(clusters) }}
+
+{{ include(environment) }}
+
 {{ include(light) }}
 
 fn average(input: vec4<f32>) -> f32 {
   return (input.x + input.y + input.z + input.w) / 4.;
 }
 
-[[stage(fragment)]]
-fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+struct Surface {
+    albedo: vec3<f32>;
+    normal: vec3<f32>;
+    roughness: f32;
+    metallic: f32;
+    ao: f32;
+};
+
+fn calculate_surface(in: VertexOutput) -> Surface {
     var albedo: vec4<f32>;
     var roughness: f32;
     var metallic: f32;
@@ -192,12 +211,13 @@ fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
       normal = in.normal.xyz;
     }
 
-    return calculate_lighting(
-        in.world_position.xyz,
-        normal,
-        albedo.rgb,
-        roughness,
-        metallic,
-        ao,
-    );
+    var surface: Surface;
+    surface.albedo = albedo.rgb;
+    surface.normal = normal;
+    surface.roughness = roughness;
+    surface.metallic = metallic;
+    surface.ao = ao;
+    return surface;
 }
+
+{{ include(fragment) }}
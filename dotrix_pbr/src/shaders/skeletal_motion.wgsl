@@ -0,0 +1,78 @@
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] current: vec4<f32>;
+    [[location(1)]] previous: vec4<f32>;
+};
+
+struct Renderer {
+    proj_view: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> u_renderer: Renderer;
+
+struct Velocity {
+    proj_view: mat4x4<f32>;
+    previous_proj_view: mat4x4<f32>;
+    inverse_proj_view: mat4x4<f32>;
+};
+[[group(0), binding(1)]]
+var<uniform> u_velocity: Velocity;
+
+struct Model {
+    transform: mat4x4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> u_model: Model;
+[[group(1), binding(1)]]
+var<uniform> u_previous_model: Model;
+
+struct Joints {
+    transform: [[stride(64)]] array<mat4x4<f32>>;
+};
+[[group(1), binding(2)]]
+var<storage, read> s_joints: Joints;
+
+{{ include(morph) }}
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec3<f32>,
+    [[location(5)]] weights: vec4<f32>,
+    [[location(6)]] joints: vec4<u32>,
+    [[builtin(vertex_index)]] vertex: u32,
+) -> VertexOutput {
+    let position = morph_position(vertex, position);
+    let skin_transform: mat4x4<f32> = mat4x4<f32>(
+        weights.x * s_joints.transform[joints.x].x +
+        weights.y * s_joints.transform[joints.y].x +
+        weights.z * s_joints.transform[joints.z].x +
+        weights.w * s_joints.transform[joints.w].x,
+
+        weights.x * s_joints.transform[joints.x].y +
+        weights.y * s_joints.transform[joints.y].y +
+        weights.z * s_joints.transform[joints.z].y +
+        weights.w * s_joints.transform[joints.w].y,
+
+        weights.x * s_joints.transform[joints.x].z +
+        weights.y * s_joints.transform[joints.y].z +
+        weights.z * s_joints.transform[joints.z].z +
+        weights.w * s_joints.transform[joints.w].z,
+
+        weights.x * s_joints.transform[joints.x].w +
+        weights.y * s_joints.transform[joints.y].w +
+        weights.z * s_joints.transform[joints.z].w +
+        weights.w * s_joints.transform[joints.w].w
+    );
+
+    // the pose of the previous frame is not kept, so the skin moves with the model only
+    let skinned = skin_transform * vec4<f32>(position, 1.0);
+    let world_position = u_model.transform * skinned;
+
+    var out: VertexOutput;
+    out.position = u_renderer.proj_view * world_position;
+    out.current = u_velocity.proj_view * world_position;
+    out.previous = u_velocity.previous_proj_view * u_previous_model.transform * skinned;
+    return out;
+}
+
+{{ include(motion) }}
@@ -23,6 +23,7 @@ struct Model {
 [[group(1), binding(0)]]
 var<uniform> u_model: Model;
 
+{{ include(morph) }}
 
 [[stage(vertex)]]
 fn vs_main(
@@ -30,7 +31,8 @@ fn vs_main(
     [[location(1)]] normal: vec3<f32>,
     [[location(2)]] tangent: vec3<f32>,
     [[location(3)]] bitangent: vec3<f32>,
-    [[location(4)]] tex_uv: vec2<f32>
+    [[location(4)]] tex_uv: vec2<f32>,
+    [[builtin(vertex_index)]] vertex: u32,
 ) -> VertexOutput {
     var out: VertexOutput;
     out.tex_uv = tex_uv;
@@ -38,7 +40,7 @@ fn vs_main(
         u_model.transform.x.xyz,
         u_model.transform.y.xyz,
         u_model.transform.z.xyz,
-    ) * normal);
+    ) * morph_normal(vertex, normal));
     out.tangent = normalize(mat3x3<f32>(
         u_model.transform.x.xyz,
         u_model.transform.y.xyz,
@@ -49,7 +51,7 @@ fn vs_main(
         u_model.transform.y.xyz,
         u_model.transform.z.xyz,
     ) * bitangent);
-    var pos: vec3<f32> = (u_model.transform * vec4<f32>(position, 1.0)).xyz;
+    var pos: vec3<f32> = (u_model.transform * vec4<f32>(morph_position(vertex, position), 1.0)).xyz;
     out.world_position = pos;
     out.position = u_renderer.proj_view * vec4<f32>(pos, 1.0);
     return out;
@@ -85,14 +87,29 @@ var r_normal_texture: texture_2d<f32>;
 [[group(0), binding(1)]]
 var r_sampler: sampler;
 
+{{ include(ssao) }}
+
+{{ include(shadow) }}
+
+{{ include(clusters) }}
+
+{{ include(environment) }}
+
 {{ include(light) }}
 
 fn average(input: vec4<f32>) -> f32 {
   return (input.x + input.y + input.z + input.w) / 4.;
 }
 
-[[stage(fragment)]]
-fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+struct Surface {
+    albedo: vec3<f32>;
+    normal: vec3<f32>;
+    roughness: f32;
+    metallic: f32;
+    ao: f32;
+};
+
+fn calculate_surface(in: VertexOutput) -> Surface {
   var albedo: vec4<f32>;
   var roughness: f32;
   var metallic: f32;
@@ -135,12 +152,13 @@ fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
     normal = in.normal.xyz;
   }
 
-  return calculate_lighting(
-      in.world_position.xyz,
-      normal,
-      albedo.rgb,
-      roughness,
-      metallic,
-      ao,
-  );
+  var surface: Surface;
+  surface.albedo = albedo.rgb;
+  surface.normal = normal;
+  surface.roughness = roughness;
+  surface.metallic = metallic;
+  surface.ao = ao;
+  return surface;
 }
+
+{{ include(fragment) }}
@@ -0,0 +1,46 @@
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] current: vec4<f32>;
+    [[location(1)]] previous: vec4<f32>;
+};
+
+struct Renderer {
+    proj_view: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> u_renderer: Renderer;
+
+struct Velocity {
+    proj_view: mat4x4<f32>;
+    previous_proj_view: mat4x4<f32>;
+    inverse_proj_view: mat4x4<f32>;
+};
+[[group(0), binding(1)]]
+var<uniform> u_velocity: Velocity;
+
+struct Model {
+    transform: mat4x4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> u_model: Model;
+[[group(1), binding(1)]]
+var<uniform> u_previous_model: Model;
+
+{{ include(morph) }}
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec3<f32>,
+    [[builtin(vertex_index)]] vertex: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let position = morph_position(vertex, position);
+    let world_position = u_model.transform * vec4<f32>(position, 1.0);
+    out.position = u_renderer.proj_view * world_position;
+    out.current = u_velocity.proj_view * world_position;
+    out.previous = u_velocity.previous_proj_view * u_previous_model.transform
+        * vec4<f32>(position, 1.0);
+    return out;
+}
+
+{{ include(motion) }}
@@ -0,0 +1,24 @@
+struct Cascade {
+    proj_view: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> u_cascade: Cascade;
+
+
+struct Model {
+    transform: mat4x4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> u_model: Model;
+
+{{ include(morph) }}
+
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec3<f32>,
+    [[builtin(vertex_index)]] vertex: u32,
+) -> [[builtin(position)]] vec4<f32> {
+    let position = morph_position(vertex, position);
+    return u_cascade.proj_view * u_model.transform * vec4<f32>(position, 1.0);
+}
@@ -0,0 +1,6 @@
+[[group({{ bind_group }}), binding({{ binding }})]]
+var r_ssao: texture_2d<f32>;
+
+fn calculate_ssao(position: vec4<f32>) -> f32 {
+    return textureLoad(r_ssao, vec2<i32>(position.xy), 0).r;
+}
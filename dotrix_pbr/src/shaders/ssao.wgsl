@@ -0,0 +1,90 @@
+struct Ssao {
+    proj: mat4x4<f32>;
+    inverse_proj: mat4x4<f32>;
+    // radius, bias, intensity, number of samples
+    params: vec4<f32>;
+    kernel: [[stride(16)]] array<vec4<f32>, 32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_ssao: Ssao;
+
+[[group(0), binding(1)]]
+var r_depth: texture_depth_2d;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+    return out;
+}
+
+fn load_depth(uv: vec2<f32>) -> f32 {
+    let size = textureDimensions(r_depth);
+    let texel = clamp(
+        vec2<i32>(uv * vec2<f32>(size)),
+        vec2<i32>(0, 0),
+        size - vec2<i32>(1, 1)
+    );
+    return textureLoad(r_depth, texel, 0);
+}
+
+fn view_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = u_ssao.inverse_proj * ndc;
+    return position.xyz / position.w;
+}
+
+// Pseudo random value of the pixel in the range 0..1
+fn hash(pixel: vec2<f32>) -> f32 {
+    return fract(sin(dot(pixel, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let depth = load_depth(in.uv);
+    let position = view_position(in.uv, depth);
+    let normal = normalize(cross(dpdy(position), dpdx(position)));
+    if (depth >= 1.0) {
+        return vec4<f32>(1.0);
+    }
+
+    // hemisphere rotated randomly around the normal, the blur hides the noise
+    let angle = hash(in.position.xy) * 6.2831853;
+    let random = vec3<f32>(cos(angle), sin(angle), 0.0);
+    let tangent = normalize(random - normal * dot(random, normal));
+    let bitangent = cross(normal, tangent);
+    let tbn = mat3x3<f32>(tangent, bitangent, normal);
+
+    let radius = u_ssao.params.x;
+    let bias = u_ssao.params.y;
+    let count = u_ssao.params.w;
+    var occlusion: f32 = 0.0;
+    var i: i32 = 0;
+    loop {
+        if (f32(i) >= count) {
+            break;
+        }
+        let sample = position + tbn * u_ssao.kernel[i].xyz * radius;
+        let clip = u_ssao.proj * vec4<f32>(sample, 1.0);
+        let ndc = clip.xy / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        let scene_depth = view_position(uv, load_depth(uv)).z;
+        let range = clamp(radius / max(abs(position.z - scene_depth), 0.0001), 0.0, 1.0);
+        if (scene_depth >= sample.z + bias) {
+            occlusion = occlusion + range;
+        }
+        i = i + 1;
+    }
+
+    let ao = pow(1.0 - occlusion / count, u_ssao.params.z);
+    return vec4<f32>(ao, ao, ao, 1.0);
+}
@@ -0,0 +1,31 @@
+[[group(0), binding(1)]]
+var r_occlusion: texture_2d<f32>;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    return out;
+}
+
+// 4x4 box blur of the occlusion noise
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(r_occlusion);
+    let center = vec2<i32>(in.position.xy);
+    var result: f32 = 0.0;
+    for (var y: i32 = -2; y < 2; y = y + 1) {
+        for (var x: i32 = -2; x < 2; x = x + 1) {
+            let texel = clamp(center + vec2<i32>(x, y), vec2<i32>(0, 0), size - vec2<i32>(1, 1));
+            result = result + textureLoad(r_occlusion, texel, 0).r;
+        }
+    }
+    let ao = result / 16.0;
+    return vec4<f32>(ao, ao, ao, 1.0);
+}
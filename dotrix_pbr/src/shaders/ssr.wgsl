@@ -0,0 +1,174 @@
+struct Ssr {
+    proj_view: mat4x4<f32>;
+    inverse_proj_view: mat4x4<f32>;
+    camera_position: vec4<f32>;
+    // max distance, number of steps, thickness, intensity
+    params: vec4<f32>;
+    // max roughness, environment is loaded, edge fade, 0
+    fade: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_ssr: Ssr;
+
+[[group(0), binding(1)]]
+var r_hdr: texture_2d<f32>;
+
+// albedo and ambient occlusion
+[[group(0), binding(2)]]
+var r_albedo: texture_2d<f32>;
+
+[[group(0), binding(3)]]
+var r_normal: texture_2d<f32>;
+
+// roughness and metallic
+[[group(0), binding(4)]]
+var r_material: texture_2d<f32>;
+
+[[group(0), binding(5)]]
+var r_depth: texture_depth_2d;
+
+[[group(0), binding(6)]]
+var r_environment: texture_cube<f32>;
+
+[[group(0), binding(7)]]
+var r_sampler: sampler;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+    return out;
+}
+
+fn load_depth(uv: vec2<f32>) -> f32 {
+    let size = textureDimensions(r_depth);
+    let texel = clamp(
+        vec2<i32>(uv * vec2<f32>(size)),
+        vec2<i32>(0, 0),
+        size - vec2<i32>(1, 1)
+    );
+    return textureLoad(r_depth, texel, 0);
+}
+
+fn world_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = u_ssr.inverse_proj_view * ndc;
+    return position.xyz / position.w;
+}
+
+// Screen coordinates of the world position, z is the clip space w
+fn screen_position(position: vec3<f32>) -> vec3<f32> {
+    let clip = u_ssr.proj_view * vec4<f32>(position, 1.0);
+    let ndc = clip.xy / clip.w;
+    return vec3<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5, clip.w);
+}
+
+// Distance from the camera to the scene, the ray reached at the screen position
+fn scene_distance(screen: vec3<f32>) -> f32 {
+    let depth = load_depth(screen.xy);
+    return distance(world_position(screen.xy, depth), u_ssr.camera_position.xyz);
+}
+
+fn is_on_screen(screen: vec3<f32>) -> bool {
+    return screen.z > 0.0 && screen.x >= 0.0 && screen.x <= 1.0 && screen.y >= 0.0 && screen.y <= 1.0;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = vec2<i32>(in.position.xy);
+    let depth = textureLoad(r_depth, texel, 0);
+    let albedo = textureLoad(r_albedo, texel, 0).rgb;
+    let normal = normalize(textureLoad(r_normal, texel, 0).xyz);
+    let material = textureLoad(r_material, texel, 0);
+    let roughness = material.r;
+    let metallic = material.g;
+
+    // nothing was rendered into the G-buffer or the surface is too rough to reflect
+    let max_roughness = u_ssr.fade.x;
+    if (depth >= 1.0 || roughness >= max_roughness) {
+        return vec4<f32>(0.0);
+    }
+
+    let position = world_position(in.uv, depth);
+    let view_direction = normalize(position - u_ssr.camera_position.xyz);
+    let direction = reflect(view_direction, normal);
+
+    let max_distance = u_ssr.params.x;
+    let steps = max(u_ssr.params.y, 1.0);
+    let thickness = u_ssr.params.z;
+    let step = direction * (max_distance / steps);
+
+    var hit: bool = false;
+    var hit_screen: vec3<f32> = vec3<f32>(0.0);
+    var ray: vec3<f32> = position;
+    var i: f32 = 0.0;
+    loop {
+        if (i >= steps) {
+            break;
+        }
+        ray = ray + step;
+        let screen = screen_position(ray);
+        if (!is_on_screen(screen)) {
+            break;
+        }
+        let difference = distance(ray, u_ssr.camera_position.xyz) - scene_distance(screen);
+        if (difference > 0.0 && difference < thickness) {
+            // binary search of the intersection between the last two steps
+            var start: vec3<f32> = ray - step;
+            var end: vec3<f32> = ray;
+            var j: i32 = 0;
+            loop {
+                if (j >= 6) {
+                    break;
+                }
+                let middle = (start + end) * 0.5;
+                let middle_screen = screen_position(middle);
+                let middle_difference = distance(middle, u_ssr.camera_position.xyz)
+                    - scene_distance(middle_screen);
+                if (middle_difference > 0.0) {
+                    end = middle;
+                } else {
+                    start = middle;
+                }
+                j = j + 1;
+            }
+            hit = true;
+            hit_screen = screen_position(end);
+            break;
+        }
+        i = i + 1.0;
+    }
+
+    var reflection: vec3<f32>;
+    var weight: f32;
+    if (hit) {
+        reflection = textureSampleLevel(r_hdr, r_sampler, hit_screen.xy, 0.0).rgb;
+        // reflections fade out close to the screen edges, where the rays leave the screen
+        let edge = min(min(hit_screen.x, 1.0 - hit_screen.x), min(hit_screen.y, 1.0 - hit_screen.y));
+        let edge_fade = clamp(edge / max(u_ssr.fade.z, 0.0001), 0.0, 1.0);
+        let environment = textureSampleLevel(r_environment, r_sampler, direction, 0.0).rgb;
+        reflection = mix(environment * u_ssr.fade.y, reflection, edge_fade);
+        weight = 1.0;
+    } else {
+        reflection = textureSampleLevel(r_environment, r_sampler, direction, 0.0).rgb;
+        weight = u_ssr.fade.y;
+    }
+
+    // Schlick approximation of the Fresnel term
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let cos_theta = max(dot(-view_direction, normal), 0.0);
+    let fresnel = f0 + (vec3<f32>(1.0) - f0) * pow(1.0 - cos_theta, 5.0);
+    let gloss = 1.0 - roughness / max_roughness;
+
+    let color = reflection * fresnel * gloss * weight * u_ssr.params.w;
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,79 @@
+struct Taa {
+    // feedback
+    params: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_taa: Taa;
+
+[[group(0), binding(1)]]
+var r_hdr: texture_2d<f32>;
+
+[[group(0), binding(2)]]
+var r_velocity: texture_2d<f32>;
+
+[[group(0), binding(3)]]
+var r_history: texture_2d<f32>;
+
+[[group(0), binding(4)]]
+var r_sampler: sampler;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(r_hdr);
+    let texel = vec2<i32>(in.position.xy);
+    let current = textureLoad(r_hdr, texel, 0).rgb;
+
+    // colors of the neighborhood limit the history, so the disoccluded pixels do not ghost
+    var neighborhood_min: vec3<f32> = current;
+    var neighborhood_max: vec3<f32> = current;
+    var y: i32 = -1;
+    loop {
+        if (y > 1) {
+            break;
+        }
+        var x: i32 = -1;
+        loop {
+            if (x > 1) {
+                break;
+            }
+            let neighbor = clamp(texel + vec2<i32>(x, y), vec2<i32>(0, 0), size - vec2<i32>(1, 1));
+            let color = textureLoad(r_hdr, neighbor, 0).rgb;
+            neighborhood_min = min(neighborhood_min, color);
+            neighborhood_max = max(neighborhood_max, color);
+            x = x + 1;
+        }
+        y = y + 1;
+    }
+
+    let velocity = textureLoad(r_velocity, texel, 0).xy;
+    let previous_uv = in.uv - velocity;
+    let history = clamp(
+        textureSampleLevel(r_history, r_sampler, previous_uv, 0.0).rgb,
+        neighborhood_min,
+        neighborhood_max
+    );
+
+    // pixels, that were not on the screen in the previous frame, have no history
+    var feedback: f32 = u_taa.params.x;
+    if (previous_uv.x < 0.0 || previous_uv.x > 1.0 || previous_uv.y < 0.0 || previous_uv.y > 1.0) {
+        feedback = 0.0;
+    }
+
+    return vec4<f32>(mix(current, history, feedback), 1.0);
+}
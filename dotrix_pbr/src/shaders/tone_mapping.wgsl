@@ -0,0 +1,70 @@
+struct ToneMapping {
+    exposure: f32;
+    gamma: f32;
+    operator: u32;
+    bloom_intensity: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_tone_mapping: ToneMapping;
+
+[[group(0), binding(1)]]
+var r_hdr: texture_2d<f32>;
+
+[[group(0), binding(2)]]
+var r_bloom: texture_2d<f32>;
+
+[[group(0), binding(3)]]
+var r_sampler: sampler;
+
+[[group(0), binding(4)]]
+var r_reflections: texture_2d<f32>;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    return out;
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (color + vec3<f32>(1.0));
+}
+
+// Krzysztof Narkowicz fit of the ACES filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp(
+        (color * (a * color + b)) / (color * (c * color + d) + e),
+        vec3<f32>(0.0),
+        vec3<f32>(1.0)
+    );
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let hdr = textureLoad(r_hdr, vec2<i32>(in.position.xy), 0).rgb
+        + textureLoad(r_reflections, vec2<i32>(in.position.xy), 0).rgb;
+    let uv = in.position.xy / vec2<f32>(textureDimensions(r_hdr));
+    let bloom = textureSample(r_bloom, r_sampler, uv).rgb;
+    let color = (hdr + bloom * u_tone_mapping.bloom_intensity) * u_tone_mapping.exposure;
+
+    var mapped: vec3<f32>;
+    if (u_tone_mapping.operator == 1u) {
+        mapped = aces(color);
+    } else {
+        mapped = reinhard(color);
+    }
+
+    return vec4<f32>(pow(mapped, vec3<f32>(1.0 / u_tone_mapping.gamma)), 1.0);
+}
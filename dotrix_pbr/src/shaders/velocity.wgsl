@@ -0,0 +1,49 @@
+struct Velocity {
+    proj_view: mat4x4<f32>;
+    previous_proj_view: mat4x4<f32>;
+    inverse_proj_view: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_velocity: Velocity;
+
+[[group(0), binding(1)]]
+var r_depth: texture_depth_2d;
+
+[[group(0), binding(2)]]
+var r_motion: texture_2d<f32>;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = vec2<i32>(in.position.xy);
+    let depth = textureLoad(r_depth, texel, 0);
+
+    // models render their own motion
+    if (depth < 1.0) {
+        return vec4<f32>(textureLoad(r_motion, texel, 0).xy, 0.0, 1.0);
+    }
+
+    // the rest of the scene is moved by the camera only
+    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth, 1.0);
+    let world_position = u_velocity.inverse_proj_view * ndc;
+    let previous = u_velocity.previous_proj_view * vec4<f32>(world_position.xyz / world_position.w, 1.0);
+    let previous_ndc = previous.xy / previous.w;
+    let previous_uv = vec2<f32>(previous_ndc.x * 0.5 + 0.5, 0.5 - previous_ndc.y * 0.5);
+
+    return vec4<f32>(in.uv - previous_uv, 0.0, 1.0);
+}
@@ -0,0 +1,805 @@
+//! Directional light cascaded shadow mapping, point and spot light shadows
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    Attachment, AttachmentSize, Binding, DepthBias, RenderPass, Sampler, Stage, UniformBuffer,
+    MAIN_PASS, OPENGL_TO_WGPU_MATRIX,
+};
+use dotrix_core::{Camera, Globals, Renderer, World};
+
+use dotrix_math::{
+    ortho, perspective, InnerSpace, Mat4, MetricSpace, Point3, Rad, SquareMatrix, Vec3, Vec4,
+};
+
+use crate::light::MAX_LIGHTS;
+use crate::Light;
+
+/// Maximal number of shadow cascades
+pub const MAX_CASCADES: usize = 4;
+
+/// Names of the render graph passes rendering the shadow cascades
+pub const SHADOW_PASSES: [&str; MAX_CASCADES] = ["shadow_0", "shadow_1", "shadow_2", "shadow_3"];
+
+/// Names of the render graph attachments of the shadow cascades
+pub const SHADOW_MAPS: [&str; MAX_CASCADES] = [
+    "shadow_map_0",
+    "shadow_map_1",
+    "shadow_map_2",
+    "shadow_map_3",
+];
+
+/// Maximal number of shadow casting point and spot lights
+pub const MAX_LOCAL_SHADOWS: usize = 4;
+
+/// Number of the shadow map layers reserved for every point or spot light
+pub const LOCAL_SHADOW_FACES: usize = 6;
+
+/// Name of the render graph texture array attachment of point and spot light shadows
+pub const LOCAL_SHADOW_MAP: &str = "local_shadow_map";
+
+/// Near plane of point and spot light projections
+const LOCAL_SHADOW_NEAR: f32 = 0.05;
+
+/// Shadow casting point or spot light, indexed among the enabled lights of its kind
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LocalLight {
+    Point(usize),
+    Spot(usize),
+}
+
+/// Cascaded shadows of the first enabled directional light and shadows of the most relevant
+/// point and spot lights (global)
+///
+/// The camera view frustum up to the `distance` is split into `cascades` slices, every slice
+/// gets its own shadow map of the same resolution, so the near shadows stay sharp while far
+/// shadows still cover large terrains. Lit pipelines sample them through
+/// `{{ include(shadow) }}`, see [`Shadows::add_to_shader`].
+///
+/// Every frame up to `local_budget` point and spot lights, that are bright and close to the
+/// camera, render their shadows into the layers of a shared texture array: six cube faces for
+/// a point light and a single perspective map for a spot light.
+pub struct Shadows {
+    /// Shadows are enabled
+    pub enabled: bool,
+    /// Width and height of every shadow map in texels
+    pub resolution: u32,
+    /// Number of cascades from 1 to [`MAX_CASCADES`]
+    pub cascades: usize,
+    /// Distance from the camera, covered by shadows
+    pub distance: f32,
+    /// Blend between logarithmic (1.0) and uniform (0.0) cascade splits
+    pub split_lambda: f32,
+    /// Fraction of the cascade blended with the next one
+    pub blend: f32,
+    /// Depth bias, that prevents shadow acne
+    pub bias: f32,
+    /// Number of point and spot lights casting shadows, up to [`MAX_LOCAL_SHADOWS`]
+    pub local_budget: usize,
+    /// Width and height of point and spot light shadow maps in texels
+    pub local_resolution: u32,
+    /// Shadow distance of spot lights
+    pub local_range: f32,
+    /// Depth bias of point and spot light shadows
+    pub local_bias: f32,
+    /// Depth bias of the shadow casters rasterized into the shadow maps, the constant and slope
+    /// scaled bias remove acne on the sloped surfaces, too large values detach the shadows
+    pub caster_bias: DepthBias,
+    /// Shadow uniform buffer
+    pub uniform: UniformBuffer,
+    /// Uniform buffers of the cascades projection view matrices for shadow casters
+    pub cascade_uniforms: [UniformBuffer; MAX_CASCADES],
+    /// Uniform buffers of point and spot lights projection view matrices for shadow casters
+    pub local_uniforms: Vec<UniformBuffer>,
+    /// Depth comparison sampler
+    pub sampler: Sampler,
+    proj_views: Vec<Mat4>,
+    bound_caster_bias: DepthBias,
+    local_lights: Vec<LocalLight>,
+    local_layers: Vec<usize>,
+    generation: Option<usize>,
+    changed: bool,
+}
+
+impl Default for Shadows {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            resolution: 2048,
+            cascades: MAX_CASCADES,
+            distance: 256.0,
+            split_lambda: 0.75,
+            blend: 0.1,
+            bias: 0.002,
+            local_budget: MAX_LOCAL_SHADOWS,
+            local_resolution: 512,
+            local_range: 50.0,
+            local_bias: 0.0005,
+            caster_bias: DepthBias::default(),
+            uniform: UniformBuffer::default(),
+            cascade_uniforms: Default::default(),
+            local_uniforms: (0..MAX_LOCAL_SHADOWS * LOCAL_SHADOW_FACES)
+                .map(|_| UniformBuffer::default())
+                .collect(),
+            sampler: Sampler::comparison(),
+            proj_views: Vec::new(),
+            bound_caster_bias: DepthBias::default(),
+            local_lights: Vec::new(),
+            local_layers: Vec::new(),
+            generation: None,
+            changed: false,
+        }
+    }
+}
+
+impl Shadows {
+    /// Integrates shadow support into shader
+    ///
+    /// The `source` shader code must contain `{{ include(shadow) }}` label before the
+    /// `{{ include(light) }}` one, the shadow uniform, shadow maps and comparison sampler take
+    /// `MAX_CASCADES + 3` bindings starting from `binding`. Then
+    /// `let shadow = calculate_shadow(world_position);` can be called and passed to
+    /// `calculate_shadowed_lighting`, point and spot lights are shadowed automatically.
+    pub fn add_to_shader(source: &str, bind_group: usize, binding: usize) -> String {
+        let mut shadow_code = include_str!("shaders/shadow.inc.wgsl")
+            .replace("{{ bind_group }}", &bind_group.to_string())
+            .replace("{{ binding }}", &binding.to_string())
+            .replace(
+                "{{ local_shadow_map_binding }}",
+                &(binding + MAX_CASCADES + 1).to_string(),
+            )
+            .replace(
+                "{{ shadow_sampler_binding }}",
+                &(binding + MAX_CASCADES + 2).to_string(),
+            );
+        for cascade in 0..MAX_CASCADES {
+            shadow_code = shadow_code.replace(
+                &format!("{{{{ shadow_map_{}_binding }}}}", cascade),
+                &(binding + cascade + 1).to_string(),
+            );
+        }
+
+        source.replace("{{ include(shadow) }}", &shadow_code)
+    }
+
+    /// Returns light projection view matrices of the rendered cascades
+    ///
+    /// The slice is empty, if there is no shadow casting light.
+    pub fn proj_views(&self) -> &[Mat4] {
+        &self.proj_views
+    }
+
+    /// Returns point and spot lights casting shadows this frame, ordered by their slots
+    pub fn local_lights(&self) -> &[LocalLight] {
+        &self.local_lights
+    }
+
+    /// Returns indices of the shadow views rendered this frame
+    ///
+    /// Cascades take indices below [`MAX_CASCADES`], they are followed by the layers of the
+    /// point and spot light shadow map. Indices are stable, so casters can keep a pipeline per
+    /// view.
+    pub fn views(&self) -> Vec<usize> {
+        (0..self.proj_views.len())
+            .chain(self.local_layers.iter().map(|layer| MAX_CASCADES + layer))
+            .collect()
+    }
+
+    /// Returns name of the render graph pass of the shadow view
+    pub fn view_pass(view: usize) -> String {
+        if view < MAX_CASCADES {
+            String::from(SHADOW_PASSES[view])
+        } else {
+            local_shadow_pass(view - MAX_CASCADES)
+        }
+    }
+
+    /// Returns projection view uniform of the shadow view for casters
+    pub fn view_uniform(&self, view: usize) -> &UniformBuffer {
+        if view < MAX_CASCADES {
+            &self.cascade_uniforms[view]
+        } else {
+            &self.local_uniforms[view - MAX_CASCADES]
+        }
+    }
+
+    /// Returns bindings of the shadow uniform, shadow maps and sampler for lit pipelines
+    pub fn bindings(&self) -> Vec<Binding<'_>> {
+        let mut bindings = vec![Binding::Uniform("Shadow", Stage::Fragment, &self.uniform)];
+        for name in SHADOW_MAPS.iter() {
+            bindings.push(Binding::Attachment("ShadowMap", Stage::Fragment, name));
+        }
+        bindings.push(Binding::Attachment(
+            "LocalShadowMap",
+            Stage::Fragment,
+            LOCAL_SHADOW_MAP,
+        ));
+        bindings.push(Binding::Sampler(
+            "ShadowSampler",
+            Stage::Fragment,
+            &self.sampler,
+        ));
+        bindings
+    }
+
+    /// Returns true if the shadow maps were created and can be bound
+    pub fn ready(&self, renderer: &Renderer) -> bool {
+        SHADOW_MAPS
+            .iter()
+            .chain([LOCAL_SHADOW_MAP].iter())
+            .all(|name| renderer.attachment(name).is_some())
+    }
+
+    /// Returns true if the shadow maps were recreated or the caster bias changed this frame and
+    /// pipelines must be rebound
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+}
+
+/// Returns orthographic projection view matrix of the directional light
+///
+/// The matrix covers `distance` around the `center` and is snapped to the shadow map texels,
+/// so the shadows do not shimmer, when the center moves.
+pub fn directional_proj_view(
+    direction: Vec3,
+    center: Vec3,
+    distance: f32,
+    resolution: u32,
+) -> Mat4 {
+    let direction = direction.normalize();
+    let up = if direction.y.abs() > 0.99 {
+        Vec3::unit_z()
+    } else {
+        Vec3::unit_y()
+    };
+    let origin = Point3::new(0.0, 0.0, 0.0);
+    let view = Mat4::look_at(
+        origin,
+        Point3::new(direction.x, direction.y, direction.z),
+        up,
+    );
+
+    let texel = 2.0 * distance / resolution.max(1) as f32;
+    let center = view * Vec4::new(center.x, center.y, center.z, 1.0);
+    let x = (center.x / texel).floor() * texel;
+    let y = (center.y / texel).floor() * texel;
+    // casters behind the covered area are also rendered
+    let depth = 2.0 * distance;
+
+    let proj = ortho(
+        x - distance,
+        x + distance,
+        y - distance,
+        y + distance,
+        -center.z - depth,
+        -center.z + depth,
+    );
+
+    OPENGL_TO_WGPU_MATRIX * proj * view
+}
+
+/// Returns far distances of the cascades, mixing logarithmic and uniform splits by `lambda`
+pub fn cascade_splits(near: f32, far: f32, cascades: usize, lambda: f32) -> Vec<f32> {
+    (1..=cascades)
+        .map(|i| {
+            let fraction = i as f32 / cascades as f32;
+            let logarithmic = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// Returns center and radius of the sphere bounding the camera frustum slice
+///
+/// The radius is rounded, so it does not change with the camera rotation and shadows do not
+/// shimmer.
+pub fn fit_cascade(
+    inverse_view: &Mat4,
+    tan_half_fov: f32,
+    aspect_ratio: f32,
+    near: f32,
+    far: f32,
+) -> (Vec3, f32) {
+    let mut corners = Vec::with_capacity(8);
+    for &distance in [near, far].iter() {
+        let y = tan_half_fov * distance;
+        let x = y * aspect_ratio;
+        for &(sx, sy) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter() {
+            let corner = inverse_view * Vec4::new(sx * x, sy * y, -distance, 1.0);
+            corners.push(Point3::new(corner.x, corner.y, corner.z));
+        }
+    }
+    let sum = corners
+        .iter()
+        .fold(Vec3::new(0.0, 0.0, 0.0), |sum, corner| {
+            sum + Vec3::new(corner.x, corner.y, corner.z)
+        });
+    let center = sum / corners.len() as f32;
+    let center_point = Point3::new(center.x, center.y, center.z);
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center_point))
+        .fold(0.0, f32::max);
+    (center, (radius * 16.0).ceil() / 16.0)
+}
+
+/// Returns distance, where the point light attenuation drops below 1/256 of its intensity
+pub fn point_light_range(constant: f32, linear: f32, quadratic: f32, intensity: f32) -> f32 {
+    let threshold = 256.0 * intensity.max(0.0);
+    if threshold <= constant {
+        return LOCAL_SHADOW_NEAR * 2.0;
+    }
+    let range = if quadratic > f32::EPSILON {
+        (-linear + (linear * linear + 4.0 * quadratic * (threshold - constant)).sqrt())
+            / (2.0 * quadratic)
+    } else if linear > f32::EPSILON {
+        (threshold - constant) / linear
+    } else {
+        f32::MAX
+    };
+    range.max(LOCAL_SHADOW_NEAR * 2.0)
+}
+
+/// Returns projection view matrices of the point light cube faces: +X, -X, +Y, -Y, +Z, -Z
+pub fn point_light_proj_views(position: Vec3, range: f32) -> Vec<Mat4> {
+    let proj = perspective(
+        Rad(std::f32::consts::FRAC_PI_2),
+        1.0,
+        LOCAL_SHADOW_NEAR,
+        range,
+    );
+    let eye = Point3::new(position.x, position.y, position.z);
+    [
+        (Vec3::unit_x(), -Vec3::unit_y()),
+        (-Vec3::unit_x(), -Vec3::unit_y()),
+        (Vec3::unit_y(), Vec3::unit_z()),
+        (-Vec3::unit_y(), -Vec3::unit_z()),
+        (Vec3::unit_z(), -Vec3::unit_y()),
+        (-Vec3::unit_z(), -Vec3::unit_y()),
+    ]
+    .iter()
+    .map(|&(direction, up)| OPENGL_TO_WGPU_MATRIX * proj * Mat4::look_at(eye, eye + direction, up))
+    .collect()
+}
+
+/// Returns perspective projection view matrix of the spot light cone
+pub fn spot_light_proj_view(
+    position: Vec3,
+    direction: Vec3,
+    outer_cut_off: f32,
+    range: f32,
+) -> Mat4 {
+    let direction = direction.normalize();
+    let up = if direction.y.abs() > 0.99 {
+        Vec3::unit_z()
+    } else {
+        Vec3::unit_y()
+    };
+    let fov = (2.0 * outer_cut_off.clamp(-1.0, 1.0).acos()).clamp(0.1, 3.0);
+    let proj = perspective(
+        Rad(fov),
+        1.0,
+        LOCAL_SHADOW_NEAR,
+        range.max(LOCAL_SHADOW_NEAR * 2.0),
+    );
+    let eye = Point3::new(position.x, position.y, position.z);
+    OPENGL_TO_WGPU_MATRIX * proj * Mat4::look_at(eye, eye + direction, up)
+}
+
+/// Returns indices of the `budget` most relevant lights by their scores
+pub fn select_local_lights(scores: &[f32], budget: usize) -> Vec<usize> {
+    let mut indices = (0..scores.len())
+        .filter(|&i| scores[i] > 0.0)
+        .collect::<Vec<_>>();
+    indices.sort_by(|&a, &b| {
+        scores[b]
+            .partial_cmp(&scores[a])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    indices.truncate(budget);
+    indices
+}
+
+/// Relevance of the light for the viewer, bright lights close to the camera win
+fn local_light_score(intensity: f32, position: Vec3, camera_position: Vec3) -> f32 {
+    intensity / (1.0 + (position - camera_position).magnitude2())
+}
+
+/// Returns name of the render graph pass of the point and spot light shadow map layer
+fn local_shadow_pass(layer: usize) -> String {
+    format!("local_shadow_{}", layer)
+}
+
+/// Shadows startup system
+pub fn startup(mut renderer: Mut<Renderer>, mut globals: Mut<Globals>) {
+    let shadows = Shadows::default();
+    let graph = renderer.graph_mut();
+    for (cascade, name) in SHADOW_MAPS.iter().enumerate() {
+        graph.add_attachment(
+            name,
+            Attachment::depth().with_size(cascade_size(&shadows, cascade)),
+        );
+        let mut pass = RenderPass::new(SHADOW_PASSES[cascade]).depth(name);
+        pass.clear_depth = true;
+        graph.add_pass(pass);
+        if let Some(main) = graph.pass_mut(MAIN_PASS) {
+            main.reads.push(String::from(*name));
+        }
+    }
+
+    graph.add_attachment(LOCAL_SHADOW_MAP, local_attachment(&shadows));
+    for layer in 0..MAX_LOCAL_SHADOWS * LOCAL_SHADOW_FACES {
+        let mut pass = RenderPass::new(&local_shadow_pass(layer))
+            .depth(LOCAL_SHADOW_MAP)
+            .layer(layer as u32);
+        pass.clear_depth = true;
+        graph.add_pass(pass);
+    }
+    if let Some(main) = graph.pass_mut(MAIN_PASS) {
+        main.reads.push(String::from(LOCAL_SHADOW_MAP));
+    }
+
+    globals.set(shadows);
+}
+
+/// Unused cascades keep minimal shadow maps, so the bindings stay valid
+fn cascade_size(shadows: &Shadows, cascade: usize) -> AttachmentSize {
+    if cascade < shadows.cascades {
+        AttachmentSize::Fixed(shadows.resolution, shadows.resolution)
+    } else {
+        AttachmentSize::Fixed(1, 1)
+    }
+}
+
+fn local_attachment(shadows: &Shadows) -> Attachment {
+    Attachment::depth()
+        .with_size(AttachmentSize::Fixed(
+            shadows.local_resolution,
+            shadows.local_resolution,
+        ))
+        .with_layers((MAX_LOCAL_SHADOWS * LOCAL_SHADOW_FACES) as u32)
+}
+
+/// Shadows loading system
+pub fn load(
+    world: Const<World>,
+    camera: Const<Camera>,
+    mut renderer: Mut<Renderer>,
+    mut globals: Mut<Globals>,
+) {
+    let shadows = match globals.get_mut::<Shadows>() {
+        Some(shadows) => shadows,
+        None => return,
+    };
+    shadows.cascades = shadows.cascades.clamp(1, MAX_CASCADES);
+
+    for (cascade, name) in SHADOW_MAPS.iter().enumerate() {
+        let size = cascade_size(shadows, cascade);
+        let resized = renderer
+            .graph()
+            .attachment(name)
+            .map(|attachment| attachment.size != size)
+            .unwrap_or(false);
+        if resized {
+            renderer
+                .graph_mut()
+                .add_attachment(name, Attachment::depth().with_size(size));
+        }
+    }
+
+    let local = local_attachment(shadows);
+    let resized = renderer
+        .graph()
+        .attachment(LOCAL_SHADOW_MAP)
+        .map(|attachment| attachment.size != local.size)
+        .unwrap_or(false);
+    if resized {
+        renderer.graph_mut().add_attachment(LOCAL_SHADOW_MAP, local);
+    }
+
+    let generation = renderer.attachments_generation();
+    // casters are rebound with the new bias
+    shadows.changed =
+        shadows.generation != Some(generation) || shadows.bound_caster_bias != shadows.caster_bias;
+    shadows.generation = Some(generation);
+    shadows.bound_caster_bias = shadows.caster_bias;
+
+    let direction = world
+        .query::<(&Light,)>()
+        .find_map(|(light,)| match light {
+            Light::Directional {
+                direction, enabled, ..
+            } if *enabled => Some(*direction),
+            _ => None,
+        })
+        .filter(|direction| direction.magnitude2() > 0.0)
+        .filter(|_| shadows.enabled);
+
+    let near = camera.near_plane.max(0.01);
+    let far = shadows.distance.max(near * 2.0);
+    let splits = cascade_splits(near, far, shadows.cascades, shadows.split_lambda);
+    let inverse_view = camera.view.and_then(|view| view.invert());
+    let aspect_ratio = camera.proj.map(|proj| proj.y.y / proj.x.x).unwrap_or(1.0);
+    let tan_half_fov = (camera.fov / 2.0).tan();
+
+    shadows.proj_views = match (direction, inverse_view) {
+        (Some(direction), Some(inverse_view)) => splits
+            .iter()
+            .enumerate()
+            .map(|(cascade, &split)| {
+                let split_near = if cascade == 0 {
+                    near
+                } else {
+                    splits[cascade - 1]
+                };
+                let (center, radius) =
+                    fit_cascade(&inverse_view, tan_half_fov, aspect_ratio, split_near, split);
+                directional_proj_view(direction, center, radius, shadows.resolution)
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let local_lights = load_local_lights(shadows, &world, camera.position());
+
+    let mut uniform = Uniform {
+        params: [
+            shadows.bias,
+            1.0 / shadows.resolution.max(1) as f32,
+            shadows.proj_views.len() as f32,
+            shadows.blend,
+        ],
+        local_params: [
+            shadows.local_bias,
+            1.0 / shadows.local_resolution.max(1) as f32,
+            shadows.local_lights.len() as f32,
+            0.0,
+        ],
+        ..Default::default()
+    };
+    if let Some(inverse_view) = inverse_view {
+        let position = inverse_view.w;
+        let direction = -inverse_view.z;
+        uniform.camera_position = [position.x, position.y, position.z, 1.0];
+        uniform.camera_direction = [direction.x, direction.y, direction.z, 0.0];
+    }
+    for (cascade, proj_view) in shadows.proj_views.iter().enumerate() {
+        uniform.proj_view[cascade] = (*proj_view).into();
+        uniform.splits[cascade] = splits[cascade];
+    }
+
+    let mut local_proj_views = vec![Mat4::identity(); shadows.local_uniforms.len()];
+    for (slot, (local_light, (position, proj_views))) in shadows
+        .local_lights
+        .iter()
+        .zip(local_lights.iter())
+        .enumerate()
+    {
+        let kind = match local_light {
+            LocalLight::Point(_) => 0.0,
+            LocalLight::Spot(_) => 1.0,
+        };
+        uniform.local[slot].position = [position.x, position.y, position.z, kind];
+        for (face, proj_view) in proj_views.iter().enumerate() {
+            uniform.local[slot].proj_view[face] = (*proj_view).into();
+            local_proj_views[slot * LOCAL_SHADOW_FACES + face] = *proj_view;
+        }
+    }
+
+    renderer.load_sampler(&mut shadows.sampler);
+    renderer.load_uniform_buffer(&mut shadows.uniform, bytemuck::cast_slice(&[uniform]));
+    for (cascade, cascade_uniform) in shadows.cascade_uniforms.iter_mut().enumerate() {
+        let proj_view = shadows
+            .proj_views
+            .get(cascade)
+            .cloned()
+            .unwrap_or_else(Mat4::identity);
+        let matrix_raw = AsRef::<[f32; 16]>::as_ref(&proj_view);
+        renderer.load_uniform_buffer(cascade_uniform, bytemuck::cast_slice(matrix_raw));
+    }
+    for (local_uniform, proj_view) in shadows.local_uniforms.iter_mut().zip(local_proj_views) {
+        let matrix_raw = AsRef::<[f32; 16]>::as_ref(&proj_view);
+        renderer.load_uniform_buffer(local_uniform, bytemuck::cast_slice(matrix_raw));
+    }
+}
+
+/// Selects shadow casting point and spot lights and returns their positions and projection
+/// view matrices by slots
+fn load_local_lights(
+    shadows: &mut Shadows,
+    world: &World,
+    camera_position: Vec3,
+) -> Vec<(Vec3, Vec<Mat4>)> {
+    let mut candidates = Vec::new();
+    let mut points = 0;
+    let mut spots = 0;
+    // indices follow the order of the lights uniform
+    for (light,) in world.query::<(&Light,)>() {
+        match light {
+            Light::Point {
+                position,
+                intensity,
+                enabled,
+                constant,
+                linear,
+                quadratic,
+                ..
+            } if *enabled && points < MAX_LIGHTS => {
+                let range = point_light_range(*constant, *linear, *quadratic, *intensity)
+                    .min(shadows.distance);
+                candidates.push((
+                    LocalLight::Point(points),
+                    *intensity,
+                    *position,
+                    point_light_proj_views(*position, range),
+                ));
+                points += 1;
+            }
+            Light::Spot {
+                position,
+                direction,
+                intensity,
+                enabled,
+                outer_cut_off,
+                ..
+            } if *enabled && spots < MAX_LIGHTS => {
+                if direction.magnitude2() > 0.0 {
+                    candidates.push((
+                        LocalLight::Spot(spots),
+                        *intensity,
+                        *position,
+                        vec![spot_light_proj_view(
+                            *position,
+                            *direction,
+                            *outer_cut_off,
+                            shadows.local_range,
+                        )],
+                    ));
+                }
+                spots += 1;
+            }
+            _ => {}
+        }
+    }
+
+    let budget = if shadows.enabled {
+        shadows.local_budget.min(MAX_LOCAL_SHADOWS)
+    } else {
+        0
+    };
+    let scores = candidates
+        .iter()
+        .map(|(_, intensity, position, _)| {
+            local_light_score(*intensity, *position, camera_position)
+        })
+        .collect::<Vec<_>>();
+    let selected = select_local_lights(&scores, budget);
+
+    shadows.local_lights = selected.iter().map(|&i| candidates[i].0).collect();
+    shadows.local_layers = selected
+        .iter()
+        .enumerate()
+        .flat_map(|(slot, &i)| {
+            (0..candidates[i].3.len()).map(move |face| slot * LOCAL_SHADOW_FACES + face)
+        })
+        .collect();
+    selected
+        .iter()
+        .map(|&i| (candidates[i].2, candidates[i].3.clone()))
+        .collect()
+}
+
+/// Uniform structure for shadow representation in shader
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct Uniform {
+    /// Light projection view matrices of the cascades
+    proj_view: [[[f32; 4]; 4]; MAX_CASCADES],
+    /// Far distances of the cascades
+    splits: [f32; MAX_CASCADES],
+    /// Camera position
+    camera_position: [f32; 4],
+    /// Camera view direction
+    camera_direction: [f32; 4],
+    /// Depth bias, texel size, number of cascades, blend fraction
+    params: [f32; 4],
+    /// Depth bias, texel size and number of point and spot light shadows
+    local_params: [f32; 4],
+    /// Point and spot light shadows
+    local: [LocalShadow; MAX_LOCAL_SHADOWS],
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
+
+/// Point or spot light shadow uniform data
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct LocalShadow {
+    /// Projection view matrices of the cube faces, spot lights use the first one
+    proj_view: [[[f32; 4]; 4]; LOCAL_SHADOW_FACES],
+    /// Light position and kind: 0.0 for point lights, 1.0 for spot lights
+    position: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for LocalShadow {}
+unsafe impl bytemuck::Pod for LocalShadow {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(matrix: &Mat4, point: Vec3) -> Vec3 {
+        let result = matrix * Vec4::new(point.x, point.y, point.z, 1.0);
+        Vec3::new(result.x, result.y, result.z) / result.w
+    }
+
+    #[test]
+    fn directional_proj_view_covers_distance_around_center() {
+        let center = Vec3::new(10.0, 5.0, -20.0);
+        let matrix = directional_proj_view(Vec3::new(-1.0, -2.0, 0.5), center, 32.0, 1024);
+
+        let projected = project(&matrix, center);
+        assert!(projected.x.abs() < 0.01 && projected.y.abs() < 0.01);
+        assert!(projected.z > 0.0 && projected.z < 1.0);
+
+        let far = project(&matrix, center + Vec3::new(0.0, 0.0, 64.0));
+        assert!(far.x.abs() > 1.0 || far.y.abs() > 1.0);
+    }
+
+    #[test]
+    fn cascades_split_and_cover_the_frustum() {
+        let splits = cascade_splits(1.0, 100.0, 4, 0.5);
+        assert_eq!(splits.len(), 4);
+        assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!((splits[3] - 100.0).abs() < 0.001);
+        assert!((cascade_splits(1.0, 100.0, 2, 0.0)[0] - 50.5).abs() < 0.001);
+
+        let view = Mat4::look_at(
+            Point3::new(0.0, 10.0, 0.0),
+            Point3::new(0.0, 10.0, -1.0),
+            Vec3::unit_y(),
+        );
+        let (center, radius) = fit_cascade(&view.invert().unwrap(), 0.5, 2.0, 1.0, 11.0);
+        assert!((center.z + 6.0).abs() < 0.001 && (center.y - 10.0).abs() < 0.001);
+        // far corner at (11, 5.5, -11) relative to the camera
+        let corner = Vec3::new(11.0, 15.5, -11.0);
+        assert!((corner - center).magnitude() <= radius);
+    }
+
+    #[test]
+    fn local_lights_cast_shadows_in_range() {
+        let range = point_light_range(1.0, 0.35, 0.44, 1.0);
+        let attenuation = 1.0 / (1.0 + 0.35 * range + 0.44 * range * range);
+        assert!((attenuation - 1.0 / 256.0).abs() < 0.0001);
+        assert!((point_light_range(1.0, 0.5, 0.0, 1.0) - 510.0).abs() < 0.001);
+
+        let light = Vec3::new(1.0, 2.0, 3.0);
+        let faces = point_light_proj_views(light, range);
+        assert_eq!(faces.len(), LOCAL_SHADOW_FACES);
+        let projected = project(&faces[0], light + Vec3::new(5.0, 1.0, -0.5));
+        assert!(projected.x.abs() < 1.0 && projected.y.abs() < 1.0);
+        assert!(projected.z > 0.0 && projected.z < 1.0);
+        let projected = project(&faces[5], light + Vec3::new(0.5, -1.0, -5.0));
+        assert!(projected.x.abs() < 1.0 && projected.y.abs() < 1.0);
+
+        let spot = spot_light_proj_view(light, Vec3::new(0.0, -1.0, 0.0), 0.65, 50.0);
+        let projected = project(&spot, light + Vec3::new(1.0, -10.0, 0.0));
+        assert!(projected.x.abs() < 0.2 && projected.y.abs() < 0.2);
+        assert!(projected.z > 0.0 && projected.z < 1.0);
+    }
+
+    #[test]
+    fn most_relevant_local_lights_are_selected() {
+        let camera = Vec3::new(0.0, 0.0, 0.0);
+        let scores = [
+            local_light_score(1.0, Vec3::new(30.0, 0.0, 0.0), camera),
+            local_light_score(1.0, Vec3::new(2.0, 0.0, 0.0), camera),
+            local_light_score(0.0, Vec3::new(0.0, 0.0, 0.0), camera),
+            local_light_score(8.0, Vec3::new(10.0, 0.0, 0.0), camera),
+        ];
+        assert_eq!(select_local_lights(&scores, 2), vec![1, 3]);
+        assert_eq!(select_local_lights(&scores, 4), vec![1, 3, 0]);
+        assert!(select_local_lights(&scores, 0).is_empty());
+    }
+}
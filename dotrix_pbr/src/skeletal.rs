@@ -1,16 +1,23 @@
 use dotrix_core::assets::{Mesh, Shader, Skin, Texture};
 use dotrix_core::camera::ProjView;
 use dotrix_core::ecs::{Const, Mut, System};
-use dotrix_core::renderer::{BindGroup, Binding, PipelineLayout, PipelineOptions, Sampler, Stage};
+use dotrix_core::renderer::{
+    BindGroup, Binding, PipelineLayout, PipelineOptions, Sampler, Stage, DEPTH_PREPASS,
+};
 use dotrix_core::{
     Application, Assets, Color, Globals, Id, Pipeline, Pose, Renderer, Transform, World,
 };
 
 use dotrix_math::{Quat, Rad, Rotation3, Vec3};
 
-use crate::{Lights, Material, Model};
+use crate::deferred::{RenderingPath, GBUFFER_PASS};
+use crate::occlusion::OcclusionCulling;
+use crate::ssao::SSAO_DEPTH_PASS;
+use crate::velocity::Velocity;
+use crate::{ClusteredLights, Environment, Lights, Material, Model, Shadows, Ssao};
 
 pub const PIPELINE_LABEL: &str = "pbr::skeletal";
+pub const MOTION_PIPELINE_LABEL: &str = "pbr::skeletal::motion";
 
 pub struct Entity {
     /// Mesh asset ID
@@ -101,8 +108,19 @@ pub fn render(
     mut renderer: Mut<Renderer>,
     mut assets: Mut<Assets>,
     globals: Const<Globals>,
+    ssao: Const<Ssao>,
+    clusters: Const<ClusteredLights>,
+    path: Const<RenderingPath>,
     world: Const<World>,
 ) {
+    let deferred = *path == RenderingPath::Deferred;
+    let velocity = globals.get::<Velocity>();
+    let velocity_enabled = velocity.map(|velocity| velocity.enabled()).unwrap_or(false);
+    // models occluding the culled objects render their depth
+    let occluders = globals
+        .get::<OcclusionCulling>()
+        .map(|culling| culling.enabled)
+        .unwrap_or(false);
     let query = world.query::<(
         &mut Model,
         &mut Pose,
@@ -111,11 +129,24 @@ pub fn render(
         &mut Pipeline,
     )>();
 
+    let shadows = globals.get::<Shadows>();
+    let shadows_changed = shadows.map(|shadows| shadows.changed()).unwrap_or(false);
+    let environment = globals.get::<Environment>();
+    let environment_changed = environment
+        .map(|environment| environment.changed())
+        .unwrap_or(false);
+
     for (model, pose, material, transform, pipeline) in query {
         if pipeline.shader.is_null() {
             pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
         }
 
+        if shadows_changed || environment_changed {
+            pipeline.bindings.unload();
+            model.depth.bindings.unload();
+            model.prepass.bindings.unload();
+        }
+
         // check if model is disabled or already rendered
         if !pipeline.cycle(&renderer) {
             continue;
@@ -138,7 +169,9 @@ pub fn render(
 
         let mesh = assets.get(model.mesh).unwrap();
 
-        if !pipeline.ready() {
+        // deferred models are shaded once anyway
+        let depth_prepass = !deferred && renderer.depth_prepass();
+        if !pipeline.ready() || !model.depth.ready() || depth_prepass && !model.prepass.ready() {
             if let Some(shader) = assets.get(pipeline.shader) {
                 if !shader.loaded() {
                     continue;
@@ -162,75 +195,202 @@ pub fn render(
                     .get::<Lights>()
                     .expect("Lights buffer must be loaded");
 
+                let shadows = shadows.expect("Shadows buffer must be loaded");
+                if !shadows.ready(&renderer) || !ssao.ready(&renderer) || !clusters.ready() {
+                    continue;
+                }
+
+                let environment = environment.expect("Environment buffer must be loaded");
+                let velocity = velocity.expect("Velocity buffer must be loaded");
+                let motion_shader_id = assets
+                    .find::<Shader>(MOTION_PIPELINE_LABEL)
+                    .unwrap_or_default();
+                let motion_shader = match assets.get(motion_shader_id) {
+                    Some(motion_shader) if motion_shader.loaded() => motion_shader,
+                    _ => continue,
+                };
+
+                let mut globals_bindings = vec![
+                    Binding::Uniform("ProjView", Stage::Vertex, &proj_view.uniform),
+                    Binding::Sampler("Sampler", Stage::Fragment, sampler),
+                ];
+                // deferred models are lit by the lighting pass
+                if !deferred {
+                    globals_bindings.push(Binding::Uniform(
+                        "Lights",
+                        Stage::Fragment,
+                        &lights.uniform,
+                    ));
+                    globals_bindings.extend(shadows.bindings());
+                    globals_bindings.push(ssao.binding());
+                    globals_bindings.extend(clusters.bindings());
+                    globals_bindings.extend(environment.bindings());
+                }
+
+                let bindings = [
+                    BindGroup::new("Globals", globals_bindings),
+                    BindGroup::new(
+                        "Locals",
+                        vec![
+                            Binding::Uniform("Transform", Stage::Vertex, &model.transform),
+                            Binding::Uniform("Material", Stage::Fragment, &material.uniform),
+                            Binding::Texture("Texture", Stage::Fragment, &texture.buffer),
+                            Binding::Texture(
+                                "RoughnessTexture",
+                                Stage::Fragment,
+                                &roughness_texture.buffer,
+                            ),
+                            Binding::Texture(
+                                "MetallicTexture",
+                                Stage::Fragment,
+                                &metallic_texture.buffer,
+                            ),
+                            Binding::Texture("AoTexture", Stage::Fragment, &ao_texture.buffer),
+                            Binding::Texture(
+                                "NormalTexture",
+                                Stage::Fragment,
+                                &normal_texture.buffer,
+                            ),
+                            Binding::Storage("Joints", Stage::Vertex, &pose.buffer),
+                        ],
+                    ),
+                    BindGroup::new(
+                        "Morph",
+                        vec![
+                            Binding::Storage("MorphTargets", Stage::Vertex, &mesh.morph_buffer),
+                            Binding::Storage("MorphWeights", Stage::Vertex, &model.morph),
+                        ],
+                    ),
+                ];
+
+                let motion_bindings = [
+                    BindGroup::new(
+                        "Globals",
+                        vec![
+                            Binding::Uniform("ProjView", Stage::Vertex, &proj_view.uniform),
+                            Binding::Uniform("Velocity", Stage::Vertex, &velocity.uniform),
+                        ],
+                    ),
+                    BindGroup::new(
+                        "Locals",
+                        vec![
+                            Binding::Uniform("Transform", Stage::Vertex, &model.transform),
+                            Binding::Uniform(
+                                "PreviousTransform",
+                                Stage::Vertex,
+                                &model.previous_transform,
+                            ),
+                            Binding::Storage("Joints", Stage::Vertex, &pose.buffer),
+                        ],
+                    ),
+                    BindGroup::new(
+                        "Morph",
+                        vec![
+                            Binding::Storage("MorphTargets", Stage::Vertex, &mesh.morph_buffer),
+                            Binding::Storage("MorphWeights", Stage::Vertex, &model.morph),
+                        ],
+                    ),
+                ];
+
+                if deferred {
+                    pipeline.options.pass = Some(String::from(GBUFFER_PASS));
+                }
                 renderer.bind(
                     pipeline,
                     PipelineLayout {
                         label: String::from(PIPELINE_LABEL),
                         mesh: Some(mesh),
                         shader,
-                        bindings: &[
-                            BindGroup::new(
-                                "Globals",
-                                vec![
-                                    Binding::Uniform("ProjView", Stage::Vertex, &proj_view.uniform),
-                                    Binding::Sampler("Sampler", Stage::Fragment, sampler),
-                                    Binding::Uniform("Lights", Stage::Fragment, &lights.uniform),
-                                ],
-                            ),
-                            BindGroup::new(
-                                "Locals",
-                                vec![
-                                    Binding::Uniform("Transform", Stage::Vertex, &model.transform),
-                                    Binding::Uniform(
-                                        "Material",
-                                        Stage::Fragment,
-                                        &material.uniform,
-                                    ),
-                                    Binding::Texture("Texture", Stage::Fragment, &texture.buffer),
-                                    Binding::Texture(
-                                        "RoughnessTexture",
-                                        Stage::Fragment,
-                                        &roughness_texture.buffer,
-                                    ),
-                                    Binding::Texture(
-                                        "MetallicTexture",
-                                        Stage::Fragment,
-                                        &metallic_texture.buffer,
-                                    ),
-                                    Binding::Texture(
-                                        "AoTexture",
-                                        Stage::Fragment,
-                                        &ao_texture.buffer,
-                                    ),
-                                    Binding::Texture(
-                                        "NormalTexture",
-                                        Stage::Fragment,
-                                        &normal_texture.buffer,
-                                    ),
-                                    Binding::Uniform("Joints", Stage::Vertex, &pose.uniform),
-                                ],
-                            ),
-                        ],
+                        bindings: &bindings,
+                        vertex_buffers: &[],
+                        push_constants: None,
+                        options: if depth_prepass {
+                            PipelineOptions::depth_prepassed()
+                        } else {
+                            PipelineOptions::default()
+                        },
+                    },
+                );
+                if depth_prepass {
+                    model.prepass.shader = pipeline.shader;
+                    model.prepass.options.pass = Some(String::from(DEPTH_PREPASS));
+                    renderer.bind(
+                        &mut model.prepass,
+                        PipelineLayout {
+                            label: String::from(PIPELINE_LABEL),
+                            mesh: Some(mesh),
+                            shader,
+                            bindings: &bindings,
+                            vertex_buffers: &[],
+                            push_constants: None,
+                            options: PipelineOptions::default(),
+                        },
+                    );
+                }
+                // the depth for the ambient occlusion is rendered with the motion
+                model.depth.shader = motion_shader_id;
+                model.depth.options.pass = Some(String::from(SSAO_DEPTH_PASS));
+                renderer.bind(
+                    &mut model.depth,
+                    PipelineLayout {
+                        label: String::from(MOTION_PIPELINE_LABEL),
+                        mesh: Some(mesh),
+                        shader: motion_shader,
+                        bindings: &motion_bindings,
+                        vertex_buffers: &[],
+                        push_constants: None,
                         options: PipelineOptions::default(),
                     },
                 );
             }
         }
 
+        // culled models still cast shadows of the lights outside of the frustum
+        model.prepass.culled = pipeline.culled;
+        model.depth.culled = pipeline.culled;
+        if depth_prepass && model.prepass.cycle(&renderer) {
+            renderer.run(&mut model.prepass, mesh);
+        }
         renderer.run(pipeline, mesh);
+        if (ssao.enabled || velocity_enabled || occluders) && model.depth.cycle(&renderer) {
+            renderer.run(&mut model.depth, mesh);
+        }
     }
 }
 
-pub fn startup(mut assets: Mut<Assets>) {
-    let shader = include_str!("shaders/skeletal.wgsl");
+pub fn startup(mut assets: Mut<Assets>, path: Const<RenderingPath>) {
+    let shader = path.add_to_shader(&Model::add_to_shader(include_str!("shaders/skeletal.wgsl")));
     assets.store_as(
         Shader {
             name: String::from(PIPELINE_LABEL),
-            code: Lights::add_to_shader(shader, 0, 2),
+            code: Environment::add_to_shader(
+                &ClusteredLights::add_to_shader(
+                    &Ssao::add_to_shader(
+                        &Shadows::add_to_shader(&Lights::add_to_shader(&shader, 0, 2), 0, 3),
+                        0,
+                        10,
+                    ),
+                    0,
+                    11,
+                ),
+                0,
+                14,
+            ),
             ..Default::default()
         },
         PIPELINE_LABEL,
     );
+
+    assets.store_as(
+        Shader {
+            name: String::from(MOTION_PIPELINE_LABEL),
+            code: Model::add_to_shader(&Velocity::add_to_shader(include_str!(
+                "shaders/skeletal_motion.wgsl"
+            ))),
+            ..Default::default()
+        },
+        MOTION_PIPELINE_LABEL,
+    );
 }
 
 pub fn extension(app: &mut Application) {
@@ -0,0 +1,284 @@
+//! Screen-space ambient occlusion
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    Attachment, BindGroup, Binding, DepthBufferMode, PipelineLayout, PipelineOptions, RenderPass,
+    Stage, TextureFormat, UniformBuffer, MAIN_PASS,
+};
+use dotrix_core::{Assets, Camera, Color, Pipeline, Renderer};
+
+use dotrix_math::{Mat4, SquareMatrix};
+
+use crate::tone_mapping::FULLSCREEN_TRIANGLE;
+
+const OCCLUSION_LABEL: &str = "dotrix::ssao::occlusion";
+const BLUR_LABEL: &str = "dotrix::ssao::blur";
+
+/// Maximal number of the hemisphere samples per pixel
+pub const MAX_SSAO_SAMPLES: usize = 32;
+
+/// Name of the render graph pass rendering the depth of the models receiving the occlusion
+pub const SSAO_DEPTH_PASS: &str = "ssao_depth";
+
+/// Name of the render graph depth attachment of the [`SSAO_DEPTH_PASS`]
+pub const SSAO_DEPTH: &str = "ssao_depth";
+
+/// Name of the render graph attachment with the blurred ambient occlusion
+pub const SSAO_TARGET: &str = "ssao";
+
+/// Name of the render graph attachment with the raw ambient occlusion
+const SSAO_OCCLUSION: &str = "ssao_occlusion";
+
+/// Screen-space ambient occlusion service
+///
+/// Solid and skeletal models render their depth in the [`SSAO_DEPTH_PASS`] before the main
+/// pass. Positions and normals are reconstructed from the depth, `samples` points of the
+/// hemisphere of the `radius` around every pixel are tested against the depth, and the
+/// occlusion is blurred into the [`SSAO_TARGET`], that multiplies the ambient light of the
+/// pipelines including `{{ include(ssao) }}`, see [`Ssao::add_to_shader`].
+pub struct Ssao {
+    /// Ambient occlusion is enabled
+    pub enabled: bool,
+    /// Radius of the sampled hemisphere in world units
+    pub radius: f32,
+    /// Depth difference ignored by the test, prevents self occlusion
+    pub bias: f32,
+    /// Power of the occlusion, higher values darken the creases more
+    pub intensity: f32,
+    /// Number of the hemisphere samples per pixel, up to [`MAX_SSAO_SAMPLES`]
+    pub samples: usize,
+    /// Ambient occlusion uniform buffer
+    pub uniform: UniformBuffer,
+    occlusion: Pipeline,
+    blur: Pipeline,
+    generation: Option<usize>,
+}
+
+impl Default for Ssao {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.0,
+            samples: 16,
+            uniform: UniformBuffer::default(),
+            occlusion: Pipeline::default().with_pass(SSAO_OCCLUSION),
+            blur: Pipeline::default().with_pass(SSAO_TARGET),
+            generation: None,
+        }
+    }
+}
+
+impl Ssao {
+    /// Integrates ambient occlusion into shader
+    ///
+    /// The `source` shader code must contain `{{ include(ssao) }}` label, the occlusion texture
+    /// takes the `binding`. Then `let occlusion = calculate_ssao(in.position);` returns the
+    /// occlusion of the fragment, that can be multiplied with the material ambient occlusion.
+    pub fn add_to_shader(source: &str, bind_group: usize, binding: usize) -> String {
+        let ssao_code = include_str!("shaders/ssao.inc.wgsl")
+            .replace("{{ bind_group }}", &format!("{:?}", bind_group))
+            .replace("{{ binding }}", &format!("{:?}", binding));
+        source.replace("{{ include(ssao) }}", &ssao_code)
+    }
+
+    /// Returns binding of the occlusion texture for lit pipelines
+    pub fn binding(&self) -> Binding<'_> {
+        Binding::Attachment("Ssao", Stage::Fragment, SSAO_TARGET)
+    }
+
+    /// Returns true if the occlusion texture is created and the pipelines can be bound
+    pub fn ready(&self, renderer: &Renderer) -> bool {
+        renderer.attachment(SSAO_TARGET).is_some()
+    }
+}
+
+/// Returns `count` points of the unit hemisphere around the Z axis
+///
+/// Points are spread by the golden angle and get denser close to the center, where the
+/// occlusion matters most.
+pub fn ssao_kernel(count: usize) -> Vec<[f32; 4]> {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+    (0..count)
+        .map(|i| {
+            let t = (i as f32 + 0.5) / count as f32;
+            let z = 1.0 - t;
+            let r = (1.0 - z * z).sqrt();
+            let phi = golden_angle * i as f32;
+            let scale = 0.1 + 0.9 * t * t;
+            [r * phi.cos() * scale, r * phi.sin() * scale, z * scale, 0.0]
+        })
+        .collect()
+}
+
+/// Ambient occlusion startup system
+pub fn startup(mut renderer: Mut<Renderer>, mut assets: Mut<Assets>) {
+    let graph = renderer.graph_mut();
+    graph.add_attachment(SSAO_DEPTH, Attachment::depth());
+    graph.add_attachment(SSAO_OCCLUSION, Attachment::color(TextureFormat::r_f16()));
+    graph.add_attachment(SSAO_TARGET, Attachment::color(TextureFormat::r_f16()));
+
+    let mut depth_pass = RenderPass::new(SSAO_DEPTH_PASS).depth(SSAO_DEPTH);
+    depth_pass.clear_depth = true;
+    graph.add_pass(depth_pass);
+    graph.add_pass(
+        RenderPass::new(SSAO_OCCLUSION)
+            .reads(SSAO_DEPTH)
+            .writes(SSAO_OCCLUSION),
+    );
+    // without the occlusion the ambient light is not affected
+    graph.add_pass(
+        RenderPass::new(SSAO_TARGET)
+            .reads(SSAO_OCCLUSION)
+            .writes(SSAO_TARGET)
+            .clear(Color::white()),
+    );
+    if let Some(main) = graph.pass_mut(MAIN_PASS) {
+        main.reads.push(String::from(SSAO_TARGET));
+    }
+
+    for (label, code) in [
+        (OCCLUSION_LABEL, include_str!("shaders/ssao.wgsl")),
+        (BLUR_LABEL, include_str!("shaders/ssao_blur.wgsl")),
+    ]
+    .iter()
+    {
+        let mut shader = Shader {
+            name: String::from(*label),
+            code: String::from(*code),
+            ..Default::default()
+        };
+        shader.load(&renderer);
+        assets.store_as(shader, label);
+    }
+}
+
+/// Ambient occlusion rendering system
+pub fn render(
+    mut ssao: Mut<Ssao>,
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    camera: Const<Camera>,
+) {
+    let proj = camera.proj.unwrap_or_else(Mat4::identity);
+    let mut uniform = Uniform {
+        proj: proj.into(),
+        inverse_proj: proj.invert().unwrap_or_else(Mat4::identity).into(),
+        params: [
+            ssao.radius,
+            ssao.bias,
+            ssao.intensity,
+            ssao.samples.clamp(1, MAX_SSAO_SAMPLES) as f32,
+        ],
+        ..Default::default()
+    };
+    for (i, sample) in ssao_kernel(ssao.samples.clamp(1, MAX_SSAO_SAMPLES))
+        .into_iter()
+        .enumerate()
+    {
+        uniform.kernel[i] = sample;
+    }
+    renderer.load_uniform_buffer(&mut ssao.uniform, bytemuck::cast_slice(&[uniform]));
+
+    // occlusion textures are recreated on resize
+    let generation = renderer.attachments_generation();
+    if ssao.generation != Some(generation) {
+        ssao.generation = Some(generation);
+        ssao.occlusion.bindings.unload();
+        ssao.blur.bindings.unload();
+    }
+
+    if !ssao.enabled {
+        return;
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    let Ssao {
+        occlusion,
+        blur,
+        uniform,
+        ..
+    } = &mut *ssao;
+
+    for (pipeline, label, source) in [
+        (occlusion, OCCLUSION_LABEL, SSAO_DEPTH),
+        (blur, BLUR_LABEL, SSAO_OCCLUSION),
+    ] {
+        if pipeline.shader.is_null() {
+            pipeline.shader = assets.find::<Shader>(label).unwrap_or_default();
+        }
+        if !pipeline.cycle(&renderer) || renderer.attachment(source).is_none() {
+            return;
+        }
+
+        if !pipeline.ready() {
+            let shader = match assets.get(pipeline.shader) {
+                Some(shader) if shader.loaded() => shader,
+                _ => return,
+            };
+            renderer.bind(
+                pipeline,
+                PipelineLayout {
+                    label: String::from(label),
+                    mesh: Some(mesh),
+                    shader,
+                    bindings: &[BindGroup::new(
+                        "Globals",
+                        vec![
+                            Binding::Uniform("Ssao", Stage::Fragment, uniform),
+                            Binding::Attachment("Source", Stage::Fragment, source),
+                        ],
+                    )],
+                    vertex_buffers: &[],
+                    push_constants: None,
+                    options: PipelineOptions {
+                        depth_buffer_mode: DepthBufferMode::Disabled,
+                        disable_cull_mode: true,
+                        ..Default::default()
+                    },
+                },
+            );
+        }
+
+        renderer.run(pipeline, mesh);
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    proj: [[f32; 4]; 4],
+    inverse_proj: [[f32; 4]; 4],
+    /// Radius, bias, intensity and number of samples
+    params: [f32; 4],
+    kernel: [[f32; 4]; MAX_SSAO_SAMPLES],
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_fits_the_unit_hemisphere() {
+        let kernel = ssao_kernel(MAX_SSAO_SAMPLES);
+        assert_eq!(kernel.len(), MAX_SSAO_SAMPLES);
+        for sample in kernel.iter() {
+            let length =
+                (sample[0] * sample[0] + sample[1] * sample[1] + sample[2] * sample[2]).sqrt();
+            assert!(sample[2] > 0.0);
+            assert!(length <= 1.0 + f32::EPSILON);
+            assert!(length >= 0.1 - f32::EPSILON);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Screen-space reflections
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    Attachment, BindGroup, Binding, DepthBufferMode, PipelineLayout, PipelineOptions, RenderPass,
+    Sampler, Stage, TextureBuffer, TextureFormat, UniformBuffer,
+};
+use dotrix_core::{Assets, Camera, Color, CubeMap, Pipeline, Renderer};
+
+use dotrix_math::{Mat4, SquareMatrix, Vec3};
+
+use crate::deferred::{GBUFFER_ALBEDO, GBUFFER_DEPTH, GBUFFER_MATERIAL, GBUFFER_NORMAL};
+use crate::tone_mapping::{FULLSCREEN_TRIANGLE, HDR_TARGET, TONE_MAPPING_PASS};
+use crate::RenderingPath;
+
+const PIPELINE_LABEL: &str = "dotrix::ssr";
+
+/// Name of the render graph pass tracing the reflections
+pub const SSR_PASS: &str = "ssr";
+
+/// Name of the render graph attachment with the reflected colors
+pub const SSR_TARGET: &str = "ssr";
+
+/// Screen-space reflections service
+///
+/// Rays reflected by the normals of the G-buffer are marched through the G-buffer depth in
+/// `steps` steps up to the `max_distance`, and the colors of the [`HDR_TARGET`] they hit are
+/// added to the scene by the tone mapping pass, weighted by the Fresnel term and the
+/// smoothness of the surface. Rays leaving the screen fall back to the `environment` map.
+///
+/// Reflections need the normal buffer, so they are traced with [`RenderingPath::Deferred`]
+/// only, that suits smooth wet surfaces and water the best.
+pub struct Ssr {
+    /// Reflections are enabled
+    pub enabled: bool,
+    /// Maximal length of the reflected rays in world units
+    pub max_distance: f32,
+    /// Number of the ray marching steps
+    pub steps: u32,
+    /// Depth behind the surfaces, where the rays still hit them
+    pub thickness: f32,
+    /// Strength of the reflections
+    pub intensity: f32,
+    /// Surfaces of this roughness and rougher do not reflect
+    pub max_roughness: f32,
+    /// Fraction of the screen, where the reflections fade into the environment
+    pub edge_fade: f32,
+    /// Environment reflected by the rays, that did not hit the scene
+    pub environment: Option<CubeMap>,
+    /// Reflections uniform buffer
+    pub uniform: UniformBuffer,
+    sampler: Sampler,
+    fallback: TextureBuffer,
+    pipeline: Pipeline,
+    generation: Option<usize>,
+    environment_loaded: bool,
+}
+
+impl Default for Ssr {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_distance: 20.0,
+            steps: 48,
+            thickness: 0.5,
+            intensity: 1.0,
+            max_roughness: 0.6,
+            edge_fade: 0.1,
+            environment: None,
+            uniform: UniformBuffer::default(),
+            sampler: Sampler::linear_clamp(),
+            fallback: TextureBuffer::default(),
+            pipeline: Pipeline::default().with_pass(SSR_PASS),
+            generation: None,
+            environment_loaded: false,
+        }
+    }
+}
+
+/// Reflections startup system
+pub fn startup(path: Const<RenderingPath>, mut renderer: Mut<Renderer>, mut assets: Mut<Assets>) {
+    let graph = renderer.graph_mut();
+    graph.add_attachment(SSR_TARGET, Attachment::color(TextureFormat::rgba_f16()));
+
+    // without the reflections nothing is added to the scene
+    let mut pass = RenderPass::new(SSR_PASS)
+        .writes(SSR_TARGET)
+        .clear(Color::rgba(0.0, 0.0, 0.0, 0.0));
+    if *path == RenderingPath::Deferred {
+        pass = [
+            HDR_TARGET,
+            GBUFFER_ALBEDO,
+            GBUFFER_NORMAL,
+            GBUFFER_MATERIAL,
+            GBUFFER_DEPTH,
+        ]
+        .iter()
+        .fold(pass, |pass, source| pass.reads(source));
+    }
+    graph.add_pass(pass);
+    if let Some(tone_mapping) = graph.pass_mut(TONE_MAPPING_PASS) {
+        tone_mapping.reads.push(String::from(SSR_TARGET));
+    }
+
+    let mut shader = Shader {
+        name: String::from(PIPELINE_LABEL),
+        code: String::from(include_str!("shaders/ssr.wgsl")),
+        ..Default::default()
+    };
+    shader.load(&renderer);
+    assets.store_as(shader, PIPELINE_LABEL);
+}
+
+/// Reflections rendering system
+pub fn render(
+    mut ssr: Mut<Ssr>,
+    path: Const<RenderingPath>,
+    mut renderer: Mut<Renderer>,
+    mut assets: Mut<Assets>,
+    camera: Const<Camera>,
+) {
+    if !ssr.enabled || *path != RenderingPath::Deferred {
+        return;
+    }
+
+    let proj_view = match (camera.proj, camera.view) {
+        (Some(proj), Some(view)) => proj * view,
+        _ => return,
+    };
+    let environment_loaded = ssr
+        .environment
+        .as_mut()
+        .map(|environment| environment.load(&renderer, &mut assets))
+        .unwrap_or(false);
+    let uniform = Uniform::new(&ssr, proj_view, camera.position(), environment_loaded);
+    renderer.load_uniform_buffer(&mut ssr.uniform, bytemuck::cast_slice(&[uniform]));
+    renderer.load_sampler(&mut ssr.sampler);
+    if !ssr.fallback.loaded() {
+        let face: &[u8] = &[0, 0, 0, 0];
+        renderer.load_texture_buffer(&mut ssr.fallback, 1, 1, &[face; 6]);
+    }
+
+    // the G-buffer is recreated on resize and the environment replaces the fallback once loaded
+    let generation = renderer.attachments_generation();
+    if ssr.generation != Some(generation) || ssr.environment_loaded != environment_loaded {
+        ssr.generation = Some(generation);
+        ssr.environment_loaded = environment_loaded;
+        ssr.pipeline.bindings.unload();
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    let Ssr {
+        uniform,
+        sampler,
+        fallback,
+        pipeline,
+        environment,
+        ..
+    } = &mut *ssr;
+    if pipeline.shader.is_null() {
+        pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+    }
+    if !pipeline.cycle(&renderer) {
+        return;
+    }
+
+    if !pipeline.ready() {
+        let shader = match assets.get(pipeline.shader) {
+            Some(shader) if shader.loaded() => shader,
+            _ => return,
+        };
+        let sources = [
+            HDR_TARGET,
+            GBUFFER_ALBEDO,
+            GBUFFER_NORMAL,
+            GBUFFER_MATERIAL,
+            GBUFFER_DEPTH,
+        ];
+        if !sources
+            .iter()
+            .all(|name| renderer.attachment(name).is_some())
+        {
+            return;
+        }
+        let environment = match environment {
+            Some(environment) if environment_loaded => &environment.buffer,
+            _ => fallback,
+        };
+
+        renderer.bind(
+            pipeline,
+            PipelineLayout {
+                label: String::from(PIPELINE_LABEL),
+                mesh: Some(mesh),
+                shader,
+                bindings: &[BindGroup::new(
+                    "Globals",
+                    vec![
+                        Binding::Uniform("Ssr", Stage::Fragment, uniform),
+                        Binding::Attachment("Hdr", Stage::Fragment, HDR_TARGET),
+                        Binding::Attachment("Albedo", Stage::Fragment, GBUFFER_ALBEDO),
+                        Binding::Attachment("Normal", Stage::Fragment, GBUFFER_NORMAL),
+                        Binding::Attachment("Material", Stage::Fragment, GBUFFER_MATERIAL),
+                        Binding::Attachment("Depth", Stage::Fragment, GBUFFER_DEPTH),
+                        Binding::TextureCube("Environment", Stage::Fragment, environment),
+                        Binding::Sampler("Sampler", Stage::Fragment, sampler),
+                    ],
+                )],
+                vertex_buffers: &[],
+                push_constants: None,
+                options: PipelineOptions {
+                    depth_buffer_mode: DepthBufferMode::Disabled,
+                    disable_cull_mode: true,
+                    ..Default::default()
+                },
+            },
+        );
+    }
+
+    renderer.run(pipeline, mesh);
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    proj_view: [[f32; 4]; 4],
+    inverse_proj_view: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    /// Max distance, number of steps, thickness and intensity
+    params: [f32; 4],
+    /// Max roughness, environment is loaded and edge fade
+    fade: [f32; 4],
+}
+
+impl Uniform {
+    fn new(ssr: &Ssr, proj_view: Mat4, position: Vec3, environment_loaded: bool) -> Self {
+        Self {
+            proj_view: proj_view.into(),
+            inverse_proj_view: proj_view.invert().unwrap_or_else(Mat4::identity).into(),
+            camera_position: [position.x, position.y, position.z, 1.0],
+            params: [
+                ssr.max_distance,
+                ssr.steps.max(1) as f32,
+                ssr.thickness,
+                ssr.intensity,
+            ],
+            fade: [
+                ssr.max_roughness.max(0.01),
+                if environment_loaded { 1.0 } else { 0.0 },
+                ssr.edge_fade,
+                0.0,
+            ],
+        }
+    }
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_clamps_steps_and_roughness() {
+        let ssr = Ssr {
+            steps: 0,
+            max_roughness: 0.0,
+            ..Default::default()
+        };
+        let proj_view = Mat4::from_scale(2.0);
+        let uniform = Uniform::new(&ssr, proj_view, Vec3::new(1.0, 2.0, 3.0), true);
+        assert_eq!(uniform.params, [20.0, 1.0, 0.5, 1.0]);
+        assert_eq!(uniform.fade, [0.01, 1.0, 0.1, 0.0]);
+        assert_eq!(uniform.camera_position, [1.0, 2.0, 3.0, 1.0]);
+        let inverse: [[f32; 4]; 4] = Mat4::from_scale(0.5).into();
+        assert_eq!(uniform.inverse_proj_view, inverse);
+
+        // degenerate matrices fall back to identity
+        let uniform = Uniform::new(&ssr, Mat4::from_scale(0.0), Vec3::new(0.0, 0.0, 0.0), false);
+        let identity: [[f32; 4]; 4] = Mat4::identity().into();
+        assert_eq!(uniform.inverse_proj_view, identity);
+        assert_eq!(uniform.fade[1], 0.0);
+    }
+}
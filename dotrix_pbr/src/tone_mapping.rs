@@ -0,0 +1,206 @@
+//! HDR rendering and tone mapping
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    Attachment, BindGroup, Binding, DepthBufferMode, PipelineLayout, PipelineOptions, RenderPass,
+    Sampler, Stage, TextureFormat, UniformBuffer, MAIN_PASS, SURFACE,
+};
+use dotrix_core::{Assets, Globals, Pipeline, Renderer};
+
+use crate::bloom::{Bloom, BLOOM_TARGET};
+use crate::ssr::SSR_TARGET;
+
+const PIPELINE_LABEL: &str = "dotrix::tone_mapping";
+
+/// Name of the mesh asset of a single triangle covering the screen
+pub(crate) const FULLSCREEN_TRIANGLE: &str = "dotrix::fullscreen_triangle";
+
+/// Name of the render graph attachment, the main pass renders the scene into
+pub const HDR_TARGET: &str = "hdr";
+
+/// Name of the render graph pass writing the tone mapped scene to the surface
+pub const TONE_MAPPING_PASS: &str = "tone_mapping";
+
+/// Name of the pass rendering the overlay, the tone mapping pass runs before it
+pub(crate) const OVERLAY_PASS: &str = "overlay";
+
+/// Curve mapping the HDR colors to the displayable range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMappingOperator {
+    /// `color / (color + 1)`
+    Reinhard,
+    /// Filmic curve of the ACES reference rendering transform
+    Aces,
+}
+
+/// Tone mapping of the HDR scene (global)
+///
+/// The main pass renders into the [`HDR_TARGET`] texture of 16 bit floats, so lighting is not
+/// clipped at 1.0. The tone mapping pass adds the [`Bloom`] and the reflections, scales the colors by `exposure`,
+/// maps them with the `operator` and writes them to the surface.
+pub struct ToneMapping {
+    /// Tone mapping curve
+    pub operator: ToneMappingOperator,
+    /// Scale of the scene colors before tone mapping
+    pub exposure: f32,
+    /// Gamma correction applied after tone mapping, if the surface is not sRGB
+    ///
+    /// The sRGB surfaces encode colors themselves, see [`Renderer::output_gamma`].
+    pub gamma: f32,
+    /// Tone mapping uniform buffer
+    pub uniform: UniformBuffer,
+    /// Attachment with the HDR scene, the anti-aliasing may replace the [`HDR_TARGET`]
+    pub(crate) source: &'static str,
+    sampler: Sampler,
+    pipeline: Pipeline,
+    generation: Option<usize>,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMappingOperator::Reinhard,
+            exposure: 1.0,
+            gamma: 2.2,
+            uniform: UniformBuffer::default(),
+            source: HDR_TARGET,
+            sampler: Sampler::linear_clamp(),
+            pipeline: Pipeline::default().with_pass(TONE_MAPPING_PASS),
+            generation: None,
+        }
+    }
+}
+
+/// Tone mapping startup system
+pub fn startup(mut renderer: Mut<Renderer>, mut assets: Mut<Assets>, mut globals: Mut<Globals>) {
+    let graph = renderer.graph_mut();
+    graph.add_attachment(
+        HDR_TARGET,
+        Attachment::color(TextureFormat::rgba_f16()).with_multisampling(),
+    );
+    if let Some(main) = graph.pass_mut(MAIN_PASS) {
+        for write in main.writes.iter_mut().filter(|write| *write == SURFACE) {
+            *write = String::from(HDR_TARGET);
+        }
+    }
+    graph.add_pass_before(
+        OVERLAY_PASS,
+        RenderPass::new(TONE_MAPPING_PASS)
+            .reads(HDR_TARGET)
+            .writes(SURFACE),
+    );
+
+    let mut mesh = Mesh::default();
+    mesh.with_vertices(&[[-1.0, -1.0], [3.0, -1.0], [-1.0, 3.0]]);
+    mesh.load(&renderer);
+    assets.store_as(mesh, FULLSCREEN_TRIANGLE);
+
+    let mut shader = Shader {
+        name: String::from(PIPELINE_LABEL),
+        code: String::from(include_str!("shaders/tone_mapping.wgsl")),
+        ..Default::default()
+    };
+    shader.load(&renderer);
+    assets.store_as(shader, PIPELINE_LABEL);
+
+    globals.set(ToneMapping::default());
+}
+
+/// Tone mapping rendering system
+pub fn render(
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    bloom: Const<Bloom>,
+    mut globals: Mut<Globals>,
+) {
+    let tone_mapping = match globals.get_mut::<ToneMapping>() {
+        Some(tone_mapping) => tone_mapping,
+        None => return,
+    };
+
+    let uniform = Uniform {
+        exposure: tone_mapping.exposure,
+        gamma: if renderer.surface_format().is_srgb() {
+            1.0
+        } else {
+            tone_mapping.gamma.max(0.01)
+        },
+        operator: tone_mapping.operator as u32,
+        bloom_intensity: if bloom.enabled { bloom.intensity } else { 0.0 },
+    };
+    renderer.load_uniform_buffer(&mut tone_mapping.uniform, bytemuck::cast_slice(&[uniform]));
+    renderer.load_sampler(&mut tone_mapping.sampler);
+
+    // the HDR, bloom and reflection textures are recreated on resize
+    let generation = renderer.attachments_generation();
+    if tone_mapping.generation != Some(generation) {
+        tone_mapping.generation = Some(generation);
+        tone_mapping.pipeline.bindings.unload();
+    }
+
+    let pipeline = &mut tone_mapping.pipeline;
+    if pipeline.shader.is_null() {
+        pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+    }
+    let ready = [tone_mapping.source, BLOOM_TARGET, SSR_TARGET]
+        .iter()
+        .all(|name| renderer.attachment(name).is_some());
+    if !pipeline.cycle(&renderer) || !ready {
+        return;
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    if !pipeline.ready() {
+        if let Some(shader) = assets.get(pipeline.shader) {
+            if !shader.loaded() {
+                return;
+            }
+            renderer.bind(
+                pipeline,
+                PipelineLayout {
+                    label: String::from(PIPELINE_LABEL),
+                    mesh: Some(mesh),
+                    shader,
+                    bindings: &[BindGroup::new(
+                        "Globals",
+                        vec![
+                            Binding::Uniform("ToneMapping", Stage::Fragment, &tone_mapping.uniform),
+                            Binding::Attachment("Hdr", Stage::Fragment, tone_mapping.source),
+                            Binding::Attachment("Bloom", Stage::Fragment, BLOOM_TARGET),
+                            Binding::Sampler("Sampler", Stage::Fragment, &tone_mapping.sampler),
+                            Binding::Attachment("Reflections", Stage::Fragment, SSR_TARGET),
+                        ],
+                    )],
+                    vertex_buffers: &[],
+                    push_constants: None,
+                    options: PipelineOptions {
+                        depth_buffer_mode: DepthBufferMode::Disabled,
+                        disable_cull_mode: true,
+                        ..Default::default()
+                    },
+                },
+            );
+        }
+    }
+
+    renderer.run(pipeline, mesh);
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    exposure: f32,
+    gamma: f32,
+    operator: u32,
+    bloom_intensity: f32,
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
@@ -0,0 +1,213 @@
+//! Velocity buffer
+use dotrix_core::assets::{Mesh, Shader};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::renderer::{
+    Attachment, BindGroup, Binding, DepthBufferMode, PipelineLayout, PipelineOptions, RenderPass,
+    Stage, TextureFormat, UniformBuffer,
+};
+use dotrix_core::{Assets, Camera, Color, Globals, Pipeline, Renderer};
+
+use dotrix_math::{Mat4, SquareMatrix};
+
+use crate::anti_aliasing::AntiAliasing;
+use crate::motion_blur::MotionBlur;
+use crate::ssao::{SSAO_DEPTH, SSAO_DEPTH_PASS};
+use crate::tone_mapping::FULLSCREEN_TRIANGLE;
+
+const PIPELINE_LABEL: &str = "dotrix::velocity";
+
+/// Name of the render graph attachment with the motion of the models
+///
+/// Solid and skeletal models render their motion together with the depth in the
+/// [`SSAO_DEPTH_PASS`].
+pub const MOTION: &str = "motion";
+
+/// Name of the render graph pass and attachment with the screen space motion of the pixels
+///
+/// The motion is stored in the texture coordinates units, from the previous frame to the
+/// current one.
+pub const VELOCITY: &str = "velocity";
+
+/// Velocity buffer (global)
+///
+/// Pixels of the solid and skeletal models move with the camera and their transformation,
+/// the rest of the scene is reprojected as a distant background. The velocity is rendered,
+/// when the temporal anti-aliasing or the motion blur needs it.
+pub struct Velocity {
+    /// Velocity uniform buffer with the projections of the current and previous frames
+    pub uniform: UniformBuffer,
+    enabled: bool,
+    previous_proj_view: Option<Mat4>,
+    pipeline: Pipeline,
+    generation: Option<usize>,
+}
+
+impl Default for Velocity {
+    fn default() -> Self {
+        Self {
+            uniform: UniformBuffer::default(),
+            enabled: false,
+            previous_proj_view: None,
+            pipeline: Pipeline::default().with_pass(VELOCITY),
+            generation: None,
+        }
+    }
+}
+
+impl Velocity {
+    /// Integrates the motion output into the shader of the models
+    ///
+    /// The `source` shader code must contain `{{ include(motion) }}` label after the
+    /// `VertexOutput` with the `current` and `previous` clip space positions.
+    pub fn add_to_shader(source: &str) -> String {
+        source.replace(
+            "{{ include(motion) }}",
+            include_str!("shaders/motion.inc.wgsl"),
+        )
+    }
+
+    /// Returns true if the velocity is rendered
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// Velocity startup system
+pub fn startup(mut renderer: Mut<Renderer>, mut assets: Mut<Assets>, mut globals: Mut<Globals>) {
+    let graph = renderer.graph_mut();
+    graph.add_attachment(MOTION, Attachment::color(TextureFormat::rg_f16()));
+    graph.add_attachment(VELOCITY, Attachment::color(TextureFormat::rg_f16()));
+    if let Some(depth_pass) = graph.pass_mut(SSAO_DEPTH_PASS) {
+        depth_pass.writes.push(String::from(MOTION));
+        depth_pass.clear_color = Some(Color::rgba(0.0, 0.0, 0.0, 0.0));
+    }
+    graph.add_pass(
+        RenderPass::new(VELOCITY)
+            .reads(SSAO_DEPTH)
+            .reads(MOTION)
+            .writes(VELOCITY),
+    );
+
+    let mut shader = Shader {
+        name: String::from(PIPELINE_LABEL),
+        code: String::from(include_str!("shaders/velocity.wgsl")),
+        ..Default::default()
+    };
+    shader.load(&renderer);
+    assets.store_as(shader, PIPELINE_LABEL);
+
+    // models bind the uniform before the first frame is rendered
+    let mut velocity = Velocity::default();
+    let uniform = Uniform {
+        proj_view: Mat4::identity().into(),
+        previous_proj_view: Mat4::identity().into(),
+        inverse_proj_view: Mat4::identity().into(),
+    };
+    renderer.load_uniform_buffer(&mut velocity.uniform, bytemuck::cast_slice(&[uniform]));
+    globals.set(velocity);
+}
+
+/// Velocity rendering system
+pub fn render(
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    camera: Const<Camera>,
+    anti_aliasing: Const<AntiAliasing>,
+    motion_blur: Const<MotionBlur>,
+    mut globals: Mut<Globals>,
+) {
+    let velocity = match globals.get_mut::<Velocity>() {
+        Some(velocity) => velocity,
+        None => return,
+    };
+
+    velocity.enabled = *anti_aliasing == AntiAliasing::Taa || motion_blur.enabled;
+    if !velocity.enabled {
+        velocity.previous_proj_view = None;
+        return;
+    }
+
+    // projections are not jittered, so the still pixels do not move
+    let proj_view = match (camera.proj, camera.view) {
+        (Some(proj), Some(view)) => proj * view,
+        _ => return,
+    };
+    let uniform = Uniform {
+        proj_view: proj_view.into(),
+        previous_proj_view: velocity.previous_proj_view.unwrap_or(proj_view).into(),
+        inverse_proj_view: proj_view.invert().unwrap_or_else(Mat4::identity).into(),
+    };
+    velocity.previous_proj_view = Some(proj_view);
+    renderer.load_uniform_buffer(&mut velocity.uniform, bytemuck::cast_slice(&[uniform]));
+
+    // the depth and motion textures are recreated on resize
+    let generation = renderer.attachments_generation();
+    if velocity.generation != Some(generation) {
+        velocity.generation = Some(generation);
+        velocity.pipeline.bindings.unload();
+    }
+
+    let Velocity {
+        uniform, pipeline, ..
+    } = velocity;
+    if pipeline.shader.is_null() {
+        pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+    }
+    let ready = [SSAO_DEPTH, MOTION]
+        .iter()
+        .all(|name| renderer.attachment(name).is_some());
+    if !pipeline.cycle(&renderer) || !ready {
+        return;
+    }
+
+    let mesh = match assets
+        .find::<Mesh>(FULLSCREEN_TRIANGLE)
+        .and_then(|id| assets.get(id))
+    {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    if !pipeline.ready() {
+        let shader = match assets.get(pipeline.shader) {
+            Some(shader) if shader.loaded() => shader,
+            _ => return,
+        };
+        renderer.bind(
+            pipeline,
+            PipelineLayout {
+                label: String::from(PIPELINE_LABEL),
+                mesh: Some(mesh),
+                shader,
+                bindings: &[BindGroup::new(
+                    "Globals",
+                    vec![
+                        Binding::Uniform("Velocity", Stage::Fragment, uniform),
+                        Binding::Attachment("Depth", Stage::Fragment, SSAO_DEPTH),
+                        Binding::Attachment("Motion", Stage::Fragment, MOTION),
+                    ],
+                )],
+                vertex_buffers: &[],
+                push_constants: None,
+                options: PipelineOptions {
+                    depth_buffer_mode: DepthBufferMode::Disabled,
+                    disable_cull_mode: true,
+                    ..Default::default()
+                },
+            },
+        );
+    }
+
+    renderer.run(pipeline, mesh);
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct Uniform {
+    proj_view: [[f32; 4]; 4],
+    previous_proj_view: [[f32; 4]; 4],
+    inverse_proj_view: [[f32; 4]; 4],
+}
+
+unsafe impl bytemuck::Zeroable for Uniform {}
+unsafe impl bytemuck::Pod for Uniform {}
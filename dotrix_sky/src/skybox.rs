@@ -152,13 +152,15 @@ pub fn render(
                             ),
                             BindGroup::new(
                                 "Locals",
-                                vec![Binding::Texture3D(
+                                vec![Binding::TextureCube(
                                     "CubeMap",
                                     Stage::Fragment,
                                     &cubemap.buffer,
                                 )],
                             ),
                         ],
+                        vertex_buffers: &[],
+                        push_constants: None,
                         options: PipelineOptions {
                             depth_buffer_mode: DepthBufferMode::Read,
                             ..Default::default()
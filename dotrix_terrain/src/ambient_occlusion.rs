@@ -0,0 +1,127 @@
+use dotrix_core::assets::Texture;
+use dotrix_core::renderer::{StorageTextureAccess, TextureBuffer, TextureFormat};
+
+use crate::Terrain;
+
+/// Horizon based ambient occlusion baking settings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientOcclusion {
+    /// Size of the baked per tile texture in texels
+    pub size: u32,
+    /// Number of directions to search the horizon in
+    pub directions: usize,
+    /// Number of height samples per direction
+    pub steps: usize,
+    /// Search radius in world units
+    pub radius: f32,
+}
+
+impl AmbientOcclusion {
+    /// Returns the ambient light visibility (0.0..1.0) at the world position
+    pub fn visibility(&self, terrain: &Terrain, x: f32, z: f32) -> f32 {
+        let directions = self.directions.max(1);
+        let steps = self.steps.max(1);
+        let height = terrain.height_at(x, z);
+        let mut occlusion = 0.0;
+
+        for direction in 0..directions {
+            let angle = direction as f32 / directions as f32 * std::f32::consts::TAU;
+            let (dz, dx) = angle.sin_cos();
+            let mut max_slope: f32 = 0.0;
+            for step in 1..=steps {
+                let distance = self.radius * step as f32 / steps as f32;
+                let sample = terrain.height_at(x + dx * distance, z + dz * distance);
+                max_slope = max_slope.max((sample - height) / distance);
+            }
+            // sine of the horizon elevation angle
+            occlusion += max_slope / (1.0 + max_slope * max_slope).sqrt();
+        }
+
+        1.0 - occlusion / directions as f32
+    }
+
+    /// Bakes ambient occlusion texture of the tile, texels are aligned with the tile vertices
+    pub fn bake(&self, terrain: &Terrain, tile_x: i32, tile_z: i32, lod: usize) -> Texture {
+        let size = self.size.max(2);
+        let half_size = terrain.tile_half_size(lod);
+        let texel_size = 2.0 * half_size / (size - 1) as f32;
+        let mut data = Vec::with_capacity((size * size * 4) as usize);
+
+        for row in 0..size {
+            let z = tile_z as f32 - half_size + row as f32 * texel_size;
+            for column in 0..size {
+                let x = tile_x as f32 - half_size + column as f32 * texel_size;
+                let value = (self.visibility(terrain, x, z) * 255.0).round() as u8;
+                data.extend_from_slice(&[value, value, value, 255]);
+            }
+        }
+
+        Texture {
+            width: size,
+            height: size,
+            depth: 1,
+            data,
+            buffer: TextureBuffer::new(StorageTextureAccess::Read, TextureFormat::rgba_u8norm()),
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        Self {
+            size: 64,
+            directions: 8,
+            steps: 8,
+            radius: 32.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Generator;
+
+    fn terrain(noise_map: Vec<f32>) -> Terrain {
+        let generator = Generator {
+            size: 17,
+            amplitude: 10.0,
+            noise_map: Some(noise_map),
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 4;
+        terrain
+    }
+
+    #[test]
+    fn flat_terrain_is_not_occluded() {
+        let terrain = terrain(vec![0.5; 17 * 17]);
+        let ambient_occlusion = AmbientOcclusion {
+            size: 3,
+            radius: 4.0,
+            ..Default::default()
+        };
+        assert_eq!(ambient_occlusion.visibility(&terrain, 0.0, 0.0), 1.0);
+
+        let texture = ambient_occlusion.bake(&terrain, 0, 0, 0);
+        assert_eq!((texture.width, texture.height), (3, 3));
+        assert!(texture.data.chunks_exact(4).all(|texel| texel == [255; 4]));
+    }
+
+    #[test]
+    fn pits_are_occluded() {
+        let mut noise_map = vec![1.0; 17 * 17];
+        noise_map[8 * 17 + 8] = 0.0;
+        let terrain = terrain(noise_map);
+        let ambient_occlusion = AmbientOcclusion {
+            radius: 4.0,
+            ..Default::default()
+        };
+        let center = ambient_occlusion.visibility(&terrain, 0.0, 0.0);
+        assert!(center < 0.5);
+        assert!(center > 0.0);
+        assert_eq!(ambient_occlusion.visibility(&terrain, 4.0, 4.0), 1.0);
+    }
+}
@@ -0,0 +1,243 @@
+/// Parameters of the terrain region
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Biome {
+    /// Multiplier of the noise values
+    pub height_scale: f32,
+    /// Offset added to the noise values after scaling
+    pub height_offset: f32,
+    /// Index of the base texture layer, see [`crate::Layers::apply_biomes`]
+    pub layer: usize,
+    /// Density of the foliage (0.0..1.0), see [`crate::Scatter::with_biomes`]
+    pub foliage_density: f32,
+}
+
+impl Default for Biome {
+    fn default() -> Self {
+        Self {
+            height_scale: 1.0,
+            height_offset: 0.0,
+            layer: 0,
+            foliage_density: 0.5,
+        }
+    }
+}
+
+/// Biomes layer of the map generator
+///
+/// The map is partitioned into Voronoi cells, each cell gets one of the biomes. Parameters of
+/// neighbouring biomes are blended near the cells borders.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Biomes {
+    /// List of biomes
+    pub list: Vec<Biome>,
+    /// Average size of the biome cell in map units
+    pub cell_size: f32,
+    /// Width of the blending zone at the borders in map units
+    pub blend: f32,
+    /// Seed of the partition
+    pub seed: u32,
+}
+
+impl Biomes {
+    /// Returns biomes indices and their normalized weights at the map position
+    pub fn weights(&self, x: f32, z: f32) -> Vec<(usize, f32)> {
+        if self.list.is_empty() {
+            return Vec::new();
+        }
+        let cell_size = self.cell_size.max(1.0);
+        let cell_x = (x / cell_size).floor() as i32;
+        let cell_z = (z / cell_size).floor() as i32;
+
+        let mut distances = Vec::with_capacity(9);
+        for dx in -1..=1 {
+            for dz in -1..=1 {
+                let cx = cell_x + dx;
+                let cz = cell_z + dz;
+                let hash = self.hash(cx, cz);
+                let px = (cx as f32 + (hash & 0xFFFF) as f32 / 65535.0) * cell_size;
+                let pz = (cz as f32 + ((hash >> 16) & 0xFFFF) as f32 / 65535.0) * cell_size;
+                let distance = ((px - x) * (px - x) + (pz - z) * (pz - z)).sqrt();
+                let biome = (self.hash(cz, cx) as usize) % self.list.len();
+                distances.push((biome, distance));
+            }
+        }
+
+        let nearest = distances
+            .iter()
+            .map(|&(_, distance)| distance)
+            .fold(f32::MAX, f32::min);
+
+        let mut weights: Vec<(usize, f32)> = Vec::with_capacity(distances.len());
+        let mut total = 0.0;
+        for (biome, distance) in distances {
+            let weight = if self.blend > 0.0 {
+                (1.0 - (distance - nearest) / self.blend).max(0.0)
+            } else if distance <= nearest {
+                1.0
+            } else {
+                0.0
+            };
+            if weight <= 0.0 {
+                continue;
+            }
+            total += weight;
+            match weights.iter_mut().find(|(index, _)| *index == biome) {
+                Some((_, value)) => *value += weight,
+                None => weights.push((biome, weight)),
+            }
+        }
+        for (_, weight) in weights.iter_mut() {
+            *weight /= total;
+        }
+        weights
+    }
+
+    /// Returns blended biome parameters at the map position
+    ///
+    /// Layer of the biome with the highest weight is used
+    pub fn sample(&self, x: f32, z: f32) -> Biome {
+        let weights = self.weights(x, z);
+        let mut result = Biome {
+            height_scale: 0.0,
+            height_offset: 0.0,
+            layer: 0,
+            foliage_density: 0.0,
+        };
+        let mut max_weight = 0.0;
+        for (index, weight) in weights {
+            let biome = &self.list[index];
+            result.height_scale += biome.height_scale * weight;
+            result.height_offset += biome.height_offset * weight;
+            result.foliage_density += biome.foliage_density * weight;
+            if weight > max_weight {
+                max_weight = weight;
+                result.layer = biome.layer;
+            }
+        }
+        if max_weight == 0.0 {
+            return Biome::default();
+        }
+        result
+    }
+
+    /// Returns blended foliage densities of the square map with `size` values per side
+    pub fn density_map(&self, size: usize) -> Vec<f32> {
+        let mut map = Vec::with_capacity(size * size);
+        for x in 0..size {
+            for z in 0..size {
+                map.push(self.sample(x as f32, z as f32).foliage_density);
+            }
+        }
+        map
+    }
+
+    /// Returns blended weights of the texture layers at the map position
+    pub fn layer_weights(&self, x: f32, z: f32) -> Vec<(usize, f32)> {
+        let mut weights: Vec<(usize, f32)> = Vec::new();
+        for (index, weight) in self.weights(x, z) {
+            let layer = self.list[index].layer;
+            match weights.iter_mut().find(|(other, _)| *other == layer) {
+                Some((_, value)) => *value += weight,
+                None => weights.push((layer, weight)),
+            }
+        }
+        weights
+    }
+
+    /// Applies biomes to the square map of noise values with `size` values per side
+    pub(crate) fn apply(&self, map: &mut [f32], size: usize) {
+        for x in 0..size {
+            for z in 0..size {
+                let biome = self.sample(x as f32, z as f32);
+                let value = &mut map[x * size + z];
+                *value = *value * biome.height_scale + biome.height_offset;
+            }
+        }
+    }
+
+    fn hash(&self, x: i32, z: i32) -> u32 {
+        let mut hash = self.seed ^ (x as u32).wrapping_mul(0x27d4_eb2d);
+        hash = (hash ^ (z as u32).wrapping_mul(0x1656_67b1)).wrapping_mul(0x9e37_79b9);
+        hash ^= hash >> 15;
+        hash = hash.wrapping_mul(0x85eb_ca6b);
+        hash ^ (hash >> 13)
+    }
+}
+
+impl Default for Biomes {
+    fn default() -> Self {
+        Self {
+            list: vec![Biome::default()],
+            cell_size: 512.0,
+            blend: 64.0,
+            seed: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn biomes() -> Biomes {
+        Biomes {
+            list: vec![
+                Biome {
+                    height_scale: 1.0,
+                    layer: 1,
+                    foliage_density: 1.0,
+                    ..Default::default()
+                },
+                Biome {
+                    height_scale: 3.0,
+                    layer: 2,
+                    foliage_density: 0.0,
+                    ..Default::default()
+                },
+            ],
+            cell_size: 64.0,
+            blend: 32.0,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    fn weights_are_normalized() {
+        let biomes = biomes();
+        for x in (0..256).step_by(8) {
+            for z in (0..256).step_by(8) {
+                let weights = biomes.weights(x as f32, z as f32);
+                assert!(!weights.is_empty());
+                let total = weights.iter().map(|(_, weight)| weight).sum::<f32>();
+                assert!((total - 1.0).abs() < 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn borders_blend_parameters() {
+        let biomes = biomes();
+        let positions = (0..256)
+            .flat_map(|x| (0..256).map(move |z| (x as f32, z as f32)))
+            .collect::<Vec<_>>();
+
+        let border = positions
+            .iter()
+            .find(|(x, z)| biomes.weights(*x, *z).len() == 2)
+            .expect("Biomes must have a border");
+        let biome = biomes.sample(border.0, border.1);
+        assert!(biome.height_scale > 1.0 && biome.height_scale < 3.0);
+        assert!(biome.foliage_density > 0.0 && biome.foliage_density < 1.0);
+        assert!((biome.height_scale + 2.0 * biome.foliage_density - 3.0).abs() < 0.0001);
+        assert_eq!(biomes.layer_weights(border.0, border.1).len(), 2);
+
+        assert!(positions
+            .iter()
+            .any(|(x, z)| biomes.weights(*x, *z).len() == 1));
+        let density_map = biomes.density_map(16);
+        assert_eq!(density_map.len(), 256);
+        assert!(density_map
+            .iter()
+            .all(|density| (0.0..=1.0).contains(density)));
+    }
+}
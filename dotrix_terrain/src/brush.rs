@@ -0,0 +1,370 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Seedable};
+
+/// Sculpting operation of the [`Brush`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sculpt {
+    /// Raises the terrain
+    Raise,
+    /// Lowers the terrain
+    Lower,
+    /// Averages heights with the neighbours
+    Smooth,
+    /// Pulls heights towards the height in the center of the brush
+    Flatten,
+    /// Adds Perlin noise
+    Noise {
+        /// Noise scale in world units
+        scale: f32,
+        /// Noise seed
+        seed: u32,
+    },
+}
+
+/// Terrain sculpting brush
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brush {
+    /// Sculpting operation
+    pub sculpt: Sculpt,
+    /// Radius of the brush in world units
+    pub radius: f32,
+    /// Height change in world units per application for `Raise`, `Lower` and `Noise`,
+    /// blending factor (0.0..1.0) for `Smooth` and `Flatten`
+    pub strength: f32,
+    /// Part of the radius (0.0..1.0) affected with the full strength
+    pub hardness: f32,
+}
+
+impl Brush {
+    /// Constructs new brush
+    pub fn new(sculpt: Sculpt, radius: f32, strength: f32) -> Self {
+        Self {
+            sculpt,
+            radius,
+            strength,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the brush weight (0.0..1.0) at the distance from its center
+    pub fn weight(&self, distance: f32) -> f32 {
+        if distance >= self.radius {
+            return 0.0;
+        }
+        let hard_radius = self.radius * self.hardness;
+        if distance <= hard_radius {
+            return 1.0;
+        }
+        let t = (distance - hard_radius) / (self.radius - hard_radius);
+        1.0 - t * t * (3.0 - 2.0 * t)
+    }
+
+    /// Applies the brush to the square map of heights with `size` values per side
+    ///
+    /// Values are indexed as `x * size + z`. `center_x` and `center_z` are map coordinates of
+    /// the brush center, `scale` converts world units of height into map values. Returns `false`
+    /// if the brush does not touch the map.
+    pub(crate) fn apply(
+        &self,
+        map: &mut [f32],
+        size: usize,
+        center_x: f32,
+        center_z: f32,
+        scale: f32,
+    ) -> bool {
+        if size == 0 || map.len() < size * size || self.radius <= 0.0 {
+            return false;
+        }
+
+        let max = (size - 1) as f32;
+        let from_x = (center_x - self.radius).floor().max(0.0);
+        let to_x = (center_x + self.radius).ceil().min(max);
+        let from_z = (center_z - self.radius).floor().max(0.0);
+        let to_z = (center_z + self.radius).ceil().min(max);
+
+        if from_x > to_x || from_z > to_z {
+            return false;
+        }
+
+        let (from_x, to_x) = (from_x as usize, to_x as usize);
+        let (from_z, to_z) = (from_z as usize, to_z as usize);
+
+        let center_height = {
+            let x = (center_x.round().max(0.0) as usize).min(size - 1);
+            let z = (center_z.round().max(0.0) as usize).min(size - 1);
+            map[x * size + z]
+        };
+        let source = match self.sculpt {
+            Sculpt::Smooth => Some(map.to_vec()),
+            _ => None,
+        };
+        let noise = match self.sculpt {
+            // single octave of `Fbm` is plain Perlin noise
+            Sculpt::Noise { seed, .. } => Some(Fbm::new().set_seed(seed).set_octaves(1)),
+            _ => None,
+        };
+
+        for x in from_x..=to_x {
+            for z in from_z..=to_z {
+                let dx = x as f32 - center_x;
+                let dz = z as f32 - center_z;
+                let weight = self.weight((dx * dx + dz * dz).sqrt());
+                if weight <= 0.0 {
+                    continue;
+                }
+                let i = x * size + z;
+                let value = map[i];
+                map[i] = match self.sculpt {
+                    Sculpt::Raise => value + self.strength * scale * weight,
+                    Sculpt::Lower => value - self.strength * scale * weight,
+                    Sculpt::Flatten => value + (center_height - value) * self.strength * weight,
+                    Sculpt::Smooth => {
+                        let source = source.as_ref().unwrap();
+                        let mut sum = 0.0;
+                        let mut count = 0.0;
+                        for nx in x.saturating_sub(1)..=(x + 1).min(size - 1) {
+                            for nz in z.saturating_sub(1)..=(z + 1).min(size - 1) {
+                                sum += source[nx * size + nz];
+                                count += 1.0;
+                            }
+                        }
+                        value + (sum / count - value) * self.strength * weight
+                    }
+                    Sculpt::Noise {
+                        scale: noise_scale, ..
+                    } => {
+                        let noise_scale = if noise_scale > 0.0 { noise_scale } else { 1.0 };
+                        let noise_value = noise.as_ref().unwrap().get([
+                            (x as f32 / noise_scale) as f64,
+                            (z as f32 / noise_scale) as f64,
+                        ]) as f32;
+                        value + noise_value * self.strength * scale * weight
+                    }
+                };
+            }
+        }
+
+        true
+    }
+
+    /// Blends the color into the square map of colors with `size` values per side
+    ///
+    /// Values are indexed as `x * size + z`, `strength` is used as the blending factor
+    /// (0.0..1.0) and the sculpting operation is ignored. Returns `false` if the brush does not
+    /// touch the map.
+    pub(crate) fn paint(
+        &self,
+        map: &mut [[f32; 4]],
+        size: usize,
+        center_x: f32,
+        center_z: f32,
+        color: [f32; 4],
+    ) -> bool {
+        if size == 0 || map.len() < size * size || self.radius <= 0.0 {
+            return false;
+        }
+
+        let max = (size - 1) as f32;
+        let from_x = (center_x - self.radius).floor().max(0.0);
+        let to_x = (center_x + self.radius).ceil().min(max);
+        let from_z = (center_z - self.radius).floor().max(0.0);
+        let to_z = (center_z + self.radius).ceil().min(max);
+
+        if from_x > to_x || from_z > to_z {
+            return false;
+        }
+
+        let strength = self.strength.clamp(0.0, 1.0);
+        for x in from_x as usize..=to_x as usize {
+            for z in from_z as usize..=to_z as usize {
+                let dx = x as f32 - center_x;
+                let dz = z as f32 - center_z;
+                let weight = self.weight((dx * dx + dz * dz).sqrt()) * strength;
+                if weight <= 0.0 {
+                    continue;
+                }
+                let value = &mut map[x * size + z];
+                for (channel, target) in value.iter_mut().zip(color.iter()) {
+                    *channel += (target - *channel) * weight;
+                }
+            }
+        }
+
+        true
+    }
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            sculpt: Sculpt::Raise,
+            radius: 16.0,
+            strength: 1.0,
+            hardness: 0.5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paint_blends_color_by_strength_and_falloff() {
+        let size = 9;
+        let mut map = vec![[1.0, 1.0, 1.0, 0.0]; size * size];
+        let brush = Brush {
+            radius: 3.0,
+            strength: 0.5,
+            hardness: 0.5,
+            ..Default::default()
+        };
+        assert!(brush.paint(&mut map, size, 4.0, 4.0, [0.0, 0.0, 0.0, 1.0]));
+
+        assert_eq!(map[4 * size + 4], [0.5, 0.5, 0.5, 0.5]);
+        let edge = map[4 * size + 6];
+        assert!(edge[0] > 0.5 && edge[0] < 1.0);
+        assert_eq!(map[0], [1.0, 1.0, 1.0, 0.0]);
+        assert!(!brush.paint(&mut map, size, 20.0, 20.0, [0.0; 4]));
+    }
+
+    fn flat_map(size: usize, value: f32) -> Vec<f32> {
+        vec![value; size * size]
+    }
+
+    #[test]
+    fn weight_falls_off_between_hard_radius_and_radius() {
+        let brush = Brush {
+            radius: 4.0,
+            hardness: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(brush.weight(0.0), 1.0);
+        assert_eq!(brush.weight(2.0), 1.0);
+        let middle = brush.weight(3.0);
+        assert!(middle > 0.0 && middle < 1.0);
+        assert!(brush.weight(3.5) < middle);
+        assert_eq!(brush.weight(4.0), 0.0);
+        assert_eq!(brush.weight(10.0), 0.0);
+    }
+
+    #[test]
+    fn raise_and_lower_respect_radius_and_falloff() {
+        let size = 9;
+        let brush = Brush {
+            radius: 3.0,
+            strength: 2.0,
+            hardness: 0.5,
+            ..Default::default()
+        };
+
+        let mut map = flat_map(size, 1.0);
+        assert!(brush.apply(&mut map, size, 4.0, 4.0, 0.5));
+        assert_eq!(map[4 * size + 4], 2.0);
+        let edge = map[4 * size + 6];
+        assert!(edge > 1.0 && edge < 2.0);
+        assert_eq!(map[4 * size + 7], 1.0);
+        assert_eq!(map[0], 1.0);
+
+        let lower = Brush {
+            sculpt: Sculpt::Lower,
+            ..brush
+        };
+        let mut map = flat_map(size, 1.0);
+        assert!(lower.apply(&mut map, size, 4.0, 4.0, 0.5));
+        assert_eq!(map[4 * size + 4], 0.0);
+        assert!(map[4 * size + 6] < 1.0 && map[4 * size + 6] > 0.0);
+        assert_eq!(map[8 * size + 8], 1.0);
+    }
+
+    #[test]
+    fn brush_is_clamped_to_map_bounds() {
+        let size = 5;
+        let brush = Brush::new(Sculpt::Raise, 2.0, 1.0);
+
+        let mut map = flat_map(size, 0.0);
+        assert!(brush.apply(&mut map, size, -1.0, -1.0, 1.0));
+        assert!(map[0] > 0.0);
+        assert_eq!(map[4 * size + 4], 0.0);
+        assert_eq!(map.len(), size * size);
+
+        let mut map = flat_map(size, 0.0);
+        assert!(!brush.apply(&mut map, size, 20.0, 20.0, 1.0));
+        assert!(map.iter().all(|&value| value == 0.0));
+        assert!(!Brush::new(Sculpt::Raise, 0.0, 1.0).apply(&mut map, size, 2.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn flatten_pulls_towards_center_height() {
+        let size = 7;
+        let mut map: Vec<f32> = (0..size * size).map(|i| (i / size) as f32).collect();
+        let brush = Brush {
+            sculpt: Sculpt::Flatten,
+            radius: 2.0,
+            strength: 1.0,
+            hardness: 1.0,
+        };
+        assert!(brush.apply(&mut map, size, 3.0, 3.0, 1.0));
+        assert_eq!(map[2 * size + 3], 3.0);
+        assert_eq!(map[4 * size + 3], 3.0);
+        assert_eq!(map[6 * size + 3], 6.0);
+
+        let half = Brush {
+            strength: 0.5,
+            ..brush
+        };
+        let mut map: Vec<f32> = (0..size * size).map(|i| (i / size) as f32).collect();
+        assert!(half.apply(&mut map, size, 3.0, 3.0, 1.0));
+        assert_eq!(map[2 * size + 3], 2.5);
+    }
+
+    #[test]
+    fn smooth_averages_neighbours() {
+        let size = 5;
+        let mut map = flat_map(size, 0.0);
+        map[2 * size + 2] = 9.0;
+        let brush = Brush {
+            sculpt: Sculpt::Smooth,
+            radius: 1.5,
+            strength: 1.0,
+            hardness: 1.0,
+        };
+        assert!(brush.apply(&mut map, size, 2.0, 2.0, 1.0));
+        assert_eq!(map[2 * size + 2], 1.0);
+        assert_eq!(map[size + 2], 1.0);
+        assert_eq!(map[size + 1], 1.0);
+        assert_eq!(map[2], 0.0);
+    }
+
+    #[test]
+    fn noise_is_deterministic_and_limited_by_strength() {
+        let size = 16;
+        let brush = Brush {
+            sculpt: Sculpt::Noise {
+                scale: 3.0,
+                seed: 7,
+            },
+            radius: 6.0,
+            strength: 0.5,
+            hardness: 1.0,
+        };
+        let mut first = flat_map(size, 0.0);
+        let mut second = flat_map(size, 0.0);
+        assert!(brush.apply(&mut first, size, 8.0, 8.0, 2.0));
+        assert!(brush.apply(&mut second, size, 8.0, 8.0, 2.0));
+        assert_eq!(first, second);
+        assert!(first.iter().any(|&value| value != 0.0));
+        assert!(first.iter().all(|&value| value.abs() <= 1.0));
+        assert_eq!(first[0], 0.0);
+
+        let other = Brush {
+            sculpt: Sculpt::Noise {
+                scale: 3.0,
+                seed: 8,
+            },
+            ..brush
+        };
+        let mut third = flat_map(size, 0.0);
+        assert!(other.apply(&mut third, size, 8.0, 8.0, 2.0));
+        assert_ne!(first, third);
+    }
+}
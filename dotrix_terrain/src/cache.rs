@@ -0,0 +1,229 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+use dotrix_core::assets::Mesh;
+use dotrix_core::renderer::AttributeFormat;
+
+/// Magic bytes of the cached tile file
+const MAGIC: &[u8; 4] = b"DTXT";
+
+/// On-disk cache of generated tile meshes
+///
+/// Tiles leaving the view distance are written to the cache directory (one file per tile) and
+/// loaded back instead of being regenerated, when the viewer returns.
+pub struct TileCache {
+    /// Directory to store the tiles files
+    pub path: PathBuf,
+    tiles: HashSet<(i32, i32, usize)>,
+}
+
+impl TileCache {
+    /// Constructs new cache in the directory
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            tiles: HashSet::new(),
+        }
+    }
+
+    /// Returns true if the tile is cached
+    pub fn contains(&self, x: i32, z: i32, lod: usize) -> bool {
+        self.tiles.contains(&(x, z, lod))
+    }
+
+    /// Writes tile mesh to the cache
+    pub fn store(&mut self, x: i32, z: i32, lod: usize, mesh: &Mesh) -> io::Result<()> {
+        fs::create_dir_all(&self.path)?;
+        let mut file = fs::File::create(self.file_path(x, z, lod))?;
+        file.write_all(&encode(mesh))?;
+        self.tiles.insert((x, z, lod));
+        Ok(())
+    }
+
+    /// Reads tile mesh from the cache
+    pub fn load(&self, x: i32, z: i32, lod: usize) -> Option<Mesh> {
+        if !self.contains(x, z, lod) {
+            return None;
+        }
+        let mut data = Vec::new();
+        fs::File::open(self.file_path(x, z, lod))
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .ok()?;
+        decode(&data)
+    }
+
+    /// Removes tiles, that does not satisfy the predicate, from the cache
+    pub fn retain(&mut self, mut predicate: impl FnMut(i32, i32, usize) -> bool) {
+        let path = &self.path;
+        self.tiles.retain(|&(x, z, lod)| {
+            let keep = predicate(x, z, lod);
+            if !keep {
+                fs::remove_file(path.join(file_name(x, z, lod))).ok();
+            }
+            keep
+        });
+    }
+
+    /// Removes all tiles from the cache
+    pub fn clear(&mut self) {
+        self.retain(|_, _, _| false);
+    }
+
+    fn file_path(&self, x: i32, z: i32, lod: usize) -> PathBuf {
+        self.path.join(file_name(x, z, lod))
+    }
+}
+
+fn file_name(x: i32, z: i32, lod: usize) -> String {
+    format!("{}_{}_{}.tile", x, z, lod)
+}
+
+/// Packs vertex attributes layout, vertices and indices of the tile mesh
+///
+/// Vertices are stored as they are packed in the mesh, in the native byte order, so the cache
+/// files are not portable between platforms.
+fn encode(mesh: &Mesh) -> Vec<u8> {
+    let stride = mesh
+        .layout
+        .iter()
+        .map(|format| format.size())
+        .sum::<usize>();
+    let indices = mesh.indices().unwrap_or(&[]);
+
+    let mut data = Vec::with_capacity(
+        MAGIC.len() + 12 + mesh.layout.len() * 4 + mesh.vertices.len() * stride + indices.len() * 4,
+    );
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&(mesh.vertices.len() as u32).to_le_bytes());
+    data.extend_from_slice(&(indices.len() as u32).to_le_bytes());
+    data.extend_from_slice(&(mesh.layout.len() as u32).to_le_bytes());
+    for format in mesh.layout.iter() {
+        data.extend_from_slice(&format_code(format).to_le_bytes());
+    }
+    for vertex in mesh.vertices.iter() {
+        data.extend_from_slice(vertex);
+    }
+    for index in indices.iter() {
+        data.extend_from_slice(&index.to_le_bytes());
+    }
+    data
+}
+
+/// Unpacks tile mesh, returns `None` if data is corrupted
+fn decode(data: &[u8]) -> Option<Mesh> {
+    if data.len() < MAGIC.len() + 12 || &data[0..MAGIC.len()] != MAGIC {
+        return None;
+    }
+    let mut words = data[MAGIC.len()..]
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+    let vertices_count = words.next()? as usize;
+    let indices_count = words.next()? as usize;
+    let attributes_count = words.next()? as usize;
+    let layout = words
+        .by_ref()
+        .take(attributes_count)
+        .map(format_from_code)
+        .collect::<Option<Vec<_>>>()?;
+    if layout.len() != attributes_count {
+        return None;
+    }
+    let stride = layout.iter().map(|format| format.size()).sum::<usize>();
+    let vertices_offset = MAGIC.len() + 12 + attributes_count * 4;
+    let indices_offset = vertices_offset + vertices_count * stride;
+    if data.len() != indices_offset + indices_count * 4 {
+        return None;
+    }
+
+    let vertices = data[vertices_offset..indices_offset]
+        .chunks_exact(stride.max(1))
+        .map(Vec::from)
+        .collect::<Vec<_>>();
+    let indices = data[indices_offset..]
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect::<Vec<_>>();
+
+    let mut mesh = Mesh {
+        vertices,
+        stride,
+        layout,
+        ..Default::default()
+    };
+    mesh.with_indices(&indices);
+    Some(mesh)
+}
+
+fn format_code(format: &AttributeFormat) -> u32 {
+    match format {
+        AttributeFormat::Float32 => 0,
+        AttributeFormat::Float32x2 => 1,
+        AttributeFormat::Float32x3 => 2,
+        AttributeFormat::Float32x4 => 3,
+        AttributeFormat::Uint16x2 => 4,
+        AttributeFormat::Uint16x4 => 5,
+        AttributeFormat::Uint32 => 6,
+        AttributeFormat::Uint32x2 => 7,
+        AttributeFormat::Uint32x3 => 8,
+        AttributeFormat::Uint32x4 => 9,
+    }
+}
+
+fn format_from_code(code: u32) -> Option<AttributeFormat> {
+    Some(match code {
+        0 => AttributeFormat::Float32,
+        1 => AttributeFormat::Float32x2,
+        2 => AttributeFormat::Float32x3,
+        3 => AttributeFormat::Float32x4,
+        4 => AttributeFormat::Uint16x2,
+        5 => AttributeFormat::Uint16x4,
+        6 => AttributeFormat::Uint32,
+        7 => AttributeFormat::Uint32x2,
+        8 => AttributeFormat::Uint32x3,
+        9 => AttributeFormat::Uint32x4,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoded_mesh_can_be_decoded() {
+        let mut mesh = Mesh::default();
+        mesh.with_vertices(&[[0.0_f32, 1.0, 2.0], [3.0, 4.0, 5.0], [6.0, 7.0, 8.0]]);
+        mesh.with_vertices(&[[0.0_f32, 1.0, 0.0]; 3]);
+        mesh.with_vertices(&[[0.0_f32, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+        mesh.with_indices(&[0, 1, 2]);
+
+        let data = encode(&mesh);
+        let decoded = decode(&data).expect("Mesh must be decoded");
+        assert_eq!(decoded.vertices, mesh.vertices);
+        assert_eq!(decoded.indices(), mesh.indices());
+        assert!(decode(&data[0..data.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn encoded_mesh_keeps_attributes_layout() {
+        let mut mesh = Mesh::default();
+        mesh.with_vertices(&[[0.0_f32, 1.0, 2.0], [3.0, 4.0, 5.0]]);
+        mesh.with_vertices(&[[0.0_f32, 0.0], [1.0, 1.0]]);
+        mesh.with_vertices(&[[1.0_f32, 0.5, 0.25, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+        mesh.with_vertices(&[[1_u32, 2, 3, 4], [5, 6, 7, 8]]);
+        mesh.with_indices(&[0, 1, 1]);
+
+        let decoded = decode(&encode(&mesh)).expect("Mesh must be decoded");
+        assert_eq!(decoded.stride, mesh.stride);
+        assert_eq!(decoded.vertices, mesh.vertices);
+        assert_eq!(
+            decoded.layout.iter().map(format_code).collect::<Vec<_>>(),
+            vec![2, 1, 3, 9]
+        );
+        let colors = decoded.vertices_as::<[f32; 4]>(2).collect::<Vec<_>>();
+        assert_eq!(colors, vec![[1.0, 0.5, 0.25, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+        assert_eq!(decoded.indices(), Some(&[0, 1, 1][..]));
+    }
+}
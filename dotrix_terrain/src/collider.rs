@@ -0,0 +1,84 @@
+/// Physics engine agnostic heightfield data
+///
+/// Heights are stored row by row, rows go along Z axis and columns along X axis, so the value of
+/// the row `r` and the column `c` is `heights[r * columns + c]` and its world position is
+/// `[origin[0] + c * scale[0], heights[..] * scale[1], origin[1] + r * scale[2]]`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeightFieldData {
+    /// Number of rows (values along Z axis)
+    pub rows: usize,
+    /// Number of columns (values along X axis)
+    pub columns: usize,
+    /// Height values
+    pub heights: Vec<f32>,
+    /// Distance between columns, heights multiplier and distance between rows
+    pub scale: [f32; 3],
+    /// World X and Z coordinates of the first value
+    pub origin: [f32; 2],
+    /// Holes flags of the values, empty if there are no holes in the region
+    ///
+    /// Cells touching a hole are not rendered and should be excluded from the collider
+    pub holes: Vec<bool>,
+}
+
+impl HeightFieldData {
+    /// Returns true if the cell with the lower corner at the row and the column is a hole
+    pub fn is_hole_cell(&self, row: usize, column: usize) -> bool {
+        if self.holes.is_empty() {
+            return false;
+        }
+        let i = row * self.columns + column;
+        let j = i + self.columns;
+        self.holes[i] || self.holes[i + 1] || self.holes[j] || self.holes[j + 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Generator, GpuGenerator, Terrain};
+
+    #[test]
+    fn heightfield_matches_heightmap() {
+        let size = 9;
+        let mut generator = Generator {
+            amplitude: 10.0,
+            size,
+            noise_map: Some((0..size * size).map(|i| (i % size) as f32 / 8.0).collect()),
+            ..Default::default()
+        };
+        generator.set_hole(4, 5, true);
+        let terrain = Terrain::new(Box::new(generator), vec![]);
+
+        let heightfield = terrain
+            .collider_heightfield([-2.0, -1.0, 10.0, 1.0])
+            .unwrap();
+        assert_eq!((heightfield.rows, heightfield.columns), (3, 7));
+        assert_eq!(heightfield.origin, [-2.0, -1.0]);
+        // values along Z axis grow by 10 / 8 per row
+        assert_eq!(heightfield.heights[0], 3.75);
+        assert_eq!(heightfield.heights[heightfield.columns], 5.0);
+        assert!(heightfield.is_hole_cell(1, 1));
+        assert!(heightfield.is_hole_cell(1, 2));
+        assert!(!heightfield.is_hole_cell(0, 2));
+        assert!(!heightfield.is_hole_cell(0, 0));
+    }
+
+    #[test]
+    fn gpu_terrain_has_no_heightfield() {
+        let size = 9;
+        let generator = Generator {
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        assert!(terrain
+            .collider_heightfield([-2.0, -2.0, 2.0, 2.0])
+            .is_some());
+
+        terrain.gpu = Some(GpuGenerator::default());
+        assert!(terrain
+            .collider_heightfield([-2.0, -2.0, 2.0, 2.0])
+            .is_none());
+    }
+}
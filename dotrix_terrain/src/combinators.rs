@@ -0,0 +1,335 @@
+use crate::Heightmap;
+
+/// Sum of two heightmaps
+pub struct Add {
+    /// First heights source
+    pub left: Box<dyn Heightmap>,
+    /// Second heights source
+    pub right: Box<dyn Heightmap>,
+}
+
+impl Add {
+    /// Constructs new sum of the heightmaps
+    pub fn new(left: Box<dyn Heightmap>, right: Box<dyn Heightmap>) -> Self {
+        Self { left, right }
+    }
+}
+
+impl Heightmap for Add {
+    fn value(&self, x: usize, z: usize) -> f32 {
+        self.left.value(x, z) + self.right.value(x, z)
+    }
+
+    fn size(&self) -> usize {
+        self.left.size().min(self.right.size())
+    }
+
+    fn is_hole(&self, x: usize, z: usize) -> bool {
+        self.left.is_hole(x, z) || self.right.is_hole(x, z)
+    }
+
+    fn dirty(&self) -> bool {
+        self.left.dirty() || self.right.dirty()
+    }
+
+    fn set_dirty(&mut self, dirty: bool) {
+        self.left.set_dirty(dirty);
+        self.right.set_dirty(dirty);
+    }
+
+    fn dirty_regions(&self) -> Vec<[usize; 4]> {
+        let mut regions = self.left.dirty_regions();
+        regions.extend(self.right.dirty_regions());
+        regions
+    }
+
+    fn clear_dirty_regions(&mut self) {
+        self.left.clear_dirty_regions();
+        self.right.clear_dirty_regions();
+    }
+}
+
+/// Product of two heightmaps multiplied by the scale
+pub struct Multiply {
+    /// First heights source
+    pub left: Box<dyn Heightmap>,
+    /// Second heights source
+    pub right: Box<dyn Heightmap>,
+    /// Multiplier of the product, e.g. `1.0 / amplitude` to keep the range of the left source
+    pub scale: f32,
+}
+
+impl Multiply {
+    /// Constructs new product of the heightmaps
+    pub fn new(left: Box<dyn Heightmap>, right: Box<dyn Heightmap>) -> Self {
+        Self {
+            left,
+            right,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Heightmap for Multiply {
+    fn value(&self, x: usize, z: usize) -> f32 {
+        self.left.value(x, z) * self.right.value(x, z) * self.scale
+    }
+
+    fn size(&self) -> usize {
+        self.left.size().min(self.right.size())
+    }
+
+    fn is_hole(&self, x: usize, z: usize) -> bool {
+        self.left.is_hole(x, z) || self.right.is_hole(x, z)
+    }
+
+    fn dirty(&self) -> bool {
+        self.left.dirty() || self.right.dirty()
+    }
+
+    fn set_dirty(&mut self, dirty: bool) {
+        self.left.set_dirty(dirty);
+        self.right.set_dirty(dirty);
+    }
+
+    fn dirty_regions(&self) -> Vec<[usize; 4]> {
+        let mut regions = self.left.dirty_regions();
+        regions.extend(self.right.dirty_regions());
+        regions
+    }
+
+    fn clear_dirty_regions(&mut self) {
+        self.left.clear_dirty_regions();
+        self.right.clear_dirty_regions();
+    }
+}
+
+/// Heightmap faded out where the mask heightmap is low
+///
+/// The mask heights are mapped from the `range` to the weight 0.0..1.0, e.g. a continent shape
+/// masking mountains, so they do not grow out of the ocean.
+pub struct Mask {
+    /// Masked heights source
+    pub source: Box<dyn Heightmap>,
+    /// Mask heights source
+    pub mask: Box<dyn Heightmap>,
+    /// Mask heights, where the weight is 0.0 and where it reaches 1.0
+    pub range: [f32; 2],
+}
+
+impl Mask {
+    /// Constructs new masked heightmap
+    pub fn new(source: Box<dyn Heightmap>, mask: Box<dyn Heightmap>, range: [f32; 2]) -> Self {
+        Self {
+            source,
+            mask,
+            range,
+        }
+    }
+
+    /// Returns weight of the source at specified X and Z pair
+    pub fn weight(&self, x: usize, z: usize) -> f32 {
+        inverse_lerp(self.range, self.mask.value(x, z))
+    }
+}
+
+impl Heightmap for Mask {
+    fn value(&self, x: usize, z: usize) -> f32 {
+        self.source.value(x, z) * self.weight(x, z)
+    }
+
+    fn size(&self) -> usize {
+        self.source.size().min(self.mask.size())
+    }
+
+    fn is_hole(&self, x: usize, z: usize) -> bool {
+        self.source.is_hole(x, z)
+    }
+
+    fn dirty(&self) -> bool {
+        self.source.dirty() || self.mask.dirty()
+    }
+
+    fn set_dirty(&mut self, dirty: bool) {
+        self.source.set_dirty(dirty);
+        self.mask.set_dirty(dirty);
+    }
+
+    fn dirty_regions(&self) -> Vec<[usize; 4]> {
+        let mut regions = self.source.dirty_regions();
+        regions.extend(self.mask.dirty_regions());
+        regions
+    }
+
+    fn clear_dirty_regions(&mut self) {
+        self.source.clear_dirty_regions();
+        self.mask.clear_dirty_regions();
+    }
+}
+
+/// Switch between two heightmaps by the control heightmap
+///
+/// The `low` source is used where the control height is below the `threshold`, the `high` one
+/// above it. Sources are blended over the `blend` range of the control heights.
+pub struct Select {
+    /// Heights source below the threshold
+    pub low: Box<dyn Heightmap>,
+    /// Heights source above the threshold
+    pub high: Box<dyn Heightmap>,
+    /// Control heights source
+    pub control: Box<dyn Heightmap>,
+    /// Control height, where the sources are switched
+    pub threshold: f32,
+    /// Range of the control heights, where the sources are blended
+    pub blend: f32,
+}
+
+impl Select {
+    /// Constructs new selection between the heightmaps
+    pub fn new(
+        low: Box<dyn Heightmap>,
+        high: Box<dyn Heightmap>,
+        control: Box<dyn Heightmap>,
+        threshold: f32,
+    ) -> Self {
+        Self {
+            low,
+            high,
+            control,
+            threshold,
+            blend: 0.0,
+        }
+    }
+
+    /// Returns weight of the `high` source at specified X and Z pair
+    pub fn weight(&self, x: usize, z: usize) -> f32 {
+        let half_blend = self.blend.max(0.0) / 2.0;
+        let range = [self.threshold - half_blend, self.threshold + half_blend];
+        inverse_lerp(range, self.control.value(x, z))
+    }
+}
+
+impl Heightmap for Select {
+    fn value(&self, x: usize, z: usize) -> f32 {
+        match self.weight(x, z) {
+            weight if weight <= 0.0 => self.low.value(x, z),
+            weight if weight >= 1.0 => self.high.value(x, z),
+            weight => self.low.value(x, z) * (1.0 - weight) + self.high.value(x, z) * weight,
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.low
+            .size()
+            .min(self.high.size())
+            .min(self.control.size())
+    }
+
+    fn is_hole(&self, x: usize, z: usize) -> bool {
+        if self.weight(x, z) < 0.5 {
+            self.low.is_hole(x, z)
+        } else {
+            self.high.is_hole(x, z)
+        }
+    }
+
+    fn dirty(&self) -> bool {
+        self.low.dirty() || self.high.dirty() || self.control.dirty()
+    }
+
+    fn set_dirty(&mut self, dirty: bool) {
+        self.low.set_dirty(dirty);
+        self.high.set_dirty(dirty);
+        self.control.set_dirty(dirty);
+    }
+
+    fn dirty_regions(&self) -> Vec<[usize; 4]> {
+        let mut regions = self.low.dirty_regions();
+        regions.extend(self.high.dirty_regions());
+        regions.extend(self.control.dirty_regions());
+        regions
+    }
+
+    fn clear_dirty_regions(&mut self) {
+        self.low.clear_dirty_regions();
+        self.high.clear_dirty_regions();
+        self.control.clear_dirty_regions();
+    }
+}
+
+/// Maps the value from the range to 0.0..1.0, step function if the range is empty
+fn inverse_lerp(range: [f32; 2], value: f32) -> f32 {
+    let delta = range[1] - range[0];
+    if delta.abs() <= f32::EPSILON {
+        return if value >= range[0] { 1.0 } else { 0.0 };
+    }
+    ((value - range[0]) / delta).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Generator;
+
+    fn generator(size: usize, value: impl Fn(usize, usize) -> f32) -> Box<dyn Heightmap> {
+        Box::new(Generator {
+            amplitude: 10.0,
+            size,
+            noise_map: Some(
+                (0..size * size)
+                    .map(|i| value(i / size, i % size))
+                    .collect(),
+            ),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn combinators_compose_values_and_dirty_flags() {
+        let size = 5;
+        let mountains = || generator(size, |_, _| 0.8);
+        let continent = || generator(size, |x, _| x as f32 / 4.0);
+
+        let sum = Add::new(mountains(), continent());
+        assert_eq!(sum.value(2, 0), 13.0);
+
+        let product = Multiply {
+            scale: 0.1,
+            ..Multiply::new(mountains(), continent())
+        };
+        assert!((product.value(4, 0) - 8.0).abs() < 1e-5);
+
+        let masked = Mask::new(mountains(), continent(), [0.0, 5.0]);
+        assert_eq!(masked.value(0, 0), 0.0);
+        assert_eq!(masked.value(2, 3), 8.0);
+        assert!((masked.value(1, 3) - 4.0).abs() < 1e-5);
+
+        let mut select = Select {
+            blend: 0.0,
+            ..Select::new(mountains(), generator(size, |_, _| 0.1), continent(), 5.0)
+        };
+        assert_eq!(select.value(1, 0), 8.0);
+        assert_eq!(select.value(3, 0), 1.0);
+        assert_eq!(select.size(), size);
+
+        assert!(!select.dirty());
+        select
+            .control
+            .downcast_mut::<Generator>()
+            .unwrap()
+            .set_dirty(true);
+        assert!(select.dirty());
+        select.set_dirty(false);
+        assert!(!select.dirty());
+        assert!(!select.control.dirty());
+
+        select
+            .high
+            .downcast_mut::<Generator>()
+            .unwrap()
+            .set_hole(1, 2, true);
+        assert_eq!(select.dirty_regions(), vec![[1, 2, 1, 2]]);
+        select.clear_dirty_regions();
+        assert!(select.dirty_regions().is_empty());
+    }
+}
@@ -0,0 +1,426 @@
+use std::convert::TryFrom;
+use std::io::{Read, Seek};
+
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+
+use crate::Generator;
+
+/// Meters per degree of latitude
+const METERS_PER_DEGREE: f32 = 111_320.0;
+/// Value of the voids in SRTM data
+const HGT_VOID: i16 = -32768;
+
+/// GeoTIFF tags and keys
+const MODEL_PIXEL_SCALE_TAG: u16 = 33550;
+const MODEL_TIEPOINT_TAG: u16 = 33922;
+const GEO_KEY_DIRECTORY_TAG: u16 = 34735;
+const GDAL_NODATA_TAG: u16 = 42113;
+const GT_MODEL_TYPE_GEO_KEY: u16 = 1024;
+const PROJ_LINEAR_UNITS_GEO_KEY: u16 = 3076;
+const VERTICAL_UNITS_GEO_KEY: u16 = 4099;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+
+/// Real-world digital elevation model
+///
+/// Samples are stored row by row, indexed as `row * columns + column`. Columns go along X axis
+/// (to the east) and rows along Z axis (to the south). Heights and spacing are in meters.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Dem {
+    /// Number of the samples along X axis
+    pub columns: usize,
+    /// Number of the samples along Z axis
+    pub rows: usize,
+    /// Heights in meters
+    pub heights: Vec<f32>,
+    /// Distance between the samples along X and Z axes in meters
+    pub spacing: [f32; 2],
+    /// Value of the samples without data
+    pub no_data: Option<f32>,
+}
+
+impl Dem {
+    /// Reads SRTM `.hgt` file
+    ///
+    /// Size of the square grid is detected from the data length (1201 for SRTM3, 3601 for
+    /// SRTM1). `latitude` of the tile center is used to convert degrees into meters along X axis.
+    pub fn read_hgt<R: Read>(mut reader: R, latitude: f32) -> Result<Self, DemError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let size = ((data.len() / 2) as f64).sqrt() as usize;
+        if size < 2 || size * size * 2 != data.len() {
+            return Err(DemError::Corruption("Wrong length of HGT data"));
+        }
+
+        let heights = data
+            .chunks_exact(2)
+            .map(|pair| i16::from_be_bytes([pair[0], pair[1]]) as f32)
+            .collect();
+        // HGT tile covers one degree
+        let degrees = 1.0 / (size - 1) as f32;
+
+        Ok(Self {
+            columns: size,
+            rows: size,
+            heights,
+            spacing: [
+                degrees * METERS_PER_DEGREE * latitude.to_radians().cos(),
+                degrees * METERS_PER_DEGREE,
+            ],
+            no_data: Some(HGT_VOID as f32),
+        })
+    }
+
+    /// Reads the first image of GeoTIFF file
+    ///
+    /// Single channel unsigned integer and floating point samples are supported. Spacing is
+    /// taken from the model pixel scale, geographic coordinates and feet are converted into
+    /// meters.
+    pub fn read_geotiff<R: Read + Seek>(reader: R) -> Result<Self, DemError> {
+        let mut decoder = Decoder::new(reader)?;
+        let (columns, rows) = decoder.dimensions()?;
+        let (columns, rows) = (columns as usize, rows as usize);
+
+        let heights: Vec<f32> = match decoder.read_image()? {
+            DecodingResult::U8(data) => data.into_iter().map(|v| v as f32).collect(),
+            DecodingResult::U16(data) => data.into_iter().map(|v| v as f32).collect(),
+            DecodingResult::U32(data) => data.into_iter().map(|v| v as f32).collect(),
+            DecodingResult::U64(data) => data.into_iter().map(|v| v as f32).collect(),
+            DecodingResult::F32(data) => data,
+            DecodingResult::F64(data) => data.into_iter().map(|v| v as f32).collect(),
+        };
+        if columns < 2 || rows < 2 || heights.len() != columns * rows {
+            return Err(DemError::Corruption("Multichannel or too small image"));
+        }
+
+        let tag = |value| Tag::from_u16_exhaustive(value);
+        let scale = match decoder.find_tag(tag(MODEL_PIXEL_SCALE_TAG))? {
+            Some(value) => value.into_f64_vec()?,
+            None => vec![1.0, 1.0, 0.0],
+        };
+        let tiepoint = match decoder.find_tag(tag(MODEL_TIEPOINT_TAG))? {
+            Some(value) => value.into_f64_vec()?,
+            None => Vec::new(),
+        };
+        let geo_keys = decoder
+            .find_tag_unsigned_vec::<u16>(tag(GEO_KEY_DIRECTORY_TAG))?
+            .unwrap_or_default();
+        let no_data = match decoder.find_tag(tag(GDAL_NODATA_TAG))? {
+            Some(value) => value
+                .into_string()?
+                .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+                .parse::<f32>()
+                .ok(),
+            None => None,
+        };
+
+        let (scale_x, scale_z) = (
+            scale.first().copied().unwrap_or(1.0) as f32,
+            scale.get(1).copied().unwrap_or(1.0) as f32,
+        );
+        let spacing = if geo_key(&geo_keys, GT_MODEL_TYPE_GEO_KEY) == Some(MODEL_TYPE_GEOGRAPHIC) {
+            // tiepoint is the top left corner: [i, j, k, longitude, latitude, height]
+            let top = tiepoint.get(4).copied().unwrap_or(0.0) as f32;
+            let latitude = top - scale_z * rows as f32 / 2.0;
+            [
+                scale_x * METERS_PER_DEGREE * latitude.to_radians().cos(),
+                scale_z * METERS_PER_DEGREE,
+            ]
+        } else {
+            let linear = unit_to_meters(geo_key(&geo_keys, PROJ_LINEAR_UNITS_GEO_KEY));
+            [scale_x * linear, scale_z * linear]
+        };
+
+        let vertical = unit_to_meters(geo_key(&geo_keys, VERTICAL_UNITS_GEO_KEY));
+        let heights = heights
+            .into_iter()
+            .map(|height| {
+                if Some(height) == no_data {
+                    height
+                } else {
+                    height * vertical
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            columns,
+            rows,
+            heights,
+            spacing,
+            no_data,
+        })
+    }
+
+    /// Returns width and depth of the model in meters
+    pub fn extent(&self) -> [f32; 2] {
+        [
+            self.columns.saturating_sub(1) as f32 * self.spacing[0],
+            self.rows.saturating_sub(1) as f32 * self.spacing[1],
+        ]
+    }
+
+    /// Returns true if the sample has no data
+    pub fn is_void(&self, column: usize, row: usize) -> bool {
+        let height = self.heights[row * self.columns + column];
+        !height.is_finite() || Some(height) == self.no_data
+    }
+
+    /// Returns the lowest and the highest heights of the samples with data
+    pub fn elevation_range(&self) -> [f32; 2] {
+        let mut range = [f32::MAX, f32::MIN];
+        for (i, &height) in self.heights.iter().enumerate() {
+            if self.is_void(i % self.columns, i / self.columns) {
+                continue;
+            }
+            range[0] = range[0].min(height);
+            range[1] = range[1].max(height);
+        }
+        if range[0] > range[1] {
+            [0.0, 0.0]
+        } else {
+            range
+        }
+    }
+
+    /// Returns bilinear interpolated height at the distance in meters from the top left corner
+    ///
+    /// Returns `None` outside of the model or next to the samples without data.
+    pub fn height(&self, x: f32, z: f32) -> Option<f32> {
+        if self.columns < 2 || self.rows < 2 || self.spacing[0] <= 0.0 || self.spacing[1] <= 0.0 {
+            return None;
+        }
+        let column = x / self.spacing[0];
+        let row = z / self.spacing[1];
+        let (max_column, max_row) = ((self.columns - 1) as f32, (self.rows - 1) as f32);
+        if column < 0.0 || row < 0.0 || column > max_column || row > max_row {
+            return None;
+        }
+        let c0 = (column.floor() as usize).min(self.columns - 2);
+        let r0 = (row.floor() as usize).min(self.rows - 2);
+        let (tx, tz) = (column - c0 as f32, row - r0 as f32);
+
+        let mut corners = [0.0; 4];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let (c, r) = (c0 + i % 2, r0 + i / 2);
+            if self.is_void(c, r) {
+                return None;
+            }
+            *corner = self.heights[r * self.columns + c];
+        }
+        let top = corners[0] * (1.0 - tx) + corners[1] * tx;
+        let bottom = corners[2] * (1.0 - tx) + corners[3] * tx;
+        Some(top * (1.0 - tz) + bottom * tz)
+    }
+
+    /// Returns number of the heightmap tiles along X and Z axes needed to cover the model
+    ///
+    /// Every tile has `size` values per side, `sample_spacing` meters between them, and shares
+    /// the edge with its neighbours.
+    pub fn tiles(&self, size: usize, sample_spacing: f32) -> [usize; 2] {
+        if size < 2 || sample_spacing <= 0.0 {
+            return [0, 0];
+        }
+        let tile_extent = (size - 1) as f32 * sample_spacing;
+        let [width, depth] = self.extent();
+        [
+            ((width / tile_extent).ceil() as usize).max(1),
+            ((depth / tile_extent).ceil() as usize).max(1),
+        ]
+    }
+
+    /// Converts the tile of the model into the heightmap generator
+    ///
+    /// The generator has `size` values per side with `sample_spacing` meters between them, so
+    /// `1.0` keeps one world unit per meter. Elevation range of the whole model is used, so
+    /// tiles match each other. Samples without data and outside of the model become holes.
+    pub fn tile(
+        &self,
+        column: usize,
+        row: usize,
+        size: usize,
+        sample_spacing: f32,
+    ) -> Option<Generator> {
+        let [columns, rows] = self.tiles(size, sample_spacing);
+        if column >= columns || row >= rows {
+            return None;
+        }
+        let [min_height, max_height] = self.elevation_range();
+        let amplitude = max_height - min_height;
+        let tile_extent = (size - 1) as f32 * sample_spacing;
+        let origin = [column as f32 * tile_extent, row as f32 * tile_extent];
+
+        let mut noise_map = Vec::with_capacity(size * size);
+        let mut holes = Vec::with_capacity(size * size);
+        for x in 0..size {
+            for z in 0..size {
+                let height = self.height(
+                    origin[0] + x as f32 * sample_spacing,
+                    origin[1] + z as f32 * sample_spacing,
+                );
+                noise_map.push(match (height, amplitude > 0.0) {
+                    (Some(height), true) => (height - min_height) / amplitude,
+                    _ => 0.0,
+                });
+                holes.push(height.is_none());
+            }
+        }
+
+        let mut generator = Generator {
+            amplitude,
+            min_height,
+            size,
+            noise_map: Some(noise_map),
+            holes: if holes.iter().any(|&hole| hole) {
+                Some(holes)
+            } else {
+                None
+            },
+            dirty: true,
+            ..Default::default()
+        };
+        // imported heights are the base the edits are made over
+        generator.set_base();
+        Some(generator)
+    }
+}
+
+/// Returns value of the key from GeoTIFF key directory
+fn geo_key(directory: &[u16], key: u16) -> Option<u16> {
+    // header of 4 values, then entries of [key, location, count, value]
+    let count = usize::from(*directory.get(3)?);
+    directory
+        .get(4..)?
+        .chunks_exact(4)
+        .take(count)
+        .find(|entry| entry[0] == key && entry[1] == 0)
+        .map(|entry| entry[3])
+}
+
+/// Returns number of meters in the EPSG linear unit
+fn unit_to_meters(unit: Option<u16>) -> f32 {
+    match unit {
+        // foot
+        Some(9002) => 0.3048,
+        // US survey foot
+        Some(9003) => 1200.0 / 3937.0,
+        _ => 1.0,
+    }
+}
+
+/// Elevation data import errors
+#[derive(Debug)]
+pub enum DemError {
+    /// Input and output error
+    Io(std::io::Error),
+    /// Error occured during TIFF decoding
+    Tiff(tiff::TiffError),
+    /// It seems that source data were corrupted
+    Corruption(&'static str),
+}
+
+impl std::error::Error for DemError {}
+
+impl std::fmt::Display for DemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DemError::Io(err) => write!(f, "Can't read elevation data ({:?})", err),
+            DemError::Tiff(err) => write!(f, "Can't decode GeoTIFF ({:?})", err),
+            DemError::Corruption(err) => write!(f, "Elevation data could be corrupted ({:?})", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for DemError {
+    fn from(err: std::io::Error) -> Self {
+        DemError::Io(err)
+    }
+}
+
+impl From<tiff::TiffError> for DemError {
+    fn from(err: tiff::TiffError) -> Self {
+        DemError::Tiff(err)
+    }
+}
+
+impl TryFrom<&Dem> for Generator {
+    type Error = DemError;
+
+    /// Converts the whole model into the square generator of its longest side samples count
+    fn try_from(dem: &Dem) -> Result<Self, Self::Error> {
+        let size = dem.columns.max(dem.rows);
+        let spacing = dem.extent()[0].max(dem.extent()[1]) / size.saturating_sub(1).max(1) as f32;
+        dem.tile(0, 0, size, spacing)
+            .ok_or(DemError::Corruption("Empty elevation model"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Heightmap;
+    use std::io::Cursor;
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    #[test]
+    fn hgt_is_tiled_into_generators() {
+        let size = 5;
+        let mut data = Vec::new();
+        for row in 0..size {
+            for column in 0..size {
+                let height: i16 = if (row, column) == (4, 4) {
+                    HGT_VOID
+                } else {
+                    (column * 100) as i16 - 100
+                };
+                data.extend_from_slice(&height.to_be_bytes());
+            }
+        }
+        let mut dem = Dem::read_hgt(Cursor::new(data), 0.0).unwrap();
+        assert_eq!(dem.columns, 5);
+        assert!((dem.spacing[1] - METERS_PER_DEGREE / 4.0).abs() < 1e-2);
+        assert_eq!(dem.elevation_range(), [-100.0, 300.0]);
+
+        dem.spacing = [10.0, 10.0];
+        assert_eq!(dem.height(15.0, 0.0), Some(50.0));
+        assert_eq!(dem.height(35.0, 35.0), None);
+        assert_eq!(dem.tiles(3, 10.0), [2, 2]);
+
+        let tile = dem.tile(1, 0, 3, 10.0).unwrap();
+        assert_eq!(tile.value(0, 0), 100.0);
+        assert_eq!(tile.value(2, 1), 300.0);
+        assert!(dem.tile(1, 1, 3, 10.0).unwrap().is_hole(2, 2));
+        assert!(dem.tile(2, 0, 3, 10.0).is_none());
+    }
+
+    #[test]
+    fn geotiff_converts_feet_into_meters() {
+        let mut data = Cursor::new(Vec::new());
+        {
+            let mut encoder = TiffEncoder::new(&mut data).unwrap();
+            let mut image = encoder.new_image::<colortype::Gray32Float>(2, 2).unwrap();
+            let directory = image.encoder();
+            directory
+                .write_tag(
+                    Tag::from_u16_exhaustive(MODEL_PIXEL_SCALE_TAG),
+                    &[30.0, 30.0, 0.0][..],
+                )
+                .unwrap();
+            let keys: &[u16] = &[1, 1, 0, 2, 1024, 0, 1, 1, 4099, 0, 1, 9002];
+            directory
+                .write_tag(Tag::from_u16_exhaustive(GEO_KEY_DIRECTORY_TAG), keys)
+                .unwrap();
+            image.write_data(&[0.0, 10.0, 20.0, 30.0]).unwrap();
+        }
+        data.set_position(0);
+
+        let dem = Dem::read_geotiff(data).unwrap();
+        assert_eq!(dem.spacing, [30.0, 30.0]);
+        assert!((dem.heights[3] - 9.144).abs() < 1e-4);
+        assert!((dem.height(30.0, 15.0).unwrap() - 6.096).abs() < 1e-4);
+
+        let generator = Generator::try_from(&dem).unwrap();
+        assert_eq!(generator.size, 2);
+        assert!((generator.value(1, 1) - 9.144).abs() < 1e-4);
+    }
+}
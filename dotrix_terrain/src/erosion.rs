@@ -0,0 +1,342 @@
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+/// Hydraulic erosion settings (droplet model)
+#[derive(Clone, Copy, PartialEq)]
+pub struct HydraulicErosion {
+    /// Number of simulated droplets
+    pub droplets: usize,
+    /// Seed for droplets spawning
+    pub seed: u64,
+    /// Radius of the erosion brush in map cells
+    pub radius: usize,
+    /// How much the droplet keeps its direction (0.0..1.0)
+    pub inertia: f32,
+    /// Multiplier for how much sediment a droplet can carry
+    pub capacity: f32,
+    /// Minimal sediment capacity to prevent carry capacity getting too close to zero on flat
+    /// terrain
+    pub min_capacity: f32,
+    /// How fast the droplet erodes the terrain (0.0..1.0)
+    pub erode_speed: f32,
+    /// How fast the droplet deposits the sediment (0.0..1.0)
+    pub deposit_speed: f32,
+    /// How fast the droplet evaporates (0.0..1.0)
+    pub evaporate_speed: f32,
+    /// Gravity acceleration of the droplet
+    pub gravity: f32,
+    /// Maximal number of steps of the droplet
+    pub lifetime: usize,
+    /// Initial water volume of the droplet
+    pub water: f32,
+    /// Initial speed of the droplet
+    pub speed: f32,
+}
+
+impl HydraulicErosion {
+    /// Erodes the square map of heights with `size` values per side
+    ///
+    /// Values are indexed as `x * size + z`, the same way as in the noise map
+    pub fn apply(&self, map: &mut [f32], size: usize) {
+        if size < 3 || map.len() < size * size {
+            return;
+        }
+
+        let brush = self.brush();
+        let max_position = (size - 1) as f32;
+        let mut pseudo_rng = SmallRng::seed_from_u64(self.seed);
+
+        for _ in 0..self.droplets {
+            let mut position_x = pseudo_rng.gen_range(0.0..max_position);
+            let mut position_z = pseudo_rng.gen_range(0.0..max_position);
+            let mut direction_x = 0.0;
+            let mut direction_z = 0.0;
+            let mut speed = self.speed;
+            let mut water = self.water;
+            let mut sediment = 0.0;
+
+            for _ in 0..self.lifetime {
+                let cell_x = position_x as usize;
+                let cell_z = position_z as usize;
+                let offset_x = position_x - cell_x as f32;
+                let offset_z = position_z - cell_z as f32;
+
+                let (height, gradient_x, gradient_z) = sample(map, size, position_x, position_z);
+
+                direction_x = direction_x * self.inertia - gradient_x * (1.0 - self.inertia);
+                direction_z = direction_z * self.inertia - gradient_z * (1.0 - self.inertia);
+
+                let length = (direction_x * direction_x + direction_z * direction_z).sqrt();
+                if length <= f32::EPSILON {
+                    break;
+                }
+                direction_x /= length;
+                direction_z /= length;
+
+                position_x += direction_x;
+                position_z += direction_z;
+
+                if position_x < 0.0
+                    || position_x >= max_position
+                    || position_z < 0.0
+                    || position_z >= max_position
+                {
+                    break;
+                }
+
+                let (new_height, _, _) = sample(map, size, position_x, position_z);
+                let delta_height = new_height - height;
+
+                let capacity =
+                    (-delta_height * speed * water * self.capacity).max(self.min_capacity);
+
+                if sediment > capacity || delta_height > 0.0 {
+                    // fill the pit or drop the excess of sediment
+                    let amount = if delta_height > 0.0 {
+                        delta_height.min(sediment)
+                    } else {
+                        (sediment - capacity) * self.deposit_speed
+                    };
+                    sediment -= amount;
+
+                    let i = cell_x * size + cell_z;
+                    map[i] += amount * (1.0 - offset_x) * (1.0 - offset_z);
+                    map[i + size] += amount * offset_x * (1.0 - offset_z);
+                    map[i + 1] += amount * (1.0 - offset_x) * offset_z;
+                    map[i + size + 1] += amount * offset_x * offset_z;
+                } else {
+                    let amount = ((capacity - sediment) * self.erode_speed).min(-delta_height);
+                    for &(dx, dz, weight) in brush.iter() {
+                        let x = cell_x as i32 + dx;
+                        let z = cell_z as i32 + dz;
+                        if x < 0 || z < 0 || x as usize >= size || z as usize >= size {
+                            continue;
+                        }
+                        let eroded = amount * weight;
+                        map[x as usize * size + z as usize] -= eroded;
+                        sediment += eroded;
+                    }
+                }
+
+                speed = (speed * speed + delta_height * self.gravity)
+                    .max(0.0)
+                    .sqrt();
+                water *= 1.0 - self.evaporate_speed;
+            }
+        }
+    }
+
+    /// Returns offsets and normalized weights of the erosion brush
+    fn brush(&self) -> Vec<(i32, i32, f32)> {
+        let radius = self.radius.max(1) as i32;
+        let mut brush = Vec::new();
+        let mut weights_sum = 0.0;
+
+        for dx in -radius..=radius {
+            for dz in -radius..=radius {
+                let distance = ((dx * dx + dz * dz) as f32).sqrt();
+                let weight = 1.0 - distance / radius as f32;
+                if weight > 0.0 {
+                    weights_sum += weight;
+                    brush.push((dx, dz, weight));
+                }
+            }
+        }
+
+        for (_, _, weight) in brush.iter_mut() {
+            *weight /= weights_sum;
+        }
+
+        brush
+    }
+}
+
+impl Default for HydraulicErosion {
+    fn default() -> Self {
+        Self {
+            droplets: 70_000,
+            seed: 0,
+            radius: 3,
+            inertia: 0.05,
+            capacity: 4.0,
+            min_capacity: 0.01,
+            erode_speed: 0.3,
+            deposit_speed: 0.3,
+            evaporate_speed: 0.01,
+            gravity: 4.0,
+            lifetime: 30,
+            water: 1.0,
+            speed: 1.0,
+        }
+    }
+}
+
+/// Redistributes material from slopes steeper than `talus` height difference per map cell
+///
+/// Values are indexed as `x * size + z`, the same way as in the noise map
+pub(crate) fn thermal(map: &mut [f32], size: usize, iterations: usize, talus: f32) {
+    if size < 2 || map.len() < size * size {
+        return;
+    }
+
+    const NEIGHBOURS: [(i32, i32, f32); 8] = [
+        (-1, 0, 1.0),
+        (1, 0, 1.0),
+        (0, -1, 1.0),
+        (0, 1, 1.0),
+        (-1, -1, std::f32::consts::SQRT_2),
+        (1, -1, std::f32::consts::SQRT_2),
+        (-1, 1, std::f32::consts::SQRT_2),
+        (1, 1, std::f32::consts::SQRT_2),
+    ];
+
+    let mut deltas = vec![0.0; size * size];
+
+    for _ in 0..iterations {
+        for x in 0..size {
+            for z in 0..size {
+                let i = x * size + z;
+                let height = map[i];
+                let mut max_excess = 0.0;
+                let mut total_excess = 0.0;
+                let mut lower = [(0, 0.0); 8];
+                let mut lower_count = 0;
+
+                for &(dx, dz, distance) in NEIGHBOURS.iter() {
+                    let nx = x as i32 + dx;
+                    let nz = z as i32 + dz;
+                    if nx < 0 || nz < 0 || nx as usize >= size || nz as usize >= size {
+                        continue;
+                    }
+                    let j = nx as usize * size + nz as usize;
+                    // diagonal neighbours are farther, so their talus is scaled by the distance
+                    let excess = height - map[j] - talus * distance;
+                    if excess > 0.0 {
+                        lower[lower_count] = (j, excess);
+                        lower_count += 1;
+                        total_excess += excess;
+                        if excess > max_excess {
+                            max_excess = excess;
+                        }
+                    }
+                }
+
+                if lower_count == 0 {
+                    continue;
+                }
+
+                let amount = max_excess / 2.0;
+                deltas[i] -= amount;
+                for &(j, excess) in lower[0..lower_count].iter() {
+                    deltas[j] += amount * excess / total_excess;
+                }
+            }
+        }
+
+        for (value, delta) in map.iter_mut().zip(deltas.iter_mut()) {
+            *value += *delta;
+            *delta = 0.0;
+        }
+    }
+}
+
+/// Returns bilinear interpolated height and gradient at the position
+fn sample(map: &[f32], size: usize, x: f32, z: f32) -> (f32, f32, f32) {
+    let cell_x = x as usize;
+    let cell_z = z as usize;
+    let offset_x = x - cell_x as f32;
+    let offset_z = z - cell_z as f32;
+
+    let i = cell_x * size + cell_z;
+    let h00 = map[i];
+    let h10 = map[i + size];
+    let h01 = map[i + 1];
+    let h11 = map[i + size + 1];
+
+    let gradient_x = (h10 - h00) * (1.0 - offset_z) + (h11 - h01) * offset_z;
+    let gradient_z = (h01 - h00) * (1.0 - offset_x) + (h11 - h10) * offset_x;
+    let height = h00 * (1.0 - offset_x) * (1.0 - offset_z)
+        + h10 * offset_x * (1.0 - offset_z)
+        + h01 * (1.0 - offset_x) * offset_z
+        + h11 * offset_x * offset_z;
+
+    (height, gradient_x, gradient_z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slope(size: usize) -> Vec<f32> {
+        (0..size * size)
+            .map(|i| {
+                let x = (i / size) as f32;
+                let z = (i % size) as f32;
+                (x + z) / (2 * size) as f32 + ((x * 0.7).sin() * (z * 0.3).cos()) * 0.05
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hydraulic_erosion_keeps_flat_map() {
+        let size = 32;
+        let mut map = vec![0.5; size * size];
+        HydraulicErosion {
+            droplets: 1000,
+            ..Default::default()
+        }
+        .apply(&mut map, size);
+
+        assert!(map.iter().all(|&value| (value - 0.5).abs() < f32::EPSILON));
+    }
+
+    #[test]
+    fn thermal_erosion_smooths_peak() {
+        let size = 9;
+        let mut map = vec![0.0; size * size];
+        map[4 * size + 4] = 1.0;
+        let total: f32 = map.iter().sum();
+
+        thermal(&mut map, size, 50, 0.1);
+
+        let peak = map[4 * size + 4];
+        assert!(peak < 0.5);
+        assert!((map.iter().sum::<f32>() - total).abs() < 0.0001);
+        for x in 3..=5 {
+            for z in 3..=5 {
+                assert!(peak - map[x * size + z] <= 0.1 * std::f32::consts::SQRT_2 + 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn thermal_erosion_scales_diagonal_talus() {
+        let size = 2;
+        let talus = 0.1;
+        let mut map = vec![0.2, 0.2, 0.2, 0.0];
+
+        thermal(&mut map, size, 1, talus);
+
+        let diagonal_amount = (0.2 - talus * std::f32::consts::SQRT_2) / 2.0;
+        assert!((map[0] - (0.2 - diagonal_amount)).abs() < 0.0001);
+        assert!((map[1] - 0.15).abs() < 0.0001);
+        assert!((map[3] - (0.1 + diagonal_amount)).abs() < 0.0001);
+    }
+
+    #[test]
+    fn hydraulic_erosion_is_deterministic() {
+        let size = 32;
+        let erosion = HydraulicErosion {
+            droplets: 1000,
+            seed: 42,
+            ..Default::default()
+        };
+        let mut map_a = slope(size);
+        let mut map_b = slope(size);
+        erosion.apply(&mut map_a, size);
+        erosion.apply(&mut map_b, size);
+
+        assert_eq!(map_a, map_b);
+        assert_ne!(map_a, slope(size));
+    }
+}
@@ -0,0 +1,38 @@
+use crate::VecXZ;
+
+/// Stage of the terrain tile lifecycle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileEventKind {
+    /// Tile became visible and waits for the generation
+    Queued,
+    /// Tile mesh was generated (or regenerated after the heights change)
+    Generated,
+    /// Hidden tile was restored from the pool without regeneration
+    Restored,
+    /// Tile mesh was uploaded to GPU
+    Uploaded,
+    /// Tile was hidden or exiled
+    Despawned,
+}
+
+/// Terrain tile lifecycle event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileEvent {
+    /// Stage of the lifecycle
+    pub kind: TileEventKind,
+    /// World X and Z coordinates of the tile center
+    pub position: VecXZ<i32>,
+    /// Level of details of the tile
+    pub lod: usize,
+}
+
+impl TileEvent {
+    /// Constructs new event
+    pub fn new(kind: TileEventKind, position: VecXZ<i32>, lod: usize) -> Self {
+        Self {
+            kind,
+            position,
+            lod,
+        }
+    }
+}
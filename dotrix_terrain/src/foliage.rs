@@ -0,0 +1,316 @@
+use std::collections::HashSet;
+
+use dotrix_core::assets::{Assets, Mesh, Shader};
+use dotrix_core::camera::ProjView;
+use dotrix_core::ecs::{Const, Context, Entity, Mut};
+use dotrix_core::renderer::{
+    BindGroup, Binding, Options, PipelineLayout, PipelineOptions, Renderer, Stage, StorageBuffer,
+    UniformBuffer, DEPTH_PREPASS,
+};
+use dotrix_core::{Color, Globals, Id, Pipeline, World};
+
+use dotrix_pbr::Lights;
+
+use crate::{Scatter, Terrain, Tile};
+
+const PIPELINE_LABEL: &str = "dotrix::terrain::foliage";
+
+/// Foliage kind scattered over the terrain tiles of the highest level of details
+///
+/// The mesh must have positions and normals as its first two attributes. Instances of the tile
+/// are rendered with a single instanced draw call.
+pub struct Foliage {
+    /// Instance mesh
+    pub mesh: Id<Mesh>,
+    /// Foliage color
+    pub color: Color,
+    /// Scattering rules
+    pub scatter: Scatter,
+    /// Distance where instances start to fade and distance, where they disappear completely
+    pub fade_distance: [f32; 2],
+    /// Foliage parameters uniform
+    pub uniform: UniformBuffer,
+}
+
+impl Foliage {
+    /// Constructs new foliage kind of the mesh
+    pub fn new(mesh: Id<Mesh>) -> Self {
+        Self {
+            mesh,
+            ..Default::default()
+        }
+    }
+
+    /// Returns instances of the tile (on the highest level of details) centered at X and Z
+    pub fn instances(&self, terrain: &Terrain, tile_x: i32, tile_z: i32) -> Vec<Instance> {
+        self.scatter
+            .points(terrain, tile_x, tile_z)
+            .into_iter()
+            .map(|point| Instance {
+                position: [
+                    point.position[0],
+                    point.position[1],
+                    point.position[2],
+                    point.scale,
+                ],
+                rotation: [point.rotation, 0.0, 0.0, 0.0],
+            })
+            .collect()
+    }
+}
+
+impl Default for Foliage {
+    fn default() -> Self {
+        Self {
+            mesh: Id::default(),
+            color: Color::rgb(0.28, 0.52, 0.16),
+            scatter: Scatter::default(),
+            fade_distance: [64.0, 96.0],
+            uniform: UniformBuffer::default(),
+        }
+    }
+}
+
+/// Foliage instance data
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Instance {
+    /// Position in XYZ and scale in W
+    pub position: [f32; 4],
+    /// Rotation around Y axis in X
+    pub rotation: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for Instance {}
+unsafe impl bytemuck::Pod for Instance {}
+
+/// Foliage instances of one kind on one tile
+pub struct FoliageBatch {
+    /// Index of the foliage kind in [`Terrain::foliage`]
+    pub foliage: usize,
+    /// Mesh of the owning tile
+    pub tile: Id<Mesh>,
+    /// Instances data
+    pub instances: Vec<Instance>,
+    /// Instances storage buffer
+    pub buffer: StorageBuffer,
+    /// Pipeline rendering the depth in the depth prepass
+    pub prepass: Pipeline,
+}
+
+/// Foliage spawn system context
+#[derive(Default)]
+pub struct Spawner {
+    tiles: HashSet<Id<Mesh>>,
+    to_exile: Vec<Entity>,
+}
+
+/// Foliage startup system
+pub fn startup(mut assets: Mut<Assets>, renderer: Const<Renderer>) {
+    let mut shader = Shader {
+        name: String::from(PIPELINE_LABEL),
+        code: Lights::add_to_shader(include_str!("shaders/foliage.wgsl"), 0, 1),
+        ..Default::default()
+    };
+    shader.load(&renderer);
+    assets.store_as(shader, PIPELINE_LABEL);
+}
+
+/// Foliage spawn system
+/// Scatters foliage over the tiles of the highest level of details, follows their lifecycle
+pub fn spawn(mut ctx: Context<Spawner>, terrain: Const<Terrain>, mut world: Mut<World>) {
+    let mut tiles = HashSet::new();
+    let mut new_tiles = Vec::new();
+    let query = world.query::<(&Tile,)>();
+    for (tile,) in query {
+        if tile.lod != 0 || tile.pooled {
+            continue;
+        }
+        let dirty = terrain.is_tile_dirty(tile.x, tile.z, tile.lod);
+        if !dirty {
+            tiles.insert(tile.mesh);
+        }
+        if dirty || !ctx.tiles.contains(&tile.mesh) {
+            new_tiles.push((tile.mesh, tile.x, tile.z));
+        }
+    }
+
+    // exile batches of the exiled or changed tiles
+    let query = world.query::<(&FoliageBatch, &Entity)>();
+    for (batch, entity) in query {
+        if !tiles.contains(&batch.tile) {
+            ctx.to_exile.push(*entity);
+        }
+    }
+    for entity in ctx.to_exile.iter() {
+        world.exile(*entity);
+    }
+    ctx.to_exile.clear();
+
+    for (mesh, x, z) in new_tiles {
+        for (index, foliage) in terrain.foliage.iter().enumerate() {
+            let instances = foliage.instances(&terrain, x, z);
+            if instances.is_empty() {
+                continue;
+            }
+            let pipeline = Pipeline {
+                options: Options {
+                    end_index: instances.len() as u32,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let prepass = Pipeline {
+                options: Options {
+                    end_index: instances.len() as u32,
+                    pass: Some(String::from(DEPTH_PREPASS)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let batch = FoliageBatch {
+                foliage: index,
+                tile: mesh,
+                instances,
+                buffer: StorageBuffer::new_readonly(),
+                prepass,
+            };
+            world.spawn(Some((batch, pipeline)));
+        }
+        tiles.insert(mesh);
+    }
+
+    ctx.tiles = tiles;
+}
+
+/// Foliage rendering system
+pub fn render(
+    mut renderer: Mut<Renderer>,
+    mut assets: Mut<Assets>,
+    globals: Const<Globals>,
+    mut terrain: Mut<Terrain>,
+    world: Const<World>,
+) {
+    for foliage in terrain.foliage.iter_mut() {
+        let params = Params {
+            color: foliage.color.into(),
+            fade_distance: [foliage.fade_distance[0], foliage.fade_distance[1], 0.0, 0.0],
+        };
+        renderer.load_uniform_buffer(&mut foliage.uniform, bytemuck::cast_slice(&[params]));
+        if let Some(mesh) = assets.get_mut(foliage.mesh) {
+            mesh.load(&renderer);
+        }
+    }
+
+    let query = world.query::<(&mut FoliageBatch, &mut Pipeline)>();
+    for (batch, pipeline) in query {
+        let foliage = match terrain.foliage.get(batch.foliage) {
+            Some(foliage) => foliage,
+            None => continue,
+        };
+
+        if pipeline.shader.is_null() {
+            pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+        }
+
+        if !pipeline.cycle(&renderer) {
+            continue;
+        }
+
+        let mesh = match assets.get(foliage.mesh) {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+
+        // the color pipeline compares depth differently after the prepass is toggled
+        let depth_prepass = renderer.depth_prepass();
+        if !depth_prepass && batch.prepass.ready() {
+            pipeline.bindings.unload();
+            batch.prepass.bindings.unload();
+        }
+        if !pipeline.ready() || depth_prepass && !batch.prepass.ready() {
+            if let Some(shader) = assets.get(pipeline.shader) {
+                if !shader.loaded() {
+                    continue;
+                }
+
+                renderer.load_storage_buffer(
+                    &mut batch.buffer,
+                    bytemuck::cast_slice(batch.instances.as_slice()),
+                );
+
+                let proj_view = globals
+                    .get::<ProjView>()
+                    .expect("ProjView buffer must be loaded");
+
+                let lights = globals
+                    .get::<Lights>()
+                    .expect("Lights buffer must be loaded");
+
+                let bindings = [
+                    BindGroup::new(
+                        "Globals",
+                        vec![
+                            Binding::Uniform("ProjView", Stage::Vertex, &proj_view.uniform),
+                            Binding::Uniform("Lights", Stage::All, &lights.uniform),
+                        ],
+                    ),
+                    BindGroup::new(
+                        "Locals",
+                        vec![
+                            Binding::Uniform("Foliage", Stage::All, &foliage.uniform),
+                            Binding::Storage("Instances", Stage::Vertex, &batch.buffer),
+                        ],
+                    ),
+                ];
+
+                renderer.bind(
+                    pipeline,
+                    PipelineLayout {
+                        label: String::from(PIPELINE_LABEL),
+                        mesh: Some(mesh),
+                        shader,
+                        bindings: &bindings,
+                        vertex_buffers: &[],
+                        push_constants: None,
+                        options: if depth_prepass {
+                            PipelineOptions::depth_prepassed()
+                        } else {
+                            PipelineOptions::default()
+                        },
+                    },
+                );
+                if depth_prepass {
+                    batch.prepass.shader = pipeline.shader;
+                    renderer.bind(
+                        &mut batch.prepass,
+                        PipelineLayout {
+                            label: String::from(PIPELINE_LABEL),
+                            mesh: Some(mesh),
+                            shader,
+                            bindings: &bindings,
+                            vertex_buffers: &[],
+                            push_constants: None,
+                            options: PipelineOptions::default(),
+                        },
+                    );
+                }
+            }
+        }
+
+        if depth_prepass && batch.prepass.cycle(&renderer) {
+            renderer.run(&mut batch.prepass, mesh);
+        }
+        renderer.run(pipeline, mesh);
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+struct Params {
+    color: [f32; 4],
+    fade_distance: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for Params {}
+unsafe impl bytemuck::Pod for Params {}
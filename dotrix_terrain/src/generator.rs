@@ -1,9 +1,22 @@
-use crate::Heightmap;
+use crate::erosion;
+use crate::resample::{self, Filter};
+use crate::{Biomes, Brush, Heightmap, HydraulicErosion, Region, RegionError, Spline, Stamp};
 use noise::{NoiseFn, Perlin};
 
 use rand::rngs::SmallRng;
 use rand::{RngCore, SeedableRng};
 
+/// Shape of the noise octaves
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Fractal {
+    /// Sum of the octaves, rolling hills
+    Fbm,
+    /// Inverted absolute octaves values, sharp mountain ridges
+    Ridged,
+    /// Absolute octaves values, dunes and puffy hills
+    Billow,
+}
+
 /// Noise configuration
 #[derive(Clone, Copy, PartialEq, PartialOrd)]
 pub struct Noise {
@@ -21,6 +34,10 @@ pub struct Noise {
     pub offset: [f32; 2],
     /// Noise seed
     pub seed: u32,
+    /// Shape of the octaves
+    pub fractal: Fractal,
+    /// Strength of the domain warping in map units, 0.0 disables warping
+    pub warp: f32,
 }
 
 impl Noise {
@@ -29,17 +46,10 @@ impl Noise {
         let mut map = Vec::with_capacity(size * size);
         let noise = Perlin::new();
 
-        let mut max_noise_height = 0.0;
-        let mut amplitude = 1.0;
-        let mut frequency;
-
         let mut pseudo_rng = SmallRng::seed_from_u64(self.seed as u64);
 
         let octaves_offsets = (0..self.octaves)
             .map(|_| {
-                max_noise_height += amplitude;
-                amplitude *= self.persistence;
-
                 [
                     Self::randomize_offset(self.offset[0], &mut pseudo_rng),
                     Self::randomize_offset(self.offset[1], &mut pseudo_rng),
@@ -47,22 +57,46 @@ impl Noise {
             })
             .collect::<Vec<_>>();
 
+        let warp_offsets = [
+            Self::randomize_offset(self.offset[0], &mut pseudo_rng),
+            Self::randomize_offset(self.offset[1], &mut pseudo_rng),
+        ];
+
         let mut min_noise_height = 0.0;
         let mut max_noise_height = 0.0;
 
         let half_size = (size / 2) as f32;
         for x in 0..size {
             for z in 0..size {
-                let mut noise_height = 0.0;
+                let mut x = x as f32 - half_size;
+                let mut z = z as f32 - half_size;
 
-                amplitude = 1.0;
-                frequency = 1.0;
+                if self.warp != 0.0 {
+                    let xf = (x + warp_offsets[0]) / self.scale;
+                    let zf = (z + warp_offsets[1]) / self.scale;
+                    let warp_x = noise.get([xf as f64, zf as f64]) as f32;
+                    let warp_z = noise.get([zf as f64, xf as f64]) as f32;
+                    x += warp_x * self.warp;
+                    z += warp_z * self.warp;
+                }
+
+                let mut noise_height = 0.0;
+                let mut amplitude = 1.0;
+                let mut frequency = 1.0;
 
                 for octave_offset in octaves_offsets.iter() {
-                    let xf = (x as f32 - half_size + octave_offset[0]) / self.scale * frequency;
-                    let zf = (z as f32 - half_size + octave_offset[1]) / self.scale * frequency;
+                    let xf = (x + octave_offset[0]) / self.scale * frequency;
+                    let zf = (z + octave_offset[1]) / self.scale * frequency;
 
                     let noise_value = noise.get([xf as f64, zf as f64]) as f32; // (-1..1);
+                    let noise_value = match self.fractal {
+                        Fractal::Fbm => noise_value,
+                        Fractal::Ridged => {
+                            let ridge = 1.0 - noise_value.abs();
+                            ridge * ridge * 2.0 - 1.0
+                        }
+                        Fractal::Billow => noise_value.abs() * 2.0 - 1.0,
+                    };
                     noise_height += noise_value * amplitude;
 
                     amplitude *= self.persistence;
@@ -92,6 +126,17 @@ impl Noise {
         map
     }
 
+    /// Returns weighted sum of noise maps, so different noises can be stacked together
+    pub fn stack(layers: &[(Noise, f32)], size: usize) -> Vec<f32> {
+        let mut map = vec![0.0; size * size];
+        for (noise, weight) in layers.iter() {
+            for (value, layer_value) in map.iter_mut().zip(noise.map(size)) {
+                *value += layer_value * weight;
+            }
+        }
+        map
+    }
+
     fn randomize_offset(value: f32, pseudo_rng: &mut SmallRng) -> f32 {
         value + (pseudo_rng.next_u32() & 0xFFFF) as f32 - 32768.0
     }
@@ -107,6 +152,8 @@ impl Default for Noise {
             scale: 250.0,
             offset: [0.0, 0.0],
             seed: 0,
+            fractal: Fractal::Fbm,
+            warp: 0.0,
         }
     }
 }
@@ -157,12 +204,379 @@ impl Default for Falloff {
 pub struct Generator {
     /// Amplitude of the heights generation
     pub amplitude: f32,
+    /// Offset added to all the heights, so it is the height of the noise value 0.0
+    ///
+    /// Noise values (reduced by the falloff) are clamped to `0.0..1.0`, so the heights are
+    /// `min_height + amplitude * value`. Negative offset puts the lowest terrain below the sea
+    /// level.
+    pub min_height: f32,
     /// Size of the heightmap
     pub size: usize,
     /// Noisemap values
     pub noise_map: Option<Vec<f32>>,
+    /// Procedural noise values the edits are made over, see [`Generator::set_base`]
+    pub base_map: Option<Vec<f32>>,
     /// Falloff values
     pub falloff_map: Option<Vec<f32>>,
+    /// Holes flags, triangles touching a hole are not generated
+    pub holes: Option<Vec<bool>>,
+    /// Heights were changed and terrain has to be respawned
+    pub dirty: bool,
+    /// Map regions (`[min_x, min_z, max_x, max_z]`, inclusive) changed since the last reset
+    pub dirty_regions: Vec<[usize; 4]>,
+}
+
+impl Generator {
+    /// Returns the lowest and the highest possible heights
+    pub fn elevation_range(&self) -> [f32; 2] {
+        [self.min_height, self.min_height + self.amplitude]
+    }
+
+    /// Sets the lowest and the highest possible heights and marks the generator dirty
+    ///
+    /// Heights can be negative, so ocean floors and canyons go below the sea level. The noise
+    /// values are mapped into the range, the values out of `0.0..1.0` are clamped.
+    pub fn set_elevation_range(&mut self, min: f32, max: f32) {
+        self.min_height = min.min(max);
+        self.amplitude = (max - min).abs();
+        self.dirty = true;
+    }
+
+    /// Keeps the current noise map as the procedural base
+    ///
+    /// Saved regions store the heights as deltas from the base, so it has to be set after the
+    /// procedural steps (noise, erosion, biomes) and before the edits.
+    pub fn set_base(&mut self) {
+        self.base_map = self.noise_map.clone();
+    }
+
+    /// Resamples the maps to the new number of values per side and marks the generator dirty
+    ///
+    /// Holes are always resampled with the nearest filter.
+    pub fn resample(&mut self, new_size: usize, filter: Filter) {
+        let size = self.size;
+        for map in [self.noise_map.as_mut(), self.base_map.as_mut()]
+            .iter_mut()
+            .flatten()
+        {
+            **map = resample::resample(map, size, new_size, filter);
+        }
+        if let Some(falloff_map) = self.falloff_map.as_mut() {
+            *falloff_map = resample::resample(falloff_map, size, new_size, filter);
+        }
+        if let Some(holes) = self.holes.as_mut() {
+            let map = holes
+                .iter()
+                .map(|&hole| hole as u8 as f32)
+                .collect::<Vec<_>>();
+            *holes = resample::resample(&map, size, new_size, Filter::Nearest)
+                .into_iter()
+                .map(|value| value > 0.5)
+                .collect();
+        }
+        self.size = new_size;
+        self.dirty_regions.clear();
+        self.dirty = true;
+    }
+
+    /// Crops the maps to the square of `size` values per side starting at the `x` and `z`
+    ///
+    /// Marks the generator dirty. Returns `false` if the square does not fit into the map.
+    pub fn crop(&mut self, x: usize, z: usize, size: usize) -> bool {
+        let map_size = self.size;
+        if x + size > map_size || z + size > map_size {
+            return false;
+        }
+        for map in [
+            self.noise_map.as_mut(),
+            self.base_map.as_mut(),
+            self.falloff_map.as_mut(),
+        ]
+        .iter_mut()
+        .flatten()
+        {
+            if let Some(cropped) = resample::crop(map, map_size, x, z, size) {
+                **map = cropped;
+            }
+        }
+        if let Some(holes) = self.holes.as_mut() {
+            let mut cropped = Vec::with_capacity(size * size);
+            for cx in x..x + size {
+                let i = cx * map_size + z;
+                cropped.extend_from_slice(&holes[i..i + size]);
+            }
+            *holes = cropped;
+        }
+        self.size = size;
+        self.dirty_regions.clear();
+        self.dirty = true;
+        true
+    }
+
+    /// Applies hydraulic erosion to the noise map and marks the generator dirty
+    pub fn erode_hydraulic(&mut self, erosion: &HydraulicErosion) {
+        if let Some(noise_map) = self.noise_map.as_mut() {
+            erosion.apply(noise_map, self.size);
+            self.dirty = true;
+        }
+    }
+
+    /// Scales and offsets the noise map values by biomes and marks the generator dirty
+    pub fn apply_biomes(&mut self, biomes: &Biomes) {
+        if let Some(noise_map) = self.noise_map.as_mut() {
+            biomes.apply(noise_map, self.size);
+            self.dirty = true;
+        }
+    }
+
+    /// Applies the sculpting brush at the map position
+    ///
+    /// Returns `false` if there is no noise map or the brush does not touch it
+    pub fn sculpt(&mut self, brush: &Brush, x: f32, z: f32) -> bool {
+        let scale = if self.amplitude > 0.0 {
+            1.0 / self.amplitude
+        } else {
+            1.0
+        };
+        let size = self.size;
+        let sculpted = self
+            .noise_map
+            .as_mut()
+            .map(|noise_map| brush.apply(noise_map, size, x, z, scale))
+            .unwrap_or(false);
+        if sculpted {
+            let radius = brush.radius;
+            self.mark_dirty_region([x - radius, z - radius, x + radius, z + radius]);
+        }
+        sculpted
+    }
+
+    /// Flattens the noise map along the spline, `offset` converts world coordinates into map ones
+    ///
+    /// Returns `false` if there is no noise map or the spline does not touch it
+    pub fn carve(&mut self, spline: &Spline, offset: f32) -> bool {
+        let scale = if self.amplitude > 0.0 {
+            1.0 / self.amplitude
+        } else {
+            1.0
+        };
+        let (size, base) = (self.size, self.min_height);
+        let carved = self
+            .noise_map
+            .as_mut()
+            .map(|noise_map| spline.apply(noise_map, size, offset, base, scale))
+            .unwrap_or(false);
+        if let (true, Some(bounds)) = (carved, spline.bounds()) {
+            self.mark_dirty_region([
+                bounds[0] + offset,
+                bounds[1] + offset,
+                bounds[2] + offset,
+                bounds[3] + offset,
+            ]);
+        }
+        carved
+    }
+
+    /// Blends the heightmap stamp into the noise map, `offset` converts world coordinates into
+    /// map ones
+    ///
+    /// Returns `false` if there is no noise map or the stamp does not touch it
+    pub fn stamp(&mut self, stamp: &Stamp, offset: f32) -> bool {
+        let scale = if self.amplitude > 0.0 {
+            1.0 / self.amplitude
+        } else {
+            1.0
+        };
+        let (size, base) = (self.size, self.min_height);
+        let stamped = self
+            .noise_map
+            .as_mut()
+            .map(|noise_map| stamp.apply(noise_map, size, offset, base, scale))
+            .unwrap_or(false);
+        if stamped {
+            let bounds = stamp.bounds();
+            self.mark_dirty_region([
+                bounds[0] + offset,
+                bounds[1] + offset,
+                bounds[2] + offset,
+                bounds[3] + offset,
+            ]);
+        }
+        stamped
+    }
+
+    /// Marks the map region (`[min_x, min_z, max_x, max_z]`) as changed, clipped by the map
+    pub fn mark_dirty_region(&mut self, region: [f32; 4]) {
+        if self.size == 0 {
+            return;
+        }
+        let max = (self.size - 1) as f32;
+        let from_x = region[0].floor().max(0.0);
+        let from_z = region[1].floor().max(0.0);
+        let to_x = region[2].ceil().min(max);
+        let to_z = region[3].ceil().min(max);
+        if from_x > to_x || from_z > to_z {
+            return;
+        }
+        self.push_dirty_region([
+            from_x as usize,
+            from_z as usize,
+            to_x as usize,
+            to_z as usize,
+        ]);
+    }
+
+    /// Stores the changed region, merging it with the last one if they touch each other
+    fn push_dirty_region(&mut self, region: [usize; 4]) {
+        if let Some(last) = self.dirty_regions.last_mut() {
+            let touches = region[0] <= last[2] + 1
+                && region[2] + 1 >= last[0]
+                && region[1] <= last[3] + 1
+                && region[3] + 1 >= last[1];
+            if touches {
+                *last = [
+                    last[0].min(region[0]),
+                    last[1].min(region[1]),
+                    last[2].max(region[2]),
+                    last[3].max(region[3]),
+                ];
+                return;
+            }
+        }
+        self.dirty_regions.push(region);
+    }
+
+    /// Marks or unmarks the value at specified X and Z pair as a hole
+    pub fn set_hole(&mut self, x: usize, z: usize, hole: bool) {
+        if x >= self.size || z >= self.size {
+            return;
+        }
+        let size = self.size;
+        if self.holes.is_none() {
+            if !hole {
+                return;
+            }
+            self.holes = Some(vec![false; size * size]);
+        }
+        if let Some(holes) = self.holes.as_mut() {
+            holes[x * size + z] = hole;
+        }
+        self.push_dirty_region([x, z, x, z]);
+    }
+
+    /// Returns a copy of the rectangular region of the map, clipped by the map bounds
+    ///
+    /// Heights are the deltas from the procedural base, the splat weights are not set.
+    pub fn region(&self, x: usize, z: usize, size_x: usize, size_z: usize) -> Option<Region> {
+        let noise_map = self.noise_map.as_ref()?;
+        if x >= self.size || z >= self.size {
+            return None;
+        }
+        let size_x = size_x.min(self.size - x);
+        let size_z = size_z.min(self.size - z);
+        let mut heights = Vec::with_capacity(size_x * size_z);
+        let mut holes = Vec::new();
+        for rx in x..x + size_x {
+            let i = rx * self.size + z;
+            let base = self
+                .base_map
+                .as_ref()
+                .and_then(|base_map| base_map.get(i..i + size_z));
+            heights.extend(
+                noise_map[i..i + size_z]
+                    .iter()
+                    .enumerate()
+                    .map(|(j, value)| value - base.map(|base| base[j]).unwrap_or(0.0)),
+            );
+            if let Some(map_holes) = self.holes.as_ref() {
+                holes.extend_from_slice(&map_holes[i..i + size_z]);
+            }
+        }
+        if !holes.iter().any(|&hole| hole) {
+            holes.clear();
+        }
+        Some(Region {
+            x,
+            z,
+            size_x,
+            size_z,
+            heights,
+            holes,
+            splat: None,
+        })
+    }
+
+    /// Checks if the region can be written into the map
+    pub(crate) fn validate_region(&self, region: &Region) -> Result<(), RegionError> {
+        if self.noise_map.is_none() {
+            return Err(RegionError::NotSupported);
+        }
+        if region.x + region.size_x > self.size || region.z + region.size_z > self.size {
+            return Err(RegionError::OutOfBounds);
+        }
+        Ok(())
+    }
+
+    /// Writes the region values into the map, adding the height deltas to the procedural base
+    ///
+    /// The region is marked as changed.
+    pub fn set_region(&mut self, region: &Region) -> Result<(), RegionError> {
+        self.validate_region(region)?;
+        let size = self.size;
+        let noise_map = self.noise_map.as_mut().ok_or(RegionError::NotSupported)?;
+        for rx in 0..region.size_x {
+            let i = (region.x + rx) * size + region.z;
+            let j = rx * region.size_z;
+            for rz in 0..region.size_z {
+                let base = self
+                    .base_map
+                    .as_ref()
+                    .and_then(|base_map| base_map.get(i + rz).copied())
+                    .unwrap_or(0.0);
+                noise_map[i + rz] = base + region.heights[j + rz];
+            }
+        }
+        if !region.holes.is_empty() || self.holes.is_some() {
+            let holes = self.holes.get_or_insert_with(|| vec![false; size * size]);
+            for rx in 0..region.size_x {
+                let i = (region.x + rx) * size + region.z;
+                let j = rx * region.size_z;
+                if region.holes.is_empty() {
+                    holes[i..i + region.size_z].fill(false);
+                } else {
+                    holes[i..i + region.size_z]
+                        .copy_from_slice(&region.holes[j..j + region.size_z]);
+                }
+            }
+        }
+        if region.size_x > 0 && region.size_z > 0 {
+            self.push_dirty_region([
+                region.x,
+                region.z,
+                region.x + region.size_x - 1,
+                region.z + region.size_z - 1,
+            ]);
+        }
+        Ok(())
+    }
+
+    /// Applies thermal erosion to the noise map and marks the generator dirty
+    ///
+    /// Material slides down from the slopes steeper than `talus_angle` (in radians) until the
+    /// slope settles or the number of `iterations` is reached.
+    pub fn erode_thermal(&mut self, iterations: usize, talus_angle: f32) {
+        if let Some(noise_map) = self.noise_map.as_mut() {
+            let amplitude = if self.amplitude > 0.0 {
+                self.amplitude
+            } else {
+                1.0
+            };
+            // world unit per map cell, so heights difference has to be scaled to the noise range
+            let talus = talus_angle.tan() / amplitude;
+            erosion::thermal(noise_map, self.size, iterations, talus);
+            self.dirty = true;
+        }
+    }
 }
 
 impl Heightmap for Generator {
@@ -181,7 +595,7 @@ impl Heightmap for Generator {
                         value -= falloff_map[i];
                     }
                 }
-                self.amplitude * num::clamp(value, 0.0, 1.0)
+                self.min_height + self.amplitude * num::clamp(value, 0.0, 1.0)
             })
             .unwrap_or(0.0)
     }
@@ -189,4 +603,118 @@ impl Heightmap for Generator {
     fn size(&self) -> usize {
         self.size
     }
+
+    fn is_hole(&self, x: usize, z: usize) -> bool {
+        self.holes
+            .as_ref()
+            .and_then(|holes| holes.get(x * self.size + z).copied())
+            .unwrap_or(false)
+    }
+
+    fn dirty(&self) -> bool {
+        self.dirty
+    }
+
+    fn set_dirty(&mut self, dirty: bool) {
+        self.dirty = dirty;
+        if !dirty {
+            self.dirty_regions.clear();
+        }
+    }
+
+    fn dirty_regions(&self) -> Vec<[usize; 4]> {
+        self.dirty_regions.clone()
+    }
+
+    fn clear_dirty_regions(&mut self) {
+        self.dirty_regions.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elevation_range_maps_clamped_noise() {
+        let size = 2;
+        let mut generator = Generator {
+            noise_map: Some(vec![0.5, 0.0, 1.5, -0.5]),
+            size,
+            ..Default::default()
+        };
+        generator.set_elevation_range(50.0, -50.0);
+        assert_eq!(generator.elevation_range(), [-50.0, 50.0]);
+        assert!(generator.dirty());
+
+        assert_eq!(generator.value(0, 0), 0.0);
+        assert_eq!(generator.value(0, 1), -50.0);
+        assert_eq!(generator.value(1, 0), 50.0);
+        assert_eq!(generator.value(1, 1), -50.0);
+    }
+
+    #[test]
+    fn holes_are_stored_inside_the_map() {
+        let size = 4;
+        let mut generator = Generator {
+            noise_map: Some(vec![0.0; size * size]),
+            size,
+            ..Default::default()
+        };
+        generator.set_hole(1, 2, false);
+        assert!(generator.holes.is_none());
+
+        generator.set_hole(1, 2, true);
+        generator.set_hole(4, 0, true);
+        generator.set_hole(0, 4, true);
+        assert!(generator.is_hole(1, 2));
+        assert!(!generator.is_hole(2, 1));
+        assert_eq!(
+            generator
+                .holes
+                .as_ref()
+                .map(|holes| holes.iter().filter(|&&h| h).count()),
+            Some(1)
+        );
+
+        generator.set_hole(1, 2, false);
+        assert!(!generator.is_hole(1, 2));
+    }
+
+    #[test]
+    fn noise_map_depends_on_seed_fractal_and_warp() {
+        let size = 16;
+        let noise = Noise {
+            scale: 8.0,
+            ..Default::default()
+        };
+        let map = noise.map(size);
+        assert_eq!(map.len(), size * size);
+        assert_eq!(map, noise.map(size));
+
+        let other_seed = Noise { seed: 1, ..noise };
+        assert_ne!(map, other_seed.map(size));
+        for fractal in [Fractal::Ridged, Fractal::Billow] {
+            assert_ne!(map, Noise { fractal, ..noise }.map(size));
+        }
+        let warped = Noise { warp: 4.0, ..noise };
+        assert_ne!(map, warped.map(size));
+        assert_eq!(warped.map(size), warped.map(size));
+    }
+
+    #[test]
+    fn stacked_noise_is_weighted_sum() {
+        let size = 8;
+        let noise = Noise {
+            scale: 4.0,
+            ..Default::default()
+        };
+        let map = noise.map(size);
+        assert_eq!(Noise::stack(&[(noise, 1.0)], size), map);
+
+        let stacked = Noise::stack(&[(noise, 0.25), (noise, 0.5)], size);
+        for (value, expected) in stacked.iter().zip(map.iter()) {
+            assert!((value - expected * 0.75).abs() < 1e-6);
+        }
+    }
 }
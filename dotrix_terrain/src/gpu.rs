@@ -0,0 +1,148 @@
+use dotrix_core::renderer::{StorageBuffer, UniformBuffer};
+use dotrix_core::{Pipeline, Renderer};
+
+use crate::{Fractal, Noise};
+
+/// Name of the render graph pass generating the tiles heights on GPU
+pub const GENERATOR_PASS: &str = "terrain_generator";
+
+/// Name of the tiles vertices buffer produced by the [`GENERATOR_PASS`]
+pub const TILES_BUFFER: &str = "dotrix::terrain::tiles";
+
+/// Settings of the tiles heights generation on GPU
+///
+/// Heights and normals of the tiles are calculated by a compute shader and consumed directly by
+/// the terrain vertex shader, so CPU only builds flat grids. The GPU noise is not bit exact with
+/// the CPU [`Noise`] implementation, heights of the heightmap are ignored.
+#[derive(Clone, Copy, PartialEq)]
+pub struct GpuGenerator {
+    /// Noise configuration
+    pub noise: Noise,
+    /// Amplitude of the heights generation
+    pub amplitude: f32,
+    /// Offset added to all the heights, see [`crate::Generator::min_height`]
+    pub min_height: f32,
+}
+
+impl Default for GpuGenerator {
+    fn default() -> Self {
+        Self {
+            noise: Noise::default(),
+            amplitude: 100.0,
+            min_height: 0.0,
+        }
+    }
+}
+
+/// GPU data of the terrain tile
+pub struct GpuTile {
+    /// Generation parameters uniform
+    pub params: UniformBuffer,
+    /// Generated normals and heights
+    pub vertices: StorageBuffer,
+    /// Generation compute pipeline
+    pub pipeline: Pipeline,
+    /// Generation was dispatched
+    pub computed: bool,
+}
+
+impl GpuTile {
+    /// Loads generation parameters and allocates the vertices buffer
+    pub(crate) fn load(
+        &mut self,
+        renderer: &Renderer,
+        generator: &GpuGenerator,
+        tile: [f32; 3],
+        vertices_per_side: u32,
+    ) {
+        let params = Params::new(generator, tile, vertices_per_side);
+        renderer.load_uniform_buffer(&mut self.params, bytemuck::cast_slice(&[params]));
+
+        let vertices = vec![[0.0_f32; 4]; (vertices_per_side * vertices_per_side) as usize];
+        renderer.load_storage_buffer(&mut self.vertices, bytemuck::cast_slice(&vertices));
+    }
+}
+
+impl Default for GpuTile {
+    fn default() -> Self {
+        Self {
+            params: UniformBuffer::default(),
+            vertices: StorageBuffer::new_readwrite(),
+            pipeline: Pipeline::default().with_pass(GENERATOR_PASS),
+            computed: false,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+struct Params {
+    tile: [f32; 4],
+    noise: [f32; 4],
+    offset: [f32; 4],
+    size: [u32; 4],
+}
+
+impl Params {
+    fn new(generator: &GpuGenerator, tile: [f32; 3], vertices_per_side: u32) -> Self {
+        let noise = &generator.noise;
+        Self {
+            tile: [tile[0], tile[1], tile[2], generator.amplitude],
+            noise: [noise.scale, noise.persistence, noise.lacunarity, noise.warp],
+            offset: [noise.offset[0], noise.offset[1], generator.min_height, 0.0],
+            size: [
+                vertices_per_side,
+                noise.octaves as u32,
+                noise.seed,
+                match noise.fractal {
+                    Fractal::Fbm => 0,
+                    Fractal::Ridged => 1,
+                    Fractal::Billow => 2,
+                },
+            ],
+        }
+    }
+}
+
+unsafe impl bytemuck::Zeroable for Params {}
+unsafe impl bytemuck::Pod for Params {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_pass_heights_offset() {
+        let generator = GpuGenerator {
+            amplitude: 40.0,
+            min_height: -15.0,
+            ..Default::default()
+        };
+        let params = Params::new(&generator, [8.0, -8.0, 2.0], 33);
+        assert_eq!(params.tile, [8.0, -8.0, 2.0, 40.0]);
+        assert_eq!(params.offset[2], -15.0);
+        assert_eq!(params.size[0], 33);
+    }
+
+    #[test]
+    fn params_pass_noise_settings() {
+        let generator = GpuGenerator {
+            noise: Noise {
+                octaves: 5,
+                seed: 7,
+                fractal: Fractal::Billow,
+                warp: 0.5,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let noise = &generator.noise;
+        let params = Params::new(&generator, [0.0, 0.0, 1.0], 17);
+        assert_eq!(
+            params.noise,
+            [noise.scale, noise.persistence, noise.lacunarity, 0.5]
+        );
+        assert_eq!(params.size, [17, 5, 7, 2]);
+        assert_eq!(&params.offset[0..2], &noise.offset[..]);
+    }
+}
@@ -1,8 +1,24 @@
-use dotrix_core::renderer::UniformBuffer;
-use dotrix_core::{Color, Renderer};
+use dotrix_core::assets::Texture;
+use dotrix_core::renderer::{StorageTextureAccess, TextureBuffer, TextureFormat, UniformBuffer};
+use dotrix_core::{Assets, Color, Id, Renderer};
+use dotrix_math::Vec3;
+
+use crate::{Biomes, RegionError, SplatWeights};
 
 pub const MAX_LAYERS: usize = 16;
 
+/// Maximal width of the layers textures atlas
+const MAX_ATLAS_WIDTH: usize = 8192;
+
+/// Number of the splat map cells, each one keeps weights of 4 layers
+const SPLAT_CELLS: u32 = (MAX_LAYERS / 4) as u32;
+
+/// Atlas, layer texture getter and neutral color of the atlas cell
+type AtlasKind<'a> = (&'a mut Texture, fn(&Layer) -> Id<Texture>, [u8; 4]);
+
+/// Height, where the layers height base reaches 1.0
+pub const MAX_HEIGHT: f32 = 300.0;
+
 /// Terrain layer
 pub struct Layer {
     /// Terrain layer color
@@ -11,6 +27,24 @@ pub struct Layer {
     pub height: f32,
     /// Terrain layer blend
     pub blend: f32,
+    /// Project the texture along the world axes, so it does not stretch on steep slopes
+    pub triplanar: bool,
+    /// Detail textures repeat size in world units, 0.0 repeats them twice per tile
+    pub tiling: f32,
+    /// Macro variation texture repeat size in world units
+    pub macro_tiling: f32,
+    /// Detail albedo texture
+    pub albedo: Id<Texture>,
+    /// Detail normal map in tangent space
+    pub normal_map: Id<Texture>,
+    /// Detail roughness map (red channel)
+    pub roughness_map: Id<Texture>,
+    /// Far distance variation texture, multiplies the detail color
+    pub macro_texture: Id<Texture>,
+    /// Roughness of the layer, multiplies the roughness map
+    pub roughness: f32,
+    /// Metallic (reflectance) of the layer
+    pub metallic: f32,
 }
 
 impl Default for Layer {
@@ -19,36 +53,444 @@ impl Default for Layer {
             color: Color::rgb(0.18, 0.62, 0.24),
             height: -1.0,
             blend: 0.1,
+            triplanar: false,
+            tiling: 0.0,
+            macro_tiling: 512.0,
+            albedo: Id::default(),
+            normal_map: Id::default(),
+            roughness_map: Id::default(),
+            macro_texture: Id::default(),
+            roughness: 1.0,
+            metallic: 0.0,
         }
     }
 }
 
 /// Terrain layers container
-#[derive(Default)]
 pub struct Layers {
     /// List of terrain layers
     pub list: Vec<Layer>,
+    /// Strength of the baked ambient occlusion (0.0..1.0)
+    pub ambient_occlusion: f32,
+    /// Texture repeat size in world units for the triplanar layers
+    pub triplanar_scale: f32,
+    /// Camera distance where the macro variation starts to fade in and where it is full
+    pub macro_distance: [f32; 2],
+    /// Atlas of the layers albedo textures
+    pub albedo_atlas: Texture,
+    /// Atlas of the layers normal maps
+    pub normal_atlas: Texture,
+    /// Atlas of the layers roughness maps
+    pub roughness_atlas: Texture,
+    /// Atlas of the layers macro variation textures
+    pub macro_atlas: Texture,
+    /// Painted weights of the layers, 4 cells side by side with a layer per color channel
+    pub splat_map: Texture,
+    /// World region covered by the splat map: `[min_x, min_z, max_x, max_z]`
+    pub splat_region: [f32; 4],
+    /// Layers were changed and have to be reloaded with the atlases rebuilt
+    pub changed: bool,
     /// Layers uniform buffer
     pub uniform: UniformBuffer,
 }
 
 impl Layers {
-    /// Loads layers uniform into GPU
+    /// Loads layers uniform and textures atlases into GPU
     pub fn load(&mut self, renderer: &Renderer) {
-        renderer.load_uniform_buffer(
-            &mut self.uniform,
-            bytemuck::cast_slice(&[Uniform::from(self.list.as_slice())]),
-        );
+        let mut uniform = Uniform::from(self.list.as_slice());
+        uniform.ambient_occlusion = self.ambient_occlusion;
+        uniform.triplanar_scale = self.triplanar_scale;
+        uniform.macro_distance = self.macro_distance;
+        uniform.splat_region = self.splat_region;
+        renderer.load_uniform_buffer(&mut self.uniform, bytemuck::cast_slice(&[uniform]));
+
+        for atlas in [
+            &mut self.albedo_atlas,
+            &mut self.normal_atlas,
+            &mut self.roughness_atlas,
+            &mut self.macro_atlas,
+            &mut self.splat_map,
+        ] {
+            atlas.load(renderer);
+        }
+    }
+
+    /// Allocates empty splat map of the resolution per layer, covering the world region
+    ///
+    /// Region is `[min_x, min_z, max_x, max_z]` in world units.
+    pub fn set_splat_map(&mut self, region: [f32; 4], resolution: u32) {
+        let resolution = resolution.max(1);
+        self.splat_map.width = resolution * SPLAT_CELLS;
+        self.splat_map.height = resolution;
+        self.splat_map.data = vec![0; (self.splat_map.width * resolution * 4) as usize];
+        self.splat_map.changed = true;
+        self.splat_region = region;
+        self.changed = true;
+    }
+
+    /// Paints the layer weight around the world position with a soft brush
+    ///
+    /// Negative strength erases the layer. Painted weight overrides the height based blending.
+    /// The splat map is uploaded lazily by the `layers` system. Returns `false` if the splat map
+    /// was not allocated or the position is out of its region.
+    pub fn paint(
+        &mut self,
+        world_pos: Vec3,
+        radius: f32,
+        layer_index: usize,
+        strength: f32,
+    ) -> bool {
+        let [min_x, min_z, max_x, max_z] = self.splat_region;
+        if layer_index >= MAX_LAYERS || max_x <= min_x || max_z <= min_z {
+            return false;
+        }
+        let resolution = self.splat_map.height as usize;
+        let width = self.splat_map.width as usize;
+        if resolution < 2 || self.splat_map.data.len() < width * resolution * 4 {
+            return false;
+        }
+
+        let texel = [
+            (max_x - min_x) / (resolution - 1) as f32,
+            (max_z - min_z) / (resolution - 1) as f32,
+        ];
+        let to_texel =
+            |value: f32, min: f32, size: f32| -> i32 { ((value - min) / size).round() as i32 };
+        let radius = radius.max(texel[0].min(texel[1]) / 2.0);
+        let from = [
+            to_texel(world_pos.x - radius, min_x, texel[0]).max(0),
+            to_texel(world_pos.z - radius, min_z, texel[1]).max(0),
+        ];
+        let to = [
+            to_texel(world_pos.x + radius, min_x, texel[0]).min(resolution as i32 - 1),
+            to_texel(world_pos.z + radius, min_z, texel[1]).min(resolution as i32 - 1),
+        ];
+        if from[0] > to[0] || from[1] > to[1] {
+            return false;
+        }
+
+        let cell = layer_index / 4;
+        let channel = layer_index % 4;
+        for y in from[1]..=to[1] {
+            for x in from[0]..=to[0] {
+                let dx = min_x + x as f32 * texel[0] - world_pos.x;
+                let dz = min_z + y as f32 * texel[1] - world_pos.z;
+                let distance = (dx * dx + dz * dz).sqrt();
+                if distance > radius {
+                    continue;
+                }
+                let falloff = 1.0 - distance / radius;
+                let offset = (y as usize * width + cell * resolution + x as usize) * 4 + channel;
+                let value = self.splat_map.data[offset] as f32 / 255.0 + strength * falloff;
+                self.splat_map.data[offset] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+        self.splat_map.changed = true;
+        true
+    }
+
+    /// Returns painted weight of the layer at the world position
+    pub fn splat_weight(&self, world_pos: Vec3, layer_index: usize) -> f32 {
+        let [min_x, min_z, max_x, max_z] = self.splat_region;
+        let resolution = self.splat_map.height as usize;
+        if layer_index >= MAX_LAYERS || resolution < 2 || max_x <= min_x || max_z <= min_z {
+            return 0.0;
+        }
+        let u = (world_pos.x - min_x) / (max_x - min_x);
+        let v = (world_pos.z - min_z) / (max_z - min_z);
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return 0.0;
+        }
+        let x = (u * (resolution - 1) as f32).round() as usize;
+        let y = (v * (resolution - 1) as f32).round() as usize;
+        let offset = (y * self.splat_map.width as usize + (layer_index / 4) * resolution + x) * 4
+            + layer_index % 4;
+        self.splat_map
+            .data
+            .get(offset)
+            .map(|value| *value as f32 / 255.0)
+            .unwrap_or(0.0)
+    }
+
+    /// Paints the base texture layers of the biomes into the splat map, blended at the borders
+    ///
+    /// Heightmap of `map_size` values per side is centered at the world origin. Painted weights
+    /// of the other layers are erased. Returns `false` if the splat map was not allocated.
+    pub fn apply_biomes(&mut self, biomes: &Biomes, map_size: usize) -> bool {
+        let [min_x, min_z, max_x, max_z] = self.splat_region;
+        let resolution = self.splat_map.height as usize;
+        let width = self.splat_map.width as usize;
+        if resolution < 2
+            || max_x <= min_x
+            || max_z <= min_z
+            || self.splat_map.data.len() < width * resolution * 4
+        {
+            return false;
+        }
+        let half_world_size = (map_size.saturating_sub(1) / 2) as f32;
+        let texel = [
+            (max_x - min_x) / (resolution - 1) as f32,
+            (max_z - min_z) / (resolution - 1) as f32,
+        ];
+        for y in 0..resolution {
+            for x in 0..resolution {
+                let map_x = min_x + x as f32 * texel[0] + half_world_size;
+                let map_z = min_z + y as f32 * texel[1] + half_world_size;
+                let weights = biomes.layer_weights(map_x, map_z);
+                for layer_index in 0..MAX_LAYERS {
+                    let weight = weights
+                        .iter()
+                        .filter(|(layer, _)| *layer == layer_index)
+                        .map(|(_, weight)| weight)
+                        .sum::<f32>();
+                    let offset =
+                        (y * width + (layer_index / 4) * resolution + x) * 4 + layer_index % 4;
+                    self.splat_map.data[offset] = (weight.clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+            }
+        }
+        self.splat_map.changed = true;
+        true
+    }
+
+    /// Returns painted weights of the splat map texels covering the world region
+    ///
+    /// Region is `[min_x, min_z]` and `[max_x, max_z]` in world units. Returns `None` if the
+    /// splat map was not allocated or nothing was painted in the region.
+    pub fn splat_weights(&self, min: [f32; 2], max: [f32; 2]) -> Option<SplatWeights> {
+        let [region_min_x, region_min_z, region_max_x, region_max_z] = self.splat_region;
+        let resolution = self.splat_map.height as usize;
+        if resolution < 2 || region_max_x <= region_min_x || region_max_z <= region_min_z {
+            return None;
+        }
+        let last = (resolution - 1) as f32;
+        let to_texel = |value: f32, min: f32, max: f32| -> f32 {
+            ((value - min) / (max - min) * last).clamp(0.0, last)
+        };
+        let from = [
+            to_texel(min[0], region_min_x, region_max_x).floor() as usize,
+            to_texel(min[1], region_min_z, region_max_z).floor() as usize,
+        ];
+        let to = [
+            to_texel(max[0], region_min_x, region_max_x).ceil() as usize,
+            to_texel(max[1], region_min_z, region_max_z).ceil() as usize,
+        ];
+        if from[0] > to[0] || from[1] > to[1] {
+            return None;
+        }
+
+        let width = self.splat_map.width as usize;
+        let texels = (to[0] - from[0] + 1) * (to[1] - from[1] + 1);
+        let mut weights = Vec::with_capacity(texels * MAX_LAYERS);
+        for x in from[0]..=to[0] {
+            for z in from[1]..=to[1] {
+                for layer_index in 0..MAX_LAYERS {
+                    let offset =
+                        (z * width + (layer_index / 4) * resolution + x) * 4 + layer_index % 4;
+                    weights.push(self.splat_map.data.get(offset).copied().unwrap_or(0));
+                }
+            }
+        }
+        if weights.iter().all(|&weight| weight == 0) {
+            return None;
+        }
+        Some(SplatWeights {
+            x: from[0],
+            z: from[1],
+            size_x: to[0] - from[0] + 1,
+            size_z: to[1] - from[1] + 1,
+            weights,
+        })
+    }
+
+    /// Checks if the painted weights fit into the splat map
+    pub(crate) fn validate_splat_weights(&self, splat: &SplatWeights) -> Result<(), RegionError> {
+        let resolution = self.splat_map.height as usize;
+        let width = self.splat_map.width as usize;
+        if resolution < 2 || self.splat_map.data.len() < width * resolution * 4 {
+            return Err(RegionError::NotSupported);
+        }
+        if splat.x + splat.size_x > resolution || splat.z + splat.size_z > resolution {
+            return Err(RegionError::OutOfBounds);
+        }
+        if splat.weights.len() != splat.size_x * splat.size_z * MAX_LAYERS {
+            return Err(RegionError::Corruption("Wrong number of splat weights"));
+        }
+        Ok(())
+    }
+
+    /// Writes painted weights into the splat map
+    pub fn set_splat_weights(&mut self, splat: &SplatWeights) -> Result<(), RegionError> {
+        self.validate_splat_weights(splat)?;
+        let resolution = self.splat_map.height as usize;
+        let width = self.splat_map.width as usize;
+        for (i, texel) in splat.weights.chunks(MAX_LAYERS).enumerate() {
+            let x = splat.x + i / splat.size_z;
+            let z = splat.z + i % splat.size_z;
+            for (layer_index, &weight) in texel.iter().enumerate() {
+                let offset = (z * width + (layer_index / 4) * resolution + x) * 4 + layer_index % 4;
+                self.splat_map.data[offset] = weight;
+            }
+        }
+        self.splat_map.changed = true;
+        Ok(())
+    }
+
+    /// Packs the layers textures into atlases, one square cell per layer
+    ///
+    /// Textures are resampled to the size of the largest one, layers without a texture get a
+    /// neutral cell. Returns `false` if some of the textures are not available yet.
+    pub fn build_atlases(&mut self, assets: &Assets) -> bool {
+        let list = &self.list[0..self.list.len().min(MAX_LAYERS)];
+        let mut kinds: [AtlasKind; 4] = [
+            (&mut self.albedo_atlas, |layer| layer.albedo, [255; 4]),
+            (
+                &mut self.normal_atlas,
+                |layer| layer.normal_map,
+                [128, 128, 255, 255],
+            ),
+            (
+                &mut self.roughness_atlas,
+                |layer| layer.roughness_map,
+                [255; 4],
+            ),
+            (&mut self.macro_atlas, |layer| layer.macro_texture, [255; 4]),
+        ];
+
+        let mut textures = Vec::with_capacity(kinds.len());
+        for (_, id, _) in kinds.iter() {
+            let mut kind_textures = Vec::with_capacity(list.len());
+            for layer in list.iter() {
+                let id = id(layer);
+                if id.is_null() {
+                    kind_textures.push(None);
+                    continue;
+                }
+                match assets.get(id) {
+                    Some(texture) if !texture.data.is_empty() => kind_textures.push(Some(texture)),
+                    _ => return false,
+                }
+            }
+            textures.push(kind_textures);
+        }
+
+        for ((atlas, _, neutral), kind_textures) in kinds.iter_mut().zip(textures) {
+            let data = pack_atlas(&kind_textures, *neutral);
+            atlas.width = data.0 as u32 * kind_textures.len().max(1) as u32;
+            atlas.height = data.0 as u32;
+            atlas.data = data.1;
+            atlas.changed = true;
+        }
+        true
+    }
+
+    /// Returns blended color of the layers at the height, the same way as the terrain shader
+    pub fn color_at(&self, height: f32) -> [f32; 4] {
+        let epsilon = 0.0001;
+        let height_percent = (height / MAX_HEIGHT).clamp(0.0, 1.0);
+        let mut color = [1.0; 4];
+        for layer in self.list.iter().take(MAX_LAYERS) {
+            let half_blend = layer.blend / 2.0;
+            let strength = ((height_percent - layer.height + half_blend + epsilon)
+                / (2.0 * half_blend + epsilon))
+                .clamp(0.0, 1.0);
+            let layer_color: [f32; 4] = layer.color.into();
+            for (value, layer_value) in color.iter_mut().zip(layer_color.iter()) {
+                *value = *value * (1.0 - strength) + layer_value * strength;
+            }
+        }
+        color
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self {
+            list: Vec::new(),
+            ambient_occlusion: 1.0,
+            triplanar_scale: 32.0,
+            macro_distance: [64.0, 256.0],
+            albedo_atlas: atlas([255; 4], true),
+            normal_atlas: atlas([128, 128, 255, 255], false),
+            roughness_atlas: atlas([255; 4], false),
+            macro_atlas: atlas([255; 4], true),
+            splat_map: Texture {
+                width: SPLAT_CELLS,
+                data: vec![0; SPLAT_CELLS as usize * 4],
+                ..atlas([0; 4], false)
+            },
+            splat_region: [0.0; 4],
+            changed: false,
+            uniform: UniformBuffer::default(),
+        }
+    }
+}
+
+/// Constructs 1x1 atlas of the neutral color
+fn atlas(color: [u8; 4], srgb: bool) -> Texture {
+    let format = if srgb {
+        TextureFormat::rgba_u8norm_srgb()
+    } else {
+        TextureFormat::rgba_u8norm()
+    };
+    Texture {
+        width: 1,
+        height: 1,
+        depth: 1,
+        data: color.to_vec(),
+        buffer: TextureBuffer::new(StorageTextureAccess::Read, format),
+        ..Default::default()
     }
 }
 
+/// Returns size of the cell and RGBA data of the atlas with textures placed side by side
+fn pack_atlas(textures: &[Option<&Texture>], neutral: [u8; 4]) -> (usize, Vec<u8>) {
+    let count = textures.len().max(1);
+    let cell = textures
+        .iter()
+        .flatten()
+        .map(|texture| texture.width.max(texture.height) as usize)
+        .max()
+        .unwrap_or(1)
+        .min(MAX_ATLAS_WIDTH / count)
+        .max(1);
+    let width = cell * count;
+    let mut data = vec![0; width * cell * 4];
+    for i in 0..count {
+        let texture = textures.get(i).copied().flatten();
+        for y in 0..cell {
+            for x in 0..cell {
+                let offset = (y * width + i * cell + x) * 4;
+                let pixel = match texture {
+                    Some(texture) => {
+                        let (texture_width, texture_height) =
+                            (texture.width as usize, texture.height as usize);
+                        let tx = x * texture_width / cell;
+                        let ty = y * texture_height / cell;
+                        let j = (ty * texture_width + tx) * 4;
+                        texture.data.get(j..j + 4).unwrap_or(&neutral)
+                    }
+                    None => &neutral,
+                };
+                data[offset..offset + 4].copy_from_slice(pixel);
+            }
+        }
+    }
+    (cell, data)
+}
+
 #[repr(C)]
 #[derive(Default, Debug, Clone, Copy)]
 struct LayerUniform {
     color: [f32; 4],
     height: f32,
     blend: f32,
-    unused: [u32; 2],
+    triplanar: u32,
+    tiling: f32,
+    macro_tiling: f32,
+    roughness: f32,
+    metallic: f32,
+    unused: u32,
 }
 
 unsafe impl bytemuck::Zeroable for LayerUniform {}
@@ -58,7 +500,12 @@ unsafe impl bytemuck::Pod for LayerUniform {}
 #[derive(Default, Debug, Clone, Copy)]
 struct Uniform {
     count: u32,
-    unused: [u32; 3],
+    ambient_occlusion: f32,
+    triplanar_scale: f32,
+    unused: u32,
+    macro_distance: [f32; 2],
+    unused_2: [u32; 2],
+    splat_region: [f32; 4],
     layers: [LayerUniform; MAX_LAYERS],
 }
 
@@ -73,7 +520,12 @@ impl From<&[Layer]> for Uniform {
                 color: layer.color.into(),
                 height: layer.height,
                 blend: layer.blend,
-                unused: [0; 2],
+                triplanar: layer.triplanar as u32,
+                tiling: layer.tiling,
+                macro_tiling: layer.macro_tiling,
+                roughness: layer.roughness,
+                metallic: layer.metallic,
+                unused: 0,
             })
             .collect::<Vec<_>>();
 
@@ -81,7 +533,12 @@ impl From<&[Layer]> for Uniform {
 
         Uniform {
             count,
-            unused: [0; 3],
+            ambient_occlusion: 1.0,
+            triplanar_scale: 32.0,
+            unused: 0,
+            macro_distance: [0.0; 2],
+            unused_2: [0; 2],
+            splat_region: [0.0; 4],
             layers: layers.try_into().unwrap(),
         }
     }
@@ -89,3 +546,100 @@ impl From<&[Layer]> for Uniform {
 
 unsafe impl bytemuck::Zeroable for Uniform {}
 unsafe impl bytemuck::Pod for Uniform {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Biome;
+
+    #[test]
+    fn atlas_packs_layers_side_by_side() {
+        let texture = Texture {
+            width: 2,
+            height: 2,
+            depth: 1,
+            data: [[10, 20, 30, 40]; 4].concat(),
+            ..Default::default()
+        };
+        let (cell, data) = pack_atlas(&[None, Some(&texture)], [1, 2, 3, 4]);
+        assert_eq!(cell, 2);
+        assert_eq!(data.len(), 4 * 2 * 4);
+        assert_eq!(&data[0..4], &[1, 2, 3, 4]);
+        assert_eq!(&data[8..12], &[10, 20, 30, 40]);
+        assert_eq!(&data[16..20], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn biomes_paint_their_layers() {
+        let mut layers = Layers::default();
+        let biomes = Biomes {
+            list: vec![Biome {
+                layer: 6,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(!layers.apply_biomes(&biomes, 17));
+
+        layers.set_splat_map([-8.0, -8.0, 8.0, 8.0], 17);
+        assert!(layers.apply_biomes(&biomes, 17));
+        let center = Vec3::new(0.0, 0.0, 0.0);
+        assert_eq!(layers.splat_weight(center, 6), 1.0);
+        assert_eq!(layers.splat_weight(center, 0), 0.0);
+    }
+
+    #[test]
+    fn paint_raises_and_erases_layer_weight() {
+        let mut layers = Layers::default();
+        let center = Vec3::new(0.0, 0.0, 0.0);
+        assert!(!layers.paint(center, 4.0, 5, 1.0));
+
+        layers.set_splat_map([-8.0, -8.0, 8.0, 8.0], 17);
+        assert!(layers.paint(center, 4.0, 5, 1.0));
+        assert!(layers.splat_weight(center, 5) > 0.99);
+        assert!(layers.splat_weight(Vec3::new(2.0, 0.0, 0.0), 5) > 0.4);
+        assert_eq!(layers.splat_weight(Vec3::new(6.0, 0.0, 0.0), 5), 0.0);
+        assert_eq!(layers.splat_weight(center, 4), 0.0);
+        assert_eq!(layers.splat_weight(center, 1), 0.0);
+
+        assert!(layers.paint(center, 4.0, 5, -1.0));
+        assert_eq!(layers.splat_weight(center, 5), 0.0);
+        assert!(!layers.paint(Vec3::new(100.0, 0.0, 0.0), 4.0, 5, 1.0));
+    }
+
+    #[test]
+    fn uniform_packs_triplanar_layers() {
+        let layers = [
+            Layer::default(),
+            Layer {
+                triplanar: true,
+                ..Default::default()
+            },
+        ];
+        let uniform = Uniform::from(&layers[..]);
+        assert_eq!(uniform.count, 2);
+        assert_eq!(uniform.layers[0].triplanar, 0);
+        assert_eq!(uniform.layers[1].triplanar, 1);
+        assert_eq!(uniform.layers[2].triplanar, 0);
+        // WGSL structs in uniform buffers are aligned to 16 bytes
+        assert_eq!(std::mem::size_of::<LayerUniform>() % 16, 0);
+        assert_eq!(std::mem::size_of::<Uniform>() % 16, 0);
+    }
+
+    #[test]
+    fn uniform_packs_layers_material() {
+        let layers = [
+            Layer {
+                roughness: 0.4,
+                metallic: 0.2,
+                ..Default::default()
+            },
+            Layer::default(),
+        ];
+        let uniform = Uniform::from(&layers[..]);
+        assert_eq!(uniform.layers[0].roughness, 0.4);
+        assert_eq!(uniform.layers[0].metallic, 0.2);
+        assert_eq!(uniform.layers[1].roughness, 1.0);
+        assert_eq!(uniform.layers[1].metallic, 0.0);
+    }
+}
@@ -4,18 +4,65 @@
 
 use std::any::Any;
 
-use dotrix_core::assets::Mesh;
-use dotrix_core::{Application, Id, System};
+use dotrix_core::assets::{Mesh, Texture};
+use dotrix_core::{Application, Frustum, Id, Pipeline, System};
+use dotrix_math::Vec3;
 
+mod ambient_occlusion;
+mod biomes;
+mod brush;
+mod cache;
+mod collider;
+mod combinators;
+mod dem;
+mod erosion;
+mod events;
+mod foliage;
 mod generator;
+mod gpu;
 mod layers;
+mod minimap;
+mod navmesh;
+mod params;
+mod props;
+mod region;
+mod resample;
+mod scatter;
 mod services;
+mod spline;
+mod stamp;
 mod systems;
+mod vec_xz;
+mod voxel;
+mod water;
 
-pub use generator::{Falloff, Generator, Noise};
+pub use ambient_occlusion::AmbientOcclusion;
+pub use biomes::{Biome, Biomes};
+pub use brush::{Brush, Sculpt};
+pub use cache::TileCache;
+pub use collider::HeightFieldData;
+pub use combinators::{Add, Mask, Multiply, Select};
+pub use dem::{Dem, DemError};
+pub use erosion::HydraulicErosion;
+pub use events::{TileEvent, TileEventKind};
+pub use foliage::{Foliage, FoliageBatch, Instance as FoliageInstance};
+pub use generator::{Falloff, Fractal, Generator, Noise};
+pub use gpu::{GpuGenerator, GpuTile, GENERATOR_PASS, TILES_BUFFER};
 pub use layers::{Layer, Layers};
+pub use minimap::Minimap;
+pub use navmesh::NavMesh;
+pub use params::GeneratorParams;
+pub use props::{Prop, PropInstance};
+pub use region::{Region, RegionError, SplatWeights};
+pub use resample::Filter;
+pub use scatter::{Scatter, ScatterPoint};
 pub use services::Terrain;
-pub use systems::{render, spawn, startup};
+pub use spline::Spline;
+pub use stamp::{Stamp, StampMode};
+pub use systems::{compute, invalidate, layers, render, spawn, startup};
+pub use vec_xz::VecXZ;
+pub use voxel::{Contouring, Density, Voxel};
+pub use water::{Water, WaterTile};
 
 /// Terrain tile component
 pub struct Tile {
@@ -27,8 +74,52 @@ pub struct Tile {
     pub lod: usize,
     /// Terrain chunk mesh ID
     pub mesh: Id<Mesh>,
+    /// World space axis aligned bounding box
+    pub bounds: [Vec3; 2],
+    /// Baked normal map texture ID, null if normals are not baked
+    pub normal_map: Id<Texture>,
+    /// Baked ambient occlusion texture ID, null if ambient occlusion is not baked
+    pub ao_map: Id<Texture>,
     /// Is loaded by GPU
     pub loaded: bool,
+    /// Tile is hidden and kept in the pool, so it can be respawned without regeneration
+    pub pooled: bool,
+    /// GPU generation data, if the tile heights are generated on GPU
+    pub gpu: Option<GpuTile>,
+    /// Pipeline rendering the depth in the depth prepass
+    pub prepass: Pipeline,
+}
+
+impl Tile {
+    /// Returns world space center of the tile bounding box
+    pub fn center(&self) -> Vec3 {
+        (self.bounds[0] + self.bounds[1]) / 2.0
+    }
+
+    /// Returns half of the tile side length in world units
+    pub fn half_size(&self) -> f32 {
+        (self.bounds[1].x - self.bounds[0].x) / 2.0
+    }
+
+    /// Checks if the tile bounding box is at least partially inside of the frustum
+    pub fn intersects_frustum(&self, frustum: &Frustum) -> bool {
+        frustum.intersects_aligned_box(self.bounds)
+    }
+
+    /// Checks if the tile bounding box intersects the world space sphere
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        let [min, max] = self.bounds;
+        let dx = (min.x - center.x).max(0.0).max(center.x - max.x);
+        let dy = (min.y - center.y).max(0.0).max(center.y - max.y);
+        let dz = (min.z - center.z).max(0.0).max(center.z - max.z);
+        dx * dx + dy * dy + dz * dz <= radius * radius
+    }
+
+    /// Checks if the world space X and Z pair is covered by the tile
+    pub fn contains(&self, x: f32, z: f32) -> bool {
+        let [min, max] = self.bounds;
+        x >= min.x && x <= max.x && z >= min.z && z <= max.z
+    }
 }
 
 /// Trait for the terrain heights source
@@ -37,6 +128,23 @@ pub trait Heightmap: Any + Sync + Send {
     fn value(&self, x: usize, z: usize) -> f32;
     /// Returns number of values per map side
     fn size(&self) -> usize;
+    /// Returns true if the value at specified X and Z pair is a hole in the terrain
+    fn is_hole(&self, _x: usize, _z: usize) -> bool {
+        false
+    }
+    /// Returns true if heights were changed and terrain has to be respawned
+    fn dirty(&self) -> bool {
+        false
+    }
+    /// Sets or resets the dirty flag
+    fn set_dirty(&mut self, _dirty: bool) {}
+    /// Returns map regions (`[min_x, min_z, max_x, max_z]`, inclusive) changed since the last
+    /// reset, so only the tiles overlapping them have to be regenerated
+    fn dirty_regions(&self) -> Vec<[usize; 4]> {
+        Vec::new()
+    }
+    /// Resets the changed regions
+    fn clear_dirty_regions(&mut self) {}
 }
 
 impl dyn Heightmap {
@@ -76,7 +184,66 @@ impl dyn Heightmap {
 /// Enables the terrain extension in Dotrix application
 pub fn extension(app: &mut Application) {
     app.add_system(System::from(startup));
+    app.add_system(System::from(params::update));
+    app.add_system(System::from(layers));
+    app.add_system(System::from(invalidate));
+    app.add_system(System::from(foliage::startup));
+    app.add_system(System::from(water::startup));
+    app.add_system(System::from(foliage::spawn));
+    app.add_system(System::from(props::spawn));
+    app.add_system(System::from(water::spawn));
+    app.add_system(System::from(minimap::update));
+    app.add_system(System::from(navmesh::update));
     app.add_system(System::from(spawn));
+    app.add_system(System::from(compute));
     app.add_system(System::from(render));
+    app.add_system(System::from(foliage::render));
+    app.add_system(System::from(water::render));
     app.add_service(Terrain::default());
+    app.add_service(GeneratorParams::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dotrix_math::{perspective, Mat4, Point3, Rad};
+
+    fn tile(bounds: [Vec3; 2]) -> Tile {
+        Tile {
+            x: 0,
+            z: 0,
+            lod: 0,
+            mesh: Id::default(),
+            bounds,
+            normal_map: Id::default(),
+            ao_map: Id::default(),
+            loaded: false,
+            pooled: false,
+            gpu: None,
+            prepass: Pipeline::default(),
+        }
+    }
+
+    #[test]
+    fn tile_bounds_queries() {
+        let tile = tile([Vec3::new(-8.0, 0.0, -8.0), Vec3::new(8.0, 4.0, 8.0)]);
+        assert_eq!(tile.center(), Vec3::new(0.0, 2.0, 0.0));
+        assert_eq!(tile.half_size(), 8.0);
+        assert!(tile.contains(8.0, -8.0));
+        assert!(!tile.contains(8.5, 0.0));
+
+        assert!(tile.intersects_sphere(Vec3::new(0.0, 10.0, 0.0), 6.0));
+        assert!(!tile.intersects_sphere(Vec3::new(0.0, 10.0, 0.0), 5.0));
+        // distance to the corner is 3.0 * sqrt(2.0)
+        assert!(!tile.intersects_sphere(Vec3::new(11.0, 2.0, 11.0), 4.0));
+        assert!(tile.intersects_sphere(Vec3::new(11.0, 2.0, 11.0), 4.5));
+
+        let proj = perspective(Rad(1.0), 1.0, 0.1, 100.0);
+        let view =
+            |target: Point3| Mat4::look_at(Point3::new(0.0, 20.0, 20.0), target, Vec3::unit_y());
+        let frustum = Frustum::from_matrix(&(proj * view(Point3::new(0.0, 0.0, 0.0))));
+        assert!(tile.intersects_frustum(&frustum));
+        let frustum = Frustum::from_matrix(&(proj * view(Point3::new(0.0, 20.0, 40.0))));
+        assert!(!tile.intersects_frustum(&frustum));
+    }
 }
@@ -0,0 +1,188 @@
+use dotrix_core::assets::{Assets, Texture};
+use dotrix_core::ecs::{Const, Mut};
+use dotrix_core::{Globals, Id};
+
+use crate::{Layers, Terrain};
+
+/// Direction of the light for the minimap hill shading
+const SUN_DIRECTION: [f32; 3] = [-0.5774, 0.5774, -0.5774];
+
+/// Top-down minimap of the terrain, kept up to date with the terrain changes
+pub struct Minimap {
+    /// Size of the minimap texture side in pixels
+    pub resolution: u32,
+    /// World space region (`[min_x, min_z, max_x, max_z]`) of the minimap
+    pub region: [f32; 4],
+    /// Minimap texture, rendered on the first update if null
+    pub texture: Id<Texture>,
+}
+
+impl Minimap {
+    /// Constructs new minimap of the world space region
+    pub fn new(resolution: u32, region: [f32; 4]) -> Self {
+        Self {
+            resolution,
+            region,
+            texture: Id::default(),
+        }
+    }
+}
+
+impl Terrain {
+    /// Rasterizes heights and layers colors of the world space region into the top-down texture
+    ///
+    /// Texture rows go along Z axis, columns along X axis. Holes are transparent.
+    pub fn render_minimap(&self, layers: &Layers, resolution: u32, region: [f32; 4]) -> Texture {
+        let mut texture = Texture {
+            width: resolution,
+            height: resolution,
+            depth: 1,
+            data: vec![0; resolution as usize * resolution as usize * 4],
+            ..Default::default()
+        };
+        self.update_minimap(&mut texture, layers, region, region);
+        texture
+    }
+
+    /// Redraws pixels of the minimap texture intersecting the world space `dirty` region
+    ///
+    /// Returns `false` if the regions do not intersect.
+    pub fn update_minimap(
+        &self,
+        texture: &mut Texture,
+        layers: &Layers,
+        region: [f32; 4],
+        dirty: [f32; 4],
+    ) -> bool {
+        let (width, height) = (texture.width as usize, texture.height as usize);
+        let size_x = region[2] - region[0];
+        let size_z = region[3] - region[1];
+        if width == 0 || height == 0 || size_x <= 0.0 || size_z <= 0.0 {
+            return false;
+        }
+        let pixel_x = size_x / width as f32;
+        let pixel_z = size_z / height as f32;
+
+        let to_pixel = |value: f32, min: f32, pixel: f32, max: usize| {
+            (((value - min) / pixel).max(0.0) as usize).min(max)
+        };
+        let from_u = to_pixel(dirty[0], region[0], pixel_x, width);
+        let to_u = to_pixel(dirty[2], region[0], pixel_x, width - 1);
+        let from_v = to_pixel(dirty[1], region[1], pixel_z, height);
+        let to_v = to_pixel(dirty[3], region[1], pixel_z, height - 1);
+        if from_u > to_u || from_v > to_v {
+            return false;
+        }
+
+        let size = self.heightmap.size();
+        let half_world_size = (size.saturating_sub(1) / 2) as f32;
+        for v in from_v..=to_v {
+            let z = region[1] + (v as f32 + 0.5) * pixel_z;
+            for u in from_u..=to_u {
+                let x = region[0] + (u as f32 + 0.5) * pixel_x;
+                let map_x = (x + half_world_size).round();
+                let map_z = (z + half_world_size).round();
+                let outside =
+                    map_x < 0.0 || map_z < 0.0 || map_x >= size as f32 || map_z >= size as f32;
+                let i = (v * width + u) * 4;
+                if outside || self.heightmap.is_hole(map_x as usize, map_z as usize) {
+                    texture.data[i..i + 4].copy_from_slice(&[0; 4]);
+                    continue;
+                }
+
+                let y = self.height_at(x, z);
+                let color = layers.color_at(y);
+                let gradient_x = (self.height_at(x + pixel_x, z) - self.height_at(x - pixel_x, z))
+                    / (2.0 * pixel_x);
+                let gradient_z = (self.height_at(x, z + pixel_z) - self.height_at(x, z - pixel_z))
+                    / (2.0 * pixel_z);
+                let length = (gradient_x * gradient_x + 1.0 + gradient_z * gradient_z).sqrt();
+                let light = (-gradient_x * SUN_DIRECTION[0]
+                    + SUN_DIRECTION[1]
+                    + -gradient_z * SUN_DIRECTION[2])
+                    / length;
+                let shade = 0.4 + 0.6 * light.max(0.0);
+
+                for (value, channel) in texture.data[i..i + 3].iter_mut().zip(color.iter()) {
+                    *value = ((channel * shade).clamp(0.0, 1.0) * 255.0) as u8;
+                }
+                texture.data[i + 3] = 255;
+            }
+        }
+        texture.changed = true;
+        true
+    }
+}
+
+/// Minimap update system, redraws only the changed parts of the terrain
+pub fn update(mut terrain: Mut<Terrain>, mut assets: Mut<Assets>, globals: Const<Globals>) {
+    let layers = match globals.get::<Layers>() {
+        Some(layers) => layers,
+        None => return,
+    };
+    let mut minimap = match terrain.minimap.take() {
+        Some(minimap) => minimap,
+        None => return,
+    };
+
+    let texture = if minimap.texture.is_null() {
+        None
+    } else {
+        assets.get_mut(minimap.texture)
+    };
+
+    match texture {
+        Some(texture) => {
+            if terrain.force_spawn || terrain.heightmap.dirty() {
+                terrain.update_minimap(texture, layers, minimap.region, minimap.region);
+            } else {
+                for dirty in terrain.dirty_regions.iter() {
+                    terrain.update_minimap(texture, layers, minimap.region, *dirty);
+                }
+            }
+        }
+        None => {
+            let texture = terrain.render_minimap(layers, minimap.resolution, minimap.region);
+            minimap.texture = assets.store(texture);
+        }
+    }
+
+    terrain.minimap = Some(minimap);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Generator, Layer};
+    use dotrix_core::Color;
+
+    #[test]
+    fn minimap_draws_layers_and_holes() {
+        let size = 33;
+        let mut generator = Generator {
+            amplitude: 1.0,
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            ..Default::default()
+        };
+        generator.set_hole(1, 1, true);
+        let terrain = Terrain::new(Box::new(generator), vec![]);
+        let layers = Layers {
+            list: vec![Layer {
+                color: Color::rgb(1.0, 0.0, 0.0),
+                height: 0.0,
+                blend: 0.0,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let texture = terrain.render_minimap(&layers, 32, [-16.0, -16.0, 16.0, 16.0]);
+        assert_eq!(texture.data.len(), 32 * 32 * 4);
+        assert_eq!(&texture.data[0..4], &[0, 0, 0, 0]);
+        let center = (16 * 32 + 16) * 4;
+        assert!(texture.data[center] > 0);
+        assert_eq!(texture.data[center + 1], 0);
+        assert_eq!(texture.data[center + 3], 255);
+    }
+}
@@ -0,0 +1,343 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use dotrix_core::ecs::Mut;
+use dotrix_math::Vec3;
+
+use crate::Terrain;
+
+/// Walkable grid of the terrain for the AI pathfinding
+///
+/// Cells match the heightmap values, rows go along Z axis and columns along X axis, so the cell
+/// of the row `r` and the column `c` is located at `[origin[0] + c, origin[1] + r]`.
+#[derive(Clone, Debug, Default)]
+pub struct NavMesh {
+    /// Number of rows (cells along Z axis)
+    pub rows: usize,
+    /// Number of columns (cells along X axis)
+    pub columns: usize,
+    /// World X and Z coordinates of the first cell
+    pub origin: [f32; 2],
+    /// Radius of the agent, cells closer than the radius to an obstacle are not walkable
+    pub agent_radius: f32,
+    /// Maximal walkable slope in radians
+    pub max_slope: f32,
+    /// Heights of the cells
+    pub heights: Vec<f32>,
+    /// Walkable flags of the cells
+    pub walkable: Vec<bool>,
+    /// Walkable flags of the cells before the agent radius was applied
+    passable: Vec<bool>,
+}
+
+impl NavMesh {
+    /// Returns `true` if the world position is walkable
+    pub fn is_walkable(&self, x: f32, z: f32) -> bool {
+        self.cell(x, z)
+            .map(|(column, row)| self.walkable[row * self.columns + column])
+            .unwrap_or(false)
+    }
+
+    /// Finds a path between the world positions over the walkable cells
+    ///
+    /// Returns world positions of the path cells including the start and the goal, or `None` if
+    /// the goal can not be reached.
+    pub fn find_path(&self, from: Vec3, to: Vec3) -> Option<Vec<Vec3>> {
+        let (start_column, start_row) = self.cell(from.x, from.z)?;
+        let (goal_column, goal_row) = self.cell(to.x, to.z)?;
+        let start = start_row * self.columns + start_column;
+        let goal = goal_row * self.columns + goal_column;
+        if !self.walkable[start] || !self.walkable[goal] {
+            return None;
+        }
+
+        let estimate = |index: usize| {
+            let dx = (index % self.columns) as f32 - goal_column as f32;
+            let dz = (index / self.columns) as f32 - goal_row as f32;
+            (dx * dx + dz * dz).sqrt()
+        };
+        let mut costs = vec![f32::INFINITY; self.walkable.len()];
+        let mut parents = vec![usize::MAX; self.walkable.len()];
+        let mut queue = BinaryHeap::new();
+        costs[start] = 0.0;
+        queue.push(Node {
+            index: start,
+            score: estimate(start),
+        });
+
+        while let Some(Node { index, score }) = queue.pop() {
+            if index == goal {
+                let mut path = vec![self.position(goal)];
+                let mut index = goal;
+                while index != start {
+                    index = parents[index];
+                    path.push(self.position(index));
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if score > costs[index] + estimate(index) {
+                continue;
+            }
+            let (column, row) = ((index % self.columns) as i64, (index / self.columns) as i64);
+            for (dx, dz) in NEIGHBOURS.iter() {
+                let (next_column, next_row) = (column + dx, row + dz);
+                if next_column < 0
+                    || next_row < 0
+                    || next_column >= self.columns as i64
+                    || next_row >= self.rows as i64
+                {
+                    continue;
+                }
+                let next = next_row as usize * self.columns + next_column as usize;
+                if !self.walkable[next] {
+                    continue;
+                }
+                let cost = costs[index] + ((dx * dx + dz * dz) as f32).sqrt();
+                if cost < costs[next] {
+                    costs[next] = cost;
+                    parents[next] = index;
+                    queue.push(Node {
+                        index: next,
+                        score: cost + estimate(next),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns column and row of the cell at the world position
+    fn cell(&self, x: f32, z: f32) -> Option<(usize, usize)> {
+        let column = (x - self.origin[0]).round();
+        let row = (z - self.origin[1]).round();
+        if column < 0.0 || row < 0.0 || column >= self.columns as f32 || row >= self.rows as f32 {
+            return None;
+        }
+        Some((column as usize, row as usize))
+    }
+
+    /// Returns world position of the cell
+    fn position(&self, index: usize) -> Vec3 {
+        Vec3::new(
+            self.origin[0] + (index % self.columns) as f32,
+            self.heights[index],
+            self.origin[1] + (index / self.columns) as f32,
+        )
+    }
+}
+
+/// Offsets of the neighbour cells
+const NEIGHBOURS: [(i64, i64); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// Pathfinding queue node, ordered by the lowest score
+struct Node {
+    index: usize,
+    score: f32,
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Node {}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .score
+            .partial_cmp(&self.score)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl Terrain {
+    /// Builds walkable grid of the whole heightmap
+    ///
+    /// Returns `None` if the heightmap is empty or the terrain is voxel based.
+    pub fn build_navmesh(&self, agent_radius: f32, max_slope: f32) -> Option<NavMesh> {
+        let size = self.heightmap.size();
+        if self.voxel.is_some() || size == 0 {
+            return None;
+        }
+        let half_world_size = ((size - 1) / 2) as f32;
+        let mut navmesh = NavMesh {
+            rows: size,
+            columns: size,
+            origin: [-half_world_size, -half_world_size],
+            agent_radius,
+            max_slope,
+            heights: vec![0.0; size * size],
+            walkable: vec![false; size * size],
+            passable: vec![false; size * size],
+        };
+        let region = [
+            -half_world_size,
+            -half_world_size,
+            half_world_size,
+            half_world_size,
+        ];
+        self.update_navmesh(&mut navmesh, region);
+        Some(navmesh)
+    }
+
+    /// Recalculates cells of the navmesh affected by changes in the world space region
+    ///
+    /// Returns `false` if the region is out of the navmesh.
+    pub fn update_navmesh(&self, navmesh: &mut NavMesh, region: [f32; 4]) -> bool {
+        let size = self.heightmap.size();
+        if navmesh.rows != size || navmesh.columns != size || size == 0 {
+            return false;
+        }
+        let radius = navmesh.agent_radius.max(0.0);
+        let reach = radius.ceil() as i64;
+        let max = size as i64 - 1;
+        let to_cell = |value: f32, origin: f32| (value - origin).round() as i64;
+        // slopes depend on neighbours, walkability on cells in the agent radius
+        let from = [
+            (to_cell(region[0], navmesh.origin[0]) - 1).max(0),
+            (to_cell(region[1], navmesh.origin[1]) - 1).max(0),
+        ];
+        let to = [
+            (to_cell(region[2], navmesh.origin[0]) + 1).min(max),
+            (to_cell(region[3], navmesh.origin[1]) + 1).min(max),
+        ];
+        if from[0] > to[0] || from[1] > to[1] {
+            return false;
+        }
+
+        let max_gradient = navmesh.max_slope.tan();
+        let height = |x: i64, z: i64| {
+            self.heightmap
+                .value(x.clamp(0, max) as usize, z.clamp(0, max) as usize)
+        };
+        for z in from[1]..=to[1] {
+            for x in from[0]..=to[0] {
+                let index = z as usize * size + x as usize;
+                let gradient_x = (height(x + 1, z) - height(x - 1, z)) / 2.0;
+                let gradient_z = (height(x, z + 1) - height(x, z - 1)) / 2.0;
+                let gradient = (gradient_x * gradient_x + gradient_z * gradient_z).sqrt();
+                navmesh.heights[index] = height(x, z);
+                navmesh.passable[index] =
+                    !self.heightmap.is_hole(x as usize, z as usize) && gradient <= max_gradient;
+            }
+        }
+
+        let radius_sq = radius * radius;
+        for z in (from[1] - reach).max(0)..=(to[1] + reach).min(max) {
+            for x in (from[0] - reach).max(0)..=(to[0] + reach).min(max) {
+                let mut walkable = true;
+                'search: for dz in -reach..=reach {
+                    for dx in -reach..=reach {
+                        if (dx * dx + dz * dz) as f32 > radius_sq {
+                            continue;
+                        }
+                        let (nx, nz) = (x + dx, z + dz);
+                        if nx < 0 || nz < 0 || nx > max || nz > max {
+                            continue;
+                        }
+                        if !navmesh.passable[nz as usize * size + nx as usize] {
+                            walkable = false;
+                            break 'search;
+                        }
+                    }
+                }
+                navmesh.walkable[z as usize * size + x as usize] = walkable;
+            }
+        }
+        true
+    }
+}
+
+/// Terrain navmesh update system, recalculates the changed regions
+pub fn update(mut terrain: Mut<Terrain>) {
+    let mut navmesh = match terrain.navmesh.take() {
+        Some(navmesh) => navmesh,
+        None => return,
+    };
+
+    if terrain.heightmap.dirty() {
+        if let Some(rebuilt) = terrain.build_navmesh(navmesh.agent_radius, navmesh.max_slope) {
+            navmesh = rebuilt;
+        }
+    } else {
+        for dirty in terrain.dirty_regions.iter() {
+            terrain.update_navmesh(&mut navmesh, *dirty);
+        }
+    }
+
+    terrain.navmesh = Some(navmesh);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Generator, Terrain};
+    use dotrix_math::Vec3;
+
+    #[test]
+    fn navmesh_avoids_steep_slopes_and_holes() {
+        let size = 17;
+        // a steep wall along Z axis at x = 12, with a gap at z = 2
+        let noise_map = (0..size * size)
+            .map(|i| {
+                let (x, z) = (i / size, i % size);
+                if x == 12 && z != 2 {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let mut generator = Generator {
+            amplitude: 10.0,
+            size,
+            noise_map: Some(noise_map),
+            ..Default::default()
+        };
+        generator.set_hole(4, 12, true);
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+
+        let navmesh = terrain.build_navmesh(0.0, 0.5).unwrap();
+        assert!(navmesh.is_walkable(-8.0, -8.0));
+        assert!(!navmesh.is_walkable(3.0, 0.0));
+        assert!(!navmesh.is_walkable(-4.0, 4.0));
+        assert!(navmesh.is_walkable(4.0, -6.0));
+
+        let path = navmesh
+            .find_path(Vec3::new(0.0, 0.0, 0.0), Vec3::new(8.0, 0.0, 0.0))
+            .unwrap();
+        assert!(path.iter().any(|point| point.z < -5.0));
+
+        // a wider agent does not fit into the gap
+        let mut navmesh = terrain.build_navmesh(1.5, 0.5).unwrap();
+        assert!(navmesh
+            .find_path(Vec3::new(0.0, 0.0, 0.0), Vec3::new(8.0, 0.0, 0.0))
+            .is_none());
+
+        // removing the hole makes its cells walkable again
+        terrain
+            .heightmap
+            .downcast_mut::<Generator>()
+            .unwrap()
+            .set_hole(4, 12, false);
+        assert!(terrain.update_navmesh(&mut navmesh, [-5.0, 3.0, -3.0, 5.0]));
+        assert!(navmesh.is_walkable(-4.0, 4.0));
+    }
+}
@@ -0,0 +1,99 @@
+use dotrix_core::ecs::Mut;
+
+use crate::{Generator, Noise, Terrain};
+
+/// Live-tunable parameters of the terrain [`Generator`] (service)
+///
+/// Editors can bind sliders to the fields. When any of them changes, the noise map of the
+/// terrain generator is rebuilt with the new parameters and the generator is marked dirty, so
+/// the terrain is respawned. Sculpting and other edits of the noise map are lost on rebuild.
+pub struct GeneratorParams {
+    /// Noise configuration
+    pub noise: Noise,
+    /// Amplitude of the heights
+    pub amplitude: f32,
+    /// Height of the noise value 0.0
+    pub min_height: f32,
+    applied: Option<(Noise, f32, f32)>,
+}
+
+impl GeneratorParams {
+    /// Constructs new parameters, applied to the generator on the next update
+    pub fn new(noise: Noise, amplitude: f32) -> Self {
+        Self {
+            noise,
+            amplitude,
+            min_height: 0.0,
+            applied: None,
+        }
+    }
+
+    /// Returns true if parameters were changed since they were applied last time
+    pub fn changed(&self) -> bool {
+        self.applied != Some((self.noise, self.amplitude, self.min_height))
+    }
+
+    /// Rebuilds the noise map of the generator, keeps it as the procedural base and marks the
+    /// generator dirty
+    pub fn apply(&mut self, generator: &mut Generator) {
+        generator.noise_map = Some(self.noise.map(generator.size));
+        generator.set_base();
+        generator.amplitude = self.amplitude;
+        generator.min_height = self.min_height;
+        generator.dirty_regions.clear();
+        generator.dirty = true;
+        self.applied = Some((self.noise, self.amplitude, self.min_height));
+    }
+}
+
+impl Default for GeneratorParams {
+    /// Default parameters are considered applied, so they do not override the terrain until
+    /// changed
+    fn default() -> Self {
+        let noise = Noise::default();
+        let amplitude = 100.0;
+        Self {
+            noise,
+            amplitude,
+            min_height: 0.0,
+            applied: Some((noise, amplitude, 0.0)),
+        }
+    }
+}
+
+/// Applies changed generator parameters to the terrain heightmap
+pub fn update(mut params: Mut<GeneratorParams>, mut terrain: Mut<Terrain>) {
+    if !params.changed() {
+        return;
+    }
+    if let Some(generator) = terrain.heightmap.downcast_mut::<Generator>() {
+        params.apply(generator);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Heightmap;
+
+    #[test]
+    fn changed_params_rebuild_generator() {
+        let mut generator = Generator {
+            size: 9,
+            ..Default::default()
+        };
+        let mut params = GeneratorParams::default();
+        assert!(!params.changed());
+
+        params.noise.seed = 7;
+        params.amplitude = 50.0;
+        assert!(params.changed());
+        params.apply(&mut generator);
+        assert!(!params.changed());
+        assert!(generator.dirty());
+        assert_eq!(generator.amplitude, 50.0);
+        assert_eq!(generator.noise_map.as_ref().map(|map| map.len()), Some(81));
+        assert_eq!(generator.base_map, generator.noise_map);
+        assert!(GeneratorParams::new(Noise::default(), 10.0).changed());
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+
+use dotrix_core::assets::{Mesh, Texture};
+use dotrix_core::ecs::{Const, Context, Entity, Mut};
+use dotrix_core::{Color, Id, Pipeline, Transform, World};
+
+use dotrix_math::{Quat, Rad, Rotation3, Vec3};
+
+use dotrix_pbr::{Material, Model};
+
+use crate::{Scatter, Terrain, Tile};
+
+/// Prop kind (trees, rocks etc.) scattered over the terrain tiles of the highest level of details
+///
+/// Every prop is spawned as a regular solid PBR entity with the [`PropInstance`] component.
+pub struct Prop {
+    /// Prop mesh
+    pub mesh: Id<Mesh>,
+    /// Prop texture
+    pub texture: Id<Texture>,
+    /// Albedo color
+    pub albedo: Color,
+    /// Scattering rules
+    pub scatter: Scatter,
+}
+
+impl Prop {
+    /// Constructs new prop kind of the mesh
+    pub fn new(mesh: Id<Mesh>) -> Self {
+        Self {
+            mesh,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for Prop {
+    fn default() -> Self {
+        Self {
+            mesh: Id::default(),
+            texture: Id::default(),
+            albedo: Color::white(),
+            scatter: Scatter {
+                density: 0.01,
+                min_spacing: 4.0,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Component of the spawned prop
+pub struct PropInstance {
+    /// Index of the prop kind in [`Terrain::props`]
+    pub prop: usize,
+    /// Mesh of the owning tile
+    pub tile: Id<Mesh>,
+}
+
+/// Props spawn system context
+#[derive(Default)]
+pub struct Spawner {
+    tiles: HashSet<Id<Mesh>>,
+    to_exile: Vec<Entity>,
+}
+
+/// Props spawn system
+/// Scatters props over the tiles of the highest level of details, follows their lifecycle
+pub fn spawn(mut ctx: Context<Spawner>, terrain: Const<Terrain>, mut world: Mut<World>) {
+    let mut tiles = HashSet::new();
+    let mut new_tiles = Vec::new();
+    let query = world.query::<(&Tile,)>();
+    for (tile,) in query {
+        if tile.lod != 0 || tile.pooled {
+            continue;
+        }
+        let dirty = terrain.is_tile_dirty(tile.x, tile.z, tile.lod);
+        if !dirty {
+            tiles.insert(tile.mesh);
+        }
+        if dirty || !ctx.tiles.contains(&tile.mesh) {
+            new_tiles.push((tile.mesh, tile.x, tile.z));
+        }
+    }
+
+    // exile props of the exiled or changed tiles
+    let query = world.query::<(&PropInstance, &Entity)>();
+    for (prop, entity) in query {
+        if !tiles.contains(&prop.tile) {
+            ctx.to_exile.push(*entity);
+        }
+    }
+    for entity in ctx.to_exile.iter() {
+        world.exile(*entity);
+    }
+    ctx.to_exile.clear();
+
+    for (mesh, x, z) in new_tiles {
+        for (index, prop) in terrain.props.iter().enumerate() {
+            world.spawn(
+                prop.scatter
+                    .points(&terrain, x, z)
+                    .into_iter()
+                    .map(|point| {
+                        (
+                            Model::from(prop.mesh),
+                            Material {
+                                albedo: prop.albedo,
+                                texture: prop.texture,
+                                ..Default::default()
+                            },
+                            Transform {
+                                translate: Vec3::from(point.position),
+                                rotate: Quat::from_angle_y(Rad(point.rotation)),
+                                scale: Vec3::new(point.scale, point.scale, point.scale),
+                            },
+                            Pipeline::default(),
+                            PropInstance {
+                                prop: index,
+                                tile: mesh,
+                            },
+                        )
+                    }),
+            );
+        }
+        tiles.insert(mesh);
+    }
+
+    ctx.tiles = tiles;
+}
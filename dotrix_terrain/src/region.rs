@@ -0,0 +1,258 @@
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::layers::MAX_LAYERS;
+
+/// Persistent state of the rectangular heightmap region
+///
+/// Values are stored row by row, indexed as `x * size_z + z` relative to the region origin.
+/// Heights are stored as deltas from the procedural base of the [`crate::Generator`], so the
+/// modifications are applied over the regenerated terrain.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    /// Map X coordinate of the region origin
+    pub x: usize,
+    /// Map Z coordinate of the region origin
+    pub z: usize,
+    /// Number of values along X axis
+    pub size_x: usize,
+    /// Number of values along Z axis
+    pub size_z: usize,
+    /// Noise values deltas from the procedural base
+    pub heights: Vec<f32>,
+    /// Holes flags, empty if there are no holes in the region
+    pub holes: Vec<bool>,
+    /// Painted layers weights of the splat map texels covering the region
+    #[serde(default)]
+    pub splat: Option<SplatWeights>,
+}
+
+/// Painted layers weights of the rectangular splat map region, see [`crate::Layers::paint`]
+///
+/// Weights are valid for the splat map allocated with the same region and resolution.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SplatWeights {
+    /// Splat map X coordinate of the first texel
+    pub x: usize,
+    /// Splat map Z coordinate of the first texel
+    pub z: usize,
+    /// Number of texels along X axis
+    pub size_x: usize,
+    /// Number of texels along Z axis
+    pub size_z: usize,
+    /// Weights of all layers per texel, indexed as `(x * size_z + z) * MAX_LAYERS + layer`
+    pub weights: Vec<u8>,
+}
+
+impl Region {
+    /// Serializes the region into the writer
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), RegionError> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Deserializes the region from the reader
+    pub fn read<R: Read>(reader: R) -> Result<Self, RegionError> {
+        let region: Self = serde_json::from_reader(reader)?;
+        let count = region.size_x * region.size_z;
+        if region.heights.len() != count
+            || !(region.holes.is_empty() || region.holes.len() == count)
+        {
+            return Err(RegionError::Corruption("Wrong number of values"));
+        }
+        if let Some(splat) = region.splat.as_ref() {
+            if splat.weights.len() != splat.size_x * splat.size_z * MAX_LAYERS {
+                return Err(RegionError::Corruption("Wrong number of splat weights"));
+            }
+        }
+        Ok(region)
+    }
+}
+
+/// Region saving and loading errors
+#[derive(Debug)]
+pub enum RegionError {
+    /// Error occured during serialization or deserialization
+    Serde(serde_json::Error),
+    /// Heightmap or splat map does not store editable values
+    NotSupported,
+    /// Region does not fit into the heightmap
+    OutOfBounds,
+    /// Heightmap has no procedural base to store the heights deltas against
+    MissingBase,
+    /// It seems that source data were corrupted
+    Corruption(&'static str),
+}
+
+impl std::error::Error for RegionError {}
+
+impl std::fmt::Display for RegionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegionError::Serde(err) => write!(f, "Can't serialize region ({:?})", err),
+            RegionError::NotSupported => write!(f, "Heightmap does not support regions"),
+            RegionError::OutOfBounds => write!(f, "Region is out of the heightmap bounds"),
+            RegionError::MissingBase => write!(f, "Heightmap has no procedural base"),
+            RegionError::Corruption(err) => write!(f, "Region could be corrupted ({:?})", err),
+        }
+    }
+}
+
+impl From<serde_json::Error> for RegionError {
+    fn from(err: serde_json::Error) -> Self {
+        RegionError::Serde(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Generator, Layers, Terrain, TileCache};
+    use dotrix_core::assets::Mesh;
+    use dotrix_math::Vec3;
+
+    #[test]
+    fn region_survives_save_and_load() {
+        let size = 8;
+        let base = (0..size * size).map(|i| i as f32).collect::<Vec<_>>();
+        let mut source = Generator {
+            size,
+            noise_map: Some(base.clone()),
+            ..Default::default()
+        };
+        source.set_base();
+        source.noise_map.as_mut().unwrap()[3 * size + 4] += 0.5;
+        source.set_hole(3, 4, true);
+
+        let mut layers = Layers::default();
+        layers.set_splat_map([-8.0, -8.0, 8.0, 8.0], 17);
+        layers.paint(Vec3::new(0.0, 0.0, 0.0), 2.0, 5, 1.0);
+
+        let mut region = source.region(2, 3, 4, 10).unwrap();
+        region.splat = layers.splat_weights([-4.0, -4.0], [4.0, 4.0]);
+        assert_eq!(region.heights[5 + 1], 0.5);
+        assert!(region.heights.iter().filter(|&&delta| delta != 0.0).count() == 1);
+
+        let mut data = Vec::new();
+        region.write(&mut data).unwrap();
+        let region = Region::read(data.as_slice()).unwrap();
+        assert_eq!((region.size_x, region.size_z), (4, 5));
+
+        // the procedural base is regenerated, the deltas are applied over it
+        let mut target = Generator {
+            size,
+            noise_map: Some(base),
+            ..Default::default()
+        };
+        target.set_base();
+        target.set_region(&region).unwrap();
+        assert_eq!(target.noise_map, source.noise_map);
+        assert_eq!(target.region(2, 3, 4, 5), source.region(2, 3, 4, 5));
+        assert_eq!(target.region(0, 0, 2, 2).unwrap().heights, vec![0.0; 4]);
+
+        let mut target_layers = Layers::default();
+        target_layers.set_splat_map([-8.0, -8.0, 8.0, 8.0], 17);
+        target_layers
+            .set_splat_weights(region.splat.as_ref().unwrap())
+            .unwrap();
+        assert_eq!(target_layers.splat_map.data, layers.splat_map.data);
+    }
+
+    #[test]
+    fn loaded_region_invalidates_tiles() {
+        let size = 65;
+        let mut generator = Generator {
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            ..Default::default()
+        };
+        generator.set_base();
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 16;
+
+        let mut data = Vec::new();
+        terrain
+            .save_region(&mut data, [-2.0, -2.0], [2.0, 2.0], &Layers::default())
+            .unwrap();
+
+        let mut mesh = Mesh::default();
+        mesh.with_vertices(&[[0.0_f32; 3]; 3]);
+        mesh.with_vertices(&[[0.0_f32, 1.0, 0.0]; 3]);
+        mesh.with_vertices(&[[0.0_f32; 2]; 3]);
+        mesh.with_indices(&[0, 1, 2]);
+        let path = std::env::temp_dir().join(format!(
+            "dotrix_terrain_loaded_region_invalidates_tiles_{}",
+            std::process::id()
+        ));
+        let mut cache = TileCache::new(&path);
+        cache.store(0, 0, 0, &mesh).unwrap();
+        cache.store(32, 32, 0, &mesh).unwrap();
+        terrain.cache = Some(cache);
+
+        terrain
+            .load_region(data.as_slice(), &mut Layers::default())
+            .unwrap();
+        assert!(!terrain.dirty_regions.is_empty());
+        assert!(terrain.is_tile_dirty(0, 0, 0));
+        assert!(!terrain.is_tile_dirty(32, 32, 0));
+
+        terrain.invalidate_cached_tiles();
+        let cache = terrain.cache.as_mut().unwrap();
+        assert!(!cache.contains(0, 0, 0));
+        assert!(cache.contains(32, 32, 0));
+        cache.clear();
+        std::fs::remove_dir_all(&path).ok();
+    }
+
+    #[test]
+    fn region_is_not_saved_without_base() {
+        let size = 9;
+        let generator = Generator {
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            ..Default::default()
+        };
+        let terrain = Terrain::new(Box::new(generator), vec![]);
+        let result = terrain.save_region(Vec::new(), [-2.0, -2.0], [2.0, 2.0], &Layers::default());
+        assert!(matches!(result, Err(RegionError::MissingBase)));
+    }
+
+    #[test]
+    fn failed_load_leaves_heights_unchanged() {
+        let size = 9;
+        let mut generator = Generator {
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            ..Default::default()
+        };
+        generator.set_base();
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+
+        let mut region = terrain
+            .heightmap
+            .downcast_ref::<Generator>()
+            .unwrap()
+            .region(0, 0, 3, 3)
+            .unwrap();
+        region.heights = vec![1.0; 9];
+        region.splat = Some(SplatWeights {
+            x: 100,
+            z: 0,
+            size_x: 1,
+            size_z: 1,
+            weights: vec![255; MAX_LAYERS],
+        });
+        let mut data = Vec::new();
+        region.write(&mut data).unwrap();
+
+        let mut layers = Layers::default();
+        layers.set_splat_map([-8.0, -8.0, 8.0, 8.0], 17);
+        let result = terrain.load_region(data.as_slice(), &mut layers);
+        assert!(matches!(result, Err(RegionError::OutOfBounds)));
+
+        let generator = terrain.heightmap.downcast_ref::<Generator>().unwrap();
+        assert_eq!(generator.noise_map, Some(vec![0.5; size * size]));
+        assert!(terrain.dirty_regions.is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+/// Interpolation of the values between the map samples
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// Value of the nearest sample
+    Nearest,
+    /// Linear interpolation of 2x2 samples
+    #[default]
+    Bilinear,
+    /// Cubic (Catmull-Rom) interpolation of 4x4 samples, smoother but may overshoot
+    Bicubic,
+}
+
+/// Resamples the square map of `size` values per side to the `new_size`
+///
+/// Values are indexed as `x * size + z`, corners of the map are kept in place.
+pub fn resample(map: &[f32], size: usize, new_size: usize, filter: Filter) -> Vec<f32> {
+    if size == 0 || map.len() < size * size {
+        return vec![0.0; new_size * new_size];
+    }
+    let step = if new_size > 1 {
+        (size - 1) as f32 / (new_size - 1) as f32
+    } else {
+        0.0
+    };
+    let value = |x: isize, z: isize| {
+        let max = size as isize - 1;
+        map[x.clamp(0, max) as usize * size + z.clamp(0, max) as usize]
+    };
+
+    let mut result = Vec::with_capacity(new_size * new_size);
+    for x in 0..new_size {
+        for z in 0..new_size {
+            let (sx, sz) = (x as f32 * step, z as f32 * step);
+            let (x0, z0) = (sx.floor() as isize, sz.floor() as isize);
+            let (tx, tz) = (sx - x0 as f32, sz - z0 as f32);
+            result.push(match filter {
+                Filter::Nearest => value(sx.round() as isize, sz.round() as isize),
+                Filter::Bilinear => {
+                    let a = value(x0, z0) * (1.0 - tz) + value(x0, z0 + 1) * tz;
+                    let b = value(x0 + 1, z0) * (1.0 - tz) + value(x0 + 1, z0 + 1) * tz;
+                    a * (1.0 - tx) + b * tx
+                }
+                Filter::Bicubic => {
+                    let mut columns = [0.0; 4];
+                    for (i, column) in columns.iter_mut().enumerate() {
+                        let cx = x0 - 1 + i as isize;
+                        *column = cubic(
+                            [
+                                value(cx, z0 - 1),
+                                value(cx, z0),
+                                value(cx, z0 + 1),
+                                value(cx, z0 + 2),
+                            ],
+                            tz,
+                        );
+                    }
+                    cubic(columns, tx)
+                }
+            });
+        }
+    }
+    result
+}
+
+/// Copies the square region of `crop_size` values per side starting at the `x` and `z`
+///
+/// Returns `None` if the region does not fit into the map.
+pub fn crop(map: &[f32], size: usize, x: usize, z: usize, crop_size: usize) -> Option<Vec<f32>> {
+    if x + crop_size > size || z + crop_size > size || map.len() < size * size {
+        return None;
+    }
+    let mut result = Vec::with_capacity(crop_size * crop_size);
+    for cx in x..x + crop_size {
+        let i = cx * size + z;
+        result.extend_from_slice(&map[i..i + crop_size]);
+    }
+    Some(result)
+}
+
+/// Catmull-Rom interpolation between the second and the third values
+fn cubic(values: [f32; 4], t: f32) -> f32 {
+    let [p0, p1, p2, p3] = values;
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resample_keeps_corners_and_ramps() {
+        // a ramp along X axis
+        let size = 5;
+        let map = (0..size * size)
+            .map(|i| (i / size) as f32)
+            .collect::<Vec<_>>();
+
+        for filter in [Filter::Nearest, Filter::Bilinear, Filter::Bicubic].iter() {
+            let result = resample(&map, size, 9, *filter);
+            assert_eq!(result.len(), 81);
+            assert_eq!(result[0], 0.0);
+            assert_eq!(result[80], 4.0);
+        }
+        let bilinear = resample(&map, size, 9, Filter::Bilinear);
+        assert!((bilinear[9] - 0.5).abs() < 1e-5);
+        let bicubic = resample(&map, size, 9, Filter::Bicubic);
+        assert!((bicubic[4 * 9] - 2.0).abs() < 1e-5);
+
+        let cropped = crop(&map, size, 1, 2, 3).unwrap();
+        assert_eq!(cropped, vec![1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 3.0, 3.0, 3.0]);
+        assert!(crop(&map, size, 3, 0, 3).is_none());
+    }
+}
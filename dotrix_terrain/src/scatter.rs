@@ -0,0 +1,251 @@
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use dotrix_core::assets::Texture;
+
+use crate::{Biomes, Terrain};
+
+/// Deterministic scattering rules of objects over the terrain tiles
+#[derive(Clone)]
+pub struct Scatter {
+    /// Number of candidates per square world unit
+    pub density: f32,
+    /// Optional density multipliers (0.0..1.0) per heightmap value, indexed as `x * size + z`
+    pub density_map: Option<Vec<f32>>,
+    /// Maximal slope angle in radians
+    pub max_slope: f32,
+    /// Minimal terrain height
+    pub min_height: f32,
+    /// Maximal terrain height
+    pub max_height: f32,
+    /// Minimal distance between two points of the same tile
+    pub min_spacing: f32,
+    /// Range of the points scale
+    pub scale: [f32; 2],
+    /// Seed of the scattering
+    pub seed: u64,
+}
+
+/// Scattered point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScatterPoint {
+    /// World position on the terrain surface
+    pub position: [f32; 3],
+    /// Rotation around Y axis in radians
+    pub rotation: f32,
+    /// Scale factor
+    pub scale: f32,
+}
+
+impl Scatter {
+    /// Sets density map from the red channel of RGBA texture, stretched over the heightmap
+    pub fn with_density_texture(mut self, texture: &Texture, map_size: usize) -> Self {
+        let (width, height) = (texture.width as usize, texture.height as usize);
+        if width == 0 || height == 0 || texture.data.len() < width * height * 4 {
+            return self;
+        }
+        let mut density_map = Vec::with_capacity(map_size * map_size);
+        for x in 0..map_size {
+            for z in 0..map_size {
+                let u = x * width / map_size;
+                let v = z * height / map_size;
+                density_map.push(texture.data[(v * width + u) * 4] as f32 / 255.0);
+            }
+        }
+        self.density_map = Some(density_map);
+        self
+    }
+
+    /// Multiplies density map by the foliage density of the biomes
+    pub fn with_biomes(mut self, biomes: &Biomes, map_size: usize) -> Self {
+        let mut density_map = biomes.density_map(map_size);
+        if let Some(current) = self.density_map.as_ref() {
+            for (density, value) in density_map.iter_mut().zip(current.iter()) {
+                *density *= value;
+            }
+        }
+        self.density_map = Some(density_map);
+        self
+    }
+
+    /// Returns points of the tile (on the highest level of details) centered at X and Z
+    ///
+    /// Points are placed on a jittered grid and then filtered by the rules. The same seed and
+    /// heights always produce the same points, so tiles can be despawned and respawned freely.
+    pub fn points(&self, terrain: &Terrain, tile_x: i32, tile_z: i32) -> Vec<ScatterPoint> {
+        let mut points: Vec<ScatterPoint> = Vec::new();
+        if self.density <= 0.0 || terrain.voxel.is_some() {
+            return points;
+        }
+        let half_size = terrain.tile_half_size(0);
+        let cell_size = 1.0 / self.density.sqrt();
+        let cells = (2.0 * half_size / cell_size).floor() as usize;
+        let seed = self.seed
+            ^ ((tile_x as u32 as u64) << 32 | tile_z as u32 as u64)
+                .wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let mut pseudo_rng = SmallRng::seed_from_u64(seed);
+
+        let map_size = terrain.heightmap.size();
+        let half_world_size = (map_size.saturating_sub(1) / 2) as f32;
+        let max_slope = self.max_slope.tan();
+        let min_spacing_sq = self.min_spacing * self.min_spacing;
+
+        for cell_x in 0..cells {
+            for cell_z in 0..cells {
+                let x = tile_x as f32 - half_size
+                    + (cell_x as f32 + pseudo_rng.gen::<f32>()) * cell_size;
+                let z = tile_z as f32 - half_size
+                    + (cell_z as f32 + pseudo_rng.gen::<f32>()) * cell_size;
+                let chance = pseudo_rng.gen::<f32>();
+                let rotation = pseudo_rng.gen::<f32>() * std::f32::consts::TAU;
+                let scale =
+                    self.scale[0] + pseudo_rng.gen::<f32>() * (self.scale[1] - self.scale[0]);
+
+                let map_x = (x + half_world_size).round();
+                let map_z = (z + half_world_size).round();
+                if map_x < 0.0
+                    || map_z < 0.0
+                    || map_x >= map_size as f32
+                    || map_z >= map_size as f32
+                {
+                    continue;
+                }
+                let (map_x, map_z) = (map_x as usize, map_z as usize);
+                if terrain.heightmap.is_hole(map_x, map_z) {
+                    continue;
+                }
+                if let Some(density_map) = self.density_map.as_ref() {
+                    let density = density_map
+                        .get(map_x * map_size + map_z)
+                        .copied()
+                        .unwrap_or(0.0);
+                    if chance >= density {
+                        continue;
+                    }
+                }
+
+                let height = terrain.height_at(x, z);
+                if height < self.min_height || height > self.max_height {
+                    continue;
+                }
+                let gradient_x =
+                    (terrain.height_at(x + 1.0, z) - terrain.height_at(x - 1.0, z)) / 2.0;
+                let gradient_z =
+                    (terrain.height_at(x, z + 1.0) - terrain.height_at(x, z - 1.0)) / 2.0;
+                if (gradient_x * gradient_x + gradient_z * gradient_z).sqrt() > max_slope {
+                    continue;
+                }
+
+                if min_spacing_sq > 0.0
+                    && points.iter().any(|point| {
+                        let dx = point.position[0] - x;
+                        let dz = point.position[2] - z;
+                        dx * dx + dz * dz < min_spacing_sq
+                    })
+                {
+                    continue;
+                }
+
+                points.push(ScatterPoint {
+                    position: [x, height, z],
+                    rotation,
+                    scale,
+                });
+            }
+        }
+        points
+    }
+}
+
+impl Default for Scatter {
+    fn default() -> Self {
+        Self {
+            density: 0.5,
+            density_map: None,
+            max_slope: std::f32::consts::FRAC_PI_4,
+            min_height: f32::MIN,
+            max_height: f32::MAX,
+            min_spacing: 0.0,
+            scale: [0.8, 1.2],
+            seed: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Biome, Generator};
+
+    fn terrain(height: f32) -> Terrain {
+        let size = 65;
+        let generator = Generator {
+            amplitude: 1.0,
+            size,
+            noise_map: Some(vec![height; size * size]),
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 16;
+        terrain
+    }
+
+    #[test]
+    fn points_are_deterministic_and_respect_height_range() {
+        let terrain = terrain(0.5);
+        let scatter = Scatter {
+            density: 1.0,
+            ..Default::default()
+        };
+        let points = scatter.points(&terrain, 0, 0);
+        assert!(!points.is_empty());
+        assert_eq!(points, scatter.points(&terrain, 0, 0));
+
+        let scatter = Scatter {
+            density: 1.0,
+            min_height: 0.6,
+            ..Default::default()
+        };
+        assert!(scatter.points(&terrain, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn biomes_scale_density() {
+        let terrain = terrain(0.5);
+        let biomes = |foliage_density| Biomes {
+            list: vec![Biome {
+                foliage_density,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let scatter = Scatter {
+            density: 1.0,
+            ..Default::default()
+        };
+        let size = terrain.heightmap.size();
+        let dense = scatter.clone().with_biomes(&biomes(1.0), size);
+        assert_eq!(dense.points(&terrain, 0, 0), scatter.points(&terrain, 0, 0));
+        let bare = scatter.with_biomes(&biomes(0.0), size);
+        assert!(bare.points(&terrain, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn points_keep_min_spacing() {
+        let terrain = terrain(0.5);
+        let scatter = Scatter {
+            density: 1.0,
+            min_spacing: 3.0,
+            ..Default::default()
+        };
+        let points = scatter.points(&terrain, 0, 0);
+        assert!(!points.is_empty());
+        for (i, a) in points.iter().enumerate() {
+            for b in points.iter().skip(i + 1) {
+                let dx = a.position[0] - b.position[0];
+                let dz = a.position[2] - b.position[2];
+                assert!(dx * dx + dz * dz >= 9.0);
+            }
+        }
+    }
+}
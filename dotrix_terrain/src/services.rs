@@ -1,9 +1,18 @@
+use std::io::{Read, Write};
+
 use dotrix_core::assets::{Mesh, Texture};
-use dotrix_core::Id;
+use dotrix_core::renderer::{PolygonMode, StorageTextureAccess, TextureBuffer, TextureFormat};
+use dotrix_core::{Color, Id};
 
 use dotrix_math::{InnerSpace, Vec3};
 
-use crate::{Generator, Heightmap};
+use crate::{
+    AmbientOcclusion, Brush, Foliage, Generator, GpuGenerator, HeightFieldData, Heightmap, Layers,
+    Minimap, NavMesh, Prop, Region, RegionError, Spline, Stamp, TileCache, TileEvent, Voxel, Water,
+};
+
+/// Vertex color of the unpainted terrain, white without wetness
+const DEFAULT_VERTEX_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.0];
 
 /// Terrain manager (configuration)
 pub struct Terrain {
@@ -11,18 +20,83 @@ pub struct Terrain {
     pub view_distance: f32,
     /// The lowest lod number (default 4)
     pub max_lod: usize,
+    /// How many times the tile side of each next LOD is longer than of the previous one
+    ///
+    /// Must be even, so the tiles of neighbour LODs do not share centers, odd values are
+    /// rounded up (default 2).
+    pub lod_scale: usize,
+    /// Maximal distance to the tiles of each LOD, where the next lower LOD takes place
+    ///
+    /// Missing values default to the tile side length of the next lower LOD.
+    pub lod_distances: Vec<f32>,
+    /// Maximal number of tiles spawned per frame, 0 means no limit
+    pub max_tiles_per_frame: usize,
+    /// Distance margin around the LOD and view distance boundaries, where the spawned tiles are
+    /// kept, so they do not flicker when the viewer moves along the boundary (default 16.0)
+    pub hysteresis: f32,
+    /// Number of the despawned tiles kept hidden for a fast respawn (default 16)
+    pub pool_size: usize,
     /// Number of polygons per chunk side (default 240)
     pub tile_size: usize,
     /// Terrain will be recalclated only if viewer has moved by that value (default 16*16=256)
     pub spawn_if_moved_by: f32,
     /// Flag to perform force terrain recalculation
     pub force_spawn: bool,
+    /// World space regions (`[min_x, min_z, max_x, max_z]`) where tiles have to be regenerated
+    pub dirty_regions: Vec<[f32; 4]>,
+    /// Per heightmap value colors, indexed as `x * size + z`, RGB tint and wetness in alpha
+    ///
+    /// `None` disables the vertex color attribute of the tile meshes.
+    pub vertex_colors: Option<Vec<[f32; 4]>>,
+    /// Map regions (`[min_x, min_z, max_x, max_z]`, inclusive) of the painted vertex colors
+    ///
+    /// Moved into `dirty_regions` with the heightmap ones by [`Terrain::collect_dirty_regions`].
+    pub(crate) dirty_color_regions: Vec<[usize; 4]>,
+    /// Tiles lifecycle events, cleared by the `invalidate` system at the beginning of the frame
+    pub events: Vec<TileEvent>,
+    /// Number of the visible tiles waiting for the generation
+    pub pending_tiles: usize,
     /// Heights source
     pub heightmap: Box<dyn Heightmap>,
+    /// Optional voxel generator, replaces the heightmap in tiles generation if set
+    pub voxel: Option<Voxel>,
+    /// Size of the baked per tile normal map in texels, normals are not baked if `None`
+    pub normal_map_size: Option<u32>,
+    /// Ambient occlusion baking settings, ambient occlusion is not baked if `None`
+    pub ambient_occlusion: Option<AmbientOcclusion>,
+    /// Optional GPU generator, replaces the heightmap in tiles generation if set
+    pub gpu: Option<GpuGenerator>,
+    /// Optional on-disk cache of the tiles, enables streaming of the despawned tiles
+    pub cache: Option<TileCache>,
+    /// How far from the viewer the despawned tiles are kept in the cache (default 1000.0)
+    ///
+    /// Should not be less than the view distance, otherwise tiles are dropped from the cache
+    /// right after they were despawned.
+    pub cache_distance: f32,
+    /// Foliage kinds scattered over the tiles of the highest level of details
+    pub foliage: Vec<Foliage>,
+    /// Prop kinds scattered over the tiles of the highest level of details
+    pub props: Vec<Prop>,
+    /// Optional water surface, spawned over the tiles going below the sea level
+    pub water: Option<Water>,
+    /// Optional minimap, kept up to date with the terrain changes
+    pub minimap: Option<Minimap>,
+    /// Optional walkable grid, kept up to date with the terrain changes
+    pub navmesh: Option<NavMesh>,
     /// Id of the terrain for texturing
     pub texture: Id<Texture>,
+    /// Albedo tint of the tiles material
+    pub albedo: Color,
+    /// Roughness multiplier of the tiles material
+    pub roughness: f32,
+    /// Metallic multiplier of the tiles material
+    pub metallic: f32,
     /// List of the terrain heights to determine UV of the texture
     pub texture_heights: Vec<f32>,
+    /// Rasterization of the tiles, [`PolygonMode::Line`] shows the structure of the LODs
+    pub polygon_mode: PolygonMode,
+    /// Polygon mode the tile pipelines are bound with
+    pub(crate) bound_polygon_mode: PolygonMode,
 }
 
 impl Terrain {
@@ -31,26 +105,65 @@ impl Terrain {
         Self {
             view_distance: 500.0,
             max_lod: 4,
+            lod_scale: 2,
+            lod_distances: Vec::new(),
+            max_tiles_per_frame: 0,
+            hysteresis: 16.0,
+            pool_size: 16,
             tile_size: 240,
             spawn_if_moved_by: 256.0,
             force_spawn: true,
+            dirty_regions: Vec::new(),
+            vertex_colors: None,
+            dirty_color_regions: Vec::new(),
+            events: Vec::new(),
+            pending_tiles: 0,
             heightmap,
+            voxel: None,
+            normal_map_size: None,
+            ambient_occlusion: None,
+            gpu: None,
+            cache: None,
+            cache_distance: 1000.0,
+            foliage: Vec::new(),
+            props: Vec::new(),
+            water: None,
+            minimap: None,
+            navmesh: None,
             texture: Id::default(),
+            albedo: Color::white(),
+            roughness: 1.0,
+            metallic: 1.0,
             texture_heights,
+            polygon_mode: PolygonMode::Fill,
+            bound_polygon_mode: PolygonMode::Fill,
         }
     }
 
     /// Generates terrain mesh
     pub fn generate_tile_mesh(&self, tile_x: i32, tile_z: i32, lod: usize) -> Mesh {
+        if let Some(voxel) = self.voxel.as_ref() {
+            let half_size = self.tile_half_size(lod);
+            let (x, z) = (tile_x as f32, tile_z as f32);
+            return voxel.mesh(
+                [x - half_size, voxel.min_y, z - half_size],
+                [x + half_size, voxel.max_y, z + half_size],
+            );
+        }
+
         let tile_size = self.tile_size;
         let vertices_per_side = tile_size + 1;
         let offset = self.tile_size as i32 / 2;
-        let scale = 2_i32.pow(lod as u32);
+        let scale = self.lod_step(lod) as i32;
 
         let capacity = vertices_per_side * vertices_per_side;
         let mut positions = Vec::with_capacity(capacity);
         let mut uvs = Vec::with_capacity(capacity);
         let mut normals = vec![[0.0, 0.0, 0.0]; capacity];
+        let mut holes = Vec::with_capacity(capacity);
+        // heights of GPU generated tiles use the shader without vertex colors
+        let vertex_colors = self.vertex_colors.as_ref().filter(|_| self.gpu.is_none());
+        let mut colors = Vec::with_capacity(if vertex_colors.is_some() { capacity } else { 0 });
         let mut indices = Vec::with_capacity(3 * 2 * self.tile_size * self.tile_size);
         let half_world_size = ((self.heightmap.size() - 1) / 2) as i32;
 
@@ -68,8 +181,23 @@ impl Terrain {
                 } else {
                     world_x + half_world_size
                 };
-                let world_y = self.heightmap.value(map_x as usize, map_z as usize);
+                // heights of GPU generated tiles are displaced in the vertex shader
+                let world_y = if self.gpu.is_some() {
+                    0.0
+                } else {
+                    self.heightmap.value(map_x as usize, map_z as usize)
+                };
                 positions.push([world_x as f32, world_y, world_z as f32]);
+                holes.push(self.heightmap.is_hole(map_x as usize, map_z as usize));
+                if let Some(vertex_colors) = vertex_colors {
+                    let i = map_x as usize * self.heightmap.size() + map_z as usize;
+                    colors.push(
+                        vertex_colors
+                            .get(i)
+                            .copied()
+                            .unwrap_or(DEFAULT_VERTEX_COLOR),
+                    );
+                }
                 uvs.push([
                     (x + offset) as f32 / 2.0 / offset as f32,
                     (z + offset) as f32 / 2.0 / offset as f32,
@@ -85,6 +213,14 @@ impl Terrain {
                 let i01 = i00 + vertices_per_side as u32;
                 let i11 = i01 + 1;
 
+                if holes[i00 as usize]
+                    || holes[i10 as usize]
+                    || holes[i01 as usize]
+                    || holes[i11 as usize]
+                {
+                    continue;
+                }
+
                 indices.push(i10);
                 indices.push(i00);
                 indices.push(i01);
@@ -123,11 +259,520 @@ impl Terrain {
         mesh.with_vertices(&positions);
         mesh.with_vertices(&normals);
         mesh.with_vertices(&uvs);
+        if vertex_colors.is_some() {
+            mesh.with_vertices(&colors);
+        }
         mesh.with_indices(&indices);
 
         mesh
     }
 
+    /// Bakes normal map texture of the tile, if `normal_map_size` is set
+    ///
+    /// Normals are calculated from the heightmap in its full resolution, so tiles with lower
+    /// level of details keep the lighting details. Texels are aligned with the tile vertices.
+    pub fn bake_tile_normal_map(&self, tile_x: i32, tile_z: i32, lod: usize) -> Option<Texture> {
+        let size = self.normal_map_size?.max(2);
+        if self.voxel.is_some() || self.gpu.is_some() {
+            return None;
+        }
+        let half_size = self.tile_half_size(lod);
+        let texel_size = 2.0 * half_size / (size - 1) as f32;
+        let step = texel_size.max(1.0);
+        let mut data = Vec::with_capacity((size * size * 4) as usize);
+
+        for row in 0..size {
+            let z = tile_z as f32 - half_size + row as f32 * texel_size;
+            for column in 0..size {
+                let x = tile_x as f32 - half_size + column as f32 * texel_size;
+                let normal = Vec3::new(
+                    self.height_at(x - step, z) - self.height_at(x + step, z),
+                    2.0 * step,
+                    self.height_at(x, z - step) - self.height_at(x, z + step),
+                )
+                .normalize();
+                data.extend_from_slice(&[
+                    ((normal.x * 0.5 + 0.5) * 255.0).round() as u8,
+                    ((normal.y * 0.5 + 0.5) * 255.0).round() as u8,
+                    ((normal.z * 0.5 + 0.5) * 255.0).round() as u8,
+                    255,
+                ]);
+            }
+        }
+
+        Some(Texture {
+            width: size,
+            height: size,
+            depth: 1,
+            data,
+            buffer: TextureBuffer::new(StorageTextureAccess::Read, TextureFormat::rgba_u8norm()),
+            ..Default::default()
+        })
+    }
+
+    /// Bakes ambient occlusion texture of the tile, if `ambient_occlusion` is set
+    pub fn bake_tile_ao_map(&self, tile_x: i32, tile_z: i32, lod: usize) -> Option<Texture> {
+        if self.voxel.is_some() || self.gpu.is_some() {
+            return None;
+        }
+        self.ambient_occlusion
+            .as_ref()
+            .map(|ambient_occlusion| ambient_occlusion.bake(self, tile_x, tile_z, lod))
+    }
+
+    /// Returns bilinear interpolated height at the world position
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let size = self.heightmap.size();
+        if size == 0 {
+            return 0.0;
+        }
+        let max = (size - 1) as f32;
+        let half_world_size = ((size - 1) / 2) as f32;
+        let map_x = (x + half_world_size).clamp(0.0, max);
+        let map_z = (z + half_world_size).clamp(0.0, max);
+        let x0 = map_x.floor() as usize;
+        let z0 = map_z.floor() as usize;
+        let x1 = (x0 + 1).min(size - 1);
+        let z1 = (z0 + 1).min(size - 1);
+        let tx = map_x - x0 as f32;
+        let tz = map_z - z0 as f32;
+
+        let h00 = self.heightmap.value(x0, z0);
+        let h10 = self.heightmap.value(x1, z0);
+        let h01 = self.heightmap.value(x0, z1);
+        let h11 = self.heightmap.value(x1, z1);
+
+        (h00 * (1.0 - tx) + h10 * tx) * (1.0 - tz) + (h01 * (1.0 - tx) + h11 * tx) * tz
+    }
+
+    /// Extracts heightfield of the world space region (`[min_x, min_z, max_x, max_z]`)
+    ///
+    /// The heightfield matches the tiles geometry on the highest level of details. Returns `None`
+    /// if the region is out of the heightmap or the terrain heights are not known on CPU (voxel
+    /// and GPU generated terrains).
+    pub fn collider_heightfield(&self, region: [f32; 4]) -> Option<HeightFieldData> {
+        let size = self.heightmap.size();
+        if self.voxel.is_some() || self.gpu.is_some() || size == 0 {
+            return None;
+        }
+        let half_world_size = ((size - 1) / 2) as f32;
+        let max = (size - 1) as f32;
+        let from_x = (region[0] + half_world_size).floor().max(0.0);
+        let from_z = (region[1] + half_world_size).floor().max(0.0);
+        let to_x = (region[2] + half_world_size).ceil().min(max);
+        let to_z = (region[3] + half_world_size).ceil().min(max);
+        if to_x <= from_x || to_z <= from_z {
+            return None;
+        }
+        let (from_x, from_z) = (from_x as usize, from_z as usize);
+        let (to_x, to_z) = (to_x as usize, to_z as usize);
+
+        let columns = to_x - from_x + 1;
+        let rows = to_z - from_z + 1;
+        let mut heights = Vec::with_capacity(rows * columns);
+        let mut holes = Vec::with_capacity(rows * columns);
+        for z in from_z..=to_z {
+            for x in from_x..=to_x {
+                heights.push(self.heightmap.value(x, z));
+                holes.push(self.heightmap.is_hole(x, z));
+            }
+        }
+        if !holes.iter().any(|&hole| hole) {
+            holes.clear();
+        }
+
+        Some(HeightFieldData {
+            rows,
+            columns,
+            heights,
+            scale: [1.0, 1.0, 1.0],
+            origin: [
+                from_x as f32 - half_world_size,
+                from_z as f32 - half_world_size,
+            ],
+            holes,
+        })
+    }
+
+    /// Sculpts the terrain with the brush at the world position
+    ///
+    /// Only tiles touched by the brush get regenerated. Returns `false` if the heightmap can not be
+    /// sculpted.
+    pub fn sculpt(&mut self, brush: &Brush, x: f32, z: f32) -> bool {
+        let half_world_size = (self.heightmap.size().saturating_sub(1) / 2) as f32;
+        let sculpted = self
+            .heightmap
+            .downcast_mut::<Generator>()
+            .map(|generator| generator.sculpt(brush, x + half_world_size, z + half_world_size))
+            .unwrap_or(false);
+
+        if sculpted {
+            self.collect_dirty_regions();
+        }
+        sculpted
+    }
+
+    /// Carves the road or path spline into the heightmap
+    ///
+    /// Only tiles touched by the spline get regenerated. Returns `false` if the heightmap can not
+    /// be carved.
+    pub fn carve(&mut self, spline: &Spline) -> bool {
+        let half_world_size = (self.heightmap.size().saturating_sub(1) / 2) as f32;
+        let carved = self
+            .heightmap
+            .downcast_mut::<Generator>()
+            .map(|generator| generator.carve(spline, half_world_size))
+            .unwrap_or(false);
+
+        if carved {
+            self.collect_dirty_regions();
+        }
+        carved
+    }
+
+    /// Enables per-vertex colors of the tiles and respawns the terrain
+    ///
+    /// All vertices start white without wetness, so the terrain looks the same until painted.
+    pub fn enable_vertex_colors(&mut self) {
+        let size = self.heightmap.size();
+        if self.vertex_colors.is_none() {
+            self.vertex_colors = Some(vec![DEFAULT_VERTEX_COLOR; size * size]);
+            self.force_spawn = true;
+            // cached meshes have no color attribute
+            if let Some(cache) = self.cache.as_mut() {
+                cache.clear();
+            }
+        }
+    }
+
+    /// Returns the vertex color nearest to the world X and Z pair, if vertex colors are enabled
+    pub fn vertex_color(&self, x: f32, z: f32) -> Option<[f32; 4]> {
+        let colors = self.vertex_colors.as_ref()?;
+        let size = self.heightmap.size();
+        let half_world_size = (size.saturating_sub(1) / 2) as f32;
+        let map_x = (x + half_world_size).round();
+        let map_z = (z + half_world_size).round();
+        if map_x < 0.0 || map_z < 0.0 {
+            return None;
+        }
+        colors.get(map_x as usize * size + map_z as usize).copied()
+    }
+
+    /// Paints the vertex colors with the brush
+    ///
+    /// RGB channels tint the albedo, alpha channel makes the surface wet. Brush `strength` is
+    /// the blending factor. Only tiles touched by the brush get regenerated. Returns `false` if
+    /// vertex colors are not enabled.
+    pub fn paint_vertex_colors(&mut self, brush: &Brush, x: f32, z: f32, color: [f32; 4]) -> bool {
+        let size = self.heightmap.size();
+        let half_world_size = (size.saturating_sub(1) / 2) as f32;
+        let (map_x, map_z) = (x + half_world_size, z + half_world_size);
+        let painted = self
+            .vertex_colors
+            .as_mut()
+            .map(|colors| brush.paint(colors, size, map_x, map_z, color))
+            .unwrap_or(false);
+
+        if painted {
+            let max = (size - 1) as f32;
+            self.dirty_color_regions.push([
+                (map_x - brush.radius).floor().clamp(0.0, max) as usize,
+                (map_z - brush.radius).floor().clamp(0.0, max) as usize,
+                (map_x + brush.radius).ceil().clamp(0.0, max) as usize,
+                (map_z + brush.radius).ceil().clamp(0.0, max) as usize,
+            ]);
+            self.collect_dirty_regions();
+        }
+        painted
+    }
+
+    /// Blends the authored heightmap stamp into the terrain
+    ///
+    /// Only tiles touched by the stamp get regenerated. Returns `false` if the heightmap can not
+    /// be stamped.
+    pub fn stamp(&mut self, stamp: &Stamp) -> bool {
+        let half_world_size = (self.heightmap.size().saturating_sub(1) / 2) as f32;
+        let stamped = self
+            .heightmap
+            .downcast_mut::<Generator>()
+            .map(|generator| generator.stamp(stamp, half_world_size))
+            .unwrap_or(false);
+
+        if stamped {
+            self.collect_dirty_regions();
+        }
+        stamped
+    }
+
+    /// Marks or unmarks heightmap values in the radius around the world position as holes
+    ///
+    /// Returns `false` if the heightmap does not support holes.
+    pub fn set_hole(&mut self, x: f32, z: f32, radius: f32, hole: bool) -> bool {
+        let half_world_size = (self.heightmap.size().saturating_sub(1) / 2) as f32;
+        let generator = match self.heightmap.downcast_mut::<Generator>() {
+            Some(generator) => generator,
+            None => return false,
+        };
+        let map_x = x + half_world_size;
+        let map_z = z + half_world_size;
+        let radius_sq = radius * radius;
+        let from_x = (map_x - radius).floor().max(0.0) as usize;
+        let to_x = (map_x + radius).ceil().max(0.0) as usize;
+        let from_z = (map_z - radius).floor().max(0.0) as usize;
+        let to_z = (map_z + radius).ceil().max(0.0) as usize;
+
+        for hx in from_x..=to_x {
+            for hz in from_z..=to_z {
+                let dx = hx as f32 - map_x;
+                let dz = hz as f32 - map_z;
+                if dx * dx + dz * dz <= radius_sq {
+                    generator.set_hole(hx, hz, hole);
+                }
+            }
+        }
+
+        self.collect_dirty_regions();
+        true
+    }
+
+    /// Saves heights, holes and painted layers weights of the world space region into the writer
+    ///
+    /// Heights are stored as deltas from the procedural base, so the generator must have one,
+    /// see [`Generator::set_base`].
+    pub fn save_region<W: Write>(
+        &self,
+        writer: W,
+        min: [f32; 2],
+        max: [f32; 2],
+        layers: &Layers,
+    ) -> Result<(), RegionError> {
+        let generator = self
+            .heightmap
+            .downcast_ref::<Generator>()
+            .ok_or(RegionError::NotSupported)?;
+        if generator.base_map.is_none() {
+            return Err(RegionError::MissingBase);
+        }
+        let half_world_size = (generator.size.saturating_sub(1) / 2) as f32;
+        let from_x = (min[0] + half_world_size).floor().max(0.0) as usize;
+        let from_z = (min[1] + half_world_size).floor().max(0.0) as usize;
+        let to_x = (max[0] + half_world_size).ceil().max(0.0) as usize;
+        let to_z = (max[1] + half_world_size).ceil().max(0.0) as usize;
+        if to_x < from_x || to_z < from_z {
+            return Err(RegionError::OutOfBounds);
+        }
+        let mut region = generator
+            .region(from_x, from_z, to_x - from_x + 1, to_z - from_z + 1)
+            .ok_or(RegionError::OutOfBounds)?;
+        region.splat = layers.splat_weights(min, max);
+        region.write(writer)
+    }
+
+    /// Loads heights, holes and painted layers weights of the region from the reader
+    ///
+    /// Only tiles covered by the region get regenerated
+    pub fn load_region<R: Read>(
+        &mut self,
+        reader: R,
+        layers: &mut Layers,
+    ) -> Result<(), RegionError> {
+        let region = Region::read(reader)?;
+        let generator = self
+            .heightmap
+            .downcast_mut::<Generator>()
+            .ok_or(RegionError::NotSupported)?;
+        // nothing is applied unless both heights and weights fit
+        generator.validate_region(&region)?;
+        if let Some(splat) = region.splat.as_ref() {
+            layers.validate_splat_weights(splat)?;
+        }
+        generator.set_region(&region)?;
+        if let Some(splat) = region.splat.as_ref() {
+            layers.set_splat_weights(splat)?;
+        }
+        self.collect_dirty_regions();
+        Ok(())
+    }
+
+    /// Generates water mesh of the tile, the depth of the water is stored in the second attribute
+    ///
+    /// Returns `None` if the whole tile is above the sea level or the terrain heights are not
+    /// known on CPU (voxel and GPU generated terrains).
+    pub fn generate_water_mesh(
+        &self,
+        water: &Water,
+        tile_x: i32,
+        tile_z: i32,
+        lod: usize,
+    ) -> Option<Mesh> {
+        if self.voxel.is_some() || self.gpu.is_some() || self.heightmap.size() == 0 {
+            return None;
+        }
+        let resolution = water.resolution.max(1);
+        let vertices_per_side = resolution + 1;
+        let half_size = self.tile_half_size(lod);
+        let step = 2.0 * half_size / resolution as f32;
+        let (tile_x, tile_z) = (tile_x as f32, tile_z as f32);
+
+        let capacity = vertices_per_side * vertices_per_side;
+        let mut positions = Vec::with_capacity(capacity);
+        let mut depths = Vec::with_capacity(capacity);
+        let mut submerged = false;
+        for z in 0..vertices_per_side {
+            let world_z = tile_z - half_size + z as f32 * step;
+            for x in 0..vertices_per_side {
+                let world_x = tile_x - half_size + x as f32 * step;
+                let depth = water.sea_level - self.height_at(world_x, world_z);
+                submerged |= depth > 0.0;
+                positions.push([world_x, water.sea_level, world_z]);
+                depths.push(depth);
+            }
+        }
+
+        if !submerged {
+            return None;
+        }
+
+        let mut indices = Vec::with_capacity(3 * 2 * resolution * resolution);
+        for z in 0..resolution {
+            let i = (z * vertices_per_side) as u32;
+            for x in 0..resolution {
+                let i00 = i + x as u32;
+                let i10 = i00 + 1;
+                let i01 = i00 + vertices_per_side as u32;
+                let i11 = i01 + 1;
+                let quad = [i00, i10, i01, i11];
+                // skip quads above the terrain
+                if quad.iter().all(|&i| depths[i as usize] <= 0.0) {
+                    continue;
+                }
+                indices.extend_from_slice(&[i10, i00, i01, i10, i01, i11]);
+            }
+        }
+
+        let mut mesh = Mesh::default();
+        mesh.with_vertices(&positions);
+        mesh.with_vertices(&depths);
+        mesh.with_indices(&indices);
+        Some(mesh)
+    }
+
+    /// Returns world space axis aligned bounding box of the tile with the mesh
+    pub fn tile_bounds(&self, tile_x: i32, tile_z: i32, lod: usize, mesh: &Mesh) -> [Vec3; 2] {
+        let half_size = self.tile_half_size(lod);
+        let (min_y, max_y) = if let Some(voxel) = self.voxel.as_ref() {
+            (voxel.min_y, voxel.max_y)
+        } else if let Some(gpu) = self.gpu.as_ref() {
+            // heights are displaced on GPU
+            (
+                gpu.min_height - gpu.amplitude.abs(),
+                gpu.min_height + gpu.amplitude.abs(),
+            )
+        } else {
+            mesh.vertices_as::<[f32; 3]>(0)
+                .fold(None, |range: Option<(f32, f32)>, position| {
+                    let y = position[1];
+                    Some(range.map_or((y, y), |(min, max)| (min.min(y), max.max(y))))
+                })
+                .unwrap_or((0.0, 0.0))
+        };
+        let (x, z) = (tile_x as f32, tile_z as f32);
+        [
+            Vec3::new(x - half_size, min_y, z - half_size),
+            Vec3::new(x + half_size, max_y, z + half_size),
+        ]
+    }
+
+    /// Sets how far the terrain tiles should be spawned
+    pub fn set_view_distance(&mut self, view_distance: f32) {
+        self.view_distance = view_distance;
+    }
+
+    /// Sets how far from the viewer the despawned tiles are kept in the cache
+    pub fn set_cache_distance(&mut self, cache_distance: f32) {
+        self.cache_distance = cache_distance;
+    }
+
+    /// Sets maximal distances to the tiles of each LOD
+    pub fn set_lod_distances(&mut self, lod_distances: Vec<f32>) {
+        self.lod_distances = lod_distances;
+    }
+
+    /// Sets maximal number of tiles spawned per frame, 0 means no limit
+    pub fn set_max_tiles_per_frame(&mut self, max_tiles_per_frame: usize) {
+        self.max_tiles_per_frame = max_tiles_per_frame;
+    }
+
+    /// Returns maximal distance to the tiles of the LOD
+    pub fn lod_distance(&self, lod: usize) -> f32 {
+        self.lod_distances
+            .get(lod)
+            .copied()
+            .unwrap_or_else(|| 2.0 * self.tile_half_size(lod + 1))
+    }
+
+    /// Sets the number of LODs and how many times the tile side grows with each of them
+    ///
+    /// `lod_scale` must be even, so the tiles of neighbour LODs do not share centers: odd values
+    /// are rounded up and values less than 2 are replaced with 2. Tiles switch LODs without
+    /// geomorphing, the transition region around the LOD boundaries is [`Terrain::hysteresis`].
+    pub fn set_lod_levels(&mut self, max_lod: usize, lod_scale: usize) {
+        self.max_lod = max_lod;
+        self.lod_scale = even_lod_scale(lod_scale);
+    }
+
+    /// Returns the distance between the vertices of the LOD tile in world units
+    pub fn lod_step(&self, lod: usize) -> usize {
+        even_lod_scale(self.lod_scale).pow(lod as u32)
+    }
+
+    /// Returns half of the tile side length in world units
+    pub fn tile_half_size(&self, lod: usize) -> f32 {
+        self.tile_size as f32 / 2.0 * self.lod_step(lod) as f32
+    }
+
+    /// Moves changed regions of the heightmap and vertex colors into the world space
+    /// `dirty_regions`
+    ///
+    /// Regions are extended by one value, so the normals of the neighbours get updated too.
+    pub fn collect_dirty_regions(&mut self) {
+        let mut regions = self.heightmap.dirty_regions();
+        regions.append(&mut self.dirty_color_regions);
+        if regions.is_empty() {
+            return;
+        }
+        let half_world_size = (self.heightmap.size().saturating_sub(1) / 2) as f32;
+        for region in regions {
+            self.dirty_regions.push([
+                region[0] as f32 - half_world_size - 1.0,
+                region[1] as f32 - half_world_size - 1.0,
+                region[2] as f32 - half_world_size + 1.0,
+                region[3] as f32 - half_world_size + 1.0,
+            ]);
+        }
+        self.heightmap.clear_dirty_regions();
+    }
+
+    /// Removes cached meshes of the tiles intersecting the dirty regions
+    pub fn invalidate_cached_tiles(&mut self) {
+        if let Some(mut cache) = self.cache.take() {
+            cache.retain(|x, z, lod| !self.is_tile_dirty(x, z, lod));
+            self.cache = Some(cache);
+        }
+    }
+
+    /// Checks if the tile intersects any of the dirty regions
+    pub fn is_tile_dirty(&self, x: i32, z: i32, lod: usize) -> bool {
+        let half_size = self.tile_half_size(lod);
+        let (x, z) = (x as f32, z as f32);
+        self.dirty_regions.iter().any(|region| {
+            x - half_size <= region[2]
+                && x + half_size >= region[0]
+                && z - half_size <= region[3]
+                && z + half_size >= region[1]
+        })
+    }
+
     /// Calculates texture UV for specific height value
     pub fn uv_from_height(&self, height: f32) -> [f32; 2] {
         let mut i = 0.0;
@@ -149,3 +794,200 @@ impl Default for Terrain {
         Self::new(Box::new(Generator::default()), texture_heights)
     }
 }
+
+/// Rounds the LOD scale up to the nearest even value, not less than 2
+fn even_lod_scale(lod_scale: usize) -> usize {
+    (lod_scale.max(2) + 1) & !1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn painted_vertex_colors_mark_touched_tiles() {
+        let size = 65;
+        let generator = Generator {
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 16;
+
+        let brush = Brush::new(crate::Sculpt::Raise, 2.0, 1.0);
+        assert!(!terrain.paint_vertex_colors(&brush, 0.0, 0.0, [1.0, 0.0, 0.0, 1.0]));
+        assert!(terrain.dirty_regions.is_empty());
+
+        terrain.enable_vertex_colors();
+        assert!(terrain.paint_vertex_colors(&brush, 0.0, 0.0, [1.0, 0.0, 0.0, 1.0]));
+        assert!(terrain.dirty_color_regions.is_empty());
+        assert_eq!(terrain.dirty_regions, vec![[-3.0, -3.0, 3.0, 3.0]]);
+        assert!(terrain.is_tile_dirty(0, 0, 0));
+        assert!(!terrain.is_tile_dirty(32, 32, 0));
+        assert_eq!(terrain.vertex_color(0.0, 0.0), Some([1.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn lod_scale_is_even() {
+        assert_eq!(even_lod_scale(0), 2);
+        assert_eq!(even_lod_scale(1), 2);
+        assert_eq!(even_lod_scale(2), 2);
+        assert_eq!(even_lod_scale(3), 4);
+        assert_eq!(even_lod_scale(4), 4);
+        assert_eq!(even_lod_scale(7), 8);
+
+        let mut terrain = Terrain::new(Box::new(Generator::default()), vec![]);
+        terrain.set_lod_levels(3, 3);
+        assert_eq!((terrain.max_lod, terrain.lod_scale), (3, 4));
+        // the field can be set directly, so the step rounds it too
+        terrain.lod_scale = 5;
+        assert_eq!(terrain.lod_step(2), 36);
+    }
+
+    #[test]
+    fn lod_step_scales_tiles_and_distances() {
+        let mut terrain = Terrain::new(Box::new(Generator::default()), vec![]);
+        terrain.tile_size = 16;
+        terrain.set_lod_levels(3, 4);
+        assert_eq!(terrain.lod_step(0), 1);
+        assert_eq!(terrain.lod_step(1), 4);
+        assert_eq!(terrain.lod_step(3), 64);
+        assert_eq!(terrain.tile_half_size(0), 8.0);
+        assert_eq!(terrain.tile_half_size(2), 128.0);
+
+        // missing distances default to the tile side of the next LOD
+        assert_eq!(terrain.lod_distance(0), 64.0);
+        assert_eq!(terrain.lod_distance(1), 256.0);
+        terrain.set_lod_distances(vec![40.0]);
+        assert_eq!(terrain.lod_distance(0), 40.0);
+        assert_eq!(terrain.lod_distance(1), 256.0);
+    }
+
+    #[test]
+    fn holes_remove_touching_triangles() {
+        let size = 17;
+        let generator = Generator {
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 4;
+        let triangles = |terrain: &Terrain| {
+            terrain
+                .generate_tile_mesh(0, 0, 0)
+                .indices()
+                .map(|indices| indices.len() / 3)
+                .unwrap_or(0)
+        };
+        assert_eq!(triangles(&terrain), 4 * 4 * 2);
+
+        // the center value is shared by four quads
+        assert!(terrain.set_hole(0.0, 0.0, 0.0, true));
+        assert_eq!(triangles(&terrain), (4 * 4 - 4) * 2);
+        assert!(terrain.is_tile_dirty(0, 0, 0));
+
+        terrain.dirty_regions.clear();
+        assert!(terrain.set_hole(0.0, 0.0, 1.0, true));
+        assert_eq!(triangles(&terrain), (4 * 4 - 12) * 2);
+
+        assert!(terrain.set_hole(0.0, 0.0, 1.0, false));
+        assert_eq!(triangles(&terrain), 4 * 4 * 2);
+    }
+
+    #[test]
+    fn gpu_generated_tiles_are_flat_grids() {
+        let size = 17;
+        let generator = Generator {
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            amplitude: 10.0,
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 4;
+        terrain.enable_vertex_colors();
+        let mesh = terrain.generate_tile_mesh(0, 0, 0);
+        assert!(mesh
+            .vertices_as::<[f32; 3]>(0)
+            .all(|position| position[1] != 0.0));
+        assert_eq!(mesh.layout.len(), 4);
+
+        terrain.gpu = Some(GpuGenerator::default());
+        let mesh = terrain.generate_tile_mesh(0, 0, 0);
+        assert!(mesh
+            .vertices_as::<[f32; 3]>(0)
+            .all(|position| position[1] == 0.0));
+        assert!(mesh
+            .vertices_as::<[f32; 3]>(1)
+            .all(|normal| normal == [0.0, 1.0, 0.0]));
+        // the GPU shader does not consume vertex colors
+        assert_eq!(mesh.layout.len(), 3);
+    }
+
+    #[test]
+    fn normal_map_follows_heightmap_slope() {
+        let size = 17;
+        // heights rise by 1.0 per unit along X
+        let noise_map = (0..size * size)
+            .map(|i| (i / size) as f32 / (size - 1) as f32)
+            .collect::<Vec<_>>();
+        let generator = Generator {
+            size,
+            amplitude: (size - 1) as f32,
+            noise_map: Some(noise_map),
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 4;
+        assert!(terrain.bake_tile_normal_map(0, 0, 0).is_none());
+
+        terrain.normal_map_size = Some(5);
+        let texture = terrain
+            .bake_tile_normal_map(0, 0, 0)
+            .expect("Normal map must be baked");
+        assert_eq!((texture.width, texture.height), (5, 5));
+        assert_eq!(texture.data.len(), 5 * 5 * 4);
+        for texel in texture.data.chunks_exact(4) {
+            assert_eq!(texel, &[37, 218, 128, 255]);
+        }
+
+        terrain.gpu = Some(GpuGenerator::default());
+        assert!(terrain.bake_tile_normal_map(0, 0, 0).is_none());
+    }
+
+    #[test]
+    fn water_mesh_covers_submerged_quads() {
+        let size = 17;
+        // heights rise by 1.0 per unit along X, from -8.0 at the west edge
+        let noise_map = (0..size * size)
+            .map(|i| (i / size) as f32 / (size - 1) as f32)
+            .collect::<Vec<_>>();
+        let generator = Generator {
+            size,
+            amplitude: (size - 1) as f32,
+            min_height: -8.0,
+            noise_map: Some(noise_map),
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 4;
+        let mut water = Water::new(0.0);
+        water.resolution = 4;
+
+        let mesh = terrain
+            .generate_water_mesh(&water, 0, 0, 0)
+            .expect("Water mesh must be generated");
+        assert!(mesh
+            .vertices_as::<[f32; 3]>(0)
+            .all(|position| position[1] == 0.0));
+        let depths = mesh.vertices_as::<f32>(1).take(5).collect::<Vec<_>>();
+        assert_eq!(depths, vec![2.0, 1.0, 0.0, -1.0, -2.0]);
+        // only two of four quads columns are below the sea level
+        assert_eq!(mesh.indices().map(|indices| indices.len()), Some(2 * 4 * 6));
+
+        water.sea_level = -3.0;
+        assert!(terrain.generate_water_mesh(&water, 0, 0, 0).is_none());
+    }
+}
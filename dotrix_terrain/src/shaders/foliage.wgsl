@@ -0,0 +1,86 @@
+// STAGE: VERTEX ---------------------------------------------------------------------------------
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+};
+
+struct Renderer {
+    proj_view: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> u_renderer: Renderer;
+
+{{ include(light) }}
+
+struct Foliage {
+    color: vec4<f32>;
+    fade_distance: vec4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> u_foliage: Foliage;
+
+struct Instance {
+    // position in XYZ, scale in W
+    position: vec4<f32>;
+    // rotation around Y axis in X
+    rotation: vec4<f32>;
+};
+
+struct Instances {
+    data: [[stride(32)]] array<Instance>;
+};
+[[group(1), binding(1)]]
+var<storage, read> s_instances: Instances;
+
+[[stage(vertex)]]
+fn vs_main(
+    [[builtin(instance_index)]] instance_index: u32,
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] normal: vec3<f32>,
+) -> VertexOutput {
+    let instance = s_instances.data[instance_index];
+    let origin = instance.position.xyz;
+
+    // shrink instances in the fading range
+    let distance = length(u_light.camera_position.xyz - origin);
+    let fade_range = max(u_foliage.fade_distance.y - u_foliage.fade_distance.x, 0.0001);
+    let fade = 1.0 - clamp((distance - u_foliage.fade_distance.x) / fade_range, 0.0, 1.0);
+    let scale = instance.position.w * fade;
+
+    let s = sin(instance.rotation.x);
+    let c = cos(instance.rotation.x);
+    let rotated_position = vec3<f32>(
+        c * position.x + s * position.z,
+        position.y,
+        c * position.z - s * position.x,
+    );
+    let rotated_normal = vec3<f32>(
+        c * normal.x + s * normal.z,
+        normal.y,
+        c * normal.z - s * normal.x,
+    );
+
+    let world_position = vec4<f32>(origin + rotated_position * scale, 1.0);
+
+    var out: VertexOutput;
+    out.world_position = world_position.xyz;
+    out.normal = normalize(rotated_normal);
+    out.position = u_renderer.proj_view * world_position;
+    return out;
+}
+
+// STAGE: FRAGMENT -------------------------------------------------------------------------------
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return calculate_lighting(
+        in.world_position,
+        in.normal,
+        u_foliage.color.rgb,
+        1.0,
+        0.0,
+        1.0,
+    );
+}
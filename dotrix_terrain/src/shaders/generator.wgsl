@@ -0,0 +1,115 @@
+// Tile heights and normals generation
+
+struct Params {
+    // tile center X, tile center Z, distance between vertices, amplitude
+    tile: vec4<f32>;
+    // scale, persistence, lacunarity, warp
+    noise: vec4<f32>;
+    // sampling offset X and Z, height offset
+    offset: vec4<f32>;
+    // vertices per side, octaves, seed, fractal
+    size: vec4<u32>;
+};
+[[group(0), binding(0)]]
+var<uniform> u_params: Params;
+
+struct Vertices {
+    // normal in XYZ, height in W
+    data: [[stride(16)]] array<vec4<f32>>;
+};
+[[group(0), binding(1)]]
+var<storage, read_write> s_vertices: Vertices;
+
+let FRACTAL_RIDGED: u32 = 1u;
+let FRACTAL_BILLOW: u32 = 2u;
+
+fn gradient(x: i32, z: i32, seed: u32) -> vec2<f32> {
+    var hash: u32 = seed ^ (bitcast<u32>(x) * 668265261u);
+    hash = (hash ^ (bitcast<u32>(z) * 374761393u)) * 2654435769u;
+    hash = hash ^ (hash >> 15u);
+    hash = hash * 2246822507u;
+    hash = hash ^ (hash >> 13u);
+    let angle = f32(hash & 65535u) / 65535.0 * 6.2831853;
+    return vec2<f32>(cos(angle), sin(angle));
+}
+
+// Gradient noise in range -1..1
+fn gradient_noise(point: vec2<f32>, seed: u32) -> f32 {
+    let cell = floor(point);
+    let f = point - cell;
+    let u = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    let x = i32(cell.x);
+    let z = i32(cell.y);
+
+    let a = dot(gradient(x, z, seed), f);
+    let b = dot(gradient(x + 1, z, seed), f - vec2<f32>(1.0, 0.0));
+    let c = dot(gradient(x, z + 1, seed), f - vec2<f32>(0.0, 1.0));
+    let d = dot(gradient(x + 1, z + 1, seed), f - vec2<f32>(1.0, 1.0));
+
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y) * 1.4142135;
+}
+
+fn height(x: f32, z: f32) -> f32 {
+    let scale = u_params.noise.x;
+    let seed = u_params.size.z;
+    let fractal = u_params.size.w;
+    var point: vec2<f32> = vec2<f32>(x, z) + u_params.offset.xy;
+
+    if (u_params.noise.w != 0.0) {
+        let warp = vec2<f32>(
+            gradient_noise(point / scale, seed + 101u),
+            gradient_noise(point.yx / scale, seed + 102u),
+        );
+        point = point + warp * u_params.noise.w;
+    }
+
+    var value: f32 = 0.0;
+    var total: f32 = 0.0;
+    var amplitude: f32 = 1.0;
+    var frequency: f32 = 1.0;
+    var octave: u32 = 0u;
+    loop {
+        if (!(octave < u_params.size.y)) { break; }
+        var noise_value: f32 = gradient_noise(point / scale * frequency, seed + octave);
+        if (fractal == FRACTAL_RIDGED) {
+            let ridge = 1.0 - abs(noise_value);
+            noise_value = ridge * ridge * 2.0 - 1.0;
+        } else {
+            if (fractal == FRACTAL_BILLOW) {
+                noise_value = abs(noise_value) * 2.0 - 1.0;
+            }
+        }
+        value = value + noise_value * amplitude;
+        total = total + amplitude;
+        amplitude = amplitude * u_params.noise.y;
+        frequency = frequency * u_params.noise.z;
+        continuing { octave = octave + 1u; }
+    }
+
+    if (total > 0.0) {
+        value = value / total;
+    }
+    return u_params.offset.z + u_params.tile.w * clamp(value, 0.0, 1.0);
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    let vertices_per_side = u_params.size.x;
+    if (index >= vertices_per_side * vertices_per_side) {
+        return;
+    }
+
+    let offset = f32((vertices_per_side - 1u) / 2u);
+    let step = u_params.tile.z;
+    let x = u_params.tile.x + (f32(index % vertices_per_side) - offset) * step;
+    let z = u_params.tile.y + (f32(index / vertices_per_side) - offset) * step;
+
+    let left = height(x - step, z);
+    let right = height(x + step, z);
+    let down = height(x, z - step);
+    let up = height(x, z + step);
+    let normal = normalize(vec3<f32>(left - right, 2.0 * step, down - up));
+
+    s_vertices.data[index] = vec4<f32>(normal, height(x, z));
+}
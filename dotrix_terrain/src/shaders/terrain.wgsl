@@ -1,76 +1,155 @@
-// STAGE: VERTEX ---------------------------------------------------------------------------------
-
-struct VertexOutput {
-    [[builtin(position)]] position: vec4<f32>;
-    [[location(0)]] world_position: vec3<f32>;
-    [[location(1)]] normal: vec3<f32>;
-    [[location(2)]] tex_uv: vec2<f32>;
-};
-
-
-struct Renderer {
-    proj_view: mat4x4<f32>;
-};
-[[group(0), binding(0)]]
-var<uniform> u_renderer: Renderer;
-
-
-[[stage(vertex)]]
-fn vs_main(
-    [[location(0)]] position: vec3<f32>,
-    [[location(1)]] normal: vec3<f32>,
-    [[location(2)]] tex_uv: vec2<f32>,
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.tex_uv = tex_uv;
-    out.normal = normalize((vec4<f32>(normal, 1.0)).xyz);
-    let world_position: vec4<f32> = vec4<f32>(position, 1.0);
-    out.world_position = world_position.xyz;
-    out.position = u_renderer.proj_view * world_position;
-    return out;
-}
+{{ include(vertex) }}
 
 
 // STAGE: FRAGMENT -------------------------------------------------------------------------------
 
 let MAX_LAYERS_COUNT: u32 = 16u;
 
+struct Material {
+    albedo: vec4<f32>;
+    has_texture: u32;
+    roughness: f32;
+    metallic: f32;
+    ao: f32;
+};
+[[group(1), binding(0)]]
+var<uniform> u_material: Material;
+
 [[group(1), binding(1)]]
 var r_texture: texture_2d<f32>;
 
 [[group(0), binding(1)]]
 var r_sampler: sampler;
 
+[[group(1), binding(2)]]
+var r_normal_map: texture_2d<f32>;
+
+[[group(1), binding(3)]]
+var r_ao_map: texture_2d<f32>;
+
+[[group(0), binding(4)]]
+var r_layers_albedo: texture_2d<f32>;
+
+[[group(0), binding(5)]]
+var r_layers_normal: texture_2d<f32>;
+
+[[group(0), binding(6)]]
+var r_layers_roughness: texture_2d<f32>;
+
+[[group(0), binding(7)]]
+var r_layers_macro: texture_2d<f32>;
+
+[[group(0), binding(8)]]
+var r_splat_map: texture_2d<f32>;
+
+{{ include(shadow) }}
+
 {{ include(light) }}
 
 struct Layer {
     color: vec4<f32>;
     height: f32;
     blend: f32;
-    unused: vec2<f32>;
+    triplanar: u32;
+    tiling: f32;
+    macro_tiling: f32;
+    roughness: f32;
+    metallic: f32;
 };
 
 struct Layers {
-    count: vec4<u32>;
-    list: [[stride(32)]] array<Layer, MAX_LAYERS_COUNT>;
+    count: u32;
+    ambient_occlusion: f32;
+    triplanar_scale: f32;
+    unused: u32;
+    macro_distance: vec2<f32>;
+    splat_region: vec4<f32>;
+    list: [[stride(48)]] array<Layer, MAX_LAYERS_COUNT>;
 };
 [[group(0), binding(3)]]
 var<uniform> u_layers: Layers;
 
+// Painted weight of the layer, splat map keeps 4 layers per cell, one in each channel
+fn splat_weight(position: vec2<f32>, layer: u32) -> f32 {
+    let region = u_layers.splat_region;
+    let size = region.zw - region.xy;
+    if (size.x <= 0.0 || size.y <= 0.0) { return 0.0; }
+    let uv = (position - region.xy) / size;
+    if (uv.x < 0.0 || uv.y < 0.0 || uv.x > 1.0 || uv.y > 1.0) { return 0.0; }
+
+    let cells = 4.0;
+    let resolution = vec2<f32>(textureDimensions(r_splat_map)) / vec2<f32>(cells, 1.0);
+    let cell_uv = (uv * (resolution - 1.0) + 0.5) / resolution;
+    let atlas_uv = vec2<f32>((f32(layer / 4u) + cell_uv.x) / cells, cell_uv.y);
+    let weights = textureSampleLevel(r_splat_map, r_sampler, atlas_uv, 0.0);
+    let channel = layer % 4u;
+    return dot(weights, vec4<f32>(
+        f32(channel == 0u),
+        f32(channel == 1u),
+        f32(channel == 2u),
+        f32(channel == 3u)
+    ));
+}
+
 fn inverse_lerp(left: f32, right: f32, value: f32) -> f32 {
     return clamp((value - left) / (right - left), 0.0, 1.0);
 }
 
+// Maps repeated UV into the cell of the layer in the atlas of square cells
+fn atlas_uv(uv: vec2<f32>, layer: u32, atlas_size: vec2<f32>) -> vec2<f32> {
+    let cells = max(atlas_size.x / atlas_size.y, 1.0);
+    let half_texel = 0.5 / atlas_size.y;
+    let cell_uv = clamp(fract(uv), vec2<f32>(half_texel), vec2<f32>(1.0 - half_texel));
+    return vec2<f32>((f32(layer) + cell_uv.x) / cells, cell_uv.y);
+}
+
+// Returns weights of the triplanar projections by the normal
+fn triplanar_weights(normal: vec3<f32>) -> vec3<f32> {
+    var weights: vec3<f32> = abs(normal);
+    weights = weights * weights * weights * weights;
+    return weights / (weights.x + weights.y + weights.z);
+}
+
+// Projects the layer albedo along the world axes, blended by the normal
+fn triplanar(position: vec3<f32>, normal: vec3<f32>, layer: u32, scale: f32) -> vec4<f32> {
+    let uv = position / scale;
+    let weights = triplanar_weights(normal);
+    let size = vec2<f32>(textureDimensions(r_layers_albedo));
+    let albedo = textureSample(r_layers_albedo, r_sampler, atlas_uv(uv.zy, layer, size)) * weights.x
+        + textureSample(r_layers_albedo, r_sampler, atlas_uv(uv.xz, layer, size)) * weights.y
+        + textureSample(r_layers_albedo, r_sampler, atlas_uv(uv.xy, layer, size)) * weights.z;
+    let base = textureSample(r_texture, r_sampler, uv.zy) * weights.x
+        + textureSample(r_texture, r_sampler, uv.xz) * weights.y
+        + textureSample(r_texture, r_sampler, uv.xy) * weights.z;
+    return albedo * base;
+}
+
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Baked normals, texels are aligned with the tile vertices
+    let normal_map_size = vec2<f32>(textureDimensions(r_normal_map));
+    let normal_uv = (in.tex_uv * (normal_map_size - 1.0) + 0.5) / normal_map_size;
+    let baked_normal: vec4<f32> = textureSample(r_normal_map, r_sampler, normal_uv);
+    let vertex_normal = normalize(mix(in.normal, baked_normal.xyz * 2.0 - 1.0, baked_normal.a));
+
     let texture_color: vec4<f32> = textureSample(r_texture, r_sampler, in.tex_uv / 0.5);
-    var albedo_color: vec4<f32> = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    var albedo_color: vec4<f32> = texture_color;
+    var detail_normal: vec3<f32> = vec3<f32>(0.0, 0.0, 1.0);
+    var roughness: f32 = 1.0;
+    var metallic: f32 = 0.0;
+
+    let albedo_size = vec2<f32>(textureDimensions(r_layers_albedo));
+    let normal_size = vec2<f32>(textureDimensions(r_layers_normal));
+    let roughness_size = vec2<f32>(textureDimensions(r_layers_roughness));
+    let macro_size = vec2<f32>(textureDimensions(r_layers_macro));
+    let camera_distance = length(u_light.camera_position.xyz - in.world_position);
+    let macro_strength = inverse_lerp(u_layers.macro_distance.x, u_layers.macro_distance.y, camera_distance);
 
     var i: u32 = 0u;
-    var count: u32 = min(u32(u_layers.count.x), MAX_LAYERS_COUNT);
+    var count: u32 = min(u_layers.count, MAX_LAYERS_COUNT);
 
     // Terrain Types
-    let max_height: f32 = 300.0;
+    let max_height: f32 = 300.0; // keep in sync with `layers::MAX_HEIGHT`
     let height_blend: f32 = 0.02;
     let epsilon: f32 = 0.0001;
     let height_percent: f32 = inverse_lerp(0.0, max_height, in.world_position.y);
@@ -78,22 +157,70 @@ fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
     // Apply terrain layers
     loop {
         if (!(i < count)) { break; }
-        let half_height_blend = u_layers.list[i].blend / 2.0;
-        let color_strength = inverse_lerp(
+        let layer = u_layers.list[i];
+        let half_height_blend = layer.blend / 2.0;
+        let color_strength = max(inverse_lerp(
             -half_height_blend - epsilon,
             half_height_blend,
-            height_percent - u_layers.list[i].height
-        );
-
-        albedo_color = albedo_color * (1.0 - color_strength) + u_layers.list[i].color * color_strength;
+            height_percent - layer.height
+        ), splat_weight(in.world_position.xz, i));
+
+        var detail_uv: vec2<f32> = in.tex_uv / 0.5;
+        if (layer.tiling > 0.0) {
+            detail_uv = in.world_position.xz / layer.tiling;
+        }
+
+        var layer_texture_color: vec4<f32> = texture_color
+            * textureSample(r_layers_albedo, r_sampler, atlas_uv(detail_uv, i, albedo_size));
+        if (layer.triplanar != 0u) {
+            var scale: f32 = u_layers.triplanar_scale;
+            if (layer.tiling > 0.0) {
+                scale = layer.tiling;
+            }
+            layer_texture_color = triplanar(in.world_position.xyz, vertex_normal, i, scale);
+        }
+
+        let macro_uv = in.world_position.xz / max(layer.macro_tiling, epsilon);
+        let macro_color = textureSample(r_layers_macro, r_sampler, atlas_uv(macro_uv, i, macro_size));
+        layer_texture_color = layer_texture_color * mix(vec4<f32>(1.0), macro_color, macro_strength);
+
+        let layer_normal = textureSample(r_layers_normal, r_sampler, atlas_uv(detail_uv, i, normal_size)).xyz * 2.0 - 1.0;
+        let layer_roughness = textureSample(r_layers_roughness, r_sampler, atlas_uv(detail_uv, i, roughness_size)).r;
+
+        albedo_color = albedo_color * (1.0 - color_strength)
+            + layer.color * layer_texture_color * color_strength;
+        detail_normal = mix(detail_normal, layer_normal, color_strength);
+        roughness = mix(roughness, layer_roughness * layer.roughness, color_strength);
+        metallic = mix(metallic, layer.metallic, color_strength);
         continuing { i = i + 1u; }
     }
 
-    // Light
-    let light_color: vec4<f32> = calculate_light(in.world_position.xyz, in.normal);
-
-    return vec4<f32>(albedo_color.rgb * texture_color.rgb * light_color.rgb, 1.0);
-
-    //mag: f32 = length(v_TexCoord-vec2(0.5));
-    // o_Target = vec4(mix(result_color.xyz, vec3(0.0), mag*mag), 1.0);
+    // Vertex colors tint the albedo, wet surface is darker and smoother
+    albedo_color = vec4<f32>(albedo_color.rgb * in.color.rgb * (1.0 - 0.5 * in.color.a), albedo_color.a);
+    roughness = roughness * (1.0 - 0.8 * in.color.a);
+
+    // Detail normal is in the tangent space of the XZ plane
+    let tangent = normalize(vec3<f32>(1.0, 0.0, 0.0) - vertex_normal * vertex_normal.x);
+    let bitangent = cross(tangent, vertex_normal);
+    let normal = normalize(
+        tangent * detail_normal.x + bitangent * detail_normal.y + vertex_normal * detail_normal.z
+    );
+
+    // Baked ambient occlusion
+    let ao_map_size = vec2<f32>(textureDimensions(r_ao_map));
+    let ao_uv = (in.tex_uv * (ao_map_size - 1.0) + 0.5) / ao_map_size;
+    let ao: f32 = mix(1.0, textureSample(r_ao_map, r_sampler, ao_uv).r, u_layers.ambient_occlusion);
+
+    // Light, the same PBR model as for the `dotrix_pbr` materials
+    let light_color: vec4<f32> = calculate_shadowed_lighting(
+        in.world_position.xyz,
+        normal,
+        albedo_color.rgb * u_material.albedo.rgb,
+        clamp(roughness * u_material.roughness, 0.0, 1.0),
+        clamp(metallic * u_material.metallic, 0.0, 1.0),
+        ao,
+        calculate_shadow(in.world_position.xyz),
+    );
+
+    return vec4<f32>(light_color.rgb, 1.0);
 }
@@ -0,0 +1,59 @@
+// STAGE: VERTEX ---------------------------------------------------------------------------------
+// VERTEX_COLOR: vertex colors in the location 3, GPU_HEIGHTS: heights generated on GPU
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] tex_uv: vec2<f32>;
+    [[location(3)]] color: vec4<f32>;
+};
+
+
+struct Renderer {
+    proj_view: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> u_renderer: Renderer;
+
+#ifdef GPU_HEIGHTS
+struct Vertices {
+    // normal in XYZ, height in W
+    data: [[stride(16)]] array<vec4<f32>>;
+};
+[[group(1), binding(4)]]
+var<storage, read_write> s_vertices: Vertices;
+#endif
+
+
+[[stage(vertex)]]
+fn vs_main(
+#ifdef GPU_HEIGHTS
+    [[builtin(vertex_index)]] vertex_index: u32,
+#endif
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] normal: vec3<f32>,
+    [[location(2)]] tex_uv: vec2<f32>,
+#ifdef VERTEX_COLOR
+    [[location(3)]] color: vec4<f32>,
+#endif
+) -> VertexOutput {
+    var out: VertexOutput;
+#ifdef VERTEX_COLOR
+    out.color = color;
+#else
+    out.color = vec4<f32>(1.0, 1.0, 1.0, 0.0);
+#endif
+    out.tex_uv = tex_uv;
+#ifdef GPU_HEIGHTS
+    let vertex = s_vertices.data[vertex_index];
+    out.normal = normalize(vertex.xyz);
+    let world_position: vec4<f32> = vec4<f32>(position.x, vertex.w, position.z, 1.0);
+#else
+    out.normal = normalize((vec4<f32>(normal, 1.0)).xyz);
+    let world_position: vec4<f32> = vec4<f32>(position, 1.0);
+#endif
+    out.world_position = world_position.xyz;
+    out.position = u_renderer.proj_view * world_position;
+    return out;
+}
@@ -0,0 +1,67 @@
+// STAGE: VERTEX ---------------------------------------------------------------------------------
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] depth: f32;
+};
+
+struct Renderer {
+    proj_view: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> u_renderer: Renderer;
+
+{{ include(light) }}
+
+struct Water {
+    shallow_color: vec4<f32>;
+    deep_color: vec4<f32>;
+    depth: f32;
+    wave_length: f32;
+    wave_speed: f32;
+    wave_strength: f32;
+    time: f32;
+};
+[[group(0), binding(2)]]
+var<uniform> u_water: Water;
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] depth: f32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.world_position = position;
+    out.depth = depth;
+    out.position = u_renderer.proj_view * vec4<f32>(position, 1.0);
+    return out;
+}
+
+// STAGE: FRAGMENT -------------------------------------------------------------------------------
+
+// Slope of the wave travelling in the direction
+fn wave_slope(position: vec2<f32>, direction: vec2<f32>, length: f32) -> vec2<f32> {
+    let k = 6.2831853 / length;
+    let phase = k * (dot(position, direction) - u_water.wave_speed * u_water.time);
+    return direction * cos(phase);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let length = max(u_water.wave_length, 0.0001);
+    let position = in.world_position.xz;
+    let slope = wave_slope(position, vec2<f32>(0.8, 0.6), length)
+        + wave_slope(position, vec2<f32>(-0.6, 0.8), length * 0.61)
+        + wave_slope(position, vec2<f32>(0.28, -0.96), length * 0.37);
+    let normal = normalize(vec3<f32>(-slope.x * u_water.wave_strength, 1.0, -slope.y * u_water.wave_strength));
+
+    // blend the shore by the depth of the water above the terrain
+    let depth = clamp(in.depth / max(u_water.depth, 0.0001), 0.0, 1.0);
+    let color = mix(u_water.shallow_color, u_water.deep_color, depth);
+    let lit = calculate_lighting(in.world_position, normal, color.rgb, 0.1, 0.0, 1.0);
+    let alpha = color.a * clamp(in.depth * 4.0, 0.0, 1.0);
+
+    // premultiplied alpha
+    return vec4<f32>(lit.rgb * alpha, alpha);
+}
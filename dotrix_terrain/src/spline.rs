@@ -0,0 +1,250 @@
+/// Road or path curve carved into the terrain
+///
+/// The curve is a Catmull-Rom spline through the control points. Heights of the terrain are
+/// flattened to the curve height inside of the `width` and smoothly blended with the original
+/// heights over the `falloff` distance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spline {
+    /// Control points in world coordinates, Y is the height of the road
+    pub points: Vec<[f32; 3]>,
+    /// Width of the flat part of the road in world units
+    pub width: f32,
+    /// Distance from the road edge, where the heights are blended with the terrain
+    pub falloff: f32,
+    /// Number of curve samples between two control points
+    pub segments: usize,
+}
+
+impl Spline {
+    /// Constructs new spline from the control points
+    pub fn new(points: Vec<[f32; 3]>, width: f32) -> Self {
+        Self {
+            points,
+            width,
+            ..Default::default()
+        }
+    }
+
+    /// Returns distance from the curve center to the outer edge of the falloff
+    pub fn radius(&self) -> f32 {
+        self.width / 2.0 + self.falloff
+    }
+
+    /// Returns world space bounds (`[min_x, min_z, max_x, max_z]`) of the affected area
+    pub fn bounds(&self) -> Option<[f32; 4]> {
+        let radius = self.radius();
+        let mut points = self.points.iter();
+        let first = points.next()?;
+        let mut bounds = [first[0], first[2], first[0], first[2]];
+        for point in points {
+            bounds[0] = bounds[0].min(point[0]);
+            bounds[1] = bounds[1].min(point[2]);
+            bounds[2] = bounds[2].max(point[0]);
+            bounds[3] = bounds[3].max(point[2]);
+        }
+        Some([
+            bounds[0] - radius,
+            bounds[1] - radius,
+            bounds[2] + radius,
+            bounds[3] + radius,
+        ])
+    }
+
+    /// Returns points of the curve polyline
+    pub fn samples(&self) -> Vec<[f32; 3]> {
+        let count = self.points.len();
+        if count < 3 {
+            return self.points.clone();
+        }
+        let segments = self.segments.max(1);
+        let mut samples = Vec::with_capacity((count - 1) * segments + 1);
+        for i in 0..count - 1 {
+            let p0 = self.points[i.saturating_sub(1)];
+            let p1 = self.points[i];
+            let p2 = self.points[i + 1];
+            let p3 = self.points[(i + 2).min(count - 1)];
+            for s in 0..segments {
+                let t = s as f32 / segments as f32;
+                samples.push(catmull_rom(p0, p1, p2, p3, t));
+            }
+        }
+        samples.push(self.points[count - 1]);
+        samples
+    }
+
+    /// Returns the road weight (0.0..1.0) at the distance from the curve center
+    pub fn weight(&self, distance: f32) -> f32 {
+        let half_width = self.width / 2.0;
+        if distance <= half_width {
+            return 1.0;
+        }
+        if self.falloff <= 0.0 || distance >= half_width + self.falloff {
+            return 0.0;
+        }
+        let t = (distance - half_width) / self.falloff;
+        1.0 - t * t * (3.0 - 2.0 * t)
+    }
+
+    /// Flattens the square map of heights with `size` values per side along the curve
+    ///
+    /// Values are indexed as `x * size + z`. `offset` converts world coordinates into map ones,
+    /// `base` is the world height of the map value 0.0 and `scale` converts world units of height
+    /// into map values. Returns `false` if the curve does not touch the map.
+    pub(crate) fn apply(
+        &self,
+        map: &mut [f32],
+        size: usize,
+        offset: f32,
+        base: f32,
+        scale: f32,
+    ) -> bool {
+        let samples = self.samples();
+        self.rasterize(&samples, size, offset, |i, weight, height| {
+            map[i] += ((height - base) * scale - map[i]) * weight;
+        })
+    }
+
+    /// Writes the road weights into the square mask with `size` values per side
+    ///
+    /// The mask is indexed the same way as the heightmap and keeps the maximal weight, so it can
+    /// be used as a texturing layer or inverted as a scattering density map.
+    pub fn paint(&self, mask: &mut [f32], size: usize, offset: f32) -> bool {
+        let samples = self.samples();
+        self.rasterize(&samples, size, offset, |i, weight, _| {
+            mask[i] = mask[i].max(weight);
+        })
+    }
+
+    fn rasterize<F>(&self, samples: &[[f32; 3]], size: usize, offset: f32, mut write: F) -> bool
+    where
+        F: FnMut(usize, f32, f32),
+    {
+        let bounds = match self.bounds() {
+            Some(bounds) if size > 0 => bounds,
+            _ => return false,
+        };
+        let max = (size - 1) as f32;
+        let from_x = (bounds[0] + offset).floor().max(0.0);
+        let from_z = (bounds[1] + offset).floor().max(0.0);
+        let to_x = (bounds[2] + offset).ceil().min(max);
+        let to_z = (bounds[3] + offset).ceil().min(max);
+        if from_x > to_x || from_z > to_z {
+            return false;
+        }
+
+        let mut touched = false;
+        for x in from_x as usize..=to_x as usize {
+            for z in from_z as usize..=to_z as usize {
+                let (distance, height) =
+                    match nearest(samples, x as f32 - offset, z as f32 - offset) {
+                        Some(nearest) => nearest,
+                        None => continue,
+                    };
+                let weight = self.weight(distance);
+                if weight > 0.0 {
+                    write(x * size + z, weight, height);
+                    touched = true;
+                }
+            }
+        }
+        touched
+    }
+}
+
+impl Default for Spline {
+    fn default() -> Self {
+        Self {
+            points: Vec::new(),
+            width: 4.0,
+            falloff: 4.0,
+            segments: 8,
+        }
+    }
+}
+
+fn catmull_rom(p0: [f32; 3], p1: [f32; 3], p2: [f32; 3], p3: [f32; 3], t: f32) -> [f32; 3] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let mut result = [0.0; 3];
+    for (i, value) in result.iter_mut().enumerate() {
+        *value = 0.5
+            * (2.0 * p1[i]
+                + (p2[i] - p0[i]) * t
+                + (2.0 * p0[i] - 5.0 * p1[i] + 4.0 * p2[i] - p3[i]) * t2
+                + (3.0 * p1[i] - p0[i] - 3.0 * p2[i] + p3[i]) * t3);
+    }
+    result
+}
+
+/// Returns horizontal distance to the polyline and its height at the nearest point
+fn nearest(samples: &[[f32; 3]], x: f32, z: f32) -> Option<(f32, f32)> {
+    if samples.len() == 1 {
+        let dx = x - samples[0][0];
+        let dz = z - samples[0][2];
+        return Some(((dx * dx + dz * dz).sqrt(), samples[0][1]));
+    }
+    let mut result: Option<(f32, f32)> = None;
+    for segment in samples.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        let (abx, abz) = (b[0] - a[0], b[2] - a[2]);
+        let length_sq = abx * abx + abz * abz;
+        let t = if length_sq > 0.0 {
+            (((x - a[0]) * abx + (z - a[2]) * abz) / length_sq).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let dx = x - (a[0] + abx * t);
+        let dz = z - (a[2] + abz * t);
+        let distance = (dx * dx + dz * dz).sqrt();
+        if result.map(|(d, _)| distance < d).unwrap_or(true) {
+            result = Some((distance, a[1] + (b[1] - a[1]) * t));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spline_flattens_road_and_keeps_far_heights() {
+        let size = 33;
+        let offset = 16.0;
+        let mut map = vec![1.0; size * size];
+        let spline = Spline {
+            points: vec![[-10.0, 0.5, 0.0], [0.0, 0.5, 0.0], [10.0, 0.5, 0.0]],
+            width: 4.0,
+            falloff: 2.0,
+            ..Default::default()
+        };
+        assert!(spline.apply(&mut map, size, offset, 0.0, 1.0));
+
+        let value = |x: f32, z: f32| map[(x + offset) as usize * size + (z + offset) as usize];
+        assert!((value(0.0, 0.0) - 0.5).abs() < 1e-5);
+        assert!((value(5.0, 2.0) - 0.5).abs() < 1e-5);
+        assert!(value(0.0, 3.0) > 0.5 && value(0.0, 3.0) < 1.0);
+        assert_eq!(value(0.0, 8.0), 1.0);
+        assert_eq!(value(15.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn spline_carves_below_sea_level() {
+        use crate::{Generator, Heightmap};
+
+        let size = 33;
+        let mut generator = Generator {
+            noise_map: Some(vec![0.5; size * size]),
+            size,
+            ..Default::default()
+        };
+        generator.set_elevation_range(-50.0, 50.0);
+        assert_eq!(generator.elevation_range(), [-50.0, 50.0]);
+        assert_eq!(generator.value(16, 16), 0.0);
+
+        let spline = Spline::new(vec![[-10.0, -20.0, 0.0], [10.0, -20.0, 0.0]], 4.0);
+        assert!(generator.carve(&spline, 16.0));
+        assert!((generator.value(16, 16) + 20.0).abs() < 1e-4);
+        assert_eq!(generator.value(16, 30), 0.0);
+    }
+}
@@ -0,0 +1,226 @@
+use dotrix_core::assets::Texture;
+
+/// The way the stamp heights are combined with the terrain heights
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StampMode {
+    /// Adds the stamp heights, negative height makes craters and valleys
+    Add,
+    /// Raises the terrain up to the stamp heights, mountains and hills
+    Max,
+    /// Lowers the terrain down to the stamp heights, pits and basins
+    Min,
+    /// Replaces the terrain heights with the stamp ones, plateaus and landmarks
+    Replace,
+}
+
+/// Authored heightmap patch blended into the procedural terrain
+///
+/// The patch is placed at the world position, rotated around Y axis and stretched over the
+/// square of `2 * radius` side. Its influence fades out towards the edge of the inscribed circle
+/// over the `falloff` part of the radius.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stamp {
+    /// Square patch of the normalized heights, indexed as `x * size + z`
+    pub heights: Vec<f32>,
+    /// Number of the patch values per side
+    pub size: usize,
+    /// World X and Z coordinates of the patch center
+    pub position: [f32; 2],
+    /// Half of the patch side length in world units
+    pub radius: f32,
+    /// Rotation around Y axis in radians
+    pub rotation: f32,
+    /// Height of the patch value 1.0 in world units
+    pub height: f32,
+    /// Part of the radius (0.0..1.0), where the stamp is blended with the terrain
+    pub falloff: f32,
+    /// Blending mode
+    pub mode: StampMode,
+}
+
+impl Stamp {
+    /// Constructs new stamp from the patch of normalized heights
+    pub fn new(heights: Vec<f32>, size: usize) -> Self {
+        Self {
+            heights,
+            size,
+            ..Default::default()
+        }
+    }
+
+    /// Constructs new stamp from the red channel of the texture
+    ///
+    /// Texture rows go along Z axis and columns along X axis, non square textures are resampled
+    /// to the square of their longest side.
+    pub fn from_texture(texture: &Texture) -> Self {
+        let (width, height) = (texture.width as usize, texture.height as usize);
+        if width == 0 || height == 0 || texture.data.len() < width * height * 4 {
+            return Self::default();
+        }
+        let size = width.max(height);
+        let mut heights = Vec::with_capacity(size * size);
+        for x in 0..size {
+            for z in 0..size {
+                let u = x * width / size;
+                let v = z * height / size;
+                heights.push(texture.data[(v * width + u) * 4] as f32 / 255.0);
+            }
+        }
+        Self::new(heights, size)
+    }
+
+    /// Returns world space bounds (`[min_x, min_z, max_x, max_z]`) of the affected area
+    pub fn bounds(&self) -> [f32; 4] {
+        // rotated square fits into the circle of its diagonal
+        let radius = self.radius * std::f32::consts::SQRT_2;
+        [
+            self.position[0] - radius,
+            self.position[1] - radius,
+            self.position[0] + radius,
+            self.position[1] + radius,
+        ]
+    }
+
+    /// Returns the stamp weight (0.0..1.0) at the distance from the center, relative to radius
+    pub fn weight(&self, distance: f32) -> f32 {
+        let falloff = self.falloff.clamp(0.0, 1.0);
+        if distance >= 1.0 {
+            return 0.0;
+        }
+        if distance <= 1.0 - falloff {
+            return 1.0;
+        }
+        let t = (distance - (1.0 - falloff)) / falloff;
+        1.0 - t * t * (3.0 - 2.0 * t)
+    }
+
+    /// Returns bilinear interpolated patch value at the normalized coordinates (0.0..1.0)
+    pub fn sample(&self, u: f32, v: f32) -> f32 {
+        if self.size == 0 || self.heights.len() < self.size * self.size {
+            return 0.0;
+        }
+        let max = (self.size - 1) as f32;
+        let x = (u * max).clamp(0.0, max);
+        let z = (v * max).clamp(0.0, max);
+        let x0 = x.floor() as usize;
+        let z0 = z.floor() as usize;
+        let x1 = (x0 + 1).min(self.size - 1);
+        let z1 = (z0 + 1).min(self.size - 1);
+        let tx = x - x0 as f32;
+        let tz = z - z0 as f32;
+        let value = |x: usize, z: usize| self.heights[x * self.size + z];
+
+        (value(x0, z0) * (1.0 - tx) + value(x1, z0) * tx) * (1.0 - tz)
+            + (value(x0, z1) * (1.0 - tx) + value(x1, z1) * tx) * tz
+    }
+
+    /// Blends the stamp into the square map of heights with `size` values per side
+    ///
+    /// Values are indexed as `x * size + z`. `offset` converts world coordinates into map ones,
+    /// `base` is the world height of the map value 0.0 and `scale` converts world units of height
+    /// into map values. Returns `false` if the stamp does not touch the map.
+    pub(crate) fn apply(
+        &self,
+        map: &mut [f32],
+        size: usize,
+        offset: f32,
+        base: f32,
+        scale: f32,
+    ) -> bool {
+        if size == 0 || self.radius <= 0.0 || self.size == 0 {
+            return false;
+        }
+        let bounds = self.bounds();
+        let max = (size - 1) as f32;
+        let from_x = (bounds[0] + offset).floor().max(0.0);
+        let from_z = (bounds[1] + offset).floor().max(0.0);
+        let to_x = (bounds[2] + offset).ceil().min(max);
+        let to_z = (bounds[3] + offset).ceil().min(max);
+        if from_x > to_x || from_z > to_z {
+            return false;
+        }
+
+        let (sin, cos) = (-self.rotation).sin_cos();
+        let mut touched = false;
+        for x in from_x as usize..=to_x as usize {
+            for z in from_z as usize..=to_z as usize {
+                let dx = (x as f32 - offset - self.position[0]) / self.radius;
+                let dz = (z as f32 - offset - self.position[1]) / self.radius;
+                let weight = self.weight((dx * dx + dz * dz).sqrt());
+                if weight <= 0.0 {
+                    continue;
+                }
+                // local coordinates of the patch
+                let local_x = dx * cos - dz * sin;
+                let local_z = dx * sin + dz * cos;
+                let height =
+                    self.sample((local_x + 1.0) / 2.0, (local_z + 1.0) / 2.0) * self.height;
+                let value = (height - base) * scale;
+                let i = x * size + z;
+                let target = match self.mode {
+                    StampMode::Add => map[i] + height * scale,
+                    StampMode::Max => map[i].max(value),
+                    StampMode::Min => map[i].min(value),
+                    StampMode::Replace => value,
+                };
+                map[i] += (target - map[i]) * weight;
+                touched = true;
+            }
+        }
+        touched
+    }
+}
+
+impl Default for Stamp {
+    fn default() -> Self {
+        Self {
+            heights: Vec::new(),
+            size: 0,
+            position: [0.0, 0.0],
+            radius: 64.0,
+            rotation: 0.0,
+            height: 100.0,
+            falloff: 0.25,
+            mode: StampMode::Max,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamp_blends_patch_with_falloff() {
+        let size = 33;
+        let offset = 16.0;
+        let mut map = vec![0.25; size * size];
+        // a ramp along the patch X axis
+        let stamp = Stamp {
+            position: [0.0, 0.0],
+            radius: 8.0,
+            height: 2.0,
+            falloff: 0.5,
+            mode: StampMode::Replace,
+            ..Stamp::new(vec![0.0, 0.0, 1.0, 1.0], 2)
+        };
+        assert!(stamp.apply(&mut map, size, offset, 0.0, 0.5));
+
+        let value = |x: f32, z: f32| map[(x + offset) as usize * size + (z + offset) as usize];
+        assert!((value(0.0, 0.0) - 0.5).abs() < 1e-5);
+        assert!((value(2.0, 0.0) - 0.625).abs() < 1e-5);
+        assert!(value(6.0, 0.0) > 0.25 && value(6.0, 0.0) < 0.875);
+        assert_eq!(value(0.0, 9.0), 0.25);
+        assert_eq!(value(15.0, 15.0), 0.25);
+
+        // rotated by a half turn the ramp goes the other way
+        let mut map = vec![0.25; size * size];
+        let stamp = Stamp {
+            rotation: std::f32::consts::PI,
+            ..stamp
+        };
+        assert!(stamp.apply(&mut map, size, offset, 0.0, 0.5));
+        let value = |x: f32, z: f32| map[(x + offset) as usize * size + (z + offset) as usize];
+        assert!((value(-2.0, 0.0) - 0.625).abs() < 1e-5);
+    }
+}
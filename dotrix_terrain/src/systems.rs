@@ -1,25 +1,88 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
-use dotrix_core::assets::{Assets, Mesh, Shader};
+use dotrix_core::assets::{Assets, Mesh, Shader, ShaderVariant, Texture};
 use dotrix_core::camera::ProjView;
 use dotrix_core::ecs::{Const, Context, Entity, Mut};
 use dotrix_core::renderer::{
-    BindGroup, Binding, PipelineLayout, PipelineOptions, Renderer, Sampler, Stage,
+    BindGroup, Binding, PipelineLayout, PipelineOptions, RenderPass, Renderer, Sampler, Stage,
+    StorageTextureAccess, TextureBuffer, TextureFormat, WorkGroups, DEPTH_PREPASS, MAIN_PASS,
 };
-use dotrix_core::{Camera, Color, Globals, Id, Pipeline, World};
+use dotrix_core::{Camera, Globals, Id, Pipeline, World};
 
-use dotrix_pbr::{Lights, Material};
+use dotrix_pbr::{Lights, Material, Shadows};
 
-use crate::{Layers, Terrain, Tile};
+use log::error;
+
+use crate::{
+    GpuTile, Layers, Terrain, Tile, TileEvent, TileEventKind, VecXZ, GENERATOR_PASS, TILES_BUFFER,
+};
 
 const PIPELINE_LABEL: &str = "dotrix::terrain";
+const GENERATOR_PIPELINE_LABEL: &str = "dotrix::terrain::generator";
+const GENERATOR_WORKGROUP_SIZE: u32 = 64;
+const FLAT_NORMAL_MAP: &str = "dotrix::terrain::flat_normal_map";
+const FLAT_AO_MAP: &str = "dotrix::terrain::flat_ao_map";
+
+/// Terrain layers system
+/// Rebuilds the layers textures atlases and reloads the layers, when they were changed
+pub fn layers(
+    mut globals: Mut<Globals>,
+    assets: Const<Assets>,
+    renderer: Const<Renderer>,
+    world: Const<World>,
+) {
+    let layers = match globals.get_mut::<Layers>() {
+        Some(layers) if layers.changed || layers.splat_map.changed => layers,
+        _ => return,
+    };
+
+    if layers.changed {
+        // wait until all of the textures are loaded
+        if !layers.build_atlases(&assets) {
+            return;
+        }
+        layers.load(&renderer);
+        layers.changed = false;
+    } else {
+        // only the painted weights were changed
+        layers.splat_map.load(&renderer);
+    }
+
+    // textures buffers were recreated, tiles have to be rebound
+    let query = world.query::<(&Tile, &mut Pipeline)>();
+    for (_, pipeline) in query {
+        pipeline.bindings = Default::default();
+    }
+}
+
+/// Terrain invalidation system, collects the changed regions of the heightmap
+///
+/// Runs before the other terrain systems, so all of them see the same dirty regions.
+pub fn invalidate(mut terrain: Mut<Terrain>) {
+    terrain.events.clear();
+    terrain.collect_dirty_regions();
+}
 
 /// Terrain spawn system context
 #[derive(Default)]
 pub struct Spawner {
-    tiles: HashMap<TileIndex, TileState>,
-    last_viewer_position: Option<[f32; 2]>,
-    to_exile: Vec<(Entity, Id<Mesh>)>,
+    tiles: HashMap<VecXZ<i32>, TileState>,
+    pool: VecDeque<Exiled>,
+    events: Vec<TileEvent>,
+    last_viewer_position: Option<VecXZ<f32>>,
+    last_lod_distances: Vec<f32>,
+    last_lod_scale: i32,
+    last_view_distance: f32,
+    to_exile: Vec<Exiled>,
+}
+
+struct Exiled {
+    entity: Entity,
+    mesh: Id<Mesh>,
+    normal_map: Id<Texture>,
+    ao_map: Id<Texture>,
+    index: VecXZ<i32>,
+    lod: usize,
 }
 
 #[derive(Default)]
@@ -29,32 +92,107 @@ struct TileState {
     spawned: bool,
 }
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone)]
-struct TileIndex {
-    x: i32,
-    z: i32,
+struct Viewer {
+    position: VecXZ<f32>,
+    view_distance: f32,
+    lod_distances: Vec<f32>,
+    lod_scale: i32,
+    hysteresis: f32,
 }
 
-struct Viewer {
-    position: [f32; 2],
-    view_distance_sq: f32,
+/// Camera position and view direction on the terrain plane
+struct Eye {
+    position: VecXZ<f32>,
+    direction: VecXZ<f32>,
+}
+
+impl Eye {
+    /// Tiles behind the camera are generated as if they were that many times farther
+    const BEHIND_PENALTY: f32 = 3.0;
+
+    fn new(camera: &Camera) -> Self {
+        let position = if camera.distance > 0.0 {
+            VecXZ::from(camera.position())
+        } else {
+            VecXZ::new(camera.target.x, camera.target.z)
+        };
+        // camera looks against its offset from the target
+        let direction = VecXZ::new(-camera.y_angle.cos(), -camera.y_angle.sin());
+        Self {
+            position,
+            direction,
+        }
+    }
+
+    /// Returns generation priority of the tile, lower values go first
+    fn priority(&self, tile: VecXZ<i32>) -> f32 {
+        let offset = tile.as_f32() - self.position;
+        let distance = offset.distance(VecXZ::default());
+        if distance <= f32::EPSILON {
+            return 0.0;
+        }
+        let cos = (offset.x * self.direction.x + offset.z * self.direction.z) / distance;
+        distance * (1.0 + (1.0 - cos) / 2.0 * (Self::BEHIND_PENALTY - 1.0))
+    }
 }
 
 /// Terrain Startup System
-pub fn startup(mut assets: Mut<Assets>, mut globals: Mut<Globals>, renderer: Const<Renderer>) {
+pub fn startup(mut assets: Mut<Assets>, mut globals: Mut<Globals>, mut renderer: Mut<Renderer>) {
+    // tiles generated on GPU are drawn by the main pass and the depth prepass
+    let graph = renderer.graph_mut();
+    graph.add_pass_before(
+        MAIN_PASS,
+        RenderPass::new(GENERATOR_PASS).produces(TILES_BUFFER),
+    );
+    for pass in [MAIN_PASS, DEPTH_PREPASS].iter() {
+        if let Some(pass) = graph.pass_mut(pass) {
+            pass.consumes.push(String::from(TILES_BUFFER));
+        }
+    }
+
     // prepare layers
     let mut layers = Layers::default();
     layers.load(&renderer);
     globals.set(layers);
 
-    // prepare shader
-    let mut shader = Shader {
-        name: String::from(PIPELINE_LABEL),
-        code: Lights::add_to_shader(include_str!("shaders/terrain.wgsl"), 0, 2),
-        ..Default::default()
-    };
-    shader.load(&renderer);
-    assets.store_as(shader, PIPELINE_LABEL);
+    // tiles without baked normals use vertex normals, and without baked AO are not occluded
+    for (label, data) in [
+        (FLAT_NORMAL_MAP, vec![128, 255, 128, 0]),
+        (FLAT_AO_MAP, vec![255, 255, 255, 255]),
+    ] {
+        let mut texture = Texture {
+            width: 1,
+            height: 1,
+            depth: 1,
+            data,
+            buffer: TextureBuffer::new(StorageTextureAccess::Read, TextureFormat::rgba_u8norm()),
+            ..Default::default()
+        };
+        texture.load(&renderer);
+        assets.store_as(texture, label);
+    }
+
+    // prepare shaders, the vertex stage of the tiles is selected by the pipeline variants
+    let terrain_code = include_str!("shaders/terrain.wgsl").replace(
+        "{{ include(vertex) }}",
+        include_str!("shaders/vertex.inc.wgsl"),
+    );
+    let shaders = [
+        (PIPELINE_LABEL, terrain_code.as_str()),
+        (
+            GENERATOR_PIPELINE_LABEL,
+            include_str!("shaders/generator.wgsl"),
+        ),
+    ];
+    for (label, code) in shaders {
+        let mut shader = Shader {
+            name: String::from(label),
+            code: Shadows::add_to_shader(&Lights::add_to_shader(code, 0, 2), 0, 9),
+            ..Default::default()
+        };
+        shader.load(&renderer);
+        assets.store_as(shader, label);
+    }
 }
 
 /// Terrain spawn system
@@ -66,31 +204,95 @@ pub fn spawn(
     mut assets: Mut<Assets>,
     mut world: Mut<World>,
 ) {
+    if terrain.heightmap.dirty() {
+        terrain.heightmap.set_dirty(false);
+        terrain.force_spawn = true;
+        if let Some(cache) = terrain.cache.as_mut() {
+            cache.clear();
+        }
+    }
+
+    // regenerate tiles affected by heights changes
+    if !terrain.dirty_regions.is_empty() {
+        if !terrain.force_spawn {
+            let query = world.query::<(&mut Tile, &mut Pipeline)>();
+            for (tile, pipeline) in query {
+                if !terrain.is_tile_dirty(tile.x, tile.z, tile.lod) {
+                    continue;
+                }
+                if let Some(mesh) = assets.get_mut(tile.mesh) {
+                    *mesh = terrain.generate_tile_mesh(tile.x, tile.z, tile.lod);
+                    tile.bounds = terrain.tile_bounds(tile.x, tile.z, tile.lod, mesh);
+                    tile.loaded = false;
+                    ctx.events.push(TileEvent::new(
+                        TileEventKind::Generated,
+                        VecXZ::new(tile.x, tile.z),
+                        tile.lod,
+                    ));
+                }
+                let baked = [
+                    (
+                        tile.normal_map,
+                        terrain.bake_tile_normal_map(tile.x, tile.z, tile.lod),
+                    ),
+                    (
+                        tile.ao_map,
+                        terrain.bake_tile_ao_map(tile.x, tile.z, tile.lod),
+                    ),
+                ];
+                for (id, baked_texture) in baked {
+                    if let (Some(texture), Some(baked_texture)) =
+                        (assets.get_mut(id), baked_texture)
+                    {
+                        *texture = baked_texture;
+                        // bindings refer the replaced texture
+                        pipeline.bindings = Default::default();
+                    }
+                }
+            }
+        }
+        terrain.invalidate_cached_tiles();
+        terrain.dirty_regions.clear();
+    }
+
     let view_distance = terrain.view_distance;
+    let lod_distances = (0..terrain.max_lod)
+        .map(|lod| terrain.lod_distance(lod))
+        .collect::<Vec<_>>();
     // get viewer
     let viewer = Viewer {
-        view_distance_sq: view_distance * view_distance,
-        position: [camera.target.x, camera.target.z],
+        view_distance,
+        position: VecXZ::new(camera.target.x, camera.target.z),
+        lod_distances: lod_distances.clone(),
+        lod_scale: terrain.lod_step(1) as i32,
+        hysteresis: terrain.hysteresis,
     };
 
+    let eye = Eye::new(&camera);
+
     // check if update is necessary
-    if let Some(last_viewer_position) = ctx.last_viewer_position.as_ref() {
-        let dx = viewer.position[0] - last_viewer_position[0];
-        let dz = viewer.position[1] - last_viewer_position[1];
-        if !terrain.force_spawn && dx * dx + dz * dz < terrain.spawn_if_moved_by {
+    let reconfigured = ctx.last_view_distance != view_distance
+        || ctx.last_lod_distances != lod_distances
+        || ctx.last_lod_scale != viewer.lod_scale;
+    if let Some(last_viewer_position) = ctx.last_viewer_position {
+        let moved_by_sq = viewer.position.distance_sq(last_viewer_position);
+        if !terrain.force_spawn && !reconfigured && moved_by_sq < terrain.spawn_if_moved_by {
+            // continue spawning of the tiles left by the frame budget
+            spawn_tiles(&mut ctx, &mut terrain, &mut assets, &mut world, &eye);
             return;
         }
     }
     ctx.last_viewer_position = Some(viewer.position);
+    ctx.last_view_distance = view_distance;
+    ctx.last_lod_distances = lod_distances;
+    ctx.last_lod_scale = viewer.lod_scale;
 
-    if terrain.force_spawn {
-        ctx.tiles.clear();
-
-        let query = world.query::<(&Tile, &mut Pipeline)>();
-        for (_, pipeline) in query {
-            pipeline.disabled = true;
-        }
+    // meshes of tiles exiled by force respawn could be outdated
+    let force_spawn = terrain.force_spawn;
+    let cache_exiled = !force_spawn;
 
+    if force_spawn {
+        ctx.tiles.clear();
         terrain.force_spawn = false;
     }
 
@@ -101,74 +303,168 @@ pub fn spawn(
 
     // calculate terrain tiles that has to be visible
     let max_lod = terrain.max_lod;
-    let tile_size = terrain.tile_size as f32 * (2.0_f32).powf(max_lod as f32);
+    let tile_size = (terrain.tile_size * terrain.lod_step(max_lod)) as f32;
     let tiles_per_view_distance = (view_distance / tile_size as f32).ceil() as i32;
     let half_tile_size = tile_size as i32 / 2;
-    let from_x = ((viewer.position[0] / tile_size).floor() * tile_size) as i32;
-    let from_z = ((viewer.position[1] / tile_size).floor() * tile_size) as i32;
-
-    for zi in -tiles_per_view_distance..tiles_per_view_distance {
-        let z = from_z + zi * tile_size as i32 + half_tile_size as i32;
-        for xi in -tiles_per_view_distance..tiles_per_view_distance {
-            let x = from_x + xi * tile_size as i32 + half_tile_size as i32;
-            // recursively calculate what lods should be spawned and spawn them
-            queue_tiles_to_spawn(
-                &mut ctx,
-                &viewer,
-                half_tile_size,
-                max_lod,
-                TileIndex { x, z },
-            );
-        }
+    let origin = VecXZ::new(
+        ((viewer.position.x / tile_size).floor() * tile_size) as i32 + half_tile_size,
+        ((viewer.position.z / tile_size).floor() * tile_size) as i32 + half_tile_size,
+    );
+
+    let cells = VecXZ::new(0, 0)
+        .spiral(tiles_per_view_distance)
+        .filter(|cell| cell.x < tiles_per_view_distance && cell.z < tiles_per_view_distance);
+    for cell in cells {
+        let position = origin + cell * tile_size as i32;
+        // recursively calculate what lods should be spawned and spawn them
+        queue_tiles_to_spawn(&mut ctx, &viewer, half_tile_size, max_lod, position);
     }
 
-    // exile tiles
-    let query = world.query::<(&Tile, &Entity)>();
-    for (tile, entity) in query {
-        let index = TileIndex {
-            x: tile.x,
-            z: tile.z,
+    // respawn pooled tiles, pool or exile the hidden ones
+    let pool_size = if force_spawn { 0 } else { terrain.pool_size };
+    let spawner = &mut *ctx;
+    let query = world.query::<(&mut Tile, &Entity, &mut Pipeline)>();
+    for (tile, entity, pipeline) in query {
+        let index = VecXZ::new(tile.x, tile.z);
+        let state = spawner
+            .tiles
+            .get_mut(&index)
+            .filter(|state| state.visible && state.lod == tile.lod && !force_spawn);
+        if let Some(state) = state {
+            if tile.pooled {
+                tile.pooled = false;
+                pipeline.disabled = false;
+                spawner.pool.retain(|pooled| pooled.entity != *entity);
+                let event = TileEvent::new(TileEventKind::Restored, index, tile.lod);
+                spawner.events.push(event);
+            }
+            state.spawned = true;
+            continue;
+        }
+        if tile.pooled && !force_spawn {
+            continue;
+        }
+        if !tile.pooled {
+            let event = TileEvent::new(TileEventKind::Despawned, index, tile.lod);
+            spawner.events.push(event);
+        }
+        let exiled = Exiled {
+            entity: *entity,
+            mesh: tile.mesh,
+            normal_map: tile.normal_map,
+            ao_map: tile.ao_map,
+            index,
+            lod: tile.lod,
         };
-        let do_exile = if let Some(tile) = ctx.tiles.get_mut(&index) {
-            !tile.visible
+        if pool_size > 0 {
+            tile.pooled = true;
+            pipeline.disabled = true;
+            spawner.pool.push_back(exiled);
         } else {
-            true
-        };
-        if do_exile {
-            ctx.to_exile.push((*entity, tile.mesh));
+            spawner.to_exile.push(exiled);
+        }
+    }
+    if force_spawn {
+        ctx.pool.clear();
+    }
+    while ctx.pool.len() > pool_size {
+        if let Some(exiled) = ctx.pool.pop_front() {
+            ctx.to_exile.push(exiled);
         }
     }
 
-    for (entity, mesh) in ctx.to_exile.iter() {
-        world.exile(*entity);
-        assets.remove(*mesh);
+    for exiled in ctx.to_exile.iter() {
+        world.exile(exiled.entity);
+        for texture in [exiled.normal_map, exiled.ao_map] {
+            if !texture.is_null() {
+                assets.remove(texture);
+            }
+        }
+        if let Some(mesh) = assets.remove(exiled.mesh) {
+            if let Some(cache) = terrain.cache.as_mut().filter(|_| cache_exiled) {
+                let (x, z, lod) = (exiled.index.x, exiled.index.z, exiled.lod);
+                if let Err(e) = cache.store(x, z, lod, &mesh) {
+                    error!("Can not cache terrain tile: {}", e);
+                }
+            }
+        }
     }
     ctx.to_exile.clear();
 
+    // drop cached tiles, that are too far from the viewer
+    let cache_distance = terrain.cache_distance;
+    if let Some(cache) = terrain.cache.as_mut() {
+        cache.retain(|x, z, _| {
+            VecXZ::new(x, z).as_f32().distance(viewer.position) <= cache_distance
+        });
+    }
+
     // cleanup tiles registry of the exiled tiles
     ctx.tiles.retain(|_, tile| tile.visible);
 
-    // spawn missing tiles
-    for (index, tile_state) in ctx.tiles.iter_mut() {
-        if tile_state.spawned {
-            continue;
-        }
+    spawn_tiles(&mut ctx, &mut terrain, &mut assets, &mut world, &eye);
+}
 
+/// Spawns missing tiles, respecting the frame budget
+///
+/// Tiles in front of the camera and nearest to it go first. Passes the lifecycle events
+/// collected by the spawner to the terrain.
+fn spawn_tiles(
+    ctx: &mut Spawner,
+    terrain: &mut Terrain,
+    assets: &mut Assets,
+    world: &mut World,
+    eye: &Eye,
+) {
+    let budget = match terrain.max_tiles_per_frame {
+        0 => usize::MAX,
+        max_tiles_per_frame => max_tiles_per_frame,
+    };
+
+    let mut queue = ctx
+        .tiles
+        .iter()
+        .filter(|(_, tile_state)| !tile_state.spawned)
+        .map(|(index, _)| (eye.priority(*index), *index))
+        .collect::<Vec<_>>();
+    queue.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    let pending_tiles = queue.len().saturating_sub(budget);
+
+    for (_, index) in queue.into_iter().take(budget) {
+        let tile_state = ctx.tiles.get_mut(&index).unwrap();
         let x = index.x;
         let z = index.z;
         let lod = tile_state.lod;
 
-        let mesh = terrain.generate_tile_mesh(x, z, lod);
+        let mesh = terrain
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.load(x, z, lod))
+            .unwrap_or_else(|| terrain.generate_tile_mesh(x, z, lod));
         let tile = Tile {
             x,
             z,
             lod,
+            bounds: terrain.tile_bounds(x, z, lod, &mesh),
             mesh: assets.store(mesh),
+            normal_map: terrain
+                .bake_tile_normal_map(x, z, lod)
+                .map(|texture| assets.store(texture))
+                .unwrap_or_default(),
+            ao_map: terrain
+                .bake_tile_ao_map(x, z, lod)
+                .map(|texture| assets.store(texture))
+                .unwrap_or_default(),
             loaded: false,
+            pooled: false,
+            gpu: terrain.gpu.as_ref().map(|_| GpuTile::default()),
+            prepass: Pipeline::default(),
         };
         let material = Material {
             texture: terrain.texture,
-            albedo: Color::white(),
+            albedo: terrain.albedo,
+            roughness: terrain.roughness,
+            metallic: terrain.metallic,
             ..Default::default()
         };
         let pipeline = Pipeline::default();
@@ -176,7 +472,12 @@ pub fn spawn(
         world.spawn(Some((tile, material, pipeline)));
 
         tile_state.spawned = true;
+        ctx.events
+            .push(TileEvent::new(TileEventKind::Generated, index, lod));
     }
+
+    terrain.pending_tiles = pending_tiles;
+    terrain.events.append(&mut ctx.events);
 }
 
 fn queue_tiles_to_spawn(
@@ -184,41 +485,127 @@ fn queue_tiles_to_spawn(
     viewer: &Viewer,
     half_tile_size: i32,
     lod: usize,
-    position: TileIndex,
+    position: VecXZ<i32>,
 ) {
-    let dx = position.x as f32 - viewer.position[0];
-    let dz = position.z as f32 - viewer.position[1];
-    let distance_sq = dx * dx + dz * dz;
-    let lod_distance_sq = (4 * half_tile_size * half_tile_size) as f32;
-    let x = position.x;
-    let z = position.z;
-
-    if distance_sq > lod_distance_sq || lod == 0 {
-        if distance_sq > viewer.view_distance_sq {
+    let distance = position.as_f32().distance(viewer.position);
+    let child_half_size = half_tile_size / viewer.lod_scale;
+    // offset of the first child tile, the others follow with the step of their side
+    let child_offset = child_half_size - half_tile_size;
+    let spawned = |position: VecXZ<i32>, lod: usize| {
+        ctx.tiles
+            .get(&position)
+            .map(|tile| tile.spawned && tile.lod == lod)
+            .unwrap_or(false)
+    };
+    // keep the spawned tiles a bit longer, so they do not flicker on the boundaries
+    let is_spawned = spawned(position, lod);
+    let lod_margin = if is_spawned {
+        -viewer.hysteresis
+    } else if lod > 0 && spawned(position + VecXZ::new(child_offset, child_offset), lod - 1) {
+        viewer.hysteresis
+    } else {
+        0.0
+    };
+
+    if lod == 0 || distance > viewer.lod_distances[lod - 1] + lod_margin {
+        let view_margin = if is_spawned { viewer.hysteresis } else { 0.0 };
+        if distance > viewer.view_distance + view_margin {
             return; // the tile is out of the view distance range
         }
-        let mut tile = ctx.tiles.entry(position).or_insert(TileState {
-            lod,
-            ..Default::default()
+        let events = &mut ctx.events;
+        let mut tile = ctx.tiles.entry(position).or_insert_with(|| {
+            events.push(TileEvent::new(TileEventKind::Queued, position, lod));
+            TileState {
+                lod,
+                ..Default::default()
+            }
         });
         tile.visible = true;
     } else {
         // Higher lod is required
-        let half_tile_size = half_tile_size / 2;
-        let x1 = x - half_tile_size as i32;
-        let x2 = x + half_tile_size as i32;
-        let z1 = z - half_tile_size as i32;
-        let z2 = z + half_tile_size as i32;
-        let higher_lod_tiles = [
-            TileIndex { x: x1, z: z1 },
-            TileIndex { x: x2, z: z1 },
-            TileIndex { x: x1, z: z2 },
-            TileIndex { x: x2, z: z2 },
-        ];
-
-        for tile in higher_lod_tiles.iter() {
-            queue_tiles_to_spawn(ctx, viewer, half_tile_size, lod - 1, *tile);
+        for i in 0..viewer.lod_scale * viewer.lod_scale {
+            let offset = VecXZ::new(
+                child_offset + i % viewer.lod_scale * 2 * child_half_size,
+                child_offset + i / viewer.lod_scale * 2 * child_half_size,
+            );
+            queue_tiles_to_spawn(ctx, viewer, child_half_size, lod - 1, position + offset);
+        }
+    }
+}
+
+/// Terrain GPU generation system
+/// Dispatches heights generation of the new tiles, if GPU generator is set
+pub fn compute(
+    mut renderer: Mut<Renderer>,
+    assets: Const<Assets>,
+    terrain: Const<Terrain>,
+    world: Const<World>,
+) {
+    let generator = match terrain.gpu.as_ref() {
+        Some(generator) => generator,
+        None => return,
+    };
+    let vertices_per_side = terrain.tile_size as u32 + 1;
+    let vertices_count = vertices_per_side * vertices_per_side;
+
+    let query = world.query::<(&mut Tile,)>();
+    for (tile,) in query {
+        let (x, z, lod) = (tile.x, tile.z, tile.lod);
+        let gpu = match tile.gpu.as_mut() {
+            Some(gpu) if !gpu.computed => gpu,
+            _ => continue,
+        };
+
+        if gpu.pipeline.shader.is_null() {
+            gpu.pipeline.shader = assets
+                .find::<Shader>(GENERATOR_PIPELINE_LABEL)
+                .unwrap_or_default();
         }
+
+        if !gpu.pipeline.ready() {
+            if let Some(shader) = assets.get(gpu.pipeline.shader) {
+                if !shader.loaded() {
+                    continue;
+                }
+                let step = terrain.lod_step(lod) as f32;
+                gpu.load(
+                    &renderer,
+                    generator,
+                    [x as f32, z as f32, step],
+                    vertices_per_side,
+                );
+                renderer.bind(
+                    &mut gpu.pipeline,
+                    PipelineLayout {
+                        label: String::from(GENERATOR_PIPELINE_LABEL),
+                        mesh: None,
+                        shader,
+                        bindings: &[BindGroup::new(
+                            "Globals",
+                            vec![
+                                Binding::Uniform("Params", Stage::Compute, &gpu.params),
+                                Binding::Storage("Vertices", Stage::Compute, &gpu.vertices),
+                            ],
+                        )],
+                        vertex_buffers: &[],
+                        push_constants: None,
+                        options: PipelineOptions::default(),
+                    },
+                );
+            } else {
+                continue;
+            }
+        }
+
+        renderer.compute(
+            &mut gpu.pipeline,
+            WorkGroups {
+                x: (vertices_count as f32 / GENERATOR_WORKGROUP_SIZE as f32).ceil() as u32,
+                y: 1,
+                z: 1,
+            },
+        );
+        gpu.computed = true;
     }
 }
 
@@ -226,16 +613,50 @@ fn queue_tiles_to_spawn(
 pub fn render(
     mut renderer: Mut<Renderer>,
     mut assets: Mut<Assets>,
+    mut terrain: Mut<Terrain>,
     globals: Const<Globals>,
+    camera: Const<Camera>,
     world: Const<World>,
 ) {
+    let frustum = camera.frustum();
+    let shadows = globals.get::<Shadows>();
+    let shadows_changed = shadows.map(|shadows| shadows.changed()).unwrap_or(false);
+    let polygon_mode = terrain.polygon_mode;
+    let polygon_mode_changed = polygon_mode != terrain.bound_polygon_mode;
+    terrain.bound_polygon_mode = polygon_mode;
     let query = world.query::<(&mut Tile, &mut Material, &mut Pipeline)>();
 
     for (tile, material, pipeline) in query {
+        if shadows_changed || polygon_mode_changed {
+            pipeline.bindings.unload();
+            tile.prepass.bindings.unload();
+        }
+
+        // skip tiles outside of the camera view
+        if let Some(frustum) = frustum.as_ref() {
+            if !tile.intersects_frustum(frustum) {
+                continue;
+            }
+        }
+
         if pipeline.shader.is_null() {
+            // meshes with vertex colors have the fourth attribute
+            let colored = assets
+                .get(tile.mesh)
+                .map(|mesh| mesh.vertex_buffer_layout().len() > 3)
+                .unwrap_or(false);
+            let gpu = tile.gpu.is_some();
+            pipeline.variant = ShaderVariant::new()
+                .flag("GPU_HEIGHTS", gpu)
+                .flag("VERTEX_COLOR", colored && !gpu);
             pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
         }
 
+        // GPU generated heights are not ready yet
+        if tile.gpu.as_ref().map(|gpu| !gpu.computed).unwrap_or(false) {
+            continue;
+        }
+
         // check if model is disabled or already rendered
         if !pipeline.cycle(&renderer) {
             continue;
@@ -246,21 +667,43 @@ pub fn render(
                 mesh.load(&renderer);
             }
             tile.loaded = true;
+            let position = VecXZ::new(tile.x, tile.z);
+            let event = TileEvent::new(TileEventKind::Uploaded, position, tile.lod);
+            terrain.events.push(event);
         }
 
         if !material.load(&renderer, &mut assets) {
             continue;
         }
 
+        let normal_map_id = if tile.normal_map.is_null() {
+            assets.find::<Texture>(FLAT_NORMAL_MAP).unwrap_or_default()
+        } else {
+            tile.normal_map
+        };
+        let ao_map_id = if tile.ao_map.is_null() {
+            assets.find::<Texture>(FLAT_AO_MAP).unwrap_or_default()
+        } else {
+            tile.ao_map
+        };
+        if !load_texture(&renderer, &mut assets, normal_map_id)
+            || !load_texture(&renderer, &mut assets, ao_map_id)
+        {
+            continue;
+        }
+
         let mesh = assets.get(tile.mesh).unwrap();
 
-        if !pipeline.ready() {
+        let depth_prepass = renderer.depth_prepass();
+        if !pipeline.ready() || depth_prepass && !tile.prepass.ready() {
             if let Some(shader) = assets.get(pipeline.shader) {
                 if !shader.loaded() {
                     continue;
                 }
 
                 let texture = assets.get(material.texture).unwrap();
+                let normal_map = assets.get(normal_map_id).unwrap();
+                let ao_map = assets.get(ao_map_id).unwrap();
 
                 let proj_view = globals
                     .get::<ProjView>()
@@ -278,36 +721,349 @@ pub fn render(
                     .get::<Layers>()
                     .expect("Terrain layers must be loaded");
 
+                let shadows = shadows.expect("Shadows buffer must be loaded");
+                if !shadows.ready(&renderer) {
+                    continue;
+                }
+
+                let mut locals = vec![
+                    Binding::Uniform("Material", Stage::Fragment, &material.uniform),
+                    Binding::Texture("Texture", Stage::Fragment, &texture.buffer),
+                    Binding::Texture("NormalMap", Stage::Fragment, &normal_map.buffer),
+                    Binding::Texture("AoMap", Stage::Fragment, &ao_map.buffer),
+                ];
+                if let Some(gpu) = tile.gpu.as_ref() {
+                    locals.push(Binding::Storage("Vertices", Stage::Vertex, &gpu.vertices));
+                }
+
+                let bindings = [
+                    BindGroup::new(
+                        "Globals",
+                        vec![
+                            Binding::Uniform("ProjView", Stage::Vertex, &proj_view.uniform),
+                            Binding::Sampler("Sampler", Stage::Fragment, sampler),
+                            Binding::Uniform("Lights", Stage::Fragment, &lights.uniform),
+                            Binding::Uniform("Layers", Stage::Fragment, &layers.uniform),
+                            Binding::Texture(
+                                "LayersAlbedo",
+                                Stage::Fragment,
+                                &layers.albedo_atlas.buffer,
+                            ),
+                            Binding::Texture(
+                                "LayersNormal",
+                                Stage::Fragment,
+                                &layers.normal_atlas.buffer,
+                            ),
+                            Binding::Texture(
+                                "LayersRoughness",
+                                Stage::Fragment,
+                                &layers.roughness_atlas.buffer,
+                            ),
+                            Binding::Texture(
+                                "LayersMacro",
+                                Stage::Fragment,
+                                &layers.macro_atlas.buffer,
+                            ),
+                            Binding::Texture("SplatMap", Stage::Fragment, &layers.splat_map.buffer),
+                        ]
+                        .into_iter()
+                        .chain(shadows.bindings())
+                        .collect(),
+                    ),
+                    BindGroup::new("Locals", locals),
+                ];
+
                 renderer.bind(
                     pipeline,
                     PipelineLayout {
                         label: String::from(PIPELINE_LABEL),
                         mesh: Some(mesh),
                         shader,
-                        bindings: &[
-                            BindGroup::new(
-                                "Globals",
-                                vec![
-                                    Binding::Uniform("ProjView", Stage::Vertex, &proj_view.uniform),
-                                    Binding::Sampler("Sampler", Stage::Fragment, sampler),
-                                    Binding::Uniform("Lights", Stage::Fragment, &lights.uniform),
-                                    Binding::Uniform("Layers", Stage::Fragment, &layers.uniform),
-                                ],
-                            ),
-                            BindGroup::new(
-                                "Locals",
-                                vec![
-                                    Binding::Uniform("Material", Stage::Vertex, &material.uniform),
-                                    Binding::Texture("Texture", Stage::Fragment, &texture.buffer),
-                                ],
-                            ),
-                        ],
-                        options: PipelineOptions::default(),
+                        bindings: &bindings,
+                        vertex_buffers: &[],
+                        push_constants: None,
+                        options: PipelineOptions {
+                            polygon_mode,
+                            ..if depth_prepass {
+                                PipelineOptions::depth_prepassed()
+                            } else {
+                                PipelineOptions::default()
+                            }
+                        },
                     },
                 );
+                if depth_prepass {
+                    tile.prepass.shader = pipeline.shader;
+                    tile.prepass.variant = pipeline.variant.clone();
+                    tile.prepass.options.pass = Some(String::from(DEPTH_PREPASS));
+                    renderer.bind(
+                        &mut tile.prepass,
+                        PipelineLayout {
+                            label: String::from(PIPELINE_LABEL),
+                            mesh: Some(mesh),
+                            shader,
+                            bindings: &bindings,
+                            vertex_buffers: &[],
+                            push_constants: None,
+                            options: PipelineOptions::default(),
+                        },
+                    );
+                }
             }
         }
 
+        if depth_prepass && tile.prepass.cycle(&renderer) {
+            renderer.run(&mut tile.prepass, mesh);
+        }
         renderer.run(pipeline, mesh);
     }
 }
+
+fn load_texture(renderer: &Renderer, assets: &mut Assets, id: Id<Texture>) -> bool {
+    match assets.get_mut(id) {
+        Some(texture) => {
+            texture.load(renderer);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Generator;
+    use dotrix_core::Color;
+
+    fn viewer(view_distance: f32, lod_distance: f32, hysteresis: f32) -> Viewer {
+        Viewer {
+            position: VecXZ::new(0.0, 0.0),
+            view_distance,
+            lod_distances: vec![lod_distance],
+            lod_scale: 2,
+            hysteresis,
+        }
+    }
+
+    /// Queues the tile of LOD 1 with the 32 units side, 22.6 units away from the viewer
+    fn queue(ctx: &mut Spawner, viewer: &Viewer) -> Vec<(i32, i32, usize)> {
+        for tile in ctx.tiles.values_mut() {
+            tile.visible = false;
+        }
+        queue_tiles_to_spawn(ctx, viewer, 16, 1, VecXZ::new(16, 16));
+        let mut queued = ctx
+            .tiles
+            .iter()
+            .filter(|(_, tile)| tile.visible)
+            .map(|(index, tile)| (index.x, index.z, tile.lod))
+            .collect::<Vec<_>>();
+        queued.sort_unstable();
+        queued
+    }
+
+    #[test]
+    fn lod_distances_split_near_tiles() {
+        let mut ctx = Spawner::default();
+        assert_eq!(
+            queue(&mut ctx, &viewer(100.0, 10.0, 0.0)),
+            vec![(16, 16, 1)]
+        );
+
+        let mut ctx = Spawner::default();
+        assert_eq!(
+            queue(&mut ctx, &viewer(100.0, 40.0, 0.0)),
+            vec![(8, 8, 0), (8, 24, 0), (24, 8, 0), (24, 24, 0)]
+        );
+
+        let mut ctx = Spawner::default();
+        assert!(queue(&mut ctx, &viewer(20.0, 10.0, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn spawning_respects_frame_budget() {
+        let size = 65;
+        let generator = Generator {
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 4;
+        terrain.set_max_tiles_per_frame(3);
+        let mut assets = Assets::new();
+        let mut world = World::new();
+        let eye = Eye {
+            position: VecXZ::new(0.0, 0.0),
+            direction: VecXZ::new(1.0, 0.0),
+        };
+
+        let mut ctx = Spawner::default();
+        queue(&mut ctx, &viewer(100.0, 40.0, 0.0));
+        spawn_tiles(&mut ctx, &mut terrain, &mut assets, &mut world, &eye);
+        assert_eq!(world.query::<(&Tile,)>().count(), 3);
+        assert_eq!(terrain.pending_tiles, 1);
+
+        spawn_tiles(&mut ctx, &mut terrain, &mut assets, &mut world, &eye);
+        assert_eq!(world.query::<(&Tile,)>().count(), 4);
+        assert_eq!(terrain.pending_tiles, 0);
+        assert!(ctx.tiles.values().all(|tile| tile.spawned));
+    }
+
+    #[test]
+    fn spawned_tiles_use_terrain_material() {
+        let size = 65;
+        let generator = Generator {
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 4;
+        terrain.albedo = Color::rgb(0.5, 0.4, 0.3);
+        terrain.roughness = 0.7;
+        terrain.metallic = 0.1;
+        let mut assets = Assets::new();
+        let mut world = World::new();
+        let eye = Eye {
+            position: VecXZ::new(0.0, 0.0),
+            direction: VecXZ::new(1.0, 0.0),
+        };
+
+        let mut ctx = Spawner::default();
+        queue(&mut ctx, &viewer(100.0, 10.0, 0.0));
+        spawn_tiles(&mut ctx, &mut terrain, &mut assets, &mut world, &eye);
+        let materials = world.query::<(&Material,)>().collect::<Vec<_>>();
+        assert_eq!(materials.len(), 1);
+        let (material,) = materials[0];
+        let albedo: [f32; 4] = material.albedo.into();
+        assert_eq!(albedo, [0.5, 0.4, 0.3, 1.0]);
+        assert_eq!((material.roughness, material.metallic), (0.7, 0.1));
+    }
+
+    #[test]
+    fn hysteresis_keeps_spawned_lods() {
+        let spawned = |lod| TileState {
+            lod,
+            visible: false,
+            spawned: true,
+        };
+        let children = vec![(8, 8, 0), (8, 24, 0), (24, 8, 0), (24, 24, 0)];
+
+        let mut ctx = Spawner::default();
+        assert_eq!(queue(&mut ctx, &viewer(100.0, 25.0, 5.0)), children);
+
+        let mut ctx = Spawner::default();
+        ctx.tiles.insert(VecXZ::new(16, 16), spawned(1));
+        assert_eq!(
+            queue(&mut ctx, &viewer(100.0, 25.0, 5.0)),
+            vec![(16, 16, 1)]
+        );
+
+        let mut ctx = Spawner::default();
+        assert_eq!(
+            queue(&mut ctx, &viewer(100.0, 20.0, 5.0)),
+            vec![(16, 16, 1)]
+        );
+
+        let mut ctx = Spawner::default();
+        ctx.tiles.insert(VecXZ::new(8, 8), spawned(0));
+        assert_eq!(queue(&mut ctx, &viewer(100.0, 20.0, 5.0)), children);
+
+        // out of the view distance, but within the hysteresis
+        let mut ctx = Spawner::default();
+        assert!(queue(&mut ctx, &viewer(20.0, 10.0, 5.0)).is_empty());
+        ctx.tiles.insert(VecXZ::new(16, 16), spawned(1));
+        assert_eq!(queue(&mut ctx, &viewer(20.0, 10.0, 5.0)), vec![(16, 16, 1)]);
+    }
+
+    #[test]
+    fn lifecycle_events_are_passed_to_terrain() {
+        let size = 65;
+        let generator = Generator {
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 4;
+        let mut assets = Assets::new();
+        let mut world = World::new();
+        let eye = Eye {
+            position: VecXZ::new(0.0, 0.0),
+            direction: VecXZ::new(1.0, 0.0),
+        };
+        let position = VecXZ::new(16, 16);
+
+        let mut ctx = Spawner::default();
+        queue(&mut ctx, &viewer(100.0, 10.0, 0.0));
+        // queued tiles are reported once
+        queue(&mut ctx, &viewer(100.0, 10.0, 0.0));
+        assert_eq!(
+            ctx.events,
+            vec![TileEvent::new(TileEventKind::Queued, position, 1)]
+        );
+
+        spawn_tiles(&mut ctx, &mut terrain, &mut assets, &mut world, &eye);
+        assert!(ctx.events.is_empty());
+        assert_eq!(
+            terrain.events,
+            vec![
+                TileEvent::new(TileEventKind::Queued, position, 1),
+                TileEvent::new(TileEventKind::Generated, position, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn tiles_in_front_of_camera_go_first() {
+        let eye = Eye {
+            position: VecXZ::new(0.0, 0.0),
+            direction: VecXZ::new(1.0, 0.0),
+        };
+        assert_eq!(eye.priority(VecXZ::new(0, 0)), 0.0);
+        assert_eq!(eye.priority(VecXZ::new(10, 0)), 10.0);
+        assert_eq!(eye.priority(VecXZ::new(0, 10)), 20.0);
+        assert_eq!(eye.priority(VecXZ::new(-10, 0)), 30.0);
+        // far tiles in front go before the near ones behind
+        assert!(eye.priority(VecXZ::new(25, 0)) < eye.priority(VecXZ::new(-10, 0)));
+        assert!(eye.priority(VecXZ::new(5, 0)) < eye.priority(VecXZ::new(10, 0)));
+    }
+
+    #[test]
+    fn budget_spawns_tiles_by_priority() {
+        let size = 65;
+        let generator = Generator {
+            size,
+            noise_map: Some(vec![0.5; size * size]),
+            ..Default::default()
+        };
+        let mut terrain = Terrain::new(Box::new(generator), vec![]);
+        terrain.tile_size = 4;
+        terrain.set_max_tiles_per_frame(1);
+        let mut assets = Assets::new();
+        let mut world = World::new();
+        // camera at the center of the LOD 1 tile looks towards -X, -Z
+        let eye = Eye {
+            position: VecXZ::new(16.0, 16.0),
+            direction: VecXZ::new(
+                -std::f32::consts::FRAC_1_SQRT_2,
+                -std::f32::consts::FRAC_1_SQRT_2,
+            ),
+        };
+
+        let mut ctx = Spawner::default();
+        queue(&mut ctx, &viewer(100.0, 40.0, 0.0));
+        let mut spawned = Vec::new();
+        for _ in 0..4 {
+            spawn_tiles(&mut ctx, &mut terrain, &mut assets, &mut world, &eye);
+            let tiles = world.query::<(&Tile,)>();
+            for (tile,) in tiles {
+                if !spawned.contains(&(tile.x, tile.z)) {
+                    spawned.push((tile.x, tile.z));
+                }
+            }
+        }
+        assert_eq!(spawned[0], (8, 8));
+        assert_eq!(spawned[3], (24, 24));
+    }
+}
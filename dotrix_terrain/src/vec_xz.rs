@@ -0,0 +1,166 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+use dotrix_math::Vec3;
+
+/// Pair of X and Z values, a position on the terrain plane
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VecXZ<T> {
+    /// Value along X axis
+    pub x: T,
+    /// Value along Z axis
+    pub z: T,
+}
+
+impl<T> VecXZ<T> {
+    /// Constructs new pair
+    pub fn new(x: T, z: T) -> Self {
+        Self { x, z }
+    }
+}
+
+impl<T: Add<Output = T>> Add for VecXZ<T> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.z + other.z)
+    }
+}
+
+impl<T: Sub<Output = T>> Sub for VecXZ<T> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.z - other.z)
+    }
+}
+
+impl<T: Mul<Output = T> + Copy> Mul<T> for VecXZ<T> {
+    type Output = Self;
+    fn mul(self, value: T) -> Self {
+        Self::new(self.x * value, self.z * value)
+    }
+}
+
+impl<T: Neg<Output = T>> Neg for VecXZ<T> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.z)
+    }
+}
+
+impl VecXZ<f32> {
+    /// Returns squared distance to the other position
+    pub fn distance_sq(self, other: Self) -> f32 {
+        let delta = self - other;
+        delta.x * delta.x + delta.z * delta.z
+    }
+
+    /// Returns distance to the other position
+    pub fn distance(self, other: Self) -> f32 {
+        self.distance_sq(other).sqrt()
+    }
+
+    /// Returns world space vector at the height
+    pub fn to_vec3(self, y: f32) -> Vec3 {
+        Vec3::new(self.x, y, self.z)
+    }
+}
+
+impl VecXZ<i32> {
+    /// Returns squared distance to the other position
+    pub fn distance_sq(self, other: Self) -> i32 {
+        let delta = self - other;
+        delta.x * delta.x + delta.z * delta.z
+    }
+
+    /// Returns Chebyshev distance to the other position, the number of the ring around it
+    pub fn chebyshev_distance(self, other: Self) -> i32 {
+        let delta = self - other;
+        delta.x.abs().max(delta.z.abs())
+    }
+
+    /// Returns the pair converted to floats
+    pub fn as_f32(self) -> VecXZ<f32> {
+        VecXZ::new(self.x as f32, self.z as f32)
+    }
+
+    /// Iterates neighbours on the square ring of the radius around the position
+    ///
+    /// The ring of radius 0 is the position itself.
+    pub fn ring(self, radius: i32) -> impl Iterator<Item = Self> {
+        let radius = radius.max(0);
+        let side = 2 * radius;
+        let count = if radius == 0 { 1 } else { 4 * side };
+        (0..count).map(move |i| {
+            if radius == 0 {
+                return self;
+            }
+            let t = i % side;
+            let offset = match i / side {
+                0 => Self::new(-radius + t, -radius),
+                1 => Self::new(radius, -radius + t),
+                2 => Self::new(radius - t, radius),
+                _ => Self::new(-radius, radius - t),
+            };
+            self + offset
+        })
+    }
+
+    /// Iterates positions of the square around the center, ring by ring starting from the center
+    pub fn spiral(self, radius: i32) -> impl Iterator<Item = Self> {
+        (0..=radius.max(0)).flat_map(move |ring| self.ring(ring))
+    }
+}
+
+impl From<Vec3> for VecXZ<f32> {
+    fn from(vec: Vec3) -> Self {
+        Self::new(vec.x, vec.z)
+    }
+}
+
+impl From<[f32; 2]> for VecXZ<f32> {
+    fn from(pair: [f32; 2]) -> Self {
+        Self::new(pair[0], pair[1])
+    }
+}
+
+impl<T> From<VecXZ<T>> for [T; 2] {
+    fn from(vec: VecXZ<T>) -> Self {
+        [vec.x, vec.z]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn spiral_covers_square_ring_by_ring() {
+        let center = VecXZ::new(10, -4);
+        assert_eq!(center.ring(0).collect::<Vec<_>>(), vec![center]);
+
+        let ring = center.ring(2).collect::<Vec<_>>();
+        assert_eq!(ring.len(), 16);
+        assert!(ring.iter().all(|cell| cell.chebyshev_distance(center) == 2));
+
+        let spiral = center.spiral(3).collect::<Vec<_>>();
+        let unique = spiral.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(spiral.len(), 49);
+        assert_eq!(unique.len(), 49);
+        assert!(spiral
+            .windows(2)
+            .all(|pair| pair[0].chebyshev_distance(center) <= pair[1].chebyshev_distance(center)));
+    }
+
+    #[test]
+    fn operators_and_conversions() {
+        let a = VecXZ::new(1.0, 2.0);
+        let b = VecXZ::from(Vec3::new(4.0, 7.0, 6.0));
+        assert_eq!(b - a, VecXZ::new(3.0, 4.0));
+        assert_eq!(a + b * 2.0, VecXZ::new(9.0, 14.0));
+        assert_eq!(a.distance(b), 5.0);
+        assert_eq!(-a, VecXZ::new(-1.0, -2.0));
+        assert_eq!(a.to_vec3(3.0), Vec3::new(1.0, 3.0, 2.0));
+        assert_eq!(<[f32; 2]>::from(a), [1.0, 2.0]);
+        assert_eq!(VecXZ::new(1, 2).as_f32(), a);
+    }
+}
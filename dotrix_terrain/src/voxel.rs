@@ -0,0 +1,518 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use dotrix_core::assets::Mesh;
+use dotrix_math::{InnerSpace, Vec3};
+
+mod dual_contouring;
+
+/// Number of cube corners
+const CORNERS: usize = 8;
+/// Number of cube edges
+const EDGES: usize = 12;
+/// Number of voxels along the side of a cell of the coarse pass, that finds the surface
+const COARSE_VOXELS: usize = 4;
+
+/// Trait for the source of the 3D density field
+///
+/// Values above the iso level are solid, values below are empty space
+pub trait Density: Any + Sync + Send {
+    /// Returns density value at the world position
+    fn value(&self, x: f32, y: f32, z: f32) -> f32;
+}
+
+impl<F> Density for F
+where
+    F: Fn(f32, f32, f32) -> f32 + Any + Sync + Send,
+{
+    fn value(&self, x: f32, y: f32, z: f32) -> f32 {
+        self(x, y, z)
+    }
+}
+
+/// Method of the surface extraction from the density field
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Contouring {
+    /// Marching cubes, smooth surfaces
+    MarchingCubes,
+    /// Dual contouring, preserves sharp edges of signed distance fields
+    DualContouring,
+}
+
+/// Voxel terrain generator building meshes from 3D density field
+pub struct Voxel {
+    /// Density field source
+    pub density: Box<dyn Density>,
+    /// Density value of the surface
+    pub iso_level: f32,
+    /// Lowest Y coordinate of the voxel volume
+    pub min_y: f32,
+    /// Highest Y coordinate of the voxel volume
+    pub max_y: f32,
+    /// Number of voxels along the tile side
+    ///
+    /// It does not depend on the tile size: each next level of details covers twice the area
+    /// with the same number of voxels, merging 8 voxels of the previous one, as an octree does.
+    pub resolution: usize,
+    /// Surface extraction method
+    pub contouring: Contouring,
+}
+
+impl Voxel {
+    /// Constructs new voxel generator
+    pub fn new(density: Box<dyn Density>) -> Self {
+        Self {
+            density,
+            iso_level: 0.0,
+            min_y: -256.0,
+            max_y: 256.0,
+            resolution: 64,
+            contouring: Contouring::MarchingCubes,
+        }
+    }
+
+    /// Returns the voxel size for the box of the tile
+    pub fn voxel_size(&self, min: [f32; 3], max: [f32; 3]) -> f32 {
+        (max[0] - min[0]) / self.resolution.max(1) as f32
+    }
+
+    /// Generates mesh for the box between `min` and `max` world positions
+    ///
+    /// Only the height range, where the coarse pass finds the surface, is sampled by voxels.
+    pub fn mesh(&self, min: [f32; 3], max: [f32; 3]) -> Mesh {
+        let step = self.voxel_size(min, max);
+        let (min_y, max_y) = match self.surface_range(min, max, step) {
+            Some(range) => range,
+            None => return build_mesh(&[], &[], &[], min, max),
+        };
+        let min = [min[0], min_y, min[2]];
+        let max = [max[0], max_y, max[2]];
+        match self.contouring {
+            Contouring::MarchingCubes => self.marching_cubes(min, max, step),
+            Contouring::DualContouring => dual_contouring::mesh(self, min, max, step),
+        }
+    }
+
+    /// Returns the height range of the box, where the surface crosses the coarse grid
+    ///
+    /// Density is sampled every [`COARSE_VOXELS`] voxels, the range is extended by a coarse
+    /// cell on both sides and snapped to the voxel grid. Features thinner than a coarse cell
+    /// may be missed.
+    fn surface_range(&self, min: [f32; 3], max: [f32; 3], step: f32) -> Option<(f32, f32)> {
+        let grid = Grid::sample(self, min, max, step * COARSE_VOXELS as f32);
+        let solid = |x, y, z| grid.value(x, y, z) > self.iso_level;
+        let mut range: Option<(usize, usize)> = None;
+
+        for x in 0..=grid.cells[0] {
+            for y in 0..=grid.cells[1] {
+                for z in 0..=grid.cells[2] {
+                    let value = solid(x, y, z);
+                    let crossed = (x < grid.cells[0] && solid(x + 1, y, z) != value)
+                        || (z < grid.cells[2] && solid(x, y, z + 1) != value);
+                    let crossed_above = y < grid.cells[1] && solid(x, y + 1, z) != value;
+                    if !crossed && !crossed_above {
+                        continue;
+                    }
+                    let top = if crossed_above { y + 1 } else { y };
+                    range = Some(match range {
+                        Some((low, high)) => (low.min(y), high.max(top)),
+                        None => (y, top),
+                    });
+                }
+            }
+        }
+
+        range.map(|(low, high)| {
+            let low = low.saturating_sub(1) * COARSE_VOXELS;
+            let high = (high + 1) * COARSE_VOXELS;
+            (
+                min[1] + low as f32 * step,
+                (min[1] + high as f32 * step).min(max[1]),
+            )
+        })
+    }
+
+    fn marching_cubes(&self, min: [f32; 3], max: [f32; 3], step: f32) -> Mesh {
+        let grid = Grid::sample(self, min, max, step);
+        let mut builder = MeshBuilder::new(self, &grid);
+        let cases = cases();
+
+        for x in 0..grid.cells[0] {
+            for y in 0..grid.cells[1] {
+                for z in 0..grid.cells[2] {
+                    let mut case = 0;
+                    for corner in 0..CORNERS {
+                        let (cx, cy, cz) = corner_offset(corner);
+                        if grid.value(x + cx, y + cy, z + cz) > self.iso_level {
+                            case |= 1 << corner;
+                        }
+                    }
+                    for triangle in cases[case].iter() {
+                        builder.triangle([x, y, z], triangle);
+                    }
+                }
+            }
+        }
+
+        builder.build(min, max)
+    }
+
+    /// Returns surface normal at the position, calculated from the density gradient
+    fn normal(&self, position: Vec3, step: f32) -> Vec3 {
+        let d = step * 0.5;
+        let density = &self.density;
+        let gradient = Vec3::new(
+            density.value(position.x + d, position.y, position.z)
+                - density.value(position.x - d, position.y, position.z),
+            density.value(position.x, position.y + d, position.z)
+                - density.value(position.x, position.y - d, position.z),
+            density.value(position.x, position.y, position.z + d)
+                - density.value(position.x, position.y, position.z - d),
+        );
+        if gradient.magnitude2() > 0.0 {
+            -gradient.normalize()
+        } else {
+            Vec3::unit_y()
+        }
+    }
+}
+
+/// Sampled density values
+struct Grid {
+    origin: [f32; 3],
+    step: f32,
+    cells: [usize; 3],
+    values: Vec<f32>,
+}
+
+impl Grid {
+    fn sample(voxel: &Voxel, min: [f32; 3], max: [f32; 3], step: f32) -> Self {
+        let cells = [
+            ((max[0] - min[0]) / step).ceil().max(1.0) as usize,
+            ((max[1] - min[1]) / step).ceil().max(1.0) as usize,
+            ((max[2] - min[2]) / step).ceil().max(1.0) as usize,
+        ];
+        let mut values = Vec::with_capacity((cells[0] + 1) * (cells[1] + 1) * (cells[2] + 1));
+        for x in 0..=cells[0] {
+            for y in 0..=cells[1] {
+                for z in 0..=cells[2] {
+                    values.push(voxel.density.value(
+                        min[0] + x as f32 * step,
+                        min[1] + y as f32 * step,
+                        min[2] + z as f32 * step,
+                    ));
+                }
+            }
+        }
+        Self {
+            origin: min,
+            step,
+            cells,
+            values,
+        }
+    }
+
+    fn value(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.values[(x * (self.cells[1] + 1) + y) * (self.cells[2] + 1) + z]
+    }
+
+    fn position(&self, x: usize, y: usize, z: usize) -> Vec3 {
+        Vec3::new(
+            self.origin[0] + x as f32 * self.step,
+            self.origin[1] + y as f32 * self.step,
+            self.origin[2] + z as f32 * self.step,
+        )
+    }
+}
+
+/// Collects vertices shared between neighbouring cubes
+struct MeshBuilder<'a> {
+    voxel: &'a Voxel,
+    grid: &'a Grid,
+    vertices: HashMap<([usize; 3], usize), u32>,
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+}
+
+impl<'a> MeshBuilder<'a> {
+    fn new(voxel: &'a Voxel, grid: &'a Grid) -> Self {
+        Self {
+            voxel,
+            grid,
+            vertices: HashMap::new(),
+            positions: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn triangle(&mut self, cell: [usize; 3], triangle: &[usize; 3]) {
+        let indices = [
+            self.vertex(cell, triangle[0]),
+            self.vertex(cell, triangle[1]),
+            self.vertex(cell, triangle[2]),
+        ];
+
+        let p0 = Vec3::from(self.positions[indices[0] as usize]);
+        let p1 = Vec3::from(self.positions[indices[1] as usize]);
+        let p2 = Vec3::from(self.positions[indices[2] as usize]);
+        let face_normal = (p1 - p0).cross(p2 - p0);
+        if face_normal.magnitude2() <= f32::EPSILON * f32::EPSILON {
+            return;
+        }
+
+        let normal = indices.iter().fold(Vec3::new(0.0, 0.0, 0.0), |sum, &i| {
+            sum + Vec3::from(self.normals[i as usize])
+        });
+
+        // wind triangles to face the empty space
+        if face_normal.dot(normal) >= 0.0 {
+            self.indices
+                .extend_from_slice(&[indices[0], indices[1], indices[2]]);
+        } else {
+            self.indices
+                .extend_from_slice(&[indices[0], indices[2], indices[1]]);
+        }
+    }
+
+    fn vertex(&mut self, cell: [usize; 3], edge: usize) -> u32 {
+        let (a, b) = edge_corners(edge);
+        let (ax, ay, az) = corner_offset(a);
+        let (bx, by, bz) = corner_offset(b);
+        let a = [cell[0] + ax, cell[1] + ay, cell[2] + az];
+        let b = [cell[0] + bx, cell[1] + by, cell[2] + bz];
+        // edges are identified by the lower corner and the axis
+        let key = (a, edge_axis(edge));
+
+        if let Some(&index) = self.vertices.get(&key) {
+            return index;
+        }
+
+        let value_a = self.grid.value(a[0], a[1], a[2]);
+        let value_b = self.grid.value(b[0], b[1], b[2]);
+        let position_a = self.grid.position(a[0], a[1], a[2]);
+        let position_b = self.grid.position(b[0], b[1], b[2]);
+        let delta = value_b - value_a;
+        let t = if delta.abs() > f32::EPSILON {
+            ((self.voxel.iso_level - value_a) / delta).clamp(0.0, 1.0)
+        } else {
+            0.5
+        };
+        let position = position_a + (position_b - position_a) * t;
+
+        let index = self.positions.len() as u32;
+        self.positions.push(position.into());
+        self.normals
+            .push(self.voxel.normal(position, self.grid.step).into());
+        self.vertices.insert(key, index);
+        index
+    }
+
+    fn build(self, min: [f32; 3], max: [f32; 3]) -> Mesh {
+        build_mesh(&self.positions, &self.normals, &self.indices, min, max)
+    }
+}
+
+/// Builds tile mesh with positions, normals and UVs projected on XZ plane
+fn build_mesh(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    indices: &[u32],
+    min: [f32; 3],
+    max: [f32; 3],
+) -> Mesh {
+    let size_x = (max[0] - min[0]).max(f32::EPSILON);
+    let size_z = (max[2] - min[2]).max(f32::EPSILON);
+    let uvs = positions
+        .iter()
+        .map(|position| {
+            [
+                (position[0] - min[0]) / size_x,
+                (position[2] - min[2]) / size_z,
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut mesh = Mesh::default();
+    mesh.with_vertices(positions);
+    mesh.with_vertices(normals);
+    mesh.with_vertices(&uvs);
+    mesh.with_indices(indices);
+    mesh
+}
+
+/// Returns offset of the cube corner, bits of the corner index are X, Y and Z offsets
+fn corner_offset(corner: usize) -> (usize, usize, usize) {
+    (corner & 1, (corner >> 1) & 1, (corner >> 2) & 1)
+}
+
+/// Returns the axis bit of the edge
+fn edge_axis(edge: usize) -> usize {
+    1 << (edge / 4)
+}
+
+/// Returns corners of the edge
+fn edge_corners(edge: usize) -> (usize, usize) {
+    let axis = edge_axis(edge);
+    // enumerate 4 corners, that have no axis bit set
+    let low_bits = edge % 4;
+    let mut corner = 0;
+    let mut bit = 0;
+    for shift in 0..3 {
+        if 1 << shift == axis {
+            continue;
+        }
+        if low_bits & (1 << bit) != 0 {
+            corner |= 1 << shift;
+        }
+        bit += 1;
+    }
+    (corner, corner | axis)
+}
+
+/// Returns the edge between two neighbour corners
+fn edge_between(a: usize, b: usize) -> usize {
+    let (low, high) = if a < b { (a, b) } else { (b, a) };
+    (0..EDGES)
+        .find(|&edge| edge_corners(edge) == (low, high))
+        .expect("Corners must be neighbours")
+}
+
+/// Returns triangles table for all 256 marching cubes cases, built once
+fn cases() -> &'static [Vec<[usize; 3]>] {
+    static CASES: OnceLock<Vec<Vec<[usize; 3]>>> = OnceLock::new();
+    CASES.get_or_init(build_cases)
+}
+
+/// Builds triangles table for all 256 marching cubes cases
+///
+/// Each face of the cube produces segments between its crossed edges. Ambiguous faces always
+/// separate solid corners, so neighbouring cubes agree on the shared face. Segments are joined
+/// into loops, that are triangulated as fans.
+fn build_cases() -> Vec<Vec<[usize; 3]>> {
+    let faces = (0..3)
+        .flat_map(|axis| {
+            let axis_bit = 1 << axis;
+            let u = 1 << ((axis + 1) % 3);
+            let v = 1 << ((axis + 2) % 3);
+            [0, axis_bit]
+                .iter()
+                .map(|&base| [base, base | u, base | u | v, base | v])
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    (0..256)
+        .map(|case: usize| {
+            let solid = |corner: usize| case & (1 << corner) != 0;
+            let mut links: [Vec<usize>; EDGES] = Default::default();
+
+            for face in faces.iter() {
+                let crossed = (0..4)
+                    .filter(|&i| solid(face[i]) != solid(face[(i + 1) % 4]))
+                    .map(|i| edge_between(face[i], face[(i + 1) % 4]))
+                    .collect::<Vec<_>>();
+
+                let segments = match crossed.len() {
+                    2 => vec![(crossed[0], crossed[1])],
+                    4 => (0..4)
+                        .filter(|&i| solid(face[i]))
+                        .map(|i| {
+                            (
+                                edge_between(face[(i + 3) % 4], face[i]),
+                                edge_between(face[i], face[(i + 1) % 4]),
+                            )
+                        })
+                        .collect(),
+                    _ => vec![],
+                };
+
+                for (a, b) in segments {
+                    links[a].push(b);
+                    links[b].push(a);
+                }
+            }
+
+            let mut visited = [false; EDGES];
+            let mut triangles = Vec::new();
+            for start in 0..EDGES {
+                if visited[start] || links[start].is_empty() {
+                    continue;
+                }
+                let mut polygon = vec![start];
+                visited[start] = true;
+                let mut previous = start;
+                let mut current = links[start][0];
+                while current != start {
+                    polygon.push(current);
+                    visited[current] = true;
+                    let next = if links[current][0] != previous {
+                        links[current][0]
+                    } else {
+                        links[current][1]
+                    };
+                    previous = current;
+                    current = next;
+                }
+                for i in 1..polygon.len() - 1 {
+                    triangles.push([polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            }
+            triangles
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_have_neighbour_corners() {
+        for edge in 0..EDGES {
+            let (a, b) = edge_corners(edge);
+            assert_eq!(b - a, edge_axis(edge));
+            assert_eq!(edge_between(a, b), edge);
+        }
+    }
+
+    #[test]
+    fn voxels_sample_only_surface_heights() {
+        let mut voxel = Voxel::new(Box::new(|_: f32, y: f32, _: f32| 10.0 - y));
+        voxel.resolution = 16;
+        let (min, max) = ([0.0, -256.0, 0.0], [16.0, 256.0, 16.0]);
+        assert_eq!(voxel.voxel_size(min, max), 1.0);
+        assert_eq!(voxel.surface_range(min, max, 1.0), Some((4.0, 16.0)));
+
+        let mesh = voxel.mesh(min, max);
+        assert!(mesh.indices().map(|indices| !indices.is_empty()).unwrap());
+        assert!(mesh
+            .vertices_as::<[f32; 3]>(0)
+            .all(|position| (position[1] - 10.0).abs() < 0.001));
+
+        let empty = Voxel::new(Box::new(|_: f32, _: f32, _: f32| -1.0));
+        assert_eq!(empty.surface_range(min, max, 1.0), None);
+    }
+
+    #[test]
+    fn sphere_mesh_is_closed() {
+        let mut voxel = Voxel::new(Box::new(|x: f32, y: f32, z: f32| {
+            5.3 - (x * x + y * y + z * z).sqrt()
+        }));
+        voxel.resolution = 16;
+        let mesh = voxel.mesh([-8.0, -8.0, -8.0], [8.0, 8.0, 8.0]);
+        let indices = mesh.indices().unwrap();
+        assert!(!indices.is_empty());
+
+        let mut edges = HashMap::new();
+        for triangle in indices.chunks(3) {
+            for i in 0..3 {
+                let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        assert!(edges.values().all(|&count| count == 2));
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use dotrix_core::assets::Mesh;
+use dotrix_math::{InnerSpace, Vec3};
+
+use super::{build_mesh, corner_offset, edge_corners, Grid, Voxel, EDGES};
+
+/// Weight of the mass point in the QEF, keeps vertices stable on flat surfaces
+const MASS_POINT_WEIGHT: f32 = 0.01;
+
+/// Generates mesh for the box between `min` and `max` world positions with dual contouring
+///
+/// The grid is extended by one voxel towards the negative direction, so each tile owns the
+/// quads on its lower boundaries and neighbouring tiles are stitched without gaps.
+pub(super) fn mesh(voxel: &Voxel, min: [f32; 3], max: [f32; 3], step: f32) -> Mesh {
+    let grid = Grid::sample(
+        voxel,
+        [min[0] - step, min[1] - step, min[2] - step],
+        max,
+        step,
+    );
+
+    let mut builder = Builder {
+        voxel,
+        grid: &grid,
+        vertices: HashMap::new(),
+        positions: Vec::new(),
+        normals: Vec::new(),
+        indices: Vec::new(),
+    };
+
+    for x in 1..grid.cells[0] {
+        for y in 1..grid.cells[1] {
+            for z in 1..grid.cells[2] {
+                let solid = grid.value(x, y, z) > voxel.iso_level;
+                for axis in 0..3 {
+                    let mut next = [x, y, z];
+                    next[axis] += 1;
+                    if solid != (grid.value(next[0], next[1], next[2]) > voxel.iso_level) {
+                        builder.quad([x, y, z], axis, solid);
+                    }
+                }
+            }
+        }
+    }
+
+    build_mesh(
+        &builder.positions,
+        &builder.normals,
+        &builder.indices,
+        min,
+        max,
+    )
+}
+
+/// Collects vertices of the cells crossed by the surface
+struct Builder<'a> {
+    voxel: &'a Voxel,
+    grid: &'a Grid,
+    vertices: HashMap<[usize; 3], u32>,
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+}
+
+impl<'a> Builder<'a> {
+    /// Connects vertices of 4 cells around the crossed edge
+    fn quad(&mut self, corner: [usize; 3], axis: usize, solid: bool) {
+        let u = (axis + 1) % 3;
+        let v = (axis + 2) % 3;
+        let mut quad = [0; 4];
+        // counter clockwise around the axis, so the quad faces the positive direction
+        for (i, &(du, dv)) in [(1, 1), (0, 1), (0, 0), (1, 0)].iter().enumerate() {
+            let mut cell = corner;
+            cell[u] -= du;
+            cell[v] -= dv;
+            quad[i] = self.vertex(cell);
+        }
+
+        // face the empty space
+        if !solid {
+            quad.reverse();
+        }
+
+        self.indices
+            .extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+    }
+
+    fn vertex(&mut self, cell: [usize; 3]) -> u32 {
+        if let Some(&index) = self.vertices.get(&cell) {
+            return index;
+        }
+
+        let position = self.solve(cell);
+        let index = self.positions.len() as u32;
+        self.positions.push(position.into());
+        self.normals
+            .push(self.voxel.normal(position, self.grid.step).into());
+        self.vertices.insert(cell, index);
+        index
+    }
+
+    /// Places the vertex by minimizing the quadratic error function of the edge intersections
+    fn solve(&self, cell: [usize; 3]) -> Vec3 {
+        let grid = self.grid;
+        let iso_level = self.voxel.iso_level;
+        let mut points = Vec::with_capacity(EDGES);
+        let mut normals = Vec::with_capacity(EDGES);
+
+        for edge in 0..EDGES {
+            let (a, b) = edge_corners(edge);
+            let (ax, ay, az) = corner_offset(a);
+            let (bx, by, bz) = corner_offset(b);
+            let a = [cell[0] + ax, cell[1] + ay, cell[2] + az];
+            let b = [cell[0] + bx, cell[1] + by, cell[2] + bz];
+            let value_a = grid.value(a[0], a[1], a[2]);
+            let value_b = grid.value(b[0], b[1], b[2]);
+            if (value_a > iso_level) == (value_b > iso_level) {
+                continue;
+            }
+            let t = ((iso_level - value_a) / (value_b - value_a)).clamp(0.0, 1.0);
+            let position_a = grid.position(a[0], a[1], a[2]);
+            let position_b = grid.position(b[0], b[1], b[2]);
+            let point = position_a + (position_b - position_a) * t;
+            points.push(point);
+            normals.push(self.voxel.normal(point, grid.step));
+        }
+
+        let min = grid.position(cell[0], cell[1], cell[2]);
+        if points.is_empty() {
+            return min + Vec3::new(0.5, 0.5, 0.5) * grid.step;
+        }
+
+        let mass_point = points
+            .iter()
+            .fold(Vec3::new(0.0, 0.0, 0.0), |sum, &point| sum + point)
+            / points.len() as f32;
+
+        // solve (AtA + wI) c = At (b - A m), where the vertex is m + c
+        let mut ata = [[0.0; 3]; 3];
+        let mut atb = [0.0; 3];
+        for (point, normal) in points.iter().zip(normals.iter()) {
+            let n = [normal.x, normal.y, normal.z];
+            let distance = normal.dot(*point - mass_point);
+            for i in 0..3 {
+                for j in 0..3 {
+                    ata[i][j] += n[i] * n[j];
+                }
+                atb[i] += n[i] * distance;
+            }
+        }
+        for (i, row) in ata.iter_mut().enumerate() {
+            row[i] += MASS_POINT_WEIGHT;
+        }
+
+        let offset = solve3(&ata, &atb).unwrap_or_else(|| Vec3::new(0.0, 0.0, 0.0));
+        let position = mass_point + offset;
+        let max = min + Vec3::new(1.0, 1.0, 1.0) * grid.step;
+
+        Vec3::new(
+            position.x.clamp(min.x, max.x),
+            position.y.clamp(min.y, max.y),
+            position.z.clamp(min.z, max.z),
+        )
+    }
+}
+
+/// Solves 3x3 linear system using the Cramer's rule
+fn solve3(m: &[[f32; 3]; 3], b: &[f32; 3]) -> Option<Vec3> {
+    let det = |m: &[[f32; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let d = det(m);
+    if d.abs() <= f32::EPSILON {
+        return None;
+    }
+    let mut result = [0.0; 3];
+    for (column, value) in result.iter_mut().enumerate() {
+        let mut replaced = *m;
+        for row in 0..3 {
+            replaced[row][column] = b[row];
+        }
+        *value = det(&replaced) / d;
+    }
+    Some(Vec3::new(result[0], result[1], result[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Contouring;
+    use super::*;
+
+    #[test]
+    fn box_mesh_keeps_sharp_corners() {
+        let mut voxel = Voxel::new(Box::new(|x: f32, y: f32, z: f32| {
+            3.5 - x.abs().max(y.abs()).max(z.abs())
+        }));
+        voxel.contouring = Contouring::DualContouring;
+        voxel.resolution = 16;
+        let mesh = voxel.mesh([-8.0, -8.0, -8.0], [8.0, 8.0, 8.0]);
+        let indices = mesh.indices().unwrap();
+        assert!(!indices.is_empty());
+
+        let mut edges = HashMap::new();
+        for triangle in indices.chunks(3) {
+            for i in 0..3 {
+                let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        assert!(edges.values().all(|&count| count == 2));
+
+        let corner = Vec3::new(3.5, 3.5, 3.5);
+        assert!(mesh
+            .vertices_as::<[f32; 3]>(0)
+            .any(|position| (Vec3::from(position) - corner).magnitude() < 0.05));
+    }
+}
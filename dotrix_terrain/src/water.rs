@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+
+use dotrix_core::assets::{Assets, Mesh, Shader};
+use dotrix_core::camera::ProjView;
+use dotrix_core::ecs::{Const, Context, Entity, Mut};
+use dotrix_core::renderer::{
+    BindGroup, Binding, DepthBufferMode, PipelineLayout, PipelineOptions, Renderer, Stage,
+    UniformBuffer,
+};
+use dotrix_core::{Color, Frame, Globals, Id, Pipeline, World};
+
+use dotrix_pbr::Lights;
+
+use crate::{Terrain, Tile};
+
+const PIPELINE_LABEL: &str = "dotrix::terrain::water";
+
+/// Water surface of the terrain
+///
+/// Flat water tiles are spawned at the sea level over the terrain tiles having heights below
+/// it. Depth of the water under every vertex is baked into the mesh, so shores are blended
+/// with the terrain by the water transparency.
+pub struct Water {
+    /// Height of the water surface
+    pub sea_level: f32,
+    /// Number of quads per water tile side
+    pub resolution: usize,
+    /// Color of the shallow water
+    pub shallow_color: Color,
+    /// Color of the deep water
+    pub deep_color: Color,
+    /// Depth where the water reaches the deep color and full opacity
+    pub depth: f32,
+    /// Length of the waves in world units
+    pub wave_length: f32,
+    /// Speed of the waves in world units per second
+    pub wave_speed: f32,
+    /// Strength of the animated normals tilting
+    pub wave_strength: f32,
+    /// Water parameters uniform
+    pub uniform: UniformBuffer,
+}
+
+impl Water {
+    /// Constructs water surface at the sea level
+    pub fn new(sea_level: f32) -> Self {
+        Self {
+            sea_level,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        Self {
+            sea_level: 0.0,
+            resolution: 48,
+            shallow_color: Color::rgba(0.18, 0.52, 0.56, 0.3),
+            deep_color: Color::rgba(0.02, 0.14, 0.28, 0.9),
+            depth: 8.0,
+            wave_length: 12.0,
+            wave_speed: 1.5,
+            wave_strength: 0.15,
+            uniform: UniformBuffer::default(),
+        }
+    }
+}
+
+/// Water tile component
+pub struct WaterTile {
+    /// Mesh of the owning terrain tile
+    pub tile: Id<Mesh>,
+    /// Water mesh
+    pub mesh: Id<Mesh>,
+}
+
+/// Water spawn system context
+#[derive(Default)]
+pub struct Spawner {
+    tiles: HashMap<Id<Mesh>, Option<Id<Mesh>>>,
+    to_exile: Vec<(Entity, Id<Mesh>)>,
+}
+
+/// Water spawn system
+/// Spawns water tiles over the terrain tiles going below the sea level
+pub fn spawn(
+    mut ctx: Context<Spawner>,
+    terrain: Const<Terrain>,
+    mut assets: Mut<Assets>,
+    mut world: Mut<World>,
+) {
+    let water = match terrain.water.as_ref() {
+        Some(water) => water,
+        None => {
+            if !ctx.tiles.is_empty() {
+                exile(&mut ctx, &mut assets, &mut world, |_| true);
+                ctx.tiles.clear();
+            }
+            return;
+        }
+    };
+
+    let mut tiles = HashMap::new();
+    let mut new_tiles = Vec::new();
+    let query = world.query::<(&Tile,)>();
+    for (tile,) in query {
+        if tile.pooled {
+            continue;
+        }
+        match ctx.tiles.get(&tile.mesh) {
+            Some(water_mesh) if !terrain.is_tile_dirty(tile.x, tile.z, tile.lod) => {
+                tiles.insert(tile.mesh, *water_mesh);
+            }
+            _ => new_tiles.push((tile.mesh, tile.x, tile.z, tile.lod)),
+        }
+    }
+
+    exile(&mut ctx, &mut assets, &mut world, |water_tile| {
+        !tiles.contains_key(&water_tile.tile)
+    });
+
+    for (tile, x, z, lod) in new_tiles {
+        let water_mesh = terrain
+            .generate_water_mesh(water, x, z, lod)
+            .map(|mesh| assets.store(mesh));
+        if let Some(mesh) = water_mesh {
+            let pipeline = Pipeline::default();
+            world.spawn(Some((WaterTile { tile, mesh }, pipeline)));
+        }
+        tiles.insert(tile, water_mesh);
+    }
+
+    ctx.tiles = tiles;
+}
+
+fn exile<F>(ctx: &mut Spawner, assets: &mut Assets, world: &mut World, filter: F)
+where
+    F: Fn(&WaterTile) -> bool,
+{
+    let query = world.query::<(&WaterTile, &Entity)>();
+    for (water_tile, entity) in query {
+        if filter(water_tile) {
+            ctx.to_exile.push((*entity, water_tile.mesh));
+        }
+    }
+    for (entity, mesh) in ctx.to_exile.drain(..) {
+        world.exile(entity);
+        assets.remove(mesh);
+    }
+}
+
+/// Water startup system
+pub fn startup(mut assets: Mut<Assets>, renderer: Const<Renderer>) {
+    let mut shader = Shader {
+        name: String::from(PIPELINE_LABEL),
+        code: Lights::add_to_shader(include_str!("shaders/water.wgsl"), 0, 1),
+        ..Default::default()
+    };
+    shader.load(&renderer);
+    assets.store_as(shader, PIPELINE_LABEL);
+}
+
+/// Water rendering system
+pub fn render(
+    mut renderer: Mut<Renderer>,
+    mut assets: Mut<Assets>,
+    globals: Const<Globals>,
+    frame: Const<Frame>,
+    mut terrain: Mut<Terrain>,
+    world: Const<World>,
+) {
+    let water = match terrain.water.as_mut() {
+        Some(water) => water,
+        None => return,
+    };
+
+    let params = Params {
+        shallow_color: water.shallow_color.into(),
+        deep_color: water.deep_color.into(),
+        depth: water.depth,
+        wave_length: water.wave_length,
+        wave_speed: water.wave_speed,
+        wave_strength: water.wave_strength,
+        time: frame.time().as_secs_f32(),
+        unused: [0.0; 3],
+    };
+    renderer.load_uniform_buffer(&mut water.uniform, bytemuck::cast_slice(&[params]));
+
+    let query = world.query::<(&WaterTile, &mut Pipeline)>();
+    for (water_tile, pipeline) in query {
+        if pipeline.shader.is_null() {
+            pipeline.shader = assets.find::<Shader>(PIPELINE_LABEL).unwrap_or_default();
+        }
+
+        if !pipeline.cycle(&renderer) {
+            continue;
+        }
+
+        match assets.get_mut(water_tile.mesh) {
+            Some(mesh) => mesh.load(&renderer),
+            None => continue,
+        }
+        let mesh = assets.get(water_tile.mesh).unwrap();
+
+        if !pipeline.ready() {
+            if let Some(shader) = assets.get(pipeline.shader) {
+                if !shader.loaded() {
+                    continue;
+                }
+
+                let proj_view = globals
+                    .get::<ProjView>()
+                    .expect("ProjView buffer must be loaded");
+
+                let lights = globals
+                    .get::<Lights>()
+                    .expect("Lights buffer must be loaded");
+
+                renderer.bind(
+                    pipeline,
+                    PipelineLayout {
+                        label: String::from(PIPELINE_LABEL),
+                        mesh: Some(mesh),
+                        shader,
+                        bindings: &[BindGroup::new(
+                            "Globals",
+                            vec![
+                                Binding::Uniform("ProjView", Stage::Vertex, &proj_view.uniform),
+                                Binding::Uniform("Lights", Stage::Fragment, &lights.uniform),
+                                Binding::Uniform("Water", Stage::All, &water.uniform),
+                            ],
+                        )],
+                        vertex_buffers: &[],
+                        push_constants: None,
+                        options: PipelineOptions {
+                            depth_buffer_mode: DepthBufferMode::Read,
+                            ..Default::default()
+                        },
+                    },
+                );
+            }
+        }
+
+        renderer.run(pipeline, mesh);
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+struct Params {
+    shallow_color: [f32; 4],
+    deep_color: [f32; 4],
+    depth: f32,
+    wave_length: f32,
+    wave_speed: f32,
+    wave_strength: f32,
+    time: f32,
+    unused: [f32; 3],
+}
+
+unsafe impl bytemuck::Zeroable for Params {}
+unsafe impl bytemuck::Pod for Params {}
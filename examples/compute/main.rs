@@ -193,6 +193,8 @@ fn compute(
                                 Binding::Storage("Particles", Stage::Compute, &spawner.particles),
                             ],
                         )],
+                        vertex_buffers: &[],
+                        push_constants: None,
                         options: PipelineOptions::default(),
                     },
                 );
@@ -252,6 +254,8 @@ fn render(
                                 Binding::Storage("Particles", Stage::Vertex, &spawner.particles),
                             ],
                         )],
+                        vertex_buffers: &[],
+                        push_constants: None,
                         options: PipelineOptions::default(),
                     },
                 );
@@ -136,6 +136,8 @@ pub fn render(
                                 ),
                             ],
                         )],
+                        vertex_buffers: &[],
+                        push_constants: None,
                         options: PipelineOptions::default(),
                     },
                 );
@@ -16,6 +16,7 @@ fn main() {
         .with(System::from(settings::startup))
         .with(System::from(match_finder::update))
         .with(System::from(settings::ui))
+        .with(System::from(settings::present_mode))
         .with(System::from(camera_update))
         .with(Service::from(MatchFinder::new()))
         .with(Service::from(Settings::default()))
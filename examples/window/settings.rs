@@ -7,8 +7,9 @@ use dotrix::egui::{
 };
 use dotrix::math::{Vec2i, Vec2u};
 use dotrix::overlay::Overlay;
+use dotrix::renderer::PresentMode;
 use dotrix::window::{CursorIcon, Fullscreen, UserAttentionType, VideoMode};
-use dotrix::{Frame, Id, Input, Window};
+use dotrix::{Frame, Id, Input, Renderer, Window};
 
 use std::{collections::hash_map::HashMap, path::PathBuf};
 
@@ -20,6 +21,7 @@ pub struct Settings {
     min_inner_size: Vec2u,
     opened_file: Option<PathBuf>,
     open_file_dialog: Option<FileDialog>,
+    present_mode: PresentMode,
     save_file_dialog: Option<FileDialog>,
     title: String,
     window_mode: WindowMode,
@@ -35,6 +37,7 @@ impl Default for Settings {
             min_inner_size: Vec2u::new(640, 480),
             opened_file: None,
             open_file_dialog: None,
+            present_mode: PresentMode::default(),
             save_file_dialog: None,
             title: String::new(),
             window_mode: WindowMode::Windowed,
@@ -387,6 +390,16 @@ pub fn ui(
                     });
                     ui.end_row();
 
+                    ui.label("set Present mode");
+                    ComboBox::from_id_source("Present mode")
+                        .selected_text(format!("{:?}", settings.present_mode))
+                        .show_ui(ui, |ui| {
+                            for mode in PRESENT_MODES.iter() {
+                                ui.selectable_value(&mut settings.present_mode, *mode, format!("{:?}", mode));
+                            }
+                        });
+                    ui.end_row();
+
                     match window.screen_size() {
                         Some(screen_size) => {
                             ui.label("set Min Inner Size - x");
@@ -576,6 +589,13 @@ pub fn ui(
     });
 }
 
+/// Applies the present mode of the settings to the renderer
+pub fn present_mode(settings: Const<Settings>, mut renderer: Mut<Renderer>) {
+    if renderer.present_mode() != settings.present_mode {
+        renderer.set_present_mode(settings.present_mode);
+    }
+}
+
 fn file_path_to_string(buf: &Option<std::path::PathBuf>) -> String {
     match buf {
         Some(path) => path.display().to_string(),
@@ -692,6 +712,12 @@ pub enum WindowMode {
     Windowed,
 }
 
+const PRESENT_MODES: &[PresentMode] = &[
+    PresentMode::Immediate,
+    PresentMode::Mailbox,
+    PresentMode::Fifo,
+];
+
 const WINDOW_MODES: &[WindowMode] = &[
     WindowMode::Fullscreen,
     WindowMode::BorderlessFullscreen,